@@ -1,21 +1,33 @@
+use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 
-use krabs_core::{LlmProvider, Message, StreamChunk, ToolRegistry, UserInputRequest};
+use krabs_core::{
+    Credentials, KrabsConfig, LlmProvider, Message, Role, SessionStore, StreamChunk, ToolRegistry,
+    UserInputRequest,
+};
 use tokio::sync::{mpsc, oneshot};
 
-use super::app::extract_api_error;
+use super::app::describe_error;
 use super::types::{DisplayEvent, PendingPermission};
 
 // ── Shared permission state (bypasses the DisplayEvent channel entirely) ──────
 
-/// Written by TuiHook, read by the TUI's tick loop.
-pub(super) type SharedPerm = Arc<Mutex<Option<PendingPermission>>>;
+/// Written by TuiHook, read by the TUI's tick loop. A queue, because
+/// `parallel_tools` can ask for several permissions at once; the TUI shows
+/// them one at a time in arrival order.
+pub(super) type SharedPerm = Arc<Mutex<VecDeque<PendingPermission>>>;
 
 // ── TUI hook — bridges KrabsAgent lifecycle events into DisplayEvents ─────────
 
 struct TuiHook {
     tx: mpsc::Sender<DisplayEvent>,
     perm: SharedPerm,
+    /// Tools only get a `[dry-run]` description, so there is nothing to approve.
+    dry_run: bool,
+    /// Where the file tools resolve paths, for diff previews.
+    root: krabs_core::ProjectRoot,
+    /// Config `auto_approve` rules, checked before prompting.
+    approver: Arc<krabs_core::AutoApprover>,
 }
 
 #[async_trait::async_trait]
@@ -24,7 +36,7 @@ impl krabs_core::Hook for TuiHook {
         &self,
         event: &krabs_core::HookEvent,
     ) -> anyhow::Result<krabs_core::HookOutput> {
-        use krabs_core::{HookEvent, HookOutput, ToolUseDecision};
+        use krabs_core::{Approval, HookEvent, HookOutput, ToolUseDecision};
         match event {
             // Before a tool runs: ask the user for permission via shared mutex,
             // completely bypassing the DisplayEvent channel to avoid deadlocks.
@@ -33,15 +45,38 @@ impl krabs_core::Hook for TuiHook {
                 args,
                 tool_use_id: _,
             } => {
+                if self.dry_run {
+                    return Ok(HookOutput::Continue);
+                }
+                let always_ask = match self.approver.decide(tool_name, args) {
+                    Approval::Rule(rule) => {
+                        let _ = self
+                            .tx
+                            .send(DisplayEvent::Status(format!(
+                                "  ✓ auto-approved: {tool_name} ({} ~ /{}/)",
+                                rule.arg, rule.arg_pattern
+                            )))
+                            .await;
+                        return Ok(HookOutput::Continue);
+                    }
+                    // The info box banner says so; no per-call notice.
+                    Approval::All => return Ok(HookOutput::Continue),
+                    Approval::Ask => false,
+                    Approval::AlwaysAsk(_) => true,
+                };
                 let (respond, rx) = oneshot::channel::<bool>();
                 let args_str = serde_json::to_string(args).unwrap_or_default();
+                let diff = super::diff::preview(tool_name, args, &self.root).await;
 
-                // Write permission request to shared state (non-blocking, no channel).
+                // Queue the permission request in shared state (non-blocking, no channel).
                 {
                     let mut guard = self.perm.lock().unwrap();
-                    *guard = Some(PendingPermission {
+                    guard.push_back(PendingPermission {
                         tool_name: tool_name.clone(),
                         args: args_str,
+                        diff,
+                        scroll: 0,
+                        always_ask,
                         respond,
                     });
                 }
@@ -57,10 +92,22 @@ impl krabs_core::Hook for TuiHook {
                 }
             }
             // After a tool succeeds: show the result in the TUI
-            HookEvent::PostToolUse { result, .. } => {
+            HookEvent::PostToolUse {
+                tool_name,
+                args,
+                result,
+                ..
+            } => {
+                // `read` results get highlighted by the file's extension.
+                let path = (tool_name == "read")
+                    .then(|| args["path"].as_str().map(String::from))
+                    .flatten();
                 let _ = self
                     .tx
-                    .send(DisplayEvent::ToolResultEnd(result.clone()))
+                    .send(DisplayEvent::ToolResultEnd {
+                        content: result.clone(),
+                        path,
+                    })
                     .await;
                 Ok(HookOutput::Continue)
             }
@@ -83,20 +130,76 @@ impl krabs_core::Hook for TuiHook {
     }
 }
 
+// ── session titles ───────────────────────────────────────────────────────────
+
+/// After a session's first turn, ask a small model for a short title and store
+/// it in the session's metadata, in the background. Failures are dropped:
+/// `/sessions` then falls back to a preview of the first message.
+pub(super) fn spawn_session_title(
+    config: &KrabsConfig,
+    creds: &Credentials,
+    session_id: &str,
+    messages: &[Message],
+) {
+    use krabs_core::session::title::{generate_title, title_model};
+
+    if !config.session_titles {
+        return;
+    }
+    let mut users = messages.iter().filter(|m| matches!(m.role, Role::User));
+    let (Some(user), None) = (users.next(), users.next()) else {
+        return;
+    };
+    let Some(reply) = messages
+        .iter()
+        .rev()
+        .find(|m| matches!(m.role, Role::Assistant) && !m.content.is_empty())
+    else {
+        return;
+    };
+    let Some(model) = title_model(creds, &config.session_title_model) else {
+        return;
+    };
+    let provider = Credentials {
+        model,
+        thinking_budget: 0,
+        reasoning_effort: None,
+        ..creds.clone()
+    }
+    .build_provider();
+    let (db_path, sqlite) = (config.db_path.clone(), config.sqlite.clone());
+    let session_id = session_id.to_string();
+    let (user, reply) = (user.content.clone(), reply.content.clone());
+    tokio::spawn(async move {
+        let _ = async {
+            let title = generate_title(provider.as_ref(), &user, &reply).await?;
+            let store = SessionStore::open_with(&db_path, &sqlite).await?;
+            store
+                .load_session(&session_id)
+                .await?
+                .set_title(&title)
+                .await
+        }
+        .await;
+    });
+}
+
 // ── background agentic task ──────────────────────────────────────────────────
 
 /// Build a per-turn `KrabsAgent` with the given provider, registry, system
 /// prompt, and a `TuiHook` wired to the display-event channel.
 #[allow(clippy::too_many_arguments)]
 pub(super) async fn build_agent(
-    config: &krabs_core::KrabsConfig,
+    config: &KrabsConfig,
     provider: Arc<dyn LlmProvider>,
     registry: Arc<ToolRegistry>,
     system_prompt: String,
     tx: mpsc::Sender<DisplayEvent>,
     perm: SharedPerm,
+    approver: Arc<krabs_core::AutoApprover>,
     resume_session_id: Option<String>,
     initial_session_id: Option<String>,
+    memory_store: Option<krabs_core::SqliteMemoryStore>,
 ) -> Arc<krabs_core::KrabsAgent> {
     use krabs_core::{DelegateTool, DispatchTool, UserInputTool};
 
@@ -107,18 +210,41 @@ pub(super) async fn build_agent(
         }
     }
     // Register orchestration tools so the agent can spawn specialised sub-agents.
-    tool_registry.register(Arc::new(DelegateTool::new(
-        config.clone(),
-        Arc::clone(&provider),
-        tool_registry.clone(),
-        krabs_core::PermissionGuard::new(),
-    )));
-    tool_registry.register(Arc::new(DispatchTool::new(
-        config.clone(),
-        Arc::clone(&provider),
-        tool_registry.clone(),
-        krabs_core::PermissionGuard::new(),
-    )));
+    // One budget per turn, shared by both tools. Their tool calls are relayed
+    // as indented progress lines so a long delegation isn't a silent wait.
+    let budget = krabs_core::token_budget(config.delegate_token_budget);
+    // Sub-agents' usage is counted by this agent, so `/usage` includes it.
+    let usage = krabs_core::UsageLedger::new();
+    let (progress_tx, mut progress_rx) = mpsc::channel::<krabs_core::SubAgentProgress>(64);
+    let fwd_tx = tx.clone();
+    tokio::spawn(async move {
+        while let Some(p) = progress_rx.recv().await {
+            let _ = fwd_tx.send(DisplayEvent::SubAgentProgress(p)).await;
+        }
+    });
+    tool_registry.register(Arc::new(
+        DelegateTool::new(
+            config.clone(),
+            Arc::clone(&provider),
+            tool_registry.clone(),
+            krabs_core::PermissionGuard::new(),
+        )
+        .with_budget(budget.clone())
+        .with_progress(Some(progress_tx.clone()))
+        .with_usage(Some(usage.clone())),
+    ));
+    tool_registry.register(Arc::new(
+        DispatchTool::new(
+            config.clone(),
+            Arc::clone(&provider),
+            tool_registry.clone(),
+            krabs_core::PermissionGuard::new(),
+        )
+        .with_budget(budget)
+        .with_limit(krabs_core::subagent_limit(config.max_concurrent_subagents))
+        .with_progress(Some(progress_tx))
+        .with_usage(Some(usage.clone())),
+    ));
     // Register the ask_user tool: a dedicated channel forwards requests to the
     // TUI event loop as DisplayEvent::UserInput, blocking the agent until the
     // user confirms their choice in the popup.
@@ -130,10 +256,21 @@ pub(super) async fn build_agent(
         }
     });
     tool_registry.register(Arc::new(UserInputTool::new(ui_tx)));
-    let builder = krabs_core::KrabsAgentBuilder::new(config.clone(), provider)
+    let mut builder = krabs_core::KrabsAgentBuilder::new(config.clone(), provider)
         .registry(tool_registry)
         .system_prompt(system_prompt)
-        .hook(Arc::new(TuiHook { tx, perm }));
+        .subagent_usage(usage)
+        .hook(Arc::new(TuiHook {
+            tx,
+            perm,
+            dry_run: config.dry_run,
+            root: krabs_core::ProjectRoot::from_config(config),
+            approver,
+        }));
+    // The memory_* tools reuse the run's memory database.
+    if let Some(store) = memory_store {
+        builder = builder.memory_store(store);
+    }
     let builder = match resume_session_id {
         Some(sid) => builder.resume_session(sid),
         None => match initial_session_id {
@@ -149,17 +286,18 @@ pub(super) async fn run_agent_turn(
     messages: Vec<Message>,
     subturn_resume: Option<krabs_core::SubturnResume>,
     tx: mpsc::Sender<DisplayEvent>,
+    cancel: krabs_core::CancellationToken,
 ) {
     let session_id = agent.session_id().map(|s| s.to_string());
     let (mut stream, done_rx) = match agent
-        .run_streaming_with_history(messages, subturn_resume)
+        .run_streaming_with_history(messages, subturn_resume, cancel)
         .await
     {
         Ok(r) => r,
         Err(e) => {
             let _ = tx
                 .send(DisplayEvent::Error {
-                    message: extract_api_error(&e.to_string()),
+                    message: describe_error(&e),
                     session_id,
                 })
                 .await;
@@ -174,6 +312,11 @@ pub(super) async fn run_agent_turn(
                     return;
                 }
             }
+            StreamChunk::Thinking { text } => {
+                if tx.send(DisplayEvent::Thinking(text)).await.is_err() {
+                    return;
+                }
+            }
             StreamChunk::ToolCallReady { call } => {
                 if tx.send(DisplayEvent::ToolCallStart(call)).await.is_err() {
                     return;
@@ -189,16 +332,25 @@ pub(super) async fn run_agent_turn(
                     return;
                 }
             }
+            StreamChunk::Interrupted { partial, resumable } => {
+                if tx
+                    .send(DisplayEvent::Interrupted { partial, resumable })
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
         }
     }
 
     // Stream closed — get final message history from done channel
-    let (session_id, final_messages) = match done_rx.await {
-        Ok(Ok((sid, msgs))) => (sid, msgs),
+    let (session_id, final_messages, hit_max_turns) = match done_rx.await {
+        Ok(Ok(done)) => done,
         Ok(Err(e)) => {
             let _ = tx
                 .send(DisplayEvent::Error {
-                    message: extract_api_error(&e.to_string()),
+                    message: describe_error(&e),
                     session_id,
                 })
                 .await;
@@ -213,6 +365,7 @@ pub(super) async fn run_agent_turn(
         .send(DisplayEvent::Done {
             messages: final_messages,
             session_id,
+            hit_max_turns,
         })
         .await;
 }
@@ -1,16 +1,27 @@
 use std::collections::HashSet;
+use std::sync::Arc;
 use std::time::Instant;
 
-use krabs_core::AgentPersona;
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use krabs_core::{AgentPersona, AutoApprover, BrandingConfig, ProviderError};
+use ratatui::layout::{Position, Rect};
 
-use super::types::{ChatMsg, ModelPicker, PendingPermission, PendingUserInput};
+use super::commands::{at_suggestions, slash_suggestions};
+use super::types::{
+    ActiveSkill, Brand, ChatMsg, ExpandedOutput, ModelPicker, PendingPermission, PendingUserInput,
+    RenderOpts, RenderedMsg,
+};
 
 // ── app state ────────────────────────────────────────────────────────────────
 
 pub(super) struct App {
     pub(super) chat: Vec<ChatMsg>,
+    /// The message being typed; may span several lines.
     pub(super) input: String,
+    /// Byte offset of the cursor in `input`.
     pub(super) cursor: usize,
+    /// Rows the input box grows to before it scrolls (config `input_max_lines`).
+    pub(super) input_max_lines: u16,
     pub(super) scroll: u16,
     /// Last computed max scroll offset (visual rows). Updated each render frame.
     pub(super) max_scroll: u16,
@@ -22,15 +33,32 @@ pub(super) struct App {
     pub(super) turn_start: Option<Instant>,
     pub(super) total_input: u32,
     pub(super) total_output: u32,
+    /// Prompt-cache token totals reported by the provider (Anthropic only).
+    pub(super) total_cache_read: u32,
+    pub(super) total_cache_creation: u32,
+    /// Tokens the next request takes up in the context window, counted by
+    /// the provider after each turn. `None` until then, and the context bars
+    /// fall back to the usage totals.
+    pub(super) context_tokens: Option<u32>,
     pub(super) suggest_idx: Option<usize>, // selected index in suggestion popup
     pub(super) active_persona: Option<AgentPersona>,
+    /// Skills loaded with `/skill use`, in the order they were added.
+    pub(super) active_skills: Vec<ActiveSkill>,
     pub(super) system_prompt_text: String,
     pub(super) persona_text: String,
     pub(super) tools_text: String,
     pub(super) memory_text: String,
     pub(super) personas: Vec<AgentPersona>,
-    /// Tools approved with "always allow" — no prompt on subsequent calls.
+    /// Tools approved for this run only (config `auto_approve_tools` and
+    /// `/tools allow`) — no prompt on subsequent calls.
+    /// Entries are exact tool names or glob patterns (e.g. `mcp__github__*`).
     pub(super) approved_tools: HashSet<String>,
+    /// Persisted "always this session" approvals for the current session.
+    pub(super) session_approvals: HashSet<String>,
+    /// Persisted "always everywhere" approvals.
+    pub(super) global_approvals: HashSet<String>,
+    /// Config `auto_approve` rules, shared with each turn's permission hook.
+    pub(super) auto_approver: Arc<AutoApprover>,
     /// Active permission prompt waiting for y / a / n keypress.
     pub(super) pending_permission: Option<PendingPermission>,
     /// Active user-input popup waiting for the user to select / confirm.
@@ -39,6 +67,21 @@ pub(super) struct App {
     pub(super) queued_input: Option<String>,
     /// Open model picker popup (None = closed).
     pub(super) model_picker: Option<ModelPicker>,
+    /// Markdown / highlighting toggles from `KrabsConfig`.
+    pub(super) render_opts: RenderOpts,
+    /// Name, accent colour and logo from `KrabsConfig::branding`.
+    pub(super) brand: Brand,
+    /// Chat pane as drawn in the last frame, for mapping clicks to messages.
+    pub(super) chat_area: Rect,
+    /// Each chat message's lines and wrapped height from the last frame.
+    pub(super) rendered: Vec<RenderedMsg>,
+    /// Suggestion popup as drawn in the last frame (None = not shown).
+    pub(super) suggest_area: Option<Rect>,
+    /// Tool output opened in full by clicking it (None = closed).
+    pub(super) expanded: Option<ExpandedOutput>,
+    /// System clipboard, opened on the first copy and kept: on X11 and
+    /// Wayland copied text is served by the process that owns it.
+    clipboard: Option<arboard::Clipboard>,
 }
 
 impl App {
@@ -47,6 +90,7 @@ impl App {
             chat: Vec::new(),
             input: String::new(),
             cursor: 0,
+            input_max_lines: 8,
             scroll: 0,
             max_scroll: 0,
             auto_scroll: true,
@@ -58,13 +102,31 @@ impl App {
             suggest_idx: None,
             total_input: 0,
             total_output: 0,
+            total_cache_read: 0,
+            total_cache_creation: 0,
+            context_tokens: None,
             active_persona: None,
+            active_skills: Vec::new(),
             personas: Vec::new(),
             approved_tools: HashSet::new(),
+            session_approvals: HashSet::new(),
+            global_approvals: HashSet::new(),
+            auto_approver: Arc::new(AutoApprover::new(&[])),
             pending_permission: None,
             pending_user_input: None,
             queued_input: None,
             model_picker: None,
+            render_opts: RenderOpts {
+                markdown: true,
+                highlight: true,
+                show_thinking: false,
+            },
+            brand: Brand::new(&BrandingConfig::default()),
+            chat_area: Rect::default(),
+            rendered: Vec::new(),
+            suggest_area: None,
+            expanded: None,
+            clipboard: None,
             system_prompt_text: String::new(),
             persona_text: String::new(),
             tools_text: String::new(),
@@ -72,6 +134,33 @@ impl App {
         }
     }
 
+    /// The system prompt handed to each turn's agent: the active persona's
+    /// prompt after the branding intro, then the body of each active skill.
+    pub(super) fn agent_prompt(&self, branding: &BrandingConfig) -> String {
+        let mut parts: Vec<String> = Vec::with_capacity(1 + self.active_skills.len());
+        if let Some(persona) = &self.active_persona {
+            parts.push(format!(
+                "{}\n\n---\n\n{}",
+                branding.intro(),
+                persona.system_prompt
+            ));
+        }
+        parts.extend(
+            self.active_skills
+                .iter()
+                .map(|s| format!("## Skill: {}\n\n{}", s.name, s.body)),
+        );
+        parts.join("\n\n")
+    }
+
+    /// Estimated tokens the active skills add to each request.
+    pub(super) fn active_skill_tokens(&self) -> u32 {
+        self.active_skills
+            .iter()
+            .map(|s| super::types::estimate_tokens(&s.body))
+            .sum()
+    }
+
     pub(super) fn push(&mut self, msg: ChatMsg) {
         self.chat.push(msg);
         if self.auto_scroll {
@@ -79,6 +168,28 @@ impl App {
         }
     }
 
+    /// True when `tool_name` matches an "always allow" name or pattern.
+    pub(super) fn is_approved(&self, tool_name: &str) -> bool {
+        self.approved_tools
+            .iter()
+            .chain(&self.session_approvals)
+            .chain(&self.global_approvals)
+            .any(|rule| krabs_core::tool_rule_matches(rule, tool_name))
+    }
+
+    /// Replace the persisted approvals with those loaded for the current session.
+    pub(super) fn set_persisted_approvals(&mut self, approvals: Vec<krabs_core::ToolApproval>) {
+        self.session_approvals.clear();
+        self.global_approvals.clear();
+        for a in approvals {
+            if a.is_global() {
+                self.global_approvals.insert(a.rule);
+            } else {
+                self.session_approvals.insert(a.rule);
+            }
+        }
+    }
+
     pub(super) fn insert_char(&mut self, c: char) {
         self.input.insert(self.cursor, c);
         self.cursor += c.len_utf8();
@@ -116,11 +227,210 @@ impl App {
             self.cursor += n;
         }
     }
+
+    /// Insert `text` at the cursor, newlines and all; `\r\n` and `\r`
+    /// become `\n`.
+    pub(super) fn insert_str(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        self.input.insert_str(self.cursor, &text);
+        self.cursor += text.len();
+    }
+
+    /// A bracketed paste. It goes into the message as typed, line breaks
+    /// included, or onto one line of a custom answer being typed; other
+    /// popups ignore it.
+    pub(super) fn paste(&mut self, text: &str) {
+        if let Some(ui) = self.pending_user_input.as_mut() {
+            if ui.custom_mode {
+                let line = text.lines().collect::<Vec<_>>().join(" ");
+                ui.custom_text.insert_str(ui.custom_cursor, &line);
+                ui.custom_cursor += line.len();
+            }
+            return;
+        }
+        if self.pending_permission.is_some()
+            || self.model_picker.is_some()
+            || self.expanded.is_some()
+        {
+            return;
+        }
+        self.suggest_idx = None;
+        self.insert_str(text);
+    }
+
+    /// Start of the line holding byte `pos` of the input.
+    fn line_start_of(&self, pos: usize) -> usize {
+        self.input[..pos].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// End (before its newline) of the line holding byte `pos` of the input.
+    fn line_end_of(&self, pos: usize) -> usize {
+        self.input[pos..]
+            .find('\n')
+            .map_or(self.input.len(), |i| pos + i)
+    }
+
+    /// Byte offset `column` characters into the line starting at `start`,
+    /// or that line's end when it is shorter.
+    fn at_column(&self, start: usize, column: usize) -> usize {
+        let end = self.line_end_of(start);
+        self.input[start..end]
+            .char_indices()
+            .nth(column)
+            .map_or(end, |(i, _)| start + i)
+    }
+
+    /// Row and column, in characters, of the cursor in the input.
+    pub(super) fn cursor_row_col(&self) -> (usize, usize) {
+        let start = self.line_start_of(self.cursor);
+        (
+            self.input[..start].matches('\n').count(),
+            self.input[start..self.cursor].chars().count(),
+        )
+    }
+
+    pub(super) fn line_start(&mut self) {
+        self.cursor = self.line_start_of(self.cursor);
+    }
+
+    pub(super) fn line_end(&mut self) {
+        self.cursor = self.line_end_of(self.cursor);
+    }
+
+    /// Move the cursor to the line above, keeping its column where the line
+    /// is long enough. False when it is already on the first line.
+    pub(super) fn cursor_up(&mut self) -> bool {
+        let start = self.line_start_of(self.cursor);
+        if start == 0 {
+            return false;
+        }
+        let (_, column) = self.cursor_row_col();
+        self.cursor = self.at_column(self.line_start_of(start - 1), column);
+        true
+    }
+
+    /// Move the cursor to the line below, keeping its column where the line
+    /// is long enough. False when it is already on the last line.
+    pub(super) fn cursor_down(&mut self) -> bool {
+        let end = self.line_end_of(self.cursor);
+        if end == self.input.len() {
+            return false;
+        }
+        let (_, column) = self.cursor_row_col();
+        self.cursor = self.at_column(end + 1, column);
+        true
+    }
+
+    /// Put `text` on the system clipboard. Fails (rather than panicking) when
+    /// there is no clipboard, e.g. over SSH or on a headless machine.
+    pub(super) fn copy_to_clipboard(&mut self, text: &str) -> Result<(), arboard::Error> {
+        let clipboard = match &mut self.clipboard {
+            Some(c) => c,
+            none => none.insert(arboard::Clipboard::new()?),
+        };
+        clipboard.set_text(text)
+    }
+
+    /// Scroll the chat up `rows`, pausing auto-scroll.
+    pub(super) fn scroll_up(&mut self, rows: u16) {
+        self.auto_scroll = false;
+        self.scroll = self.scroll.saturating_sub(rows);
+    }
+
+    /// Scroll the chat down `rows`; reaching the bottom resumes auto-scroll.
+    pub(super) fn scroll_down(&mut self, rows: u16) {
+        self.scroll = self.scroll.saturating_add(rows);
+        if self.scroll >= self.max_scroll {
+            self.scroll = u16::MAX;
+            self.auto_scroll = true;
+        }
+    }
+
+    /// Complete the input with the selected (or first) `@persona` or slash
+    /// command suggestion.
+    pub(super) fn accept_suggestion(&mut self) {
+        let idx = self.suggest_idx.take().unwrap_or(0);
+        let completion = if self.input.starts_with('@') && !self.input.contains(' ') {
+            at_suggestions(&self.input[1..], &self.personas)
+                .get(idx)
+                .map(|(name, _)| format!("@{name}"))
+        } else {
+            slash_suggestions(&self.input)
+                .get(idx)
+                .map(|(cmd, _)| cmd.to_string())
+        };
+        if let Some(text) = completion {
+            self.input = text;
+            self.cursor = self.input.len();
+        }
+    }
+
+    /// Wheel scrolling, and left clicks on a suggestion (selects it) or on a
+    /// tool result (opens it in full). While the full output is open the
+    /// wheel scrolls it and a click closes it.
+    pub(super) fn on_mouse(&mut self, ev: MouseEvent) {
+        let clicked = matches!(ev.kind, MouseEventKind::Down(MouseButton::Left));
+        if let Some(out) = self.expanded.as_mut() {
+            match ev.kind {
+                MouseEventKind::ScrollUp => out.scroll = out.scroll.saturating_sub(3),
+                MouseEventKind::ScrollDown => out.scroll = out.scroll.saturating_add(3),
+                _ if clicked => self.expanded = None,
+                _ => {}
+            }
+            return;
+        }
+        match ev.kind {
+            MouseEventKind::ScrollUp => self.scroll_up(3),
+            MouseEventKind::ScrollDown => self.scroll_down(3),
+            _ if clicked => {
+                let pos = Position::new(ev.column, ev.row);
+                if let Some(area) = self.suggest_area.filter(|a| a.contains(pos)) {
+                    // Rows inside the border map to suggestions in order.
+                    if ev.row > area.y && ev.row + 1 < area.bottom() {
+                        self.suggest_idx = Some((ev.row - area.y - 1) as usize);
+                        self.accept_suggestion();
+                    }
+                } else if self.chat_area.contains(pos) {
+                    let hit = super::render::chat_msg_at(self, ev.row);
+                    if let Some(ChatMsg::ToolResult(content, _)) = hit.map(|i| &self.chat[i]) {
+                        self.expanded = Some(ExpandedOutput {
+                            title: " tool output — click or esc to close ".into(),
+                            content: content.clone(),
+                            scroll: 0,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Glob pattern covering every tool from the same MCP server as `tool_name`
+/// (`mcp__{server}__*`), or `None` for non-MCP tools.
+pub(super) fn mcp_server_pattern(tool_name: &str) -> Option<String> {
+    let rest = tool_name.strip_prefix("mcp__")?;
+    let (server, _) = rest.split_once("__")?;
+    Some(format!("mcp__{server}__*"))
 }
 
 // ── error formatting ──────────────────────────────────────────────────────────
 
-pub(super) fn extract_api_error(raw: &str) -> String {
+/// One-line message for a failed turn. Provider errors are described by kind;
+/// for a refused request the provider's own message is dug out of the body.
+pub(super) fn describe_error(e: &anyhow::Error) -> String {
+    match e.downcast_ref::<ProviderError>() {
+        Some(
+            ProviderError::BadRequest(body)
+            | ProviderError::ContextLengthExceeded(body)
+            | ProviderError::Network(body),
+        ) => extract_api_error(body),
+        Some(other) => format!("API error: {other}"),
+        None => extract_api_error(&e.to_string()),
+    }
+}
+
+fn extract_api_error(raw: &str) -> String {
     // Try to find JSON in the error string and extract the message field
     if let Some(start) = raw.find('[').or_else(|| raw.find('{')) {
         let json_str = &raw[start..];
@@ -140,3 +450,34 @@ pub(super) fn extract_api_error(raw: &str) -> String {
     }
     raw.to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_moves_between_lines_of_a_pasted_message() {
+        let mut app = App::new();
+        app.paste("fn main() {\r\n    panic!();\r\n}");
+        assert_eq!(app.input, "fn main() {\n    panic!();\n}");
+        assert_eq!(app.cursor_row_col(), (2, 1));
+
+        // Up keeps the column, clamped to a shorter line's end.
+        assert!(app.cursor_up());
+        assert_eq!(app.cursor_row_col(), (1, 1));
+        app.line_end();
+        assert_eq!(app.cursor_row_col(), (1, 13));
+        assert!(app.cursor_up());
+        assert_eq!(app.cursor_row_col(), (0, 11));
+        assert!(!app.cursor_up());
+        assert!(app.cursor_down());
+        assert!(app.cursor_down());
+        assert_eq!(app.cursor_row_col(), (2, 1));
+        assert!(!app.cursor_down());
+
+        app.line_start();
+        app.insert_char('\n');
+        assert_eq!(app.input, "fn main() {\n    panic!();\n\n}");
+        assert_eq!(app.cursor_row_col(), (3, 0));
+    }
+}
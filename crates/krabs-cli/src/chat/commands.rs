@@ -1,9 +1,12 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use krabs_core::{
-    skills::loader::SkillLoader, AgentPersona, BaseAgent, Credentials, CustomModelEntry,
-    HookConfig, HookEntry, KrabsConfig, LlmProvider, McpRegistry, McpServer, Message, SkillsConfig,
-    ToolRegistry,
+    output_limit, skills::loader::SkillLoader, AgentPersona, BaseAgent, ConversationContext,
+    CredentialProfiles, Credentials, CustomModelEntry, HookConfig, HookEntry, KrabsConfig,
+    LlmProvider, McpRegistry, McpServer, McpToolCache, Message, ReasoningEffort, SkillsConfig,
+    ToolDef, ToolRegistry,
 };
 
 use super::app::App;
@@ -14,12 +17,20 @@ use super::types::{ChatMsg, InfoBar, ModelEntry, ModelPicker};
 pub(super) const SLASH_COMMANDS: &[(&str, &str)] = &[
     (
         "/tools",
-        "list available tools  usage: /tools [allow <name>|deny <name>]",
+        "list available tools  usage: /tools [allow <name|glob>|deny <name|glob>]",
+    ),
+    (
+        "/permissions",
+        "list what skips the permission popup, and what each auto_approve rule approved",
     ),
     ("/skills", "list project skills"),
+    (
+        "/skill",
+        "load a skill's instructions into every turn  usage: /skill [use <name>|drop <name>]",
+    ),
     (
         "/mcp",
-        "list/add/remove MCP servers  usage: /mcp [list|add|add-sse|remove|tools]",
+        "list/add/remove MCP servers  usage: /mcp [list|add|add-sse|remove|tools|refresh|prompts|prompt|resources]",
     ),
     (
         "/hooks",
@@ -27,9 +38,68 @@ pub(super) const SLASH_COMMANDS: &[(&str, &str)] = &[
     ),
     ("/agents", "list agent personas  |  use @<name> to activate"),
     ("/models", "open model picker"),
+    (
+        "/effort",
+        "show or set reasoning effort  usage: /effort [low|medium|high|<tokens>|off]",
+    ),
+    (
+        "/set",
+        "change a request setting for this session  usage: /set max_tokens <n|off>",
+    ),
+    (
+        "/profile",
+        "list or switch credential profiles  usage: /profile [name]",
+    ),
     ("/usage", "show context window usage"),
-    ("/clear", "clear screen and conversation"),
+    (
+        "/prompt",
+        "show the system prompt sent each turn, section by section",
+    ),
+    (
+        "/clear",
+        "clear screen and conversation; later turns stay in this session",
+    ),
+    ("/new", "start a new session, keeping model and persona"),
     ("/resume", "resume a session  usage: /resume <session-id>"),
+    (
+        "/sessions",
+        "list recent sessions  usage: /sessions [--tag <label>]",
+    ),
+    ("/tag", "label the current session  usage: /tag <label>"),
+    (
+        "/errors",
+        "show errors recorded for this session  usage: /errors [--all]",
+    ),
+    (
+        "/stats",
+        "per-tool calls, errors, retries and latency  usage: /stats [--all]",
+    ),
+    ("/branch", "fork the session at its latest checkpoint"),
+    ("/compact", "summarize the conversation to free up context"),
+    (
+        "/retry",
+        "run the last message again  usage: /retry [extra instructions]",
+    ),
+    (
+        "/continue",
+        "carry on a turn that stopped at max_turns or was cancelled",
+    ),
+    (
+        "/export",
+        "write the session transcript  usage: /export <path.md|path.json>",
+    ),
+    (
+        "/restore",
+        "undo a write from its backup  usage: /restore <path>  (needs write_backups)",
+    ),
+    (
+        "/undo",
+        "revert the agent's last file changes  usage: /undo [n]",
+    ),
+    (
+        "/copy",
+        "copy an assistant reply to the clipboard  usage: /copy [n]  (ctrl+y: latest)",
+    ),
     ("/quit", "exit Krabs"),
 ];
 
@@ -64,23 +134,6 @@ pub(super) const KNOWN_MODELS: &[(&str, &[&str])] = &[
     ),
 ];
 
-pub(super) fn context_limit(model: &str) -> u32 {
-    let m = model.to_lowercase();
-    if m.contains("gemini") {
-        1_000_000
-    } else if m.contains("claude") {
-        200_000
-    } else if m.contains("gpt-4o") || m.contains("gpt-4-turbo") {
-        128_000
-    } else if m.contains("gpt-4") {
-        8_192
-    } else if m.contains("gpt-3.5") {
-        16_385
-    } else {
-        1_000_000
-    }
-}
-
 pub(super) fn slash_suggestions(prefix: &str) -> Vec<(&'static str, &'static str)> {
     SLASH_COMMANDS
         .iter()
@@ -90,18 +143,35 @@ pub(super) fn slash_suggestions(prefix: &str) -> Vec<(&'static str, &'static str
 }
 
 /// Return persona names whose names start with `prefix` (after stripping `@`).
+/// `@<name>` completions: project personas first, then the built-in agents
+/// they don't shadow.
 pub(super) fn at_suggestions<'a>(
     prefix: &str,
     personas: &'a [AgentPersona],
 ) -> Vec<(&'a str, &'a str)> {
-    personas
+    let project = personas
         .iter()
         .filter(|p| p.name.starts_with(prefix))
-        .map(|p| (p.name.as_str(), p.description.as_deref().unwrap_or("")))
-        .collect()
+        .map(|p| (p.name.as_str(), p.description.as_deref().unwrap_or("")));
+    let builtin = BaseAgent::all()
+        .iter()
+        .filter(|a| a.name().starts_with(prefix))
+        .filter(|a| !personas.iter().any(|p| p.name == a.name()))
+        .map(|a| (a.name(), a.description()));
+    project.chain(builtin).collect()
 }
 
-pub(super) fn cmd_agents(app: &mut App, args: &str) {
+/// Resolve `@<name>`: a project persona from `./krabs/agents/` wins, then a
+/// built-in [`BaseAgent`] of that name.
+pub(super) fn find_persona(name: &str, personas: &[AgentPersona]) -> Option<AgentPersona> {
+    personas
+        .iter()
+        .find(|p| p.name == name)
+        .cloned()
+        .or_else(|| BaseAgent::from_name(name).map(AgentPersona::from))
+}
+
+pub(super) fn cmd_agents(app: &mut App, args: &str, persona_paths: &[PathBuf]) {
     let parts: Vec<&str> = args.split_whitespace().collect();
     match parts.as_slice() {
         [] | ["list"] => {
@@ -110,24 +180,38 @@ pub(super) fn cmd_agents(app: &mut App, args: &str) {
             app.push(ChatMsg::Info(format!("{} built-in agent(s):", base.len())));
             for agent in base {
                 app.push(ChatMsg::Info(format!(
-                    "  @{:<20}  (built-in)",
-                    agent.name()
+                    "  @{:<20}  {} (built-in)",
+                    agent.name(),
+                    agent.description()
                 )));
             }
 
-            // ── project personas (discovered from ./krabs/agents/) ────────────
-            let personas = AgentPersona::discover();
+            // ── personas discovered in `persona_paths` ───────────────────────
+            let (personas, errors) = AgentPersona::discover_with_errors(persona_paths);
             app.personas = personas;
+            for error in errors {
+                app.push(ChatMsg::Error(error));
+            }
             if app.personas.is_empty() {
-                app.push(ChatMsg::Info(
-                    "no project personas found — add markdown files to ./krabs/agents/".into(),
-                ));
+                let dirs: Vec<String> = persona_paths
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect();
+                app.push(ChatMsg::Info(format!(
+                    "no personas found — add markdown files to {}",
+                    dirs.join(" or ")
+                )));
             } else {
                 let lines: Vec<String> = {
-                    let mut v = vec![format!("{} project persona(s):", app.personas.len())];
+                    let mut v = vec![format!("{} persona(s):", app.personas.len())];
                     for p in &app.personas {
                         let desc = p.description.as_deref().unwrap_or("");
-                        v.push(format!("  @{:<20}  {}", p.name, desc));
+                        v.push(format!(
+                            "  @{:<20}  {} ({})",
+                            p.name,
+                            desc,
+                            p.source.as_str()
+                        ));
                     }
                     v
                 };
@@ -224,6 +308,7 @@ pub(super) fn apply_model_entry(
     provider: &mut Arc<dyn LlmProvider>,
     info: &mut InfoBar,
     max_ctx: &mut u32,
+    config: &KrabsConfig,
 ) {
     creds.provider = entry.provider.clone();
     creds.model = entry.model.clone();
@@ -234,7 +319,7 @@ pub(super) fn apply_model_entry(
         creds.api_key = key.clone();
     }
     *provider = Arc::from(creds.build_provider());
-    *max_ctx = context_limit(&creds.model);
+    *max_ctx = config.context_limit(&creds.model);
     info.provider = creds.provider.clone();
     info.model = creds.model.clone();
 }
@@ -258,10 +343,176 @@ pub(super) fn cmd_models(
     });
 }
 
+/// /effort [level] — show the reasoning effort, or set it for the rest of the
+/// session. Providers and models without a reasoning control ignore it.
+pub(super) fn cmd_effort(
+    app: &mut App,
+    args: &str,
+    creds: &mut Credentials,
+    provider: &mut Arc<dyn LlmProvider>,
+) {
+    if args.is_empty() {
+        app.push(ChatMsg::Info(match creds.reasoning_effort {
+            Some(effort) => format!("reasoning effort: {effort}"),
+            None => "reasoning effort: unset (provider default)".into(),
+        }));
+        return;
+    }
+    let effort = match args {
+        "off" | "none" | "default" => None,
+        _ => match args.parse::<ReasoningEffort>() {
+            Ok(effort) => Some(effort),
+            Err(e) => {
+                app.push(ChatMsg::Error(e.to_string()));
+                return;
+            }
+        },
+    };
+    creds.reasoning_effort = effort;
+    *provider = Arc::from(creds.build_provider());
+    app.push(ChatMsg::Info(match effort {
+        Some(effort) => format!("reasoning effort set to {effort}"),
+        None => "reasoning effort unset".into(),
+    }));
+}
+
+/// /set <setting> <value> — change a request setting for the rest of the
+/// session. Only `max_tokens` for now; a value over the model's known output
+/// limit is refused.
+pub(super) fn cmd_set(
+    app: &mut App,
+    args: &str,
+    creds: &mut Credentials,
+    provider: &mut Arc<dyn LlmProvider>,
+) {
+    let (setting, value) = args.split_once(' ').unwrap_or((args, ""));
+    let value = value.trim();
+    match setting {
+        "max_tokens" | "max_output_tokens" => {
+            if value.is_empty() {
+                app.push(ChatMsg::Info(match creds.max_output_tokens {
+                    Some(n) => format!("max_tokens: {n}"),
+                    None => "max_tokens: unset (provider default)".into(),
+                }));
+                return;
+            }
+            let max = match value {
+                "off" | "none" | "default" => None,
+                _ => match value.parse::<u32>() {
+                    Ok(0) | Err(_) => {
+                        app.push(ChatMsg::Error(format!(
+                            "max_tokens must be a positive number or off, got {value:?}"
+                        )));
+                        return;
+                    }
+                    Ok(n) => Some(n),
+                },
+            };
+            if let (Some(n), Some(limit)) = (max, output_limit(&creds.model)) {
+                if n > limit {
+                    app.push(ChatMsg::Error(format!(
+                        "{} supports at most {limit} output tokens",
+                        creds.model
+                    )));
+                    return;
+                }
+            }
+            creds.max_output_tokens = max;
+            *provider = Arc::from(creds.build_provider());
+            app.push(ChatMsg::Info(match max {
+                Some(n) => format!("max_tokens set to {n}"),
+                None => "max_tokens unset".into(),
+            }));
+        }
+        "" => app.push(ChatMsg::Error("usage: /set max_tokens <n|off>".into())),
+        other => app.push(ChatMsg::Error(format!(
+            "unknown setting {other:?}; try /set max_tokens <n|off>"
+        ))),
+    }
+}
+
+/// /profile [name] — list the profiles in `~/.krabs/credentials.json`, or switch
+/// provider, model, API key and base URL to the named one.
+pub(super) fn cmd_profile(
+    app: &mut App,
+    args: &str,
+    creds: &mut Credentials,
+    provider: &mut Arc<dyn LlmProvider>,
+    info: &mut InfoBar,
+    max_ctx: &mut u32,
+    config: &KrabsConfig,
+) {
+    let profiles = match CredentialProfiles::load() {
+        Ok(p) => p,
+        Err(e) => {
+            app.push(ChatMsg::Error(format!(
+                "failed to read credentials.json: {e}"
+            )));
+            return;
+        }
+    };
+
+    if args.is_empty() {
+        if profiles.profiles.is_empty() {
+            app.push(ChatMsg::Info(format!(
+                "no profiles in {}",
+                CredentialProfiles::path().display()
+            )));
+            return;
+        }
+        app.push(ChatMsg::Info("credential profiles:".into()));
+        for name in profiles.names() {
+            let Some(p) = profiles.get(name) else {
+                continue;
+            };
+            let active = p.provider == creds.provider
+                && p.model == creds.model
+                && p.base_url == creds.base_url;
+            app.push(ChatMsg::Info(format!(
+                "  {} {:15}  {} / {}",
+                if active { "*" } else { " " },
+                name,
+                p.provider,
+                p.model
+            )));
+        }
+        return;
+    }
+
+    let Some(next) = profiles.get(args) else {
+        app.push(ChatMsg::Error(format!(
+            "unknown profile '{args}'. Available: {}",
+            profiles.names().join(", ")
+        )));
+        return;
+    };
+    creds.provider = next.provider;
+    creds.api_key = next.api_key;
+    creds.base_url = next.base_url;
+    creds.model = next.model;
+    *provider = Arc::from(creds.build_provider());
+    *max_ctx = config.context_limit(&creds.model);
+    info.provider = creds.provider.clone();
+    info.model = creds.model.clone();
+    app.push(ChatMsg::Info(format!(
+        "switched to profile {args}: {} / {} ({})",
+        creds.provider, creds.model, creds.base_url
+    )));
+}
+
 pub(super) fn cmd_tools(app: &mut App, registry: &ToolRegistry) {
     app.push(ChatMsg::Info("available tools:".into()));
     for d in registry.tool_defs() {
-        let approved = if app.approved_tools.contains(&d.name) {
+        let matches = |rules: &std::collections::HashSet<String>| {
+            rules
+                .iter()
+                .any(|r| krabs_core::tool_rule_matches(r, &d.name))
+        };
+        let approved = if matches(&app.global_approvals) {
+            " [always allowed everywhere]"
+        } else if matches(&app.session_approvals) {
+            " [always allowed this session]"
+        } else if app.is_approved(&d.name) {
             " [auto-approved]"
         } else {
             ""
@@ -271,17 +522,35 @@ pub(super) fn cmd_tools(app: &mut App, registry: &ToolRegistry) {
             d.name, d.description, approved
         )));
     }
+    if !app.global_approvals.is_empty() || !app.session_approvals.is_empty() {
+        let mut global: Vec<&str> = app.global_approvals.iter().map(String::as_str).collect();
+        let mut session: Vec<&str> = app.session_approvals.iter().map(String::as_str).collect();
+        global.sort_unstable();
+        session.sort_unstable();
+        let mut lines = vec!["saved approvals:".to_string()];
+        if !global.is_empty() {
+            lines.push(format!("  everywhere:    {}", global.join(", ")));
+        }
+        if !session.is_empty() {
+            lines.push(format!("  this session:  {}", session.join(", ")));
+        }
+        for line in lines {
+            app.push(ChatMsg::Info(line));
+        }
+    }
     app.push(ChatMsg::Info(
-        "  /tools allow <name>  pre-approve a tool (no popup)".into(),
+        "  /tools allow <name>  pre-approve a tool or glob pattern, e.g. mcp__github__*".into(),
     ));
     app.push(ChatMsg::Info(
-        "  /tools deny <name>   remove a pre-approval".into(),
+        "  /tools deny <name>   remove a pre-approval or saved approval".into(),
     ));
 }
 
 pub(super) fn cmd_tools_allow(app: &mut App, name: &str) {
     if name.is_empty() {
-        app.push(ChatMsg::Error("usage: /tools allow <tool-name>".into()));
+        app.push(ChatMsg::Error(
+            "usage: /tools allow <tool-name|pattern>".into(),
+        ));
         return;
     }
     app.approved_tools.insert(name.to_string());
@@ -290,12 +559,37 @@ pub(super) fn cmd_tools_allow(app: &mut App, name: &str) {
     )));
 }
 
-pub(super) fn cmd_tools_deny(app: &mut App, name: &str) {
+/// /tools deny — drop `name` from the in-memory approvals and revoke any saved
+/// approval for it, both global and for `session_id`.
+pub(super) async fn cmd_tools_deny(
+    app: &mut App,
+    config: &KrabsConfig,
+    session_id: Option<&str>,
+    name: &str,
+) {
     if name.is_empty() {
-        app.push(ChatMsg::Error("usage: /tools deny <tool-name>".into()));
+        app.push(ChatMsg::Error(
+            "usage: /tools deny <tool-name|pattern>".into(),
+        ));
         return;
     }
-    if app.approved_tools.remove(name) {
+    let in_memory = app.approved_tools.remove(name);
+    let in_session = app.session_approvals.remove(name);
+    let in_global = app.global_approvals.remove(name);
+    let saved = in_session || in_global;
+    if saved {
+        let result = async {
+            let store = krabs_core::SessionStore::from_config(config).await?;
+            store.revoke_tool_approval(name, session_id).await
+        }
+        .await;
+        if let Err(e) = result {
+            app.push(ChatMsg::Error(format!(
+                "revoking saved approval failed: {e}"
+            )));
+        }
+    }
+    if in_memory || saved {
         app.push(ChatMsg::Info(format!(
             "  ✗ '{name}' approval removed — popup will appear again"
         )));
@@ -304,6 +598,188 @@ pub(super) fn cmd_tools_deny(app: &mut App, name: &str) {
     }
 }
 
+/// /permissions — every approval that skips the popup: whole tools, then
+/// `auto_approve` argument rules with the calls each has approved.
+pub(super) fn cmd_permissions(app: &mut App) {
+    let sorted = |rules: &std::collections::HashSet<String>| {
+        let mut rules: Vec<&str> = rules.iter().map(String::as_str).collect();
+        rules.sort_unstable();
+        rules.join(", ")
+    };
+    let mut lines = Vec::new();
+    for (label, rules) in [
+        ("this run:     ", &app.approved_tools),
+        ("this session: ", &app.session_approvals),
+        ("everywhere:   ", &app.global_approvals),
+    ] {
+        if !rules.is_empty() {
+            lines.push(format!("  {label} {}", sorted(rules)));
+        }
+    }
+    if !lines.is_empty() {
+        lines.insert(0, "always allowed tools:".into());
+    }
+    if app.auto_approver.approves_all() {
+        lines.push(
+            "auto_approve_all is ON: every tool call runs without asking, \
+             except those matching never_auto_approve"
+                .into(),
+        );
+    }
+    if app.auto_approver.rules().next().is_some() {
+        lines.push("auto_approve rules:".into());
+        for status in app.auto_approver.rules() {
+            let rule = status.rule;
+            let outcome = match status.error {
+                Some(e) => format!("invalid pattern, never matches: {e}"),
+                None if status.hits == 1 => "1 call approved".into(),
+                None => format!("{} calls approved", status.hits),
+            };
+            lines.push(format!(
+                "  {:15}  {} ~ /{}/  {outcome}",
+                rule.tool, rule.arg, rule.arg_pattern
+            ));
+        }
+    }
+    if app.auto_approver.never_rules().next().is_some() {
+        lines.push("never_auto_approve rules (always ask):".into());
+        for status in app.auto_approver.never_rules() {
+            let rule = status.rule;
+            let outcome = match status.error {
+                Some(e) => format!("invalid pattern, every call asks: {e}"),
+                None => format!("asked {}×", status.hits),
+            };
+            lines.push(format!(
+                "  {:15}  {} ~ /{}/  {outcome}",
+                rule.tool, rule.arg, rule.arg_pattern
+            ));
+        }
+    }
+    if lines.is_empty() {
+        lines.push(
+            "nothing is auto-approved: every tool call asks first. Add rules with \
+             auto_approve_tools / auto_approve in config.json, or answer [a]/[g] at the prompt"
+                .into(),
+        );
+    }
+    for line in lines {
+        app.push(ChatMsg::Info(line));
+    }
+}
+
+/// Load the saved tool approvals that apply to `session_id` into `app`.
+pub(super) async fn load_approvals(app: &mut App, config: &KrabsConfig, session_id: Option<&str>) {
+    let result = async {
+        let store = krabs_core::SessionStore::from_config(config).await?;
+        store.tool_approvals(session_id).await
+    }
+    .await;
+    match result {
+        Ok(approvals) => app.set_persisted_approvals(approvals),
+        Err(e) => app.push(ChatMsg::Error(format!(
+            "loading saved approvals failed: {e}"
+        ))),
+    }
+}
+
+/// Remember `rule` as always allowed — in `session_id`, or everywhere when `None`.
+pub(super) async fn save_approval(
+    app: &mut App,
+    config: &KrabsConfig,
+    rule: &str,
+    session_id: Option<&str>,
+) {
+    match session_id {
+        Some(_) => app.session_approvals.insert(rule.to_string()),
+        None => app.global_approvals.insert(rule.to_string()),
+    };
+    if !config.persist_sessions {
+        return;
+    }
+    let result = async {
+        let store = krabs_core::SessionStore::from_config(config).await?;
+        store.approve_tool(rule, session_id).await
+    }
+    .await;
+    if let Err(e) = result {
+        app.push(ChatMsg::Error(format!(
+            "saving approval failed (kept for this run only): {e}"
+        )));
+    }
+}
+
+/// /skill [use <name>|drop <name>] — force-load a skill's SKILL.md body into
+/// the system prompt of every turn, or stop. With no arguments, lists the
+/// active skills.
+pub(super) async fn cmd_skill(app: &mut App, args: &str, skills_config: &SkillsConfig) {
+    use super::types::{estimate_tokens, fmt_k, ActiveSkill};
+
+    let (sub, name) = args.split_once(' ').unwrap_or((args, ""));
+    let name = name.trim();
+    match sub {
+        "" if app.active_skills.is_empty() => app.push(ChatMsg::Info(
+            "no active skills — /skill use <name> loads one".into(),
+        )),
+        "" => {
+            let lines: Vec<String> = app
+                .active_skills
+                .iter()
+                .map(|s| {
+                    format!(
+                        "  {:20}  ~{} tokens",
+                        s.name,
+                        fmt_k(estimate_tokens(&s.body))
+                    )
+                })
+                .collect();
+            app.push(ChatMsg::Info(format!("{} active skill(s):", lines.len())));
+            for line in lines {
+                app.push(ChatMsg::Info(line));
+            }
+        }
+        "use" if !name.is_empty() => {
+            if app.active_skills.iter().any(|s| s.name == name) {
+                app.push(ChatMsg::Info(format!("skill '{name}' is already active")));
+                return;
+            }
+            let skills = SkillLoader::discover(skills_config);
+            let Some(skill) = skills.iter().find(|s| s.name == name) else {
+                app.push(ChatMsg::Error(format!(
+                    "skill '{name}' not found — /skills lists them"
+                )));
+                return;
+            };
+            match skill.load_body().await {
+                Ok(body) => {
+                    app.push(ChatMsg::Info(format!(
+                        "skill '{name}' active (~{} tokens per turn) — /skill drop {name} removes it",
+                        fmt_k(estimate_tokens(&body))
+                    )));
+                    app.active_skills.push(ActiveSkill {
+                        name: skill.name.clone(),
+                        body,
+                    });
+                }
+                Err(e) => app.push(ChatMsg::Error(format!(
+                    "loading skill '{name}' failed: {e}"
+                ))),
+            }
+        }
+        "drop" if !name.is_empty() => {
+            let before = app.active_skills.len();
+            app.active_skills.retain(|s| s.name != name);
+            if app.active_skills.len() < before {
+                app.push(ChatMsg::Info(format!("skill '{name}' dropped")));
+            } else {
+                app.push(ChatMsg::Error(format!("skill '{name}' is not active")));
+            }
+        }
+        _ => app.push(ChatMsg::Error(
+            "usage: /skill [use <name>|drop <name>]".into(),
+        )),
+    }
+}
+
 pub(super) fn cmd_skills(app: &mut App, skills_config: &SkillsConfig) {
     let skills = SkillLoader::discover(skills_config);
     if skills.is_empty() {
@@ -323,6 +799,9 @@ pub(super) fn cmd_skills(app: &mut App, skills_config: &SkillsConfig) {
 /// /mcp add-sse <name> <url>     — add an SSE server
 /// /mcp remove <name>            — remove a server
 /// /mcp tools                    — list tools from all connected servers
+/// /mcp prompts                  — list prompts from all connected servers
+/// /mcp resources                — list resources from all connected servers
+/// /mcp refresh                  — re-discover tools and rewrite the tool cache
 pub(super) async fn cmd_mcp(app: &mut App, args: &str) {
     let parts: Vec<&str> = args.split_whitespace().collect();
 
@@ -412,21 +891,196 @@ pub(super) async fn cmd_mcp(app: &mut App, args: &str) {
             }
         }
 
+        ["refresh"] => {
+            let reg = McpRegistry::load().await;
+            if reg.servers.is_empty() {
+                app.push(ChatMsg::Info("no MCP servers configured".into()));
+                return;
+            }
+            app.push(ChatMsg::Info("re-discovering MCP tools…".into()));
+            match reg.refresh_cache(&McpToolCache::path()).await {
+                Ok(results) => {
+                    for (name, result) in results {
+                        match result {
+                            Ok(n) => app.push(ChatMsg::Info(format!("  ● {name:20}  {n} tools"))),
+                            Err(e) => app.push(ChatMsg::Error(format!("  {name}: {e}"))),
+                        }
+                    }
+                }
+                Err(e) => app.push(ChatMsg::Error(format!("failed to save tool cache: {e}"))),
+            }
+        }
+
+        ["prompts"] => {
+            let reg = McpRegistry::load().await;
+            if reg.servers.is_empty() {
+                app.push(ChatMsg::Info("no MCP servers configured".into()));
+                return;
+            }
+            app.push(ChatMsg::Info("connecting to MCP servers…".into()));
+            let live = reg.connect_all().await;
+            if live.is_empty() {
+                app.push(ChatMsg::Error("no servers connected".into()));
+                return;
+            }
+            let prompts = live.prompts_for_all().await;
+            if prompts.is_empty() {
+                app.push(ChatMsg::Info("no prompts discovered".into()));
+                return;
+            }
+            app.push(ChatMsg::Info(format!("{} MCP prompts:", prompts.len())));
+            for (server, p) in &prompts {
+                let args: Vec<String> = p
+                    .arguments
+                    .iter()
+                    .map(|a| {
+                        if a.required {
+                            format!("{}=…", a.name)
+                        } else {
+                            format!("[{}=…]", a.name)
+                        }
+                    })
+                    .collect();
+                app.push(ChatMsg::Info(format!(
+                    "  {server}/{} {}  {}",
+                    p.name,
+                    args.join(" "),
+                    p.description
+                )));
+            }
+            app.push(ChatMsg::Info(
+                "  /mcp prompt <server>/<name> [key=value…] sends it as your next message".into(),
+            ));
+        }
+
+        ["resources"] => {
+            let reg = McpRegistry::load().await;
+            if reg.servers.is_empty() {
+                app.push(ChatMsg::Info("no MCP servers configured".into()));
+                return;
+            }
+            app.push(ChatMsg::Info("connecting to MCP servers…".into()));
+            let live = reg.connect_all().await;
+            if live.is_empty() {
+                app.push(ChatMsg::Error("no servers connected".into()));
+                return;
+            }
+            let resources = live.resources_for_all().await;
+            if resources.is_empty() {
+                app.push(ChatMsg::Info("no resources discovered".into()));
+                return;
+            }
+            app.push(ChatMsg::Info(format!("{} MCP resources:", resources.len())));
+            for (server, r) in &resources {
+                app.push(ChatMsg::Info(format!(
+                    "  {server}/{}  {}  {}",
+                    r.name, r.uri, r.description
+                )));
+            }
+        }
+
         _ => {
             app.push(ChatMsg::Info(
-                "usage: /mcp [list|add <name> <cmd> [args…]|add-sse <name> <url>|remove <name>|tools]".into(),
+                "usage: /mcp [list|add <name> <cmd> [args…]|add-sse <name> <url>|remove <name>|tools|refresh|prompts|prompt <server>/<name> [key=value…]|resources]".into(),
             ));
         }
     }
 }
 
+/// Parse `key=value` prompt arguments. A word without `=` continues the
+/// previous value, so `topic=async rust` sets `topic` to "async rust".
+fn parse_prompt_args(words: &[&str]) -> HashMap<String, String> {
+    let mut args = HashMap::new();
+    let mut last: Option<String> = None;
+    for word in words {
+        match word.split_once('=') {
+            Some((key, value)) => {
+                args.insert(key.to_string(), value.to_string());
+                last = Some(key.to_string());
+            }
+            None => {
+                if let Some(value) = last.as_ref().and_then(|k| args.get_mut(k)) {
+                    value.push(' ');
+                    value.push_str(word);
+                }
+            }
+        }
+    }
+    args
+}
+
+/// /mcp prompt <server>/<name> [key=value…]
+///
+/// Fetch an MCP prompt with its arguments substituted and return its text, to
+/// be sent as the next user message. `None` (with an error shown) on failure.
+pub(super) async fn cmd_mcp_prompt(app: &mut App, args: &str) -> Option<String> {
+    let words: Vec<&str> = args.split_whitespace().collect();
+    let Some((server, name)) = words.first().and_then(|w| w.split_once('/')) else {
+        app.push(ChatMsg::Error(
+            "usage: /mcp prompt <server>/<name> [key=value…]  (see /mcp prompts)".into(),
+        ));
+        return None;
+    };
+    let arguments = parse_prompt_args(&words[1..]);
+
+    let result = async {
+        let mut reg = McpRegistry::load().await;
+        reg.servers.retain(|s| s.name == server);
+        if reg.servers.is_empty() {
+            anyhow::bail!("server '{server}' not found");
+        }
+        let live = reg.connect_all().await;
+        if live.is_empty() {
+            anyhow::bail!("server '{server}' did not connect");
+        }
+        let info = live
+            .prompts_for_all()
+            .await
+            .into_iter()
+            .find_map(|(_, p)| (p.name == name).then_some(p))
+            .ok_or_else(|| anyhow::anyhow!("prompt '{name}' not found on '{server}'"))?;
+        let missing: Vec<&str> = info
+            .arguments
+            .iter()
+            .filter(|a| a.required && !arguments.contains_key(&a.name))
+            .map(|a| a.name.as_str())
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!("missing required argument(s): {}", missing.join(", "));
+        }
+        let messages = live.get_prompt(server, name, &arguments).await?;
+        let text = messages
+            .iter()
+            .map(|m| m.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        if text.trim().is_empty() {
+            anyhow::bail!("prompt '{name}' has no text content");
+        }
+        anyhow::Ok(text)
+    }
+    .await;
+
+    match result {
+        Ok(text) => {
+            app.push(ChatMsg::Info(format!("using MCP prompt {server}/{name}")));
+            Some(text)
+        }
+        Err(e) => {
+            app.push(ChatMsg::Error(format!("/mcp prompt: {e}")));
+            None
+        }
+    }
+}
+
 /// /hooks [list]
 /// /hooks add <name> <event> [matcher] [action] [reason…]
 /// /hooks remove <name>
 ///
 /// event   : AgentStart | AgentStop | TurnStart | TurnEnd |
-///           PreToolUse | PostToolUse | PostToolUseFailure
-/// action  : deny | stop | log  (default: log)
+///           PreToolUse | PostToolUse | PostToolUseFailure | UserMessage
+/// action  : deny | stop | log | exec  (default: log); for exec the trailing
+///           text is the shell command to run
 pub(super) fn cmd_hooks(app: &mut App, args: &str) {
     let mut config = HookConfig::load();
     let parts: Vec<&str> = args.split_whitespace().collect();
@@ -442,7 +1096,7 @@ pub(super) fn cmd_hooks(app: &mut App, args: &str) {
                 app.push(ChatMsg::Info(format!("{} hook(s):", config.hooks.len())));
                 for h in &config.hooks {
                     let matcher = h.matcher.as_deref().unwrap_or("*");
-                    let reason = h.reason.as_deref().unwrap_or("");
+                    let reason = h.command.as_deref().or(h.reason.as_deref()).unwrap_or("");
                     app.push(ChatMsg::Info(format!(
                         "  {:20}  event={:<22}  matcher={:<12}  action={:<6}  {}",
                         h.name, h.event, matcher, h.action, reason,
@@ -453,13 +1107,20 @@ pub(super) fn cmd_hooks(app: &mut App, args: &str) {
 
         // /hooks add <name> <event> [matcher] [action] [reason…]
         ["add", name, event, rest @ ..] => {
-            let (matcher, action, reason) = parse_hook_rest(rest);
+            let (matcher, action, mut reason) = parse_hook_rest(rest);
+            let command = if action == "exec" {
+                reason.take()
+            } else {
+                None
+            };
             let entry = HookEntry {
                 name: name.to_string(),
                 event: event.to_string(),
                 matcher,
                 action,
                 reason,
+                command,
+                timeout_ms: None,
             };
             config.add(entry);
             match config.save() {
@@ -490,10 +1151,10 @@ pub(super) fn cmd_hooks(app: &mut App, args: &str) {
 
 /// Parse the trailing `[matcher] [action] [reason…]` tokens.
 /// matcher — any token that is not a known action keyword
-/// action  — deny | stop | log  (default: log)
+/// action  — deny | stop | log | exec  (default: log)
 /// reason  — remaining tokens joined by space
 pub(super) fn parse_hook_rest(rest: &[&str]) -> (Option<String>, String, Option<String>) {
-    const ACTIONS: &[&str] = &["deny", "stop", "log"];
+    const ACTIONS: &[&str] = &["deny", "stop", "log", "exec"];
     let mut matcher: Option<String> = None;
     let mut action = "log".to_string();
     let mut reason_parts: Vec<&str> = Vec::new();
@@ -520,11 +1181,89 @@ pub(super) fn parse_hook_rest(rest: &[&str]) -> (Option<String>, String, Option<
     (matcher, action, reason)
 }
 
-pub(super) async fn cmd_usage(app: &mut App, max_ctx: u32, skills_config: &SkillsConfig) {
+/// Count the next request — system prompt, history and tool definitions —
+/// with the provider's tokenizer, for the context bars. After a turn the
+/// history starts with the agent's system prompt; before one, the prompt
+/// shown in `/usage` stands in for it.
+pub(super) async fn count_context(
+    app: &mut App,
+    provider: &dyn LlmProvider,
+    messages: &[Message],
+    tools: &[ToolDef],
+) {
+    let mut request = Vec::with_capacity(messages.len() + 1);
+    if !messages
+        .first()
+        .is_some_and(|m| matches!(m.role, krabs_core::Role::System))
+    {
+        let system = format!("{}\n\n{}", app.system_prompt_text, app.persona_text);
+        request.push(Message::system(system.trim()));
+    }
+    request.extend_from_slice(messages);
+    if let Ok(tokens) = provider.count_tokens(&request, tools).await {
+        app.context_tokens = Some(tokens);
+    }
+}
+
+/// /prompt — open the system prompt a turn would send now in a scrollable
+/// popup, each section headed by its size in bytes and estimated tokens.
+pub(super) async fn cmd_prompt(
+    app: &mut App,
+    config: &KrabsConfig,
+    provider: Arc<dyn LlmProvider>,
+    memory_store: Option<&krabs_core::SqliteMemoryStore>,
+) {
+    use super::types::{estimate_tokens, fmt_k, ExpandedOutput};
+
+    // Built like a turn's agent, minus the session, tools and hooks, which
+    // don't change the prompt; the global memory does.
+    let mut builder = krabs_core::KrabsAgentBuilder::new(config.clone(), provider)
+        .system_prompt(app.agent_prompt(&config.branding));
+    if let Some(store) = memory_store {
+        let global = store.with_namespace(krabs_core::GLOBAL_NAMESPACE);
+        builder = builder.global_memory(Arc::new(global));
+    }
+    let agent = builder.build();
+    let sections = agent.system_prompt_sections().await;
+    let total: usize =
+        sections.iter().map(|s| s.text.len()).sum::<usize>() + 2 * sections.len().saturating_sub(1);
+
+    let mut content = format!(
+        "{total} bytes, ~{} tokens, {} sections\n",
+        fmt_k((total as f32 / 4.0).ceil() as u32),
+        sections.len()
+    );
+    for s in &sections {
+        content.push_str(&format!(
+            "\n── {} · {} bytes, ~{} tokens ──\n\n{}\n",
+            s.name,
+            s.text.len(),
+            fmt_k(estimate_tokens(&s.text)),
+            s.text
+        ));
+    }
+    app.expanded = Some(ExpandedOutput {
+        title: " system prompt — esc to close ".into(),
+        content,
+        scroll: 0,
+    });
+}
+
+pub(super) async fn cmd_usage(
+    app: &mut App,
+    max_ctx: u32,
+    skills_config: &SkillsConfig,
+    provider: &dyn LlmProvider,
+    messages: &[Message],
+    tools: &[ToolDef],
+) {
     use super::types::{estimate_tokens, fmt_k};
     const BAR: usize = 40;
 
-    let used = app.total_input + app.total_output;
+    count_context(app, provider, messages, tools).await;
+    let used = app
+        .context_tokens
+        .unwrap_or(app.total_input + app.total_output);
     let pct = (used as f32 / max_ctx as f32 * 100.0).min(100.0);
 
     // Compute estimated token counts per category
@@ -541,9 +1280,10 @@ pub(super) async fn cmd_usage(app: &mut App, max_ctx: u32, skills_config: &Skill
         .collect::<Vec<_>>()
         .join("\n");
     let t_skills = estimate_tokens(&skills_text);
+    let t_active = app.active_skill_tokens();
 
     // Messages estimate from API-reported totals, minus estimated overhead
-    let overhead = t_system + t_persona + t_memory + t_tools + t_skills;
+    let overhead = t_system + t_persona + t_memory + t_tools + t_skills + t_active;
     let t_messages = used.saturating_sub(overhead);
     let t_free = max_ctx.saturating_sub(used);
 
@@ -552,9 +1292,10 @@ pub(super) async fn cmd_usage(app: &mut App, max_ctx: u32, skills_config: &Skill
 
     let segs = [
         (seg(t_system), 'S', ratatui::style::Color::Green),
-        (seg(t_persona), 'P', ratatui::style::Color::Rgb(255, 128, 0)),
+        (seg(t_persona), 'P', app.brand.accent),
         (seg(t_tools), 'T', ratatui::style::Color::Magenta),
         (seg(t_skills), 'K', ratatui::style::Color::LightGreen),
+        (seg(t_active), 'A', ratatui::style::Color::Yellow),
         (seg(t_memory), 'M', ratatui::style::Color::Blue),
         (seg(t_messages), 'C', ratatui::style::Color::Cyan),
         (seg(t_free), 'F', ratatui::style::Color::DarkGray),
@@ -573,9 +1314,10 @@ pub(super) async fn cmd_usage(app: &mut App, max_ctx: u32, skills_config: &Skill
         ("persona ", t_persona, 1),
         ("tools   ", t_tools, 2),
         ("skills  ", t_skills, 3),
-        ("memory  ", t_memory, 4),
-        ("messages", t_messages, 5),
-        ("free    ", t_free, 6),
+        ("active  ", t_active, 4),
+        ("memory  ", t_memory, 5),
+        ("messages", t_messages, 6),
+        ("free    ", t_free, 7),
     ];
 
     for (label, tok, idx) in &rows {
@@ -597,6 +1339,587 @@ pub(super) async fn cmd_usage(app: &mut App, max_ctx: u32, skills_config: &Skill
         fmt_k(used),
         fmt_k(max_ctx)
     )));
+
+    let cached = app.total_cache_read + app.total_cache_creation;
+    if cached > 0 {
+        // Anthropic reports cached tokens separately from input_tokens.
+        let prompt = app.total_input + cached;
+        let hit = app.total_cache_read as f32 / prompt as f32 * 100.0;
+        app.push(ChatMsg::Info(String::new()));
+        app.push(ChatMsg::Info(format!(
+            "prompt cache  {hit:.1}% hit  ({} read, {} written, {} uncached)",
+            fmt_k(app.total_cache_read),
+            fmt_k(app.total_cache_creation),
+            fmt_k(app.total_input)
+        )));
+    }
+}
+
+/// /branch — fork `session_id` at its latest checkpoint.
+/// Returns the new session ID and its `(input, output)` token totals.
+pub(super) async fn cmd_branch(
+    app: &mut App,
+    config: &KrabsConfig,
+    session_id: &str,
+) -> Option<(String, (u32, u32))> {
+    use krabs_core::SessionStore;
+
+    let result = async {
+        let store = SessionStore::from_config(config).await?;
+        let session = store.load_session(session_id).await?;
+        let cp = session
+            .latest_checkpoint()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("session has no checkpoint yet"))?;
+        let branch = session.branch_from(cp.last_msg_id).await?;
+        let totals = branch.total_token_usage().await?;
+        anyhow::Ok((branch.id.clone(), totals))
+    }
+    .await;
+
+    match result {
+        Ok(v) => Some(v),
+        Err(e) => {
+            app.push(ChatMsg::Error(format!("branch failed: {e}")));
+            None
+        }
+    }
+}
+
+/// Sessions listed by `/sessions` before the rest are summarised as a count.
+const SESSIONS_SHOWN: usize = 20;
+
+fn fmt_age(created_at: i64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let secs = (now - created_at).max(0);
+    match secs {
+        s if s < 60 => "just now".to_string(),
+        s if s < 3_600 => format!("{}m ago", s / 60),
+        s if s < 86_400 => format!("{}h ago", s / 3_600),
+        s => format!("{}d ago", s / 86_400),
+    }
+}
+
+/// /sessions — list recent sessions, newest first. `args` may be `--tag <label>`.
+pub(super) async fn cmd_sessions(app: &mut App, config: &KrabsConfig, args: &str) {
+    use krabs_core::SessionStore;
+
+    let tag = match args.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] => None,
+        ["--tag", label] => Some(*label),
+        _ => {
+            app.push(ChatMsg::Error("usage: /sessions [--tag <label>]".into()));
+            return;
+        }
+    };
+
+    let result = async {
+        let store = SessionStore::from_config(config).await?;
+        store.list_sessions(tag).await
+    }
+    .await;
+
+    let sessions = match result {
+        Ok(s) => s,
+        Err(e) => {
+            app.push(ChatMsg::Error(format!("listing sessions failed: {e}")));
+            return;
+        }
+    };
+    if sessions.is_empty() {
+        app.push(ChatMsg::Info(match tag {
+            Some(t) => format!("no sessions tagged '{t}'"),
+            None => "no sessions yet".into(),
+        }));
+        return;
+    }
+    for s in sessions.iter().take(SESSIONS_SHOWN) {
+        let tags = if s.tags.is_empty() {
+            String::new()
+        } else {
+            format!("  [{}]", s.tags.join(", "))
+        };
+        let title = s.title.as_deref().unwrap_or("(empty)");
+        app.push(ChatMsg::Info(format!(
+            "  {}  {:<10}  {title}  · {}{tags}",
+            s.id,
+            fmt_age(s.created_at),
+            s.model
+        )));
+    }
+    if sessions.len() > SESSIONS_SHOWN {
+        app.push(ChatMsg::Info(format!(
+            "  … {} more",
+            sessions.len() - SESSIONS_SHOWN
+        )));
+    }
+    app.push(ChatMsg::Info("resume one with /resume <session-id>".into()));
+}
+
+/// /errors — the errors persisted for `session_id`, grouped by turn.
+pub(super) async fn cmd_errors(app: &mut App, config: &KrabsConfig, session_id: &str) {
+    use krabs_core::SessionStore;
+
+    let result = async {
+        let store = SessionStore::from_config(config).await?;
+        store.load_session(session_id).await?.errors().await
+    }
+    .await;
+
+    let errors = match result {
+        Ok(e) => e,
+        Err(e) => {
+            app.push(ChatMsg::Error(format!("loading errors failed: {e}")));
+            return;
+        }
+    };
+    if errors.is_empty() {
+        app.push(ChatMsg::Info("no errors recorded for this session".into()));
+        return;
+    }
+    app.push(ChatMsg::Info(format!(
+        "{} error(s) in this session",
+        errors.len()
+    )));
+    let mut turn = None;
+    for e in &errors {
+        if turn != Some(e.turn) {
+            turn = Some(e.turn);
+            app.push(ChatMsg::Info(format!("  turn {}", e.turn)));
+        }
+        app.push(ChatMsg::Info(format!(
+            "    {}  attempt {}  · {}",
+            e.context,
+            e.attempt + 1,
+            fmt_age(e.created_at)
+        )));
+        for line in e.message.lines() {
+            app.push(ChatMsg::Error(format!("      {line}")));
+        }
+    }
+}
+
+/// /errors --all — error counts per session and context, across every session.
+pub(super) async fn cmd_errors_all(app: &mut App, config: &KrabsConfig) {
+    use krabs_core::SessionStore;
+
+    let result = async {
+        let store = SessionStore::from_config(config).await?;
+        store.error_counts().await
+    }
+    .await;
+
+    let counts = match result {
+        Ok(c) => c,
+        Err(e) => {
+            app.push(ChatMsg::Error(format!("loading errors failed: {e}")));
+            return;
+        }
+    };
+    if counts.is_empty() {
+        app.push(ChatMsg::Info("no errors recorded in any session".into()));
+        return;
+    }
+    // Rows arrive newest first; keep that order while grouping by session.
+    let mut sessions: Vec<(&str, i64, Vec<String>, usize)> = Vec::new();
+    for c in &counts {
+        let idx = match sessions.iter().position(|(sid, ..)| *sid == c.session_id) {
+            Some(idx) => idx,
+            None => {
+                sessions.push((&c.session_id, c.last_at, Vec::new(), 0));
+                sessions.len() - 1
+            }
+        };
+        let entry = &mut sessions[idx];
+        entry.2.push(format!("{} ×{}", c.context, c.count));
+        entry.3 += c.count;
+    }
+    let total: usize = sessions.iter().map(|(.., n)| n).sum();
+    app.push(ChatMsg::Info(format!(
+        "{total} error(s) across {} session(s)",
+        sessions.len()
+    )));
+    for (sid, last_at, kinds, n) in sessions.iter().take(SESSIONS_SHOWN) {
+        app.push(ChatMsg::Info(format!(
+            "  {sid}  {n:>4}  last {:<10}  {}",
+            fmt_age(*last_at),
+            kinds.join(", ")
+        )));
+    }
+    if sessions.len() > SESSIONS_SHOWN {
+        app.push(ChatMsg::Info(format!(
+            "  … {} more",
+            sessions.len() - SESSIONS_SHOWN
+        )));
+    }
+}
+
+/// /stats [--all] — per-tool call counts, errors, retries and latency for
+/// `session_id`, or summed over every session when `None`.
+pub(super) async fn cmd_stats(app: &mut App, config: &KrabsConfig, session_id: Option<&str>) {
+    use krabs_core::SessionStore;
+
+    let result = async {
+        let store = SessionStore::from_config(config).await?;
+        match session_id {
+            Some(sid) => store.load_session(sid).await?.tool_stats().await,
+            None => store.tool_stats_all().await,
+        }
+    }
+    .await;
+
+    let stats = match result {
+        Ok(s) => s,
+        Err(e) => {
+            app.push(ChatMsg::Error(format!("loading tool stats failed: {e}")));
+            return;
+        }
+    };
+    let scope = if session_id.is_some() {
+        "this session"
+    } else {
+        "all sessions"
+    };
+    if stats.is_empty() {
+        app.push(ChatMsg::Info(format!("no tool calls recorded in {scope}")));
+        return;
+    }
+    let calls: usize = stats.iter().map(|s| s.calls).sum();
+    app.push(ChatMsg::Info(format!(
+        "{calls} tool call(s) in {scope}, by tool:"
+    )));
+    app.push(ChatMsg::Info(format!(
+        "  {:<28} {:>6} {:>6} {:>7} {:>8} {:>9}",
+        "tool", "calls", "errors", "retries", "avg", "total"
+    )));
+    for s in &stats {
+        let line = format!(
+            "  {:<28} {:>6} {:>6} {:>7} {:>8} {:>9}",
+            s.tool_name,
+            s.calls,
+            s.errors,
+            s.retries,
+            fmt_ms(s.avg_ms()),
+            fmt_ms(s.total_ms)
+        );
+        // Flag tools that fail more often than they succeed.
+        if s.errors * 2 > s.calls {
+            app.push(ChatMsg::Error(line));
+        } else {
+            app.push(ChatMsg::Info(line));
+        }
+    }
+}
+
+/// `ms` as `850ms`, `12.3s` or `4m05s`.
+fn fmt_ms(ms: u64) -> String {
+    match ms {
+        0..=999 => format!("{ms}ms"),
+        1_000..=59_999 => format!("{:.1}s", ms as f64 / 1000.0),
+        _ => format!("{}m{:02}s", ms / 60_000, ms / 1000 % 60),
+    }
+}
+
+/// /tag — add `label` to `session_id`'s tags.
+pub(super) async fn cmd_tag(app: &mut App, config: &KrabsConfig, session_id: &str, label: &str) {
+    use krabs_core::SessionStore;
+
+    let result = async {
+        let store = SessionStore::from_config(config).await?;
+        let session = store.load_session(session_id).await?;
+        let added = session.add_tag(label).await?;
+        let tags = session.tags().await?;
+        anyhow::Ok((added, tags))
+    }
+    .await;
+
+    match result {
+        Ok((true, tags)) => app.push(ChatMsg::Info(format!(
+            "tagged session '{label}'  (tags: {})",
+            tags.join(", ")
+        ))),
+        Ok((false, _)) => app.push(ChatMsg::Info(format!("session already tagged '{label}'"))),
+        Err(e) => app.push(ChatMsg::Error(format!("tag failed: {e}"))),
+    }
+}
+
+/// /export — write `session_id`'s transcript to `path` as Markdown or JSON,
+/// chosen by the file extension.
+pub(super) async fn cmd_export(app: &mut App, config: &KrabsConfig, session_id: &str, path: &str) {
+    use krabs_core::{session::export, SessionStore};
+
+    let path = std::path::Path::new(path);
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let result = async {
+        let store = SessionStore::from_config(config).await?;
+        let session = store.load_session(session_id).await?;
+        let messages = session.messages().await?;
+        let usage = session.token_usage().await?;
+        let body = match ext.as_deref() {
+            Some("md") | Some("markdown") => export::to_markdown(&messages, &usage),
+            Some("json") => export::to_json(&messages, &usage)?,
+            _ => anyhow::bail!("unsupported extension — use .md or .json"),
+        };
+        tokio::fs::write(path, body).await?;
+        anyhow::Ok(messages.len())
+    }
+    .await;
+
+    match result {
+        Ok(n) => app.push(ChatMsg::Info(format!(
+            "exported {n} messages to {}",
+            path.display()
+        ))),
+        Err(e) => app.push(ChatMsg::Error(format!("export failed: {e}"))),
+    }
+}
+
+/// /restore <path> — put back the copy `write` saved before it last
+/// overwrote `path`.
+pub(super) async fn cmd_restore(app: &mut App, config: &KrabsConfig, path: &str) {
+    if path.is_empty() {
+        app.push(ChatMsg::Error("usage: /restore <path>".into()));
+        return;
+    }
+    let path = std::path::Path::new(path);
+    match krabs_core::restore_backup(path, &config.write_backup_suffix).await {
+        Ok(backup) => app.push(ChatMsg::Info(format!(
+            "restored {} from {}",
+            path.display(),
+            backup.display()
+        ))),
+        Err(e) if !config.write_backups => app.push(ChatMsg::Error(format!(
+            "restore failed: {e} (write_backups is off)"
+        ))),
+        Err(e) => app.push(ChatMsg::Error(format!("restore failed: {e}"))),
+    }
+}
+
+/// /undo [n] — revert the last `n` file changes (default 1) the agent made
+/// with `write` or `apply_patch` in `session_id`, from its file snapshots.
+pub(super) async fn cmd_undo(app: &mut App, config: &KrabsConfig, session_id: &str, args: &str) {
+    use krabs_core::{session::snapshot, SessionStore};
+
+    let n = if args.is_empty() {
+        1
+    } else {
+        match args.parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => {
+                app.push(ChatMsg::Error("usage: /undo [n]".into()));
+                return;
+            }
+        }
+    };
+    let result = async {
+        let store = SessionStore::from_config(config).await?;
+        let session = store.load_session(session_id).await?;
+        snapshot::undo(&session, n).await
+    }
+    .await;
+    match result {
+        Ok(paths) if paths.is_empty() => app.push(ChatMsg::Info("nothing to undo".into())),
+        Ok(paths) => app.push(ChatMsg::Info(format!("restored {}", paths.join(", ")))),
+        Err(e) => app.push(ChatMsg::Error(format!("undo failed: {e}"))),
+    }
+}
+
+/// /copy [n] — copy the nth assistant reply (1 = first; default: the latest)
+/// to the system clipboard. Ctrl+Y runs it with no argument.
+pub(super) fn cmd_copy(app: &mut App, args: &str) {
+    let replies: Vec<&str> = app
+        .chat
+        .iter()
+        .filter_map(|m| match m {
+            ChatMsg::Assistant(t) => Some(t.as_str()),
+            _ => None,
+        })
+        .collect();
+    let index = if args.is_empty() {
+        replies.len().checked_sub(1)
+    } else {
+        match args.parse::<usize>() {
+            Ok(n) if n >= 1 => Some(n - 1),
+            _ => {
+                app.push(ChatMsg::Error(
+                    "usage: /copy [n]  (n = 1 for the first reply)".into(),
+                ));
+                return;
+            }
+        }
+    };
+    let total = replies.len();
+    let Some(text) = index.and_then(|i| replies.get(i)).map(|t| t.to_string()) else {
+        app.push(ChatMsg::Error(if total == 0 {
+            "no assistant reply to copy yet".into()
+        } else {
+            format!("no reply #{args} — this conversation has {total}")
+        }));
+        return;
+    };
+    let n = index.map_or(total, |i| i + 1);
+    match app.copy_to_clipboard(&text) {
+        Ok(()) => app.push(ChatMsg::Info(format!(
+            "copied reply {n}/{total} ({} chars)",
+            text.chars().count()
+        ))),
+        Err(e) => app.push(ChatMsg::Error(format!(
+            "copy failed — no clipboard available ({e}); over SSH or without a display, \
+             use /export instead"
+        ))),
+    }
+}
+
+/// /compact — replace the live conversation with an LLM-written summary and,
+/// when the session is persisted, save the summary so a resume starts from it.
+pub(super) async fn cmd_compact(
+    app: &mut App,
+    config: &KrabsConfig,
+    provider: &dyn LlmProvider,
+    ctx: &mut ConversationContext,
+    session_id: Option<&str>,
+) {
+    use krabs_core::{agents::compact, SessionStore};
+
+    let compaction =
+        match compact::compact(provider, ctx.messages(), config.compact_keep_tool_results).await {
+            Ok(Some(c)) => c,
+            Ok(None) => {
+                app.push(ChatMsg::Info(
+                    "conversation is already short — nothing to compact".into(),
+                ));
+                return;
+            }
+            Err(e) => {
+                app.push(ChatMsg::Error(format!("compact failed: {e}")));
+                return;
+            }
+        };
+
+    let reclaimed = compaction.tokens_reclaimed();
+    app.chat.clear();
+    app.push(ChatMsg::Info(format!(
+        "── conversation compacted · ~{reclaimed} tokens reclaimed ──"
+    )));
+
+    if let Some(sid) = session_id {
+        let saved = async {
+            let store = SessionStore::from_config(config).await?;
+            let session = store.load_session(sid).await?;
+            session.persist_compaction(&compaction, 0).await
+        }
+        .await;
+        if let Err(e) = saved {
+            app.push(ChatMsg::Error(format!("could not save the summary: {e}")));
+        }
+    }
+    ctx.replace_messages(compaction.messages);
+}
+
+/// `/retry [tweak]`: take the last turn back out of the conversation, the
+/// chat and the session, and return its message, with `tweak` appended, to
+/// be sent again. `None` when there is nothing to retry or the session can't
+/// be rolled back.
+pub(super) async fn cmd_retry(
+    app: &mut App,
+    config: &KrabsConfig,
+    ctx: &mut ConversationContext,
+    session_id: Option<&str>,
+    tweak: &str,
+) -> Option<String> {
+    use krabs_core::{Role, SessionStore};
+
+    if !ctx.messages().iter().any(|m| matches!(m.role, Role::User)) {
+        app.push(ChatMsg::Error(
+            "nothing to retry — send a message first".into(),
+        ));
+        return None;
+    }
+    if let Some(sid) = session_id {
+        let rolled_back = async {
+            let store = SessionStore::from_config(config).await?;
+            store.load_session(sid).await?.rollback_last_turn().await
+        }
+        .await;
+        if let Err(e) = rolled_back {
+            app.push(ChatMsg::Error(format!(
+                "could not roll back the session: {e}"
+            )));
+            return None;
+        }
+    }
+    let message = ctx.pop_turn()?;
+    if let Some(idx) = app.chat.iter().rposition(|m| matches!(m, ChatMsg::User(_))) {
+        app.chat.truncate(idx);
+    }
+    Some(match tweak {
+        "" => message,
+        tweak => format!("{message}\n\n{tweak}"),
+    })
+}
+
+/// How `/continue` picks the last turn up.
+pub(super) enum Continuation {
+    /// Send this message, as `/retry` would.
+    Send(String),
+    /// Run the agent loop again over the history as it stands.
+    Resume(krabs_core::TurnInput),
+}
+
+/// `/continue`: carry on a turn that stopped at `max_turns` or was cancelled,
+/// with a fresh `max_turns` budget and without retyping the task. A turn that
+/// stopped part-way is resumed from its last tool results, minus the
+/// `max_turns` note, which is also taken out of the session; one cancelled
+/// before any reply is retried. `None` when there is nothing to continue.
+pub(super) async fn cmd_continue(
+    app: &mut App,
+    config: &KrabsConfig,
+    ctx: &mut ConversationContext,
+    session_id: Option<&str>,
+) -> Option<Continuation> {
+    use krabs_core::{SessionStore, TurnEnd};
+
+    match ctx.last_turn_end() {
+        TurnEnd::Empty => {
+            app.push(ChatMsg::Error(
+                "nothing to continue — send a message first".into(),
+            ));
+            None
+        }
+        TurnEnd::Answered => {
+            app.push(ChatMsg::Info(
+                "the last turn finished — send a message to go on".into(),
+            ));
+            None
+        }
+        TurnEnd::Unanswered => cmd_retry(app, config, ctx, session_id, "")
+            .await
+            .map(Continuation::Send),
+        TurnEnd::Unfinished => {
+            let (input, dropped_note) = ctx.continue_turn();
+            if let Some(sid) = session_id.filter(|_| dropped_note) {
+                let rolled_back = async {
+                    let store = SessionStore::from_config(config).await?;
+                    store.load_session(sid).await?.rollback_last_message().await
+                }
+                .await;
+                if let Err(e) = rolled_back {
+                    app.push(ChatMsg::Error(format!(
+                        "could not roll back the session: {e}"
+                    )));
+                    return None;
+                }
+            }
+            app.push(ChatMsg::Info("continuing the last turn".into()));
+            Some(Continuation::Resume(input))
+        }
+    }
 }
 
 /// Load a persisted session's history and convert it to display messages.
@@ -611,7 +1934,7 @@ pub(super) async fn load_resume_history(
 ) {
     use krabs_core::{session::session::Session as KrabsSession, SessionStore};
 
-    let store = match SessionStore::open(&config.db_path).await {
+    let store = match SessionStore::from_config(config).await {
         Ok(s) => s,
         Err(_) => return (Vec::new(), Vec::new(), None),
     };
@@ -620,8 +1943,12 @@ pub(super) async fn load_resume_history(
         Err(_) => return (Vec::new(), Vec::new(), None),
     };
 
-    let (stored, subturn_resume) = match session.latest_checkpoint().await {
+    let (stored, subturn_resume, partial) = match session.latest_checkpoint().await {
         Ok(Some(cp)) => {
+            let partial = session
+                .latest_partial(cp.last_msg_id)
+                .await
+                .unwrap_or_default();
             let _ = session.rollback_to(cp.last_msg_id).await;
             let msgs = session
                 .messages_up_to(cp.last_msg_id)
@@ -632,18 +1959,29 @@ pub(super) async fn load_resume_history(
                 completed_tool_count: idx + 1,
                 last_call_id: cp.subturn_call_id.unwrap_or_default(),
             });
-            (msgs, sr)
+            (msgs, sr, partial)
         }
-        _ => (session.messages().await.unwrap_or_default(), None),
+        _ => (
+            session.messages().await.unwrap_or_default(),
+            None,
+            session.latest_partial(0).await.unwrap_or_default(),
+        ),
     };
 
+    // A /compact summary replaces everything before it.
+    let start = stored
+        .iter()
+        .rposition(|s| s.role == "summary")
+        .unwrap_or(0);
+
     let mut messages = Vec::new();
     let mut display: Vec<ChatMsg> = Vec::new();
 
-    for s in &stored {
+    for s in stored[start..].iter().filter(|s| s.role != "partial") {
         if let Ok(msg) = KrabsSession::stored_to_message(s) {
             let dm = match s.role.as_str() {
                 "user" => ChatMsg::User(s.content.clone()),
+                "summary" => ChatMsg::Info("── conversation compacted ──".into()),
                 "assistant" if s.tool_args.is_none() => ChatMsg::Assistant(s.content.clone()),
                 _ => ChatMsg::Info(format!("[{}] {}", s.role, s.content)),
             };
@@ -652,9 +1990,18 @@ pub(super) async fn load_resume_history(
         }
     }
 
+    if let Some(text) = partial {
+        display.push(ChatMsg::Info(format!(
+            "[interrupted reply, not in history] {text}"
+        )));
+    }
+
     (messages, display, subturn_resume)
 }
 
-pub(super) fn build_registry() -> ToolRegistry {
-    ToolRegistry::with_defaults()
+pub(crate) fn build_registry(config: &KrabsConfig) -> ToolRegistry {
+    let mut registry = ToolRegistry::from_config(config);
+    registry.register(Arc::new(krabs_core::GitStatusTool));
+    registry.register(Arc::new(krabs_core::GitDiffTool));
+    registry
 }
@@ -0,0 +1,88 @@
+//! Before/after diffs of file-changing tool calls, for the permission popup.
+
+use krabs_core::ProjectRoot;
+use serde_json::Value;
+use similar::TextDiff;
+
+/// Lines of context kept around each change.
+const CONTEXT_LINES: usize = 3;
+
+/// The unified diff a `write` or `apply_patch` call would make to the files
+/// on disk, one entry per line. `None` for other tools, or when the change
+/// can't be worked out (the tool call will report why).
+pub(super) async fn preview(
+    tool_name: &str,
+    args: &Value,
+    root: &ProjectRoot,
+) -> Option<Vec<String>> {
+    match tool_name {
+        "write" => {
+            let path = args["path"].as_str()?;
+            let resolved = root.resolve(path).ok()?;
+            let existing = tokio::fs::read_to_string(&resolved).await.ok();
+            let updated = match (args["old_string"].as_str(), args["new_string"].as_str()) {
+                (Some(old), Some(new)) => {
+                    let existing = existing.as_deref()?;
+                    if !existing.contains(old) {
+                        return None;
+                    }
+                    existing.replacen(old, new, 1)
+                }
+                _ => args["content"].as_str().unwrap_or("").to_string(),
+            };
+            Some(unified(path, existing.as_deref(), &updated))
+        }
+        "apply_patch" => Some(args["patch"].as_str()?.lines().map(String::from).collect()),
+        _ => None,
+    }
+}
+
+/// `old` → `new` as a unified diff of `path`. A missing `old` is a new file.
+fn unified(path: &str, old: Option<&str>, new: &str) -> Vec<String> {
+    let from = match old {
+        Some(_) => format!("a/{path}"),
+        None => "/dev/null".to_string(),
+    };
+    let to = format!("b/{path}");
+    let diff = TextDiff::from_lines(old.unwrap_or(""), new);
+    let text = diff
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(&from, &to)
+        .to_string();
+    if text.is_empty() {
+        return vec![format!("{path}: no changes")];
+    }
+    text.lines().map(String::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn writes_and_edits_diff_against_the_file_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "one\ntwo\nthree\n").unwrap();
+        let root = ProjectRoot::new(dir.path(), true);
+
+        let edit = json!({ "path": "a.txt", "old_string": "two", "new_string": "2" });
+        let lines = preview("write", &edit, &root).await.unwrap();
+        assert_eq!(&lines[..2], ["--- a/a.txt", "+++ b/a.txt"]);
+        assert!(lines.contains(&"-two".to_string()));
+        assert!(lines.contains(&"+2".to_string()));
+        assert!(lines.contains(&" one".to_string()));
+
+        let create = json!({ "path": "new.txt", "content": "hi\n" });
+        let lines = preview("write", &create, &root).await.unwrap();
+        assert_eq!(lines[0], "--- /dev/null");
+        assert_eq!(lines.last().map(String::as_str), Some("+hi"));
+
+        let missing = json!({ "path": "a.txt", "old_string": "four", "new_string": "4" });
+        assert!(preview("write", &missing, &root).await.is_none());
+        assert!(preview("bash", &json!({ "command": "ls" }), &root)
+            .await
+            .is_none());
+    }
+}
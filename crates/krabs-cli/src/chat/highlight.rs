@@ -0,0 +1,82 @@
+use std::sync::LazyLock;
+
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Span;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+// Loading these parses every bundled grammar and theme — do it once, not per frame.
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEME: LazyLock<Theme> = LazyLock::new(|| {
+    ThemeSet::load_defaults()
+        .themes
+        .remove("base16-ocean.dark")
+        .unwrap_or_default()
+});
+
+/// Highlights one code block line by line, carrying parser state between lines.
+pub(super) struct CodeHighlighter {
+    inner: HighlightLines<'static>,
+}
+
+impl CodeHighlighter {
+    /// Highlighter for a fence hint such as `rust`, `py`, or `TypeScript`.
+    /// `None` for unknown languages — callers render those uncolored.
+    pub(super) fn for_language(lang: &str) -> Option<Self> {
+        let lang = lang.trim();
+        if lang.is_empty() {
+            return None;
+        }
+        SYNTAXES
+            .find_syntax_by_token(lang)
+            .or_else(|| SYNTAXES.find_syntax_by_token(&lang.to_lowercase()))
+            .map(Self::new)
+    }
+
+    /// Highlighter chosen from a file path's extension.
+    pub(super) fn for_path(path: &str) -> Option<Self> {
+        let ext = std::path::Path::new(path).extension()?.to_str()?;
+        SYNTAXES.find_syntax_by_extension(ext).map(Self::new)
+    }
+
+    fn new(syntax: &'static SyntaxReference) -> Self {
+        Self {
+            inner: HighlightLines::new(syntax, &THEME),
+        }
+    }
+
+    /// Styled spans for `line`, on top of `base` (e.g. a code-block background).
+    /// `None` if the grammar fails on this line; the caller should fall back to
+    /// plain text.
+    pub(super) fn line(&mut self, line: &str, base: Style) -> Option<Vec<Span<'static>>> {
+        // The "newlines" syntax set expects each line to end with '\n'.
+        let with_nl = format!("{line}\n");
+        let ranges = self.inner.highlight_line(&with_nl, &SYNTAXES).ok()?;
+        Some(
+            ranges
+                .into_iter()
+                .filter_map(|(style, text)| {
+                    let text = text.trim_end_matches('\n');
+                    (!text.is_empty())
+                        .then(|| Span::styled(text.to_string(), to_ratatui(style, base)))
+                })
+                .collect(),
+        )
+    }
+}
+
+fn to_ratatui(style: syntect::highlighting::Style, base: Style) -> Style {
+    let fg = style.foreground;
+    let mut out = base.fg(Color::Rgb(fg.r, fg.g, fg.b));
+    if style.font_style.contains(FontStyle::BOLD) {
+        out = out.add_modifier(Modifier::BOLD);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        out = out.add_modifier(Modifier::ITALIC);
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        out = out.add_modifier(Modifier::UNDERLINED);
+    }
+    out
+}
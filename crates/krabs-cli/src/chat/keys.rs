@@ -0,0 +1,245 @@
+//! Configurable keybindings.
+//!
+//! The key handler in `run` only knows the built-in chords. A `Keymap`
+//! translates each key before it gets there: a chord the config binds to an
+//! action becomes that action's built-in chord, and the built-in chord of a
+//! rebound action is dropped unless something else claims it.
+
+use std::collections::{HashMap, HashSet};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use krabs_core::KeybindConfig;
+
+/// Every action that can be rebound, with the chord `run` handles it on.
+const ACTIONS: &[(&str, &str)] = &[
+    ("submit", "enter"),
+    ("newline", "alt+enter"),
+    ("cancel", "ctrl+c"),
+    ("dismiss", "esc"),
+    ("complete", "tab"),
+    ("history-prev", "ctrl+p"),
+    ("history-next", "ctrl+n"),
+    ("line-start", "ctrl+a"),
+    ("line-end", "ctrl+e"),
+    ("cursor-left", "left"),
+    ("cursor-right", "right"),
+    ("delete-back", "backspace"),
+    ("scroll-up", "up"),
+    ("scroll-down", "down"),
+    ("page-up", "pageup"),
+    ("page-down", "pagedown"),
+    ("copy-reply", "ctrl+y"),
+    ("toggle-thinking", "ctrl+t"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Chord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Chord {
+    /// Terminals disagree on how shifted and control keys arrive, so fold
+    /// them: Shift is carried by the character's case (or `BackTab`), and
+    /// control characters are lowercase.
+    fn new(code: KeyCode, mut modifiers: KeyModifiers) -> Self {
+        let shift = modifiers.contains(KeyModifiers::SHIFT);
+        let code = match code {
+            KeyCode::Char(c) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                if modifiers.contains(KeyModifiers::CONTROL) {
+                    KeyCode::Char(c.to_ascii_lowercase())
+                } else if shift {
+                    KeyCode::Char(c.to_ascii_uppercase())
+                } else {
+                    KeyCode::Char(c)
+                }
+            }
+            KeyCode::Tab if shift => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            KeyCode::BackTab => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            code => code,
+        };
+        Self { code, modifiers }
+    }
+
+    fn of(key: &KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+
+    /// Parse `ctrl+p`, `alt+shift+up`, `f5`, `esc`, `G`…
+    fn parse(chord: &str) -> Option<Self> {
+        let mut parts: Vec<&str> = chord.trim().split('+').collect();
+        let key = parts.pop()?;
+        let mut modifiers = KeyModifiers::NONE;
+        for part in parts {
+            modifiers |= match part.trim().to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "option" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return None,
+            };
+        }
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_ascii_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "space" => KeyCode::Char(' '),
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                name => KeyCode::F(name.strip_prefix('f')?.parse().ok()?),
+            },
+        };
+        Some(Self::new(code, modifiers))
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Keymap {
+    /// Configured chord → the built-in chord of its action.
+    remapped: HashMap<Chord, Chord>,
+    /// Built-in chords of rebound actions that no binding claims.
+    unbound: HashSet<Chord>,
+}
+
+impl Keymap {
+    /// Build the keymap from `config`, with a warning for each unknown
+    /// action, unparseable chord and chord bound to two actions.
+    pub(super) fn new(config: &KeybindConfig) -> (Self, Vec<String>) {
+        let mut keymap = Self::default();
+        let mut warnings = Vec::new();
+        for (action, chords) in &config.bindings {
+            let Some(default) = ACTIONS
+                .iter()
+                .find(|(name, _)| name == action)
+                .and_then(|(_, chord)| Chord::parse(chord))
+            else {
+                warnings.push(format!("keybindings: unknown action '{action}'"));
+                continue;
+            };
+            keymap.unbound.insert(default);
+            for chord in chords.as_slice() {
+                let Some(parsed) = Chord::parse(chord) else {
+                    warnings.push(format!(
+                        "keybindings: '{chord}' for '{action}' isn't a key chord"
+                    ));
+                    continue;
+                };
+                if let Some(previous) = keymap.remapped.insert(parsed, default) {
+                    if previous != default {
+                        warnings.push(format!(
+                            "keybindings: '{chord}' is bound to more than one action"
+                        ));
+                    }
+                }
+            }
+        }
+        let remapped = &keymap.remapped;
+        keymap.unbound.retain(|chord| !remapped.contains_key(chord));
+        (keymap, warnings)
+    }
+
+    /// The key `run` should handle in place of `key`: a `Null` key when
+    /// `key` was unbound.
+    pub(super) fn resolve(&self, key: KeyEvent) -> KeyEvent {
+        let chord = Chord::of(&key);
+        match self.remapped.get(&chord) {
+            Some(target) => KeyEvent {
+                code: target.code,
+                modifiers: target.modifiers,
+                ..key
+            },
+            None if self.unbound.contains(&chord) => KeyEvent {
+                code: KeyCode::Null,
+                modifiers: KeyModifiers::NONE,
+                ..key
+            },
+            None => key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use krabs_core::KeyChords;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn built_in_chords_parse() {
+        for (action, chord) in ACTIONS {
+            assert!(Chord::parse(chord).is_some(), "{action}: {chord}");
+        }
+        assert_eq!(
+            Chord::parse("shift+tab"),
+            Some(Chord::new(KeyCode::BackTab, KeyModifiers::SHIFT))
+        );
+        assert_eq!(
+            Chord::parse("Ctrl+Alt+F5"),
+            Some(Chord::new(
+                KeyCode::F(5),
+                KeyModifiers::CONTROL | KeyModifiers::ALT
+            ))
+        );
+        assert!(Chord::parse("hyper+x").is_none());
+        assert!(Chord::parse("ctrl+").is_none());
+    }
+
+    #[test]
+    fn rebound_actions_move_to_their_new_chords() {
+        let mut config = KeybindConfig::default();
+        config
+            .bindings
+            .insert("history-prev".into(), KeyChords::One("ctrl+k".into()));
+        config.bindings.insert(
+            "submit".into(),
+            KeyChords::Many(vec!["enter".into(), "ctrl+j".into()]),
+        );
+        config
+            .bindings
+            .insert("frobnicate".into(), KeyChords::One("x".into()));
+        let (keymap, warnings) = Keymap::new(&config);
+        assert_eq!(warnings, ["keybindings: unknown action 'frobnicate'"]);
+
+        let ctrl = KeyModifiers::CONTROL;
+        let k = keymap.resolve(key(KeyCode::Char('k'), ctrl));
+        assert_eq!((k.code, k.modifiers), (KeyCode::Char('p'), ctrl));
+        // Ctrl+P is free now, so it doesn't reach the history handler.
+        assert_eq!(
+            keymap.resolve(key(KeyCode::Char('p'), ctrl)).code,
+            KeyCode::Null
+        );
+        assert_eq!(
+            keymap.resolve(key(KeyCode::Char('j'), ctrl)).code,
+            KeyCode::Enter
+        );
+        assert_eq!(
+            keymap.resolve(key(KeyCode::Enter, KeyModifiers::NONE)).code,
+            KeyCode::Enter
+        );
+        // Untouched actions keep their defaults.
+        let n = keymap.resolve(key(KeyCode::Char('n'), ctrl));
+        assert_eq!((n.code, n.modifiers), (KeyCode::Char('n'), ctrl));
+    }
+}
@@ -1,8 +1,13 @@
 mod agent;
 mod app;
 mod commands;
+mod diff;
+mod highlight;
+mod keys;
 mod render;
 mod run;
 mod types;
 
+pub(crate) use commands::build_registry;
+pub(crate) use run::apply_config;
 pub use run::run;
@@ -12,9 +12,10 @@ use std::io;
 use std::time::Duration;
 use tokio::sync::mpsc;
 
-use super::app::App;
+use super::app::{mcp_server_pattern, App};
 use super::commands::{at_suggestions, slash_suggestions};
-use super::types::{estimate_tokens, InfoBar};
+use super::highlight::CodeHighlighter;
+use super::types::{estimate_tokens, wrapped_height, Brand, InfoBar, RenderedMsg};
 
 pub(super) const SPINNER: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
 
@@ -27,27 +28,34 @@ pub(super) const LOGO: &[&str] = &[
     "╚═╝  ╚═╝╚═╝  ╚═╝╚═╝  ╚═╝╚═════╝ ╚══════╝",
 ];
 
-pub(super) const MR_KRABS_ORANGE: Color = Color::Rgb(255, 128, 0);
-
 pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Frame) {
     let area = frame.area();
+    let accent = app.brand.accent;
+    app.suggest_area = None;
     let has_session = info.session_id.is_some();
-    let info_height: u16 = match (app.active_persona.is_some(), has_session) {
-        (true, true) => 8,
-        (true, false) | (false, true) => 7,
-        (false, false) => 6,
-    };
+    // Four lines plus the borders, then one per optional line.
+    let info_height: u16 = 6
+        + u16::from(app.active_persona.is_some())
+        + u16::from(!app.active_skills.is_empty())
+        + u16::from(has_session)
+        + u16::from(info.approve_all);
+    // One row per input line, up to the cap, inside the borders.
+    let input_rows = u16::try_from(app.input.split('\n').count())
+        .unwrap_or(u16::MAX)
+        .clamp(1, app.input_max_lines.max(1));
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(info_height), // info box
-            Constraint::Min(1),              // chat
-            Constraint::Length(3),           // input
+            Constraint::Length(info_height),    // info box
+            Constraint::Min(1),                 // chat
+            Constraint::Length(input_rows + 2), // input
         ])
         .split(area);
 
     // ── info box ──────────────────────────────────────────────────────────────
-    let used = app.total_input + app.total_output;
+    let used = app
+        .context_tokens
+        .unwrap_or(app.total_input + app.total_output);
     let pct = (used as f32 / max_ctx as f32 * 100.0).min(100.0);
 
     // Build segmented context bar
@@ -63,7 +71,7 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
     };
     let cat_segs = [
         (seg_w(t_system), Color::Green),
-        (seg_w(t_persona), MR_KRABS_ORANGE),
+        (seg_w(t_persona), accent),
         (seg_w(t_tools), Color::Magenta),
         (seg_w(t_memory), Color::Blue),
         (seg_w(t_messages), Color::Cyan),
@@ -101,6 +109,8 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
         Line::from(vec![
             Span::styled("  cwd     ", Style::default().fg(Color::DarkGray)),
             Span::styled(&info.cwd, Style::default().fg(Color::Cyan)),
+            Span::styled("   root  ", Style::default().fg(Color::DarkGray)),
+            Span::styled(&info.root, Style::default().fg(Color::Cyan)),
         ]),
         Line::from(vec![
             Span::styled("  tools   ", Style::default().fg(Color::DarkGray)),
@@ -115,6 +125,27 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
             spans
         }),
     ];
+    if info.dry_run {
+        info_lines[0].spans.extend([
+            Span::raw("   "),
+            Span::styled(
+                " DRY RUN ",
+                Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Magenta)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ]);
+    }
+    if info.approve_all {
+        info_lines.push(Line::from(Span::styled(
+            "  ⚠ PERMISSIONS OFF — tool calls run without asking (auto_approve_all) ",
+            Style::default()
+                .fg(Color::White)
+                .bg(Color::Red)
+                .add_modifier(Modifier::BOLD),
+        )));
+    }
     if let Some(ref sid) = info.session_id {
         let short = &sid[..sid.len().min(8)];
         info_lines.push(Line::from(vec![
@@ -132,89 +163,138 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
             Span::styled("  persona ", Style::default().fg(Color::DarkGray)),
             Span::styled(
                 format!("@{}", persona.name),
-                Style::default()
-                    .fg(MR_KRABS_ORANGE)
-                    .add_modifier(Modifier::BOLD),
+                Style::default().fg(accent).add_modifier(Modifier::BOLD),
             ),
         ]));
     }
+    if !app.active_skills.is_empty() {
+        let names: Vec<&str> = app.active_skills.iter().map(|s| s.name.as_str()).collect();
+        info_lines.push(Line::from(vec![
+            Span::styled("  skills  ", Style::default().fg(Color::DarkGray)),
+            Span::styled(names.join(", "), Style::default().fg(Color::Yellow)),
+        ]));
+    }
     let info_widget = Paragraph::new(info_lines).block(
         Block::default()
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(MR_KRABS_ORANGE))
+            .border_style(Style::default().fg(accent))
             .title(Span::styled(
-                " krabs ",
-                Style::default()
-                    .fg(MR_KRABS_ORANGE)
-                    .add_modifier(Modifier::BOLD),
+                app.brand.title.as_str(),
+                Style::default().fg(accent).add_modifier(Modifier::BOLD),
             )),
     );
     frame.render_widget(info_widget, chunks[0]);
 
     // ── chat messages ─────────────────────────────────────────────────────────
-    let mut lines: Vec<Line> = vec![Line::raw("")];
-    for msg in &app.chat {
-        lines.extend(msg.to_lines());
-    }
+    // Messages keep their rendered lines and wrapped heights between frames;
+    // only new or changed ones (the streaming reply) go through markdown and
+    // syntax highlighting again.
+    let inner_w = chunks[1].width.saturating_sub(2);
+    let view_h = chunks[1].height.saturating_sub(2);
+    RenderedMsg::refresh(
+        &mut app.rendered,
+        &app.chat,
+        app.render_opts,
+        &app.brand,
+        inner_w,
+    );
 
     // Spinner at end while thinking
-    if app.spinning {
-        lines.push(Line::from(Span::styled(
+    let spinner: Vec<Line<'static>> = if app.spinning {
+        vec![Line::from(Span::styled(
             format!("  {} thinking…", SPINNER[app.spin_i % SPINNER.len()]),
             Style::default().fg(Color::Cyan),
-        )));
+        ))]
+    } else {
+        Vec::new()
+    };
+    let blank = [Line::raw("")];
+    // The chat starts with one blank line.
+    let blocks = || {
+        std::iter::once((1, &blank[..]))
+            .chain(app.rendered.iter().map(|r| (r.height, &r.lines[..])))
+            .chain(std::iter::once((
+                wrapped_height(&spinner, inner_w),
+                &spinner[..],
+            )))
+    };
+
+    let total_visual: usize = blocks().map(|(height, _)| height).sum();
+    let max_scroll = u16::try_from(total_visual)
+        .unwrap_or(u16::MAX)
+        .saturating_sub(view_h);
+    let mut scroll = app.scroll;
+    if scroll == u16::MAX {
+        scroll = max_scroll;
+    }
+    scroll = scroll.min(max_scroll);
+
+    // Hand the widget only the messages that reach into the view.
+    let (view_top, view_bottom) = (scroll as usize, scroll as usize + view_h as usize);
+    let mut lines: Vec<Line> = Vec::new();
+    let mut skip = 0;
+    let mut top = 0;
+    for (height, block) in blocks() {
+        if top + height > view_top && top < view_bottom {
+            if lines.is_empty() {
+                skip = view_top - top;
+            }
+            lines.extend_from_slice(block);
+        }
+        top += height;
     }
 
-    // Build the chat widget so we can ask ratatui itself how many visual rows
-    // it needs (using the real WordWrapper, accounting for word-wrap correctly).
     let chat_block = Block::default()
         .borders(Borders::ALL)
         .border_style(Style::default().fg(Color::DarkGray))
         .title(Span::styled(" chat ", Style::default().fg(Color::DarkGray)));
-
     let msg_widget = Paragraph::new(lines)
         .block(chat_block)
-        .wrap(Wrap { trim: false });
-
-    // line_count(inner_w) uses the actual word-wrap algorithm and returns the
-    // total visual rows INCLUDING the 2 block border rows — subtract them.
-    let inner_w = chunks[1].width.saturating_sub(2);
-    let view_h = chunks[1].height.saturating_sub(2);
-    let total_visual = msg_widget.line_count(inner_w).saturating_sub(2) as u16;
-    let max_scroll = total_visual.saturating_sub(view_h);
-    app.max_scroll = max_scroll;
-    if app.scroll == u16::MAX {
-        app.scroll = max_scroll;
-    }
-    app.scroll = app.scroll.min(max_scroll);
-
-    let msg_widget = msg_widget.scroll((app.scroll, 0));
+        .wrap(Wrap { trim: false })
+        .scroll((skip as u16, 0));
     frame.render_widget(msg_widget, chunks[1]);
+    app.max_scroll = max_scroll;
+    app.scroll = scroll;
+    app.chat_area = chunks[1];
 
     // ── input box ─────────────────────────────────────────────────────────────
     let busy = app.spinning;
     let border_col = if busy { Color::DarkGray } else { Color::Cyan };
 
-    let before = &app.input[..app.cursor];
-    let (cur_ch, after) = if app.cursor < app.input.len() {
-        let ch = app.input[app.cursor..].chars().next().unwrap();
-        let end = app.cursor + ch.len_utf8();
-        (ch.to_string(), app.input[end..].to_string())
+    let text_style = Style::default().fg(Color::White);
+    let (cursor_row, _) = app.cursor_row_col();
+    let mut line_start = 0;
+    let input_lines: Vec<Line> = app
+        .input
+        .split('\n')
+        .map(|line| {
+            let start = line_start;
+            line_start += line.len() + 1;
+            let Some(at) = app.cursor.checked_sub(start).filter(|at| *at <= line.len()) else {
+                return Line::styled(line.to_string(), text_style);
+            };
+            let mut rest = line[at..].chars();
+            let cur_ch = rest.next().map_or(" ".to_string(), String::from);
+            Line::from(vec![
+                Span::styled(line[..at].to_string(), text_style),
+                Span::styled(cur_ch, Style::default().fg(Color::Black).bg(Color::White)),
+                Span::styled(rest.as_str().to_string(), text_style),
+            ])
+        })
+        .collect();
+    // Keep the cursor's line in view once the input outgrows the box.
+    let input_scroll = (cursor_row as u16).saturating_sub(input_rows - 1);
+
+    let title = if input_lines.len() > 1 {
+        format!(" message ({} lines) ", input_lines.len())
     } else {
-        (" ".to_string(), String::new())
+        " message ".to_string()
     };
-
-    let input_line = Line::from(vec![
-        Span::styled(before.to_string(), Style::default().fg(Color::White)),
-        Span::styled(cur_ch, Style::default().fg(Color::Black).bg(Color::White)),
-        Span::styled(after, Style::default().fg(Color::White)),
-    ]);
-
-    let input_widget = Paragraph::new(input_line).block(
+    let input_widget = Paragraph::new(input_lines).scroll((input_scroll, 0)).block(
         Block::default()
             .borders(Borders::ALL)
             .border_style(Style::default().fg(border_col))
-            .title(Span::styled(" message ", Style::default().fg(border_col))),
+            .title(Span::styled(title, Style::default().fg(border_col))),
     );
 
     frame.render_widget(input_widget, chunks[2]);
@@ -260,13 +340,27 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
 
             frame.render_widget(ratatui::widgets::Clear, pop_rect);
             frame.render_widget(popup, pop_rect);
+            app.suggest_area = Some(pop_rect);
         }
     }
 
     // ── Permission dialog ──────────────────────────────────────────────────────
-    if let Some(ref perm) = app.pending_permission {
-        let pop_w = (area.width * 3 / 4).clamp(40, 72);
-        let pop_h = 7u16;
+    if let Some(ref mut perm) = app.pending_permission {
+        let diff_len = perm.diff.as_ref().map_or(0, Vec::len);
+        // Blank, tool, blank and hint lines and the borders around a diff,
+        // plus a scroll line if it doesn't fit.
+        let scrollable = diff_len + 6 > area.height as usize;
+        let chrome = if scrollable { 7 } else { 6 };
+        let (pop_w, pop_h) = if diff_len > 0 {
+            (
+                (area.width * 9 / 10).clamp(40, 120),
+                (diff_len + chrome)
+                    .min(area.height as usize)
+                    .max(chrome + 1) as u16,
+            )
+        } else {
+            ((area.width * 3 / 4).clamp(40, 76), 7u16)
+        };
         let pop_x = area.x + (area.width.saturating_sub(pop_w)) / 2;
         let pop_y = area.y + (area.height.saturating_sub(pop_h)) / 2;
         let pop_rect = ratatui::layout::Rect::new(pop_x, pop_y, pop_w, pop_h);
@@ -279,7 +373,7 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
             perm.args.clone()
         };
 
-        let perm_lines = vec![
+        let mut perm_lines = vec![
             Line::raw(""),
             Line::from(vec![
                 Span::styled("  tool  ", Style::default().fg(Color::DarkGray)),
@@ -290,16 +384,46 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
                         .add_modifier(Modifier::BOLD),
                 ),
             ]),
-            Line::from(vec![
-                Span::styled("  args  ", Style::default().fg(Color::DarkGray)),
-                Span::styled(args_display, Style::default().fg(Color::White)),
-            ]),
-            Line::raw(""),
-            Line::from(vec![Span::styled(
-                "  [y] allow once   [a] always allow   [n] deny",
-                Style::default().fg(Color::Cyan),
-            )]),
         ];
+        match &perm.diff {
+            Some(diff) if !diff.is_empty() => {
+                let rows = (pop_h as usize).saturating_sub(chrome);
+                perm.scroll = perm.scroll.min(diff_len.saturating_sub(rows));
+                let width = (pop_w as usize).saturating_sub(4);
+                perm_lines.extend(
+                    diff.iter()
+                        .skip(perm.scroll)
+                        .take(rows)
+                        .map(|line| diff_line(line, width)),
+                );
+                perm_lines.push(Line::raw(""));
+                if scrollable {
+                    let last = (perm.scroll + rows).min(diff_len);
+                    perm_lines.push(Line::from(Span::styled(
+                        format!(
+                            "  lines {}–{last} of {diff_len}   [↑/↓] scroll  [PgUp/PgDn] page",
+                            perm.scroll + 1
+                        ),
+                        Style::default().fg(Color::DarkGray),
+                    )));
+                }
+            }
+            _ => {
+                perm_lines.push(Line::from(vec![
+                    Span::styled("  args  ", Style::default().fg(Color::DarkGray)),
+                    Span::styled(args_display, Style::default().fg(Color::White)),
+                ]));
+                perm_lines.push(Line::raw(""));
+            }
+        }
+        perm_lines.push(Line::from(vec![Span::styled(
+            if mcp_server_pattern(&perm.tool_name).is_some() {
+                "  [y] once  [a] session  [g] everywhere  [s] whole server  [n] deny"
+            } else {
+                "  [y] once   [a] always this session   [g] always everywhere   [n] deny"
+            },
+            Style::default().fg(Color::Cyan),
+        )]));
 
         let perm_widget = Paragraph::new(perm_lines).block(
             Block::default()
@@ -322,7 +446,12 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
         use krabs_core::InputMode;
         let pop_w = (area.width * 3 / 4).clamp(44, 76);
         let n_opts = ui.options.len() as u16; // includes "custom…"
-        let pop_h = 4 + n_opts + if ui.custom_mode { 2 } else { 1 };
+        let pop_h = if ui.mode == InputMode::FreeText {
+            // question + text field + hint, with blank spacers and borders
+            8
+        } else {
+            4 + n_opts + if ui.custom_mode { 2 } else { 1 }
+        };
         let pop_x = area.x + (area.width.saturating_sub(pop_w)) / 2;
         let pop_y = area.y + (area.height.saturating_sub(pop_h)) / 2;
         let pop_rect = ratatui::layout::Rect::new(pop_x, pop_y, pop_w, pop_h);
@@ -355,6 +484,7 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
                         "  [ ] "
                     }
                 }
+                InputMode::FreeText => "  ",
             };
             let style = if focused {
                 Style::default()
@@ -371,11 +501,20 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
             };
             lines.push(Line::from(Span::styled(label, style)));
         }
+        if ui.mode == InputMode::FreeText {
+            lines.push(Line::from(Span::styled(
+                format!("  › {}_", ui.custom_text),
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            )));
+        }
 
         lines.push(Line::raw(""));
         let hint = match ui.mode {
             InputMode::ChooseOne => "  ↑↓ move   enter select   esc cancel",
             InputMode::ChooseMany => "  ↑↓ move   space toggle   enter confirm   esc cancel",
+            InputMode::FreeText => "  type your answer   enter submit   esc cancel",
         };
         lines.push(Line::from(Span::styled(
             hint,
@@ -385,6 +524,7 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
         let (border_color, title) = match ui.mode {
             InputMode::ChooseOne => (Color::Cyan, " agent question — choose one "),
             InputMode::ChooseMany => (Color::Magenta, " agent question — choose many "),
+            InputMode::FreeText => (Color::Yellow, " agent question — free text "),
         };
 
         let popup = Paragraph::new(lines).block(
@@ -499,12 +639,12 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
                 .map(|(i, (name, desc))| {
                     let selected = app.suggest_idx == Some(i);
                     let style = if selected {
-                        Style::default().fg(Color::Black).bg(MR_KRABS_ORANGE)
+                        Style::default().fg(Color::Black).bg(accent)
                     } else {
                         Style::default().fg(Color::White)
                     };
                     let desc_style = if selected {
-                        Style::default().fg(Color::Black).bg(MR_KRABS_ORANGE)
+                        Style::default().fg(Color::Black).bg(accent)
                     } else {
                         Style::default().fg(Color::DarkGray)
                     };
@@ -518,31 +658,82 @@ pub(super) fn render(app: &mut App, max_ctx: u32, info: &InfoBar, frame: &mut Fr
             let popup = Paragraph::new(popup_lines).block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .border_style(Style::default().fg(MR_KRABS_ORANGE))
-                    .title(Span::styled(
-                        " personas ",
-                        Style::default().fg(MR_KRABS_ORANGE),
-                    )),
+                    .border_style(Style::default().fg(accent))
+                    .title(Span::styled(" personas ", Style::default().fg(accent))),
             );
 
             frame.render_widget(ratatui::widgets::Clear, pop_rect);
             frame.render_widget(popup, pop_rect);
+            app.suggest_area = Some(pop_rect);
         }
     }
+
+    // ── full tool output (opened by clicking a tool result) ──────────────────
+    if let Some(out) = app.expanded.as_mut() {
+        let rect = chunks[1];
+        let lines: Vec<Line> = out.content.lines().map(Line::raw).collect();
+        let popup = Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(accent))
+                    .title(Span::styled(
+                        out.title.as_str(),
+                        Style::default().fg(accent).add_modifier(Modifier::BOLD),
+                    )),
+            )
+            .wrap(Wrap { trim: false });
+        let rows = popup
+            .line_count(rect.width.saturating_sub(2))
+            .saturating_sub(2) as u16;
+        out.scroll = out
+            .scroll
+            .min(rows.saturating_sub(rect.height.saturating_sub(2)));
+        frame.render_widget(ratatui::widgets::Clear, rect);
+        frame.render_widget(popup.scroll((out.scroll, 0)), rect);
+    }
+}
+
+/// Index of the chat message drawn at screen row `row`, using the chat pane,
+/// scroll offset and message heights of the last frame.
+pub(super) fn chat_msg_at(app: &App, row: u16) -> Option<usize> {
+    let area = app.chat_area;
+    if row <= area.y || row + 1 >= area.bottom() {
+        return None;
+    }
+    let target = (row - area.y - 1) as usize + app.scroll as usize;
+    // The chat starts with one blank line.
+    let mut top = 1;
+    for (i, rendered) in app.rendered.iter().enumerate().take(app.chat.len()) {
+        let height = rendered.height;
+        if target < top + height {
+            return (target >= top).then_some(i);
+        }
+        top += height;
+    }
+    None
 }
 
 pub(super) async fn show_splash(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
     key_rx: &mut mpsc::Receiver<Event>,
+    brand: &Brand,
     provider: &str,
     model: &str,
 ) -> Result<()> {
     let subtitle = format!("{}  |  {}", provider, model);
+    let logo_w = brand
+        .logo
+        .iter()
+        .map(|row| row.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(subtitle.chars().count()) as u16
+        + 2;
     loop {
         terminal.draw(|f| {
             let area = f.area();
-            let logo_w = 42u16;
-            let logo_h = LOGO.len() as u16;
+            let logo_h = brand.logo.len() as u16;
             let box_w = logo_w + 4;
             let box_h = logo_h + 6; // logo + subtitle + hint + padding
 
@@ -551,13 +742,14 @@ pub(super) async fn show_splash(
             let rect =
                 ratatui::layout::Rect::new(x, y, box_w.min(area.width), box_h.min(area.height));
 
-            let mut lines: Vec<Line> = LOGO
+            let mut lines: Vec<Line> = brand
+                .logo
                 .iter()
                 .map(|row| {
                     Line::from(Span::styled(
-                        *row,
+                        row.as_str(),
                         Style::default()
-                            .fg(MR_KRABS_ORANGE)
+                            .fg(brand.accent)
                             .add_modifier(Modifier::BOLD),
                     ))
                 })
@@ -574,11 +766,11 @@ pub(super) async fn show_splash(
 
             let block = Block::default()
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(MR_KRABS_ORANGE))
+                .border_style(Style::default().fg(brand.accent))
                 .title(Span::styled(
-                    " krabs ",
+                    brand.title.as_str(),
                     Style::default()
-                        .fg(MR_KRABS_ORANGE)
+                        .fg(brand.accent)
                         .add_modifier(Modifier::BOLD),
                 ));
 
@@ -598,3 +790,265 @@ pub(super) async fn show_splash(
     }
     Ok(())
 }
+
+// ── markdown ─────────────────────────────────────────────────────────────────
+
+const CODE_BG: Color = Color::Rgb(40, 44, 52);
+
+/// Convert assistant markdown into styled lines, each prefixed with `indent`.
+///
+/// Line-oriented and single-pass so it stays cheap enough to run on every
+/// streamed token. Handles fenced code blocks, `#` headers, bullet lists, block
+/// quotes, and inline `code` / **bold** / *italic*. Anything it doesn't
+/// recognise — including unclosed delimiters — is printed as raw text.
+/// With `highlight`, fences carrying a known language hint are syntax-colored.
+/// Headers are drawn in `accent`.
+pub(super) fn markdown_lines(
+    text: &str,
+    indent: &str,
+    highlight: bool,
+    accent: Color,
+) -> Vec<Line<'static>> {
+    let base = Style::default().fg(Color::White);
+    let code = Style::default().fg(Color::Rgb(220, 220, 220)).bg(CODE_BG);
+    let mut lines = Vec::new();
+    let mut in_fence = false;
+    let mut highlighter: Option<CodeHighlighter> = None;
+
+    for raw in text.lines() {
+        let trimmed = raw.trim_start();
+        if let Some(lang) = trimmed.strip_prefix("```") {
+            in_fence = !in_fence;
+            highlighter = if in_fence && highlight {
+                CodeHighlighter::for_language(lang)
+            } else {
+                None
+            };
+            lines.push(Line::from(Span::styled(
+                format!("{indent}{trimmed}"),
+                Style::default().fg(Color::DarkGray),
+            )));
+            continue;
+        }
+        if in_fence {
+            let mut spans = vec![Span::raw(indent.to_string()), Span::styled(" ", code)];
+            match highlighter.as_mut().and_then(|h| h.line(raw, code)) {
+                Some(colored) => spans.extend(colored),
+                None => spans.push(Span::styled(raw.to_string(), code)),
+            }
+            spans.push(Span::styled(" ", code));
+            lines.push(Line::from(spans));
+            continue;
+        }
+
+        let mut spans = vec![Span::raw(indent.to_string())];
+        let lead = &raw[..raw.len() - trimmed.len()];
+        if let Some(title) = header_text(trimmed) {
+            spans.extend(inline_spans(
+                title,
+                base.fg(accent).add_modifier(Modifier::BOLD),
+            ));
+        } else if let Some(item) = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|m| trimmed.strip_prefix(m))
+        {
+            spans.push(Span::styled(
+                format!("{lead}• "),
+                Style::default().fg(Color::Cyan),
+            ));
+            spans.extend(inline_spans(item, base));
+        } else if let Some(quote) = trimmed.strip_prefix("> ").or(trimmed.strip_prefix('>')) {
+            spans.push(Span::styled(
+                format!("{lead}│ "),
+                Style::default().fg(Color::DarkGray),
+            ));
+            spans.extend(inline_spans(
+                quote,
+                Style::default()
+                    .fg(Color::Gray)
+                    .add_modifier(Modifier::ITALIC),
+            ));
+        } else {
+            spans.extend(inline_spans(raw, base));
+        }
+        lines.push(Line::from(spans));
+    }
+    lines
+}
+
+/// Text of an ATX header (`# Title` … `###### Title`), if `line` is one.
+/// One line of a unified diff, coloured by kind and cut to `width` columns.
+fn diff_line(line: &str, width: usize) -> Line<'static> {
+    let style = if line.starts_with("+++") || line.starts_with("---") {
+        Style::default()
+            .fg(Color::White)
+            .add_modifier(Modifier::BOLD)
+    } else if line.starts_with("@@") {
+        Style::default().fg(Color::Cyan)
+    } else if line.starts_with('+') {
+        Style::default().fg(Color::Green)
+    } else if line.starts_with('-') {
+        Style::default().fg(Color::Red)
+    } else {
+        Style::default().fg(Color::Gray)
+    };
+    let text: String = line.replace('\t', "    ").chars().take(width).collect();
+    Line::from(Span::styled(format!("  {text}"), style))
+}
+
+fn header_text(line: &str) -> Option<&str> {
+    let hashes = line.bytes().take_while(|&b| b == b'#').count();
+    if !(1..=6).contains(&hashes) {
+        return None;
+    }
+    line[hashes..].strip_prefix(' ').map(str::trim)
+}
+
+/// Inline spans for `code`, **bold** / __bold__ and *italic* / _italic_.
+/// Unmatched delimiters fall through as literal text.
+fn inline_spans(text: &str, base: Style) -> Vec<Span<'static>> {
+    fn flush(plain: &mut String, style: Style, spans: &mut Vec<Span<'static>>) {
+        if !plain.is_empty() {
+            spans.push(Span::styled(std::mem::take(plain), style));
+        }
+    }
+
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '`' => {
+                if let Some(end) = rest[1..].find('`') {
+                    flush(&mut plain, base, &mut spans);
+                    spans.push(Span::styled(
+                        rest[1..1 + end].to_string(),
+                        base.fg(Color::LightYellow).bg(CODE_BG),
+                    ));
+                    rest = &rest[end + 2..];
+                    continue;
+                }
+            }
+            '*' | '_' => {
+                let doubled = rest[1..].starts_with(c);
+                let delim = &rest[..if doubled { 2 } else { 1 }];
+                let after = &rest[delim.len()..];
+                // Emphasis must hug its text, and intraword `_` (snake_case) stays literal.
+                let opens = after.chars().next().is_some_and(|n| !n.is_whitespace())
+                    && !(c == '_' && plain.chars().last().is_some_and(char::is_alphanumeric));
+                let close = after.find(delim).filter(|&end| opens && end > 0);
+                if let Some(end) = close {
+                    flush(&mut plain, base, &mut spans);
+                    let modifier = if doubled {
+                        Modifier::BOLD
+                    } else {
+                        Modifier::ITALIC
+                    };
+                    spans.extend(inline_spans(&after[..end], base.add_modifier(modifier)));
+                    rest = &after[end + delim.len()..];
+                    continue;
+                }
+                plain.push_str(delim);
+                rest = after;
+                continue;
+            }
+            _ => {}
+        }
+        plain.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    flush(&mut plain, base, &mut spans);
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(line: &Line) -> String {
+        line.spans.iter().map(|s| s.content.as_ref()).collect()
+    }
+
+    #[test]
+    fn inline_markup_is_stripped_and_styled() {
+        let spans = inline_spans("use `cargo test` for **all** *crates*", Style::default());
+        let joined: String = spans.iter().map(|s| s.content.as_ref()).collect();
+        assert_eq!(joined, "use cargo test for all crates");
+        assert!(spans[3].style.add_modifier.contains(Modifier::BOLD));
+        assert!(spans[5].style.add_modifier.contains(Modifier::ITALIC));
+    }
+
+    #[test]
+    fn unclosed_delimiters_print_raw() {
+        let spans = inline_spans("a **b and `c and snake_case_name", Style::default());
+        let joined: String = spans.iter().map(|s| s.content.as_ref()).collect();
+        assert_eq!(joined, "a **b and `c and snake_case_name");
+    }
+
+    #[test]
+    fn code_fence_lines_get_background() {
+        let lines = markdown_lines(
+            "intro\n```rust\nfn main() {}\n```\n- item",
+            "  ",
+            false,
+            Color::Reset,
+        );
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[2].spans[1].style.bg, Some(CODE_BG));
+        assert_eq!(text(&lines[2]), "   fn main() {} ");
+        assert_eq!(text(&lines[4]), "  • item");
+    }
+
+    #[test]
+    fn known_language_is_colored_and_unknown_falls_back() {
+        let rust = markdown_lines("```rust\nlet x = 1;\n```", "", true, Color::Reset);
+        assert!(rust[1].spans.len() > 4, "expected per-token spans");
+        assert_eq!(text(&rust[1]), " let x = 1; ");
+
+        let unknown = markdown_lines("```no-such-lang\nlet x = 1;\n```", "", true, Color::Reset);
+        assert_eq!(unknown[1].spans.len(), 4);
+        assert_eq!(text(&unknown[1]), " let x = 1; ");
+    }
+
+    #[test]
+    fn headers_drop_hashes() {
+        let lines = markdown_lines("## Plan", "", false, Color::Reset);
+        assert_eq!(text(&lines[0]), "Plan");
+        assert_eq!(header_text("#hashtag"), None);
+    }
+
+    #[test]
+    fn unchanged_messages_keep_their_rendered_lines() {
+        use super::super::types::{ChatMsg, RenderOpts};
+
+        let brand = Brand::new(&krabs_core::BrandingConfig::default());
+        let opts = RenderOpts {
+            markdown: true,
+            highlight: true,
+            show_thinking: false,
+        };
+        let mut chat = vec![
+            ChatMsg::User("hi".into()),
+            ChatMsg::Assistant("one two three".into()),
+        ];
+        let mut cache = Vec::new();
+        RenderedMsg::refresh(&mut cache, &chat, opts, &brand, 80);
+        let first = cache[0].lines.as_ptr();
+
+        chat[1] = ChatMsg::Assistant("one two three four".into());
+        RenderedMsg::refresh(&mut cache, &chat, opts, &brand, 80);
+        assert_eq!(cache[0].lines.as_ptr(), first);
+        assert!(cache[1].lines.iter().any(|l| text(l).contains("four")));
+
+        // A narrower pane re-wraps without rendering again.
+        let wide = cache[1].height;
+        RenderedMsg::refresh(&mut cache, &chat, opts, &brand, 8);
+        assert_eq!(cache[0].lines.as_ptr(), first);
+        assert!(cache[1].height > wide);
+
+        chat.truncate(1);
+        RenderedMsg::refresh(&mut cache, &chat, opts, &brand, 8);
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -1,28 +1,44 @@
+use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::Result;
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
 };
 use krabs_core::{
-    AgentPersona, ConversationContext, Credentials, KrabsConfig, LlmProvider, Message, Role,
+    AgentPersona, AutoApprover, CancellationToken, ConversationContext, Credentials, KrabsConfig,
+    LlmProvider,
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
 use std::io;
 use tokio::sync::mpsc;
 
-use super::agent::{build_agent, run_agent_turn, SharedPerm};
-use super::app::App;
+use super::agent::{build_agent, run_agent_turn, spawn_session_title, SharedPerm};
+use super::app::{mcp_server_pattern, App};
 use super::commands::{
-    at_suggestions, build_registry, cmd_agents, cmd_hooks, cmd_mcp, cmd_models, cmd_skills,
-    cmd_tools, cmd_tools_allow, cmd_tools_deny, cmd_usage, context_limit, load_resume_history,
-    slash_suggestions,
+    at_suggestions, build_registry, cmd_agents, cmd_branch, cmd_compact, cmd_continue, cmd_copy,
+    cmd_effort, cmd_errors, cmd_errors_all, cmd_export, cmd_hooks, cmd_mcp, cmd_mcp_prompt,
+    cmd_models, cmd_permissions, cmd_profile, cmd_prompt, cmd_restore, cmd_retry, cmd_sessions,
+    cmd_set, cmd_skill, cmd_skills, cmd_stats, cmd_tag, cmd_tools, cmd_tools_allow, cmd_tools_deny,
+    cmd_undo, cmd_usage, count_context, find_persona, load_approvals, load_resume_history,
+    save_approval, slash_suggestions, Continuation,
 };
+use super::keys::Keymap;
 use super::render::{render, show_splash};
-use super::types::{ChatMsg, DisplayEvent, InfoBar, PendingUserInput};
+use super::types::{Brand, ChatMsg, DisplayEvent, InfoBar, PendingUserInput, RenderOpts};
+
+/// Diff lines PgUp / PgDn move in the permission popup.
+const DIFF_PAGE: usize = 10;
 
 // ── async helper: recv or park ───────────────────────────────────────────────
 
@@ -33,13 +49,9 @@ async fn recv_event(rx: &mut Option<mpsc::Receiver<DisplayEvent>>) -> Option<Dis
     }
 }
 
-// ── main entry ───────────────────────────────────────────────────────────────
-
-pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
-    let krabs_config = KrabsConfig::load().unwrap_or_default();
-    let mut creds = creds;
-    // Apply krabs_config overrides into creds so .krabs.json / config.json
-    // values take precedence over credentials.json.
+/// Apply `krabs_config` overrides onto `creds`, so `.krabs.json` /
+/// `config.json` values take precedence over `credentials.json`.
+pub(crate) fn apply_config(creds: &mut Credentials, krabs_config: &KrabsConfig) {
     let default_cfg = KrabsConfig::default();
     if !krabs_config.provider.is_empty() {
         creds.provider = krabs_config.provider.clone();
@@ -53,9 +65,44 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
     if !krabs_config.api_key.is_empty() && krabs_config.api_key != creds.api_key {
         creds.api_key = krabs_config.api_key.clone();
     }
+    creds.prompt_caching = krabs_config.enable_prompt_caching;
+    creds.thinking_budget = krabs_config.thinking_budget_tokens;
+    creds.reasoning_effort = krabs_config.reasoning_effort;
+    creds.max_output_tokens = krabs_config.max_output_tokens;
+    creds.stop_sequences = krabs_config.stop_sequences.clone();
+    creds.openai_api = krabs_config.openai_api;
+    creds.trace_http = krabs_config.trace_http;
+    creds.request_timeout_ms = krabs_config.request_timeout_ms;
+    creds.connect_timeout_ms = krabs_config.connect_timeout_ms;
+}
+
+// ── main entry ───────────────────────────────────────────────────────────────
+
+pub async fn run(
+    creds: Credentials,
+    resume_id: Option<String>,
+    dry_run: bool,
+    no_persist: bool,
+    skip_permissions: bool,
+) -> Result<()> {
+    let mut krabs_config = KrabsConfig::load().unwrap_or_default();
+    // `--dry-run` forces it on; a config file can't turn it back off.
+    krabs_config.dry_run |= dry_run;
+    // Likewise `--no-persist` forces persistence off.
+    krabs_config.persist_sessions &= !no_persist;
+    // And `--dangerously-skip-permissions` forces auto-approval on.
+    krabs_config.auto_approve_all |= skip_permissions;
+    if krabs_config.auto_approve_all {
+        tracing::warn!(
+            "auto_approve_all is on: tool calls run without asking, except {} never_auto_approve rule(s)",
+            krabs_config.never_auto_approve.len()
+        );
+    }
+    let mut creds = creds;
+    apply_config(&mut creds, &krabs_config);
     let mut provider: Arc<dyn LlmProvider> = Arc::from(creds.build_provider());
-    let registry = Arc::new(build_registry());
-    let mut max_ctx = context_limit(&creds.model);
+    let registry = Arc::new(build_registry(&krabs_config));
+    let mut max_ctx = krabs_config.context_limit(&creds.model);
     let cwd = std::env::current_dir()
         .map(|p| p.to_string_lossy().to_string())
         .unwrap_or_else(|_| "unknown".into());
@@ -63,21 +110,37 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
         provider: creds.provider.clone(),
         model: creds.model.clone(),
         cwd,
+        root: krabs_config.resolved_project_root().display().to_string(),
         tools: registry.names().join(", "),
         session_id: None,
+        dry_run: krabs_config.dry_run,
+        approve_all: krabs_config.auto_approve_all,
     };
 
+    // Terminals that can tell Shift+Enter from Enter only do so once asked.
+    let key_enhancement = supports_keyboard_enhancement().unwrap_or(false);
+
     // Terminal setup — install a panic hook so we always restore the terminal
     // even if something panics, otherwise the shell is left in raw mode.
     let original_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
-        let _ = disable_raw_mode();
-        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+        restore_terminal(key_enhancement);
         original_hook(info);
     }));
 
     enable_raw_mode()?;
-    execute!(io::stdout(), EnterAlternateScreen)?;
+    execute!(
+        io::stdout(),
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableBracketedPaste
+    )?;
+    if key_enhancement {
+        execute!(
+            io::stdout(),
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        )?;
+    }
     let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
     terminal.clear()?;
 
@@ -93,14 +156,46 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
         }
     });
 
-    show_splash(&mut terminal, &mut key_rx, &creds.provider, &creds.model).await?;
+    let brand = Brand::new(&krabs_config.branding);
+    show_splash(
+        &mut terminal,
+        &mut key_rx,
+        &brand,
+        &creds.provider,
+        &creds.model,
+    )
+    .await?;
 
     let mut app = App::new();
-    app.personas = AgentPersona::discover();
+    app.brand = brand;
+    let (personas, persona_errors) =
+        AgentPersona::discover_with_errors(&krabs_config.persona_paths);
+    app.personas = personas;
+    for error in persona_errors {
+        app.push(ChatMsg::Error(error));
+    }
+    app.render_opts = RenderOpts {
+        markdown: krabs_config.render_markdown,
+        highlight: krabs_config.highlight_code,
+        show_thinking: false,
+    };
+    app.input_max_lines = krabs_config.input_max_lines;
     // Pre-approve tools listed in config so the permission popup never fires for them.
     for tool in &krabs_config.auto_approve_tools {
         app.approved_tools.insert(tool.clone());
     }
+    app.auto_approver = Arc::new(
+        AutoApprover::new(&krabs_config.auto_approve)
+            .with_never(&krabs_config.never_auto_approve)
+            .with_approve_all(krabs_config.auto_approve_all),
+    );
+    for warning in app.auto_approver.warnings() {
+        app.push(ChatMsg::Error(warning));
+    }
+    let (keymap, keymap_warnings) = Keymap::new(&krabs_config.keybindings);
+    for warning in keymap_warnings {
+        app.push(ChatMsg::Error(warning));
+    }
     // Conversation context: the canonical source of truth for messages
     // across multi-turn conversations.
     let mut ctx: ConversationContext;
@@ -126,14 +221,37 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
         }
     } else {
         ctx = ConversationContext::new();
-        let new_id = krabs_core::new_session_id();
-        info.session_id = Some(new_id.clone());
-        pending_session_id = Some(new_id);
+        if krabs_config.persist_sessions {
+            let new_id = krabs_core::new_session_id();
+            info.session_id = Some(new_id.clone());
+            pending_session_id = Some(new_id);
+        }
     }
 
-    let perm: SharedPerm = Arc::new(Mutex::new(None));
+    // Saved "always allow" decisions: global ones plus this session's.
+    if krabs_config.persist_sessions {
+        let approval_sid = active_resume_id.clone().or_else(|| info.session_id.clone());
+        load_approvals(&mut app, &krabs_config, approval_sid.as_deref()).await;
+    }
+
+    let perm: SharedPerm = Arc::new(Mutex::new(VecDeque::new()));
+    // One memory database for the run: every turn's agent and `/prompt`
+    // share its connections.
+    let memory_store = if krabs_config.persist_sessions && krabs_config.memory_tools {
+        krabs_core::SqliteMemoryStore::open_with(
+            &krabs_config.db_path,
+            krabs_core::GLOBAL_NAMESPACE,
+            &krabs_config.sqlite,
+        )
+        .await
+        .ok()
+    } else {
+        None
+    };
     let mut stream_rx: Option<mpsc::Receiver<DisplayEvent>> = None;
     let mut turn_handle: Option<tokio::task::JoinHandle<()>> = None;
+    // Cooperative stop for the running turn; `abort()` is the fallback.
+    let mut turn_cancel: Option<CancellationToken> = None;
 
     'main: loop {
         terminal.draw(|f| render(&mut app, max_ctx, &info, f))?;
@@ -161,10 +279,22 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                         }
                         if app.auto_scroll { app.scroll = u16::MAX; }
                     }
+                    Some(DisplayEvent::Thinking(t)) => {
+                        app.spinning = false;
+                        match app.chat.last_mut() {
+                            Some(ChatMsg::Thinking(s)) => s.push_str(&t),
+                            _ => app.chat.push(ChatMsg::Thinking(t)),
+                        }
+                        if app.auto_scroll { app.scroll = u16::MAX; }
+                    }
                     Some(DisplayEvent::UserInput(req)) => {
                         app.spinning = false;
+                        // Free-text questions open straight into the text field.
+                        let free_text = req.mode == krabs_core::InputMode::FreeText;
                         let mut options = req.options.clone();
-                        options.push("custom…".into());
+                        if !free_text {
+                            options.push("custom…".into());
+                        }
                         let n = options.len();
                         app.pending_user_input = Some(PendingUserInput {
                             mode: req.mode,
@@ -172,7 +302,7 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                             options,
                             selected: vec![false; n],
                             cursor: 0,
-                            custom_mode: false,
+                            custom_mode: free_text,
                             custom_text: String::new(),
                             custom_cursor: 0,
                             respond: req.respond,
@@ -180,25 +310,43 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                     }
                     Some(DisplayEvent::ToolCallStart(call)) => {
                         app.spinning = false;
-                        app.push(ChatMsg::ToolCall(format!("{} {}", call.name, call.args)));
+                        let text = format!("{} {}", call.name, call.args);
+                        app.push(if krabs_config.dry_run {
+                            ChatMsg::DryRunCall(text)
+                        } else {
+                            ChatMsg::ToolCall(text)
+                        });
                     }
-                    Some(DisplayEvent::ToolResultEnd(content)) => {
-                        app.push(ChatMsg::ToolResult(content));
+                    Some(DisplayEvent::ToolResultEnd { content, path }) => {
+                        app.push(ChatMsg::ToolResult(content, path));
                         app.spinning = true;
                     }
                     Some(DisplayEvent::TurnUsage(u)) => {
                         app.total_input += u.input_tokens;
                         app.total_output += u.output_tokens;
+                        app.total_cache_read += u.cache_read_tokens;
+                        app.total_cache_creation += u.cache_creation_tokens;
                         app.push(ChatMsg::Usage(u.input_tokens, u.output_tokens));
                     }
-                    Some(DisplayEvent::Done { messages: final_msgs, session_id }) => {
+                    Some(DisplayEvent::Done { messages: final_msgs, session_id, hit_max_turns }) => {
+                        if let Some(sid) = &session_id {
+                            spawn_session_title(&krabs_config, &creds, sid, &final_msgs);
+                        }
                         ctx.complete_turn(final_msgs);
+                        count_context(&mut app, provider.as_ref(), ctx.messages(), &registry.tool_defs()).await;
                         app.spinning = false;
                         stream_rx = None;
                         turn_handle = None;
+                        turn_cancel = None;
                         if let Some(start) = app.turn_start.take() {
                             app.push(ChatMsg::TurnEnd(start.elapsed().as_secs_f64()));
                         }
+                        if hit_max_turns {
+                            app.push(ChatMsg::Warning(format!(
+                                "stopped after max_turns ({}) before finishing — /continue to keep going",
+                                krabs_config.max_turns
+                            )));
+                        }
                         // Always snap to bottom when the turn completes so the full
                         // response (including the timing line) is visible regardless of
                         // whether the user scrolled up during streaming.
@@ -218,20 +366,25 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                 &krabs_config,
                                 Arc::clone(&provider),
                                 Arc::clone(&registry),
-                                String::new(),
+                                app.agent_prompt(&krabs_config.branding),
                                 tx.clone(),
                                 Arc::clone(&perm),
+                                Arc::clone(&app.auto_approver),
                                 active_resume_id.take(),
                                 None,
+                                memory_store.clone(),
                             )
                             .await;
-                            turn_handle = Some(tokio::spawn(run_agent_turn(agent, turn_input.messages, turn_input.subturn_resume, tx)));
+                            let cancel = CancellationToken::new();
+                            turn_cancel = Some(cancel.clone());
+                            turn_handle = Some(tokio::spawn(run_agent_turn(agent, turn_input.messages, turn_input.subturn_resume, tx, cancel)));
                         }
                     }
                     Some(DisplayEvent::Error { message, session_id }) => {
                         app.spinning = false;
                         stream_rx = None;
                         turn_handle = None;
+                        turn_cancel = None;
                         app.push(ChatMsg::Error(message));
                         app.auto_scroll = true;
                         app.scroll = u16::MAX;
@@ -248,19 +401,40 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                 &krabs_config,
                                 Arc::clone(&provider),
                                 Arc::clone(&registry),
-                                String::new(),
+                                app.agent_prompt(&krabs_config.branding),
                                 tx.clone(),
                                 Arc::clone(&perm),
+                                Arc::clone(&app.auto_approver),
                                 active_resume_id.take(),
                                 None,
+                                memory_store.clone(),
                             )
                             .await;
-                            turn_handle = Some(tokio::spawn(run_agent_turn(agent, turn_input.messages, turn_input.subturn_resume, tx)));
+                            let cancel = CancellationToken::new();
+                            turn_cancel = Some(cancel.clone());
+                            turn_handle = Some(tokio::spawn(run_agent_turn(agent, turn_input.messages, turn_input.subturn_resume, tx, cancel)));
                         }
                     }
                     Some(DisplayEvent::Status(text)) => {
                         app.push(ChatMsg::Info(text));
                     }
+                    Some(DisplayEvent::SubAgentProgress(p)) => {
+                        app.push(ChatMsg::Info(format!("    [{}] {}", p.agent, p.text)));
+                    }
+                    Some(DisplayEvent::Interrupted { partial, resumable }) => {
+                        if resumable {
+                            app.push(ChatMsg::Info("⚡ stream interrupted — continuing from the partial reply".into()));
+                        } else {
+                            // The retry restarts the reply; drop what was shown of it.
+                            if let Some(ChatMsg::Assistant(s)) = app.chat.last_mut() {
+                                if s.ends_with(partial.as_str()) {
+                                    s.truncate(s.len() - partial.len());
+                                }
+                                if s.is_empty() { app.chat.pop(); }
+                            }
+                            app.push(ChatMsg::Info("⚡ stream interrupted — retrying the reply".into()));
+                        }
+                    }
                 }
             }
 
@@ -268,24 +442,71 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
             key = key_rx.recv() => {
                 let Some(ev) = key else { break };
 
+                if let Event::Mouse(mouse) = ev {
+                    app.on_mouse(mouse);
+                    continue 'main;
+                }
+                // Pasted text arrives whole, so its newlines don't submit.
+                if let Event::Paste(text) = ev {
+                    app.paste(&text);
+                    continue 'main;
+                }
                 let Event::Key(key) = ev else { continue 'main };
                 if key.kind != KeyEventKind::Press { continue 'main; }
+                let key = keymap.resolve(key);
 
                 // Ctrl+C: cancel turn if running, quit if idle
                 if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
                     if app.pending_permission.is_some() || app.spinning || stream_rx.is_some() {
                         // Deny any pending permission prompt (dropping sender signals false to task)
                         app.pending_permission = None;
-                        if let Some(h) = turn_handle.take() { h.abort(); }
-                        stream_rx = None;
-                        app.spinning = false;
-                        app.push(ChatMsg::Info("cancelled".into()));
+                        app.pending_user_input = None;
+                        // First Ctrl+C lets the agent stop at its next safe point so the
+                        // turn ends on a checkpoint; a second one aborts it outright.
+                        match turn_cancel.as_ref().filter(|c| !c.is_cancelled()) {
+                            Some(cancel) => {
+                                cancel.cancel();
+                                app.push(ChatMsg::Info("cancelling — finishing the current step (ctrl+c again to force)".into()));
+                            }
+                            None => {
+                                if let Some(h) = turn_handle.take() { h.abort(); }
+                                turn_cancel = None;
+                                stream_rx = None;
+                                app.spinning = false;
+                                app.push(ChatMsg::Info("cancelled".into()));
+                            }
+                        }
                     } else {
                         break;
                     }
                     continue 'main;
                 }
 
+                // Full tool output popup: scroll it, Esc / q / Enter closes it
+                if let Some(out) = app.expanded.as_mut() {
+                    match key.code {
+                        KeyCode::Up => out.scroll = out.scroll.saturating_sub(3),
+                        KeyCode::Down => out.scroll = out.scroll.saturating_add(3),
+                        KeyCode::PageUp => out.scroll = out.scroll.saturating_sub(10),
+                        KeyCode::PageDown => out.scroll = out.scroll.saturating_add(10),
+                        KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => app.expanded = None,
+                        _ => {}
+                    }
+                    continue 'main;
+                }
+
+                // Ctrl+Y: copy the latest assistant reply
+                if key.code == KeyCode::Char('y') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    cmd_copy(&mut app, "");
+                    continue 'main;
+                }
+
+                // Ctrl+T: expand / collapse reasoning sections
+                if key.code == KeyCode::Char('t') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    app.render_opts.show_thinking = !app.render_opts.show_thinking;
+                    continue 'main;
+                }
+
                 let busy = app.spinning || stream_rx.is_some();
 
                 // ── Model picker popup ────────────────────────────────────────
@@ -318,6 +539,7 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                     &mut provider,
                                     &mut info,
                                     &mut max_ctx,
+                                    &krabs_config,
                                 );
                                 app.push(ChatMsg::Info(format!(
                                     "switched to {} / {} ({})",
@@ -336,7 +558,8 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                 // ── User-input popup ──────────────────────────────────────────
                 if app.pending_user_input.is_some() {
                     let ui = app.pending_user_input.as_mut().unwrap();
-                    let last = ui.options.len() - 1; // index of the "custom…" entry
+                    let last = ui.options.len().saturating_sub(1); // index of the "custom…" entry
+                    let free_text = ui.mode == krabs_core::InputMode::FreeText;
 
                     if ui.custom_mode {
                         // Typing a custom answer
@@ -354,8 +577,8 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                             KeyCode::Enter => {
                                 let text = ui.custom_text.trim().to_string();
                                 if text.is_empty() {
-                                    // Back out of custom mode
-                                    ui.custom_mode = false;
+                                    // Back out of custom mode (free text has nowhere to go back to)
+                                    ui.custom_mode = free_text;
                                 } else {
                                     let answer = text;
                                     if let Some(p) = app.pending_user_input.take() {
@@ -365,6 +588,13 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                     }
                                 }
                             }
+                            KeyCode::Esc if free_text => {
+                                if let Some(p) = app.pending_user_input.take() {
+                                    app.push(ChatMsg::Info("  ↳ (cancelled)".into()));
+                                    let _ = p.respond.send(String::new());
+                                    app.spinning = true;
+                                }
+                            }
                             KeyCode::Esc => {
                                 ui.custom_mode = false;
                                 ui.custom_text.clear();
@@ -402,6 +632,8 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                             }
                                         }
                                     }
+                                    // Free text never leaves custom_mode.
+                                    krabs_core::InputMode::FreeText => {}
                                     krabs_core::InputMode::ChooseMany => {
                                         if ui.cursor == last && !ui.custom_mode {
                                             ui.custom_mode = true;
@@ -445,7 +677,7 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                     continue 'main;
                 }
 
-                // ── Permission prompt: intercept y / a / n ────────────────────
+                // ── Permission prompt: intercept y / a / g / s / n ────────────
                 if app.pending_permission.is_some() {
                     match key.code {
                         // Allow once
@@ -456,18 +688,47 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                 app.spinning = true;
                             }
                         }
-                        // Allow always (add to approved set)
+                        // Allow always in this session (saved, restored on resume)
                         KeyCode::Char('a') => {
                             if let Some(p) = app.pending_permission.take() {
-                                app.approved_tools.insert(p.tool_name.clone());
+                                let sid = active_resume_id.clone().or_else(|| info.session_id.clone());
+                                save_approval(&mut app, &krabs_config, &p.tool_name, sid.as_deref()).await;
                                 app.push(ChatMsg::Info(format!(
-                                    "  ✓ always allow: {}",
+                                    "  ✓ always allow this session: {}",
                                     p.tool_name
                                 )));
                                 let _ = p.respond.send(true);
                                 app.spinning = true;
                             }
                         }
+                        // Allow always in every session (revoke with /tools deny)
+                        KeyCode::Char('g') => {
+                            if let Some(p) = app.pending_permission.take() {
+                                save_approval(&mut app, &krabs_config, &p.tool_name, None).await;
+                                app.push(ChatMsg::Info(format!(
+                                    "  ✓ always allow everywhere: {}  (revoke with /tools deny {})",
+                                    p.tool_name, p.tool_name
+                                )));
+                                let _ = p.respond.send(true);
+                                app.spinning = true;
+                            }
+                        }
+                        // Allow always in this session for every tool of the same MCP server
+                        KeyCode::Char('s') => {
+                            let pattern = app
+                                .pending_permission
+                                .as_ref()
+                                .and_then(|p| mcp_server_pattern(&p.tool_name));
+                            if let Some(pattern) = pattern {
+                                if let Some(p) = app.pending_permission.take() {
+                                    let sid = active_resume_id.clone().or_else(|| info.session_id.clone());
+                                    save_approval(&mut app, &krabs_config, &pattern, sid.as_deref()).await;
+                                    app.push(ChatMsg::Info(format!("  ✓ always allow this session: {pattern}")));
+                                    let _ = p.respond.send(true);
+                                    app.spinning = true;
+                                }
+                            }
+                        }
                         // Deny
                         KeyCode::Char('n') | KeyCode::Esc => {
                             if let Some(p) = app.pending_permission.take() {
@@ -476,11 +737,30 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                 app.spinning = true;
                             }
                         }
+                        // Scroll the diff; the popup keeps it within bounds.
+                        KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown => {
+                            if let Some(p) = app.pending_permission.as_mut() {
+                                p.scroll = match key.code {
+                                    KeyCode::Up => p.scroll.saturating_sub(1),
+                                    KeyCode::Down => p.scroll + 1,
+                                    KeyCode::PageUp => p.scroll.saturating_sub(DIFF_PAGE),
+                                    _ => p.scroll + DIFF_PAGE,
+                                };
+                            }
+                        }
                         _ => {}
                     }
                     continue 'main;
                 }
 
+                // Up / Down move between the lines of a multi-line message
+                // before they pick suggestions or scroll.
+                if key.code == KeyCode::Up && app.cursor_up()
+                    || key.code == KeyCode::Down && app.cursor_down()
+                {
+                    continue 'main;
+                }
+
                 // Scroll (always available)
                 match key.code {
                     KeyCode::Up if !busy => {
@@ -503,8 +783,7 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                 Some(i) => i - 1,
                             });
                         } else {
-                            app.auto_scroll = false;
-                            app.scroll = app.scroll.saturating_sub(3);
+                            app.scroll_up(3);
                         }
                         continue 'main;
                     }
@@ -528,38 +807,24 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                 Some(i) => (i + 1) % len,
                             });
                         } else {
-                            app.scroll = app.scroll.saturating_add(3);
-                            if app.scroll >= app.max_scroll {
-                                app.scroll = u16::MAX;
-                                app.auto_scroll = true;
-                            }
+                            app.scroll_down(3);
                         }
                         continue 'main;
                     }
                     KeyCode::Up => {
-                        app.auto_scroll = false;
-                        app.scroll = app.scroll.saturating_sub(3);
+                        app.scroll_up(3);
                         continue 'main;
                     }
                     KeyCode::Down => {
-                        app.scroll = app.scroll.saturating_add(3);
-                        if app.scroll >= app.max_scroll {
-                            app.scroll = u16::MAX;
-                            app.auto_scroll = true;
-                        }
+                        app.scroll_down(3);
                         continue 'main;
                     }
                     KeyCode::PageUp => {
-                        app.auto_scroll = false;
-                        app.scroll = app.scroll.saturating_sub(10);
+                        app.scroll_up(10);
                         continue 'main;
                     }
                     KeyCode::PageDown => {
-                        app.scroll = app.scroll.saturating_add(10);
-                        if app.scroll >= app.max_scroll {
-                            app.scroll = u16::MAX;
-                            app.auto_scroll = true;
-                        }
+                        app.scroll_down(10);
                         continue 'main;
                     }
                     _ => {}
@@ -568,23 +833,7 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                 match key.code {
                     // Tab: autocomplete selected suggestion
                     KeyCode::Tab => {
-                        if app.input.starts_with('@') && !app.input.contains(' ') {
-                            let at_sugg = at_suggestions(&app.input[1..], &app.personas);
-                            if !at_sugg.is_empty() {
-                                let idx = app.suggest_idx.unwrap_or(0);
-                                app.input = format!("@{}", at_sugg[idx].0);
-                                app.cursor = app.input.len();
-                                app.suggest_idx = None;
-                            }
-                        } else {
-                            let suggestions = slash_suggestions(&app.input);
-                            if !suggestions.is_empty() {
-                                let idx = app.suggest_idx.unwrap_or(0);
-                                app.input = suggestions[idx].0.to_string();
-                                app.cursor = app.input.len();
-                                app.suggest_idx = None;
-                            }
-                        }
+                        app.accept_suggestion();
                         continue 'main;
                     }
                     // Escape: dismiss suggestion popup
@@ -595,10 +844,10 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                     KeyCode::Left  => { app.suggest_idx = None; app.cursor_left(); }
                     KeyCode::Right => { app.suggest_idx = None; app.cursor_right(); }
                     KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                        app.cursor = 0;
+                        app.line_start();
                     }
                     KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                        app.cursor = app.input.len();
+                        app.line_end();
                     }
                     KeyCode::Backspace => { app.suggest_idx = None; app.backspace(); }
 
@@ -626,6 +875,11 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                         }
                     }
 
+                    // Alt+Enter, or Shift+Enter where the terminal reports it: a line break
+                    KeyCode::Enter if key.modifiers.intersects(KeyModifiers::ALT | KeyModifiers::SHIFT) => {
+                        app.suggest_idx = None;
+                        app.insert_char('\n');
+                    }
                     KeyCode::Enter => {
                         // If a slash suggestion is selected, complete it instead of submitting
                         let slash_sugg = slash_suggestions(&app.input);
@@ -657,6 +911,16 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                         app.auto_scroll = true;
                         app.scroll = u16::MAX;
 
+                        // `/mcp prompt` expands to the prompt's text and is then
+                        // sent (or queued) exactly like a typed message.
+                        let input = match input.strip_prefix("/mcp prompt ") {
+                            Some(args) => match cmd_mcp_prompt(&mut app, args).await {
+                                Some(text) => text,
+                                None => continue 'main,
+                            },
+                            None => input,
+                        };
+
                         // Queue message if a turn is running; it will be dispatched on Done.
                         if busy {
                             app.push(ChatMsg::User(input.clone()));
@@ -669,10 +933,9 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                             let name = input[1..].trim();
                             // Rediscover if personas not loaded
                             if app.personas.is_empty() {
-                                app.personas = AgentPersona::discover();
+                                app.personas = AgentPersona::discover(&krabs_config.persona_paths);
                             }
-                            if let Some(pos) = app.personas.iter().position(|p| p.name == name) {
-                                let persona = app.personas.remove(pos);
+                            if let Some(persona) = find_persona(name, &app.personas) {
                                 // Optionally switch provider
                                 if persona.model.is_some() || persona.provider.is_some() {
                                     let new_model = persona.model.as_deref().unwrap_or(&creds.model);
@@ -688,23 +951,13 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                         new_prov, new_model
                                     )));
                                 }
+                                let kind = if persona.is_builtin() { "built-in agent".to_string() } else { format!("{} persona", persona.source.as_str()) };
                                 app.push(ChatMsg::Info(format!(
-                                    "switched to persona '@{}'",
-                                    persona.name
+                                    "switched to {} '@{}'",
+                                    kind, persona.name
                                 )));
-                                app.personas.insert(pos, persona);
-                                // Activate — re-borrow by index
-                                let persona_name = app.personas[pos].name.clone();
-                                app.active_persona = Some(AgentPersona {
-                                    name: app.personas[pos].name.clone(),
-                                    description: app.personas[pos].description.clone(),
-                                    model: app.personas[pos].model.clone(),
-                                    provider: app.personas[pos].provider.clone(),
-                                    system_prompt: app.personas[pos].system_prompt.clone(),
-                                    path: app.personas[pos].path.clone(),
-                                });
-                                app.persona_text = app.personas[pos].system_prompt.clone();
-                                let _ = persona_name; // used above
+                                app.persona_text = persona.system_prompt.clone();
+                                app.active_persona = Some(persona);
                             } else {
                                 app.push(ChatMsg::Error(format!(
                                     "persona '@{}' not found — use /agents list to see available personas",
@@ -714,14 +967,76 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                             continue 'main;
                         }
 
+                        // Set by `/continue` to rerun the loop over the
+                        // history instead of starting a turn with `input`.
+                        let mut continuing = None;
+                        // `/retry` rolls the last turn back and sends its
+                        // message again, as if it had just been typed.
+                        let input = if input == "/retry" || input.starts_with("/retry ") {
+                            let tweak = input["/retry".len()..].trim();
+                            let current = active_resume_id.clone().or_else(|| {
+                                info.session_id
+                                    .clone()
+                                    .filter(|_| pending_session_id.is_none())
+                            });
+                            match cmd_retry(&mut app, &krabs_config, &mut ctx, current.as_deref(), tweak).await {
+                                Some(text) => text,
+                                None => continue 'main,
+                            }
+                        } else if input == "/continue" {
+                            let current = active_resume_id.clone().or_else(|| {
+                                info.session_id
+                                    .clone()
+                                    .filter(|_| pending_session_id.is_none())
+                            });
+                            match cmd_continue(&mut app, &krabs_config, &mut ctx, current.as_deref()).await {
+                                Some(Continuation::Send(text)) => text,
+                                Some(Continuation::Resume(turn_input)) => {
+                                    continuing = Some(turn_input);
+                                    input
+                                }
+                                None => continue 'main,
+                            }
+                        } else {
+                            input
+                        };
+
                         match input.as_str() {
                             "/quit" => break 'main,
+                            // The context is reset but the session kept:
+                            // later turns are appended to it.
                             "/clear" => {
                                 app.chat.clear();
                                 ctx = ConversationContext::new();
-                                active_resume_id = None;
                                 app.total_input = 0;
                                 app.total_output = 0;
+                                app.total_cache_read = 0;
+                                app.total_cache_creation = 0;
+                                app.context_tokens = None;
+                            }
+                            "/new" => {
+                                app.chat.clear();
+                                ctx = ConversationContext::new();
+                                app.total_input = 0;
+                                app.total_output = 0;
+                                app.total_cache_read = 0;
+                                app.total_cache_creation = 0;
+                                app.context_tokens = None;
+                                active_resume_id = None;
+                                if krabs_config.persist_sessions {
+                                    // Pre-assigned, as at startup, so the next
+                                    // turn is persisted under it.
+                                    let new_id = krabs_core::new_session_id();
+                                    info.session_id = Some(new_id.clone());
+                                    pending_session_id = Some(new_id.clone());
+                                    load_approvals(&mut app, &krabs_config, Some(&new_id)).await;
+                                    app.push(ChatMsg::Info(format!("New session {new_id}")));
+                                } else {
+                                    info.session_id = None;
+                                    app.push(ChatMsg::Info(
+                                        "New conversation (sessions aren't persisted)".into(),
+                                    ));
+                                }
                             }
                             s if s.starts_with("/resume ") => {
                                 let sid = s.strip_prefix("/resume ").unwrap_or("").trim();
@@ -739,33 +1054,210 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                         ctx = ConversationContext::from_history(history, sr);
                                         app.total_input = 0;
                                         app.total_output = 0;
+                                        app.total_cache_read = 0;
+                                        app.total_cache_creation = 0;
+                                        app.context_tokens = None;
                                         for dm in display_msgs {
                                             app.chat.push(dm);
                                         }
                                         active_resume_id = Some(sid.to_string());
+                                        load_approvals(&mut app, &krabs_config, Some(sid)).await;
                                         app.push(ChatMsg::Info(format!(
                                             "Resumed session {sid}"
                                         )));
                                     }
                                 }
                             }
+                            "/branch" => {
+                                // The persisted session: the resumed one, or the
+                                // pre-assigned one once its first turn has run.
+                                let current = active_resume_id.clone().or_else(|| {
+                                    info.session_id
+                                        .clone()
+                                        .filter(|_| pending_session_id.is_none())
+                                });
+                                match current {
+                                    Some(sid) => {
+                                        if let Some((branch_id, (inp, out))) =
+                                            cmd_branch(&mut app, &krabs_config, &sid).await
+                                        {
+                                            let (history, display_msgs, sr): (Vec<_>, Vec<_>, _) =
+                                                load_resume_history(&krabs_config, &branch_id).await;
+                                            app.chat.clear();
+                                            for dm in display_msgs {
+                                                app.chat.push(dm);
+                                            }
+                                            ctx = ConversationContext::from_history(history, sr);
+                                            app.total_input = inp;
+                                            app.total_output = out;
+                                            app.total_cache_read = 0;
+                                            app.total_cache_creation = 0;
+                                            app.context_tokens = None;
+                                            info.session_id = Some(branch_id.clone());
+                                            active_resume_id = Some(branch_id.clone());
+                                            load_approvals(&mut app, &krabs_config, Some(&branch_id)).await;
+                                            app.push(ChatMsg::Info(format!(
+                                                "branched {sid} → {branch_id}"
+                                            )));
+                                        }
+                                    }
+                                    None => app.push(ChatMsg::Error(
+                                        "nothing to branch yet — send a message first".into(),
+                                    )),
+                                }
+                            }
+                            "/compact" => {
+                                let current = active_resume_id.clone().or_else(|| {
+                                    info.session_id
+                                        .clone()
+                                        .filter(|_| pending_session_id.is_none())
+                                });
+                                cmd_compact(
+                                    &mut app,
+                                    &krabs_config,
+                                    provider.as_ref(),
+                                    &mut ctx,
+                                    current.as_deref(),
+                                )
+                                .await;
+                                count_context(&mut app, provider.as_ref(), ctx.messages(), &registry.tool_defs()).await;
+                            }
+                            s if s == "/sessions" || s.starts_with("/sessions ") => {
+                                let args = s.strip_prefix("/sessions").unwrap_or("").trim();
+                                cmd_sessions(&mut app, &krabs_config, args).await;
+                            }
+                            s if s == "/tag" || s.starts_with("/tag ") => {
+                                let label = s.strip_prefix("/tag").unwrap_or("").trim();
+                                let current = active_resume_id.clone().or_else(|| {
+                                    info.session_id
+                                        .clone()
+                                        .filter(|_| pending_session_id.is_none())
+                                });
+                                match (current, label.is_empty()) {
+                                    (_, true) => app.push(ChatMsg::Error(
+                                        "usage: /tag <label>".into(),
+                                    )),
+                                    (Some(sid), false) => {
+                                        cmd_tag(&mut app, &krabs_config, &sid, label).await
+                                    }
+                                    (None, false) => app.push(ChatMsg::Error(
+                                        "nothing to tag yet — send a message first".into(),
+                                    )),
+                                }
+                            }
+                            s if s == "/errors" || s.starts_with("/errors ") => {
+                                let args = s.strip_prefix("/errors").unwrap_or("").trim();
+                                let current = active_resume_id.clone().or_else(|| {
+                                    info.session_id
+                                        .clone()
+                                        .filter(|_| pending_session_id.is_none())
+                                });
+                                match (args, current) {
+                                    ("--all", _) => cmd_errors_all(&mut app, &krabs_config).await,
+                                    ("", Some(sid)) => {
+                                        cmd_errors(&mut app, &krabs_config, &sid).await
+                                    }
+                                    ("", None) => app.push(ChatMsg::Info(
+                                        "no errors — nothing has run in this session yet".into(),
+                                    )),
+                                    _ => app.push(ChatMsg::Error(
+                                        "usage: /errors [--all]".into(),
+                                    )),
+                                }
+                            }
+                            s if s == "/stats" || s.starts_with("/stats ") => {
+                                let args = s.strip_prefix("/stats").unwrap_or("").trim();
+                                let current = active_resume_id.clone().or_else(|| {
+                                    info.session_id
+                                        .clone()
+                                        .filter(|_| pending_session_id.is_none())
+                                });
+                                match (args, current) {
+                                    ("--all", _) => cmd_stats(&mut app, &krabs_config, None).await,
+                                    ("", Some(sid)) => {
+                                        cmd_stats(&mut app, &krabs_config, Some(&sid)).await
+                                    }
+                                    ("", None) => app.push(ChatMsg::Info(
+                                        "no tool stats — nothing has run in this session yet".into(),
+                                    )),
+                                    _ => app.push(ChatMsg::Error(
+                                        "usage: /stats [--all]".into(),
+                                    )),
+                                }
+                            }
+                            s if s == "/restore" || s.starts_with("/restore ") => {
+                                let path = s.strip_prefix("/restore").unwrap_or("").trim();
+                                cmd_restore(&mut app, &krabs_config, path).await;
+                            }
+                            s if s == "/undo" || s.starts_with("/undo ") => {
+                                let args = s.strip_prefix("/undo").unwrap_or("").trim();
+                                let current = active_resume_id.clone().or_else(|| {
+                                    info.session_id
+                                        .clone()
+                                        .filter(|_| pending_session_id.is_none())
+                                });
+                                match current {
+                                    Some(sid) => cmd_undo(&mut app, &krabs_config, &sid, args).await,
+                                    None => app.push(ChatMsg::Info("nothing to undo".into())),
+                                }
+                            }
+                            s if s == "/copy" || s.starts_with("/copy ") => {
+                                let args = s.strip_prefix("/copy").unwrap_or("").trim();
+                                cmd_copy(&mut app, args);
+                            }
+                            s if s == "/export" || s.starts_with("/export ") => {
+                                let path = s.strip_prefix("/export").unwrap_or("").trim();
+                                let current = active_resume_id.clone().or_else(|| {
+                                    info.session_id
+                                        .clone()
+                                        .filter(|_| pending_session_id.is_none())
+                                });
+                                match (current, path.is_empty()) {
+                                    (_, true) => app.push(ChatMsg::Error(
+                                        "usage: /export <path.md|path.json>".into(),
+                                    )),
+                                    (Some(sid), false) => {
+                                        cmd_export(&mut app, &krabs_config, &sid, path).await
+                                    }
+                                    (None, false) => app.push(ChatMsg::Error(
+                                        "nothing to export yet — send a message first".into(),
+                                    )),
+                                }
+                            }
                             s if s == "/tools" || s.starts_with("/tools ") => {
                                 let args = s.strip_prefix("/tools").unwrap_or("").trim();
                                 match args.split_once(' ') {
                                     Some(("allow", name)) => cmd_tools_allow(&mut app, name.trim()),
-                                    Some(("deny", name))  => cmd_tools_deny(&mut app, name.trim()),
+                                    Some(("deny", name))  => {
+                                        let sid = active_resume_id.clone().or_else(|| info.session_id.clone());
+                                        cmd_tools_deny(&mut app, &krabs_config, sid.as_deref(), name.trim()).await
+                                    }
                                     _ => cmd_tools(&mut app, &registry),
                                 }
                             }
+                            "/permissions" => cmd_permissions(&mut app),
                             "/skills" => cmd_skills(&mut app, &krabs_config.skills),
+                            s if s == "/skill" || s.starts_with("/skill ") => {
+                                let args = s.strip_prefix("/skill").unwrap_or("").trim();
+                                cmd_skill(&mut app, args, &krabs_config.skills).await;
+                            }
                             s if s == "/mcp" || s.starts_with("/mcp ") => {
                                 let mcp_args = s.strip_prefix("/mcp").unwrap_or("").trim();
                                 cmd_mcp(&mut app, mcp_args).await;
                             }
-                            "/usage"  => cmd_usage(&mut app, max_ctx, &krabs_config.skills).await,
+                            "/prompt" => {
+                                cmd_prompt(&mut app, &krabs_config, Arc::clone(&provider), memory_store.as_ref()).await
+                            }
+                            "/usage"  => {
+                                cmd_usage(
+                                    &mut app, max_ctx, &krabs_config.skills,
+                                    provider.as_ref(), ctx.messages(), &registry.tool_defs(),
+                                )
+                                .await
+                            }
                             s if s == "/agents" || s.starts_with("/agents ") => {
                                 let args = s.strip_prefix("/agents").unwrap_or("").trim();
-                                cmd_agents(&mut app, args);
+                                cmd_agents(&mut app, args, &krabs_config.persona_paths);
                             }
                             s if s == "/hooks" || s.starts_with("/hooks ") => {
                                 let args = s.strip_prefix("/hooks").unwrap_or("").trim();
@@ -779,31 +1271,35 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                     &krabs_config.custom_models,
                                 );
                             }
+                            s if s == "/effort" || s.starts_with("/effort ") => {
+                                let args = s.strip_prefix("/effort").unwrap_or("").trim();
+                                cmd_effort(&mut app, args, &mut creds, &mut provider);
+                            }
+                            s if s == "/set" || s.starts_with("/set ") => {
+                                let args = s.strip_prefix("/set").unwrap_or("").trim();
+                                cmd_set(&mut app, args, &mut creds, &mut provider);
+                            }
+                            s if s == "/profile" || s.starts_with("/profile ") => {
+                                let args = s.strip_prefix("/profile").unwrap_or("").trim();
+                                cmd_profile(
+                                    &mut app, args, &mut creds,
+                                    &mut provider, &mut info, &mut max_ctx,
+                                    &krabs_config,
+                                );
+                            }
                             _ => {
-                                app.push(ChatMsg::User(input.clone()));
-
-                                let turn_input = ctx.begin_turn(&input);
-                                // Optionally prepend persona system prompt
-                                let mut turn_messages = turn_input.messages;
-                                if let Some(ref persona) = app.active_persona {
-                                    let base_prompt = format!(
-                                        "You are Krabs, an agentic assistant.\n\n---\n\n{}",
-                                        persona.system_prompt
-                                    );
-                                    let has_system = turn_messages
-                                        .first()
-                                        .map(|m| matches!(m.role, Role::System))
-                                        .unwrap_or(false);
-                                    if !has_system {
-                                        turn_messages.insert(0, Message::system(&base_prompt));
+                                let turn_input = match continuing {
+                                    Some(turn_input) => turn_input,
+                                    None => {
+                                        app.push(ChatMsg::User(input.clone()));
+                                        ctx.begin_turn(&input)
                                     }
-                                }
+                                };
                                 app.spinning = true;
                                 app.turn_start = Some(std::time::Instant::now());
 
                                 // Capture context breakdown estimates (once per turn)
-                                const BASE_SYSTEM_PROMPT: &str = "You are Krabs, an agentic assistant.";
-                                app.system_prompt_text = BASE_SYSTEM_PROMPT.to_string();
+                                app.system_prompt_text = krabs_config.branding.intro();
                                 app.tools_text = serde_json::to_string(&registry.tool_defs())
                                     .unwrap_or_default();
 
@@ -814,18 +1310,24 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                     &krabs_config,
                                     Arc::clone(&provider),
                                     Arc::clone(&registry),
-                                    String::new(), // system prompt injected by KrabsAgent
+                                    // KrabsAgent puts its base prompt before it.
+                                    app.agent_prompt(&krabs_config.branding),
                                     tx.clone(),
                                     Arc::clone(&perm),
+                                    Arc::clone(&app.auto_approver),
                                     active_resume_id.take(),
                                     pending_session_id.take(),
+                                    memory_store.clone(),
                                 )
                                 .await;
+                                let cancel = CancellationToken::new();
+                                turn_cancel = Some(cancel.clone());
                                 turn_handle = Some(tokio::spawn(run_agent_turn(
                                     agent,
-                                    turn_messages,
+                                    turn_input.messages,
                                     turn_input.subturn_resume,
                                     tx,
+                                    cancel,
                                 )));
                             }
                         }
@@ -840,12 +1342,19 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
             _ = tokio::time::sleep(Duration::from_millis(80)) => {
                 if app.spinning { app.spin_i += 1; }
 
-                // Poll shared permission state (written by TuiHook via mutex)
+                // Poll shared permission state (queued by TuiHook via mutex)
                 if app.pending_permission.is_none() {
-                    if let Ok(mut guard) = perm.try_lock() {
-                        if let Some(pending) = guard.take() {
+                    if let Ok(mut queue) = perm.try_lock() {
+                        // A turn that is being cancelled runs no further tools.
+                        if turn_cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+                            for pending in queue.drain(..) {
+                                let _ = pending.respond.send(false);
+                            }
+                            continue 'main;
+                        }
+                        while let Some(pending) = queue.pop_front() {
                             app.spinning = false;
-                            if app.approved_tools.contains(&pending.tool_name) {
+                            if !pending.always_ask && app.is_approved(&pending.tool_name) {
                                 let _ = pending.respond.send(true);
                             } else {
                                 let truncated = if pending.args.len() > 60 {
@@ -853,11 +1362,18 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
                                 } else {
                                     pending.args.clone()
                                 };
+                                let server_hint = if mcp_server_pattern(&pending.tool_name).is_some() {
+                                    "  [s] whole server"
+                                } else {
+                                    ""
+                                };
                                 app.push(ChatMsg::Info(format!(
-                                    "⚠ permission needed: {} {}  →  [y] allow  [a] always  [n] deny",
+                                    "⚠ permission needed: {} {}  →  [y] once  [a] this session  [g] everywhere{server_hint}  [n] deny",
                                     pending.tool_name, truncated
                                 )));
+                                // The rest stay queued until this one is answered.
                                 app.pending_permission = Some(pending);
+                                break;
                             }
                         }
                     }
@@ -866,7 +1382,21 @@ pub async fn run(creds: Credentials, resume_id: Option<String>) -> Result<()> {
         }
     }
 
-    let _ = disable_raw_mode();
-    let _ = execute!(io::stdout(), LeaveAlternateScreen);
+    restore_terminal(key_enhancement);
     Ok(())
 }
+
+/// Undo the terminal setup of [`run`]; errors are ignored, there is nothing
+/// left to do about them.
+fn restore_terminal(key_enhancement: bool) {
+    let _ = disable_raw_mode();
+    if key_enhancement {
+        let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+    }
+    let _ = execute!(
+        io::stdout(),
+        DisableBracketedPaste,
+        DisableMouseCapture,
+        LeaveAlternateScreen
+    );
+}
@@ -1,30 +1,159 @@
-use krabs_core::{Message, TokenUsage, ToolCall, UserInputRequest};
+use krabs_core::{BrandingConfig, Message, TokenUsage, ToolCall, UserInputRequest};
 use ratatui::{
     style::{Color, Modifier, Style},
     text::{Line, Span},
+    widgets::{Paragraph, Wrap},
 };
 use tokio::sync::oneshot;
 
+use super::highlight::CodeHighlighter;
+
 // ── chat message types ───────────────────────────────────────────────────────
 
-#[derive(Clone)]
+/// Tool results show this many lines inline; clicking one opens it in full.
+const TOOL_RESULT_LINES: usize = 40;
+
+/// Display toggles applied when turning chat messages into lines.
+#[derive(Clone, Copy, PartialEq)]
+pub(super) struct RenderOpts {
+    /// Render assistant messages through `render::markdown_lines`.
+    pub(super) markdown: bool,
+    /// Syntax-highlight fenced code and `read` results.
+    pub(super) highlight: bool,
+    /// Expand reasoning sections; toggled with Ctrl+T.
+    pub(super) show_thinking: bool,
+}
+
+/// Name, accent colour and logo from `KrabsConfig::branding`, resolved for
+/// drawing.
+pub(super) struct Brand {
+    /// Lowercased name padded with spaces, for box titles and the reply badge.
+    pub(super) title: String,
+    pub(super) accent: Color,
+    /// Splash-screen logo; the built-in `render::LOGO` when none is configured.
+    pub(super) logo: Vec<String>,
+}
+
+impl Brand {
+    pub(super) fn new(config: &BrandingConfig) -> Self {
+        let [r, g, b] = config.accent;
+        let logo = if config.logo.is_empty() {
+            super::render::LOGO
+                .iter()
+                .map(|row| row.to_string())
+                .collect()
+        } else {
+            config.logo.clone()
+        };
+        Self {
+            title: format!(" {} ", config.name.to_lowercase()),
+            accent: Color::Rgb(r, g, b),
+            logo,
+        }
+    }
+}
+
+/// Text opened in full in a popup (a tool result, or `/prompt`), with its
+/// own scroll offset.
+pub(super) struct ExpandedOutput {
+    pub(super) title: String,
+    pub(super) content: String,
+    pub(super) scroll: u16,
+}
+
+/// A skill loaded with `/skill use`: its SKILL.md body goes into the system
+/// prompt of every turn until `/skill drop`.
+pub(super) struct ActiveSkill {
+    pub(super) name: String,
+    pub(super) body: String,
+}
+
+#[derive(Clone, PartialEq)]
 pub(super) enum ChatMsg {
     User(String),
     Assistant(String),
+    /// Model reasoning, shown dimmed and collapsed unless `show_thinking`.
+    Thinking(String),
     ToolCall(String),
-    ToolResult(String),
+    /// A tool call that was only described, not executed (`dry_run`).
+    DryRunCall(String),
+    /// Tool output, plus the source file it came from (for `read`) so it can
+    /// be syntax-highlighted by extension.
+    ToolResult(String, Option<String>),
     Usage(u32, u32),
     /// End-of-turn marker: elapsed seconds for the full thinking+answering cycle.
     TurnEnd(f64),
     Info(String),
+    /// Something went short of plan without failing, e.g. a turn cut off at
+    /// `max_turns`.
+    Warning(String),
     Error(String),
 }
 
+/// A chat message's lines as drawn, kept across frames so only messages
+/// that changed (in practice the streaming tail) are rendered again.
+pub(super) struct RenderedMsg {
+    msg: ChatMsg,
+    opts: RenderOpts,
+    pub(super) lines: Vec<Line<'static>>,
+    width: u16,
+    /// Rows `lines` take once word-wrapped to `width`.
+    pub(super) height: usize,
+}
+
+impl RenderedMsg {
+    fn new(msg: &ChatMsg, opts: RenderOpts, brand: &Brand, width: u16) -> Self {
+        let lines = msg.to_lines(opts, brand);
+        Self {
+            msg: msg.clone(),
+            opts,
+            height: wrapped_height(&lines, width),
+            lines,
+            width,
+        }
+    }
+
+    /// Bring `cache` in line with `chat`: reuse entries whose message and
+    /// options are unchanged (re-wrapping them if `width` moved) and render
+    /// the rest.
+    pub(super) fn refresh(
+        cache: &mut Vec<RenderedMsg>,
+        chat: &[ChatMsg],
+        opts: RenderOpts,
+        brand: &Brand,
+        width: u16,
+    ) {
+        cache.truncate(chat.len());
+        for (i, msg) in chat.iter().enumerate() {
+            match cache.get_mut(i) {
+                Some(r) if r.msg == *msg && r.opts == opts => {
+                    if r.width != width {
+                        r.height = wrapped_height(&r.lines, width);
+                        r.width = width;
+                    }
+                }
+                Some(r) => *r = RenderedMsg::new(msg, opts, brand, width),
+                None => cache.push(RenderedMsg::new(msg, opts, brand, width)),
+            }
+        }
+    }
+}
+
+/// Rows `lines` take in a pane `width` columns wide, using ratatui's own
+/// word wrapping.
+pub(super) fn wrapped_height(lines: &[Line<'static>], width: u16) -> usize {
+    Paragraph::new(lines.to_vec())
+        .wrap(Wrap { trim: false })
+        .line_count(width)
+}
+
 impl ChatMsg {
-    pub(super) fn to_lines(&self) -> Vec<Line<'static>> {
+    pub(super) fn to_lines(&self, opts: RenderOpts, brand: &Brand) -> Vec<Line<'static>> {
         match self {
-            ChatMsg::User(t) => vec![
-                Line::from(vec![
+            ChatMsg::User(t) => {
+                let style = Style::default().fg(Color::Cyan);
+                let mut rows = t.lines();
+                let mut lines = vec![Line::from(vec![
                     Span::styled(
                         " you ",
                         Style::default()
@@ -33,23 +162,59 @@ impl ChatMsg {
                             .add_modifier(Modifier::BOLD),
                     ),
                     Span::raw("  "),
-                    Span::styled(t.clone(), Style::default().fg(Color::Cyan)),
-                ]),
-                Line::raw(""),
-            ],
+                    Span::styled(rows.next().unwrap_or("").to_string(), style),
+                ])];
+                // Further lines of a multi-line message line up under the first.
+                lines.extend(rows.map(|row| Line::styled(format!("       {row}"), style)));
+                lines.push(Line::raw(""));
+                lines
+            }
             ChatMsg::Assistant(t) => {
                 let mut lines = vec![Line::from(Span::styled(
-                    " krabs ",
+                    brand.title.clone(),
                     Style::default()
                         .fg(Color::Black)
                         .bg(Color::Green)
                         .add_modifier(Modifier::BOLD),
                 ))];
+                if opts.markdown {
+                    lines.extend(super::render::markdown_lines(
+                        t,
+                        "  ",
+                        opts.highlight,
+                        brand.accent,
+                    ));
+                } else {
+                    for l in t.lines() {
+                        lines.push(Line::from(Span::styled(
+                            format!("  {l}"),
+                            Style::default().fg(Color::White),
+                        )));
+                    }
+                }
+                lines.push(Line::raw(""));
+                lines
+            }
+            ChatMsg::Thinking(t) => {
+                let dim = Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::DIM);
+                if !opts.show_thinking {
+                    let n = t.lines().count();
+                    return vec![
+                        Line::from(Span::styled(
+                            format!("  ▸ thinking · {n} lines  (ctrl+t to expand)"),
+                            dim.add_modifier(Modifier::ITALIC),
+                        )),
+                        Line::raw(""),
+                    ];
+                }
+                let mut lines = vec![Line::from(Span::styled(
+                    "  ▾ thinking  (ctrl+t to collapse)",
+                    dim.add_modifier(Modifier::ITALIC),
+                ))];
                 for l in t.lines() {
-                    lines.push(Line::from(Span::styled(
-                        format!("  {l}"),
-                        Style::default().fg(Color::White),
-                    )));
+                    lines.push(Line::from(Span::styled(format!("  │ {l}"), dim)));
                 }
                 lines.push(Line::raw(""));
                 lines
@@ -63,17 +228,47 @@ impl ChatMsg {
                 ),
                 Span::styled(t.clone(), Style::default().fg(Color::Yellow)),
             ])],
-            ChatMsg::ToolResult(t) => {
+            ChatMsg::DryRunCall(t) => vec![Line::from(vec![
+                Span::raw("  "),
+                Span::styled(
+                    " dry-run ",
+                    Style::default()
+                        .fg(Color::Black)
+                        .bg(Color::Magenta)
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(" "),
+                Span::styled(t.clone(), Style::default().fg(Color::Magenta)),
+            ])],
+            ChatMsg::ToolResult(t, path) => {
+                let dim = Style::default().fg(Color::DarkGray);
+                let mut highlighter = path
+                    .as_deref()
+                    .filter(|_| opts.highlight)
+                    .and_then(CodeHighlighter::for_path);
                 let mut lines: Vec<Line> = t
                     .lines()
-                    .take(40)
+                    .take(TOOL_RESULT_LINES)
                     .map(|l| {
-                        Line::from(Span::styled(
-                            format!("    {l}"),
-                            Style::default().fg(Color::DarkGray),
-                        ))
+                        let spans = highlighter
+                            .as_mut()
+                            .and_then(|h| h.line(l, Style::default()));
+                        match spans {
+                            Some(mut spans) => {
+                                spans.insert(0, Span::raw("    "));
+                                Line::from(spans)
+                            }
+                            None => Line::from(Span::styled(format!("    {l}"), dim)),
+                        }
                     })
                     .collect();
+                let hidden = t.lines().count().saturating_sub(TOOL_RESULT_LINES);
+                if hidden > 0 {
+                    lines.push(Line::from(Span::styled(
+                        format!("    … {hidden} more lines (click to expand)"),
+                        dim.add_modifier(Modifier::ITALIC),
+                    )));
+                }
                 lines.push(Line::raw(""));
                 lines
             }
@@ -102,6 +297,17 @@ impl ChatMsg {
                 )),
                 Line::raw(""),
             ],
+            ChatMsg::Warning(t) => vec![
+                Line::from(vec![
+                    Span::styled(
+                        " warning ",
+                        Style::default().fg(Color::Black).bg(Color::Yellow),
+                    ),
+                    Span::raw("  "),
+                    Span::styled(t.clone(), Style::default().fg(Color::Yellow)),
+                ]),
+                Line::raw(""),
+            ],
             ChatMsg::Error(t) => vec![
                 Line::from(vec![
                     Span::styled(" error ", Style::default().fg(Color::White).bg(Color::Red)),
@@ -118,26 +324,50 @@ impl ChatMsg {
 
 pub(super) enum DisplayEvent {
     Token(String),
+    /// Reasoning text, kept apart from the answer.
+    Thinking(String),
     /// Sent by `ask_user` tool; TUI renders a choice popup and blocks the agent.
     UserInput(UserInputRequest),
     ToolCallStart(ToolCall),
-    ToolResultEnd(String),
+    /// Tool output and, for `read`, the path of the file it came from.
+    ToolResultEnd {
+        content: String,
+        path: Option<String>,
+    },
     TurnUsage(TokenUsage),
     Done {
         messages: Vec<Message>,
         session_id: Option<String>,
+        /// The turn stopped at `max_turns`; the last reply is partial.
+        hit_max_turns: bool,
     },
     Error {
         message: String,
         session_id: Option<String>,
     },
     Status(String),
+    /// A tool call or start/finish line from a delegated sub-agent.
+    SubAgentProgress(krabs_core::SubAgentProgress),
+    /// The model stream broke mid-reply. Unless `resumable`, the `partial`
+    /// text already shown is superseded by the retry.
+    Interrupted {
+        partial: String,
+        resumable: bool,
+    },
 }
 
 /// Active permission prompt waiting for a user keypress.
 pub(super) struct PendingPermission {
     pub(super) tool_name: String,
     pub(super) args: String,
+    /// For file-changing tools, the diff the call would make, shown in
+    /// place of `args`.
+    pub(super) diff: Option<Vec<String>>,
+    /// First diff line shown in the popup.
+    pub(super) scroll: usize,
+    /// A `never_auto_approve` rule matched: ask even if the tool is
+    /// always allowed.
+    pub(super) always_ask: bool,
     pub(super) respond: oneshot::Sender<bool>,
 }
 
@@ -185,8 +415,14 @@ pub(super) struct InfoBar {
     pub(super) provider: String,
     pub(super) model: String,
     pub(super) cwd: String,
+    /// Where the file tools resolve relative paths.
+    pub(super) root: String,
     pub(super) tools: String,
     pub(super) session_id: Option<String>,
+    /// Tool calls are described instead of executed; badged in the info box.
+    pub(super) dry_run: bool,
+    /// `auto_approve_all` is on; bannered in red in the info box.
+    pub(super) approve_all: bool,
 }
 
 pub(super) fn estimate_tokens(s: &str) -> u32 {
@@ -0,0 +1,363 @@
+//! `krabs run "<prompt>"` — run one task to completion without the TUI.
+//!
+//! Progress (streamed text, tool calls, status notices) goes to stderr and the
+//! final answer to stdout, so the output can be piped. A failed run returns an
+//! error, which exits nonzero.
+//!
+//! The prompt can also come from `--file <path>` or stdin (`-`, `--stdin`, or
+//! anything piped in); with a prompt on the command line those are appended
+//! to it as context, so `git diff | krabs run "review this"` works.
+
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use krabs_core::{
+    CancellationToken, Credentials, JsonlObserver, KrabsAgentBuilder, KrabsConfig, LlmProvider,
+    Message, Role, StreamChunk, ToolRegistry,
+};
+
+use crate::chat::{apply_config, build_registry};
+use crate::logging::LogArgs;
+
+/// What `krabs run` prints on stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The final answer only.
+    Text,
+    /// `{ "result", "session_id", "messages" }` with the full transcript.
+    Json,
+    /// One JSON event per line as the run goes (`token`, `tool_call`,
+    /// `tool_result`, `usage`, `done`); see `krabs_core::JsonlObserver`.
+    Jsonl,
+}
+
+/// Options of `krabs run`, parsed from the arguments after `run`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RunArgs {
+    /// Prompt words from the command line, joined; may be empty.
+    pub prompt: String,
+    /// Read stdin even when it is a terminal (`-` or `--stdin`).
+    pub stdin: bool,
+    pub file: Option<PathBuf>,
+    pub output: OutputFormat,
+    pub no_tools: bool,
+    pub max_turns: Option<usize>,
+    pub dry_run: bool,
+    pub no_persist: bool,
+}
+
+pub const USAGE: &str = "usage: krabs run [\"<prompt>\" | -] [--stdin] [--file PATH] \
+[--output text|json|jsonl] [--no-tools] [--max-turns N] [--dry-run] [--no-persist] [--profile NAME] \
+[-q|-v|-vv] [--log-file PATH]";
+
+impl RunArgs {
+    /// Parse `args`, the command line after `run`. Words that aren't flags
+    /// are joined into the prompt.
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut words: Vec<&str> = Vec::new();
+        let mut output = OutputFormat::Text;
+        let mut no_tools = false;
+        let mut max_turns = None;
+        let mut dry_run = false;
+        let mut no_persist = false;
+        let mut stdin = false;
+        let mut file = None;
+
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            match arg.as_str() {
+                "--output" => {
+                    output = match it.next().map(String::as_str) {
+                        Some("text") => OutputFormat::Text,
+                        Some("json") => OutputFormat::Json,
+                        Some("jsonl") => OutputFormat::Jsonl,
+                        other => {
+                            bail!("--output expects text, json or jsonl, got {other:?}\n{USAGE}")
+                        }
+                    }
+                }
+                "--max-turns" => {
+                    let n = it.next().context(USAGE)?;
+                    match n.parse::<usize>() {
+                        Ok(n) if n > 0 => max_turns = Some(n),
+                        _ => bail!("--max-turns expects a positive number, got {n:?}"),
+                    }
+                }
+                "--file" => file = Some(PathBuf::from(it.next().context(USAGE)?)),
+                "-" | "--stdin" => stdin = true,
+                "--no-tools" => no_tools = true,
+                "--dry-run" => dry_run = true,
+                "--no-persist" => no_persist = true,
+                // Handled by `main` before the subcommand runs.
+                "--profile" | "--log-file" => {
+                    it.next();
+                }
+                flag if LogArgs::is_flag(flag) => {}
+                flag if flag.starts_with("--") => bail!("unknown option {flag}\n{USAGE}"),
+                word => words.push(word),
+            }
+        }
+
+        Ok(Self {
+            prompt: words.join(" "),
+            stdin,
+            file,
+            output,
+            no_tools,
+            max_turns,
+            dry_run,
+            no_persist,
+        })
+    }
+}
+
+/// Combine the command-line prompt with the `--file` and stdin contents.
+///
+/// The first non-empty source is the prompt; each later one is appended to it
+/// under a `--- <source> ---` header.
+fn build_prompt(
+    prompt: &str,
+    file: Option<(&Path, String)>,
+    stdin: Option<String>,
+) -> Result<String> {
+    let mut out = prompt.trim().to_string();
+    let file = file.map(|(path, text)| (path.display().to_string(), text));
+    let extra = file
+        .into_iter()
+        .chain(stdin.map(|s| ("stdin".to_string(), s)));
+    for (label, text) in extra {
+        let text = text.trim_end();
+        if text.trim().is_empty() {
+            continue;
+        }
+        if out.is_empty() {
+            out = text.to_string();
+        } else {
+            out = format!("{out}\n\n--- {label} ---\n{text}");
+        }
+    }
+    if out.is_empty() {
+        bail!("missing prompt\n{USAGE}");
+    }
+    Ok(out)
+}
+
+/// Read the prompt sources named by `args`. Stdin is read when asked for or
+/// when it is not a terminal (something is piped in).
+async fn read_prompt(args: &RunArgs) -> Result<String> {
+    use tokio::io::AsyncReadExt;
+
+    let file = match &args.file {
+        Some(path) => {
+            let text = tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("could not read {}", path.display()))?;
+            Some((path.as_path(), text))
+        }
+        None => None,
+    };
+    let stdin = if args.stdin || !std::io::stdin().is_terminal() {
+        let mut text = String::new();
+        tokio::io::stdin()
+            .read_to_string(&mut text)
+            .await
+            .context("could not read stdin")?;
+        Some(text)
+    } else {
+        None
+    };
+    build_prompt(&args.prompt, file, stdin)
+}
+
+/// Run the prompt from `args` as a single task and print the result.
+pub async fn run(creds: Credentials, args: RunArgs) -> Result<()> {
+    let prompt = read_prompt(&args).await?;
+    let mut config = KrabsConfig::load().unwrap_or_default();
+    config.dry_run |= args.dry_run;
+    config.persist_sessions &= !args.no_persist;
+    if let Some(n) = args.max_turns {
+        config.max_turns = n;
+    }
+    let mut creds = creds;
+    apply_config(&mut creds, &config);
+    let provider: Arc<dyn LlmProvider> = Arc::from(creds.build_provider());
+
+    // Nobody is there to approve a tool call, so tools run unprompted; use
+    // `--no-tools` or `--dry-run` to keep the agent hands-off.
+    let registry = if args.no_tools {
+        ToolRegistry::new()
+    } else {
+        build_registry(&config)
+    };
+    let mut builder = KrabsAgentBuilder::new(config, provider).registry(registry);
+    if args.output == OutputFormat::Jsonl {
+        builder = builder.observer(Arc::new(JsonlObserver::stdout()));
+    }
+    let agent = builder.build_async().await;
+
+    let messages = vec![Message::user(prompt)];
+    let (mut stream, done_rx) = Arc::clone(&agent)
+        .run_streaming_with_history(messages, None, CancellationToken::new())
+        .await?;
+
+    let mut stderr = std::io::stderr();
+    while let Some(chunk) = stream.recv().await {
+        match chunk {
+            StreamChunk::Delta { text } => {
+                let _ = write!(stderr, "{text}");
+            }
+            StreamChunk::ToolCallReady { call } => {
+                let _ = writeln!(stderr, "\n→ {}({})", call.name, call.args);
+            }
+            StreamChunk::Status { text } => {
+                let _ = writeln!(stderr, "\n{text}");
+            }
+            StreamChunk::Interrupted { .. } => {
+                let _ = writeln!(stderr, "\n[stream interrupted — retrying]");
+            }
+            StreamChunk::Thinking { .. } | StreamChunk::Done { .. } => {}
+        }
+    }
+    let _ = writeln!(stderr);
+
+    let (session_id, messages, hit_max_turns) =
+        done_rx.await.context("agent stopped without a result")??;
+    if hit_max_turns {
+        let _ = writeln!(
+            stderr,
+            "warning: stopped at max_turns before finishing; the answer is partial"
+        );
+    }
+    let result = final_answer(&messages)
+        .context("agent finished without an answer")?
+        .to_string();
+
+    match args.output {
+        OutputFormat::Text => println!("{result}"),
+        // The `done` event already carried the answer.
+        OutputFormat::Jsonl => {}
+        OutputFormat::Json => {
+            let out = serde_json::json!({
+                "result": result,
+                "session_id": session_id,
+                "hit_max_turns": hit_max_turns,
+                "messages": messages,
+            });
+            println!("{}", serde_json::to_string_pretty(&out)?);
+        }
+    }
+    Ok(())
+}
+
+/// The last non-empty assistant reply in `messages`.
+fn final_answer(messages: &[Message]) -> Option<&str> {
+    messages
+        .iter()
+        .rev()
+        .find(|m| matches!(m.role, Role::Assistant) && !m.content.trim().is_empty())
+        .map(|m| m.content.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &[&str]) -> Vec<String> {
+        s.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_prompt_and_flags() {
+        let parsed = RunArgs::parse(&args(&[
+            "fix",
+            "the build",
+            "--output",
+            "json",
+            "--no-tools",
+            "--max-turns",
+            "3",
+            "--profile",
+            "work",
+            "--file",
+            "notes.md",
+        ]))
+        .unwrap();
+        assert_eq!(
+            parsed,
+            RunArgs {
+                prompt: "fix the build".into(),
+                stdin: false,
+                file: Some(PathBuf::from("notes.md")),
+                output: OutputFormat::Json,
+                no_tools: true,
+                max_turns: Some(3),
+                dry_run: false,
+                no_persist: false,
+            }
+        );
+    }
+
+    #[test]
+    fn jsonl_output_is_accepted() {
+        let parsed = RunArgs::parse(&args(&["--output", "jsonl", "hi"])).unwrap();
+        assert_eq!(parsed.output, OutputFormat::Jsonl);
+        assert!(RunArgs::parse(&args(&["--output", "xml"])).is_err());
+    }
+
+    #[test]
+    fn dash_reads_stdin() {
+        let parsed = RunArgs::parse(&args(&["-"])).unwrap();
+        assert!(parsed.stdin);
+        assert!(parsed.prompt.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(RunArgs::parse(&args(&["hi", "--file"])).is_err());
+        assert!(RunArgs::parse(&args(&["hi", "--output", "yaml"])).is_err());
+        assert!(RunArgs::parse(&args(&["hi", "--max-turns", "0"])).is_err());
+        assert!(RunArgs::parse(&args(&["hi", "--loud"])).is_err());
+    }
+
+    #[test]
+    fn logging_flags_are_left_to_main() {
+        let parsed =
+            RunArgs::parse(&args(&["-vv", "hi", "--log-file", "k.log", "--quiet"])).unwrap();
+        assert_eq!(parsed.prompt, "hi");
+    }
+
+    #[test]
+    fn prompt_sources_combine() {
+        assert_eq!(
+            build_prompt("", None, Some("question\n".into())).unwrap(),
+            "question"
+        );
+        assert_eq!(
+            build_prompt("review this", None, Some("diff --git a/x b/x\n".into())).unwrap(),
+            "review this\n\n--- stdin ---\ndiff --git a/x b/x"
+        );
+        assert_eq!(
+            build_prompt(
+                "",
+                Some((Path::new("task.md"), "do it".into())),
+                Some("ctx".into())
+            )
+            .unwrap(),
+            "do it\n\n--- stdin ---\nctx"
+        );
+        // Nothing piped in and no prompt given.
+        assert!(build_prompt(" ", None, Some(String::new())).is_err());
+    }
+
+    #[test]
+    fn final_answer_skips_tool_call_messages() {
+        let messages = vec![
+            Message::user("hi"),
+            Message::assistant("the answer"),
+            Message::assistant_tool_calls(Vec::new()),
+        ];
+        assert_eq!(final_answer(&messages), Some("the answer"));
+    }
+}
@@ -0,0 +1,131 @@
+//! Where the CLI's `tracing` output goes. Logs are always written to a file:
+//! the TUI draws over the whole terminal, and `krabs run` keeps stdout and
+//! stderr for its own output.
+//!
+//! `-q` logs errors only, the default is warnings, `-v` adds info and `-vv`
+//! debug for Krabs itself. Without a flag `RUST_LOG` is honoured. Logs go to
+//! `~/.krabs/logs/krabs.<date>.log`, one file a day, or to `--log-file <path>`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use krabs_core::KrabsConfig;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::EnvFilter;
+
+/// Daily log files kept in `~/.krabs/logs`; older ones are deleted.
+const MAX_LOG_FILES: usize = 7;
+
+/// Logging flags, accepted anywhere on the command line.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LogArgs {
+    /// `-q` = -1, each `-v` = +1; `None` without any of them.
+    verbosity: Option<i8>,
+    file: Option<PathBuf>,
+}
+
+impl LogArgs {
+    /// Pick the logging flags out of `args`, ignoring everything else.
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut parsed = Self::default();
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            let step = match arg.as_str() {
+                "-q" | "--quiet" => -1,
+                "-v" | "--verbose" => 1,
+                "-vv" => 2,
+                "--log-file" => {
+                    let path = it.next().context("--log-file expects a path")?;
+                    parsed.file = Some(PathBuf::from(path));
+                    continue;
+                }
+                _ => continue,
+            };
+            parsed.verbosity = Some(parsed.verbosity.unwrap_or(0).saturating_add(step));
+        }
+        Ok(parsed)
+    }
+
+    /// Is `arg` one of the flags [`parse`](Self::parse) handles? `--log-file`
+    /// also takes the next argument.
+    pub fn is_flag(arg: &str) -> bool {
+        matches!(arg, "-q" | "--quiet" | "-v" | "--verbose" | "-vv")
+    }
+
+    fn filter(&self) -> EnvFilter {
+        let verbosity = match self.verbosity {
+            Some(v) => v,
+            None => match EnvFilter::try_from_default_env() {
+                Ok(filter) => return filter,
+                Err(_) => 0,
+            },
+        };
+        let (others, krabs) = match verbosity {
+            v if v < 0 => ("error", "error"),
+            0 => ("warn", "warn"),
+            1 => ("warn", "info"),
+            _ => ("warn", "debug"),
+        };
+        EnvFilter::new(format!("{others},krabs_core={krabs},krabs_cli={krabs}"))
+    }
+}
+
+/// Start writing logs as `args` ask. Logs are flushed until the returned
+/// guard is dropped, so keep it for the life of the program.
+pub fn init(args: &LogArgs) -> Result<WorkerGuard> {
+    let appender = match &args.file {
+        Some(path) => {
+            let Some(name) = path.file_name() else {
+                bail!("--log-file expects a file, got {}", path.display());
+            };
+            let dir = path
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            RollingFileAppender::builder()
+                .rotation(Rotation::NEVER)
+                .filename_prefix(name.to_string_lossy())
+                .build(dir)?
+        }
+        None => RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix("krabs")
+            .filename_suffix("log")
+            .max_log_files(MAX_LOG_FILES)
+            .build(KrabsConfig::resolve_path("logs"))?,
+    };
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    tracing_subscriber::fmt()
+        .with_env_filter(args.filter())
+        .with_writer(writer)
+        .with_ansi(false)
+        .try_init()
+        .map_err(|e| anyhow::anyhow!("failed to start logging: {e}"))?;
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &[&str]) -> Vec<String> {
+        s.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn flags_are_found_anywhere() {
+        let parsed = LogArgs::parse(&args(&["run", "-vv", "hi", "--log-file", "/tmp/k.log"]));
+        assert_eq!(
+            parsed.unwrap(),
+            LogArgs {
+                verbosity: Some(2),
+                file: Some(PathBuf::from("/tmp/k.log")),
+            }
+        );
+        let quiet = LogArgs::parse(&args(&["-q", "--resume", "abc"])).unwrap();
+        assert_eq!(quiet.verbosity, Some(-1));
+        assert_eq!(LogArgs::parse(&args(&["run"])).unwrap(), LogArgs::default());
+        assert!(LogArgs::parse(&args(&["--log-file"])).is_err());
+    }
+}
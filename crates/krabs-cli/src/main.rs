@@ -1,7 +1,13 @@
 mod chat;
+mod headless;
+mod logging;
+mod portable;
+mod replay;
 mod setup;
 
-use anyhow::Result;
+use std::io::IsTerminal;
+
+use anyhow::{bail, Result};
 use krabs_core::Credentials;
 
 #[tokio::main]
@@ -9,17 +15,64 @@ async fn main() -> Result<()> {
     dotenvy::dotenv().ok();
 
     let args: Vec<String> = std::env::args().collect();
+    // Held until exit so buffered log lines are flushed.
+    let _log_guard = match logging::init(&logging::LogArgs::parse(&args)?) {
+        Ok(guard) => Some(guard),
+        Err(e) => {
+            eprintln!("krabs: logging disabled: {e:#}");
+            None
+        }
+    };
+    // `krabs replay`, `export` and `import` call no model, so need no
+    // credentials.
+    match args.get(1).map(String::as_str) {
+        Some("replay") => return replay::run(replay::ReplayArgs::parse(&args[2..])?).await,
+        Some("export") => {
+            return portable::export(portable::ExportArgs::parse(&args[2..])?).await;
+        }
+        Some("import") => {
+            return portable::import(portable::ImportArgs::parse(&args[2..])?).await;
+        }
+        _ => {}
+    }
+    // `krabs run "<prompt>"` — one-shot, no TUI. Parsed before credentials so
+    // a usage error doesn't need a configured provider.
+    let run_args = match args.get(1).map(String::as_str) {
+        Some("run") => Some(headless::RunArgs::parse(&args[2..])?),
+        _ => None,
+    };
     let resume_id = args
         .windows(2)
         .find(|w| w[0] == "--resume")
         .map(|w| w[1].clone());
+    let profile = args
+        .windows(2)
+        .find(|w| w[0] == "--profile")
+        .map(|w| w[1].clone());
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let no_persist = args.iter().any(|a| a == "--no-persist");
+    let skip_permissions = args.iter().any(|a| a == "--dangerously-skip-permissions");
 
-    let creds = match Credentials::from_env() {
-        Some(c) if c.is_configured() => c,
-        _ => {
-            setup::run_setup()?;
-            unreachable!()
-        }
+    // --profile wins; otherwise env vars, then the default profile in credentials.json.
+    let creds = match profile {
+        Some(name) => Credentials::load_profile(&name)?,
+        None => match Credentials::from_env() {
+            Some(c) if c.is_configured() => c,
+            _ => match Credentials::load()? {
+                Some(c) if c.is_configured() => c,
+                _ => {
+                    setup::run_setup()?;
+                    unreachable!()
+                }
+            },
+        },
     };
-    chat::run(creds, resume_id).await
+    match run_args {
+        Some(run_args) => headless::run(creds, run_args).await,
+        // The TUI needs a terminal on stdin for raw mode and key events.
+        None if !std::io::stdin().is_terminal() => {
+            bail!("stdin is not a terminal — use `krabs run -` to send piped input as a prompt")
+        }
+        None => chat::run(creds, resume_id, dry_run, no_persist, skip_permissions).await,
+    }
 }
@@ -0,0 +1,134 @@
+//! `krabs export <session-id> <file.krabs>` and `krabs import <file.krabs>`
+//! — move a session between machines.
+//!
+//! The file is JSON: the session's metadata, its messages up to the last
+//! checkpoint and its token usage, tagged with a format version. Importing
+//! it writes a checkpoint at the last message, so `krabs --resume <id>`
+//! carries on from there.
+
+use anyhow::{bail, Context, Result};
+use krabs_core::session::export::PortableSession;
+use krabs_core::{KrabsConfig, SessionStore};
+use std::path::PathBuf;
+
+use crate::logging::LogArgs;
+
+pub const EXPORT_USAGE: &str =
+    "usage: krabs export <session-id> <file.krabs> [-q|-v|-vv] [--log-file PATH]";
+pub const IMPORT_USAGE: &str = "usage: krabs import <file.krabs> [-q|-v|-vv] [--log-file PATH]";
+
+/// The positional arguments after `export` / `import`, with logging flags
+/// (handled by `main`) skipped.
+fn positional(args: &[String], usage: &str) -> Result<Vec<String>> {
+    let mut out = Vec::new();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--log-file" => {
+                it.next();
+            }
+            flag if LogArgs::is_flag(flag) => {}
+            flag if flag.starts_with("--") => bail!("unknown option {flag}\n{usage}"),
+            arg => out.push(arg.to_string()),
+        }
+    }
+    Ok(out)
+}
+
+/// Options of `krabs export`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExportArgs {
+    pub session_id: String,
+    pub path: PathBuf,
+}
+
+impl ExportArgs {
+    pub fn parse(args: &[String]) -> Result<Self> {
+        match <[String; 2]>::try_from(positional(args, EXPORT_USAGE)?) {
+            Ok([session_id, path]) => Ok(Self {
+                session_id,
+                path: path.into(),
+            }),
+            Err(_) => bail!("{EXPORT_USAGE}"),
+        }
+    }
+}
+
+/// Options of `krabs import`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImportArgs {
+    pub path: PathBuf,
+}
+
+impl ImportArgs {
+    pub fn parse(args: &[String]) -> Result<Self> {
+        match <[String; 1]>::try_from(positional(args, IMPORT_USAGE)?) {
+            Ok([path]) => Ok(Self { path: path.into() }),
+            Err(_) => bail!("{IMPORT_USAGE}"),
+        }
+    }
+}
+
+pub async fn export(args: ExportArgs) -> Result<()> {
+    let config = KrabsConfig::load().unwrap_or_default();
+    let store = SessionStore::from_config(&config).await?;
+    let session = store.load_session(&args.session_id).await?;
+    let portable = session.to_portable().await?;
+    std::fs::write(&args.path, portable.to_json()?)
+        .with_context(|| format!("writing {}", args.path.display()))?;
+    println!(
+        "exported session {} ({} entries) to {}",
+        portable.id,
+        portable.entries.len(),
+        args.path.display()
+    );
+    Ok(())
+}
+
+pub async fn import(args: ImportArgs) -> Result<()> {
+    let json = std::fs::read_to_string(&args.path)
+        .with_context(|| format!("reading {}", args.path.display()))?;
+    let portable = PortableSession::from_json(&json)
+        .with_context(|| format!("importing {}", args.path.display()))?;
+    let config = KrabsConfig::load().unwrap_or_default();
+    let store = SessionStore::from_config(&config).await?;
+    let session = store.import_session(&portable).await?;
+    if session.id != portable.id {
+        eprintln!(
+            "session {} already exists here; imported as a new session",
+            portable.id
+        );
+    }
+    // The id alone on stdout, for scripts.
+    println!("{}", session.id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &[&str]) -> Vec<String> {
+        s.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_positional_arguments_around_log_flags() {
+        assert_eq!(
+            ExportArgs::parse(&args(&["abc", "-v", "out.krabs"])).unwrap(),
+            ExportArgs {
+                session_id: "abc".into(),
+                path: "out.krabs".into(),
+            }
+        );
+        assert!(ExportArgs::parse(&args(&["abc"])).is_err());
+        assert_eq!(
+            ImportArgs::parse(&args(&["--log-file", "k.log", "in.krabs"])).unwrap(),
+            ImportArgs {
+                path: "in.krabs".into()
+            }
+        );
+        assert!(ImportArgs::parse(&args(&["a.krabs", "b.krabs"])).is_err());
+        assert!(ImportArgs::parse(&args(&["--force", "a.krabs"])).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! `krabs replay <session-id>` — step through a stored session without the
+//! model.
+//!
+//! Each message and tool call is printed in order. Side-effect-free tools
+//! (`read`, `glob`, `grep`, …) are called again with the recorded arguments
+//! and any output that differs from the stored result is shown as a diff;
+//! other tools are listed but never re-run. Divergence exits nonzero.
+
+use anyhow::{bail, Context, Result};
+use krabs_core::{
+    recorded_tool_calls, KrabsConfig, ReplayOutcome, Replayer, Session, SessionStore,
+};
+use similar::TextDiff;
+
+use crate::chat::build_registry;
+use crate::logging::LogArgs;
+
+/// Characters of a message or tool call shown per line.
+const PREVIEW_CHARS: usize = 100;
+/// Diff lines shown per diverged call.
+const MAX_DIFF_LINES: usize = 20;
+
+/// Options of `krabs replay`, parsed from the arguments after `replay`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReplayArgs {
+    pub session_id: String,
+    /// Only list the recorded calls; call nothing again.
+    pub no_exec: bool,
+}
+
+pub const USAGE: &str =
+    "usage: krabs replay <session-id> [--no-exec] [-q|-v|-vv] [--log-file PATH]";
+
+impl ReplayArgs {
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut session_id = None;
+        let mut no_exec = false;
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            match arg.as_str() {
+                "--no-exec" => no_exec = true,
+                // Handled by `main`.
+                "--log-file" => {
+                    it.next();
+                }
+                flag if LogArgs::is_flag(flag) => {}
+                flag if flag.starts_with("--") => bail!("unknown option {flag}\n{USAGE}"),
+                id if session_id.is_none() => session_id = Some(id.to_string()),
+                extra => bail!("unexpected argument {extra:?}\n{USAGE}"),
+            }
+        }
+        Ok(Self {
+            session_id: session_id.context(USAGE)?,
+            no_exec,
+        })
+    }
+}
+
+pub async fn run(args: ReplayArgs) -> Result<()> {
+    let config = KrabsConfig::load().unwrap_or_default();
+    let store = SessionStore::from_config(&config).await?;
+    let session = store.load_session(&args.session_id).await?;
+    let messages = session.messages().await?;
+    let recorded = recorded_tool_calls(&messages)?;
+    let registry = build_registry(&config);
+    let replayer = Replayer::new(&config, &registry);
+
+    println!(
+        "session {}: {} messages, {} tool calls",
+        args.session_id,
+        messages.len(),
+        recorded.len()
+    );
+    let (mut rerun, mut diverged) = (0, 0);
+    let mut calls = recorded.iter();
+    for msg in &messages {
+        match msg.role.as_str() {
+            "tool" => {}
+            "assistant" if msg.tool_args.is_some() => {
+                let n = Session::decode_tool_calls(msg)?.len();
+                for rec in calls.by_ref().take(n) {
+                    println!(
+                        "turn {:<3} → {} {}",
+                        rec.turn,
+                        rec.call.name,
+                        preview(&rec.call.args.to_string())
+                    );
+                    if args.no_exec {
+                        continue;
+                    }
+                    match replayer.replay(rec).await {
+                        ReplayOutcome::Same => {
+                            rerun += 1;
+                            println!("           ✓ same result");
+                        }
+                        ReplayOutcome::Diverged { current } => {
+                            rerun += 1;
+                            diverged += 1;
+                            println!("           ✗ result changed:");
+                            let stored = rec.result.as_deref().unwrap_or("");
+                            print_diff(stored, &current);
+                        }
+                        ReplayOutcome::Skipped(reason) => {
+                            println!("           – not re-run: {reason}");
+                        }
+                    }
+                }
+            }
+            role => println!("turn {:<3} {role}: {}", msg.turn, preview(&msg.content)),
+        }
+    }
+
+    if args.no_exec {
+        return Ok(());
+    }
+    println!("{rerun} tool calls re-run, {diverged} diverged");
+    if diverged > 0 {
+        bail!("{diverged} of {rerun} re-run tool calls returned something else");
+    }
+    Ok(())
+}
+
+/// The first line of `text`, cut to [`PREVIEW_CHARS`].
+fn preview(text: &str) -> String {
+    let line = text.lines().next().unwrap_or("");
+    let mut out: String = line.chars().take(PREVIEW_CHARS).collect();
+    if out.len() < line.len() || text.lines().nth(1).is_some() {
+        out.push('…');
+    }
+    out
+}
+
+/// `stored` → `current` as a short unified diff, indented under the call.
+fn print_diff(stored: &str, current: &str) {
+    let diff = TextDiff::from_lines(stored, current);
+    let text = diff
+        .unified_diff()
+        .context_radius(1)
+        .header("stored", "now")
+        .to_string();
+    let lines: Vec<&str> = text.lines().collect();
+    for line in lines.iter().take(MAX_DIFF_LINES) {
+        println!("             {line}");
+    }
+    if lines.len() > MAX_DIFF_LINES {
+        println!(
+            "             … {} more diff lines",
+            lines.len() - MAX_DIFF_LINES
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &[&str]) -> Vec<String> {
+        s.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_session_id_and_flags() {
+        assert_eq!(
+            ReplayArgs::parse(&args(&["abc", "--no-exec", "-v"])).unwrap(),
+            ReplayArgs {
+                session_id: "abc".into(),
+                no_exec: true,
+            }
+        );
+        assert!(ReplayArgs::parse(&args(&[])).is_err());
+        assert!(ReplayArgs::parse(&args(&["abc", "def"])).is_err());
+        assert!(ReplayArgs::parse(&args(&["abc", "--loud"])).is_err());
+    }
+}
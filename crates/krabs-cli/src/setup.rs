@@ -11,6 +11,7 @@ pub fn run_setup() -> Result<()> {
          KRABS_BASE_URL=https://api.openai.com/v1\n\
          KRABS_MODEL=gpt-4o\n\
          \n\
+         Or add named profiles to ~/.krabs/credentials.json and pick one with --profile <name>.\n\
          See .env.example for the full list of supported variables."
     )
 }
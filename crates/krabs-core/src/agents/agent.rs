@@ -1,29 +1,77 @@
+use crate::agents::compact;
+use crate::agents::metrics::ToolMetrics;
+use crate::agents::minikrabs::{budget_exhausted, charge_budget, TokenBudget, UsageLedger};
+use crate::agents::observer::{AgentObserver, NoopObserver};
+use crate::agents::structured;
 use crate::config::KrabsConfig;
 use crate::hooks::hook::{HookEvent, HookOutput, ToolUseDecision};
 use crate::hooks::langfuse::LangfuseHookBuilder;
 use crate::hooks::registry::HookRegistry;
 use crate::hooks::telemetry::{TelemetryHook, TelemetryHookBuilder};
+use crate::hooks::{ConfigHook, HookConfig};
+use crate::mcp::cache::McpToolCache;
 use crate::mcp::mcp::McpRegistry;
-use crate::memory::MemoryStore;
+use crate::memory::{
+    memory_prompt, session_namespace, MemoryStore, ScopedMemory, SqliteMemoryStore,
+    GLOBAL_NAMESPACE,
+};
 use crate::permissions::PermissionGuard;
+use crate::prompts::PromptSection;
+use crate::providers::error::ProviderError;
 use crate::providers::provider::{
-    LlmProvider, LlmResponse, Message, Role, StreamChunk, TokenUsage, ToolCall,
+    malformed_args_error, LlmProvider, LlmResponse, Message, ResponseFormat, Role, StreamChunk,
+    TokenUsage, ToolCall,
 };
 use crate::router::{RouteDecision, RulesRouter, TaskRouter};
 use crate::sandbox::{SandboxProxy, SandboxedTool};
 use crate::session::session::{ResumeState, Session, SessionStore, SubturnResume};
+use crate::session::snapshot::{self, FileSnapshot};
 use crate::skills::registry::SkillRegistry;
+use crate::tools::memory::{
+    MemoryReadTool, MemorySearchTool, MemoryWriteTool, RecallTool, RememberTool,
+};
 use crate::tools::read_skill::ReadSkillTool;
 use crate::tools::registry::ToolRegistry;
+use crate::tools::root::ProjectRoot;
 use anyhow::Result;
 use async_trait::async_trait;
+use futures_util::future::join_all;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::future::Future;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, warn};
 
+/// Result given to tool calls that were still queued when the turn was cancelled.
+const CANCELLED_TOOL_RESULT: &str = "Cancelled by the user before this tool ran.";
+
+/// How the note a run leaves at `max_turns` ends; see [`is_max_turns_note`].
+const MAX_TURNS_NOTE_END: &str = "Ask to continue to pick up from here.]";
+
+/// Whether `message` is the closing note of a run that stopped at
+/// `max_turns`. It only restates the run's last text, so it can be dropped
+/// when the run is carried on.
+pub(crate) fn is_max_turns_note(message: &Message) -> bool {
+    matches!(message.role, Role::Assistant)
+        && message.tool_calls.is_none()
+        && message.content.ends_with(MAX_TURNS_NOTE_END)
+}
+
+/// The error result for a call whose streamed arguments weren't valid JSON,
+/// which is answered without running the tool.
+fn malformed_call_error(call: &ToolCall) -> Option<String> {
+    let error = malformed_args_error(&call.args)?;
+    Some(format!(
+        "Tool call not run: its arguments were not valid JSON ({error}). \
+         Call {} again with complete JSON arguments.",
+        call.name
+    ))
+}
+
 enum ResumeMode {
     New,
     Resume { session_id: String },
@@ -38,6 +86,19 @@ pub trait Agent: Send + Sync {
 pub struct AgentOutput {
     pub result: String,
     pub tool_calls_made: usize,
+    /// Tokens the agent has used so far, as in [`KrabsAgent::total_tokens`].
+    #[serde(default)]
+    pub input_tokens: u32,
+    #[serde(default)]
+    pub output_tokens: u32,
+    /// The session the run was persisted to, for resuming it; `None` when
+    /// nothing is persisted.
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// The run used up `max_turns` before the model gave a final reply;
+    /// `result` is the last text it wrote, with a note saying so.
+    #[serde(default)]
+    pub hit_max_turns: bool,
 }
 
 pub struct KrabsAgent {
@@ -46,10 +107,14 @@ pub struct KrabsAgent {
     pub provider: Arc<dyn LlmProvider>,
     pub registry: ToolRegistry,
     pub memory: Box<dyn MemoryStore>,
+    /// Memory shared by every session, listed in each turn's system prompt.
+    pub global_memory: Option<Arc<dyn MemoryStore>>,
     pub permissions: PermissionGuard,
     pub system_prompt: String,
     pub skills: Option<Arc<SkillRegistry>>,
     pub hooks: HookRegistry,
+    /// Called from the streaming loop; [`NoopObserver`] unless one was set.
+    pub observer: Arc<dyn AgentObserver>,
     /// Active session for this agent. `None` only when the SQLite store could
     /// not be opened (e.g. read-only filesystem). Every message and token-usage
     /// row is persisted here automatically by the agent loop.
@@ -58,6 +123,19 @@ pub struct KrabsAgent {
     _sandbox_proxy: Option<SandboxProxy>,
     total_input_tokens: std::sync::atomic::AtomicU32,
     total_output_tokens: std::sync::atomic::AtomicU32,
+    /// Shared sub-agent budget, charged after every LLM call. `None` = unlimited.
+    token_budget: Option<TokenBudget>,
+    /// Ledger this sub-agent reports each LLM call's usage to, and its label.
+    usage_report: Option<(UsageLedger, String)>,
+    /// Ledger this agent's sub-agents report to; counted after every tool batch.
+    subagent_usage: Option<UsageLedger>,
+    /// Providers switched to, in order, when LLM calls keep failing past
+    /// `max_retries`, each with the name used in the switch notice.
+    fallbacks: Vec<(String, Arc<dyn LlmProvider>)>,
+    /// 0 while `provider` is in use, `i` once `fallbacks[i - 1]` took over.
+    active_provider: std::sync::atomic::AtomicUsize,
+    /// Calls, failures, retries and latency of each tool this agent ran.
+    tool_metrics: ToolMetrics,
 }
 
 pub struct KrabsAgentBuilder {
@@ -66,13 +144,81 @@ pub struct KrabsAgentBuilder {
     provider: Arc<dyn LlmProvider>,
     registry: ToolRegistry,
     memory: Box<dyn MemoryStore>,
+    global_memory: Option<Arc<dyn MemoryStore>>,
+    memory_store: Option<SqliteMemoryStore>,
     permissions: PermissionGuard,
     system_prompt: String,
     skills: Option<Arc<SkillRegistry>>,
     hooks: HookRegistry,
+    observer: Arc<dyn AgentObserver>,
     mcp_registry: Option<McpRegistry>,
     resume_mode: ResumeMode,
     initial_session_id: Option<String>,
+    token_budget: Option<TokenBudget>,
+    usage_report: Option<(UsageLedger, String)>,
+    subagent_usage: Option<UsageLedger>,
+    fallbacks: Vec<(String, Arc<dyn LlmProvider>)>,
+}
+
+/// Apply `config.max_concurrent_requests` to the process. An unset (0)
+/// limit leaves whatever another agent's config set.
+fn apply_request_limit(config: &KrabsConfig) {
+    if config.max_concurrent_requests > 0 {
+        crate::providers::set_max_concurrent_requests(config.max_concurrent_requests);
+    }
+}
+
+/// Providers for `config.fallback_models`, named by their entry names.
+fn config_fallbacks(config: &KrabsConfig) -> Vec<(String, Arc<dyn LlmProvider>)> {
+    config
+        .fallback_models
+        .iter()
+        .map(|entry| (entry.name.clone(), Arc::from(entry.build_provider(config))))
+        .collect()
+}
+
+/// How many times a request over the context budget has a tool result cut
+/// before it is sent anyway.
+const MAX_SHRINK_PASSES: usize = 4;
+
+/// Tool results are never cut below this many bytes.
+const MIN_TOOL_RESULT_BYTES: usize = 256;
+
+/// Whether `error` is the provider refusing a request as over the model's
+/// context window.
+fn context_overflow(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<ProviderError>(),
+        Some(ProviderError::ContextLengthExceeded(_))
+    )
+}
+
+/// Cut the longest tool result in `messages` by about `excess_tokens` (~4
+/// bytes each). Returns `false` when none is long enough to cut.
+fn shrink_largest_tool_result(messages: &mut [Message], excess_tokens: usize) -> bool {
+    const MARKER: &str = "\n\n[…output truncated to fit context window…]";
+    let Some(msg) = messages
+        .iter_mut()
+        .filter(|m| matches!(m.role, Role::Tool))
+        .max_by_key(|m| m.content.len())
+    else {
+        return false;
+    };
+    if msg.content.len() <= MIN_TOOL_RESULT_BYTES + MARKER.len() {
+        return false;
+    }
+    let cut = excess_tokens.saturating_mul(4) + MARKER.len();
+    let mut keep = msg
+        .content
+        .len()
+        .saturating_sub(cut)
+        .max(MIN_TOOL_RESULT_BYTES);
+    while !msg.content.is_char_boundary(keep) {
+        keep -= 1;
+    }
+    msg.content.truncate(keep);
+    msg.content.push_str(MARKER);
+    true
 }
 
 impl KrabsAgentBuilder {
@@ -83,13 +229,20 @@ impl KrabsAgentBuilder {
             provider: Arc::new(provider),
             registry: ToolRegistry::default(),
             memory: Box::new(crate::memory::memory::InMemoryStore::new()),
+            global_memory: None,
+            memory_store: None,
             permissions: PermissionGuard::new(),
             system_prompt: String::new(),
             skills: None,
             hooks: HookRegistry::default(),
+            observer: Arc::new(NoopObserver),
             mcp_registry: None,
             resume_mode: ResumeMode::New,
             initial_session_id: None,
+            token_budget: None,
+            usage_report: None,
+            subagent_usage: None,
+            fallbacks: Vec::new(),
         }
     }
 
@@ -119,6 +272,22 @@ impl KrabsAgentBuilder {
         self
     }
 
+    /// Memory whose notes are listed in the system prompt each turn.
+    /// `build_async` opens the database's global memory when
+    /// `config.memory_tools` is on and this isn't set.
+    pub fn global_memory(mut self, memory: Arc<dyn MemoryStore>) -> Self {
+        self.global_memory = Some(memory);
+        self
+    }
+
+    /// An open memory database for the `memory_*` tools, so agents built
+    /// one after another share its connections. `build_async` opens
+    /// `config.db_path` when this isn't set.
+    pub fn memory_store(mut self, store: SqliteMemoryStore) -> Self {
+        self.memory_store = Some(store);
+        self
+    }
+
     pub fn permissions(mut self, permissions: PermissionGuard) -> Self {
         self.permissions = permissions;
         self
@@ -141,6 +310,45 @@ impl KrabsAgentBuilder {
         self
     }
 
+    /// Follow streaming runs through callbacks instead of the chunk channel.
+    /// Replaces any observer set before.
+    pub fn observer(mut self, observer: Arc<dyn AgentObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Charge every LLM call against a budget shared with sibling sub-agents.
+    /// The agent stops with an error before its next turn once it is exhausted.
+    pub fn token_budget(mut self, budget: TokenBudget) -> Self {
+        self.token_budget = Some(budget);
+        self
+    }
+
+    /// Report the usage of every LLM call to `ledger` under `label`
+    /// (e.g. `delegate:planner`), for the parent agent to count as its own.
+    pub fn report_usage(mut self, ledger: UsageLedger, label: impl Into<String>) -> Self {
+        self.usage_report = Some((ledger, label.into()));
+        self
+    }
+
+    /// Count usage that sub-agents report to `ledger` in this agent's totals,
+    /// persisted under the sub-agent's label.
+    pub fn subagent_usage(mut self, ledger: UsageLedger) -> Self {
+        self.subagent_usage = Some(ledger);
+        self
+    }
+
+    /// Add a provider to switch to when LLM calls keep failing, tried before
+    /// the ones from `config.fallback_models`.
+    pub fn fallback_provider(
+        mut self,
+        name: impl Into<String>,
+        provider: impl LlmProvider + 'static,
+    ) -> Self {
+        self.fallbacks.push((name.into(), Arc::new(provider)));
+        self
+    }
+
     pub fn with_mcp_registry(mut self, registry: McpRegistry) -> Self {
         self.mcp_registry = Some(registry);
         self
@@ -150,11 +358,12 @@ impl KrabsAgentBuilder {
     ///
     /// This is the preferred builder path — it enables automatic persistence of
     /// every message and token-usage row into the SQLite database at
-    /// `config.db_path`.
+    /// `config.db_path`, unless `config.persist_sessions` is off.
     pub async fn build_async(mut self) -> Arc<KrabsAgent> {
+        apply_request_limit(&self.config);
         if let Some(mcp) = self.mcp_registry.take() {
-            let live = mcp.connect_all().await;
-            for tool in live.tools_for_all().await {
+            let ttl = std::time::Duration::from_secs(self.config.mcp_cache_ttl_secs);
+            for tool in mcp.tools_cached(&McpToolCache::path(), ttl).await {
                 self.registry.register(Arc::from(tool));
             }
         }
@@ -165,18 +374,36 @@ impl KrabsAgentBuilder {
             match SandboxProxy::start(Arc::clone(&sandbox_cfg)).await {
                 Ok(proxy) => {
                     let port = proxy.port();
+                    let format = crate::tools::format::ToolOutputFormat::from_config(&self.config);
+                    self.registry.register(Arc::new(SandboxedTool::wrap(
+                        crate::tools::bash::BashTool::new(
+                            crate::tools::bash::BashPolicy::from_config(&self.config),
+                        )
+                        .with_format(format.clone()),
+                        Arc::clone(&sandbox_cfg),
+                        port,
+                    )));
+                    let root = ProjectRoot::from_config(&self.config);
                     self.registry.register(Arc::new(SandboxedTool::wrap(
-                        crate::tools::bash::BashTool,
+                        crate::tools::read::ReadTool::default()
+                            .with_root(root.clone())
+                            .with_format(format),
                         Arc::clone(&sandbox_cfg),
                         port,
                     )));
                     self.registry.register(Arc::new(SandboxedTool::wrap(
-                        crate::tools::read::ReadTool,
+                        crate::tools::write::WriteTool::new(self.config.backup_suffix())
+                            .with_root(root.clone()),
                         Arc::clone(&sandbox_cfg),
                         port,
                     )));
                     self.registry.register(Arc::new(SandboxedTool::wrap(
-                        crate::tools::write::WriteTool,
+                        crate::tools::patch::ApplyPatchTool::default().with_root(root),
+                        Arc::clone(&sandbox_cfg),
+                        port,
+                    )));
+                    self.registry.register(Arc::new(SandboxedTool::wrap(
+                        crate::tools::web_fetch::WebFetchTool,
                         Arc::clone(&sandbox_cfg),
                         port,
                     )));
@@ -191,9 +418,32 @@ impl KrabsAgentBuilder {
             None
         };
 
+        // Hooks from ~/.krabs/hooks.json go ahead of the builder's, so a
+        // configured deny wins before an interactive hook asks the user.
+        let sandbox = sandbox_proxy
+            .as_ref()
+            .map(|proxy| (Arc::new(self.config.sandbox.clone()), proxy.port()));
+        self.hooks
+            .register_first(HookConfig::load().hooks.into_iter().map(|entry| {
+                let hook = match &sandbox {
+                    Some((cfg, port)) => {
+                        ConfigHook::new(entry).with_sandbox(Arc::clone(cfg), *port)
+                    }
+                    None => ConfigHook::new(entry),
+                };
+                Arc::new(hook) as Arc<dyn crate::hooks::hook::Hook>
+            }));
+
         let provider_name = crate::providers::provider_name_from_url(&self.config.base_url);
-        let session = match SessionStore::open(&self.config.db_path).await {
-            Ok(store) => {
+        let store = if self.config.persist_sessions {
+            Some(SessionStore::from_config(&self.config).await)
+        } else {
+            debug!("Session persistence disabled");
+            None
+        };
+        let session = match store {
+            None => None,
+            Some(Ok(store)) => {
                 let result = match &self.resume_mode {
                     ResumeMode::New => {
                         store
@@ -224,7 +474,7 @@ impl KrabsAgentBuilder {
                     }
                 }
             }
-            Err(e) => {
+            Some(Err(e)) => {
                 warn!(
                     "Failed to open session store at {:?}: {e}",
                     self.config.db_path
@@ -233,6 +483,44 @@ impl KrabsAgentBuilder {
             }
         };
 
+        // Session and global memory for the `memory_*` tools, next to the
+        // session in the same database.
+        if self.config.memory_tools {
+            if let Some(s) = &session {
+                let store = match self.memory_store.take() {
+                    Some(store) => Ok(store),
+                    None => {
+                        SqliteMemoryStore::open_with(
+                            &self.config.db_path,
+                            GLOBAL_NAMESPACE,
+                            &self.config.sqlite,
+                        )
+                        .await
+                    }
+                };
+                match store {
+                    Ok(store) => {
+                        let session_memory = store.with_namespace(session_namespace(&s.id));
+                        let global: Arc<dyn MemoryStore> =
+                            Arc::new(store.with_namespace(GLOBAL_NAMESPACE));
+                        let memory =
+                            ScopedMemory::new(Arc::new(session_memory), Arc::clone(&global));
+                        self.registry
+                            .register(Arc::new(MemoryWriteTool::new(memory.clone())));
+                        self.registry
+                            .register(Arc::new(MemoryReadTool::new(memory.clone())));
+                        self.registry
+                            .register(Arc::new(MemorySearchTool::new(memory.clone())));
+                        self.registry
+                            .register(Arc::new(RememberTool::new(memory.clone())));
+                        self.registry.register(Arc::new(RecallTool::new(memory)));
+                        self.global_memory.get_or_insert(global);
+                    }
+                    Err(e) => warn!("Failed to open memory store: {e}"),
+                }
+            }
+        }
+
         // Auto-register telemetry hook if enabled in config
         if self.config.telemetry.enabled {
             let session_id = session.as_ref().map(|s| s.id.clone());
@@ -255,6 +543,9 @@ impl KrabsAgentBuilder {
             if let Some(path) = jsonl_path {
                 builder = builder.jsonl_path(path);
             }
+            if let Some(url) = &self.config.telemetry.otlp_endpoint {
+                builder = builder.otlp_endpoint(url);
+            }
 
             self.hooks.register(Arc::new(builder.build()));
         }
@@ -275,40 +566,61 @@ impl KrabsAgentBuilder {
             self.hooks.register(Arc::new(builder.build()));
         }
 
+        let mut fallbacks = self.fallbacks;
+        fallbacks.extend(config_fallbacks(&self.config));
         Arc::new(KrabsAgent {
             agent_id: self.agent_id,
             config: self.config,
             provider: self.provider,
             registry: self.registry,
             memory: self.memory,
+            global_memory: self.global_memory,
             permissions: self.permissions,
             system_prompt: self.system_prompt,
             skills: self.skills,
             hooks: self.hooks,
+            observer: self.observer,
             session,
             _sandbox_proxy: sandbox_proxy,
             total_input_tokens: std::sync::atomic::AtomicU32::new(0),
             total_output_tokens: std::sync::atomic::AtomicU32::new(0),
+            token_budget: self.token_budget,
+            usage_report: self.usage_report,
+            subagent_usage: self.subagent_usage,
+            fallbacks,
+            active_provider: std::sync::atomic::AtomicUsize::new(0),
+            tool_metrics: ToolMetrics::default(),
         })
     }
 
     /// Sync build — no MCP, no session persistence.
     /// Prefer [`build_async`](Self::build_async) for production use.
     pub fn build(self) -> Arc<KrabsAgent> {
+        apply_request_limit(&self.config);
+        let mut fallbacks = self.fallbacks;
+        fallbacks.extend(config_fallbacks(&self.config));
         Arc::new(KrabsAgent {
             agent_id: self.agent_id,
             config: self.config,
             provider: self.provider,
             registry: self.registry,
             memory: self.memory,
+            global_memory: self.global_memory,
             permissions: self.permissions,
             system_prompt: self.system_prompt,
             skills: self.skills,
             hooks: self.hooks,
+            observer: self.observer,
             session: None,
             _sandbox_proxy: None,
             total_input_tokens: std::sync::atomic::AtomicU32::new(0),
             total_output_tokens: std::sync::atomic::AtomicU32::new(0),
+            token_budget: self.token_budget,
+            usage_report: self.usage_report,
+            subagent_usage: self.subagent_usage,
+            fallbacks,
+            active_provider: std::sync::atomic::AtomicUsize::new(0),
+            tool_metrics: ToolMetrics::default(),
         })
     }
 }
@@ -322,48 +634,91 @@ impl KrabsAgent {
         permissions: PermissionGuard,
         system_prompt: String,
     ) -> Self {
+        apply_request_limit(&config);
         Self {
             agent_id: uuid::Uuid::new_v4().to_string(),
+            fallbacks: config_fallbacks(&config),
             config,
             provider: Arc::new(provider),
             registry,
             memory: Box::new(memory),
+            global_memory: None,
             permissions,
             system_prompt,
             skills: None,
             hooks: HookRegistry::default(),
+            observer: Arc::new(NoopObserver),
             session: None,
             _sandbox_proxy: None,
             total_input_tokens: std::sync::atomic::AtomicU32::new(0),
             total_output_tokens: std::sync::atomic::AtomicU32::new(0),
+            token_budget: None,
+            usage_report: None,
+            subagent_usage: None,
+            active_provider: std::sync::atomic::AtomicUsize::new(0),
+            tool_metrics: ToolMetrics::default(),
         }
     }
 
-    /// Sync skills from disk then return the full system prompt for this turn.
+    /// Sync skills and the project prompt file from disk then return the full
+    /// system prompt for this turn.
     ///
     /// The immutable base (SOUL + SYSTEM_PROMPT) is always prepended and cannot
-    /// be overridden by any caller-supplied system prompt.
+    /// be overridden by any caller-supplied system prompt. The project prompt
+    /// (`system_prompt_path`) follows the caller's prompt, or replaces it when
+    /// `system_prompt_replace` is set.
     async fn current_system_prompt(&self) -> String {
-        let base = crate::prompts::base_system_prompt();
+        let sections = self.system_prompt_sections().await;
+        sections
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
 
-        let extension = match &self.skills {
-            None => self.system_prompt.clone(),
+    /// The non-empty parts of the system prompt, in the order they are sent:
+    /// `soul`, `instructions`, `agent prompt`, `project prompt`, `skills`,
+    /// `memory`. Syncs skills, re-reads the project prompt file and lists the
+    /// global memory like a turn does.
+    pub async fn system_prompt_sections(&self) -> Vec<PromptSection> {
+        use crate::prompts::system::{SOUL, SYSTEM_PROMPT_BASE};
+
+        let project = crate::prompts::load_project_prompt(&self.config.system_prompt_path).await;
+        let caller = if project.is_some() && self.config.system_prompt_replace {
+            String::new()
+        } else {
+            self.system_prompt.clone()
+        };
+        let skills = match &self.skills {
+            None => String::new(),
             Some(registry) => {
                 registry.sync().await;
-                let section = registry.metadata_prompt().await;
-                if section.is_empty() {
-                    self.system_prompt.clone()
-                } else {
-                    format!("{}\n\n{}", self.system_prompt, section)
-                }
+                registry.metadata_prompt().await
             }
         };
 
-        if extension.is_empty() {
-            base
-        } else {
-            format!("{}\n\n{}", base, extension)
-        }
+        let memory = match &self.global_memory {
+            None => None,
+            Some(global) => memory_prompt(global.as_ref(), self.config.memory_prompt_tokens)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Failed to read global memory: {e}");
+                    None
+                }),
+        };
+
+        [
+            ("soul", SOUL.to_string()),
+            ("instructions", SYSTEM_PROMPT_BASE.to_string()),
+            ("agent prompt", caller),
+            ("project prompt", project.unwrap_or_default()),
+            ("skills", skills),
+            ("memory", memory.unwrap_or_default()),
+        ]
+        .into_iter()
+        .filter(|(_, text)| !text.is_empty())
+        .map(|(name, text)| PromptSection { name, text })
+        .collect()
     }
 
     // -----------------------------------------------------------------------
@@ -401,7 +756,7 @@ impl KrabsAgent {
              Reply with exactly one word: reactive, planned, or explore."
         );
         let msgs = vec![Message::user(&prompt)];
-        match self.provider.complete(&msgs, &[]).await {
+        match self.active_provider().complete(&msgs, &[]).await {
             Ok(crate::providers::provider::LlmResponse::Message { content, .. }) => {
                 let word = content.trim().to_lowercase();
                 if word.contains("planned") {
@@ -458,12 +813,15 @@ impl KrabsAgent {
                 return Ok(ResumeState {
                     messages: Vec::new(),
                     subturn_resume: None,
+                    partial: None,
                 })
             }
         };
 
-        let (stored, subturn_resume) = match session.latest_checkpoint().await? {
+        let (stored, subturn_resume, partial) = match session.latest_checkpoint().await? {
             Some(cp) => {
+                // Read an interrupted reply before the rollback discards it.
+                let partial = session.latest_partial(cp.last_msg_id).await?;
                 session.rollback_to(cp.last_msg_id).await?;
                 let msgs = session.messages_up_to(cp.last_msg_id).await?;
                 let subturn = cp.subturn_tool_idx.map(|idx| SubturnResume {
@@ -471,25 +829,90 @@ impl KrabsAgent {
                     completed_tool_count: idx + 1,
                     last_call_id: cp.subturn_call_id.unwrap_or_default(),
                 });
-                (msgs, subturn)
+                (msgs, subturn, partial)
             }
-            None => (session.messages().await?, None),
+            None => (
+                session.messages().await?,
+                None,
+                session.latest_partial(0).await?,
+            ),
         };
 
         let messages = stored
             .iter()
+            .filter(|s| s.role != "partial")
             .map(Session::stored_to_message)
             .collect::<Result<Vec<_>>>()?;
 
         Ok(ResumeState {
             messages,
             subturn_resume,
+            partial,
         })
     }
 
+    /// The provider LLM calls go to: `provider` until it has failed past
+    /// retries, then the fallback that replaced it.
+    fn active_provider(&self) -> &Arc<dyn LlmProvider> {
+        match self
+            .active_provider
+            .load(std::sync::atomic::Ordering::SeqCst)
+        {
+            0 => &self.provider,
+            i => self
+                .fallbacks
+                .get(i - 1)
+                .map(|(_, p)| p)
+                .unwrap_or(&self.provider),
+        }
+    }
+
+    /// Move on to the next fallback provider for the rest of this run.
+    /// Returns the notice announcing the switch, or `None` when no fallback
+    /// is left.
+    fn switch_to_fallback(&self, error: &anyhow::Error) -> Option<String> {
+        let current = self
+            .active_provider
+            .load(std::sync::atomic::Ordering::SeqCst);
+        let (name, _) = self.fallbacks.get(current)?;
+        // A concurrent call may already have switched; either way the caller
+        // retries on a provider that hasn't failed it yet.
+        let _ = self.active_provider.compare_exchange(
+            current,
+            current + 1,
+            std::sync::atomic::Ordering::SeqCst,
+            std::sync::atomic::Ordering::SeqCst,
+        );
+        let msg =
+            format!("⇄ model failed after retries ({error}) — switching to fallback '{name}'");
+        warn!("{msg}");
+        Some(msg)
+    }
+
+    /// How long to wait before retry `attempt` (counting from 0) after `error`,
+    /// or `None` when it isn't worth retrying on this provider: attempts are
+    /// used up, or the error is a [`ProviderError`] that the same request
+    /// would hit again (bad credentials, a refused request). A rate limit
+    /// waits at least as long as the server asked. Errors of other types are
+    /// retried.
+    fn retry_delay(&self, error: &anyhow::Error, attempt: usize) -> Option<u64> {
+        if attempt >= self.config.max_retries {
+            return None;
+        }
+        let backoff = self.config.retry_base_delay_ms * 2u64.pow(attempt as u32);
+        match error.downcast_ref::<ProviderError>() {
+            Some(e) if !e.is_retryable() => None,
+            Some(ProviderError::RateLimited {
+                retry_after: Some(wait),
+            }) => Some(backoff.max(wait.as_millis() as u64)),
+            _ => Some(backoff),
+        }
+    }
+
     /// Retry an async operation with exponential backoff, persisting each
-    /// failure. Returns `Ok` on the first success, or `Err` after exhausting
-    /// all attempts.
+    /// failure. Failures [`retry_delay`](Self::retry_delay) rules out go
+    /// straight to the next fallback. Returns `Ok` on the first success, or `Err` after exhausting
+    /// all attempts on the primary provider and every fallback.
     async fn call_with_retry<F, Fut, T>(
         &self,
         turn: usize,
@@ -502,15 +925,14 @@ impl KrabsAgent {
         Fut: Future<Output = Result<T>>,
     {
         let max = self.config.max_retries;
-        let base_ms = self.config.retry_base_delay_ms;
 
-        for attempt in 0..=max {
+        let mut attempt = 0;
+        loop {
             match f().await {
                 Ok(v) => return Ok(v),
                 Err(e) => {
                     self.persist_error(turn, context, &e, attempt).await;
-                    if attempt < max {
-                        let delay = base_ms * 2u64.pow(attempt as u32);
+                    if let Some(delay) = self.retry_delay(&e, attempt) {
                         let msg = format!(
                             "↻ LLM attempt {}/{} failed: {e} — retrying in {delay}ms…",
                             attempt + 1,
@@ -521,19 +943,30 @@ impl KrabsAgent {
                             let _ = tx.send(StreamChunk::Status { text: msg }).await;
                         }
                         tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
+                        attempt += 1;
+                    } else if context_overflow(&e) {
+                        // The caller trims the history and asks again.
+                        return Err(e);
+                    } else if let Some(msg) = self.switch_to_fallback(&e) {
+                        // `f` reads `active_provider`, so the next call goes
+                        // to the fallback.
+                        if let Some(tx) = status_tx {
+                            let _ = tx.send(StreamChunk::Status { text: msg }).await;
+                        }
+                        attempt = 0;
                     } else {
                         return Err(e);
                     }
                 }
             }
         }
-        unreachable!()
     }
 
     /// Call a tool with exponential-backoff retry on both hard errors (Err)
     /// and soft errors (ToolResult { is_error: true }).
     /// After exhausting retries, returns the final ToolResult for the LLM to handle.
     /// If `status_tx` is provided, a `StreamChunk::Status` is emitted on each retry.
+    /// Every call except a dry run is counted in the tool's stats.
     async fn call_tool_with_retry(
         &self,
         turn: usize,
@@ -542,12 +975,47 @@ impl KrabsAgent {
         args: serde_json::Value,
         status_tx: Option<&mpsc::Sender<StreamChunk>>,
     ) -> crate::tools::tool::ToolResult {
+        let started = std::time::Instant::now();
+        // Malformed arguments won't improve on retry; hand the problems
+        // straight back to the model.
+        if let Err(e) = crate::tools::tool::validate_args(tool.as_ref(), &args) {
+            self.record_tool_call(tool_name, true, 0, started.elapsed())
+                .await;
+            return crate::tools::tool::ToolResult::err(e.to_string());
+        }
+        if self.config.dry_run {
+            return crate::tools::tool::ToolResult::ok(format!(
+                "[dry-run] would call {tool_name} with {args}"
+            ));
+        }
+
+        let (result, retries) = self
+            .call_tool_attempts(turn, tool_name, tool, args, status_tx)
+            .await;
+        self.record_tool_call(tool_name, result.is_error, retries, started.elapsed())
+            .await;
+        result
+    }
+
+    /// The attempts of [`call_tool_with_retry`](Self::call_tool_with_retry):
+    /// the final result and how many retries it took.
+    async fn call_tool_attempts(
+        &self,
+        turn: usize,
+        tool_name: &str,
+        tool: Arc<dyn crate::tools::tool::Tool>,
+        args: serde_json::Value,
+        status_tx: Option<&mpsc::Sender<StreamChunk>>,
+    ) -> (crate::tools::tool::ToolResult, usize) {
         let max = self.config.tool_max_retries;
         let base_ms = self.config.retry_base_delay_ms;
+        let timeout_ms = tool.timeout_ms(self.config.tool_timeout_ms);
 
         for attempt in 0..=max {
-            match tool.call(args.clone()).await {
-                Ok(result) if !result.is_error => return result,
+            match crate::tools::tool::call_with_timeout(tool.as_ref(), args.clone(), timeout_ms)
+                .await
+            {
+                Ok(result) if !result.is_error => return (result, attempt),
                 Ok(result) => {
                     if attempt < max {
                         let delay = base_ms * 2u64.pow(attempt as u32);
@@ -563,7 +1031,7 @@ impl KrabsAgent {
                         }
                         tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
                     } else {
-                        return result;
+                        return (result, attempt);
                     }
                 }
                 Err(e) => {
@@ -582,7 +1050,7 @@ impl KrabsAgent {
                         }
                         tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
                     } else {
-                        return crate::tools::tool::ToolResult::err(e.to_string());
+                        return (crate::tools::tool::ToolResult::err(e.to_string()), attempt);
                     }
                 }
             }
@@ -590,6 +1058,62 @@ impl KrabsAgent {
         unreachable!()
     }
 
+    /// End a run that used up `max_turns` without a final reply: append an
+    /// assistant message with the model's last text and a note that it was
+    /// cut short, so the work so far isn't lost and the conversation can be
+    /// continued. Fires `AgentStop` and returns the message's text.
+    async fn finish_at_max_turns(&self, messages: &mut Vec<Message>) -> String {
+        let max = self.config.max_turns;
+        let turn = max.saturating_sub(1);
+        let e = anyhow::anyhow!("Max turns ({max}) exceeded");
+        self.persist_error(max, "max_turns", &e, 0).await;
+        warn!("Max turns ({max}) reached, stopping with a partial result");
+
+        // Everything since the task came in.
+        let this_run: Vec<&Message> = messages
+            .iter()
+            .rev()
+            .take_while(|m| !matches!(m.role, Role::User))
+            .collect();
+        let mut tools: Vec<&str> = Vec::new();
+        let mut calls = 0;
+        for call in this_run
+            .iter()
+            .rev()
+            .filter_map(|m| m.tool_calls.as_ref())
+            .flatten()
+        {
+            calls += 1;
+            if !tools.contains(&call.name.as_str()) {
+                tools.push(&call.name);
+            }
+        }
+        let progress = match calls {
+            0 => String::new(),
+            n => format!(" after {n} tool call(s) ({})", tools.join(", ")),
+        };
+        let note = format!(
+            "[Stopped at the max_turns limit ({max}){progress} before finishing. {MAX_TURNS_NOTE_END}"
+        );
+        let last = this_run
+            .iter()
+            .find(|m| matches!(m.role, Role::Assistant) && !m.content.trim().is_empty());
+        let content = match last {
+            Some(m) => format!("{}\n\n{note}", m.content.trim_end()),
+            None => note,
+        };
+        let final_msg = Message::assistant(&content);
+        self.persist_message(&final_msg, turn).await;
+        messages.push(final_msg);
+        self.write_checkpoint(turn).await;
+        self.hooks
+            .fire(&HookEvent::AgentStop {
+                result: content.clone(),
+            })
+            .await;
+        content
+    }
+
     async fn write_checkpoint(&self, turn: usize) {
         if let Some(s) = &self.session {
             if let Err(e) = s.write_checkpoint(turn).await {
@@ -606,6 +1130,18 @@ impl KrabsAgent {
         }
     }
 
+    /// Fire `UserMessage` for `content`. Returns the message as the hooks
+    /// rewrote it, or `None` when they left it alone.
+    async fn rewrite_user_message(&self, content: &str) -> Option<String> {
+        let event = HookEvent::UserMessage {
+            content: content.to_string(),
+        };
+        match self.hooks.fire(&event).await {
+            HookOutput::ReplaceUserMessage(content) => Some(content),
+            _ => None,
+        }
+    }
+
     /// Fire-and-log helper so persist errors never abort the agent loop.
     async fn persist_message(&self, msg: &Message, turn: usize) {
         if let Some(s) = &self.session {
@@ -615,6 +1151,22 @@ impl KrabsAgent {
         }
     }
 
+    async fn persist_partial(&self, turn: usize, content: &str) {
+        if let Some(s) = &self.session {
+            if let Err(e) = s.persist_partial(content, turn).await {
+                warn!("Failed to persist partial reply: {e}");
+            }
+        }
+    }
+
+    async fn clear_partials(&self) {
+        if let Some(s) = &self.session {
+            if let Err(e) = s.clear_partials().await {
+                warn!("Failed to clear partial reply: {e}");
+            }
+        }
+    }
+
     async fn persist_token_usage(&self, turn: usize, input: u32, output: u32) {
         if let Some(s) = &self.session {
             if let Err(e) = s.persist_token_usage(turn, input, output).await {
@@ -637,6 +1189,29 @@ impl KrabsAgent {
         }
     }
 
+    /// Count a finished tool call in the agent's and the session's stats.
+    async fn record_tool_call(
+        &self,
+        tool_name: &str,
+        is_error: bool,
+        retries: usize,
+        elapsed: std::time::Duration,
+    ) {
+        self.tool_metrics
+            .record(tool_name, is_error, retries, elapsed);
+        if let Some(s) = &self.session {
+            let ms = elapsed.as_millis() as u64;
+            if let Err(e) = s.record_tool_call(tool_name, is_error, retries, ms).await {
+                warn!("Failed to persist tool stats: {e}");
+            }
+        }
+    }
+
+    /// Per-tool stats of every call this agent has run, most-called first.
+    pub fn tool_stats(&self) -> Vec<crate::agents::metrics::ToolStats> {
+        self.tool_metrics.snapshot()
+    }
+
     pub fn total_tokens(&self) -> (u32, u32) {
         (
             self.total_input_tokens
@@ -646,38 +1221,278 @@ impl KrabsAgent {
         )
     }
 
+    fn charge_budget(&self, tokens: u32) {
+        if let Some(budget) = &self.token_budget {
+            charge_budget(budget, tokens);
+        }
+    }
+
+    fn add_tokens(&self, usage: &TokenUsage) {
+        self.total_input_tokens
+            .fetch_add(usage.input_tokens, std::sync::atomic::Ordering::Relaxed);
+        self.total_output_tokens
+            .fetch_add(usage.output_tokens, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Count one LLM call: totals, budget, session row, and the parent's
+    /// ledger when this is a sub-agent.
+    async fn account_usage(&self, turn: usize, usage: &TokenUsage) {
+        self.add_tokens(usage);
+        self.charge_budget(usage.input_tokens + usage.output_tokens);
+        self.persist_token_usage(turn, usage.input_tokens, usage.output_tokens)
+            .await;
+        if let Some((ledger, label)) = &self.usage_report {
+            ledger.record(label, usage);
+        }
+    }
+
+    /// Count what sub-agents reported since the last call, each under its
+    /// label. Their budget was charged as they ran, so it isn't charged
+    /// again. Returns the summed usage, if there was any.
+    async fn absorb_subagent_usage(&self, turn: usize) -> Option<TokenUsage> {
+        let entries = self.subagent_usage.as_ref()?.drain();
+        if entries.is_empty() {
+            return None;
+        }
+        let mut total = TokenUsage::default();
+        for (label, usage) in &entries {
+            self.add_tokens(usage);
+            if let Some(s) = &self.session {
+                if let Err(e) = s
+                    .persist_token_usage_for(turn, label, usage.input_tokens, usage.output_tokens)
+                    .await
+                {
+                    warn!("Failed to persist {label} token usage: {e}");
+                }
+            }
+            // A sub-agent's own sub-agents count towards its parent too.
+            if let Some((ledger, _)) = &self.usage_report {
+                ledger.record(label, usage);
+            }
+            total.input_tokens += usage.input_tokens;
+            total.output_tokens += usage.output_tokens;
+            total.cache_read_tokens += usage.cache_read_tokens;
+            total.cache_creation_tokens += usage.cache_creation_tokens;
+            total.reasoning_tokens += usage.reasoning_tokens;
+        }
+        Some(total)
+    }
+
+    fn ensure_budget(&self) -> Result<()> {
+        if budget_exhausted(self.token_budget.as_ref()) {
+            anyhow::bail!("sub-agent token budget exhausted");
+        }
+        Ok(())
+    }
+
     pub fn context_used_pct(&self) -> f32 {
         let (inp, out) = self.total_tokens();
         let total = (inp + out) as f32;
         total / self.config.max_context_tokens as f32
     }
 
+    /// Tokens a request may take: `max_context_tokens` less the reserve
+    /// kept for the reply.
+    fn context_budget(&self) -> usize {
+        self.config
+            .max_context_tokens
+            .saturating_sub(self.config.context_reserve_tokens)
+    }
+
+    /// Tokens that sending `messages` with `tools` takes, as counted by the
+    /// active provider.
+    async fn request_tokens(
+        &self,
+        messages: &[Message],
+        tools: &[crate::tools::tool::ToolDef],
+    ) -> usize {
+        let tokens = match self.active_provider().count_tokens(messages, tools).await {
+            Ok(n) => n,
+            Err(e) => {
+                debug!("Token count failed, estimating: {e}");
+                crate::providers::tokens::estimate_tokens(messages, tools)
+            }
+        };
+        tokens as usize
+    }
+
+    /// Make the next request fit the context budget: past 80% of it the
+    /// oldest messages are trimmed, and if the request still doesn't fit,
+    /// the largest tool results are cut down until it does.
+    ///
+    /// The request is sized with the local estimate; the provider is asked
+    /// for an exact count only past half the budget, at most once per call,
+    /// and later passes scale the estimate by how far off it was.
+    async fn fit_context(
+        &self,
+        messages: &mut Vec<Message>,
+        tools: &[crate::tools::tool::ToolDef],
+    ) {
+        let budget = self.context_budget();
+        let estimate = |messages: &[Message]| {
+            crate::providers::tokens::estimate_tokens(messages, tools) as usize
+        };
+        let estimated = estimate(messages);
+        if estimated * 2 <= budget {
+            return;
+        }
+        let tokens = self.request_tokens(messages, tools).await;
+        if tokens * 5 <= budget * 4 {
+            return;
+        }
+        let scale = tokens as f64 / estimated.max(1) as f64;
+        warn!(
+            "Context at {}% of the budget, trimming oldest messages",
+            tokens * 100 / budget.max(1)
+        );
+        self.trim_context(messages);
+
+        for _ in 0..MAX_SHRINK_PASSES {
+            let tokens = (estimate(messages) as f64 * scale).ceil() as usize;
+            if tokens <= budget {
+                return;
+            }
+            if !shrink_largest_tool_result(messages, tokens - budget) {
+                break;
+            }
+        }
+        warn!("Request is still over the context budget of {budget} tokens");
+    }
+
+    /// A non-streaming LLM call with retry. If the provider refuses the
+    /// request as over its context window — our count can be off, or
+    /// `max_context_tokens` set too high — the oldest messages are trimmed
+    /// and the call is made once more.
+    async fn complete_fitting(
+        &self,
+        turn: usize,
+        messages: &mut Vec<Message>,
+        tool_defs: &[crate::tools::tool::ToolDef],
+        format: &ResponseFormat,
+    ) -> Result<LlmResponse> {
+        let mut trimmed = false;
+        loop {
+            let result = self
+                .call_with_retry(turn, "llm_complete", None, || {
+                    let msgs = Message::validate_tool_pairing(messages).into_owned();
+                    let defs = tool_defs.to_vec();
+                    async move {
+                        self.active_provider()
+                            .complete_with_format(&msgs, &defs, format)
+                            .await
+                    }
+                })
+                .await;
+            match result {
+                Err(e) if !trimmed && context_overflow(&e) => {
+                    warn!("Provider reports the context window exceeded, trimming and retrying");
+                    self.trim_context(messages);
+                    trimmed = true;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// [`stream_with_retry`](Self::stream_with_retry), trimming the oldest
+    /// messages and trying once more if the provider refuses the request as
+    /// over its context window.
+    async fn stream_fitting(
+        &self,
+        turn: usize,
+        messages: &mut Vec<Message>,
+        tool_defs: &[crate::tools::tool::ToolDef],
+        tx: &mpsc::Sender<StreamChunk>,
+        cancel: &CancellationToken,
+    ) -> Result<Option<(String, Vec<ToolCall>, Option<TokenUsage>)>> {
+        let mut trimmed = false;
+        loop {
+            match self
+                .stream_with_retry(turn, messages, tool_defs, tx, cancel)
+                .await
+            {
+                Err(e) if !trimmed && context_overflow(&e) => {
+                    let text =
+                        "⚠ context window exceeded — trimming history and retrying".to_string();
+                    warn!("{text}");
+                    let _ = tx.send(StreamChunk::Status { text }).await;
+                    self.trim_context(messages);
+                    trimmed = true;
+                }
+                result => return result,
+            }
+        }
+    }
+
     /// Streaming LLM call with exponential-backoff retry.
     ///
     /// Returns `Ok(Some((delta, calls, usage)))` on success.
-    /// Returns `Ok(None)` when the CLI consumer dropped (Ctrl+C) — caller should stop cleanly.
-    /// Returns `Err` after exhausting all retry attempts.
+    /// Returns `Ok(None)` when `cancel` fired or the consumer dropped — caller should stop cleanly.
+    /// Returns `Err` after exhausting all retry attempts on the primary
+    /// provider and every fallback; see [`retry_delay`](Self::retry_delay)
+    /// for which failures are retried.
+    ///
+    /// When an attempt fails after streaming some text, the partial text is
+    /// persisted (role `partial`) and a `StreamChunk::Interrupted` is sent. If
+    /// the provider supports continuation the next attempt resumes from it;
+    /// otherwise the turn is retried from scratch.
     async fn stream_with_retry(
         &self,
         turn: usize,
         messages: &[Message],
         tool_defs: &[crate::tools::tool::ToolDef],
         tx: &mpsc::Sender<StreamChunk>,
+        cancel: &CancellationToken,
     ) -> Result<Option<(String, Vec<ToolCall>, Option<TokenUsage>)>> {
         let max = self.config.max_retries;
-        let base_ms = self.config.retry_base_delay_ms;
-        let mut attempt_result = None;
-        for attempt in 0..=max {
-            match self.stream_one_attempt(messages, tool_defs, tx).await {
-                Ok(Some(v)) => {
-                    attempt_result = Some(v);
-                    break;
+        // Text kept from interrupted attempts. Only non-empty when the provider
+        // supports continuation; it is then sent back as an assistant prefill.
+        let mut prefix = String::new();
+        let mut persisted_partial = false;
+        let messages = Message::validate_tool_pairing(messages);
+        let messages = messages.as_ref();
+        let mut attempt = 0;
+        loop {
+            let mut partial = String::new();
+            let result = if prefix.is_empty() {
+                self.stream_one_attempt(messages, tool_defs, tx, cancel, &mut partial)
+                    .await
+            } else {
+                let mut continued = messages.to_vec();
+                continued.push(Message::assistant(&prefix));
+                self.stream_one_attempt(&continued, tool_defs, tx, cancel, &mut partial)
+                    .await
+            };
+            match result {
+                Ok(Some((delta, calls, usage))) => {
+                    if persisted_partial {
+                        self.clear_partials().await;
+                    }
+                    return Ok(Some((format!("{prefix}{delta}"), calls, usage)));
                 }
                 Ok(None) => return Ok(None), // cancelled
                 Err(e) => {
                     self.persist_error(turn, "llm_stream", &e, attempt).await;
-                    if attempt < max {
-                        let delay = base_ms * 2u64.pow(attempt as u32);
+                    if !partial.is_empty() {
+                        let resumable = self.active_provider().supports_continuation();
+                        let text = if resumable {
+                            // Prefills may not end in whitespace.
+                            prefix.push_str(&partial);
+                            prefix.truncate(prefix.trim_end().len());
+                            prefix.clone()
+                        } else {
+                            partial
+                        };
+                        self.persist_partial(turn, &text).await;
+                        persisted_partial = true;
+                        let _ = tx
+                            .send(StreamChunk::Interrupted {
+                                partial: text,
+                                resumable,
+                            })
+                            .await;
+                    }
+                    if let Some(delay) = self.retry_delay(&e, attempt) {
                         let msg = format!(
                             "↻ LLM stream attempt {}/{} failed: {e} — retrying in {delay}ms…",
                             attempt + 1,
@@ -685,16 +1500,32 @@ impl KrabsAgent {
                         );
                         warn!("{msg}");
                         let _ = tx.send(StreamChunk::Status { text: msg }).await;
-                        tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
+                        tokio::select! {
+                            _ = cancel.cancelled() => return Ok(None),
+                            _ = tokio::time::sleep(tokio::time::Duration::from_millis(delay)) => {}
+                        }
+                        attempt += 1;
+                    } else if context_overflow(&e) {
+                        return Err(e);
+                    } else if let Some(msg) = self.switch_to_fallback(&e) {
+                        // The fallback starts the reply over, so a kept prefix
+                        // is dropped and consumers are told to discard it.
+                        if !prefix.is_empty() {
+                            let _ = tx
+                                .send(StreamChunk::Interrupted {
+                                    partial: std::mem::take(&mut prefix),
+                                    resumable: false,
+                                })
+                                .await;
+                        }
+                        let _ = tx.send(StreamChunk::Status { text: msg }).await;
+                        attempt = 0;
                     } else {
                         return Err(e);
                     }
                 }
             }
         }
-        Ok(Some(
-            attempt_result.expect("loop exited without result or error"),
-        ))
     }
 
     /// Perform a single streaming LLM call for one turn.
@@ -703,23 +1534,27 @@ impl KrabsAgent {
     /// channel (consumer) in parallel to avoid deadlocks with extended-thinking
     /// models that emit many chunks.
     ///
-    /// Chunks that are safe to forward to the outer `tx` (`Delta` and
-    /// `ToolCallReady`) are forwarded immediately. If `tx` is closed (Ctrl+C),
-    /// the producer task is aborted and `Ok(None)` is returned to signal
-    /// cancellation.
+    /// Chunks that are safe to forward to the outer `tx` (`Delta`, `Thinking`,
+    /// `ToolCallReady` and the provider's `Status` warnings) are forwarded
+    /// immediately. If `cancel` fires or `tx` is
+    /// closed, the producer task is aborted and `Ok(None)` is returned to
+    /// signal cancellation. Nothing of the abandoned reply is persisted.
     ///
     /// Returns `Ok(Some((delta, tool_calls, usage)))` on success.
-    /// Returns `Ok(None)` when the outer consumer (CLI) has dropped `tx`.
+    /// Returns `Ok(None)` when cancelled or the outer consumer has dropped `tx`.
     /// Returns `Err` on any LLM/stream error — the caller decides whether to retry.
+    /// Text received before the error is left in `delta_content`.
     async fn stream_one_attempt(
         &self,
         messages: &[Message],
         tool_defs: &[crate::tools::tool::ToolDef],
         tx: &mpsc::Sender<StreamChunk>,
+        cancel: &CancellationToken,
+        delta_content: &mut String,
     ) -> Result<Option<(String, Vec<ToolCall>, Option<TokenUsage>)>> {
         let (turn_tx, mut turn_rx) = mpsc::channel::<StreamChunk>(4096);
 
-        let provider_for_stream = Arc::clone(&self.provider);
+        let provider_for_stream = Arc::clone(self.active_provider());
         let msgs_for_stream = messages.to_vec();
         let defs_for_stream = tool_defs.to_vec();
         let turn_tx_for_stream = turn_tx.clone();
@@ -731,20 +1566,39 @@ impl KrabsAgent {
             });
         drop(turn_tx);
 
-        let mut delta_content = String::new();
         let mut tool_calls = Vec::new();
         let mut usage = None;
 
-        while let Some(chunk) = turn_rx.recv().await {
+        loop {
+            let chunk = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => {
+                    stream_task.abort();
+                    return Ok(None);
+                }
+                chunk = turn_rx.recv() => match chunk {
+                    Some(chunk) => chunk,
+                    None => break,
+                },
+            };
             match &chunk {
-                StreamChunk::Delta { text } => delta_content.push_str(text),
+                StreamChunk::Delta { text } => {
+                    delta_content.push_str(text);
+                    self.observer.on_token(text).await;
+                }
                 StreamChunk::ToolCallReady { call } => tool_calls.push(call.clone()),
                 StreamChunk::Done { usage: u } => usage = Some(u.clone()),
-                StreamChunk::Status { .. } => {}
+                // Forwarded for display only; never part of the reply.
+                StreamChunk::Thinking { .. }
+                | StreamChunk::Status { .. }
+                | StreamChunk::Interrupted { .. } => {}
             }
             if matches!(
                 chunk,
-                StreamChunk::Delta { .. } | StreamChunk::ToolCallReady { .. }
+                StreamChunk::Delta { .. }
+                    | StreamChunk::Thinking { .. }
+                    | StreamChunk::ToolCallReady { .. }
+                    | StreamChunk::Status { .. }
             ) && tx.send(chunk).await.is_err()
             {
                 stream_task.abort();
@@ -761,7 +1615,7 @@ impl KrabsAgent {
             Err(_) => {} // cancelled — already handled above
         }
 
-        Ok(Some((delta_content, tool_calls, usage)))
+        Ok(Some((std::mem::take(delta_content), tool_calls, usage)))
     }
 
     pub async fn run_streaming(self: Arc<Self>, task: &str) -> Result<mpsc::Receiver<StreamChunk>> {
@@ -773,15 +1627,12 @@ impl KrabsAgent {
             let system_prompt = agent.current_system_prompt().await;
             let messages = vec![Message::system(&system_prompt), Message::user(task.clone())];
             if let Err(e) = agent
-                .streaming_loop_inner(task, messages, None, tx.clone())
+                .streaming_loop_inner(task, messages, None, tx.clone(), &CancellationToken::new())
                 .await
             {
                 let _ = tx
                     .send(StreamChunk::Done {
-                        usage: crate::providers::provider::TokenUsage {
-                            input_tokens: 0,
-                            output_tokens: 0,
-                        },
+                        usage: crate::providers::provider::TokenUsage::default(),
                     })
                     .await;
                 tracing::error!("streaming_loop failed: {e}");
@@ -794,24 +1645,33 @@ impl KrabsAgent {
     /// Run the streaming agent loop over an existing conversation history.
     ///
     /// `messages` should contain the full conversation so far, including the
-    /// new user message at the end. The system prompt at position 0 (if any)
-    /// is replaced each turn with the current computed system prompt.
+    /// new user message at the end. History that ends otherwise, e.g. in the
+    /// tool results of a turn cancelled or stopped at `max_turns`, carries
+    /// that turn on with a fresh `max_turns` budget. The system prompt at
+    /// position 0 (if any) is replaced each turn with the current computed
+    /// system prompt.
     ///
     /// Returns a stream of `StreamChunk`s and a oneshot that fires with the
     /// final message list (including all new assistant + tool messages) when
-    /// the agent loop completes, or `Err` if the loop fails.
+    /// the agent loop completes, or `Err` if the loop fails. The flag is set
+    /// when the loop stopped at `max_turns` with a partial answer.
     /// The session ID for this agent, if persistence is active.
     pub fn session_id(&self) -> Option<&str> {
         self.session.as_ref().map(|s| s.id.as_str())
     }
 
+    /// Cancelling `cancel` stops the loop at the next safe point: an in-flight
+    /// LLM call is dropped, a running tool call finishes, calls still queued
+    /// get a "cancelled" result, and the turn is checkpointed before the
+    /// oneshot fires with the history so far.
     pub async fn run_streaming_with_history(
         self: Arc<Self>,
         messages: Vec<Message>,
         subturn_resume: Option<SubturnResume>,
+        cancel: CancellationToken,
     ) -> Result<(
         mpsc::Receiver<StreamChunk>,
-        oneshot::Receiver<Result<(Option<String>, Vec<Message>)>>,
+        oneshot::Receiver<Result<(Option<String>, Vec<Message>, bool)>>,
     )> {
         let (tx, rx) = mpsc::channel(64);
         let (done_tx, done_rx) = oneshot::channel();
@@ -826,19 +1686,21 @@ impl KrabsAgent {
 
         tokio::task::spawn(async move {
             match agent
-                .streaming_loop_inner(task, messages, subturn_resume, tx.clone())
+                .streaming_loop_inner(task, messages, subturn_resume, tx.clone(), &cancel)
                 .await
             {
-                Ok(final_messages) => {
-                    let _ = done_tx.send(Ok((session_id, final_messages)));
+                Ok((final_messages, hit_max_turns)) => {
+                    agent
+                        .observer
+                        .on_done(session_id.as_deref(), Ok(&final_messages))
+                        .await;
+                    let _ = done_tx.send(Ok((session_id, final_messages, hit_max_turns)));
                 }
                 Err(e) => {
+                    agent.observer.on_done(session_id.as_deref(), Err(&e)).await;
                     let _ = tx
                         .send(StreamChunk::Done {
-                            usage: crate::providers::provider::TokenUsage {
-                                input_tokens: 0,
-                                output_tokens: 0,
-                            },
+                            usage: crate::providers::provider::TokenUsage::default(),
                         })
                         .await;
                     let _ = done_tx.send(Err(e));
@@ -853,16 +1715,39 @@ impl KrabsAgent {
     /// `messages` is the full initial conversation (system + history + user turn).
     /// `subturn_resume` is set when resuming from a sub-turn checkpoint: turn 0 skips
     /// the LLM call and re-uses the tool calls already in `messages`, executing only
-    /// the ones not yet completed.
+    /// the ones not yet completed. `cancel` is checked between LLM calls and tool
+    /// calls; see [`run_streaming_with_history`](Self::run_streaming_with_history).
     async fn streaming_loop_inner(
         &self,
-        task: String,
+        mut task: String,
         mut messages: Vec<Message>,
         mut subturn_resume: Option<SubturnResume>,
         tx: mpsc::Sender<StreamChunk>,
-    ) -> Result<Vec<Message>> {
+        cancel: &CancellationToken,
+    ) -> Result<(Vec<Message>, bool)> {
         let tool_defs = self.registry.tool_defs();
 
+        // A user message last is a new turn. Anything else carries on one that
+        // stopped early, whose messages are all persisted already.
+        let new_message = messages
+            .last()
+            .is_some_and(|m| matches!(m.role, Role::User));
+
+        // Let hooks rewrite the new user message before it is routed, persisted
+        // or sent. A resumed sub-turn already went through this.
+        if subturn_resume.is_none() && new_message {
+            if let Some(user_msg) = messages
+                .iter_mut()
+                .rev()
+                .find(|m| matches!(m.role, Role::User))
+            {
+                if let Some(content) = self.rewrite_user_message(&user_msg.content).await {
+                    task.clone_from(&content);
+                    user_msg.content = content;
+                }
+            }
+        }
+
         // Classify task and pick execution strategy
         let route = self.route(&task).await;
         info!(route = route.as_str(), "Task routed");
@@ -872,9 +1757,14 @@ impl KrabsAgent {
             .await;
 
         // Persist the newest user message (the one just submitted, at the end of history).
-        if let Some(user_msg) = messages.iter().rev().find(|m| matches!(m.role, Role::User)) {
-            self.persist_message(user_msg, 0).await;
-        }
+        if let Some(user_msg) = messages
+            .iter()
+            .rev()
+            .find(|m| matches!(m.role, Role::User))
+            .filter(|_| new_message || subturn_resume.is_some())
+        {
+            self.persist_message(user_msg, 0).await;
+        }
 
         // Ensure a system message is at position 0 (only if non-empty)
         let system_prompt = self.current_system_prompt_for(&route).await;
@@ -896,10 +1786,13 @@ impl KrabsAgent {
             messages.remove(0);
         }
 
+        let mut thresholds_fired: Vec<u8> = Vec::new();
         for turn in 0..self.config.max_turns {
-            // If the consumer (CLI) dropped its receiver (e.g. Ctrl+C), stop immediately.
-            if tx.is_closed() {
-                return Ok(messages);
+            // Cancelled, or the consumer dropped its receiver: the previous
+            // turn ended on a checkpoint, so stop here.
+            if cancel.is_cancelled() || tx.is_closed() {
+                info!(turn, "Streaming loop cancelled");
+                return Ok((messages, false));
             }
 
             let system_prompt = self.current_system_prompt_for(&route).await;
@@ -915,16 +1808,21 @@ impl KrabsAgent {
                 }
             }
 
+            self.ensure_budget()?;
             self.hooks.fire(&HookEvent::TurnStart { turn }).await;
 
-            if self.context_used_pct() > 0.8 {
-                warn!(
-                    "Context at {}%, trimming oldest messages",
-                    (self.context_used_pct() * 100.0) as u32
-                );
-                self.trim_context(&mut messages);
+            // Skip on a sub-turn replay: compacting would drop the pending tool calls.
+            let replaying = turn == 0 && subturn_resume.is_some();
+            if !replaying
+                && self
+                    .check_context_thresholds(turn, &mut thresholds_fired, &mut messages)
+                    .await
+            {
+                return Ok((messages, false));
             }
 
+            self.fit_context(&mut messages, &tool_defs).await;
+
             debug!(
                 "Stream turn {}: calling LLM with {} messages",
                 turn,
@@ -957,30 +1855,26 @@ impl KrabsAgent {
                     (String::new(), existing_calls, None)
                 } else {
                     match self
-                        .stream_with_retry(turn, &messages, &tool_defs, &tx)
+                        .stream_fitting(turn, &mut messages, &tool_defs, &tx, cancel)
                         .await?
                     {
                         Some(v) => v,
-                        None => return Ok(messages), // Ctrl+C
+                        None => return Ok((messages, false)), // Ctrl+C
                     }
                 }
             } else {
                 match self
-                    .stream_with_retry(turn, &messages, &tool_defs, &tx)
+                    .stream_fitting(turn, &mut messages, &tool_defs, &tx, cancel)
                     .await?
                 {
                     Some(v) => v,
-                    None => return Ok(messages), // Ctrl+C
+                    None => return Ok((messages, false)), // Ctrl+C
                 }
             };
 
             if let Some(usage) = usage_this_turn {
-                self.total_input_tokens
-                    .fetch_add(usage.input_tokens, std::sync::atomic::Ordering::Relaxed);
-                self.total_output_tokens
-                    .fetch_add(usage.output_tokens, std::sync::atomic::Ordering::Relaxed);
-                self.persist_token_usage(turn, usage.input_tokens, usage.output_tokens)
-                    .await;
+                self.account_usage(turn, &usage).await;
+                self.observer.on_usage(&usage).await;
                 let _ = tx.send(StreamChunk::Done { usage }).await;
             }
 
@@ -1006,114 +1900,66 @@ impl KrabsAgent {
                     .map(|sr| sr.completed_tool_count)
                     .unwrap_or(0);
 
-                for (tool_idx, mut call) in tool_calls_this_turn.into_iter().enumerate() {
-                    // Skip calls whose results are already in the history.
-                    if tool_idx < skip_count {
-                        debug!(
-                            "Sub-turn resume: skipping already-completed tool '{}' (idx {})",
-                            call.name, tool_idx
-                        );
-                        continue;
-                    }
-                    if !self.permissions.is_allowed(&call.name) {
-                        let msg = format!("Permission denied for tool: {}", call.name);
-                        warn!("{}", msg);
-                        let result_msg = Message::tool_result(&msg, &call.id, &call.name);
-                        self.persist_message(&result_msg, turn).await;
-                        self.write_subturn_checkpoint(turn, tool_idx, &call.id)
-                            .await;
-                        messages.push(result_msg);
-                        continue;
-                    }
-
-                    // PreToolUse hook
-                    let pre = self
-                        .hooks
-                        .fire(&HookEvent::PreToolUse {
-                            tool_name: call.name.clone(),
-                            args: call.args.clone(),
-                            tool_use_id: call.id.clone(),
-                        })
-                        .await;
-
-                    match pre {
-                        HookOutput::ToolDecision(ToolUseDecision::Deny { reason }) => {
-                            let msg = format!("Tool call denied by hook: {}", reason);
-                            warn!("{}", msg);
-                            let result_msg = Message::tool_result(&msg, &call.id, &call.name);
-                            self.persist_message(&result_msg, turn).await;
-                            self.write_subturn_checkpoint(turn, tool_idx, &call.id)
-                                .await;
-                            messages.push(result_msg);
-                            continue;
+                let mut pending = tool_calls_this_turn
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(tool_idx, call)| {
+                        // Skip calls whose results are already in the history.
+                        if *tool_idx < skip_count {
+                            debug!(
+                                "Sub-turn resume: skipping already-completed tool '{}' (idx {})",
+                                call.name, tool_idx
+                            );
                         }
-                        HookOutput::ToolDecision(ToolUseDecision::ModifyArgs { args }) => {
-                            debug!("Hook modified args for tool: {}", call.name);
-                            call.args = args;
-                        }
-                        _ => {}
-                    }
-
-                    match self.registry.get(&call.name) {
-                        Some(tool) => {
-                            debug!("Calling tool: {} with args: {}", call.name, call.args);
-                            let result = self
-                                .call_tool_with_retry(
-                                    turn,
-                                    &call.name,
-                                    tool,
-                                    call.args.clone(),
-                                    Some(&tx),
-                                )
-                                .await;
-                            let post = if result.is_error {
-                                self.hooks
-                                    .fire(&HookEvent::PostToolUseFailure {
-                                        tool_name: call.name.clone(),
-                                        args: call.args.clone(),
-                                        error: result.content.clone(),
-                                        tool_use_id: call.id.clone(),
-                                    })
-                                    .await
-                            } else {
-                                self.hooks
-                                    .fire(&HookEvent::PostToolUse {
-                                        tool_name: call.name.clone(),
-                                        args: call.args.clone(),
-                                        result: result.content.clone(),
-                                        tool_use_id: call.id.clone(),
-                                    })
-                                    .await
-                            };
-                            let mut content = if let HookOutput::AppendContext(ctx) = post {
-                                format!("{}\n{}", result.content, ctx)
-                            } else {
-                                result.content
-                            };
-                            let max_chars = self.config.max_tool_result_chars;
-                            if max_chars > 0 && content.len() > max_chars {
-                                content.truncate(max_chars);
-                                content.push_str("\n\n[…output truncated to fit context window…]");
-                            }
-                            let result_msg = Message::tool_result(&content, &call.id, &call.name);
+                        *tool_idx >= skip_count
+                    })
+                    .peekable();
+
+                while let Some(first) = pending.next() {
+                    if cancel.is_cancelled() {
+                        // Answer the calls that never ran so the turn is
+                        // complete, and checkpoint it as a clean resume point.
+                        info!(turn, "Cancelled between tool calls");
+                        for (_, call) in std::iter::once(first).chain(pending.by_ref()) {
+                            let result_msg =
+                                Message::tool_result(CANCELLED_TOOL_RESULT, &call.id, &call.name);
                             self.persist_message(&result_msg, turn).await;
-                            // Sub-turn checkpoint: safe resume point after this tool result.
-                            self.write_subturn_checkpoint(turn, tool_idx, &call.id)
-                                .await;
                             messages.push(result_msg);
                         }
-                        None => {
-                            let msg = format!("Tool not found: {}", call.name);
-                            warn!("{}", msg);
-                            let result_msg = Message::tool_result(&msg, &call.id, &call.name);
-                            self.persist_message(&result_msg, turn).await;
-                            self.write_subturn_checkpoint(turn, tool_idx, &call.id)
-                                .await;
-                            messages.push(result_msg);
+                        self.write_checkpoint(turn).await;
+                        self.hooks.fire(&HookEvent::TurnEnd { turn }).await;
+                        return Ok((messages, false));
+                    }
+                    // A run of consecutive parallel-safe calls executes concurrently;
+                    // anything else is a batch of one, so ordering is preserved.
+                    let mut batch = vec![first];
+                    if self.config.parallel_tools && self.is_parallel_safe(&batch[0].1.name) {
+                        while let Some(next) =
+                            pending.next_if(|(_, call)| self.is_parallel_safe(&call.name))
+                        {
+                            batch.push(next);
                         }
                     }
+                    let results = join_all(
+                        batch
+                            .iter()
+                            .map(|(_, call)| self.execute_tool_call(turn, call, &tx)),
+                    )
+                    .await;
+                    for ((tool_idx, call), result_msg) in batch.iter().zip(results) {
+                        self.persist_message(&result_msg, turn).await;
+                        // Sub-turn checkpoint: safe resume point after this tool result.
+                        self.write_subturn_checkpoint(turn, *tool_idx, &call.id)
+                            .await;
+                        messages.push(result_msg);
+                    }
                 }
 
+                // Sub-agents' usage is sent as its own `Done`, so displays
+                // that sum `Done` usage include it.
+                if let Some(usage) = self.absorb_subagent_usage(turn).await {
+                    let _ = tx.send(StreamChunk::Done { usage }).await;
+                }
                 // Sub-turn resume is consumed after the first tool turn completes.
                 subturn_resume = None;
                 self.write_checkpoint(turn).await;
@@ -1130,30 +1976,289 @@ impl KrabsAgent {
                         result: delta_content,
                     })
                     .await;
-                return Ok(messages);
+                return Ok((messages, false));
             }
         }
 
-        let e = anyhow::anyhow!("Max turns ({}) exceeded", self.config.max_turns);
-        self.persist_error(self.config.max_turns, "max_turns", &e, 0)
+        self.finish_at_max_turns(&mut messages).await;
+        Ok((messages, true))
+    }
+
+    fn is_parallel_safe(&self, tool_name: &str) -> bool {
+        self.registry
+            .get(tool_name)
+            .map(|t| t.is_parallel_safe())
+            .unwrap_or(false)
+    }
+
+    /// Why `tool_name` may not be called, or `None` when it may. Checks the
+    /// agent's own permissions, then the restrictions of the active skill.
+    async fn permission_denial(&self, tool_name: &str) -> Option<String> {
+        if !self.permissions.is_allowed(tool_name) {
+            return Some(format!("Permission denied for tool: {}", tool_name));
+        }
+        let skill = self.skills.as_ref()?.denied_by(tool_name).await?;
+        Some(format!(
+            "Permission denied for tool: {} (restricted by active skill '{}')",
+            tool_name, skill
+        ))
+    }
+
+    /// Run one tool call through permissions, hooks, retry and truncation, and
+    /// return its `tool_result` message. Persisting it is left to the caller so
+    /// results of a parallel batch can be written in call order.
+    async fn execute_tool_call(
+        &self,
+        turn: usize,
+        call: &ToolCall,
+        tx: &mpsc::Sender<StreamChunk>,
+    ) -> Message {
+        self.observer.on_tool_call(call).await;
+        let (msg, is_error) = self.run_tool_call(turn, call, tx).await;
+        self.observer
+            .on_tool_result(call, &msg.content, is_error)
             .await;
-        Err(e)
+        msg
+    }
+
+    /// [`execute_tool_call`](Self::execute_tool_call) without the observer
+    /// notifications; also returns whether the call failed or was refused.
+    async fn run_tool_call(
+        &self,
+        turn: usize,
+        call: &ToolCall,
+        tx: &mpsc::Sender<StreamChunk>,
+    ) -> (Message, bool) {
+        if let Some(msg) = malformed_call_error(call) {
+            warn!("{}", msg);
+            return (Message::tool_result(&msg, &call.id, &call.name), true);
+        }
+        if let Some(msg) = self.permission_denial(&call.name).await {
+            warn!("{}", msg);
+            return (Message::tool_result(&msg, &call.id, &call.name), true);
+        }
+        if !self.observer.on_permission_request(call).await {
+            let msg = format!(
+                "Permission denied for tool: {} (denied by observer)",
+                call.name
+            );
+            warn!("{}", msg);
+            return (Message::tool_result(&msg, &call.id, &call.name), true);
+        }
+
+        // PreToolUse hook
+        let pre = self
+            .hooks
+            .fire(&HookEvent::PreToolUse {
+                tool_name: call.name.clone(),
+                args: call.args.clone(),
+                tool_use_id: call.id.clone(),
+            })
+            .await;
+
+        let args = match pre {
+            HookOutput::ToolDecision(ToolUseDecision::Deny { reason }) => {
+                let msg = format!("Tool call denied by hook: {}", reason);
+                warn!("{}", msg);
+                return (Message::tool_result(&msg, &call.id, &call.name), true);
+            }
+            HookOutput::ToolDecision(ToolUseDecision::ModifyArgs { args }) => {
+                debug!("Hook modified args for tool: {}", call.name);
+                args
+            }
+            _ => call.args.clone(),
+        };
+
+        let Some(tool) = self.registry.get(&call.name) else {
+            let msg = format!("Tool not found: {}", call.name);
+            warn!("{}", msg);
+            return (Message::tool_result(&msg, &call.id, &call.name), true);
+        };
+
+        debug!("Calling tool: {} with args: {}", call.name, args);
+        let max_bytes = tool.max_output_bytes(self.config.max_tool_output_bytes);
+        let snapshots = self.capture_snapshots(&call.name, &args).await;
+        let result = self
+            .call_tool_with_retry(turn, &call.name, tool, args.clone(), Some(tx))
+            .await
+            .truncated(max_bytes);
+        if !result.is_error {
+            self.persist_snapshots(turn, &call.id, snapshots).await;
+        }
+        let post = if result.is_error {
+            self.hooks
+                .fire(&HookEvent::PostToolUseFailure {
+                    tool_name: call.name.clone(),
+                    args,
+                    error: result.content.clone(),
+                    tool_use_id: call.id.clone(),
+                })
+                .await
+        } else {
+            self.hooks
+                .fire(&HookEvent::PostToolUse {
+                    tool_name: call.name.clone(),
+                    args,
+                    result: result.content.clone(),
+                    tool_use_id: call.id.clone(),
+                })
+                .await
+        };
+        let content = if let HookOutput::AppendContext(ctx) = post {
+            format!("{}\n{}", result.content, ctx)
+        } else {
+            result.content
+        };
+        let msg = Message::tool_result(&content, &call.id, &call.name).with_images(result.images);
+        (msg, result.is_error)
+    }
+
+    /// Read the files `tool_name` is about to change, for `/undo`. Empty when
+    /// there's no session, snapshots are off, or this is a dry run.
+    async fn capture_snapshots(&self, tool_name: &str, args: &Value) -> Vec<FileSnapshot> {
+        if self.session.is_none() || self.config.snapshot_max_count == 0 || self.config.dry_run {
+            return Vec::new();
+        }
+        let mut paths = snapshot::changed_paths(tool_name, args);
+        if paths.is_empty() {
+            return Vec::new();
+        }
+        // `write` and `apply_patch` open their paths under the project root;
+        // snapshot those files.
+        if matches!(tool_name, "write" | "apply_patch") {
+            let root = ProjectRoot::from_config(&self.config);
+            paths.retain_mut(|p| match root.resolve(p) {
+                Ok(resolved) => {
+                    *p = resolved.display().to_string();
+                    true
+                }
+                Err(_) => false,
+            });
+        }
+        snapshot::capture(paths, self.config.snapshot_max_file_bytes).await
+    }
+
+    /// Record the snapshots of a call that succeeded, then drop old ones past
+    /// the configured limits.
+    async fn persist_snapshots(&self, turn: usize, call_id: &str, snapshots: Vec<FileSnapshot>) {
+        let Some(s) = &self.session else {
+            return;
+        };
+        if snapshots.is_empty() {
+            return;
+        }
+        for snap in &snapshots {
+            if let Err(e) = s
+                .persist_file_snapshot(turn, call_id, &snap.path, snap.content.as_deref())
+                .await
+            {
+                warn!("Failed to snapshot {}: {e}", snap.path);
+            }
+        }
+        if let Err(e) = s
+            .prune_file_snapshots(
+                self.config.snapshot_max_count,
+                self.config.snapshot_max_total_bytes,
+            )
+            .await
+        {
+            warn!("Failed to prune file snapshots: {e}");
+        }
+    }
+
+    /// Fire `ContextThreshold` for every configured boundary reached and not yet
+    /// fired this run. Returns `true` when a hook asked the agent to stop.
+    async fn check_context_thresholds(
+        &self,
+        turn: usize,
+        fired: &mut Vec<u8>,
+        messages: &mut Vec<Message>,
+    ) -> bool {
+        let used = self.context_used_pct() * 100.0;
+        let (input_tokens, output_tokens) = self.total_tokens();
+        for &pct in &self.config.context_thresholds {
+            if used < f32::from(pct) || fired.contains(&pct) {
+                continue;
+            }
+            fired.push(pct);
+            let event = HookEvent::ContextThreshold {
+                pct,
+                input_tokens,
+                output_tokens,
+            };
+            match self.hooks.fire(&event).await {
+                HookOutput::Stop => {
+                    info!(pct, "Context threshold hook stopped the agent");
+                    return true;
+                }
+                HookOutput::Compact => self.compact_context(turn, messages).await,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Replace `messages` with a summary of themselves, persisting the summary
+    /// so a resumed session starts from it. Failures leave `messages` untouched.
+    async fn compact_context(&self, turn: usize, messages: &mut Vec<Message>) {
+        let compaction = match compact::compact(
+            self.active_provider().as_ref(),
+            messages,
+            self.config.compact_keep_tool_results,
+        )
+        .await
+        {
+            Ok(Some(c)) => c,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Context compaction failed: {e}");
+                return;
+            }
+        };
+        info!(
+            reclaimed = compaction.tokens_reclaimed(),
+            "Compacted context on threshold hook"
+        );
+        if let Some(s) = &self.session {
+            if let Err(e) = s.persist_compaction(&compaction, turn).await {
+                warn!("Failed to persist compaction summary: {e}");
+            }
+        }
+        *messages = compaction.messages;
     }
 
     fn trim_context(&self, messages: &mut Vec<Message>) {
+        // System and developer instructions are never trimmed.
         let system_count = messages
             .iter()
-            .filter(|m| matches!(m.role, Role::System))
+            .filter(|m| matches!(m.role, Role::System | Role::Developer))
             .count();
-        while messages.len() > system_count + 2 {
-            let idx = messages
+        let first_non_system = |messages: &[Message]| {
+            messages
                 .iter()
-                .position(|m| !matches!(m.role, Role::System));
-            if let Some(i) = idx {
-                messages.remove(i);
-            } else {
+                .position(|m| !matches!(m.role, Role::System | Role::Developer))
+        };
+        while messages.len() > system_count + 2 {
+            let Some(i) = first_non_system(messages) else {
+                break;
+            };
+            // A tool call and its results are removed together, never split.
+            let removed = messages.remove(i);
+            if removed.tool_calls.is_some() {
+                while messages
+                    .get(i)
+                    .is_some_and(|m| matches!(m.role, Role::Tool))
+                {
+                    messages.remove(i);
+                }
+            }
+        }
+        // Never leave results at the front whose call was trimmed.
+        while let Some(i) = first_non_system(messages) {
+            if !matches!(messages[i].role, Role::Tool) {
                 break;
             }
+            messages.remove(i);
         }
     }
 }
@@ -1161,8 +2266,77 @@ impl KrabsAgent {
 #[async_trait]
 impl Agent for KrabsAgent {
     async fn run(&self, task: &str) -> Result<AgentOutput> {
+        let (output, _, _) = self.run_loop(task, &ResponseFormat::Text).await?;
+        Ok(output)
+    }
+}
+
+impl KrabsAgent {
+    /// Run `task` and parse the final answer as JSON matching `schema`.
+    ///
+    /// Every LLM call asks the provider for a final message in that shape (see
+    /// [`LlmProvider::complete_with_format`]). The reply is still validated; one
+    /// that fails is sent back once with the problems, and an error is returned
+    /// if the repaired reply fails too.
+    pub async fn run_structured<T: DeserializeOwned>(
+        &self,
+        task: &str,
+        schema: Value,
+    ) -> Result<T> {
+        let format = ResponseFormat::JsonSchema(schema);
+        let (output, mut messages, turn) = self.run_loop(task, &format).await?;
+        if output.hit_max_turns {
+            anyhow::bail!(
+                "Max turns ({}) exceeded before a structured reply",
+                self.config.max_turns
+            );
+        }
+        let problem = match structured::parse_structured(&output.result, &format) {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        warn!("Structured reply failed validation, asking for a repair: {problem:#}");
+
+        let turn = turn + 1;
+        let repair = Message::user(format!("{}\n{problem:#}", structured::REPAIR_PROMPT));
+        self.persist_message(&repair, turn).await;
+        messages.push(repair);
+        let response = self
+            .call_with_retry(turn, "llm_complete", None, || {
+                let msgs = Message::validate_tool_pairing(&messages).into_owned();
+                let format = &format;
+                async move {
+                    self.active_provider()
+                        .complete_with_format(&msgs, &[], format)
+                        .await
+                }
+            })
+            .await?;
+        let LlmResponse::Message { content, usage } = response else {
+            anyhow::bail!("model called tools instead of repairing its structured reply");
+        };
+        self.account_usage(turn, &usage).await;
+        self.persist_message(&Message::assistant(&content), turn)
+            .await;
+        structured::parse_structured(&content, &format).map_err(|e| {
+            anyhow::anyhow!(
+                "model returned invalid structured output after a repair attempt: {e:#}"
+            )
+        })
+    }
+
+    /// The non-streaming agent loop behind [`Agent::run`], asking for `format`
+    /// on every LLM call. Also returns the final history and the last turn.
+    async fn run_loop(
+        &self,
+        task: &str,
+        format: &ResponseFormat,
+    ) -> Result<(AgentOutput, Vec<Message>, usize)> {
         let tool_defs = self.registry.tool_defs();
 
+        let rewritten = self.rewrite_user_message(task).await;
+        let task = rewritten.as_deref().unwrap_or(task);
+
         // Classify task and pick execution strategy
         let route = self.route(task).await;
         info!(route = route.as_str(), "Task routed");
@@ -1184,15 +2358,10 @@ impl Agent for KrabsAgent {
             let system_prompt = self.current_system_prompt_for(&route).await;
             messages[0] = Message::system(&system_prompt);
 
+            self.ensure_budget()?;
             self.hooks.fire(&HookEvent::TurnStart { turn }).await;
 
-            if self.context_used_pct() > 0.8 {
-                warn!(
-                    "Context at {}%, trimming oldest messages",
-                    (self.context_used_pct() * 100.0) as u32
-                );
-                self.trim_context(&mut messages);
-            }
+            self.fit_context(&mut messages, &tool_defs).await;
 
             debug!(
                 "Turn {}: calling LLM with {} messages",
@@ -1200,11 +2369,7 @@ impl Agent for KrabsAgent {
                 messages.len()
             );
             let response = self
-                .call_with_retry(turn, "llm_complete", None, || {
-                    let msgs = messages.clone();
-                    let defs = tool_defs.clone();
-                    async move { self.provider.complete(&msgs, &defs).await }
-                })
+                .complete_fitting(turn, &mut messages, &tool_defs, format)
                 .await?;
 
             match response {
@@ -1213,12 +2378,7 @@ impl Agent for KrabsAgent {
                         "Turn {}: got final message ({} tokens)",
                         turn, usage.output_tokens
                     );
-                    self.total_input_tokens
-                        .fetch_add(usage.input_tokens, std::sync::atomic::Ordering::Relaxed);
-                    self.total_output_tokens
-                        .fetch_add(usage.output_tokens, std::sync::atomic::Ordering::Relaxed);
-                    self.persist_token_usage(turn, usage.input_tokens, usage.output_tokens)
-                        .await;
+                    self.account_usage(turn, &usage).await;
                     let final_msg = Message::assistant(&content);
                     self.persist_message(&final_msg, turn).await;
                     messages.push(final_msg);
@@ -1229,19 +2389,20 @@ impl Agent for KrabsAgent {
                             result: content.clone(),
                         })
                         .await;
-                    return Ok(AgentOutput {
+                    let (input_tokens, output_tokens) = self.total_tokens();
+                    let output = AgentOutput {
                         result: content,
                         tool_calls_made,
-                    });
+                        input_tokens,
+                        output_tokens,
+                        session_id: self.session_id().map(String::from),
+                        hit_max_turns: false,
+                    };
+                    return Ok((output, messages, turn));
                 }
                 LlmResponse::ToolCalls { calls, usage } => {
                     info!("Turn {}: got {} tool calls", turn, calls.len());
-                    self.total_input_tokens
-                        .fetch_add(usage.input_tokens, std::sync::atomic::Ordering::Relaxed);
-                    self.total_output_tokens
-                        .fetch_add(usage.output_tokens, std::sync::atomic::Ordering::Relaxed);
-                    self.persist_token_usage(turn, usage.input_tokens, usage.output_tokens)
-                        .await;
+                    self.account_usage(turn, &usage).await;
 
                     let assistant_msg = Message::assistant_tool_calls(calls.clone());
                     self.persist_message(&assistant_msg, turn).await;
@@ -1250,8 +2411,11 @@ impl Agent for KrabsAgent {
                     for mut call in calls {
                         tool_calls_made += 1;
 
-                        if !self.permissions.is_allowed(&call.name) {
-                            let msg = format!("Permission denied for tool: {}", call.name);
+                        let refusal = match malformed_call_error(&call) {
+                            Some(msg) => Some(msg),
+                            None => self.permission_denial(&call.name).await,
+                        };
+                        if let Some(msg) = refusal {
                             warn!("{}", msg);
                             let result_msg = Message::tool_result(&msg, &call.id, &call.name);
                             self.persist_message(&result_msg, turn).await;
@@ -1288,6 +2452,8 @@ impl Agent for KrabsAgent {
                         match self.registry.get(&call.name) {
                             Some(tool) => {
                                 debug!("Calling tool: {} with args: {}", call.name, call.args);
+                                let max_bytes =
+                                    tool.max_output_bytes(self.config.max_tool_output_bytes);
                                 let result = self
                                     .call_tool_with_retry(
                                         turn,
@@ -1296,7 +2462,8 @@ impl Agent for KrabsAgent {
                                         call.args.clone(),
                                         None,
                                     )
-                                    .await;
+                                    .await
+                                    .truncated(max_bytes);
                                 let post = if result.is_error {
                                     self.hooks
                                         .fire(&HookEvent::PostToolUseFailure {
@@ -1322,7 +2489,8 @@ impl Agent for KrabsAgent {
                                     result.content
                                 };
                                 let result_msg =
-                                    Message::tool_result(&content, &call.id, &call.name);
+                                    Message::tool_result(&content, &call.id, &call.name)
+                                        .with_images(result.images);
                                 self.persist_message(&result_msg, turn).await;
                                 messages.push(result_msg);
                             }
@@ -1338,13 +2506,1049 @@ impl Agent for KrabsAgent {
                 }
             }
 
+            self.absorb_subagent_usage(turn).await;
             self.write_checkpoint(turn).await;
             self.hooks.fire(&HookEvent::TurnEnd { turn }).await;
         }
 
-        let e = anyhow::anyhow!("Max turns ({}) exceeded", self.config.max_turns);
-        self.persist_error(self.config.max_turns, "max_turns", &e, 0)
+        let result = self.finish_at_max_turns(&mut messages).await;
+        let (input_tokens, output_tokens) = self.total_tokens();
+        let output = AgentOutput {
+            result,
+            tool_calls_made,
+            input_tokens,
+            output_tokens,
+            session_id: self.session_id().map(String::from),
+            hit_max_turns: true,
+        };
+        let last_turn = self.config.max_turns.saturating_sub(1);
+        Ok((output, messages, last_turn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tool::ToolDef;
+
+    struct Echo;
+
+    #[async_trait]
+    impl LlmProvider for Echo {
+        async fn complete(&self, _: &[Message], _: &[ToolDef]) -> Result<LlmResponse> {
+            Ok(LlmResponse::Message {
+                content: "done".into(),
+                usage: TokenUsage::default(),
+            })
+        }
+
+        async fn stream_complete(
+            &self,
+            _: &[Message],
+            _: &[ToolDef],
+            _: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A primary that is always overloaded.
+    struct Overloaded;
+
+    #[async_trait]
+    impl LlmProvider for Overloaded {
+        async fn complete(&self, _: &[Message], _: &[ToolDef]) -> Result<LlmResponse> {
+            Err(ProviderError::Overloaded.into())
+        }
+
+        async fn stream_complete(
+            &self,
+            _: &[Message],
+            _: &[ToolDef],
+            _: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            Err(ProviderError::Overloaded.into())
+        }
+    }
+
+    /// Rejects the key, counting how often it was asked.
+    #[derive(Default)]
+    struct BadKey(Arc<std::sync::atomic::AtomicUsize>);
+
+    #[async_trait]
+    impl LlmProvider for BadKey {
+        async fn complete(&self, _: &[Message], _: &[ToolDef]) -> Result<LlmResponse> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Err(ProviderError::Auth.into())
+        }
+
+        async fn stream_complete(
+            &self,
+            _: &[Message],
+            _: &[ToolDef],
+            _: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Err(ProviderError::Auth.into())
+        }
+    }
+
+    /// Refuses any request with more than `max` messages as over its context
+    /// window; otherwise streams "done". Counts the requests it gets.
+    struct SmallWindow {
+        max: usize,
+        calls: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for SmallWindow {
+        async fn complete(&self, m: &[Message], t: &[ToolDef]) -> Result<LlmResponse> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if m.len() > self.max {
+                return Err(
+                    ProviderError::ContextLengthExceeded("prompt is too long".into()).into(),
+                );
+            }
+            Echo.complete(m, t).await
+        }
+
+        async fn stream_complete(
+            &self,
+            m: &[Message],
+            t: &[ToolDef],
+            tx: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if m.len() > self.max {
+                return Err(
+                    ProviderError::ContextLengthExceeded("prompt is too long".into()).into(),
+                );
+            }
+            StreamsDone.stream_complete(m, t, tx).await
+        }
+    }
+
+    /// Streams "done" and stops.
+    struct StreamsDone;
+
+    #[async_trait]
+    impl LlmProvider for StreamsDone {
+        async fn complete(&self, m: &[Message], t: &[ToolDef]) -> Result<LlmResponse> {
+            Echo.complete(m, t).await
+        }
+
+        async fn stream_complete(
+            &self,
+            _: &[Message],
+            _: &[ToolDef],
+            tx: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            let _ = tx
+                .send(StreamChunk::Delta {
+                    text: "done".into(),
+                })
+                .await;
+            let _ = tx
+                .send(StreamChunk::Done {
+                    usage: TokenUsage::default(),
+                })
+                .await;
+            Ok(())
+        }
+    }
+
+    fn fast_retries() -> KrabsConfig {
+        KrabsConfig {
+            max_retries: 1,
+            retry_base_delay_ms: 1,
+            ..KrabsConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_primary_falls_back() {
+        let agent = KrabsAgentBuilder::new(fast_retries(), Overloaded)
+            .fallback_provider("backup", Echo)
+            .build();
+        let out = agent.run("hello").await.unwrap();
+        assert_eq!(out.result, "done");
+
+        // Without a fallback the same failure ends the turn.
+        let agent = KrabsAgentBuilder::new(fast_retries(), Overloaded).build();
+        assert!(agent.run("hello").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn final_errors_skip_retries_but_not_fallbacks() {
+        let config = KrabsConfig {
+            max_retries: 3,
+            ..fast_retries()
+        };
+        let calls = Arc::default();
+        let agent = KrabsAgentBuilder::new(config.clone(), BadKey(Arc::clone(&calls))).build();
+        let err = agent.run("hello").await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProviderError>(),
+            Some(&ProviderError::Auth)
+        );
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let agent = KrabsAgentBuilder::new(config, BadKey::default())
+            .fallback_provider("backup", Echo)
+            .build();
+        assert_eq!(agent.run("hello").await.unwrap().result, "done");
+    }
+
+    #[tokio::test]
+    async fn context_overflow_trims_history_and_retries_once() {
+        let calls = Arc::default();
+        let provider = SmallWindow {
+            max: 3,
+            calls: Arc::clone(&calls),
+        };
+        let agent = KrabsAgentBuilder::new(fast_retries(), provider)
+            .fallback_provider("backup", Echo)
+            .build();
+        let history = vec![
+            Message::user("first"),
+            Message::assistant("one"),
+            Message::user("second"),
+            Message::assistant("two"),
+            Message::user("third"),
+        ];
+        let (mut stream, done) = agent
+            .run_streaming_with_history(history, None, CancellationToken::new())
+            .await
+            .unwrap();
+        while stream.recv().await.is_some() {}
+        let (_, messages, _) = done.await.unwrap().unwrap();
+
+        // Neither retried as-is nor handed to the fallback: trimmed, then sent again.
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert!(!messages.iter().any(|m| m.content == "first"));
+        assert_eq!(messages.last().map(|m| m.content.as_str()), Some("done"));
+    }
+
+    #[tokio::test]
+    async fn streaming_fallback_is_announced_and_completes() {
+        let agent = KrabsAgentBuilder::new(fast_retries(), Overloaded)
+            .fallback_provider("backup", StreamsDone)
+            .build();
+        let history = vec![Message::user("hello")];
+        let (mut stream, done) = agent
+            .run_streaming_with_history(history, None, CancellationToken::new())
+            .await
+            .unwrap();
+
+        let mut statuses = Vec::new();
+        while let Some(chunk) = stream.recv().await {
+            if let StreamChunk::Status { text } = chunk {
+                statuses.push(text);
+            }
+        }
+        assert!(statuses.iter().any(|s| s.contains("fallback 'backup'")));
+
+        let (_, messages, _) = done.await.unwrap().unwrap();
+        // The conversation is kept as it was, with the fallback's reply added.
+        assert!(messages
+            .iter()
+            .any(|m| matches!(m.role, Role::User) && m.content == "hello"));
+        let reply = messages.last().unwrap();
+        assert!(matches!(reply.role, Role::Assistant));
+        assert_eq!(reply.content, "done");
+    }
+
+    /// Asks for `sysinfo` on its first stream, then answers "done".
+    #[derive(Default)]
+    struct CallsSysinfo {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LlmProvider for CallsSysinfo {
+        async fn complete(&self, m: &[Message], t: &[ToolDef]) -> Result<LlmResponse> {
+            Echo.complete(m, t).await
+        }
+
+        async fn stream_complete(
+            &self,
+            m: &[Message],
+            t: &[ToolDef],
+            tx: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            if self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) > 0 {
+                return StreamsDone.stream_complete(m, t, tx).await;
+            }
+            let call = ToolCall {
+                id: "call-1".into(),
+                name: "sysinfo".into(),
+                args: serde_json::json!({}),
+                thought_signature: None,
+            };
+            let _ = tx.send(StreamChunk::ToolCallReady { call }).await;
+            let _ = tx
+                .send(StreamChunk::Done {
+                    usage: TokenUsage::default(),
+                })
+                .await;
+            Ok(())
+        }
+    }
+
+    /// Records every callback and refuses all tool calls.
+    #[derive(Default)]
+    struct Recorder {
+        log: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl Recorder {
+        fn push(&self, entry: String) {
+            if let Ok(mut log) = self.log.lock() {
+                log.push(entry);
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AgentObserver for Recorder {
+        async fn on_token(&self, text: &str) {
+            self.push(format!("token {text}"));
+        }
+        async fn on_tool_call(&self, call: &ToolCall) {
+            self.push(format!("call {}", call.name));
+        }
+        async fn on_permission_request(&self, call: &ToolCall) -> bool {
+            self.push(format!("ask {}", call.name));
+            false
+        }
+        async fn on_tool_result(&self, call: &ToolCall, content: &str, is_error: bool) {
+            self.push(format!("result {} {is_error} {content}", call.name));
+        }
+        async fn on_usage(&self, _usage: &TokenUsage) {
+            self.push("usage".into());
+        }
+        async fn on_done(
+            &self,
+            _session_id: Option<&str>,
+            outcome: Result<&[Message], &anyhow::Error>,
+        ) {
+            self.push(format!("done {}", outcome.is_ok()));
+        }
+    }
+
+    #[tokio::test]
+    async fn observer_sees_the_run_and_can_deny_tools() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Arc::new(crate::tools::sysinfo::SysInfoTool::default()));
+        let recorder = Arc::new(Recorder::default());
+        let agent = KrabsAgentBuilder::new(KrabsConfig::default(), CallsSysinfo::default())
+            .registry(registry)
+            .observer(Arc::clone(&recorder) as Arc<dyn AgentObserver>)
+            .build();
+
+        let (mut stream, done) = agent
+            .run_streaming_with_history(
+                vec![Message::user("where am I?")],
+                None,
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        while stream.recv().await.is_some() {}
+        done.await.unwrap().unwrap();
+
+        let log = recorder.log.lock().unwrap().clone();
+        assert_eq!(
+            log,
+            [
+                "usage",
+                "call sysinfo",
+                "ask sysinfo",
+                "result sysinfo true Permission denied for tool: sysinfo (denied by observer)",
+                "token done",
+                "usage",
+                "done true",
+            ]
+        );
+    }
+
+    /// Logs when each call starts and ends, pausing in between so that
+    /// concurrent calls overlap.
+    struct Probe {
+        name: &'static str,
+        parallel_safe: bool,
+        log: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl Probe {
+        fn push(&self, entry: String) {
+            if let Ok(mut log) = self.log.lock() {
+                log.push(entry);
+            }
+        }
+    }
+
+    #[async_trait]
+    impl crate::tools::tool::Tool for Probe {
+        fn name(&self) -> &str {
+            self.name
+        }
+        fn description(&self) -> &str {
+            "Logs its calls"
+        }
+        fn parameters(&self) -> Value {
+            serde_json::json!({ "type": "object" })
+        }
+        async fn call(&self, args: Value) -> Result<crate::tools::tool::ToolResult> {
+            let id = args["id"].as_str().unwrap_or_default().to_string();
+            self.push(format!("start {id}"));
+            tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+            self.push(format!("end {id}"));
+            Ok(crate::tools::tool::ToolResult::ok(id))
+        }
+        fn is_parallel_safe(&self) -> bool {
+            self.parallel_safe
+        }
+    }
+
+    /// Streams `read`, `read`, `bash`, `read` calls (ids `c0`..`c3`) once,
+    /// then answers.
+    #[derive(Default)]
+    struct CallsMixed {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LlmProvider for CallsMixed {
+        async fn complete(&self, m: &[Message], t: &[ToolDef]) -> Result<LlmResponse> {
+            Echo.complete(m, t).await
+        }
+
+        async fn stream_complete(
+            &self,
+            m: &[Message],
+            t: &[ToolDef],
+            tx: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            if self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) > 0 {
+                return StreamsDone.stream_complete(m, t, tx).await;
+            }
+            for (i, name) in ["read", "read", "bash", "read"].into_iter().enumerate() {
+                let call = ToolCall {
+                    id: format!("c{i}"),
+                    name: name.into(),
+                    args: serde_json::json!({ "id": format!("c{i}") }),
+                    thought_signature: None,
+                };
+                let _ = tx.send(StreamChunk::ToolCallReady { call }).await;
+            }
+            let _ = tx
+                .send(StreamChunk::Done {
+                    usage: TokenUsage::default(),
+                })
+                .await;
+            Ok(())
+        }
+    }
+
+    /// Run [`CallsMixed`] against `read` / `bash` probes; returns the probe
+    /// log, the ids of the tool results in history order, and the call ids
+    /// of the sub-turn checkpoints in the order they were written.
+    async fn run_mixed(parallel_tools: bool) -> (Vec<String>, Vec<String>, Vec<String>) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("krabs.db");
+        let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut registry = ToolRegistry::new();
+        for (name, parallel_safe) in [("read", true), ("bash", false)] {
+            registry.register(Arc::new(Probe {
+                name,
+                parallel_safe,
+                log: Arc::clone(&log),
+            }));
+        }
+        let config = KrabsConfig {
+            parallel_tools,
+            ..config(db.clone(), true)
+        };
+        let agent = KrabsAgentBuilder::new(config, CallsMixed::default())
+            .registry(registry)
+            .build_async()
+            .await;
+        let session_id = agent.session_id().unwrap().to_string();
+
+        let (mut stream, done) = agent
+            .run_streaming_with_history(
+                vec![Message::user("look around")],
+                None,
+                CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        while stream.recv().await.is_some() {}
+        let (_, messages, _) = done.await.unwrap().unwrap();
+        let results = messages
+            .iter()
+            .filter(|m| matches!(m.role, Role::Tool))
+            .filter_map(|m| m.tool_call_id.clone())
+            .collect();
+
+        let pool = sqlx::SqlitePool::connect(&format!("sqlite://{}", db.display()))
+            .await
+            .unwrap();
+        let checkpoints = sqlx::query_scalar(
+            "SELECT subturn_call_id FROM checkpoints \
+             WHERE session_id = ? AND subturn_call_id IS NOT NULL ORDER BY id",
+        )
+        .bind(&session_id)
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+        let log = log.lock().unwrap().clone();
+        (log, results, checkpoints)
+    }
+
+    #[tokio::test]
+    async fn parallel_safe_calls_overlap_and_keep_their_order() {
+        let ids = ["c0", "c1", "c2", "c3"];
+
+        let (log, results, checkpoints) = run_mixed(true).await;
+        // Both leading reads start before either ends.
+        assert_eq!(log[..2], ["start c0", "start c1"], "{log:?}");
+        // bash runs alone, and the read after it waits for it.
+        assert_eq!(
+            log[4..],
+            ["start c2", "end c2", "start c3", "end c3"],
+            "{log:?}"
+        );
+        assert_eq!(results, ids);
+        assert_eq!(checkpoints, ids);
+
+        let (log, results, checkpoints) = run_mixed(false).await;
+        assert_eq!(
+            log,
+            [
+                "start c0", "end c0", "start c1", "end c1", "start c2", "end c2", "start c3",
+                "end c3",
+            ]
+        );
+        assert_eq!(results, ids);
+        assert_eq!(checkpoints, ids);
+    }
+
+    /// Streams a `sysinfo` call whose arguments are cut off, then answers.
+    #[derive(Default)]
+    struct TruncatedArgs {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LlmProvider for TruncatedArgs {
+        async fn complete(&self, m: &[Message], t: &[ToolDef]) -> Result<LlmResponse> {
+            Echo.complete(m, t).await
+        }
+
+        async fn stream_complete(
+            &self,
+            m: &[Message],
+            t: &[ToolDef],
+            tx: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            if self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) > 0 {
+                return StreamsDone.stream_complete(m, t, tx).await;
+            }
+            let mut buffer = crate::providers::provider::ToolCallBuffer::default();
+            buffer.push(0, Some("call-1"), Some("sysinfo"), Some(r#"{"env": ["#));
+            for chunk in buffer.finish_all() {
+                let _ = tx.send(chunk).await;
+            }
+            let _ = tx
+                .send(StreamChunk::Done {
+                    usage: TokenUsage::default(),
+                })
+                .await;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_tool_calls_get_an_error_result_and_are_not_run() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Arc::new(crate::tools::sysinfo::SysInfoTool::default()));
+        let recorder = Arc::new(Recorder::default());
+        let agent = KrabsAgentBuilder::new(KrabsConfig::default(), TruncatedArgs::default())
+            .registry(registry)
+            .observer(Arc::clone(&recorder) as Arc<dyn AgentObserver>)
+            .build();
+
+        let (mut stream, done) = agent
+            .run_streaming_with_history(vec![Message::user("env?")], None, CancellationToken::new())
+            .await
+            .unwrap();
+        let mut statuses = Vec::new();
+        while let Some(chunk) = stream.recv().await {
+            if let StreamChunk::Status { text } = chunk {
+                statuses.push(text);
+            }
+        }
+        let (_, messages, _) = done.await.unwrap().unwrap();
+        assert!(
+            statuses.iter().any(|s| s.contains("malformed arguments")),
+            "{statuses:?}"
+        );
+
+        let result = messages
+            .iter()
+            .find(|m| matches!(m.role, Role::Tool))
+            .unwrap();
+        assert!(
+            result.content.starts_with("Tool call not run"),
+            "{}",
+            result.content
+        );
+        // Refused before the permission check, so never offered to run.
+        let log = recorder.log.lock().unwrap().clone();
+        assert!(!log.iter().any(|l| l.starts_with("ask ")), "{log:?}");
+        assert_eq!(messages.last().map(|m| m.content.as_str()), Some("done"));
+    }
+
+    /// Never stops calling `sysinfo`.
+    struct NeverDone;
+
+    #[async_trait]
+    impl LlmProvider for NeverDone {
+        async fn complete(&self, _: &[Message], _: &[ToolDef]) -> Result<LlmResponse> {
+            let call = ToolCall {
+                id: "call-1".into(),
+                name: "sysinfo".into(),
+                args: serde_json::json!({}),
+                thought_signature: None,
+            };
+            Ok(LlmResponse::ToolCalls {
+                calls: vec![call],
+                usage: TokenUsage::default(),
+            })
+        }
+
+        async fn stream_complete(
+            &self,
+            _: &[Message],
+            _: &[ToolDef],
+            _: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn max_turns_ends_with_a_partial_result() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Arc::new(crate::tools::sysinfo::SysInfoTool::default()));
+        let config = KrabsConfig {
+            max_turns: 2,
+            ..KrabsConfig::default()
+        };
+        let agent = KrabsAgentBuilder::new(config, NeverDone)
+            .registry(registry)
+            .build();
+        let out = agent.run("loop").await.unwrap();
+        assert!(out.hit_max_turns);
+        assert_eq!(out.tool_calls_made, 2);
+        assert!(out
+            .result
+            .contains("max_turns limit (2) after 2 tool call(s) (sysinfo)"));
+
+        let out = KrabsAgentBuilder::new(KrabsConfig::default(), Echo)
+            .build()
+            .run("hello")
+            .await
+            .unwrap();
+        assert!(!out.hit_max_turns);
+    }
+
+    fn config(db_path: PathBuf, persist_sessions: bool) -> KrabsConfig {
+        KrabsConfig {
+            db_path,
+            persist_sessions,
+            ..KrabsConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn sessions_are_persisted_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("krabs.db");
+        let agent = KrabsAgentBuilder::new(config(db.clone(), true), Echo)
+            .build_async()
             .await;
-        Err(e)
+        let out = agent.run("hello").await.unwrap();
+
+        let id = agent.session_id().unwrap();
+        assert_eq!(out.session_id.as_deref(), Some(id));
+        let store = SessionStore::open(&db).await.unwrap();
+        assert!(store.session_message_count(id).await.unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn memory_tools_come_with_a_persisted_session() {
+        use serde_json::json;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("krabs.db");
+        let agent = KrabsAgentBuilder::new(config(db.clone(), true), Echo)
+            .build_async()
+            .await;
+        let write = agent.registry.get("memory_write").unwrap();
+        write
+            .call(
+                json!({ "key": "indent", "value": "Prefers tabs over spaces", "scope": "global" }),
+            )
+            .await
+            .unwrap();
+        write
+            .call(json!({ "key": "task", "value": "rename the crate", "scope": "session" }))
+            .await
+            .unwrap();
+
+        // A later session gets the global note in its prompt, not the other
+        // session's.
+        let next = KrabsAgentBuilder::new(config(db, true), Echo)
+            .build_async()
+            .await;
+        let sections = next.system_prompt_sections().await;
+        let memory = sections.iter().find(|s| s.name == "memory").unwrap();
+        assert!(memory.text.contains("- indent: Prefers tabs over spaces"));
+        assert!(!memory.text.contains("rename the crate"));
+        let read = next.registry.get("memory_read").unwrap();
+        let got = read.call(json!({ "key": "task" })).await.unwrap();
+        assert_eq!(got.content, "No note stored under 'task'.");
+
+        let unpersisted = KrabsAgentBuilder::new(config(dir.path().join("off.db"), false), Echo)
+            .build_async()
+            .await;
+        assert!(unpersisted.registry.get("memory_write").is_none());
+        assert!(unpersisted.registry.get("remember").is_none());
+    }
+
+    #[tokio::test]
+    async fn memory_tools_use_the_given_memory_store() {
+        use serde_json::json;
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteMemoryStore::open(&dir.path().join("notes.db"), GLOBAL_NAMESPACE)
+            .await
+            .unwrap();
+        let agent = KrabsAgentBuilder::new(config(dir.path().join("krabs.db"), true), Echo)
+            .memory_store(store.clone())
+            .build_async()
+            .await;
+        agent
+            .registry
+            .get("memory_write")
+            .unwrap()
+            .call(json!({ "key": "indent", "value": "tabs", "scope": "global" }))
+            .await
+            .unwrap();
+
+        assert_eq!(store.get("indent").await.unwrap().as_deref(), Some("tabs"));
+    }
+
+    /// Delegates to a planner when asked by "parent", then answers; every
+    /// call costs 100 input and 10 output tokens.
+    struct Delegates;
+
+    #[async_trait]
+    impl LlmProvider for Delegates {
+        async fn complete(&self, m: &[Message], _: &[ToolDef]) -> Result<LlmResponse> {
+            let usage = TokenUsage {
+                input_tokens: 100,
+                output_tokens: 10,
+                ..TokenUsage::default()
+            };
+            let asked_by_parent = m
+                .iter()
+                .any(|m| matches!(m.role, Role::User) && m.content.contains("parent"));
+            if asked_by_parent && !m.iter().any(|m| matches!(m.role, Role::Tool)) {
+                let call = ToolCall {
+                    id: "call-1".into(),
+                    name: "delegate".into(),
+                    args: serde_json::json!({ "profile": "planner", "task": "plan it" }),
+                    thought_signature: None,
+                };
+                return Ok(LlmResponse::ToolCalls {
+                    calls: vec![call],
+                    usage,
+                });
+            }
+            Ok(LlmResponse::Message {
+                content: "done".into(),
+                usage,
+            })
+        }
+
+        async fn stream_complete(
+            &self,
+            _: &[Message],
+            _: &[ToolDef],
+            _: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn parent_totals_include_subagent_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path().join("krabs.db"), true);
+        let provider: Arc<dyn LlmProvider> = Arc::new(Delegates);
+        let ledger = UsageLedger::new();
+        let mut registry = ToolRegistry::new();
+        registry.register(Arc::new(
+            crate::tools::delegate::DelegateTool::new(
+                config.clone(),
+                Arc::clone(&provider),
+                ToolRegistry::new(),
+                PermissionGuard::new(),
+            )
+            .with_usage(Some(ledger.clone())),
+        ));
+        let agent = KrabsAgentBuilder::new(config, provider)
+            .registry(registry)
+            .subagent_usage(ledger)
+            .build_async()
+            .await;
+        let out = agent.run("parent").await.unwrap();
+
+        // Two calls of its own plus the planner's one.
+        assert_eq!(agent.total_tokens(), (300, 30));
+        assert_eq!((out.input_tokens, out.output_tokens), (300, 30));
+        let rows = agent.session.as_ref().unwrap().token_usage().await.unwrap();
+        let labels: Vec<_> = rows.iter().map(|r| r.context.as_deref()).collect();
+        assert_eq!(labels, [None, Some("delegate:planner"), None]);
+    }
+
+    /// Redacts a password from the user message.
+    struct Redact;
+
+    #[async_trait]
+    impl crate::hooks::hook::Hook for Redact {
+        async fn on_event(&self, event: &HookEvent) -> Result<HookOutput> {
+            Ok(match event {
+                HookEvent::UserMessage { content } => {
+                    HookOutput::ReplaceUserMessage(content.replace("hunter2", "[redacted]"))
+                }
+                _ => HookOutput::Continue,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn user_message_hooks_rewrite_what_is_sent_and_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let agent = KrabsAgentBuilder::new(config(dir.path().join("krabs.db"), true), StreamsDone)
+            .hook(Arc::new(Redact))
+            .build_async()
+            .await;
+        let session = agent.session.clone().unwrap();
+        let history = vec![Message::user("my password is hunter2")];
+        let (mut stream, done) = agent
+            .run_streaming_with_history(history, None, CancellationToken::new())
+            .await
+            .unwrap();
+        while stream.recv().await.is_some() {}
+
+        let (_, messages, _) = done.await.unwrap().unwrap();
+        assert!(messages
+            .iter()
+            .any(|m| matches!(m.role, Role::User) && m.content == "my password is [redacted]"));
+        let stored = session.messages().await.unwrap();
+        let user = stored.iter().find(|m| m.role == "user").unwrap();
+        assert_eq!(user.content, "my password is [redacted]");
+    }
+
+    /// Returns far more output than the context window holds.
+    struct Huge;
+
+    #[async_trait]
+    impl crate::tools::tool::Tool for Huge {
+        fn name(&self) -> &str {
+            "huge"
+        }
+        fn description(&self) -> &str {
+            "Dumps a lot of text"
+        }
+        fn parameters(&self) -> Value {
+            serde_json::json!({ "type": "object" })
+        }
+        async fn call(&self, _: Value) -> Result<crate::tools::tool::ToolResult> {
+            Ok(crate::tools::tool::ToolResult::ok("x".repeat(200_000)))
+        }
+    }
+
+    /// Calls `huge` once, then answers; records the size of every request
+    /// and how often it was asked to count tokens.
+    #[derive(Default)]
+    struct CallsHuge {
+        requests: std::sync::Mutex<Vec<u32>>,
+        counts: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LlmProvider for CallsHuge {
+        async fn complete(&self, m: &[Message], t: &[ToolDef]) -> Result<LlmResponse> {
+            if let Ok(mut requests) = self.requests.lock() {
+                requests.push(crate::providers::tokens::estimate_tokens(m, t));
+            }
+            if !m.iter().any(|m| matches!(m.role, Role::Tool)) {
+                let call = ToolCall {
+                    id: "call-1".into(),
+                    name: "huge".into(),
+                    args: serde_json::json!({}),
+                    thought_signature: None,
+                };
+                return Ok(LlmResponse::ToolCalls {
+                    calls: vec![call],
+                    usage: TokenUsage::default(),
+                });
+            }
+            Echo.complete(m, t).await
+        }
+
+        async fn stream_complete(
+            &self,
+            _: &[Message],
+            _: &[ToolDef],
+            _: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        async fn count_tokens(&self, m: &[Message], t: &[ToolDef]) -> Result<u32> {
+            self.counts
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(crate::providers::tokens::estimate_tokens(m, t))
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_tool_results_are_cut_before_sending() {
+        let config = KrabsConfig {
+            max_context_tokens: 8_000,
+            context_reserve_tokens: 2_000,
+            max_tool_output_bytes: 0,
+            persist_sessions: false,
+            ..KrabsConfig::default()
+        };
+        let mut registry = ToolRegistry::new();
+        registry.register(Arc::new(Huge));
+        let provider = Arc::new(CallsHuge::default());
+        let agent = KrabsAgentBuilder::new(config, Arc::clone(&provider) as Arc<dyn LlmProvider>)
+            .registry(registry)
+            .build();
+        let out = agent.run("dump it").await.unwrap();
+        assert_eq!(out.result, "done");
+
+        let requests = provider.requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        // The 50k-token result was cut to fit the 6k budget.
+        assert!(requests[1] <= 6_000, "{requests:?}");
+        assert!(requests[1] > 1_000, "{requests:?}");
+        // The small first request was never counted remotely; the oversized
+        // one was counted once, however many passes it took to shrink.
+        assert_eq!(provider.counts.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    /// Like `Huge`, but opts out of output truncation.
+    struct Uncapped;
+
+    #[async_trait]
+    impl crate::tools::tool::Tool for Uncapped {
+        fn name(&self) -> &str {
+            "huge"
+        }
+        fn description(&self) -> &str {
+            "Dumps some text"
+        }
+        fn parameters(&self) -> Value {
+            serde_json::json!({ "type": "object" })
+        }
+        fn max_output_bytes(&self, _: usize) -> usize {
+            0
+        }
+        async fn call(&self, _: Value) -> Result<crate::tools::tool::ToolResult> {
+            Ok(crate::tools::tool::ToolResult::ok("x".repeat(20_000)))
+        }
+    }
+
+    #[tokio::test]
+    async fn tools_can_opt_out_of_output_truncation() {
+        let config = KrabsConfig {
+            max_tool_output_bytes: 1_000,
+            persist_sessions: false,
+            ..KrabsConfig::default()
+        };
+        let mut registry = ToolRegistry::new();
+        registry.register(Arc::new(Uncapped));
+        let provider = Arc::new(CallsHuge::default());
+        let agent = KrabsAgentBuilder::new(config, Arc::clone(&provider) as Arc<dyn LlmProvider>)
+            .registry(registry)
+            .build();
+        agent.run("dump it").await.unwrap();
+
+        let requests = provider.requests.lock().unwrap().clone();
+        // All 20k bytes (~5k tokens) were sent, not a 1k cut.
+        assert!(requests[1] > 4_000, "{requests:?}");
+    }
+
+    #[tokio::test]
+    async fn no_persist_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("krabs.db");
+        let agent = KrabsAgentBuilder::new(config(db.clone(), false), Echo)
+            .build_async()
+            .await;
+        let out = agent.run("hello").await.unwrap();
+
+        assert_eq!(out.result, "done");
+        assert!(agent.session_id().is_none());
+        assert!(out.session_id.is_none());
+        assert!(!db.exists());
+    }
+
+    #[tokio::test]
+    async fn system_prompt_is_its_sections_joined() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("system.md");
+        std::fs::write(&project, "Use tabs.\n").unwrap();
+        let config = KrabsConfig {
+            system_prompt_path: project,
+            ..KrabsConfig::default()
+        };
+        let agent = KrabsAgentBuilder::new(config.clone(), Echo)
+            .system_prompt("You review code.")
+            .build();
+
+        let sections = agent.system_prompt_sections().await;
+        let names: Vec<&str> = sections.iter().map(|s| s.name).collect();
+        assert_eq!(
+            names,
+            ["soul", "instructions", "agent prompt", "project prompt"]
+        );
+        assert_eq!(sections[3].text, "Use tabs.");
+        let prompt = agent.current_system_prompt().await;
+        assert!(prompt.ends_with("You review code.\n\nUse tabs."));
+
+        // A replacing project prompt drops the agent's own.
+        let agent = KrabsAgentBuilder::new(
+            KrabsConfig {
+                system_prompt_replace: true,
+                ..config
+            },
+            Echo,
+        )
+        .system_prompt("You review code.")
+        .build();
+        let sections = agent.system_prompt_sections().await;
+        assert!(sections.iter().all(|s| s.name != "agent prompt"));
     }
 }
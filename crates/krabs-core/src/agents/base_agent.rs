@@ -38,6 +38,20 @@ impl BaseAgent {
         }
     }
 
+    /// Returns a one-line summary of this agent profile.
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Planner => "turns an objective into a sequenced, executable plan",
+            Self::FrontendDeveloper => "builds fast, accessible, maintainable user interfaces",
+            Self::Explorer => "read-only reconnaissance of a folder, module or concept",
+        }
+    }
+
+    /// Looks up a built-in agent profile by its canonical name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::all().iter().copied().find(|a| a.name() == name)
+    }
+
     /// Returns all built-in agent profiles.
     pub fn all() -> &'static [Self] {
         &[Self::Planner, Self::FrontendDeveloper, Self::Explorer]
@@ -0,0 +1,331 @@
+use crate::providers::provider::{LlmProvider, LlmResponse, Message, Role};
+use anyhow::Result;
+use std::collections::HashSet;
+
+/// Prefix of the user message that replaces a compacted history.
+pub const SUMMARY_PREFIX: &str = "[Summary of the conversation so far]";
+
+/// Histories with fewer non-system messages than this are not worth compacting.
+const MIN_MESSAGES: usize = 6;
+
+/// Per-message cap on text sent to the summarizer, so a huge tool output
+/// can't blow the summarizer's own context.
+const MAX_CHARS_PER_MESSAGE: usize = 4_000;
+
+const SUMMARY_PROMPT: &str = "You compress a conversation between a user and a coding \
+assistant into a summary the assistant can continue from. Keep the user's goals, \
+decisions made, files and commands involved, open questions and the next planned step. \
+Drop pleasantries and raw tool output that no longer matters. Reply with the summary only.";
+
+/// Result of a successful [`compact`].
+#[derive(Debug, Clone)]
+pub struct Compaction {
+    /// The new history: the original system message (if any), the summary,
+    /// then the kept tool results with the calls that produced them.
+    pub messages: Vec<Message>,
+    pub summary: String,
+    /// How many trailing messages of `messages` were carried over verbatim.
+    pub kept: usize,
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+}
+
+impl Compaction {
+    /// The messages carried over verbatim after the summary.
+    pub fn kept_messages(&self) -> &[Message] {
+        &self.messages[self.messages.len() - self.kept..]
+    }
+
+    /// Everything after the summary: developer messages, then the kept ones.
+    pub fn carried_messages(&self) -> &[Message] {
+        let summary = self
+            .messages
+            .iter()
+            .position(|m| !matches!(m.role, Role::System))
+            .unwrap_or(self.messages.len());
+        self.messages.get(summary + 1..).unwrap_or_default()
+    }
+
+    pub fn tokens_reclaimed(&self) -> usize {
+        self.tokens_before.saturating_sub(self.tokens_after)
+    }
+}
+
+/// Rough token estimate (~4 bytes per token) for content and tool-call args.
+pub fn estimate_tokens(messages: &[Message]) -> usize {
+    let bytes: usize = messages
+        .iter()
+        .map(|m| {
+            let args: usize = m
+                .tool_calls
+                .iter()
+                .flatten()
+                .map(|c| c.name.len() + c.args.to_string().len())
+                .sum();
+            m.content.len() + args
+        })
+        .sum();
+    bytes / 4
+}
+
+/// The user message carrying `summary` into the compacted history.
+pub fn summary_message(summary: &str) -> Message {
+    Message::user(format!("{SUMMARY_PREFIX}\n\n{summary}"))
+}
+
+/// Pick the latest `keep` tool results that can be carried over verbatim,
+/// and the assistant messages that requested them, trimmed to those calls.
+///
+/// Returns the kept messages in history order and the indices of the kept
+/// tool results (which the summarizer then skips). A tool result whose call
+/// is no longer in the history is summarized instead.
+fn kept_tool_results(messages: &[Message], keep: usize) -> (Vec<Message>, HashSet<usize>) {
+    let mut results = HashSet::new();
+    let mut owners = HashSet::new();
+    let mut ids = HashSet::new();
+    for (i, m) in messages.iter().enumerate().rev() {
+        if results.len() == keep {
+            break;
+        }
+        let Some(id) = m
+            .tool_call_id
+            .as_deref()
+            .filter(|_| matches!(m.role, Role::Tool))
+        else {
+            continue;
+        };
+        let owner = messages[..i].iter().rposition(|a| {
+            matches!(a.role, Role::Assistant) && a.tool_calls.iter().flatten().any(|c| c.id == id)
+        });
+        if let Some(owner) = owner {
+            results.insert(i);
+            owners.insert(owner);
+            ids.insert(id);
+        }
+    }
+
+    let kept = messages
+        .iter()
+        .enumerate()
+        .filter_map(|(i, m)| {
+            if results.contains(&i) {
+                return Some(m.clone());
+            }
+            if !owners.contains(&i) {
+                return None;
+            }
+            let mut owner = m.clone();
+            if let Some(calls) = owner.tool_calls.as_mut() {
+                calls.retain(|c| ids.contains(c.id.as_str()));
+            }
+            Some(owner)
+        })
+        .collect();
+    (kept, results)
+}
+
+/// Render the non-system part of `messages` as a plain transcript for the
+/// summarizer, leaving out the messages at `skip`.
+fn transcript(messages: &[Message], skip: &HashSet<usize>) -> String {
+    let mut out = String::new();
+    for (i, m) in messages.iter().enumerate() {
+        if skip.contains(&i) {
+            continue;
+        }
+        let line = match m.role {
+            Role::System | Role::Developer => continue,
+            Role::User => format!("User: {}", m.content),
+            Role::Assistant => match &m.tool_calls {
+                Some(calls) if !calls.is_empty() => calls
+                    .iter()
+                    .map(|c| format!("Assistant called {}({})", c.name, c.args))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                _ => format!("Assistant: {}", m.content),
+            },
+            Role::Tool => format!(
+                "Tool {} returned: {}",
+                m.tool_name.as_deref().unwrap_or("?"),
+                m.content
+            ),
+        };
+        let mut end = line.len().min(MAX_CHARS_PER_MESSAGE);
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        out.push_str(&line[..end]);
+        if end < line.len() {
+            out.push_str(" […]");
+        }
+        out.push_str("\n\n");
+    }
+    out
+}
+
+/// Summarize `messages` with `provider` and build the compacted history.
+///
+/// The latest `keep_tool_results` tool results (e.g. a file the model just
+/// read) are kept verbatim after the summary, together with the calls that
+/// produced them; prose and older tool results are folded into the summary.
+///
+/// Returns `Ok(None)` when the conversation is too short to be worth it.
+pub async fn compact(
+    provider: &dyn LlmProvider,
+    messages: &[Message],
+    keep_tool_results: usize,
+) -> Result<Option<Compaction>> {
+    let convo = messages
+        .iter()
+        .filter(|m| !matches!(m.role, Role::System | Role::Developer))
+        .count();
+    if convo < MIN_MESSAGES {
+        return Ok(None);
+    }
+
+    let (kept, skip) = kept_tool_results(messages, keep_tool_results);
+    let request = [
+        Message::system(SUMMARY_PROMPT),
+        Message::user(transcript(messages, &skip)),
+    ];
+    let summary = match provider.complete(&request, &[]).await? {
+        LlmResponse::Message { content, .. } => content.trim().to_string(),
+        LlmResponse::ToolCalls { .. } => anyhow::bail!("summarizer replied with tool calls"),
+    };
+    if summary.is_empty() {
+        anyhow::bail!("summarizer returned an empty summary");
+    }
+
+    let mut compacted: Vec<Message> = messages
+        .first()
+        .filter(|m| matches!(m.role, Role::System))
+        .cloned()
+        .into_iter()
+        .collect();
+    compacted.push(summary_message(&summary));
+    // Developer instructions are standing orders, not conversation: keep them.
+    compacted.extend(
+        messages
+            .iter()
+            .filter(|m| matches!(m.role, Role::Developer))
+            .cloned(),
+    );
+    let kept_len = kept.len();
+    compacted.extend(kept);
+
+    Ok(Some(Compaction {
+        tokens_before: estimate_tokens(messages),
+        tokens_after: estimate_tokens(&compacted),
+        messages: compacted,
+        summary,
+        kept: kept_len,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::provider::{StreamChunk, TokenUsage, ToolCall};
+    use crate::tools::tool::ToolDef;
+    use async_trait::async_trait;
+    use tokio::sync::mpsc;
+
+    struct FixedSummary;
+
+    #[async_trait]
+    impl LlmProvider for FixedSummary {
+        async fn complete(&self, _: &[Message], _: &[ToolDef]) -> Result<LlmResponse> {
+            Ok(LlmResponse::Message {
+                content: "User is refactoring the parser.".into(),
+                usage: TokenUsage::default(),
+            })
+        }
+
+        async fn stream_complete(
+            &self,
+            _: &[Message],
+            _: &[ToolDef],
+            _: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn long_history() -> Vec<Message> {
+        let mut msgs = vec![Message::system("you are krabs")];
+        for i in 0..4 {
+            msgs.push(Message::user(format!("step {i}: {}", "x".repeat(400))));
+            msgs.push(Message::assistant(format!("done {i}: {}", "y".repeat(400))));
+        }
+        msgs
+    }
+
+    #[tokio::test]
+    async fn short_conversations_are_left_alone() {
+        let msgs = vec![Message::user("hi"), Message::assistant("hello")];
+        assert!(compact(&FixedSummary, &msgs, 2).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn compaction_keeps_system_and_summary() {
+        let c = compact(&FixedSummary, &long_history(), 2)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(c.messages.len(), 2);
+        assert!(matches!(c.messages[0].role, Role::System));
+        assert!(c.messages[1].content.starts_with(SUMMARY_PREFIX));
+        assert!(c.messages[1].content.ends_with("refactoring the parser."));
+        assert!(c.tokens_reclaimed() > 0);
+    }
+
+    fn read_call(id: &str, path: &str) -> Message {
+        Message::assistant_tool_calls(vec![ToolCall {
+            id: id.into(),
+            name: "read".into(),
+            args: serde_json::json!({ "path": path }),
+            thought_signature: None,
+        }])
+    }
+
+    #[tokio::test]
+    async fn latest_tool_result_survives_compaction() {
+        let mut msgs = long_history();
+        msgs.push(read_call("old", "a.rs"));
+        msgs.push(Message::tool_result("OLD FILE BODY", "old", "read"));
+        msgs.push(Message::assistant("a.rs looks fine, checking b.rs"));
+        msgs.push(read_call("new", "b.rs"));
+        msgs.push(Message::tool_result("NEW FILE BODY", "new", "read"));
+
+        let c = compact(&FixedSummary, &msgs, 1).await.unwrap().unwrap();
+        let kept = c.kept_messages();
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].tool_calls.as_ref().unwrap()[0].id, "new");
+        assert_eq!(kept[1].content, "NEW FILE BODY");
+        assert!(c.messages.iter().all(|m| m.content != "OLD FILE BODY"));
+
+        // The old result goes to the summarizer; the kept one doesn't.
+        let (_, skip) = kept_tool_results(&msgs, 1);
+        let t = transcript(&msgs, &skip);
+        assert!(t.contains("OLD FILE BODY"));
+        assert!(!t.contains("NEW FILE BODY"));
+    }
+
+    #[tokio::test]
+    async fn developer_messages_are_carried_over() {
+        let mut msgs = long_history();
+        msgs.insert(1, Message::developer("answer in French"));
+
+        let c = compact(&FixedSummary, &msgs, 0).await.unwrap().unwrap();
+        let carried = c.carried_messages();
+        assert_eq!(carried.len(), 1);
+        assert!(matches!(carried[0].role, Role::Developer));
+        assert!(!transcript(&msgs, &HashSet::new()).contains("French"));
+    }
+
+    #[test]
+    fn transcript_caps_long_messages() {
+        let t = transcript(&[Message::user("z".repeat(10_000))], &HashSet::new());
+        assert!(t.len() < 4_100);
+        assert!(t.contains("[…]"));
+    }
+}
@@ -1,4 +1,5 @@
-use crate::providers::provider::Message;
+use super::agent::is_max_turns_note;
+use crate::providers::provider::{Message, Role};
 use crate::session::session::SubturnResume;
 
 /// Snapshot passed to the agent for one turn.
@@ -7,6 +8,21 @@ pub struct TurnInput {
     pub subturn_resume: Option<SubturnResume>,
 }
 
+/// How the latest turn ended, judged from the history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TurnEnd {
+    /// There is no turn yet.
+    Empty,
+    /// The model gave its final reply.
+    Answered,
+    /// The turn stopped before the model said anything: its user message is
+    /// the last one.
+    Unanswered,
+    /// The turn stopped part-way: cancelled after some tool calls, or at
+    /// `max_turns`.
+    Unfinished,
+}
+
 /// Persistent state of a multi-turn conversation.
 ///
 /// Both CLI and server previously re-implemented this pattern: a typed
@@ -55,6 +71,62 @@ impl ConversationContext {
         self.turn_count += 1;
     }
 
+    /// Replace the whole history, e.g. with a compacted one. Any pending
+    /// sub-turn resume refers to the old history and is dropped.
+    pub fn replace_messages(&mut self, messages: Vec<Message>) {
+        self.messages = messages;
+        self.subturn_resume = None;
+    }
+
+    /// Drop the latest user message and everything after it, returning the
+    /// message's text so the turn can be run again. `None` if there is no
+    /// user message.
+    pub fn pop_turn(&mut self) -> Option<String> {
+        let idx = self
+            .messages
+            .iter()
+            .rposition(|m| matches!(m.role, Role::User))?;
+        self.subturn_resume = None;
+        self.messages.drain(idx..).next().map(|m| m.content)
+    }
+
+    /// How the latest turn ended.
+    pub fn last_turn_end(&self) -> TurnEnd {
+        match self
+            .messages
+            .iter()
+            .rev()
+            .find(|m| !matches!(m.role, Role::System))
+        {
+            None => TurnEnd::Empty,
+            Some(m) if matches!(m.role, Role::User) => TurnEnd::Unanswered,
+            Some(m)
+                if matches!(m.role, Role::Assistant)
+                    && m.tool_calls.is_none()
+                    && !is_max_turns_note(m) =>
+            {
+                TurnEnd::Answered
+            }
+            Some(_) => TurnEnd::Unfinished,
+        }
+    }
+
+    /// Snapshot for carrying on an [`Unfinished`](TurnEnd::Unfinished) turn
+    /// without a new user message. The note a run leaves at `max_turns` is
+    /// dropped first, so the model picks up from the tool results before it;
+    /// the returned flag says whether there was one.
+    pub fn continue_turn(&mut self) -> (TurnInput, bool) {
+        let dropped_note = self.messages.last().is_some_and(is_max_turns_note);
+        if dropped_note {
+            self.messages.pop();
+        }
+        let input = TurnInput {
+            messages: self.messages.clone(),
+            subturn_resume: self.subturn_resume.take(),
+        };
+        (input, dropped_note)
+    }
+
     /// Read-only access to current messages.
     pub fn messages(&self) -> &[Message] {
         &self.messages
@@ -71,3 +143,44 @@ impl Default for ConversationContext {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::provider::ToolCall;
+
+    #[test]
+    fn turns_that_stopped_early_can_be_continued() {
+        let mut ctx = ConversationContext::new();
+        assert_eq!(ctx.last_turn_end(), TurnEnd::Empty);
+        ctx.begin_turn("refactor it");
+        assert_eq!(ctx.last_turn_end(), TurnEnd::Unanswered);
+
+        let call = ToolCall {
+            id: "c1".into(),
+            name: "bash".into(),
+            args: serde_json::json!({}),
+            thought_signature: None,
+        };
+        let mut messages = ctx.messages().to_vec();
+        messages.push(Message::assistant_tool_calls(vec![call]));
+        messages.push(Message::tool_result("ok", "c1", "bash"));
+        messages.push(Message::assistant(
+            "[Stopped at the max_turns limit (1) before finishing. Ask to continue to pick up from here.]",
+        ));
+        ctx.complete_turn(messages);
+        assert_eq!(ctx.last_turn_end(), TurnEnd::Unfinished);
+
+        let (input, dropped_note) = ctx.continue_turn();
+        assert!(dropped_note);
+        assert!(matches!(
+            input.messages.last().map(|m| &m.role),
+            Some(Role::Tool)
+        ));
+
+        let mut messages = input.messages;
+        messages.push(Message::assistant("done"));
+        ctx.complete_turn(messages);
+        assert_eq!(ctx.last_turn_end(), TurnEnd::Answered);
+    }
+}
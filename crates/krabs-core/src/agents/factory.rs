@@ -7,6 +7,7 @@ use crate::tools::registry::ToolRegistry;
 use crate::tools::tool::Tool;
 
 use super::agent::KrabsAgentBuilder;
+use super::minikrabs::UsageLedger;
 
 /// Session wiring options for a single turn's agent.
 pub enum SessionOpts {
@@ -31,6 +32,7 @@ pub struct AgentFactory {
     provider: Arc<dyn LlmProvider>,
     base_registry: ToolRegistry,
     system_prompt: String,
+    subagent_usage: Option<UsageLedger>,
 }
 
 impl AgentFactory {
@@ -44,6 +46,7 @@ impl AgentFactory {
             provider,
             base_registry: registry,
             system_prompt: String::new(),
+            subagent_usage: None,
         }
     }
 
@@ -52,6 +55,13 @@ impl AgentFactory {
         self
     }
 
+    /// Count the usage sub-agents report to `ledger` in each turn's agent,
+    /// e.g. the ledger returned by [`ToolRegistry::with_orchestration`].
+    pub fn with_subagent_usage(mut self, ledger: UsageLedger) -> Self {
+        self.subagent_usage = Some(ledger);
+        self
+    }
+
     pub fn config(&self) -> &KrabsConfig {
         &self.config
     }
@@ -87,6 +97,9 @@ impl AgentFactory {
         if !self.system_prompt.is_empty() {
             builder = builder.system_prompt(&self.system_prompt);
         }
+        if let Some(ledger) = &self.subagent_usage {
+            builder = builder.subagent_usage(ledger.clone());
+        }
 
         builder = match session_opts {
             SessionOpts::New { session_id } => builder.session_id(session_id),
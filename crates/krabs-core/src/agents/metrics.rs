@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How much one tool was used and how it fared.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolStats {
+    pub tool_name: String,
+    pub calls: usize,
+    /// Calls whose final result was an error, after any retries.
+    pub errors: usize,
+    /// Extra attempts made after a failed one.
+    pub retries: usize,
+    /// Time spent in the tool, retries and backoff included.
+    pub total_ms: u64,
+}
+
+impl ToolStats {
+    pub fn avg_ms(&self) -> u64 {
+        if self.calls == 0 {
+            0
+        } else {
+            self.total_ms / self.calls as u64
+        }
+    }
+
+    /// Sort most-called first, then by name.
+    pub fn sort(stats: &mut [ToolStats]) {
+        stats.sort_by(|a, b| {
+            b.calls
+                .cmp(&a.calls)
+                .then_with(|| a.tool_name.cmp(&b.tool_name))
+        });
+    }
+}
+
+/// Per-tool [`ToolStats`] of one agent, kept in memory for as long as it
+/// lives. The session keeps its own copy in SQLite.
+#[derive(Debug, Default)]
+pub struct ToolMetrics {
+    tools: Mutex<HashMap<String, ToolStats>>,
+}
+
+impl ToolMetrics {
+    pub fn record(&self, tool_name: &str, is_error: bool, retries: usize, elapsed: Duration) {
+        let mut tools = self.tools.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = tools
+            .entry(tool_name.to_string())
+            .or_insert_with(|| ToolStats {
+                tool_name: tool_name.to_string(),
+                ..ToolStats::default()
+            });
+        stats.calls += 1;
+        stats.errors += usize::from(is_error);
+        stats.retries += retries;
+        stats.total_ms += elapsed.as_millis() as u64;
+    }
+
+    /// Every tool called so far, most-called first.
+    pub fn snapshot(&self) -> Vec<ToolStats> {
+        let tools = self.tools.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stats: Vec<ToolStats> = tools.values().cloned().collect();
+        ToolStats::sort(&mut stats);
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_accumulate_per_tool() {
+        let metrics = ToolMetrics::default();
+        metrics.record("read", false, 0, Duration::from_millis(10));
+        metrics.record("bash", true, 2, Duration::from_millis(300));
+        metrics.record("read", false, 0, Duration::from_millis(30));
+
+        let stats = metrics.snapshot();
+        assert_eq!(stats[0].tool_name, "read");
+        assert_eq!((stats[0].calls, stats[0].errors), (2, 0));
+        assert_eq!(stats[0].avg_ms(), 20);
+        assert_eq!(
+            (stats[1].calls, stats[1].errors, stats[1].retries),
+            (1, 1, 2)
+        );
+    }
+}
@@ -1,8 +1,205 @@
 use super::agent::{Agent, AgentOutput, KrabsAgent};
+use crate::hooks::hook::{Hook, HookEvent, HookOutput};
+use crate::providers::provider::TokenUsage;
 use anyhow::Result;
-use std::sync::Arc;
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
 use tokio::task::JoinHandle;
 
+/// Remaining tokens shared by every sub-agent spawned from one parent run.
+pub type TokenBudget = Arc<AtomicU32>;
+
+/// Build a shared budget from `delegate_token_budget`. `None` when the limit is 0 (unlimited).
+pub fn token_budget(limit: u32) -> Option<TokenBudget> {
+    (limit > 0).then(|| Arc::new(AtomicU32::new(limit)))
+}
+
+/// Deduct `tokens` from the budget, saturating at zero. Returns what is left.
+pub fn charge_budget(budget: &AtomicU32, tokens: u32) -> u32 {
+    let prev = budget
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+            Some(left.saturating_sub(tokens))
+        })
+        .unwrap_or_else(|left| left);
+    prev.saturating_sub(tokens)
+}
+
+/// `true` once a budget has been fully consumed.
+pub fn budget_exhausted(budget: Option<&TokenBudget>) -> bool {
+    budget.is_some_and(|b| b.load(Ordering::Relaxed) == 0)
+}
+
+/// Token usage reported by sub-agents, waiting for the parent to count it.
+///
+/// Clones share the same entries. Build sub-agents with
+/// [`KrabsAgentBuilder::report_usage`](super::KrabsAgentBuilder::report_usage)
+/// and the parent with
+/// [`KrabsAgentBuilder::subagent_usage`](super::KrabsAgentBuilder::subagent_usage)
+/// on the same ledger; the parent drains it after every tool batch, so its
+/// `total_tokens()` includes what its sub-agents spent.
+#[derive(Debug, Clone, Default)]
+pub struct UsageLedger {
+    entries: Arc<Mutex<Vec<(String, TokenUsage)>>>,
+}
+
+impl UsageLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `usage` of one LLM call under `label` (e.g. `delegate:planner`).
+    pub fn record(&self, label: &str, usage: &TokenUsage) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.push((label.to_string(), usage.clone()));
+        }
+    }
+
+    /// Take everything recorded so far, oldest first.
+    pub fn drain(&self) -> Vec<(String, TokenUsage)> {
+        self.entries
+            .lock()
+            .map(|mut entries| std::mem::take(&mut *entries))
+            .unwrap_or_default()
+    }
+}
+
+/// Caps how many sub-agents run at once. Clones share the same slots, so one
+/// limit handed to several spawners and `dispatch` tools bounds them together.
+#[derive(Debug, Clone)]
+pub struct SubAgentLimit {
+    slots: Arc<Semaphore>,
+    max: usize,
+    queued: Arc<AtomicUsize>,
+}
+
+/// Build a shared limit from `max_concurrent_subagents`. `None` when the limit is 0 (unlimited).
+pub fn subagent_limit(max: usize) -> Option<SubAgentLimit> {
+    (max > 0).then(|| SubAgentLimit {
+        slots: Arc::new(Semaphore::new(max)),
+        max,
+        queued: Arc::new(AtomicUsize::new(0)),
+    })
+}
+
+impl SubAgentLimit {
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Wait for a free slot; the sub-agent runs while the permit is held.
+    /// When every slot is taken, `on_queued` is first called with the number
+    /// of sub-agents now waiting, this one included.
+    pub async fn acquire(&self, on_queued: impl FnOnce(usize)) -> Result<OwnedSemaphorePermit> {
+        if let Ok(permit) = Arc::clone(&self.slots).try_acquire_owned() {
+            return Ok(permit);
+        }
+        let waiting = self.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        on_queued(waiting);
+        let permit = Arc::clone(&self.slots).acquire_owned().await;
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        Ok(permit?)
+    }
+}
+
+/// Status line for a sub-agent waiting on a full [`SubAgentLimit`].
+pub fn queued_text(waiting: usize, limit: &SubAgentLimit) -> String {
+    format!(
+        "queued — {waiting} sub-agent(s) waiting for one of {} slot(s)",
+        limit.max()
+    )
+}
+
+/// Wait for a slot in `limit`, if any, reporting a queue wait to `progress`.
+pub(crate) async fn acquire_slot(
+    limit: Option<&SubAgentLimit>,
+    progress: Option<(&str, &ProgressSink)>,
+) -> Result<Option<OwnedSemaphorePermit>> {
+    let Some(limit) = limit else {
+        return Ok(None);
+    };
+    let permit = limit
+        .acquire(|waiting| {
+            let text = queued_text(waiting, limit);
+            match progress {
+                Some((agent, sink)) => report(sink, agent, text),
+                None => tracing::debug!("{text}"),
+            }
+        })
+        .await?;
+    Ok(Some(permit))
+}
+
+/// One line of activity from a running sub-agent, labelled with its name.
+#[derive(Debug, Clone)]
+pub struct SubAgentProgress {
+    pub agent: String,
+    pub text: String,
+}
+
+/// Where sub-agents report progress. Sends never block: when the receiver is
+/// slow or gone, lines are dropped rather than stalling the sub-agent.
+pub type ProgressSink = mpsc::Sender<SubAgentProgress>;
+
+/// Tool arguments are cut to this many chars in progress lines.
+const PROGRESS_ARGS_CHARS: usize = 80;
+
+fn report(sink: &ProgressSink, agent: &str, text: String) {
+    let _ = sink.try_send(SubAgentProgress {
+        agent: agent.to_string(),
+        text,
+    });
+}
+
+fn clip(s: &str, max: usize) -> String {
+    match s.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &s[..end]),
+        None => s.to_string(),
+    }
+}
+
+/// Hook that forwards a sub-agent's tool calls to a [`ProgressSink`].
+/// Register it on the sub-agent's builder so the parent can show what the
+/// sub-agent is doing while it runs.
+pub struct ProgressHook {
+    agent: String,
+    sink: ProgressSink,
+}
+
+impl ProgressHook {
+    pub fn new(agent: impl Into<String>, sink: ProgressSink) -> Self {
+        Self {
+            agent: agent.into(),
+            sink,
+        }
+    }
+}
+
+#[async_trait]
+impl Hook for ProgressHook {
+    async fn on_event(&self, event: &HookEvent) -> Result<HookOutput> {
+        let text = match event {
+            HookEvent::PreToolUse {
+                tool_name, args, ..
+            } => format!(
+                "→ {tool_name} {}",
+                clip(&args.to_string(), PROGRESS_ARGS_CHARS)
+            ),
+            HookEvent::PostToolUseFailure {
+                tool_name, error, ..
+            } => {
+                let first = error.lines().next().unwrap_or_default();
+                format!("✗ {tool_name}: {}", clip(first, PROGRESS_ARGS_CHARS))
+            }
+            HookEvent::ContextThreshold { pct, .. } => format!("context {pct}% full"),
+            _ => return Ok(HookOutput::Continue),
+        };
+        report(&self.sink, &self.agent, text);
+        Ok(HookOutput::Continue)
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum SpawnMode {
     Process,
@@ -30,14 +227,47 @@ impl MiniKrabsHandle {
 pub struct MiniKrabsSpawner {
     // Arc is justified here: the agent is shared across multiple spawned tasks.
     agent: Arc<KrabsAgent>,
+    budget: Option<TokenBudget>,
+    limit: Option<SubAgentLimit>,
+    progress: Option<(String, ProgressSink)>,
 }
 
 impl MiniKrabsSpawner {
     pub fn new(agent: Arc<KrabsAgent>) -> Self {
-        Self { agent }
+        Self {
+            agent,
+            budget: None,
+            limit: None,
+            progress: None,
+        }
+    }
+
+    /// Run at most `limit.max()` spawned tasks at once; the rest queue until a
+    /// slot frees up.
+    pub fn with_limit(mut self, limit: SubAgentLimit) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Refuse to spawn once `budget` is exhausted. The agent itself should be
+    /// built with the same budget so it is charged as it consumes tokens.
+    pub fn with_budget(mut self, budget: TokenBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Report each spawned task's start and finish to `sink`, labelled `name`.
+    /// To also see its tool calls, build the agent with a [`ProgressHook`]
+    /// sharing the same sink.
+    pub fn with_progress(mut self, name: impl Into<String>, sink: ProgressSink) -> Self {
+        self.progress = Some((name.into(), sink));
+        self
     }
 
     pub async fn spawn(&self, task: &str, mode: SpawnMode) -> Result<MiniKrabsHandle> {
+        if budget_exhausted(self.budget.as_ref()) {
+            anyhow::bail!("sub-agent token budget exhausted — not spawning: {task}");
+        }
         match mode {
             SpawnMode::Process => match self.try_spawn_process(task).await {
                 Ok(handle) => Ok(handle),
@@ -64,7 +294,11 @@ impl MiniKrabsSpawner {
         });
         tokio::fs::write(&tmp, serde_json::to_string(&task_json)?).await?;
 
+        let limit = self.limit.clone();
+        let progress = self.progress.clone();
         let handle: JoinHandle<Result<AgentOutput>> = tokio::spawn(async move {
+            let progress = progress.as_ref().map(|(name, sink)| (name.as_str(), sink));
+            let _slot = acquire_slot(limit.as_ref(), progress).await?;
             let output = tokio::process::Command::new(&binary)
                 .arg("--task-json")
                 .arg(&tmp)
@@ -93,7 +327,28 @@ impl MiniKrabsSpawner {
     fn spawn_task(&self, task: &str) -> Result<MiniKrabsHandle> {
         let agent = Arc::clone(&self.agent);
         let task = task.to_string();
-        let handle = tokio::spawn(async move { agent.run(&task).await });
+        let progress = self.progress.clone();
+        let limit = self.limit.clone();
+        let handle = tokio::spawn(async move {
+            let sink = progress.as_ref().map(|(name, sink)| (name.as_str(), sink));
+            let _slot = acquire_slot(limit.as_ref(), sink).await?;
+            if let Some((name, sink)) = &progress {
+                report(
+                    sink,
+                    name,
+                    format!("started: {}", clip(&task, PROGRESS_ARGS_CHARS)),
+                );
+            }
+            let output = agent.run(&task).await;
+            if let Some((name, sink)) = &progress {
+                let text = match &output {
+                    Ok(out) => format!("done ({} tool call(s))", out.tool_calls_made),
+                    Err(e) => format!("failed: {e}"),
+                };
+                report(sink, name, text);
+            }
+            output
+        });
         Ok(MiniKrabsHandle {
             inner: HandleInner::Task(handle),
         })
@@ -133,3 +388,59 @@ fn which_krabs_cli() -> Result<std::path::PathBuf> {
     }
     anyhow::bail!("krabs-cli not found in PATH")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_limit_means_unlimited() {
+        assert!(token_budget(0).is_none());
+        assert!(!budget_exhausted(None));
+    }
+
+    #[test]
+    fn charge_saturates_at_zero() {
+        let budget = token_budget(100).unwrap();
+        assert_eq!(charge_budget(&budget, 40), 60);
+        assert!(!budget_exhausted(Some(&budget)));
+        assert_eq!(charge_budget(&budget, 500), 0);
+        assert!(budget_exhausted(Some(&budget)));
+    }
+
+    #[tokio::test]
+    async fn progress_hook_reports_tool_calls() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let hook = ProgressHook::new("planner", tx);
+        hook.on_event(&HookEvent::TurnStart { turn: 0 })
+            .await
+            .unwrap();
+        hook.on_event(&HookEvent::PreToolUse {
+            tool_name: "read".to_string(),
+            args: serde_json::json!({"path": "a.rs"}),
+            tool_use_id: "1".to_string(),
+        })
+        .await
+        .unwrap();
+        hook.on_event(&HookEvent::PostToolUseFailure {
+            tool_name: "read".to_string(),
+            args: serde_json::json!({}),
+            error: "not found\nmore".to_string(),
+            tool_use_id: "1".to_string(),
+        })
+        .await
+        .unwrap();
+
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.agent, "planner");
+        assert_eq!(first.text, r#"→ read {"path":"a.rs"}"#);
+        assert_eq!(rx.recv().await.unwrap().text, "✗ read: not found");
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn clip_marks_truncation() {
+        assert_eq!(clip("abc", 5), "abc");
+        assert_eq!(clip("abcdef", 3), "abc…");
+    }
+}
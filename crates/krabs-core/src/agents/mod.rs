@@ -1,15 +1,25 @@
 pub mod agent;
 pub mod base_agent;
+pub mod compact;
 pub mod context;
 pub mod factory;
+pub mod metrics;
 pub mod minikrabs;
+pub mod observer;
 pub mod persona;
 pub mod pool;
+pub mod structured;
 
 pub use crate::session::{ResumeState, SubturnResume};
 pub use agent::{Agent, AgentOutput, KrabsAgent, KrabsAgentBuilder};
 pub use base_agent::BaseAgent;
-pub use context::{ConversationContext, TurnInput};
+pub use compact::Compaction;
+pub use context::{ConversationContext, TurnEnd, TurnInput};
 pub use factory::{AgentFactory, SessionOpts};
-pub use minikrabs::{MiniKrabsSpawner, SpawnMode};
+pub use metrics::{ToolMetrics, ToolStats};
+pub use minikrabs::{
+    subagent_limit, token_budget, MiniKrabsSpawner, ProgressHook, ProgressSink, SpawnMode,
+    SubAgentLimit, SubAgentProgress, TokenBudget, UsageLedger,
+};
+pub use observer::{AgentObserver, JsonlObserver, NoopObserver, JSONL_SCHEMA_VERSION};
 pub use pool::{AgentHandle, AgentId, AgentPool, AgentStatus, HandleError, PoolError};
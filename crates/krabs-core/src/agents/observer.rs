@@ -0,0 +1,226 @@
+use crate::providers::provider::{Message, Role, TokenUsage, ToolCall};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Callbacks for following a streaming run, for embedders that would rather
+/// not drain the `StreamChunk` channel or write a [`Hook`](crate::hooks::Hook).
+///
+/// Register one with [`KrabsAgentBuilder::observer`](super::KrabsAgentBuilder::observer).
+/// It is called from the streaming loop alongside the channel, so a slow
+/// observer slows the run down. Every method defaults to doing nothing.
+#[async_trait]
+pub trait AgentObserver: Send + Sync {
+    /// A piece of assistant reply text, as it streams in. Text of an attempt
+    /// that fails and is retried has already been reported.
+    async fn on_token(&self, _text: &str) {}
+
+    /// The model asked for `call`; it is about to go through permissions.
+    async fn on_tool_call(&self, _call: &ToolCall) {}
+
+    /// Whether `call` may run, asked once it has passed the agent's own
+    /// permission checks. Returning `false` denies it. Default: allow.
+    async fn on_permission_request(&self, _call: &ToolCall) -> bool {
+        true
+    }
+
+    /// `call` finished, or was denied, with `content` as the text sent back
+    /// to the model.
+    async fn on_tool_result(&self, _call: &ToolCall, _content: &str, _is_error: bool) {}
+
+    /// Token usage of one LLM call.
+    async fn on_usage(&self, _usage: &TokenUsage) {}
+
+    /// The run ended, with the final conversation or the error that stopped
+    /// it. `session_id` is set when the run was persisted.
+    async fn on_done(
+        &self,
+        _session_id: Option<&str>,
+        _outcome: Result<&[Message], &anyhow::Error>,
+    ) {
+    }
+}
+
+/// An observer that ignores everything; the builder's default.
+pub struct NoopObserver;
+
+#[async_trait]
+impl AgentObserver for NoopObserver {}
+
+/// Version of the [`JsonlObserver`] event schema, sent as `"v"` on every
+/// line. Bumped only when a field is removed or changes meaning; new event
+/// types and fields may be added without a bump.
+pub const JSONL_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonlEvent<'a> {
+    Token {
+        text: &'a str,
+    },
+    ToolCall {
+        id: &'a str,
+        name: &'a str,
+        args: &'a serde_json::Value,
+    },
+    ToolResult {
+        id: &'a str,
+        name: &'a str,
+        content: &'a str,
+        is_error: bool,
+    },
+    Usage(&'a TokenUsage),
+    Done {
+        session_id: Option<&'a str>,
+        /// The last assistant reply; `null` when the run failed.
+        result: Option<&'a str>,
+        error: Option<String>,
+    },
+}
+
+#[derive(Serialize)]
+struct JsonlLine<'a> {
+    v: u32,
+    #[serde(flatten)]
+    event: JsonlEvent<'a>,
+}
+
+/// Writes each event of a run as one line of JSON, for programs driving
+/// Krabs from behind a GUI or web frontend:
+///
+/// ```text
+/// {"v":1,"type":"token","text":"Let me look"}
+/// {"v":1,"type":"tool_call","id":"c1","name":"read","args":{"path":"src/lib.rs"}}
+/// {"v":1,"type":"tool_result","id":"c1","name":"read","content":"…","is_error":false}
+/// {"v":1,"type":"usage","input_tokens":812,"output_tokens":64,"cache_read_tokens":0,"cache_creation_tokens":0,"reasoning_tokens":0}
+/// {"v":1,"type":"done","session_id":"…","result":"It parses the config.","error":null}
+/// ```
+///
+/// Each line is flushed as it is written. Write errors are ignored: a reader
+/// that went away shouldn't stop the run.
+pub struct JsonlObserver<W> {
+    out: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonlObserver<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out: Mutex::new(out),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn emit(&self, event: JsonlEvent<'_>) {
+        let line = JsonlLine {
+            v: JSONL_SCHEMA_VERSION,
+            event,
+        };
+        let Ok(json) = serde_json::to_string(&line) else {
+            return;
+        };
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = writeln!(out, "{json}").and_then(|()| out.flush());
+    }
+}
+
+impl JsonlObserver<std::io::Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(std::io::stdout())
+    }
+}
+
+#[async_trait]
+impl<W: Write + Send> AgentObserver for JsonlObserver<W> {
+    async fn on_token(&self, text: &str) {
+        self.emit(JsonlEvent::Token { text });
+    }
+
+    async fn on_tool_call(&self, call: &ToolCall) {
+        self.emit(JsonlEvent::ToolCall {
+            id: &call.id,
+            name: &call.name,
+            args: &call.args,
+        });
+    }
+
+    async fn on_tool_result(&self, call: &ToolCall, content: &str, is_error: bool) {
+        self.emit(JsonlEvent::ToolResult {
+            id: &call.id,
+            name: &call.name,
+            content,
+            is_error,
+        });
+    }
+
+    async fn on_usage(&self, usage: &TokenUsage) {
+        self.emit(JsonlEvent::Usage(usage));
+    }
+
+    async fn on_done(&self, session_id: Option<&str>, outcome: Result<&[Message], &anyhow::Error>) {
+        let (result, error) = match outcome {
+            Ok(messages) => (
+                messages
+                    .iter()
+                    .rev()
+                    .find(|m| matches!(m.role, Role::Assistant) && !m.content.trim().is_empty())
+                    .map(|m| m.content.as_str()),
+                None,
+            ),
+            Err(e) => (None, Some(format!("{e:#}"))),
+        };
+        self.emit(JsonlEvent::Done {
+            session_id,
+            result,
+            error,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn jsonl_events_are_versioned_lines() {
+        let observer = JsonlObserver::new(Vec::new());
+        let call = ToolCall {
+            id: "c1".into(),
+            name: "read".into(),
+            args: json!({ "path": "a.rs" }),
+            thought_signature: None,
+        };
+        observer.on_token("hi").await;
+        observer.on_tool_call(&call).await;
+        observer.on_tool_result(&call, "fn main() {}", false).await;
+        observer
+            .on_usage(&TokenUsage {
+                input_tokens: 10,
+                output_tokens: 2,
+                ..Default::default()
+            })
+            .await;
+        let messages = [Message::user("q"), Message::assistant("done it")];
+        observer.on_done(Some("s1"), Ok(&messages)).await;
+
+        let out = String::from_utf8(observer.into_inner()).unwrap();
+        let lines: Vec<Value> = out
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert!(lines.iter().all(|l| l["v"] == JSONL_SCHEMA_VERSION));
+        let types: Vec<&str> = lines.iter().map(|l| l["type"].as_str().unwrap()).collect();
+        assert_eq!(
+            types,
+            ["token", "tool_call", "tool_result", "usage", "done"]
+        );
+        assert_eq!(lines[1]["args"]["path"], "a.rs");
+        assert_eq!(lines[3]["input_tokens"], 10);
+        assert_eq!(lines[4]["result"], "done it");
+        assert_eq!(lines[4]["session_id"], "s1");
+    }
+}
@@ -1,17 +1,30 @@
+use super::base_agent::BaseAgent;
 use anyhow::Result;
 use std::path::{Path, PathBuf};
 use tracing::warn;
 
-/// An agent persona loaded from `./krabs/agents/<name>.md`.
+/// An agent persona loaded from `<name>.md` in one of the `persona_paths`
+/// directories (by default `./krabs/agents/`, then `~/.config/krabs/agents/`).
 ///
 /// Markdown body (after optional YAML frontmatter) is appended to the base
 /// system prompt when the persona is activated. Frontmatter may optionally
-/// override `model` and `provider`.
+/// override `model` and `provider`, and name a parent persona with
+/// `extends: <name>`: the parent's prompt comes first, this body is appended
+/// to it, and the parent's `model` / `provider` apply unless overridden here.
+/// The parent is another discovered persona or a built-in agent.
+///
+/// Built-in [`BaseAgent`] profiles convert into personas too; those have no
+/// model or provider override and an empty `path`.
+#[derive(Debug, Clone)]
 pub struct AgentPersona {
+    /// Where the persona was found.
+    pub source: PersonaSource,
     pub name: String,
     pub description: Option<String>,
     pub model: Option<String>,
     pub provider: Option<String>,
+    /// Name of the persona this one builds on.
+    pub extends: Option<String>,
     /// Persona body — the system-prompt extension text.
     pub system_prompt: String,
     pub path: PathBuf,
@@ -28,7 +41,7 @@ impl AgentPersona {
 
         let content = std::fs::read_to_string(path)?;
 
-        let (description, model, provider, system_prompt) =
+        let (description, model, provider, extends, system_prompt) =
             if let Some(stripped) = content.strip_prefix("---") {
                 // Strip the leading "---\n"
                 let after_open = stripped.trim_start_matches('\n');
@@ -42,50 +55,108 @@ impl AgentPersona {
                     let description = yaml["description"].as_str().map(String::from);
                     let model = yaml["model"].as_str().map(String::from);
                     let provider = yaml["provider"].as_str().map(String::from);
+                    let extends = yaml["extends"].as_str().map(String::from);
 
-                    (description, model, provider, body)
+                    (description, model, provider, extends, body)
                 } else {
-                    (None, None, None, content)
+                    (None, None, None, None, content)
                 }
             } else {
-                (None, None, None, content)
+                (None, None, None, None, content)
             };
 
         Ok(Self {
+            source: PersonaSource::Project,
             name,
             description,
             model,
             provider,
+            extends,
             system_prompt,
             path: path.to_path_buf(),
         })
     }
 
-    /// Scan `./krabs/agents/` for `*.md` files, parse each one, skip bad
-    /// files with a warning (never fatal). Returns personas sorted by name.
-    pub fn discover() -> Vec<Self> {
+    /// Whether this persona came from a built-in [`BaseAgent`] rather than a
+    /// file.
+    pub fn is_builtin(&self) -> bool {
+        self.source == PersonaSource::Builtin
+    }
+
+    /// Scan each of `paths` (the config's `persona_paths`) for `*.md` files,
+    /// parse each one, skip bad files with a warning (never fatal). On a
+    /// name found in several directories the earliest one wins. Returns
+    /// personas sorted by name, with `extends` chains merged.
+    pub fn discover(paths: &[PathBuf]) -> Vec<Self> {
+        let (personas, errors) = Self::discover_with_errors(paths);
+        for error in errors {
+            warn!("{error}");
+        }
+        personas
+    }
+
+    /// Like [`discover`](Self::discover), but returns why each skipped
+    /// persona was skipped instead of logging it.
+    pub fn discover_with_errors(paths: &[PathBuf]) -> (Vec<Self>, Vec<String>) {
         let cwd = std::env::current_dir().unwrap_or_default();
-        let dir = cwd.join("krabs").join("agents");
+        let dirs: Vec<(PathBuf, PersonaSource)> = paths
+            .iter()
+            .map(|path| match path.strip_prefix("~") {
+                Ok(rest) => (
+                    dirs::home_dir().unwrap_or_default().join(rest),
+                    PersonaSource::Global,
+                ),
+                Err(_) if path.is_absolute() => (path.clone(), PersonaSource::Global),
+                Err(_) => (cwd.join(path), PersonaSource::Project),
+            })
+            .collect();
+        Self::discover_in(&dirs)
+    }
+
+    /// Personas in `dirs`, earlier directories shadowing later ones.
+    fn discover_in(dirs: &[(PathBuf, PersonaSource)]) -> (Vec<Self>, Vec<String>) {
+        let mut personas: Vec<Self> = Vec::new();
+        let mut errors = Vec::new();
+        for (dir, source) in dirs {
+            for mut persona in Self::read_dir(dir, &mut errors) {
+                if personas.iter().all(|p| p.name != persona.name) {
+                    persona.source = *source;
+                    personas.push(persona);
+                }
+            }
+        }
+
+        let mut resolved = Vec::with_capacity(personas.len());
+        for persona in &personas {
+            match persona.inherit(&personas) {
+                Ok(merged) => resolved.push(merged),
+                Err(e) => errors.push(format!("Skipping agent persona @{}: {}", persona.name, e)),
+            }
+        }
+        resolved.sort_by(|a, b| a.name.cmp(&b.name));
+        (resolved, errors)
+    }
 
+    /// The personas parsed from `dir`; problems go to `errors`.
+    fn read_dir(dir: &Path, errors: &mut Vec<String>) -> Vec<Self> {
         if !dir.exists() {
             return Vec::new();
         }
 
-        let entries = match std::fs::read_dir(&dir) {
+        let entries = match std::fs::read_dir(dir) {
             Ok(e) => e,
             Err(e) => {
-                warn!("Failed to read agents directory {:?}: {}", dir, e);
+                errors.push(format!("Failed to read agents directory {:?}: {}", dir, e));
                 return Vec::new();
             }
         };
 
         let mut personas: Vec<Self> = Vec::new();
-
         for entry in entries {
             let entry = match entry {
                 Ok(e) => e,
                 Err(e) => {
-                    warn!("Failed to read directory entry: {}", e);
+                    errors.push(format!("Failed to read directory entry: {}", e));
                     continue;
                 }
             };
@@ -97,11 +168,196 @@ impl AgentPersona {
 
             match Self::parse(&path) {
                 Ok(persona) => personas.push(persona),
-                Err(e) => warn!("Skipping agent persona at {:?}: {}", path, e),
+                Err(e) => errors.push(format!("Skipping agent persona at {:?}: {}", path, e)),
             }
         }
-
-        personas.sort_by(|a, b| a.name.cmp(&b.name));
         personas
     }
+
+    /// This persona merged with its `extends` chain, looked up in `personas`
+    /// first and then among the built-in agents. Fails on a missing parent
+    /// or a cycle.
+    fn inherit(&self, personas: &[Self]) -> Result<Self> {
+        // Walk up to the root; a built-in agent extends nothing, so it ends
+        // the chain.
+        let mut builtin = None;
+        let mut chain: Vec<&Self> = vec![self];
+        while let Some(parent) = chain.last().and_then(|p| p.extends.as_deref()) {
+            if let Some(start) = chain.iter().position(|p| p.name == parent) {
+                let mut names: Vec<&str> = chain[start..].iter().map(|p| p.name.as_str()).collect();
+                names.push(parent);
+                anyhow::bail!("circular `extends`: {}", names.join(" → "));
+            }
+            if let Some(p) = personas.iter().find(|p| p.name == parent) {
+                chain.push(p);
+                continue;
+            }
+            match BaseAgent::from_name(parent) {
+                Some(agent) => {
+                    builtin = Some(Self::from(agent));
+                    break;
+                }
+                None => anyhow::bail!("`extends: {parent}` names no persona or built-in agent"),
+            }
+        }
+        chain.extend(builtin.as_ref());
+
+        // Apply prompts from the root down; the nearest model, provider and
+        // description win.
+        let mut merged = self.clone();
+        merged.system_prompt.clear();
+        for persona in chain.iter().rev() {
+            let body = persona.system_prompt.trim_end();
+            if body.is_empty() {
+                continue;
+            }
+            if !merged.system_prompt.is_empty() {
+                merged.system_prompt.push_str("\n\n");
+            }
+            merged.system_prompt.push_str(body);
+        }
+        for ancestor in &chain[1..] {
+            merged.description = merged.description.or_else(|| ancestor.description.clone());
+            merged.model = merged.model.or_else(|| ancestor.model.clone());
+            merged.provider = merged.provider.or_else(|| ancestor.provider.clone());
+        }
+        Ok(merged)
+    }
+}
+
+impl From<BaseAgent> for AgentPersona {
+    fn from(agent: BaseAgent) -> Self {
+        Self {
+            source: PersonaSource::Builtin,
+            name: agent.name().to_string(),
+            description: Some(agent.description().to_string()),
+            model: None,
+            provider: None,
+            extends: None,
+            system_prompt: agent.system_prompt().to_string(),
+            path: PathBuf::new(),
+        }
+    }
+}
+
+/// Where an [`AgentPersona`] was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersonaSource {
+    /// A built-in [`BaseAgent`].
+    Builtin,
+    /// A relative `persona_paths` directory, i.e. one in the project.
+    Project,
+    /// An absolute or `~/` directory, shared by every project.
+    Global,
+}
+
+impl PersonaSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Builtin => "built-in",
+            Self::Project => "project",
+            Self::Global => "global",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, content: &str) {
+        std::fs::write(dir.join(format!("{name}.md")), content).unwrap();
+    }
+
+    fn project(dir: &Path) -> Vec<(PathBuf, PersonaSource)> {
+        vec![(dir.to_path_buf(), PersonaSource::Project)]
+    }
+
+    #[test]
+    fn project_personas_shadow_global_ones() {
+        let project_dir = tempfile::tempdir().unwrap();
+        let global_dir = tempfile::tempdir().unwrap();
+        write(project_dir.path(), "reviewer", "Project reviewer.\n");
+        write(global_dir.path(), "reviewer", "Global reviewer.\n");
+        write(global_dir.path(), "tone", "Be terse.\n");
+        write(
+            project_dir.path(),
+            "terse-dev",
+            "---\nextends: tone\n---\nWrite Rust.\n",
+        );
+
+        let (personas, errors) = AgentPersona::discover_in(&[
+            (project_dir.path().to_path_buf(), PersonaSource::Project),
+            (global_dir.path().to_path_buf(), PersonaSource::Global),
+        ]);
+        assert!(errors.is_empty(), "{errors:?}");
+        let get = |name: &str| personas.iter().find(|p| p.name == name).unwrap();
+        assert_eq!(personas.len(), 3);
+        assert_eq!(get("reviewer").system_prompt, "Project reviewer.");
+        assert_eq!(get("reviewer").source, PersonaSource::Project);
+        assert_eq!(get("tone").source, PersonaSource::Global);
+        // A project persona may build on a global one.
+        assert_eq!(get("terse-dev").system_prompt, "Be terse.\n\nWrite Rust.");
+    }
+
+    #[test]
+    fn extends_appends_to_the_parent_prompt() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "standards",
+            "---\nmodel: gpt-4o\n---\nUse snake_case.\n",
+        );
+        write(
+            dir.path(),
+            "reviewer",
+            "---\ndescription: reviews\nextends: standards\n---\nReview diffs.\n",
+        );
+        write(
+            dir.path(),
+            "deep",
+            "---\nextends: reviewer\nmodel: o3\n---\nBe thorough.\n",
+        );
+        write(
+            dir.path(),
+            "plans",
+            "---\nextends: planner\n---\nKeep it short.\n",
+        );
+
+        let (personas, errors) = AgentPersona::discover_in(&project(dir.path()));
+        assert!(errors.is_empty(), "{errors:?}");
+        let get = |name: &str| personas.iter().find(|p| p.name == name).unwrap();
+
+        let reviewer = get("reviewer");
+        assert_eq!(reviewer.system_prompt, "Use snake_case.\n\nReview diffs.");
+        assert_eq!(reviewer.model.as_deref(), Some("gpt-4o"));
+        let deep = get("deep");
+        assert_eq!(
+            deep.system_prompt,
+            "Use snake_case.\n\nReview diffs.\n\nBe thorough."
+        );
+        assert_eq!(deep.model.as_deref(), Some("o3"));
+        assert_eq!(deep.description.as_deref(), Some("reviews"));
+        assert!(get("plans")
+            .system_prompt
+            .starts_with(BaseAgent::Planner.system_prompt().trim_end()));
+    }
+
+    #[test]
+    fn missing_and_circular_parents_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "a", "---\nextends: b\n---\nA\n");
+        write(dir.path(), "b", "---\nextends: a\n---\nB\n");
+        write(dir.path(), "orphan", "---\nextends: nobody\n---\nO\n");
+        write(dir.path(), "ok", "fine\n");
+
+        let (personas, errors) = AgentPersona::discover_in(&project(dir.path()));
+        assert_eq!(
+            personas.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(),
+            ["ok"]
+        );
+        assert_eq!(errors.len(), 3);
+        assert!(errors.iter().any(|e| e.contains("a → b → a")));
+        assert!(errors.iter().any(|e| e.contains("`extends: nobody`")));
+    }
 }
@@ -0,0 +1,103 @@
+use crate::providers::provider::ResponseFormat;
+use crate::tools::tool::schema_problems;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Sent back with the problems when a structured reply fails validation.
+pub const REPAIR_PROMPT: &str = "Your reply was not valid JSON for the requested schema. \
+Reply again with only the corrected JSON: no prose and no code fence. Problems:";
+
+/// Parse a structured reply: drop a Markdown code fence if the model added
+/// one, check the JSON against `format`'s schema, then deserialize it.
+pub fn parse_structured<T: DeserializeOwned>(reply: &str, format: &ResponseFormat) -> Result<T> {
+    let value: Value = serde_json::from_str(strip_code_fence(reply))
+        .map_err(|e| anyhow::anyhow!("the reply is not valid JSON: {e}"))?;
+    if let Some(schema) = format.schema() {
+        let validator = jsonschema::validator_for(&schema)
+            .map_err(|e| anyhow::anyhow!("the response schema is invalid: {e}"))?;
+        let problems = schema_problems(&validator, &value);
+        if !problems.is_empty() {
+            anyhow::bail!(
+                "the reply does not match the schema:\n{}",
+                problems.join("\n")
+            );
+        }
+    }
+    serde_json::from_value(value)
+        .map_err(|e| anyhow::anyhow!("the reply does not fit the expected type: {e}"))
+}
+
+/// `text` without a surrounding ```` ``` ```` / ```` ```json ```` fence.
+fn strip_code_fence(text: &str) -> &str {
+    let trimmed = text.trim();
+    let Some(inner) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    let inner = inner.strip_suffix("```").unwrap_or(inner);
+    // Drop the language tag on the opening line.
+    match inner.split_once('\n') {
+        Some((tag, body)) if !tag.trim_start().starts_with(['{', '[']) => body.trim(),
+        _ => inner.trim(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Verdict {
+        ok: bool,
+        reason: String,
+    }
+
+    fn format() -> ResponseFormat {
+        ResponseFormat::JsonSchema(json!({
+            "type": "object",
+            "properties": {
+                "ok": { "type": "boolean" },
+                "reason": { "type": "string" }
+            },
+            "required": ["ok", "reason"]
+        }))
+    }
+
+    #[test]
+    fn valid_replies_deserialize_with_or_without_a_fence() {
+        let want = Verdict {
+            ok: true,
+            reason: "fine".into(),
+        };
+        let plain: Verdict =
+            parse_structured(r#"{"ok": true, "reason": "fine"}"#, &format()).unwrap();
+        assert_eq!(plain, want);
+        let fenced: Verdict = parse_structured(
+            "```json\n{\"ok\": true, \"reason\": \"fine\"}\n```",
+            &format(),
+        )
+        .unwrap();
+        assert_eq!(fenced, want);
+    }
+
+    #[test]
+    fn invalid_json_and_schema_violations_are_explained() {
+        let err = parse_structured::<Verdict>("sure! here you go", &format()).unwrap_err();
+        assert!(err.to_string().contains("not valid JSON"), "{err}");
+
+        let err = parse_structured::<Verdict>(r#"{"ok": "yes"}"#, &format()).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("does not match the schema"), "{msg}");
+        assert!(msg.contains("/ok"), "{msg}");
+        assert!(msg.contains("reason"), "{msg}");
+    }
+
+    #[test]
+    fn json_object_format_accepts_any_object() {
+        let value: Value = parse_structured(r#"{"a": 1}"#, &ResponseFormat::JsonObject).unwrap();
+        assert_eq!(value["a"], 1);
+        assert!(parse_structured::<Value>("[1]", &ResponseFormat::JsonObject).is_err());
+    }
+}
@@ -1,6 +1,12 @@
+use crate::config::credentials::Credentials;
+use crate::permissions::ApprovalRule;
+use crate::providers::provider::{LlmProvider, ReasoningEffort};
+use crate::providers::OpenAiApi;
 use crate::sandbox::SandboxConfig;
+use crate::skills::source::SkillSource;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,17 +15,123 @@ pub struct SkillsConfig {
     pub paths: Vec<PathBuf>,
     #[serde(default)]
     pub enabled: Vec<String>,
+    /// Extra local or git skill sources, scanned before `paths`.
+    #[serde(default)]
+    pub sources: Vec<SkillSource>,
+    /// Where git sources are checked out. Default: `~/.krabs/skills-cache`.
+    #[serde(default = "default_skills_cache_dir")]
+    pub cache_dir: PathBuf,
+    /// Seconds before a git source is fetched again. Default: 3600.
+    #[serde(default = "default_skills_refresh_secs")]
+    pub refresh_secs: u64,
 }
 
 fn default_skill_paths() -> Vec<PathBuf> {
     vec![PathBuf::from("skills")]
 }
 
+fn default_skills_cache_dir() -> PathBuf {
+    KrabsConfig::resolve_path("skills-cache")
+}
+
+fn default_skills_refresh_secs() -> u64 {
+    3600
+}
+
 impl Default for SkillsConfig {
     fn default() -> Self {
         Self {
             paths: default_skill_paths(),
             enabled: Vec::new(),
+            sources: Vec::new(),
+            cache_dir: default_skills_cache_dir(),
+            refresh_secs: default_skills_refresh_secs(),
+        }
+    }
+}
+
+/// Assistant name, accent colour and logo, for embedders shipping their own
+/// branded CLI. Unset fields fall back to the Krabs defaults.
+///
+/// Example in `.krabs.json`:
+/// ```json
+/// {
+///   "branding": {
+///     "name": "Acme",
+///     "accent": [0, 120, 215],
+///     "logo": ["ACME"]
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrandingConfig {
+    /// Assistant name used in the system prompt and TUI titles. Default: "Krabs".
+    #[serde(default = "default_brand_name")]
+    pub name: String,
+    /// Accent colour as `[r, g, b]`. Default: `[255, 128, 0]`.
+    #[serde(default = "default_brand_accent")]
+    pub accent: [u8; 3],
+    /// Splash-screen logo, one entry per line. Empty = the built-in logo.
+    #[serde(default)]
+    pub logo: Vec<String>,
+}
+
+fn default_brand_name() -> String {
+    "Krabs".to_string()
+}
+
+fn default_brand_accent() -> [u8; 3] {
+    [255, 128, 0]
+}
+
+impl Default for BrandingConfig {
+    fn default() -> Self {
+        Self {
+            name: default_brand_name(),
+            accent: default_brand_accent(),
+            logo: Vec::new(),
+        }
+    }
+}
+
+impl BrandingConfig {
+    /// Opening line of the system prompt, naming the assistant.
+    pub fn intro(&self) -> String {
+        format!("You are {}, an agentic assistant.", self.name)
+    }
+}
+
+/// TUI keybindings: named actions mapped to key chords, replacing that
+/// action's built-in chords. Actions left out keep their defaults.
+///
+/// Example in `.krabs.json`:
+/// ```json
+/// {
+///   "keybindings": {
+///     "history-prev": "ctrl+k",
+///     "submit": ["enter", "ctrl+j"]
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct KeybindConfig {
+    pub bindings: BTreeMap<String, KeyChords>,
+}
+
+/// One chord such as `"ctrl+p"`, or a list of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeyChords {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyChords {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            KeyChords::One(chord) => std::slice::from_ref(chord),
+            KeyChords::Many(chords) => chords,
         }
     }
 }
@@ -155,6 +267,65 @@ pub struct TelemetryConfig {
     /// Defaults to `/tmp/krabs-telemetry-<session_id>.jsonl` when enabled and not set.
     #[serde(default)]
     pub jsonl_path: Option<String>,
+
+    /// OTLP/HTTP traces endpoint (e.g. `http://localhost:4318/v1/traces`) to
+    /// export runs to as spans. `None` = disabled.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+}
+
+/// SQLite settings for the session database, applied to every connection.
+///
+/// Example in `.krabs.json`:
+/// ```json
+/// {
+///   "sqlite": {
+///     "journal_mode": "wal",
+///     "busy_timeout_ms": 5000,
+///     "synchronous": "normal"
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SqliteConfig {
+    /// `journal_mode` pragma: `"wal"`, `"delete"`, `"truncate"`, `"persist"`,
+    /// `"memory"` or `"off"`. WAL lets agents sharing the database write
+    /// without blocking each other's readers. Default: "wal".
+    #[serde(default = "default_sqlite_journal_mode")]
+    pub journal_mode: String,
+
+    /// How long a write waits for another connection's lock before failing
+    /// with `database is locked`, in milliseconds. Default: 5000.
+    #[serde(default = "default_sqlite_busy_timeout_ms")]
+    pub busy_timeout_ms: u64,
+
+    /// `synchronous` pragma: `"off"`, `"normal"`, `"full"` or `"extra"`.
+    /// With WAL, `"normal"` can lose the last commits on power loss but never
+    /// corrupts the database. Default: "normal".
+    #[serde(default = "default_sqlite_synchronous")]
+    pub synchronous: String,
+}
+
+fn default_sqlite_journal_mode() -> String {
+    "wal".to_string()
+}
+
+fn default_sqlite_busy_timeout_ms() -> u64 {
+    5_000
+}
+
+fn default_sqlite_synchronous() -> String {
+    "normal".to_string()
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        Self {
+            journal_mode: default_sqlite_journal_mode(),
+            busy_timeout_ms: default_sqlite_busy_timeout_ms(),
+            synchronous: default_sqlite_synchronous(),
+        }
+    }
 }
 
 /// A named custom model entry pointing at an OpenAI-compatible endpoint.
@@ -168,7 +339,8 @@ pub struct TelemetryConfig {
 ///       "provider": "openai",
 ///       "base_url": "http://localhost:8080/v1",
 ///       "api_key": "",
-///       "model": "llama3.2"
+///       "model": "llama3.2",
+///       "context_window": 131072
 ///     }
 ///   ]
 /// }
@@ -187,6 +359,33 @@ pub struct CustomModelEntry {
     pub api_key: String,
     /// Model identifier sent in the request (e.g. `"llama3.2"`, `"mistral"`).
     pub model: String,
+    /// Context window in tokens. Takes precedence over `model_context_limits`.
+    #[serde(default)]
+    pub context_window: Option<u32>,
+}
+
+impl CustomModelEntry {
+    /// Build a provider for this entry, with the timeout, tracing, caching and
+    /// thinking settings taken from `config`.
+    pub fn build_provider(&self, config: &KrabsConfig) -> Box<dyn LlmProvider> {
+        Credentials {
+            provider: self.provider.clone(),
+            api_key: self.api_key.clone(),
+            base_url: self.base_url.clone(),
+            model: self.model.clone(),
+            is_default: false,
+            prompt_caching: config.enable_prompt_caching,
+            thinking_budget: config.thinking_budget_tokens,
+            reasoning_effort: config.reasoning_effort,
+            max_output_tokens: config.max_output_tokens,
+            stop_sequences: config.stop_sequences.clone(),
+            openai_api: config.openai_api,
+            trace_http: config.trace_http,
+            request_timeout_ms: config.request_timeout_ms,
+            connect_timeout_ms: config.connect_timeout_ms,
+        }
+        .build_provider()
+    }
 }
 
 fn default_entry_provider() -> String {
@@ -209,13 +408,53 @@ pub struct KrabsConfig {
     pub max_turns: usize,
     #[serde(default = "default_db_path")]
     pub db_path: PathBuf,
+    /// Write sessions (messages, usage, checkpoints) to the database at
+    /// `db_path`. When false nothing is persisted and sessions can't be
+    /// resumed. Default: true.
+    #[serde(default = "default_persist_sessions")]
+    pub persist_sessions: bool,
+    /// Give the model `memory_write`, `memory_read` and `memory_search`
+    /// tools for notes kept per session or globally in the database at
+    /// `db_path`. Only when `persist_sessions` is on. Default: true.
+    #[serde(default = "default_memory_tools")]
+    pub memory_tools: bool,
+    /// Cap, in estimated tokens, on the list of global memory notes added to
+    /// the system prompt each turn. 0 = don't add it. Default: 500.
+    #[serde(default = "default_memory_prompt_tokens")]
+    pub memory_prompt_tokens: usize,
     #[serde(default = "default_max_context_tokens")]
     pub max_context_tokens: usize,
+    /// Tokens held back from `max_context_tokens` for the model's reply. Old
+    /// messages are trimmed once a request passes 80% of what remains, and a
+    /// request that still doesn't fit has its largest tool results cut down
+    /// before it is sent. Default: 4096.
+    #[serde(default = "default_context_reserve_tokens")]
+    pub context_reserve_tokens: usize,
+    /// Project prompt file added to the system prompt every turn, relative to
+    /// the working directory. A missing file adds nothing. Default: "krabs/system.md".
+    #[serde(default = "default_system_prompt_path")]
+    pub system_prompt_path: PathBuf,
+    /// Use the `system_prompt_path` file in place of the caller-supplied
+    /// system prompt rather than after it. The built-in base prompt is kept
+    /// either way. Default: false.
+    #[serde(default)]
+    pub system_prompt_replace: bool,
     #[serde(default)]
     pub skills: SkillsConfig,
+    /// Directories scanned for `@<name>` personas, highest precedence first:
+    /// a name found in several is taken from the earliest. Relative paths
+    /// are project directories; absolute and `~/` ones are global.
+    /// Default: `krabs/agents`, `~/.config/krabs/agents`.
+    #[serde(default = "default_persona_paths")]
+    pub persona_paths: Vec<PathBuf>,
     /// User-defined custom model entries loaded from config.
     #[serde(default)]
     pub custom_models: Vec<CustomModelEntry>,
+    /// Models to switch to, in order, when an LLM call still fails after
+    /// `max_retries`. A switch lasts for the rest of the agent's run.
+    /// Default: `[]`.
+    #[serde(default)]
+    pub fallback_models: Vec<CustomModelEntry>,
     /// How many times to retry a failed LLM API call before giving up.
     #[serde(default = "default_max_retries")]
     pub max_retries: usize,
@@ -229,6 +468,20 @@ pub struct KrabsConfig {
     /// Default: 1 (2 total attempts — one try + one retry).
     #[serde(default = "default_tool_max_retries")]
     pub tool_max_retries: usize,
+    /// Milliseconds one tool call may run before it fails as timed out (and is
+    /// retried per `tool_max_retries`). Tools may override this via
+    /// `Tool::timeout_ms`. 0 = no limit. Default: 300000 (5 minutes).
+    #[serde(default = "default_tool_timeout_ms")]
+    pub tool_timeout_ms: u64,
+    /// Milliseconds one LLM request may take in total, including streaming
+    /// the response, before it fails and is retried per `max_retries`.
+    /// 0 = no limit. Default: 600000 (10 minutes).
+    #[serde(default = "default_request_timeout_ms")]
+    pub request_timeout_ms: u64,
+    /// Milliseconds to wait for a connection to the provider before the
+    /// request fails and is retried. 0 = no limit. Default: 15000.
+    #[serde(default = "default_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
     /// Telemetry export configuration.
     #[serde(default)]
     pub telemetry: TelemetryConfig,
@@ -242,11 +495,263 @@ pub struct KrabsConfig {
     /// Example: `["bash", "read_file", "web_fetch"]`
     #[serde(default)]
     pub auto_approve_tools: Vec<String>,
-    /// Maximum length (in characters) of a tool result before it is truncated.
-    /// Prevents context-overflow errors when tools return large outputs (e.g. web pages).
-    /// Set to 0 to disable truncation. Default: 8000.
-    #[serde(default = "default_max_tool_result_chars")]
-    pub max_tool_result_chars: usize,
+    /// Finer-grained pre-approvals: a call skips the permission popup when
+    /// one rule names its tool and its pattern matches the given argument.
+    /// Example: `[{ "tool": "bash", "arg": "command", "arg_pattern": "^git (status|diff)$" }]`
+    #[serde(default)]
+    pub auto_approve: Vec<ApprovalRule>,
+    /// Skip the permission popup for every tool call, except those matching
+    /// `never_auto_approve`. The TUI shows a red banner while it is on; the
+    /// `--dangerously-skip-permissions` flag turns it on. Default: false.
+    #[serde(default)]
+    pub auto_approve_all: bool,
+    /// Calls that always ask, even under `auto_approve_all`, an
+    /// `auto_approve` rule or a saved "always allow". Same shape as
+    /// `auto_approve`.
+    /// Example: `[{ "tool": "bash", "arg": "command", "arg_pattern": "\\brm\\b" }]`
+    #[serde(default)]
+    pub never_auto_approve: Vec<ApprovalRule>,
+    /// Regexes a `bash` command must match one of to run, checked before it
+    /// is executed whatever the permission settings. Empty = any command not
+    /// refused by `bash_deny`. Default: empty.
+    #[serde(default)]
+    pub bash_allow: Vec<String>,
+    /// Regexes of `bash` commands refused without running, checked before
+    /// `bash_allow`. Wiping `/` or `~`, `mkfs`, writing to a disk device and
+    /// fork bombs are refused as well unless a `bash_allow` pattern matches.
+    /// Default: empty.
+    #[serde(default)]
+    pub bash_deny: Vec<String>,
+    /// Maximum size (in bytes) of a raw tool output sent to the model. Larger
+    /// outputs keep their head and last few lines around a truncation marker.
+    /// Tools may override this via `Tool::max_output_bytes`. 0 = unlimited. Default: 32768.
+    /// [`load`](Self::load) reads the retired `max_tool_result_chars` key as this.
+    #[serde(default = "default_max_tool_output_bytes")]
+    pub max_tool_output_bytes: usize,
+    /// When `read` or `bash` gets bytes that aren't UTF-8 it returns
+    /// "binary file, N bytes, type <mime>" instead, with a hexdump of this
+    /// many leading bytes. 0 = no hexdump. Default: 256.
+    #[serde(default = "default_tool_output_hexdump_bytes")]
+    pub tool_output_hexdump_bytes: usize,
+    /// Pretty-print JSON that `bash` prints. Default: true.
+    #[serde(default = "default_tool_output_pretty_json")]
+    pub tool_output_pretty_json: bool,
+    /// JSON from `bash`, or a JSON file `read` without a range, larger than
+    /// this many bytes is shown as its top-level structure only. 0 = never.
+    /// Default: 65536.
+    #[serde(default = "default_tool_output_json_outline_bytes")]
+    pub tool_output_json_outline_bytes: usize,
+    /// Matches `glob` and `grep` return per call when the model doesn't pass
+    /// `max_results`; the rest are reachable via `offset`. Default: 100.
+    #[serde(default = "default_search_max_results")]
+    pub search_max_results: usize,
+    /// Environment variables the `sysinfo` tool may report. Names containing
+    /// `KEY`, `TOKEN`, `SECRET` or `PASSWORD` are withheld even if listed.
+    /// Default: `PATH`, `HOME`, `USER`, `SHELL`, `LANG`, `LC_ALL`, `TERM`,
+    /// `TMPDIR`.
+    #[serde(default = "default_sysinfo_env_vars")]
+    pub sysinfo_env_vars: Vec<String>,
+    /// Directory `read`, `write`, `glob` and `grep` resolve relative paths
+    /// against; relative to the working directory if relative itself. Unset =
+    /// the nearest directory at or above the working directory holding `.git`
+    /// or `krabs/`, else the working directory. Default: unset.
+    #[serde(default)]
+    pub project_root: Option<PathBuf>,
+    /// Refuse file tool paths outside the project root, whether absolute or
+    /// reached with `..`. Default: false.
+    #[serde(default)]
+    pub confine_to_root: bool,
+    /// Before the `write` tool overwrites a file, copy it to
+    /// `<path><write_backup_suffix>`; `/restore <path>` puts it back.
+    /// Default: false.
+    #[serde(default)]
+    pub write_backups: bool,
+    /// Suffix of the backups made with `write_backups`. Default: `.krabs.bak`.
+    #[serde(default = "default_write_backup_suffix")]
+    pub write_backup_suffix: String,
+    /// Files over this many bytes aren't snapshotted before `write` or
+    /// `apply_patch` changes them, so `/undo` can't restore them.
+    /// Default: 1048576 (1 MiB).
+    #[serde(default = "default_snapshot_max_file_bytes")]
+    pub snapshot_max_file_bytes: u64,
+    /// Most file snapshots kept per session for `/undo`; the oldest are
+    /// dropped first. 0 turns snapshots off. Default: 50.
+    #[serde(default = "default_snapshot_max_count")]
+    pub snapshot_max_count: usize,
+    /// Most bytes of file snapshots kept per session; the oldest are dropped
+    /// first. Default: 52428800 (50 MiB).
+    #[serde(default = "default_snapshot_max_total_bytes")]
+    pub snapshot_max_total_bytes: u64,
+    /// Seconds an MCP server's cached tool list is trusted at startup. Within
+    /// that window the agent registers the cached tools without waiting for
+    /// the server and refreshes the cache in the background. 0 = always
+    /// connect first. Default: 86400.
+    #[serde(default = "default_mcp_cache_ttl_secs")]
+    pub mcp_cache_ttl_secs: u64,
+    /// Total tokens the `delegate` and `dispatch` sub-agents spawned within one
+    /// top-level run may consume between them. 0 = unlimited. Default: 0.
+    #[serde(default)]
+    pub delegate_token_budget: u32,
+    /// Sub-agents that may run at once across `dispatch` calls of one run;
+    /// the rest wait for a free slot. 0 = unlimited. Default: 4.
+    #[serde(default = "default_max_concurrent_subagents")]
+    pub max_concurrent_subagents: usize,
+    /// LLM requests that may be in flight at once across the whole process —
+    /// every agent, sub-agent and provider — so parallel sub-agents don't
+    /// trip rate limits. The rest wait for a slot. 0 = unlimited, unless an
+    /// agent built from another config in the same process set one.
+    /// Default: 0.
+    #[serde(default)]
+    pub max_concurrent_requests: usize,
+    /// Send Anthropic `cache_control` breakpoints on the system prompt and tool
+    /// definitions. Ignored by other providers. Default: false.
+    #[serde(default)]
+    pub enable_prompt_caching: bool,
+    /// Reasoning-token budget for Anthropic extended thinking; the thinking is
+    /// streamed to the TUI but never kept in the conversation. 0 = off. Default: 0.
+    #[serde(default)]
+    pub thinking_budget_tokens: u32,
+    /// Reasoning effort for models that take one: `"low"`, `"medium"`,
+    /// `"high"` or a token budget. Sent as `reasoning_effort` to OpenAI
+    /// o-series models and as the extended-thinking budget to Anthropic,
+    /// where it replaces `thinking_budget_tokens`; other providers ignore it.
+    /// Default: unset.
+    #[serde(default)]
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// Cap on the tokens of each reply: `max_tokens` for Anthropic, Gemini
+    /// and most OpenAI models, `max_completion_tokens` for o-series and
+    /// GPT-5. Lowered to the model's own limit when known (see
+    /// [`output_limit`]). Default: unset (Anthropic: 8096, others: the
+    /// provider's default).
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+    /// Sequences that end a reply where they first appear: `stop` for OpenAI
+    /// and Gemini, `stop_sequences` for Anthropic. OpenAI o-series models
+    /// don't accept them. Default: none.
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    /// Which OpenAI endpoint to use: `"chat"` (Chat Completions),
+    /// `"responses"` (the Responses API), or `"auto"`, which uses the
+    /// Responses API only for models OpenAI serves nowhere else (o1-pro,
+    /// o3-pro, codex, …) on api.openai.com. Azure and compatible servers stay
+    /// on Chat Completions under `"auto"`. Default: `"auto"`.
+    #[serde(default)]
+    pub openai_api: OpenAiApi,
+    /// Render assistant replies in the TUI as markdown (code blocks, bold,
+    /// lists). Set to false for plain text. Default: true.
+    #[serde(default = "default_render_markdown")]
+    pub render_markdown: bool,
+    /// Syntax-highlight fenced code in assistant replies and `read` results
+    /// in the TUI. Unknown languages render uncolored. Default: true.
+    #[serde(default = "default_highlight_code")]
+    pub highlight_code: bool,
+    /// Rows the TUI's message box grows to for multi-line input before it
+    /// scrolls instead. Default: 8.
+    #[serde(default = "default_input_max_lines")]
+    pub input_max_lines: u16,
+    /// Run consecutive parallel-safe tool calls from one assistant message
+    /// concurrently (see `Tool::is_parallel_safe`). Results are still appended
+    /// in call order. Default: false.
+    #[serde(default)]
+    pub parallel_tools: bool,
+    /// Don't execute tool calls: each gets a `[dry-run] would call …` result
+    /// instead and the run continues, so what an agent would do can be audited
+    /// with no side effects. Default: false.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Log every raw provider request and response body at `debug` level
+    /// (target `krabs::http`) with API keys redacted. Also enabled by setting
+    /// `KRABS_TRACE_HTTP=1`. Default: false.
+    #[serde(default = "default_trace_http")]
+    pub trace_http: bool,
+    /// Context window (tokens) per model, keyed by model name or a substring
+    /// of it, e.g. `{"qwen2.5-coder": 32768}`. Consulted before the built-in
+    /// table. Used for the `/usage` bar.
+    #[serde(default)]
+    pub model_context_limits: HashMap<String, u32>,
+    /// Context-usage percentages at which a `ContextThreshold` hook event is
+    /// fired, each at most once per run. Default: `[75, 90]`.
+    #[serde(default = "default_context_thresholds")]
+    pub context_thresholds: Vec<u8>,
+    /// Number of most recent tool results kept verbatim when the context is
+    /// compacted; older ones are folded into the summary. Default: 2.
+    #[serde(default = "default_compact_keep_tool_results")]
+    pub compact_keep_tool_results: usize,
+    /// After a session's first turn, ask a small model for a short title shown
+    /// by `/sessions`. Skipped for providers with no known cheap model unless
+    /// `session_title_model` is set. Default: true.
+    #[serde(default = "default_session_titles")]
+    pub session_titles: bool,
+    /// Model used for session titles. Empty = a small model picked per
+    /// provider. Default: "".
+    #[serde(default)]
+    pub session_title_model: String,
+    /// Name, accent colour and logo shown by the TUI and used in the system
+    /// prompt. Default: Krabs branding.
+    #[serde(default)]
+    pub branding: BrandingConfig,
+    /// Pragmas for the session database at `db_path`. Default: WAL, a 5 s
+    /// busy timeout and `synchronous = normal`.
+    #[serde(default)]
+    pub sqlite: SqliteConfig,
+    /// TUI keybindings, by action name. Default: the built-in bindings.
+    #[serde(default)]
+    pub keybindings: KeybindConfig,
+}
+
+/// Built-in context windows, matched by substring. More specific names
+/// must come before their prefixes (`gpt-4o` before `gpt-4`).
+const BUILTIN_CONTEXT_LIMITS: &[(&str, u32)] = &[
+    ("gemini", 1_048_576),
+    ("claude", 200_000),
+    ("gpt-5", 400_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5", 16_385),
+    ("o4-mini", 200_000),
+    ("o3", 200_000),
+    ("o1", 200_000),
+    ("llama3", 128_000),
+    ("qwen", 32_768),
+    ("mistral", 32_768),
+];
+
+/// Context window assumed for models nothing else matches.
+const DEFAULT_CONTEXT_LIMIT: u32 = 32_768;
+
+/// Most tokens a reply may have, by model-name substring. The first match
+/// wins, so longer names come before their prefixes.
+const BUILTIN_OUTPUT_LIMITS: &[(&str, u32)] = &[
+    ("gemini-2.5", 65_536),
+    ("gemini-2.0", 8_192),
+    ("gemini-1.5", 8_192),
+    ("claude-3-haiku", 4_096),
+    ("claude-3-opus", 4_096),
+    ("claude-3-5", 8_192),
+    ("claude-3-7-sonnet", 64_000),
+    ("claude-sonnet-4", 64_000),
+    ("claude-haiku-4", 64_000),
+    ("claude-opus-4-1", 32_000),
+    ("claude-opus-4-2", 32_000),
+    ("gpt-5", 128_000),
+    ("gpt-4.1", 32_768),
+    ("gpt-4o", 16_384),
+    ("gpt-4-turbo", 4_096),
+    ("gpt-3.5", 4_096),
+    ("o4-mini", 100_000),
+    ("o3", 100_000),
+    ("o1", 100_000),
+];
+
+/// The most output tokens `model` supports, if Krabs knows it.
+pub fn output_limit(model: &str) -> Option<u32> {
+    let m = model.to_lowercase();
+    BUILTIN_OUTPUT_LIMITS
+        .iter()
+        .find(|(k, _)| m.contains(k))
+        .map(|&(_, n)| n)
 }
 
 fn default_model() -> String {
@@ -257,6 +762,10 @@ fn default_base_url() -> String {
     std::env::var("KRABS_BASE_URL").unwrap_or_else(|_| "https://api.openai.com/v1".to_string())
 }
 
+fn default_trace_http() -> bool {
+    crate::providers::redact::trace_http_from_env()
+}
+
 fn default_max_turns() -> usize {
     50
 }
@@ -265,10 +774,18 @@ fn default_db_path() -> PathBuf {
     KrabsConfig::resolve_path("krabs.db")
 }
 
+fn default_context_thresholds() -> Vec<u8> {
+    vec![75, 90]
+}
+
 fn default_max_context_tokens() -> usize {
     128_000
 }
 
+fn default_context_reserve_tokens() -> usize {
+    4096
+}
+
 fn default_max_retries() -> usize {
     3
 }
@@ -277,12 +794,132 @@ fn default_retry_base_delay_ms() -> u64 {
     500
 }
 
+fn default_tool_timeout_ms() -> u64 {
+    300_000
+}
+
+pub(crate) fn default_request_timeout_ms() -> u64 {
+    600_000
+}
+
+pub(crate) fn default_connect_timeout_ms() -> u64 {
+    15_000
+}
+
 fn default_tool_max_retries() -> usize {
     1
 }
 
-fn default_max_tool_result_chars() -> usize {
-    8000
+/// Config keys that were renamed, as (old, new).
+const LEGACY_KEYS: &[(&str, &str)] = &[("max_tool_result_chars", "max_tool_output_bytes")];
+
+/// Move retired keys in a config file's object to their current names, so
+/// an old file keeps working. The current key wins if both are set.
+fn rename_legacy_keys(value: &mut serde_json::Value) {
+    let Some(obj) = value.as_object_mut() else {
+        return;
+    };
+    for (old, new) in LEGACY_KEYS {
+        let Some(v) = obj.remove(*old) else {
+            continue;
+        };
+        tracing::warn!("config key `{old}` is deprecated; use `{new}` (in bytes) instead");
+        obj.entry(*new).or_insert(v);
+    }
+}
+
+fn default_tool_output_hexdump_bytes() -> usize {
+    crate::tools::format::DEFAULT_HEXDUMP_BYTES
+}
+
+fn default_tool_output_pretty_json() -> bool {
+    true
+}
+
+fn default_tool_output_json_outline_bytes() -> usize {
+    crate::tools::format::DEFAULT_JSON_OUTLINE_BYTES
+}
+
+fn default_search_max_results() -> usize {
+    crate::tools::glob::DEFAULT_MAX_RESULTS
+}
+
+fn default_persona_paths() -> Vec<PathBuf> {
+    vec![
+        PathBuf::from("krabs/agents"),
+        PathBuf::from("~/.config/krabs/agents"),
+    ]
+}
+
+fn default_sysinfo_env_vars() -> Vec<String> {
+    crate::tools::sysinfo::DEFAULT_ENV_VARS
+        .iter()
+        .map(|v| v.to_string())
+        .collect()
+}
+
+fn default_snapshot_max_file_bytes() -> u64 {
+    1024 * 1024
+}
+
+fn default_snapshot_max_count() -> usize {
+    50
+}
+
+fn default_snapshot_max_total_bytes() -> u64 {
+    50 * 1024 * 1024
+}
+
+fn default_write_backup_suffix() -> String {
+    crate::tools::write::DEFAULT_BACKUP_SUFFIX.to_string()
+}
+
+fn default_max_concurrent_subagents() -> usize {
+    4
+}
+
+fn default_mcp_cache_ttl_secs() -> u64 {
+    86_400
+}
+
+fn default_max_tool_output_bytes() -> usize {
+    32_768
+}
+
+fn default_system_prompt_path() -> PathBuf {
+    PathBuf::from("krabs").join("system.md")
+}
+
+fn default_persist_sessions() -> bool {
+    true
+}
+
+fn default_memory_tools() -> bool {
+    true
+}
+
+fn default_memory_prompt_tokens() -> usize {
+    500
+}
+
+fn default_compact_keep_tool_results() -> usize {
+    2
+}
+
+fn default_session_titles() -> bool {
+    true
+}
+
+fn default_render_markdown() -> bool {
+    true
+}
+
+fn default_highlight_code() -> bool {
+    true
+}
+
+fn default_input_max_lines() -> u16 {
+    8
 }
 
 impl Default for KrabsConfig {
@@ -296,18 +933,70 @@ impl Default for KrabsConfig {
                 .unwrap_or_default(),
             max_turns: default_max_turns(),
             db_path: default_db_path(),
+            persist_sessions: default_persist_sessions(),
+            memory_tools: default_memory_tools(),
+            memory_prompt_tokens: default_memory_prompt_tokens(),
             max_context_tokens: default_max_context_tokens(),
+            context_reserve_tokens: default_context_reserve_tokens(),
+            system_prompt_path: default_system_prompt_path(),
+            system_prompt_replace: false,
             skills: SkillsConfig::default(),
+            persona_paths: default_persona_paths(),
             custom_models: Vec::new(),
+            fallback_models: Vec::new(),
             max_retries: default_max_retries(),
             retry_base_delay_ms: default_retry_base_delay_ms(),
             sandbox: SandboxConfig::default(),
             tool_max_retries: default_tool_max_retries(),
+            tool_timeout_ms: default_tool_timeout_ms(),
+            request_timeout_ms: default_request_timeout_ms(),
+            connect_timeout_ms: default_connect_timeout_ms(),
             telemetry: TelemetryConfig::default(),
             langfuse: LangfuseConfig::default(),
             router: RouterConfig::default(),
             auto_approve_tools: Vec::new(),
-            max_tool_result_chars: default_max_tool_result_chars(),
+            auto_approve: Vec::new(),
+            auto_approve_all: false,
+            never_auto_approve: Vec::new(),
+            bash_allow: Vec::new(),
+            bash_deny: Vec::new(),
+            max_tool_output_bytes: default_max_tool_output_bytes(),
+            tool_output_hexdump_bytes: default_tool_output_hexdump_bytes(),
+            tool_output_pretty_json: default_tool_output_pretty_json(),
+            tool_output_json_outline_bytes: default_tool_output_json_outline_bytes(),
+            search_max_results: default_search_max_results(),
+            sysinfo_env_vars: default_sysinfo_env_vars(),
+            project_root: None,
+            confine_to_root: false,
+            write_backups: false,
+            write_backup_suffix: default_write_backup_suffix(),
+            snapshot_max_file_bytes: default_snapshot_max_file_bytes(),
+            snapshot_max_count: default_snapshot_max_count(),
+            snapshot_max_total_bytes: default_snapshot_max_total_bytes(),
+            mcp_cache_ttl_secs: default_mcp_cache_ttl_secs(),
+            delegate_token_budget: 0,
+            max_concurrent_subagents: default_max_concurrent_subagents(),
+            max_concurrent_requests: 0,
+            enable_prompt_caching: false,
+            thinking_budget_tokens: 0,
+            reasoning_effort: None,
+            max_output_tokens: None,
+            stop_sequences: Vec::new(),
+            openai_api: OpenAiApi::default(),
+            render_markdown: default_render_markdown(),
+            highlight_code: default_highlight_code(),
+            input_max_lines: default_input_max_lines(),
+            parallel_tools: false,
+            dry_run: false,
+            trace_http: default_trace_http(),
+            model_context_limits: HashMap::new(),
+            context_thresholds: default_context_thresholds(),
+            compact_keep_tool_results: default_compact_keep_tool_results(),
+            session_titles: default_session_titles(),
+            session_title_model: String::new(),
+            branding: BrandingConfig::default(),
+            sqlite: SqliteConfig::default(),
+            keybindings: KeybindConfig::default(),
         }
     }
 }
@@ -318,7 +1007,9 @@ impl KrabsConfig {
 
         let mut config = if config_path.exists() {
             let data = std::fs::read_to_string(&config_path)?;
-            serde_json::from_str::<KrabsConfig>(&data)?
+            let mut value: serde_json::Value = serde_json::from_str(&data)?;
+            rename_legacy_keys(&mut value);
+            serde_json::from_value(value)?
         } else {
             KrabsConfig::default()
         };
@@ -330,16 +1021,7 @@ impl KrabsConfig {
 
         if let Some(local) = local_path {
             let data = std::fs::read_to_string(local)?;
-            let override_val: serde_json::Value = serde_json::from_str(&data)?;
-            let mut base = serde_json::to_value(&config)?;
-            if let (Some(base_obj), Some(over_obj)) =
-                (base.as_object_mut(), override_val.as_object())
-            {
-                for (k, v) in over_obj {
-                    base_obj.insert(k.clone(), v.clone());
-                }
-            }
-            config = serde_json::from_value(base)?;
+            config = config.merged_with(serde_json::from_str(&data)?)?;
         }
 
         // Environment variable overrides — always take highest precedence.
@@ -375,6 +1057,66 @@ impl KrabsConfig {
         Ok(config)
     }
 
+    /// Context window for `model`, resolved in order: a `custom_models` entry
+    /// with a `context_window`, an exact `model_context_limits` key, the longest
+    /// `model_context_limits` key contained in the name, the built-in table,
+    /// and finally a conservative default.
+    pub fn context_limit(&self, model: &str) -> u32 {
+        if let Some(n) = self
+            .custom_models
+            .iter()
+            .find(|m| m.model == model)
+            .and_then(|m| m.context_window)
+        {
+            return n;
+        }
+        if let Some(&n) = self.model_context_limits.get(model) {
+            return n;
+        }
+        let m = model.to_lowercase();
+        let configured = self
+            .model_context_limits
+            .iter()
+            .filter(|(k, _)| m.contains(&k.to_lowercase()))
+            .max_by_key(|(k, _)| k.len())
+            .map(|(_, &n)| n);
+        configured
+            .or_else(|| {
+                BUILTIN_CONTEXT_LIMITS
+                    .iter()
+                    .find(|(k, _)| m.contains(k))
+                    .map(|&(_, n)| n)
+            })
+            .unwrap_or(DEFAULT_CONTEXT_LIMIT)
+    }
+
+    /// The configured `project_root`, or the detected one.
+    pub fn resolved_project_root(&self) -> PathBuf {
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        match &self.project_root {
+            Some(root) => cwd.join(root),
+            None => crate::tools::root::detect_project_root(&cwd).unwrap_or(cwd),
+        }
+    }
+
+    /// This config with the top-level keys of `overrides` (a `.krabs.json`)
+    /// put over it.
+    fn merged_with(&self, mut overrides: serde_json::Value) -> Result<Self> {
+        rename_legacy_keys(&mut overrides);
+        let mut base = serde_json::to_value(self)?;
+        if let (Some(base_obj), Some(over_obj)) = (base.as_object_mut(), overrides.as_object()) {
+            for (k, v) in over_obj {
+                base_obj.insert(k.clone(), v.clone());
+            }
+        }
+        Ok(serde_json::from_value(base)?)
+    }
+
+    /// The backup suffix for the `write` tool, or `None` with backups off.
+    pub fn backup_suffix(&self) -> Option<String> {
+        self.write_backups.then(|| self.write_backup_suffix.clone())
+    }
+
     pub fn resolve_path(relative: &str) -> PathBuf {
         dirs::home_dir()
             .unwrap_or_else(|| PathBuf::from("."))
@@ -382,3 +1124,64 @@ impl KrabsConfig {
             .join(relative)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_table_prefers_specific_names() {
+        let config = KrabsConfig::default();
+        assert_eq!(config.context_limit("gpt-4o-mini"), 128_000);
+        assert_eq!(config.context_limit("gpt-4-0613"), 8_192);
+        assert_eq!(config.context_limit("claude-sonnet-4-6"), 200_000);
+    }
+
+    #[test]
+    fn local_overrides_read_the_legacy_output_cap_key() {
+        let local = serde_json::json!({
+            "max_tool_result_chars": 9000,
+            "bash_deny": ["rm -rf *"],
+        });
+        let config = KrabsConfig::default().merged_with(local).unwrap();
+        assert_eq!(config.max_tool_output_bytes, 9000);
+        assert_eq!(config.bash_deny, ["rm -rf *"]);
+
+        let both = serde_json::json!({
+            "max_tool_result_chars": 9000,
+            "max_tool_output_bytes": 4096,
+        });
+        let config = KrabsConfig::default().merged_with(both).unwrap();
+        assert_eq!(config.max_tool_output_bytes, 4096);
+    }
+
+    #[test]
+    fn unknown_models_get_the_conservative_default() {
+        let config = KrabsConfig::default();
+        assert_eq!(config.context_limit("my-finetune"), DEFAULT_CONTEXT_LIMIT);
+    }
+
+    #[test]
+    fn config_map_overrides_builtin_and_longest_key_wins() {
+        let mut config = KrabsConfig::default();
+        config.model_context_limits.insert("gpt-4o".into(), 64_000);
+        config.model_context_limits.insert("gpt".into(), 4_000);
+        assert_eq!(config.context_limit("gpt-4o-2024-08-06"), 64_000);
+        assert_eq!(config.context_limit("gpt-3.5-turbo"), 4_000);
+    }
+
+    #[test]
+    fn custom_model_context_window_wins() {
+        let mut config = KrabsConfig::default();
+        config.model_context_limits.insert("llama3.2".into(), 8_000);
+        config.custom_models.push(CustomModelEntry {
+            name: "local".into(),
+            provider: "openai".into(),
+            base_url: "http://localhost:8080/v1".into(),
+            api_key: String::new(),
+            model: "llama3.2".into(),
+            context_window: Some(16_384),
+        });
+        assert_eq!(config.context_limit("llama3.2"), 16_384);
+    }
+}
@@ -1,14 +1,57 @@
-use crate::providers::provider::LlmProvider;
-use crate::providers::{AnthropicProvider, GeminiProvider, OpenAiProvider};
+use crate::config::config::{
+    default_connect_timeout_ms, default_request_timeout_ms, output_limit, KrabsConfig,
+};
+use crate::providers::provider::{LlmProvider, ReasoningEffort};
+use crate::providers::redact::trace_http_from_env;
+use crate::providers::{AnthropicProvider, GeminiProvider, OpenAiApi, OpenAiProvider};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Profile name given to a flat, single-profile `credentials.json`.
+pub const DEFAULT_PROFILE: &str = "default";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Credentials {
     pub provider: String,
+    #[serde(default)]
     pub api_key: String,
+    /// Empty means the provider's default endpoint.
+    #[serde(default)]
     pub base_url: String,
+    /// Empty means the provider's default model.
+    #[serde(default)]
     pub model: String,
+    #[serde(default)]
     pub is_default: bool,
+    /// Mirrors `KrabsConfig::enable_prompt_caching`; never written to disk.
+    #[serde(skip)]
+    pub prompt_caching: bool,
+    /// Mirrors `KrabsConfig::thinking_budget_tokens`; never written to disk.
+    #[serde(skip)]
+    pub thinking_budget: u32,
+    /// Mirrors `KrabsConfig::reasoning_effort`; never written to disk.
+    #[serde(skip)]
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// Mirrors `KrabsConfig::max_output_tokens`; never written to disk.
+    #[serde(skip)]
+    pub max_output_tokens: Option<u32>,
+    /// Mirrors `KrabsConfig::stop_sequences`; never written to disk.
+    #[serde(skip)]
+    pub stop_sequences: Vec<String>,
+    /// Mirrors `KrabsConfig::openai_api`; never written to disk.
+    #[serde(skip)]
+    pub openai_api: OpenAiApi,
+    /// Mirrors `KrabsConfig::trace_http`; never written to disk.
+    #[serde(skip)]
+    pub trace_http: bool,
+    /// Mirrors `KrabsConfig::request_timeout_ms`; never written to disk.
+    #[serde(skip, default = "default_request_timeout_ms")]
+    pub request_timeout_ms: u64,
+    /// Mirrors `KrabsConfig::connect_timeout_ms`; never written to disk.
+    #[serde(skip, default = "default_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
 }
 
 impl Credentials {
@@ -36,9 +79,47 @@ impl Credentials {
             base_url,
             model,
             is_default: true,
+            prompt_caching: false,
+            thinking_budget: 0,
+            reasoning_effort: None,
+            max_output_tokens: None,
+            stop_sequences: Vec::new(),
+            openai_api: OpenAiApi::default(),
+            trace_http: trace_http_from_env(),
+            request_timeout_ms: default_request_timeout_ms(),
+            connect_timeout_ms: default_connect_timeout_ms(),
+        })
+    }
+
+    /// Load the default profile from `~/.krabs/credentials.json`.
+    /// Returns `Ok(None)` when the file does not exist or holds no profiles.
+    pub fn load() -> Result<Option<Self>> {
+        let profiles = CredentialProfiles::load()?;
+        Ok(profiles.default_name().and_then(|name| profiles.get(name)))
+    }
+
+    /// Load the profile called `name` from `~/.krabs/credentials.json`.
+    pub fn load_profile(name: &str) -> Result<Self> {
+        let profiles = CredentialProfiles::load()?;
+        profiles.get(name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "unknown credentials profile '{name}'. Available: {}",
+                profiles.names().join(", ")
+            )
         })
     }
 
+    /// Fill an empty `base_url` / `model` with the provider's defaults.
+    fn with_defaults(mut self) -> Self {
+        if self.base_url.is_empty() {
+            self.base_url = Self::default_base_url_for(&self.provider);
+        }
+        if self.model.is_empty() {
+            self.model = Self::default_model_for(&self.provider);
+        }
+        self
+    }
+
     fn default_base_url_for(provider: &str) -> String {
         match provider {
             "anthropic" => "https://api.anthropic.com".to_string(),
@@ -63,19 +144,198 @@ impl Credentials {
         self.provider == "ollama" || !self.api_key.is_empty()
     }
 
+    /// `max_output_tokens`, lowered to what the model supports.
+    pub fn output_token_cap(&self) -> Option<u32> {
+        let max = self.max_output_tokens?;
+        match output_limit(&self.model) {
+            Some(limit) if max > limit => {
+                tracing::warn!(
+                    "max_output_tokens {max} is over {}'s limit of {limit}; using {limit}",
+                    self.model
+                );
+                Some(limit)
+            }
+            _ => Some(max),
+        }
+    }
+
     pub fn build_provider(&self) -> Box<dyn LlmProvider> {
+        let max_output = self.output_token_cap();
+        let stop = self.stop_sequences.clone();
         match self.provider.as_str() {
-            "anthropic" => Box::new(AnthropicProvider::new(
-                &self.base_url,
-                &self.api_key,
-                &self.model,
-            )),
-            "gemini" | "google" => Box::new(GeminiProvider::new(&self.api_key, &self.model)),
-            _ => Box::new(OpenAiProvider::new(
-                &self.base_url,
-                &self.api_key,
-                &self.model,
-            )),
+            "anthropic" => Box::new(
+                AnthropicProvider::new(&self.base_url, &self.api_key, &self.model)
+                    .with_prompt_caching(self.prompt_caching)
+                    .with_thinking_budget(self.thinking_budget)
+                    .with_reasoning_effort(self.reasoning_effort)
+                    .with_max_output_tokens(max_output)
+                    .with_stop_sequences(stop)
+                    .with_http_trace(self.trace_http)
+                    .with_timeouts(self.request_timeout_ms, self.connect_timeout_ms),
+            ),
+            "gemini" | "google" => Box::new(
+                GeminiProvider::new(&self.api_key, &self.model)
+                    .with_max_output_tokens(max_output)
+                    .with_stop_sequences(stop)
+                    .with_http_trace(self.trace_http)
+                    .with_timeouts(self.request_timeout_ms, self.connect_timeout_ms),
+            ),
+            "azure" => Box::new(
+                OpenAiProvider::azure_from_url(&self.base_url, &self.model, &self.api_key)
+                    .with_reasoning_effort(self.reasoning_effort)
+                    .with_max_output_tokens(max_output)
+                    .with_stop_sequences(stop)
+                    .with_api(self.openai_api)
+                    .with_http_trace(self.trace_http)
+                    .with_timeouts(self.request_timeout_ms, self.connect_timeout_ms),
+            ),
+            _ => Box::new(
+                OpenAiProvider::new(&self.base_url, &self.api_key, &self.model)
+                    .with_reasoning_effort(self.reasoning_effort)
+                    .with_max_output_tokens(max_output)
+                    .with_stop_sequences(stop)
+                    .with_api(self.openai_api)
+                    .with_http_trace(self.trace_http)
+                    .with_timeouts(self.request_timeout_ms, self.connect_timeout_ms),
+            ),
+        }
+    }
+}
+
+/// Named credential sets stored in `~/.krabs/credentials.json`.
+///
+/// The file is either a map of profiles:
+/// ```json
+/// { "default_profile": "work", "profiles": { "work": { "provider": "anthropic", ... } } }
+/// ```
+/// or, for backward compatibility, a single flat [`Credentials`] object, which
+/// is read as one profile named [`DEFAULT_PROFILE`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CredentialProfiles {
+    #[serde(default)]
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Credentials>,
+}
+
+impl CredentialProfiles {
+    pub fn path() -> PathBuf {
+        KrabsConfig::resolve_path("credentials.json")
+    }
+
+    /// Read `~/.krabs/credentials.json`; a missing file yields no profiles.
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        if !path.exists() {
+            return Ok(Self::default());
         }
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse either file format (see the type docs).
+    pub fn parse(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        if value.get("profiles").is_some() {
+            return Ok(serde_json::from_value(value)?);
+        }
+        let single: Credentials = serde_json::from_value(value)?;
+        Ok(Self {
+            default_profile: Some(DEFAULT_PROFILE.to_string()),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), single)]),
+        })
+    }
+
+    /// Profile names in alphabetical order.
+    pub fn names(&self) -> Vec<&str> {
+        self.profiles.keys().map(String::as_str).collect()
+    }
+
+    /// The profile to use when none is named: `default_profile`, else the one
+    /// flagged `is_default`, else the only profile.
+    pub fn default_name(&self) -> Option<&str> {
+        if let Some(name) = self.default_profile.as_deref() {
+            return Some(name);
+        }
+        self.profiles
+            .iter()
+            .find(|(_, c)| c.is_default)
+            .map(|(name, _)| name.as_str())
+            .or_else(|| match self.profiles.len() {
+                1 => self.profiles.keys().next().map(String::as_str),
+                _ => None,
+            })
+    }
+
+    /// A copy of profile `name` with provider defaults filled in.
+    pub fn get(&self, name: &str) -> Option<Credentials> {
+        self.profiles
+            .get(name)
+            .cloned()
+            .map(Credentials::with_defaults)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_file_is_a_single_default_profile() {
+        let profiles = CredentialProfiles::parse(
+            r#"{"provider": "anthropic", "api_key": "sk-ant-x", "base_url": "https://api.anthropic.com", "model": "claude-sonnet-4-6", "is_default": true}"#,
+        )
+        .unwrap();
+        assert_eq!(profiles.default_name(), Some(DEFAULT_PROFILE));
+        let creds = profiles.get(DEFAULT_PROFILE).unwrap();
+        assert_eq!(creds.provider, "anthropic");
+        assert_eq!(creds.model, "claude-sonnet-4-6");
+    }
+
+    #[test]
+    fn named_profiles_fill_in_provider_defaults() {
+        let profiles = CredentialProfiles::parse(
+            r#"{
+                "default_profile": "work",
+                "profiles": {
+                    "work": {"provider": "anthropic", "api_key": "sk-ant-x"},
+                    "home": {"provider": "openai", "api_key": "sk-y", "model": "gpt-4o-mini"}
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(profiles.names(), vec!["home", "work"]);
+        assert_eq!(profiles.default_name(), Some("work"));
+        let work = profiles.get("work").unwrap();
+        assert_eq!(work.base_url, "https://api.anthropic.com");
+        assert_eq!(work.model, "claude-opus-4-6");
+        assert_eq!(profiles.get("home").unwrap().model, "gpt-4o-mini");
+        assert!(profiles.get("missing").is_none());
+    }
+
+    #[test]
+    fn is_default_flag_picks_the_default_without_default_profile() {
+        let profiles = CredentialProfiles::parse(
+            r#"{"profiles": {
+                "a": {"provider": "openai"},
+                "b": {"provider": "ollama", "is_default": true}
+            }}"#,
+        )
+        .unwrap();
+        assert_eq!(profiles.default_name(), Some("b"));
+    }
+
+    #[test]
+    fn output_token_cap_stops_at_the_model_limit() {
+        let mut creds = CredentialProfiles::parse(r#"{"provider": "openai", "model": "gpt-4o"}"#)
+            .unwrap()
+            .get(DEFAULT_PROFILE)
+            .unwrap();
+        assert_eq!(creds.output_token_cap(), None);
+        creds.max_output_tokens = Some(4_000);
+        assert_eq!(creds.output_token_cap(), Some(4_000));
+        creds.max_output_tokens = Some(50_000);
+        assert_eq!(creds.output_token_cap(), Some(16_384));
+        creds.model = "my-local-model".into();
+        assert_eq!(creds.output_token_cap(), Some(50_000));
     }
 }
@@ -2,4 +2,4 @@
 pub mod config;
 pub mod credentials;
 pub use config::{KrabsConfig, SkillsConfig};
-pub use credentials::Credentials;
+pub use credentials::{CredentialProfiles, Credentials};
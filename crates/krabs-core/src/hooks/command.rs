@@ -0,0 +1,291 @@
+use crate::hooks::config::HookEntry;
+use crate::hooks::hook::{Hook, HookEvent, HookOutput, ToolUseDecision};
+use crate::sandbox::tool::sandboxed_bash;
+use crate::sandbox::SandboxConfig;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+/// An `exec` command that prints this prefix followed by a JSON object on a
+/// successful `PreToolUse` replaces the tool's arguments with that object.
+pub const MODIFY_ARGS_PREFIX: &str = "modify_args:";
+
+/// An `exec` command that prints this prefix followed by text on a successful
+/// `UserMessage` replaces the user's message with that text.
+pub const REPLACE_MESSAGE_PREFIX: &str = "replace_message:";
+
+/// How long an `exec` command may run when the entry sets no `timeout_ms`.
+pub const DEFAULT_EXEC_TIMEOUT_MS: u64 = 10_000;
+
+/// Runs one [`HookEntry`] from `~/.krabs/hooks.json`.
+///
+/// Actions:
+/// - `deny` — deny the tool call on `PreToolUse`, with `reason`
+/// - `stop` — halt the agent
+/// - `log` — log the event at `info` level
+/// - `exec` — run `command` (or `reason`) with `bash -c`, the event JSON on
+///   stdin. On `PreToolUse` a nonzero exit denies the call with stdout (or
+///   stderr) as the reason, and a successful run printing
+///   [`MODIFY_ARGS_PREFIX`]`{…}` replaces the arguments. On `UserMessage` a
+///   successful run printing [`REPLACE_MESSAGE_PREFIX`]`…` replaces the
+///   message. A missing command, one that can't be run or times out, or
+///   invalid `modify_args` JSON denies the call too, so a broken guardrail
+///   fails closed. On other events a failure is only logged.
+pub struct ConfigHook {
+    entry: HookEntry,
+    /// Sandbox settings and proxy port that `exec` commands run under.
+    sandbox: Option<(Arc<SandboxConfig>, u16)>,
+}
+
+impl ConfigHook {
+    pub fn new(entry: HookEntry) -> Self {
+        Self {
+            entry,
+            sandbox: None,
+        }
+    }
+
+    /// Run `exec` commands the way sandboxed `bash` runs, through the proxy
+    /// on `proxy_port`.
+    pub fn with_sandbox(mut self, config: Arc<SandboxConfig>, proxy_port: u16) -> Self {
+        self.sandbox = Some((config, proxy_port));
+        self
+    }
+
+    fn deny(&self, reason: impl Into<String>) -> HookOutput {
+        HookOutput::ToolDecision(ToolUseDecision::Deny {
+            reason: reason.into(),
+        })
+    }
+
+    async fn exec(&self, command: &str, event: &HookEvent) -> Result<HookOutput> {
+        let pre_tool_use = matches!(event, HookEvent::PreToolUse { .. });
+        let timeout_ms = self.entry.timeout_ms.unwrap_or(DEFAULT_EXEC_TIMEOUT_MS);
+        let run = tokio::time::timeout(
+            Duration::from_millis(timeout_ms),
+            self.run_command(command, event),
+        );
+        let output = match run.await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) if pre_tool_use => {
+                return Ok(self.deny(format!("hook '{}' failed: {e}", self.entry.name)))
+            }
+            Ok(Err(e)) => return Err(e),
+            Err(_) if pre_tool_use => {
+                return Ok(self.deny(format!(
+                    "hook '{}' timed out after {timeout_ms}ms",
+                    self.entry.name
+                )))
+            }
+            Err(_) => anyhow::bail!("command timed out after {timeout_ms}ms"),
+        };
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stdout = stdout.trim();
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = match (stdout, stderr.trim()) {
+                ("", "") => format!("hook '{}' exited with {}", self.entry.name, output.status),
+                ("", stderr) => stderr.to_string(),
+                (stdout, _) => stdout.to_string(),
+            };
+            if pre_tool_use {
+                return Ok(self.deny(reason));
+            }
+            anyhow::bail!("command exited with {}: {reason}", output.status);
+        }
+
+        match stdout.strip_prefix(MODIFY_ARGS_PREFIX) {
+            Some(args) if pre_tool_use => match serde_json::from_str(args.trim()) {
+                Ok(args) => Ok(HookOutput::ToolDecision(ToolUseDecision::ModifyArgs {
+                    args,
+                })),
+                Err(e) => Ok(self.deny(format!(
+                    "hook '{}' printed invalid arguments: {e}",
+                    self.entry.name
+                ))),
+            },
+            _ => match stdout.strip_prefix(REPLACE_MESSAGE_PREFIX) {
+                Some(content) if matches!(event, HookEvent::UserMessage { .. }) => Ok(
+                    HookOutput::ReplaceUserMessage(content.trim_start().to_string()),
+                ),
+                _ => Ok(HookOutput::Continue),
+            },
+        }
+    }
+
+    async fn run_command(&self, command: &str, event: &HookEvent) -> Result<std::process::Output> {
+        let (mut cmd, _profile) = match &self.sandbox {
+            Some((config, port)) => sandboxed_bash(config, *port, command)?,
+            None => {
+                let mut cmd = tokio::process::Command::new("bash");
+                cmd.arg("-c").arg(command);
+                (cmd, None)
+            }
+        };
+        let mut child = cmd
+            .env("KRABS_HOOK_EVENT", event.name())
+            .env("KRABS_TOOL_NAME", event.tool_name().unwrap_or_default())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("failed to run '{command}': {e}"))?;
+
+        let payload = serde_json::to_vec(event)?;
+        let stdin = child.stdin.take();
+        let write = async move {
+            if let Some(mut stdin) = stdin {
+                // A command that exits without reading stdin is fine.
+                let _ = stdin.write_all(&payload).await;
+            }
+        };
+        let (_, output) = tokio::join!(write, child.wait_with_output());
+        Ok(output?)
+    }
+}
+
+#[async_trait]
+impl Hook for ConfigHook {
+    fn matcher(&self) -> Option<&str> {
+        self.entry.matcher.as_deref()
+    }
+
+    async fn on_event(&self, event: &HookEvent) -> Result<HookOutput> {
+        if !self.entry.event.eq_ignore_ascii_case(event.name()) {
+            return Ok(HookOutput::Continue);
+        }
+        match self.entry.action.as_str() {
+            "deny" if matches!(event, HookEvent::PreToolUse { .. }) => Ok(self.deny(
+                self.entry
+                    .reason
+                    .clone()
+                    .unwrap_or_else(|| format!("denied by hook '{}'", self.entry.name)),
+            )),
+            "stop" => Ok(HookOutput::Stop),
+            "log" => {
+                info!(hook = %self.entry.name, "{}", serde_json::to_string(event)?);
+                Ok(HookOutput::Continue)
+            }
+            "exec" => {
+                let command = self
+                    .entry
+                    .command
+                    .as_deref()
+                    .or(self.entry.reason.as_deref());
+                match command {
+                    Some(command) => self.exec(command, event).await,
+                    None if matches!(event, HookEvent::PreToolUse { .. }) => {
+                        Ok(self.deny(format!("exec hook '{}' has no command", self.entry.name)))
+                    }
+                    None => anyhow::bail!("exec hook '{}' has no command", self.entry.name),
+                }
+            }
+            _ => Ok(HookOutput::Continue),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn exec_hook(command: &str) -> ConfigHook {
+        ConfigHook::new(HookEntry {
+            name: "guard".into(),
+            event: "PreToolUse".into(),
+            matcher: Some("bash".into()),
+            action: "exec".into(),
+            reason: None,
+            command: Some(command.into()),
+            timeout_ms: Some(2_000),
+        })
+    }
+
+    fn pre_tool_use(cmd: &str) -> HookEvent {
+        HookEvent::PreToolUse {
+            tool_name: "bash".into(),
+            args: json!({ "command": cmd }),
+            tool_use_id: "t1".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn exec_decides_from_exit_code_and_stdout() {
+        // Deny anything mentioning rm, reading the event from stdin.
+        let guard = exec_hook("if grep -q 'rm ' ; then echo 'no deleting'; exit 1; fi");
+        match guard.on_event(&pre_tool_use("rm -rf /")).await.unwrap() {
+            HookOutput::ToolDecision(ToolUseDecision::Deny { reason }) => {
+                assert_eq!(reason, "no deleting")
+            }
+            other => panic!("expected Deny, got {other:?}"),
+        }
+        assert!(matches!(
+            guard.on_event(&pre_tool_use("ls")).await.unwrap(),
+            HookOutput::Continue
+        ));
+
+        let rewrite = exec_hook(r#"echo 'modify_args: {"command": "ls -la"}'"#);
+        match rewrite.on_event(&pre_tool_use("ls")).await.unwrap() {
+            HookOutput::ToolDecision(ToolUseDecision::ModifyArgs { args }) => {
+                assert_eq!(args, json!({ "command": "ls -la" }))
+            }
+            other => panic!("expected ModifyArgs, got {other:?}"),
+        }
+
+        let mut scrub = exec_hook("echo 'replace_message: use [key]'");
+        scrub.entry.event = "UserMessage".into();
+        let message = HookEvent::UserMessage {
+            content: "use sk-abc123".into(),
+        };
+        match scrub.on_event(&message).await.unwrap() {
+            HookOutput::ReplaceUserMessage(content) => assert_eq!(content, "use [key]"),
+            other => panic!("expected ReplaceUserMessage, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn exec_fails_closed_on_timeout_and_ignores_other_events() {
+        let mut slow = exec_hook("sleep 5");
+        slow.entry.timeout_ms = Some(50);
+        assert!(matches!(
+            slow.on_event(&pre_tool_use("ls")).await.unwrap(),
+            HookOutput::ToolDecision(ToolUseDecision::Deny { .. })
+        ));
+        assert!(matches!(
+            slow.on_event(&HookEvent::TurnStart { turn: 0 })
+                .await
+                .unwrap(),
+            HookOutput::Continue
+        ));
+    }
+
+    #[tokio::test]
+    async fn exec_denies_on_invalid_modify_args() {
+        let broken = exec_hook("echo 'modify_args: {not json'");
+        match broken.on_event(&pre_tool_use("ls")).await.unwrap() {
+            HookOutput::ToolDecision(ToolUseDecision::Deny { reason }) => {
+                assert!(reason.contains("invalid arguments"), "{reason}")
+            }
+            other => panic!("expected Deny, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn exec_without_a_command_denies() {
+        let mut empty = exec_hook("");
+        empty.entry.command = None;
+        match empty.on_event(&pre_tool_use("ls")).await.unwrap() {
+            HookOutput::ToolDecision(ToolUseDecision::Deny { reason }) => {
+                assert!(reason.contains("has no command"), "{reason}")
+            }
+            other => panic!("expected Deny, got {other:?}"),
+        }
+    }
+}
@@ -16,15 +16,23 @@ pub struct HookEntry {
     pub name: String,
     /// Lifecycle event this hook listens to.
     /// One of: AgentStart, AgentStop, TurnStart, TurnEnd,
-    ///         PreToolUse, PostToolUse, PostToolUseFailure
+    ///         PreToolUse, PostToolUse, PostToolUseFailure, ContextThreshold,
+    ///         UserMessage
     pub event: String,
     /// Optional regex matched against the tool name (tool events only).
     pub matcher: Option<String>,
     /// What to do when the event fires.
-    /// One of: deny, stop, log
+    /// One of: deny, stop, log, exec
     pub action: String,
-    /// Reason string (used for `deny` action).
+    /// Reason string (used for `deny` action). An `exec` hook without a
+    /// `command` runs this instead.
     pub reason: Option<String>,
+    /// Shell command run by the `exec` action; see [`ConfigHook`](super::ConfigHook).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Milliseconds an `exec` command may run. Default: 10000.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -34,9 +34,36 @@ pub enum HookEvent {
         error: String,
         tool_use_id: String,
     },
+    /// Fired with each new user message, before it is persisted or sent to
+    /// the LLM. Hooks may return `ReplaceUserMessage` to rewrite it or
+    /// `AppendContext` to add text after it.
+    UserMessage { content: String },
+    /// Fired at the top of a turn when context usage first reaches one of the
+    /// configured `context_thresholds`. `pct` is the boundary crossed; fires at
+    /// most once per boundary per run. Hooks may return `Stop` or `Compact`.
+    ContextThreshold {
+        pct: u8,
+        input_tokens: u32,
+        output_tokens: u32,
+    },
 }
 
 impl HookEvent {
+    /// The event's name as written in `hooks.json`, e.g. `PreToolUse`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::AgentStart { .. } => "AgentStart",
+            Self::AgentStop { .. } => "AgentStop",
+            Self::TurnStart { .. } => "TurnStart",
+            Self::TurnEnd { .. } => "TurnEnd",
+            Self::PreToolUse { .. } => "PreToolUse",
+            Self::PostToolUse { .. } => "PostToolUse",
+            Self::PostToolUseFailure { .. } => "PostToolUseFailure",
+            Self::ContextThreshold { .. } => "ContextThreshold",
+            Self::UserMessage { .. } => "UserMessage",
+        }
+    }
+
     /// Returns the tool name if this is a tool-related event.
     pub fn tool_name(&self) -> Option<&str> {
         match self {
@@ -70,19 +97,27 @@ pub enum HookOutput {
     Continue,
     /// PreToolUse only: control whether/how the tool executes.
     ToolDecision(ToolUseDecision),
-    /// PostToolUse only: append text to the tool result visible to the LLM.
+    /// PostToolUse: append text to the tool result visible to the LLM.
+    /// UserMessage: append text to the user's message.
     AppendContext(String),
+    /// UserMessage only: replace the user's message with this text.
+    ReplaceUserMessage(String),
     /// Inject an extra system message into the conversation this turn.
     SystemMessage(String),
     /// Halt the agent after this hook fires.
     Stop,
+    /// ContextThreshold only: summarize the conversation so far and continue
+    /// from the summary.
+    Compact,
 }
 
 /// A hook that intercepts agent lifecycle events.
 ///
 /// Register hooks via `KrabsAgentBuilder::hook()`. Multiple hooks are executed
-/// in registration order; for `PreToolUse`, `Deny` takes priority over
-/// `ModifyArgs`, which takes priority over `Allow`.
+/// in registration order. For `PreToolUse`, the first `Deny` wins and the
+/// hooks after it are not called, and each `ModifyArgs` is applied on top of
+/// the previous ones; see [`HookRegistry::fire`](super::HookRegistry::fire)
+/// for the full rules.
 #[async_trait]
 pub trait Hook: Send + Sync {
     /// Optional regex pattern matched against the tool name for tool events.
@@ -431,6 +431,8 @@ impl Hook for LangfuseHook {
                 self.send(BatchPayload::single(make_event("trace-create", body)))
                     .await;
             }
+
+            HookEvent::ContextThreshold { .. } | HookEvent::UserMessage { .. } => {}
         }
 
         Ok(HookOutput::Continue)
@@ -1,9 +1,12 @@
+pub mod command;
 pub mod config;
 pub mod hook;
 pub mod langfuse;
+mod otlp;
 pub mod registry;
 pub mod telemetry;
 
+pub use command::ConfigHook;
 pub use config::{HookConfig, HookEntry};
 pub use hook::{Hook, HookEvent, HookOutput, ToolUseDecision};
 pub use langfuse::{LangfuseHook, LangfuseHookBuilder};
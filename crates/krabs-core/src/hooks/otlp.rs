@@ -0,0 +1,282 @@
+//! OTLP span export for [`TelemetryHook`](crate::hooks::telemetry::TelemetryHook).
+//!
+//! Mirrors the Langfuse mapping so agent runs show up in Jaeger, Grafana Tempo
+//! or any other OTLP collector next to the host application's own traces.
+//!
+//! # Mapping
+//! | HookEvent            | OTLP effect                                   |
+//! |----------------------|-----------------------------------------------|
+//! | `AgentStart`         | Starts the root `agent-run` span              |
+//! | `TurnStart`          | Starts a `turn-N` span, child of the root     |
+//! | `PreToolUse`         | Starts a span named after the tool, child of the current turn |
+//! | `PostToolUse`        | Ends the tool span with `krabs.tool.output`   |
+//! | `PostToolUseFailure` | Ends the tool span with an error status       |
+//! | `TurnEnd`            | Ends the turn span                            |
+//! | `AgentStop`          | Ends the root span with `krabs.result`        |
+
+use crate::hooks::hook::HookEvent;
+use anyhow::Result;
+use opentelemetry::trace::{Status, TraceContextExt, Tracer, TracerProvider as _};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+const SERVICE_NAME: &str = "krabs";
+
+/// Attribute values longer than this are truncated so one huge tool output
+/// can't blow the collector's span size limit.
+const MAX_ATTRIBUTE_BYTES: usize = 8_192;
+
+fn truncate(s: &str) -> String {
+    let mut end = s.len().min(MAX_ATTRIBUTE_BYTES);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s[..end].to_string()
+}
+
+// ---------------------------------------------------------------------------
+// Internal state shared across async on_event calls
+// ---------------------------------------------------------------------------
+
+#[derive(Default)]
+struct OtlpState {
+    /// Context holding the root span started on AgentStart
+    trace: Option<Context>,
+    /// turn index → context holding the turn span
+    turn_spans: HashMap<usize, Context>,
+    /// tool_use_id → context holding the tool span
+    tool_spans: HashMap<String, Context>,
+    /// Most recent open turn index (for parenting tool spans)
+    current_turn: Option<usize>,
+}
+
+/// Converts hook events into OTLP spans and ships them with a batching
+/// exporter over OTLP/HTTP.
+pub(crate) struct OtlpExporter {
+    // Held so the batch processor keeps running; dropping it flushes and shuts down.
+    _provider: TracerProvider,
+    tracer: opentelemetry_sdk::trace::Tracer,
+    session_id: Option<String>,
+    agent_id: Option<String>,
+    state: Mutex<OtlpState>,
+}
+
+impl OtlpExporter {
+    /// Build an exporter sending to `endpoint` (e.g. `http://localhost:4318/v1/traces`).
+    pub(crate) fn new(
+        endpoint: &str,
+        session_id: Option<String>,
+        agent_id: Option<String>,
+    ) -> Result<Self> {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(endpoint)
+            .build()?;
+        let provider = TracerProvider::builder()
+            .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+            .with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                SERVICE_NAME,
+            )]))
+            .build();
+        let tracer = provider.tracer(SERVICE_NAME);
+        Ok(Self {
+            _provider: provider,
+            tracer,
+            session_id,
+            agent_id,
+            state: Mutex::new(OtlpState::default()),
+        })
+    }
+
+    fn start_span(&self, name: String, parent: &Context, attrs: Vec<KeyValue>) -> Context {
+        let span = self
+            .tracer
+            .span_builder(name)
+            .with_attributes(attrs)
+            .start_with_context(&self.tracer, parent);
+        parent.with_span(span)
+    }
+
+    pub(crate) async fn record(&self, event: &HookEvent) {
+        let mut state = self.state.lock().await;
+        match event {
+            HookEvent::AgentStart { task } => {
+                // A new run starts a fresh trace; close anything left over
+                // from a run that never reached AgentStop.
+                for (_, cx) in state.tool_spans.drain() {
+                    cx.span().end();
+                }
+                for (_, cx) in state.turn_spans.drain() {
+                    cx.span().end();
+                }
+                if let Some(cx) = state.trace.take() {
+                    cx.span().end();
+                }
+                state.current_turn = None;
+
+                let mut attrs = vec![KeyValue::new("krabs.task", truncate(task))];
+                if let Some(sid) = &self.session_id {
+                    attrs.push(KeyValue::new("session.id", sid.clone()));
+                }
+                if let Some(aid) = &self.agent_id {
+                    attrs.push(KeyValue::new("krabs.agent_id", aid.clone()));
+                }
+                let root = Context::new();
+                state.trace = Some(self.start_span("agent-run".to_string(), &root, attrs));
+            }
+
+            HookEvent::TurnStart { turn } => {
+                let Some(trace) = state.trace.clone() else {
+                    return;
+                };
+                let attrs = vec![KeyValue::new("krabs.turn", *turn as i64)];
+                let cx = self.start_span(format!("turn-{turn}"), &trace, attrs);
+                state.turn_spans.insert(*turn, cx);
+                state.current_turn = Some(*turn);
+            }
+
+            HookEvent::PreToolUse {
+                tool_name,
+                args,
+                tool_use_id,
+            } => {
+                let Some(trace) = state.trace.as_ref() else {
+                    return;
+                };
+                let parent = state
+                    .current_turn
+                    .and_then(|t| state.turn_spans.get(&t))
+                    .unwrap_or(trace)
+                    .clone();
+                let attrs = vec![
+                    KeyValue::new("krabs.tool.name", tool_name.clone()),
+                    KeyValue::new("krabs.tool.use_id", tool_use_id.clone()),
+                    KeyValue::new("krabs.tool.args", truncate(&args.to_string())),
+                ];
+                let cx = self.start_span(tool_name.clone(), &parent, attrs);
+                state.tool_spans.insert(tool_use_id.clone(), cx);
+            }
+
+            HookEvent::PostToolUse {
+                result,
+                tool_use_id,
+                ..
+            } => {
+                if let Some(cx) = state.tool_spans.remove(tool_use_id) {
+                    let span = cx.span();
+                    span.set_attribute(KeyValue::new("krabs.tool.output", truncate(result)));
+                    span.set_status(Status::Ok);
+                    span.end();
+                }
+            }
+
+            HookEvent::PostToolUseFailure {
+                error, tool_use_id, ..
+            } => {
+                if let Some(cx) = state.tool_spans.remove(tool_use_id) {
+                    let span = cx.span();
+                    span.set_status(Status::error(truncate(error)));
+                    span.end();
+                }
+            }
+
+            HookEvent::TurnEnd { turn } => {
+                if let Some(cx) = state.turn_spans.remove(turn) {
+                    cx.span().end();
+                }
+                if state.current_turn == Some(*turn) {
+                    state.current_turn = None;
+                }
+            }
+
+            HookEvent::AgentStop { result } => {
+                if let Some(cx) = state.trace.take() {
+                    let span = cx.span();
+                    span.set_attribute(KeyValue::new("krabs.result", truncate(result)));
+                    span.end();
+                }
+            }
+
+            HookEvent::ContextThreshold { pct, .. } => {
+                if let Some(cx) = state
+                    .current_turn
+                    .and_then(|t| state.turn_spans.get(&t))
+                    .or(state.trace.as_ref())
+                {
+                    cx.span().add_event(
+                        "context_threshold",
+                        vec![KeyValue::new("krabs.context_pct", i64::from(*pct))],
+                    );
+                }
+            }
+
+            HookEvent::UserMessage { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn spans_are_opened_and_closed_per_turn_and_tool() {
+        let exporter =
+            OtlpExporter::new("http://127.0.0.1:1/v1/traces", None, Some("a".into())).unwrap();
+
+        exporter
+            .record(&HookEvent::AgentStart {
+                task: "t".to_string(),
+            })
+            .await;
+        exporter.record(&HookEvent::TurnStart { turn: 0 }).await;
+        exporter
+            .record(&HookEvent::PreToolUse {
+                tool_name: "read".to_string(),
+                args: json!({"path": "x"}),
+                tool_use_id: "id-1".to_string(),
+            })
+            .await;
+        {
+            let state = exporter.state.lock().await;
+            assert!(state.trace.is_some());
+            assert_eq!(state.turn_spans.len(), 1);
+            assert_eq!(state.tool_spans.len(), 1);
+        }
+
+        exporter
+            .record(&HookEvent::PostToolUse {
+                tool_name: "read".to_string(),
+                args: json!({"path": "x"}),
+                result: "ok".to_string(),
+                tool_use_id: "id-1".to_string(),
+            })
+            .await;
+        exporter.record(&HookEvent::TurnEnd { turn: 0 }).await;
+        exporter
+            .record(&HookEvent::AgentStop {
+                result: "done".to_string(),
+            })
+            .await;
+
+        let state = exporter.state.lock().await;
+        assert!(state.trace.is_none());
+        assert!(state.turn_spans.is_empty());
+        assert!(state.tool_spans.is_empty());
+        assert_eq!(state.current_turn, None);
+    }
+
+    #[test]
+    fn truncate_respects_char_boundaries() {
+        let s = "é".repeat(MAX_ATTRIBUTE_BYTES);
+        let t = truncate(&s);
+        assert!(t.len() <= MAX_ATTRIBUTE_BYTES);
+        assert!(t.chars().all(|c| c == 'é'));
+    }
+}
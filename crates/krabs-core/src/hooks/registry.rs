@@ -1,5 +1,6 @@
 use crate::hooks::hook::{Hook, HookEvent, HookOutput, ToolUseDecision};
 use regex::Regex;
+use std::borrow::Cow;
 use std::sync::Arc;
 use tracing::warn;
 
@@ -16,35 +17,54 @@ impl HookRegistry {
         self.hooks.push(hook);
     }
 
-    /// Fire all matching hooks for an event and return the resolved output.
+    /// Register `hooks` ahead of every hook registered so far, keeping their
+    /// order, so they get the first say in [`fire`](Self::fire).
+    pub fn register_first(&mut self, hooks: impl IntoIterator<Item = Arc<dyn Hook>>) {
+        self.hooks.splice(0..0, hooks);
+    }
+
+    /// Fire all matching hooks for an event, in registration order, and
+    /// return the resolved output.
     ///
     /// Resolution rules:
-    /// - `PreToolUse`: Deny > ModifyArgs > Allow (first match wins per tier)
-    /// - All other events: first Stop > first SystemMessage > first AppendContext > Continue
+    /// - `PreToolUse`: the first `Deny` wins and later hooks are not called.
+    ///   `ModifyArgs` compose: each later hook sees the arguments as modified
+    ///   so far, and the result carries the final arguments.
+    /// - `UserMessage`: `ReplaceUserMessage` compose the same way, and every
+    ///   `AppendContext` text is added after the final content. The result is
+    ///   a `ReplaceUserMessage` if any hook changed the message.
+    /// - All other events: Stop, then Compact, then the first SystemMessage,
+    ///   then AppendContext, then Continue. Every `AppendContext` text is
+    ///   kept, joined by newlines in registration order.
     pub async fn fire(&self, event: &HookEvent) -> HookOutput {
-        let matching: Vec<_> = self
+        // Collected up front: a lazy filter borrowing `self` across the
+        // awaits below makes the future not `Send`.
+        let matching: Vec<Arc<dyn Hook>> = self
             .hooks
             .iter()
             .filter(|h| self.matches(h.as_ref(), event))
+            .cloned()
             .collect();
 
         if matching.is_empty() {
             return HookOutput::Continue;
         }
 
-        let mut outputs = Vec::with_capacity(matching.len());
+        if matches!(event, HookEvent::PreToolUse { .. }) {
+            return fire_pre_tool_use(&matching, event).await;
+        }
+        if matches!(event, HookEvent::UserMessage { .. }) {
+            return fire_user_message(&matching, event).await;
+        }
+
+        let mut outputs = Vec::new();
         for hook in matching {
             match hook.on_event(event).await {
                 Ok(out) => outputs.push(out),
                 Err(e) => warn!("hook error on {:?}: {}", event.tool_name(), e),
             }
         }
-
-        if matches!(event, HookEvent::PreToolUse { .. }) {
-            resolve_pre_tool_use(outputs)
-        } else {
-            resolve_general(outputs)
-        }
+        resolve_general(outputs)
     }
 
     fn matches(&self, hook: &dyn Hook, event: &HookEvent) -> bool {
@@ -71,38 +91,93 @@ impl Default for HookRegistry {
     }
 }
 
-/// Deny > ModifyArgs > Allow
-fn resolve_pre_tool_use(outputs: Vec<HookOutput>) -> HookOutput {
-    let mut modify = None;
+/// Run `hooks` on a `PreToolUse` event: the first Deny short-circuits,
+/// ModifyArgs are applied in turn, anything else is no opinion.
+async fn fire_pre_tool_use(hooks: &[Arc<dyn Hook>], event: &HookEvent) -> HookOutput {
+    let mut current = Cow::Borrowed(event);
+    let mut modified = false;
 
-    for out in outputs {
-        match out {
-            HookOutput::ToolDecision(ToolUseDecision::Deny { .. }) => return out,
-            HookOutput::ToolDecision(ToolUseDecision::ModifyArgs { .. }) if modify.is_none() => {
-                modify = Some(out);
+    for hook in hooks {
+        match hook.on_event(&current).await {
+            Ok(HookOutput::ToolDecision(deny @ ToolUseDecision::Deny { .. })) => {
+                return HookOutput::ToolDecision(deny);
             }
-            _ => {}
+            Ok(HookOutput::ToolDecision(ToolUseDecision::ModifyArgs { args })) => {
+                if let HookEvent::PreToolUse { args: slot, .. } = current.to_mut() {
+                    *slot = args;
+                    modified = true;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!("hook error on {:?}: {}", event.tool_name(), e),
         }
     }
 
-    modify.unwrap_or(HookOutput::Continue)
+    match current {
+        Cow::Owned(HookEvent::PreToolUse { args, .. }) if modified => {
+            HookOutput::ToolDecision(ToolUseDecision::ModifyArgs { args })
+        }
+        _ => HookOutput::Continue,
+    }
 }
 
-/// Stop > SystemMessage > AppendContext > Continue
+/// Run `hooks` on a `UserMessage` event: replacements are applied in turn,
+/// appended context is collected and added after the final content.
+async fn fire_user_message(hooks: &[Arc<dyn Hook>], event: &HookEvent) -> HookOutput {
+    let mut current = Cow::Borrowed(event);
+    let mut appended: Vec<String> = Vec::new();
+
+    for hook in hooks {
+        match hook.on_event(&current).await {
+            Ok(HookOutput::ReplaceUserMessage(content)) => {
+                if let HookEvent::UserMessage { content: slot } = current.to_mut() {
+                    *slot = content;
+                }
+            }
+            Ok(HookOutput::AppendContext(text)) => appended.push(text),
+            Ok(_) => {}
+            Err(e) => warn!("hook error on UserMessage: {}", e),
+        }
+    }
+
+    let mut content = match current {
+        Cow::Owned(HookEvent::UserMessage { content }) => content,
+        Cow::Borrowed(HookEvent::UserMessage { content }) if !appended.is_empty() => {
+            content.clone()
+        }
+        _ => return HookOutput::Continue,
+    };
+    if !appended.is_empty() {
+        content.push_str("\n\n");
+        content.push_str(&appended.join("\n"));
+    }
+    HookOutput::ReplaceUserMessage(content)
+}
+
+/// Stop > Compact > SystemMessage > AppendContext (concatenated) > Continue
 fn resolve_general(outputs: Vec<HookOutput>) -> HookOutput {
+    let mut compact = false;
     let mut system_msg = None;
-    let mut append_ctx = None;
+    let mut append_ctx: Vec<String> = Vec::new();
 
     for out in outputs {
         match out {
             HookOutput::Stop => return HookOutput::Stop,
+            HookOutput::Compact => compact = true,
             HookOutput::SystemMessage(_) if system_msg.is_none() => system_msg = Some(out),
-            HookOutput::AppendContext(_) if append_ctx.is_none() => append_ctx = Some(out),
+            HookOutput::AppendContext(text) => append_ctx.push(text),
             _ => {}
         }
     }
 
-    system_msg.or(append_ctx).unwrap_or(HookOutput::Continue)
+    if compact {
+        return HookOutput::Compact;
+    }
+    system_msg
+        .or_else(|| {
+            (!append_ctx.is_empty()).then(|| HookOutput::AppendContext(append_ctx.join("\n")))
+        })
+        .unwrap_or(HookOutput::Continue)
 }
 
 #[cfg(test)]
@@ -112,6 +187,7 @@ mod tests {
     use anyhow::Result;
     use async_trait::async_trait;
     use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     // ── helpers ──────────────────────────────────────────────────────────────
 
@@ -164,6 +240,38 @@ mod tests {
         }
     }
 
+    /// Sets one key of the `PreToolUse` args it is given.
+    struct SetArg {
+        key: &'static str,
+        value: serde_json::Value,
+        calls: AtomicUsize,
+    }
+
+    impl SetArg {
+        fn new(key: &'static str, value: serde_json::Value) -> Arc<Self> {
+            Arc::new(Self {
+                key,
+                value,
+                calls: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Hook for SetArg {
+        async fn on_event(&self, event: &HookEvent) -> Result<HookOutput> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let HookEvent::PreToolUse { args, .. } = event else {
+                return Ok(HookOutput::Continue);
+            };
+            let mut args = args.clone();
+            args[self.key] = self.value.clone();
+            Ok(HookOutput::ToolDecision(ToolUseDecision::ModifyArgs {
+                args,
+            }))
+        }
+    }
+
     struct ErrorHook;
 
     #[async_trait]
@@ -182,7 +290,7 @@ mod tests {
         assert!(matches!(out, HookOutput::Continue));
     }
 
-    // ── PreToolUse resolution: Deny > ModifyArgs > Continue ──────────────────
+    // ── PreToolUse resolution: Deny short-circuits, ModifyArgs compose ─────────
 
     #[tokio::test]
     async fn pre_tool_allow_returns_continue() {
@@ -232,28 +340,42 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn pre_tool_first_modify_wins_when_multiple() {
-        let first_args = json!({"cmd": "first"});
-        let second_args = json!({"cmd": "second"});
+    async fn pre_tool_modify_args_compose_in_registration_order() {
         let mut reg = HookRegistry::new();
+        reg.register(SetArg::new("timeout", json!(30)));
+        reg.register(FixedHook::new(HookOutput::Continue));
+        reg.register(SetArg::new("cmd", json!("ls -la")));
+        let out = reg.fire(&pre_tool_event("bash")).await;
+        match out {
+            HookOutput::ToolDecision(ToolUseDecision::ModifyArgs { args }) => {
+                assert_eq!(args, json!({"cmd": "ls -la", "timeout": 30}));
+            }
+            other => panic!("expected composed ModifyArgs, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn pre_tool_deny_short_circuits_later_hooks() {
+        let later = SetArg::new("cmd", json!("echo"));
+        let mut reg = HookRegistry::new();
+        reg.register(SetArg::new("timeout", json!(30)));
         reg.register(FixedHook::new(HookOutput::ToolDecision(
-            ToolUseDecision::ModifyArgs {
-                args: first_args.clone(),
+            ToolUseDecision::Deny {
+                reason: "blocked".into(),
             },
         )));
-        reg.register(FixedHook::new(HookOutput::ToolDecision(
-            ToolUseDecision::ModifyArgs { args: second_args },
-        )));
+        reg.register(later.clone());
         let out = reg.fire(&pre_tool_event("bash")).await;
         match out {
-            HookOutput::ToolDecision(ToolUseDecision::ModifyArgs { args }) => {
-                assert_eq!(args, first_args);
+            HookOutput::ToolDecision(ToolUseDecision::Deny { reason }) => {
+                assert_eq!(reason, "blocked")
             }
-            other => panic!("expected first ModifyArgs, got {:?}", other),
+            other => panic!("expected Deny, got {:?}", other),
         }
+        assert_eq!(later.calls.load(Ordering::SeqCst), 0);
     }
 
-    // ── general resolution: Stop > SystemMessage > AppendContext > Continue ──
+    // ── general resolution: Stop > Compact > SystemMessage > AppendContext > Continue ──
 
     #[tokio::test]
     async fn general_stop_wins() {
@@ -264,6 +386,22 @@ mod tests {
         assert!(matches!(out, HookOutput::Stop));
     }
 
+    #[tokio::test]
+    async fn general_compact_wins_over_system_message_but_not_stop() {
+        let event = HookEvent::ContextThreshold {
+            pct: 90,
+            input_tokens: 1,
+            output_tokens: 1,
+        };
+        let mut reg = HookRegistry::new();
+        reg.register(FixedHook::new(HookOutput::SystemMessage("msg".into())));
+        reg.register(FixedHook::new(HookOutput::Compact));
+        assert!(matches!(reg.fire(&event).await, HookOutput::Compact));
+
+        reg.register(FixedHook::new(HookOutput::Stop));
+        assert!(matches!(reg.fire(&event).await, HookOutput::Stop));
+    }
+
     #[tokio::test]
     async fn general_system_message_wins_over_append() {
         let mut reg = HookRegistry::new();
@@ -284,6 +422,58 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn general_append_context_concatenates() {
+        let mut reg = HookRegistry::new();
+        reg.register(FixedHook::new(HookOutput::AppendContext("first".into())));
+        reg.register(FixedHook::new(HookOutput::Continue));
+        reg.register(FixedHook::new(HookOutput::AppendContext("second".into())));
+        let out = reg.fire(&post_tool_event("bash")).await;
+        match out {
+            HookOutput::AppendContext(s) => assert_eq!(s, "first\nsecond"),
+            other => panic!("expected AppendContext, got {:?}", other),
+        }
+    }
+
+    // ── UserMessage resolution: replacements compose, context is appended ──────
+
+    /// Upper-cases the `UserMessage` content it is given.
+    struct Shout;
+
+    #[async_trait]
+    impl Hook for Shout {
+        async fn on_event(&self, event: &HookEvent) -> Result<HookOutput> {
+            let HookEvent::UserMessage { content } = event else {
+                return Ok(HookOutput::Continue);
+            };
+            Ok(HookOutput::ReplaceUserMessage(content.to_uppercase()))
+        }
+    }
+
+    #[tokio::test]
+    async fn user_message_replacements_compose_and_context_is_appended() {
+        let event = HookEvent::UserMessage {
+            content: "my key is sk-123".into(),
+        };
+        let mut reg = HookRegistry::new();
+        reg.register(FixedHook::new(HookOutput::Continue));
+        assert!(matches!(reg.fire(&event).await, HookOutput::Continue));
+
+        reg.register(FixedHook::new(HookOutput::ReplaceUserMessage(
+            "my key is [redacted]".into(),
+        )));
+        reg.register(Arc::new(Shout));
+        reg.register(FixedHook::new(HookOutput::AppendContext(
+            "branch: main".into(),
+        )));
+        match reg.fire(&event).await {
+            HookOutput::ReplaceUserMessage(s) => {
+                assert_eq!(s, "MY KEY IS [REDACTED]\n\nbranch: main")
+            }
+            other => panic!("expected ReplaceUserMessage, got {:?}", other),
+        }
+    }
+
     // ── matcher filtering ─────────────────────────────────────────────────────
 
     #[tokio::test]
@@ -1,4 +1,5 @@
 use crate::hooks::hook::{Hook, HookEvent, HookOutput};
+use crate::hooks::otlp::OtlpExporter;
 use anyhow::Result;
 use async_trait::async_trait;
 use serde::Serialize;
@@ -23,6 +24,8 @@ fn event_type_str(event: &HookEvent) -> &'static str {
         HookEvent::PreToolUse { .. } => "pre_tool_use",
         HookEvent::PostToolUse { .. } => "post_tool_use",
         HookEvent::PostToolUseFailure { .. } => "post_tool_use_failure",
+        HookEvent::ContextThreshold { .. } => "context_threshold",
+        HookEvent::UserMessage { .. } => "user_message",
     }
 }
 
@@ -47,12 +50,13 @@ impl<'a> TelemetryEnvelope<'a> {
     }
 }
 
-/// A hook that exports all agent lifecycle events to up to three backends:
-/// HTTP/JSON, an mpsc channel, and a JSONL file.
+/// A hook that exports all agent lifecycle events to up to four backends:
+/// HTTP/JSON, an mpsc channel, a JSONL file, and OTLP spans.
 pub struct TelemetryHook {
     http_endpoint: Option<Arc<str>>,
     channel_tx: Option<mpsc::Sender<String>>,
     jsonl_path: Option<Arc<PathBuf>>,
+    otlp: Option<OtlpExporter>,
     session_id: Option<Arc<str>>,
     agent_id: Option<Arc<str>>,
     http_client: Arc<reqwest::Client>,
@@ -71,6 +75,7 @@ pub struct TelemetryHookBuilder {
     http_endpoint: Option<String>,
     channel_tx: Option<mpsc::Sender<String>>,
     jsonl_path: Option<PathBuf>,
+    otlp_endpoint: Option<String>,
     session_id: Option<String>,
     agent_id: Option<String>,
 }
@@ -81,6 +86,7 @@ impl TelemetryHookBuilder {
             http_endpoint: None,
             channel_tx: None,
             jsonl_path: None,
+            otlp_endpoint: None,
             session_id: None,
             agent_id: None,
         }
@@ -101,6 +107,13 @@ impl TelemetryHookBuilder {
         self
     }
 
+    /// Export events as OTLP spans (trace per run, span per turn and per tool)
+    /// to this OTLP/HTTP traces endpoint, e.g. `http://localhost:4318/v1/traces`.
+    pub fn otlp_endpoint(mut self, url: impl Into<String>) -> Self {
+        self.otlp_endpoint = Some(url.into());
+        self
+    }
+
     pub fn session_id(mut self, id: impl Into<String>) -> Self {
         self.session_id = Some(id.into());
         self
@@ -111,11 +124,19 @@ impl TelemetryHookBuilder {
         self
     }
 
+    /// Build the hook. An OTLP exporter that fails to initialise is logged and
+    /// skipped so telemetry never stops the agent from running.
     pub fn build(self) -> TelemetryHook {
+        let otlp = self.otlp_endpoint.as_deref().and_then(|url| {
+            OtlpExporter::new(url, self.session_id.clone(), self.agent_id.clone())
+                .map_err(|e| tracing::warn!("OTLP exporter for {url} disabled: {e}"))
+                .ok()
+        });
         TelemetryHook {
             http_endpoint: self.http_endpoint.map(|s| Arc::from(s.as_str())),
             channel_tx: self.channel_tx,
             jsonl_path: self.jsonl_path.map(Arc::new),
+            otlp,
             session_id: self.session_id.map(|s| Arc::from(s.as_str())),
             agent_id: self.agent_id.map(|s| Arc::from(s.as_str())),
             http_client: Arc::new(reqwest::Client::new()),
@@ -173,6 +194,11 @@ impl Hook for TelemetryHook {
             });
         }
 
+        // OTLP backend: spans are batched and exported in the background
+        if let Some(otlp) = &self.otlp {
+            otlp.record(event).await;
+        }
+
         Ok(HookOutput::Continue)
     }
 }
@@ -235,6 +261,14 @@ mod tests {
             }),
             "post_tool_use_failure"
         );
+        assert_eq!(
+            event_type_str(&HookEvent::ContextThreshold {
+                pct: 75,
+                input_tokens: 0,
+                output_tokens: 0,
+            }),
+            "context_threshold"
+        );
     }
 
     #[test]
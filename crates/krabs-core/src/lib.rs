@@ -14,44 +14,75 @@ pub mod tools;
 
 pub use agents::agent::{Agent, AgentOutput, KrabsAgent, KrabsAgentBuilder};
 pub use agents::base_agent::BaseAgent;
-pub use agents::context::{ConversationContext, TurnInput};
+pub use agents::compact::Compaction;
+pub use agents::context::{ConversationContext, TurnEnd, TurnInput};
 pub use agents::factory::{AgentFactory, SessionOpts};
-pub use agents::persona::AgentPersona;
+pub use agents::metrics::{ToolMetrics, ToolStats};
+pub use agents::minikrabs::{
+    subagent_limit, token_budget, ProgressHook, ProgressSink, SubAgentLimit, SubAgentProgress,
+    TokenBudget, UsageLedger,
+};
+pub use agents::observer::{AgentObserver, JsonlObserver, NoopObserver, JSONL_SCHEMA_VERSION};
+pub use agents::persona::{AgentPersona, PersonaSource};
 pub use agents::pool::{AgentHandle, AgentId, AgentPool, AgentStatus, HandleError, PoolError};
 pub use config::config::{
-    CustomModelEntry, KrabsConfig, LangfuseConfig, RouterConfig, RouterRule, SkillsConfig,
-    TelemetryConfig,
+    output_limit, BrandingConfig, CustomModelEntry, KeyChords, KeybindConfig, KrabsConfig,
+    LangfuseConfig, RouterConfig, RouterRule, SkillsConfig, SqliteConfig, TelemetryConfig,
 };
-pub use config::credentials::Credentials;
+pub use config::credentials::{CredentialProfiles, Credentials};
 pub use hooks::{
-    Hook, HookConfig, HookEntry, HookEvent, HookOutput, HookRegistry, LangfuseHook,
+    ConfigHook, Hook, HookConfig, HookEntry, HookEvent, HookOutput, HookRegistry, LangfuseHook,
     LangfuseHookBuilder, TelemetryHook, TelemetryHookBuilder, ToolUseDecision,
 };
-pub use mcp::mcp::{LiveMcpRegistry, McpRegistry, McpServer};
-pub use mcp::{McpClient, McpReadResourceTool, McpTool};
-pub use permissions::PermissionGuard;
+pub use mcp::cache::McpToolCache;
+pub use mcp::mcp::{LiveMcpRegistry, McpConnection, McpRegistry, McpServer};
+pub use mcp::{
+    McpClient, McpPromptArgument, McpPromptInfo, McpPromptMessage, McpReadResourceTool,
+    McpResourceCache, McpTool,
+};
+pub use memory::{
+    InMemoryStore, MemoryScope, MemoryStore, ScopedMemory, SqliteMemoryStore, GLOBAL_NAMESPACE,
+};
+pub use permissions::{
+    tool_rule_matches, Approval, ApprovalRule, ApprovalRuleStatus, AutoApprover, PermissionGuard,
+};
 pub use providers::provider::{
-    LlmProvider, LlmResponse, Message, Role, StreamChunk, TokenUsage, ToolCall,
+    EffortLevel, ImageContent, LlmProvider, LlmResponse, Message, ReasoningEffort, ResponseFormat,
+    Role, StreamChunk, TokenUsage, ToolCall,
 };
 pub use router::{parse_decision, FixedRouter, RouteDecision, RulesRouter, TaskRouter};
 pub use sandbox::{SandboxConfig, SandboxProxy, SandboxedTool};
 
-pub use providers::{AnthropicProvider, GeminiProvider, OpenAiProvider};
+pub use prompts::PromptSection;
+pub use providers::{
+    set_max_concurrent_requests, AnthropicProvider, GeminiProvider, OpenAiApi, OpenAiProvider,
+    ProviderError,
+};
+pub use session::replay::{recorded_tool_calls, RecordedToolCall, ReplayOutcome, Replayer};
 pub use session::session::{
-    ResumeState, Session, SessionStore, SessionSummary, StoredCheckpoint, StoredError,
-    StoredMessage, SubturnResume,
+    ErrorCount, ResumeState, Session, SessionStore, SessionSummary, StoredCheckpoint, StoredError,
+    StoredMessage, StoredTokenUsage, SubturnResume, ToolApproval,
 };
 pub use skills::{FsSkill, SkillRegistry};
-pub use tools::bash::BashTool;
+pub use tokio_util::sync::CancellationToken;
+pub use tools::bash::{BashPolicy, BashTool};
 pub use tools::delegate::DelegateTool;
 pub use tools::dispatch::DispatchTool;
+pub use tools::format::ToolOutputFormat;
+pub use tools::git::{GitDiffTool, GitStatusTool};
 pub use tools::glob::{GlobTool, GrepTool};
+pub use tools::memory::{
+    MemoryReadTool, MemorySearchTool, MemoryWriteTool, RecallTool, RememberTool,
+};
+pub use tools::patch::ApplyPatchTool;
 pub use tools::read::ReadTool;
 pub use tools::registry::ToolRegistry;
+pub use tools::root::ProjectRoot;
+pub use tools::sysinfo::SysInfoTool;
 pub use tools::tool::{Tool, ToolDef, ToolResult};
 pub use tools::user_input::{InputMode, UserInputRequest, UserInputTool};
 pub use tools::web_fetch::WebFetchTool;
-pub use tools::write::WriteTool;
+pub use tools::write::{restore_backup, WriteTool};
 pub use tools::ReadSkillTool;
 
 /// Generate a fresh session UUID (used by the CLI to pre-assign a session ID
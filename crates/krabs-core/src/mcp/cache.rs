@@ -0,0 +1,156 @@
+//! On-disk cache of each MCP server's tool list, so agents can register MCP
+//! tools at startup without waiting for every server to connect.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use super::client::McpToolInfo;
+use super::mcp::McpServer;
+
+/// One server's cached `tools/list` result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedTools {
+    /// How the server was reached when the list was fetched; a changed
+    /// transport, command or URL makes the entry stale.
+    fingerprint: String,
+    /// Unix seconds of the fetch.
+    fetched_at: u64,
+    tools: Vec<McpToolInfo>,
+}
+
+/// Tool lists keyed by server name, stored in `~/.krabs/mcp-cache.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct McpToolCache {
+    servers: HashMap<String, CachedTools>,
+}
+
+fn fingerprint(server: &McpServer) -> String {
+    format!("{} {}", server.transport_label(), server.endpoint())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl McpToolCache {
+    pub fn path() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".krabs")
+            .join("mcp-cache.json")
+    }
+
+    /// Load the cache at `path`. A missing or unreadable file is an empty cache.
+    pub async fn load(path: &Path) -> Self {
+        tokio::fs::read_to_string(path)
+            .await
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, serde_json::to_string_pretty(self)?).await?;
+        Ok(())
+    }
+
+    /// Cached tools of `server`, if fetched less than `ttl` ago with the
+    /// server's current transport settings.
+    pub fn fresh(&self, server: &McpServer, ttl: Duration) -> Option<&[McpToolInfo]> {
+        let entry = self.servers.get(&server.name)?;
+        let age = now_secs().saturating_sub(entry.fetched_at);
+        (entry.fingerprint == fingerprint(server) && age < ttl.as_secs())
+            .then_some(entry.tools.as_slice())
+    }
+
+    pub fn insert(&mut self, server: &McpServer, tools: Vec<McpToolInfo>) {
+        self.servers.insert(
+            server.name.clone(),
+            CachedTools {
+                fingerprint: fingerprint(server),
+                fetched_at: now_secs(),
+                tools,
+            },
+        );
+    }
+
+    pub fn invalidate(&mut self, server_name: &str) {
+        self.servers.remove(server_name);
+    }
+
+    /// Record fresh tool lists (`Ok`) and drop entries of servers that failed
+    /// (`Err`) in the cache at `path`. Save errors are logged.
+    pub async fn update(path: &Path, results: Vec<(McpServer, Result<Vec<McpToolInfo>>)>) {
+        let mut cache = Self::load(path).await;
+        for (server, result) in results {
+            match result {
+                Ok(tools) => cache.insert(&server, tools),
+                Err(_) => cache.invalidate(&server.name),
+            }
+        }
+        if let Err(e) = cache.save(path).await {
+            warn!("Failed to save MCP tool cache {:?}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool(name: &str) -> McpToolInfo {
+        McpToolInfo {
+            name: name.into(),
+            description: String::new(),
+            input_schema: json!({"type": "object"}),
+        }
+    }
+
+    #[test]
+    fn entries_expire_and_follow_server_settings() {
+        let server = McpServer::stdio("fs", "mcp-fs", vec!["/tmp".into()]);
+        let mut cache = McpToolCache::default();
+        cache.insert(&server, vec![tool("read_file")]);
+
+        let day = Duration::from_secs(86_400);
+        assert_eq!(cache.fresh(&server, day).map(<[_]>::len), Some(1));
+        assert!(cache.fresh(&server, Duration::ZERO).is_none());
+
+        // A different command is a different server as far as the cache goes.
+        let moved = McpServer::stdio("fs", "mcp-fs", vec!["/srv".into()]);
+        assert!(cache.fresh(&moved, day).is_none());
+    }
+
+    #[tokio::test]
+    async fn update_stores_successes_and_drops_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mcp-cache.json");
+        let (a, b) = (
+            McpServer::sse("a", "http://a"),
+            McpServer::sse("b", "http://b"),
+        );
+        let day = Duration::from_secs(86_400);
+
+        McpToolCache::update(
+            &path,
+            vec![(a.clone(), Ok(vec![tool("x")])), (b.clone(), Ok(vec![]))],
+        )
+        .await;
+        McpToolCache::update(&path, vec![(b.clone(), Err(anyhow::anyhow!("refused")))]).await;
+
+        let cache = McpToolCache::load(&path).await;
+        assert!(cache.fresh(&a, day).is_some());
+        assert!(cache.fresh(&b, day).is_none());
+    }
+}
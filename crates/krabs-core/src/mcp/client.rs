@@ -1,8 +1,11 @@
 use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::HashMap;
+use tokio::sync::broadcast;
 use tracing::info;
 
+use super::jsonrpc::JsonRpcNotification;
 use super::transport::{SseTransport, StdioTransport, Transport};
 
 const PROTOCOL_VERSION: &str = "2024-11-05";
@@ -39,11 +42,55 @@ pub struct McpResourceContent {
     pub blob: Option<String>, // base64 encoded
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpPromptArgument {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpPromptInfo {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub arguments: Vec<McpPromptArgument>,
+}
+
+/// One message of a rendered prompt, flattened to its text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct McpPromptMessage {
+    pub role: String,
+    pub text: String,
+}
+
+impl McpPromptMessage {
+    /// Text of a `prompts/get` message: inline text, or the text of an embedded
+    /// resource. Images and other binary content have no text form and are skipped.
+    fn from_value(v: &Value) -> Option<Self> {
+        let content = &v["content"];
+        let text = match content["type"].as_str() {
+            Some("text") => content["text"].as_str(),
+            Some("resource") => content["resource"]["text"].as_str(),
+            _ => None,
+        }?;
+        Some(Self {
+            role: v["role"].as_str().unwrap_or("user").to_string(),
+            text: text.to_string(),
+        })
+    }
+}
+
 // ── McpClient ────────────────────────────────────────────────────────────────
 
 pub struct McpClient {
     pub server_name: String,
     transport: Transport,
+    /// `capabilities` object from the server's `initialize` response.
+    capabilities: Value,
 }
 
 impl McpClient {
@@ -57,6 +104,7 @@ impl McpClient {
         let mut client = Self {
             server_name: server_name.into(),
             transport,
+            capabilities: Value::Null,
         };
         client.initialize().await?;
         Ok(client)
@@ -68,6 +116,7 @@ impl McpClient {
         let mut client = Self {
             server_name: server_name.into(),
             transport,
+            capabilities: Value::Null,
         };
         client.initialize().await?;
         Ok(client)
@@ -96,6 +145,7 @@ impl McpClient {
             server_name,
             result["protocolVersion"].as_str().unwrap_or("?")
         );
+        self.capabilities = result["capabilities"].clone();
 
         // Send initialized notification
         self.transport
@@ -104,6 +154,31 @@ impl McpClient {
         Ok(())
     }
 
+    /// `true` if the server advertised the `prompts` capability.
+    pub fn supports_prompts(&self) -> bool {
+        self.capabilities.get("prompts").is_some()
+    }
+
+    /// `true` if the server advertised the `resources` capability.
+    pub fn supports_resources(&self) -> bool {
+        self.capabilities.get("resources").is_some()
+    }
+
+    /// `true` if the server offers `resources/subscribe` and the transport
+    /// can deliver the `notifications/resources/updated` that follow.
+    pub fn supports_resource_subscriptions(&self) -> bool {
+        self.capabilities["resources"]["subscribe"]
+            .as_bool()
+            .unwrap_or(false)
+            && matches!(self.transport, Transport::Stdio(_))
+    }
+
+    /// Notifications the server sends from now on, if the transport delivers
+    /// them.
+    pub fn notifications(&self) -> Option<broadcast::Receiver<JsonRpcNotification>> {
+        self.transport.notifications()
+    }
+
     /// Discover all tools exposed by this server.
     pub async fn list_tools(&self) -> Result<Vec<McpToolInfo>> {
         let result = self.transport.request("tools/list", None).await?;
@@ -172,4 +247,88 @@ impl McpClient {
             .map(|c| serde_json::from_value(c.clone()).map_err(Into::into))
             .collect()
     }
+
+    /// Ask to be sent `notifications/resources/updated` when `uri` changes.
+    pub async fn subscribe_resource(&self, uri: &str) -> Result<()> {
+        let params = json!({ "uri": uri });
+        self.transport
+            .request("resources/subscribe", Some(params))
+            .await?;
+        Ok(())
+    }
+
+    /// Discover prompts exposed by this server. Empty when the server doesn't
+    /// advertise the `prompts` capability.
+    pub async fn list_prompts(&self) -> Result<Vec<McpPromptInfo>> {
+        if !self.supports_prompts() {
+            return Ok(vec![]);
+        }
+        let result = self.transport.request("prompts/list", None).await?;
+        let prompts = match result["prompts"].as_array() {
+            Some(p) => p,
+            None => return Ok(vec![]),
+        };
+        prompts
+            .iter()
+            .map(|p| serde_json::from_value(p.clone()).map_err(Into::into))
+            .collect()
+    }
+
+    /// Render a prompt, letting the server substitute `arguments`.
+    pub async fn get_prompt(
+        &self,
+        name: &str,
+        arguments: &HashMap<String, String>,
+    ) -> Result<Vec<McpPromptMessage>> {
+        let params = json!({ "name": name, "arguments": arguments });
+        let result = self.transport.request("prompts/get", Some(params)).await?;
+        let messages = match result["messages"].as_array() {
+            Some(m) => m,
+            None => bail!("prompts/get: expected 'messages' array"),
+        };
+        Ok(messages
+            .iter()
+            .filter_map(McpPromptMessage::from_value)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompt_messages_keep_text_and_embedded_resources() {
+        let text = json!({"role": "user", "content": {"type": "text", "text": "review this"}});
+        let resource = json!({
+            "role": "assistant",
+            "content": {"type": "resource", "resource": {"uri": "file:///a", "text": "fn a() {}"}}
+        });
+        let image = json!({"role": "user", "content": {"type": "image", "data": "..."}});
+
+        assert_eq!(
+            McpPromptMessage::from_value(&text),
+            Some(McpPromptMessage {
+                role: "user".into(),
+                text: "review this".into()
+            })
+        );
+        assert_eq!(
+            McpPromptMessage::from_value(&resource).map(|m| m.text),
+            Some("fn a() {}".into())
+        );
+        assert!(McpPromptMessage::from_value(&image).is_none());
+    }
+
+    #[test]
+    fn prompt_info_defaults_missing_fields() {
+        let info: McpPromptInfo = serde_json::from_value(json!({
+            "name": "summarize",
+            "arguments": [{"name": "topic", "required": true}, {"name": "tone"}]
+        }))
+        .unwrap();
+        assert_eq!(info.description, "");
+        assert!(info.arguments[0].required);
+        assert!(!info.arguments[1].required);
+    }
 }
@@ -1,13 +1,18 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tracing::{info, warn};
+use std::time::Duration;
+use tokio::sync::{OnceCell, Semaphore};
+use tracing::{debug, info, warn};
 
 use crate::tools::tool::Tool;
 
-use super::client::McpClient;
-use super::tool::{McpReadResourceTool, McpTool};
+use super::cache::McpToolCache;
+use super::client::{McpClient, McpPromptInfo, McpPromptMessage, McpResourceInfo, McpToolInfo};
+use super::resources::McpResourceCache;
+use super::tool::{server_limit, McpReadResourceTool, McpTool};
 
 // ── Server config ────────────────────────────────────────────────────────────
 
@@ -30,12 +35,30 @@ pub struct McpServer {
     pub url: String,
     #[serde(default = "default_true")]
     pub enabled: bool,
+    /// Maximum number of tool calls in flight to this server at once.
+    /// Calls beyond the limit wait for a slot. Default: 4.
+    #[serde(default = "default_max_concurrency")]
+    pub max_concurrency: usize,
+    /// Seconds a resource read is reused for, if the server supports
+    /// resource subscriptions; a change notification drops it sooner. Servers
+    /// without subscriptions are always re-read. 0 disables caching.
+    /// Default: 300.
+    #[serde(default = "default_resource_cache_ttl_secs")]
+    pub resource_cache_ttl_secs: u64,
 }
 
 fn default_true() -> bool {
     true
 }
 
+fn default_max_concurrency() -> usize {
+    4
+}
+
+fn default_resource_cache_ttl_secs() -> u64 {
+    300
+}
+
 impl McpServer {
     pub fn stdio(name: impl Into<String>, command: impl Into<String>, args: Vec<String>) -> Self {
         Self {
@@ -45,6 +68,8 @@ impl McpServer {
             args,
             url: String::new(),
             enabled: true,
+            max_concurrency: default_max_concurrency(),
+            resource_cache_ttl_secs: default_resource_cache_ttl_secs(),
         }
     }
 
@@ -56,9 +81,16 @@ impl McpServer {
             args: vec![],
             url: url.into(),
             enabled: true,
+            max_concurrency: default_max_concurrency(),
+            resource_cache_ttl_secs: default_resource_cache_ttl_secs(),
         }
     }
 
+    pub fn with_max_concurrency(mut self, max: usize) -> Self {
+        self.max_concurrency = max;
+        self
+    }
+
     pub fn transport_label(&self) -> &str {
         if !self.transport.is_empty() {
             &self.transport
@@ -87,6 +119,83 @@ impl McpServer {
     }
 }
 
+// ── Connection ───────────────────────────────────────────────────────────────
+
+/// A server's client, connected on first use when its tools were registered
+/// from the cache.
+pub struct McpConnection {
+    server: McpServer,
+    client: OnceCell<Arc<McpClient>>,
+    resources: McpResourceCache,
+}
+
+impl McpConnection {
+    /// A connection that is opened by the first call to [`client`](Self::client).
+    pub fn lazy(server: McpServer) -> Self {
+        Self::with_client(server, OnceCell::new())
+    }
+
+    pub fn connected(server: McpServer, client: Arc<McpClient>) -> Self {
+        Self::with_client(server, OnceCell::new_with(Some(client)))
+    }
+
+    fn with_client(server: McpServer, client: OnceCell<Arc<McpClient>>) -> Self {
+        let ttl = Duration::from_secs(server.resource_cache_ttl_secs);
+        Self {
+            server,
+            client,
+            resources: McpResourceCache::new(ttl),
+        }
+    }
+
+    pub fn server_name(&self) -> &str {
+        &self.server.name
+    }
+
+    /// Resource reads from this server, shared by every tool using it.
+    pub fn resources(&self) -> &McpResourceCache {
+        &self.resources
+    }
+
+    /// The connected client, connecting now if needed. A failed attempt is
+    /// retried on the next call.
+    pub async fn client(&self) -> Result<&Arc<McpClient>> {
+        self.client
+            .get_or_try_init(|| async { self.server.connect().await.map(Arc::new) })
+            .await
+    }
+}
+
+/// MCP tools for `infos`, plus the server's `read_resource` tool.
+fn server_tools(
+    connection: &Arc<McpConnection>,
+    limit: &Arc<Semaphore>,
+    infos: Vec<McpToolInfo>,
+) -> Vec<Box<dyn Tool>> {
+    let mut tools: Vec<Box<dyn Tool>> = infos
+        .into_iter()
+        .map(|info| {
+            Box::new(McpTool::new(
+                Arc::clone(connection),
+                Arc::clone(limit),
+                info.name,
+                info.description,
+                info.input_schema,
+            )) as Box<dyn Tool>
+        })
+        .collect();
+    tools.push(Box::new(McpReadResourceTool::new(
+        Arc::clone(connection),
+        Arc::clone(limit),
+    )));
+    tools
+}
+
+/// Connect `connection` and fetch its tool list.
+async fn fetch_tools(connection: &McpConnection) -> Result<Vec<McpToolInfo>> {
+    connection.client().await?.list_tools().await
+}
+
 // ── Registry (persisted) ─────────────────────────────────────────────────────
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -132,9 +241,94 @@ impl McpRegistry {
         self.servers.len() < before
     }
 
+    /// Tools of every enabled server, for registering in a `ToolRegistry`.
+    ///
+    /// Servers whose tool list in the cache at `cache_path` is younger than
+    /// `ttl` are not waited for: their tools are built from the cache and
+    /// connect on first use, while a background task re-lists them and
+    /// updates the cache. Other servers are connected now and their lists
+    /// cached. A server that fails to connect is dropped from the cache.
+    pub async fn tools_cached(self, cache_path: &Path, ttl: Duration) -> Vec<Box<dyn Tool>> {
+        let cache = McpToolCache::load(cache_path).await;
+        let mut tools = Vec::new();
+        let mut stale = Vec::new();
+        let mut refresh = Vec::new();
+
+        for server in self.servers.into_iter().filter(|s| s.enabled) {
+            let limit = server_limit(server.max_concurrency);
+            let connection = Arc::new(McpConnection::lazy(server.clone()));
+            match cache.fresh(&server, ttl) {
+                Some(infos) => {
+                    debug!("MCP server '{}' tools loaded from cache", server.name);
+                    tools.extend(server_tools(&connection, &limit, infos.to_vec()));
+                    refresh.push((server, connection));
+                }
+                None => stale.push((server, connection, limit)),
+            }
+        }
+
+        let fetched =
+            futures_util::future::join_all(stale.iter().map(|(_, c, _)| fetch_tools(c))).await;
+        let mut results = Vec::new();
+        for ((server, connection, limit), result) in stale.into_iter().zip(fetched) {
+            match &result {
+                Ok(infos) => {
+                    info!("MCP server '{}' connected", server.name);
+                    tools.extend(server_tools(&connection, &limit, infos.clone()));
+                }
+                Err(e) => warn!("MCP server '{}' failed to connect: {}", server.name, e),
+            }
+            results.push((server, result));
+        }
+        if !results.is_empty() {
+            McpToolCache::update(cache_path, results).await;
+        }
+
+        if !refresh.is_empty() {
+            let cache_path = cache_path.to_path_buf();
+            tokio::spawn(async move {
+                let fetched =
+                    futures_util::future::join_all(refresh.iter().map(|(_, c)| fetch_tools(c)))
+                        .await;
+                let results = refresh
+                    .into_iter()
+                    .zip(fetched)
+                    .map(|((server, _), result)| {
+                        if let Err(e) = &result {
+                            warn!("MCP server '{}' failed to connect: {}", server.name, e);
+                        }
+                        (server, result)
+                    })
+                    .collect();
+                McpToolCache::update(&cache_path, results).await;
+            });
+        }
+        tools
+    }
+
+    /// Connect every enabled server, re-list its tools and rewrite the cache
+    /// at `cache_path` from scratch. Returns each server's tool count or error.
+    pub async fn refresh_cache(&self, cache_path: &Path) -> Result<Vec<(String, Result<usize>)>> {
+        let mut cache = McpToolCache::default();
+        let mut counts = Vec::new();
+        for server in self.servers.iter().filter(|s| s.enabled) {
+            match fetch_tools(&McpConnection::lazy(server.clone())).await {
+                Ok(infos) => {
+                    counts.push((server.name.clone(), Ok(infos.len())));
+                    cache.insert(server, infos);
+                }
+                Err(e) => counts.push((server.name.clone(), Err(e))),
+            }
+        }
+        cache.save(cache_path).await?;
+        Ok(counts)
+    }
+
     /// Connect all enabled servers and return a `LiveMcpRegistry` with active connections.
     pub async fn connect_all(self) -> LiveMcpRegistry {
         let mut clients = Vec::new();
+        let mut servers = Vec::new();
+        let mut limits = Vec::new();
         for server in &self.servers {
             if !server.enabled {
                 continue;
@@ -143,13 +337,19 @@ impl McpRegistry {
                 Ok(client) => {
                     info!("MCP server '{}' connected", server.name);
                     clients.push(Arc::new(client));
+                    servers.push(server.clone());
+                    limits.push(server_limit(server.max_concurrency));
                 }
                 Err(e) => {
                     warn!("MCP server '{}' failed to connect: {}", server.name, e);
                 }
             }
         }
-        LiveMcpRegistry { clients }
+        LiveMcpRegistry {
+            clients,
+            servers,
+            limits,
+        }
     }
 }
 
@@ -157,6 +357,11 @@ impl McpRegistry {
 
 pub struct LiveMcpRegistry {
     pub clients: Vec<Arc<McpClient>>,
+    /// Settings of each connected server, index-aligned with `clients`.
+    servers: Vec<McpServer>,
+    /// Per-server call limiters, index-aligned with `clients`. Every tool from
+    /// one server shares its semaphore; different servers never wait on each other.
+    limits: Vec<Arc<Semaphore>>,
 }
 
 impl LiveMcpRegistry {
@@ -164,18 +369,13 @@ impl LiveMcpRegistry {
     /// ready to be registered in a `ToolRegistry`.
     pub async fn tools_for_all(&self) -> Vec<Box<dyn Tool>> {
         let mut tools: Vec<Box<dyn Tool>> = Vec::new();
-        for client in &self.clients {
+        let servers = self.clients.iter().zip(&self.servers).zip(&self.limits);
+        for ((client, server), limit) in servers {
             match client.list_tools().await {
                 Ok(infos) => {
-                    for info in infos {
-                        tools.push(Box::new(McpTool::new(
-                            Arc::clone(client),
-                            info.name,
-                            info.description,
-                            info.input_schema,
-                        )));
-                    }
-                    tools.push(Box::new(McpReadResourceTool::new(Arc::clone(client))));
+                    let connection =
+                        Arc::new(McpConnection::connected(server.clone(), Arc::clone(client)));
+                    tools.extend(server_tools(&connection, limit, infos));
                 }
                 Err(e) => {
                     warn!(
@@ -188,6 +388,64 @@ impl LiveMcpRegistry {
         tools
     }
 
+    /// Discover prompts from every connected server that advertises them, as
+    /// `(server_name, prompt)` pairs. Servers without the capability are skipped.
+    pub async fn prompts_for_all(&self) -> Vec<(&str, McpPromptInfo)> {
+        let mut prompts = Vec::new();
+        for client in &self.clients {
+            match client.list_prompts().await {
+                Ok(infos) => {
+                    prompts.extend(infos.into_iter().map(|p| (client.server_name.as_str(), p)));
+                }
+                Err(e) => {
+                    warn!(
+                        "MCP server '{}' prompts/list failed: {}",
+                        client.server_name, e
+                    );
+                }
+            }
+        }
+        prompts
+    }
+
+    /// List resources from every connected server that advertises them, as
+    /// `(server_name, resource)` pairs. Servers without the capability are skipped.
+    pub async fn resources_for_all(&self) -> Vec<(&str, McpResourceInfo)> {
+        let mut resources = Vec::new();
+        for client in self.clients.iter().filter(|c| c.supports_resources()) {
+            match client.list_resources().await {
+                Ok(infos) => {
+                    resources.extend(infos.into_iter().map(|r| (client.server_name.as_str(), r)));
+                }
+                Err(e) => {
+                    warn!(
+                        "MCP server '{}' resources/list failed: {}",
+                        client.server_name, e
+                    );
+                }
+            }
+        }
+        resources
+    }
+
+    /// Render prompt `name` from `server` with `arguments` substituted.
+    pub async fn get_prompt(
+        &self,
+        server: &str,
+        name: &str,
+        arguments: &HashMap<String, String>,
+    ) -> Result<Vec<McpPromptMessage>> {
+        let client = self
+            .clients
+            .iter()
+            .find(|c| c.server_name == server)
+            .ok_or_else(|| anyhow::anyhow!("MCP server '{server}' is not connected"))?;
+        if !client.supports_prompts() {
+            anyhow::bail!("MCP server '{server}' does not provide prompts");
+        }
+        client.get_prompt(name, arguments).await
+    }
+
     pub fn is_empty(&self) -> bool {
         self.clients.is_empty()
     }
@@ -1,10 +1,14 @@
+pub mod cache;
 pub mod client;
 pub mod jsonrpc;
 #[allow(clippy::module_inception)]
 pub mod mcp;
+pub mod resources;
 pub mod tool;
 pub mod transport;
 
-pub use client::McpClient;
-pub use mcp::{LiveMcpRegistry, McpRegistry, McpServer};
+pub use cache::McpToolCache;
+pub use client::{McpClient, McpPromptArgument, McpPromptInfo, McpPromptMessage};
+pub use mcp::{LiveMcpRegistry, McpConnection, McpRegistry, McpServer};
+pub use resources::McpResourceCache;
 pub use tool::{McpReadResourceTool, McpTool};
@@ -0,0 +1,168 @@
+//! In-memory cache of MCP resource reads.
+//!
+//! Only servers that support `resources/subscribe` are cached: each resource
+//! is subscribed to on first read, and a `notifications/resources/updated`
+//! for it drops the cached copy. Entries also expire after the server's TTL,
+//! in case a notification is lost. Other servers are read every time.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::{broadcast, Mutex};
+use tracing::debug;
+
+use super::client::{McpClient, McpResourceContent};
+use super::jsonrpc::JsonRpcNotification;
+
+const RESOURCE_UPDATED: &str = "notifications/resources/updated";
+
+#[derive(Default)]
+struct State {
+    /// Cached contents by URI, with when they were read.
+    entries: HashMap<String, (Instant, Vec<McpResourceContent>)>,
+    /// URIs the server was asked to notify us about.
+    subscribed: HashSet<String>,
+    updates: Option<broadcast::Receiver<JsonRpcNotification>>,
+}
+
+impl State {
+    /// Apply every notification received since the last call.
+    fn drain_updates(&mut self) {
+        let Some(updates) = self.updates.as_mut() else {
+            return;
+        };
+        loop {
+            match updates.try_recv() {
+                Ok(notif) if notif.method == RESOURCE_UPDATED => {
+                    let params = notif.params.unwrap_or_default();
+                    if let Some(uri) = params["uri"].as_str() {
+                        debug!("MCP resource {uri} changed");
+                        self.entries.remove(uri);
+                    }
+                }
+                Ok(_) => {}
+                Err(TryRecvError::Empty) => break,
+                // Missed notifications could have been for anything.
+                Err(TryRecvError::Lagged(_)) => self.entries.clear(),
+                // The connection is gone: nothing can be kept fresh anymore.
+                Err(TryRecvError::Closed) => {
+                    self.entries.clear();
+                    self.subscribed.clear();
+                    self.updates = None;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Resource reads of one server, kept while the server says they're current.
+pub struct McpResourceCache {
+    ttl: Duration,
+    state: Mutex<State>,
+}
+
+impl McpResourceCache {
+    /// A cache whose entries live at most `ttl`. A zero `ttl` caches nothing.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// The cached contents of `uri`, unless they changed or expired.
+    pub async fn get(&self, uri: &str) -> Option<Vec<McpResourceContent>> {
+        let mut state = self.state.lock().await;
+        state.drain_updates();
+        match state.entries.get(uri) {
+            Some((read_at, contents)) if read_at.elapsed() < self.ttl => Some(contents.clone()),
+            Some(_) => {
+                state.entries.remove(uri);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Subscribe to changes of `uri` on `client`, once per URI. Returns
+    /// whether reads of `uri` may be cached: `false` when caching is off or
+    /// the server can't tell us about changes.
+    pub async fn watch(&self, client: &McpClient, uri: &str) -> bool {
+        if self.ttl.is_zero() || !client.supports_resource_subscriptions() {
+            return false;
+        }
+        let mut state = self.state.lock().await;
+        if state.subscribed.contains(uri) {
+            return true;
+        }
+        if state.updates.is_none() {
+            state.updates = client.notifications();
+        }
+        if state.updates.is_none() {
+            return false;
+        }
+        match client.subscribe_resource(uri).await {
+            Ok(()) => {
+                state.subscribed.insert(uri.to_string());
+                true
+            }
+            Err(e) => {
+                debug!("Not caching MCP resource {uri}: {e}");
+                false
+            }
+        }
+    }
+
+    /// Remember `contents` as what `uri` holds now.
+    pub async fn insert(&self, uri: &str, contents: Vec<McpResourceContent>) {
+        self.state
+            .lock()
+            .await
+            .entries
+            .insert(uri.to_string(), (Instant::now(), contents));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn content(text: &str) -> Vec<McpResourceContent> {
+        vec![McpResourceContent {
+            uri: "file:///a".into(),
+            mime_type: "text/plain".into(),
+            text: Some(text.into()),
+            blob: None,
+        }]
+    }
+
+    #[tokio::test]
+    async fn updates_and_expiry_drop_cached_reads() {
+        let (tx, rx) = broadcast::channel(4);
+        let cache = McpResourceCache::new(Duration::from_secs(60));
+        cache.state.lock().await.updates = Some(rx);
+
+        cache.insert("file:///a", content("v1")).await;
+        cache.insert("file:///b", content("b")).await;
+        assert_eq!(
+            cache.get("file:///a").await.unwrap()[0].text.as_deref(),
+            Some("v1")
+        );
+
+        let notify =
+            |uri: &str| JsonRpcNotification::new(RESOURCE_UPDATED, Some(json!({ "uri": uri })));
+        tx.send(notify("file:///a")).unwrap();
+        assert!(cache.get("file:///a").await.is_none());
+        assert!(cache.get("file:///b").await.is_some());
+
+        drop(tx);
+        assert!(cache.get("file:///b").await.is_none());
+
+        let expired = McpResourceCache::new(Duration::ZERO);
+        expired.insert("file:///a", content("v1")).await;
+        assert!(expired.get("file:///a").await.is_none());
+    }
+}
@@ -1,18 +1,42 @@
+use std::future::Future;
 use std::sync::Arc;
 
 use anyhow::Result;
 use async_trait::async_trait;
 use serde_json::Value;
+use tokio::sync::Semaphore;
 
 use crate::tools::tool::{Tool, ToolResult};
 
-use super::client::McpClient;
+use super::client::McpResourceContent;
+use super::mcp::McpConnection;
+
+/// Semaphore capping concurrent calls to one server. A limit of 0 is treated as 1.
+pub fn server_limit(max_concurrency: usize) -> Arc<Semaphore> {
+    Arc::new(Semaphore::new(max_concurrency.max(1)))
+}
+
+/// Run `fut` once a slot in `limit` is free.
+async fn limited<F: Future>(limit: &Semaphore, fut: F) -> Result<F::Output> {
+    let _permit = limit.acquire().await?;
+    Ok(fut.await)
+}
+
+/// Result of a call whose server could not be reached.
+fn unavailable(connection: &McpConnection, e: anyhow::Error) -> ToolResult {
+    ToolResult::err(format!(
+        "MCP server '{}' is unavailable: {}",
+        connection.server_name(),
+        e
+    ))
+}
 
 /// Wraps an MCP server tool as a Krabs `Tool`.
 ///
 /// Registered in the tool registry as `mcp__{server}__{tool}`.
 pub struct McpTool {
-    pub client: Arc<McpClient>,
+    pub connection: Arc<McpConnection>,
+    pub limit: Arc<Semaphore>,
     pub tool_name: String,
     pub description: String,
     pub schema: Value,
@@ -21,15 +45,17 @@ pub struct McpTool {
 
 impl McpTool {
     pub fn new(
-        client: Arc<McpClient>,
+        connection: Arc<McpConnection>,
+        limit: Arc<Semaphore>,
         tool_name: impl Into<String>,
         description: impl Into<String>,
         schema: Value,
     ) -> Self {
         let tool_name = tool_name.into();
-        let registered_name = format!("mcp__{}__{}", client.server_name, tool_name);
+        let registered_name = format!("mcp__{}__{}", connection.server_name(), tool_name);
         Self {
-            client,
+            connection,
+            limit,
             tool_name,
             description: description.into(),
             schema,
@@ -53,26 +79,45 @@ impl Tool for McpTool {
     }
 
     async fn call(&self, args: Value) -> Result<ToolResult> {
-        match self.client.call_tool(&self.tool_name, args).await {
-            Ok((content, is_error)) => Ok(ToolResult { content, is_error }),
+        let client = match self.connection.client().await {
+            Ok(client) => client,
+            Err(e) => return Ok(unavailable(&self.connection, e)),
+        };
+        match limited(&self.limit, client.call_tool(&self.tool_name, args)).await? {
+            Ok((content, is_error)) => Ok(ToolResult {
+                content,
+                is_error,
+                images: Vec::new(),
+            }),
             Err(e) => Ok(ToolResult::err(e.to_string())),
         }
     }
 }
 
+/// The text parts of a resource, one per line.
+fn resource_text(contents: &[McpResourceContent]) -> String {
+    contents
+        .iter()
+        .filter_map(|c| c.text.as_deref())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 /// Exposes MCP resources as a readable tool.
 ///
 /// Registered as `mcp__{server}__read_resource`.
 pub struct McpReadResourceTool {
-    pub client: Arc<McpClient>,
+    pub connection: Arc<McpConnection>,
+    pub limit: Arc<Semaphore>,
     pub registered_name: String,
 }
 
 impl McpReadResourceTool {
-    pub fn new(client: Arc<McpClient>) -> Self {
-        let registered_name = format!("mcp__{}__read_resource", client.server_name);
+    pub fn new(connection: Arc<McpConnection>, limit: Arc<Semaphore>) -> Self {
+        let registered_name = format!("mcp__{}__read_resource", connection.server_name());
         Self {
-            client,
+            connection,
+            limit,
             registered_name,
         }
     }
@@ -106,16 +151,70 @@ impl Tool for McpReadResourceTool {
             .as_str()
             .ok_or_else(|| anyhow::anyhow!("uri is required"))?;
 
-        match self.client.read_resource(uri).await {
+        let cache = self.connection.resources();
+        if let Some(contents) = cache.get(uri).await {
+            return Ok(ToolResult::ok(resource_text(&contents)));
+        }
+        let client = match self.connection.client().await {
+            Ok(client) => client,
+            Err(e) => return Ok(unavailable(&self.connection, e)),
+        };
+        let cacheable = limited(&self.limit, cache.watch(client, uri)).await?;
+        match limited(&self.limit, client.read_resource(uri)).await? {
             Ok(contents) => {
-                let text = contents
-                    .iter()
-                    .filter_map(|c| c.text.as_deref())
-                    .collect::<Vec<_>>()
-                    .join("\n");
+                let text = resource_text(&contents);
+                if cacheable {
+                    cache.insert(uri, contents).await;
+                }
                 Ok(ToolResult::ok(text))
             }
             Err(e) => Ok(ToolResult::err(e.to_string())),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future::join_all;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Stand-in for a slow MCP server that records how many calls overlap.
+    #[derive(Default)]
+    struct FakeServer {
+        in_flight: AtomicUsize,
+        max_seen: AtomicUsize,
+    }
+
+    impl FakeServer {
+        async fn call(&self) {
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_seen.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_to_one_server_respect_the_limit() {
+        let server = FakeServer::default();
+        let limit = server_limit(3);
+        let results = join_all((0..12).map(|_| limited(&limit, server.call()))).await;
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(server.max_seen.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn different_servers_do_not_share_a_limit() {
+        let shared = FakeServer::default();
+        let (a, b) = (server_limit(1), server_limit(1));
+        join_all((0..4).map(|i| limited(if i % 2 == 0 { &a } else { &b }, shared.call()))).await;
+        assert_eq!(shared.max_seen.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn zero_limit_still_allows_one_call() {
+        assert_eq!(server_limit(0).available_permits(), 1);
+    }
+}
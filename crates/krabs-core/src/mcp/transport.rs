@@ -2,20 +2,34 @@ use anyhow::{bail, Result};
 use futures_util::StreamExt;
 use reqwest::Client;
 use serde_json::Value;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, ChildStdin, ChildStdout};
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, oneshot, Mutex};
+use tokio::task::JoinHandle;
 use tracing::debug;
 
 use super::jsonrpc::{JsonRpcNotification, JsonRpcRequest, JsonRpcResponse};
 
 // ── Stdio transport ──────────────────────────────────────────────────────────
 
+/// Requests waiting for their response, by id. `None` once the server has
+/// closed its stdout, so new requests fail instead of waiting forever.
+type Pending = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<JsonRpcResponse>>>>>;
+
+/// How many server notifications are buffered for each subscriber.
+const NOTIFICATION_BUFFER: usize = 64;
+
 pub struct StdioTransport {
     stdin: Mutex<ChildStdin>,
-    stdout: Mutex<BufReader<ChildStdout>>,
     id_counter: AtomicU64,
+    pending: Pending,
+    notifications: broadcast::Sender<JsonRpcNotification>,
+    /// Reads the server's stdout, routing responses to `pending` and
+    /// notifications to `notifications`.
+    reader: JoinHandle<()>,
     _child: Mutex<Child>,
 }
 
@@ -37,10 +51,20 @@ impl StdioTransport {
             .take()
             .ok_or_else(|| anyhow::anyhow!("no stdout from MCP process"))?;
 
+        let pending: Pending = Arc::new(Mutex::new(Some(HashMap::new())));
+        let (notifications, _) = broadcast::channel(NOTIFICATION_BUFFER);
+        let reader = tokio::spawn(read_messages(
+            stdout,
+            Arc::clone(&pending),
+            notifications.clone(),
+        ));
+
         Ok(Self {
             stdin: Mutex::new(stdin),
-            stdout: Mutex::new(BufReader::new(stdout)),
             id_counter: AtomicU64::new(1),
+            pending,
+            notifications,
+            reader,
             _child: Mutex::new(child),
         })
     }
@@ -52,44 +76,25 @@ impl StdioTransport {
         line.push('\n');
         debug!("MCP stdio → {}", line.trim());
 
-        {
-            let mut stdin = self.stdin.lock().await;
-            stdin.write_all(line.as_bytes()).await?;
-            stdin.flush().await?;
-        }
-
-        // Read lines until we find a response matching our id
-        loop {
-            let mut buf = String::new();
-            {
-                let mut stdout = self.stdout.lock().await;
-                stdout.read_line(&mut buf).await?;
-            }
-            let trimmed = buf.trim();
-            if trimmed.is_empty() {
-                bail!("MCP server closed connection");
-            }
-            debug!("MCP stdio ← {}", trimmed);
-
-            // Skip notifications (no "id" field or id is null)
-            let raw: Value = match serde_json::from_str(trimmed) {
-                Ok(v) => v,
-                Err(_) => continue,
-            };
-            if raw.get("id").is_none() || raw["id"].is_null() {
-                continue; // notification
-            }
-
-            let resp: JsonRpcResponse = serde_json::from_value(raw)?;
-            if resp.id != Some(id) {
-                continue;
+        let (tx, rx) = oneshot::channel();
+        match self.pending.lock().await.as_mut() {
+            Some(pending) => pending.insert(id, tx),
+            None => bail!("MCP server closed connection"),
+        };
+        if let Err(e) = self.write_line(&line).await {
+            if let Some(pending) = self.pending.lock().await.as_mut() {
+                pending.remove(&id);
             }
+            return Err(e);
+        }
 
-            if let Some(err) = resp.error {
-                bail!("MCP error {}: {}", err.code, err.message);
-            }
-            return Ok(resp.result.unwrap_or(Value::Null));
+        let Ok(resp) = rx.await else {
+            bail!("MCP server closed connection");
+        };
+        if let Some(err) = resp.error {
+            bail!("MCP error {}: {}", err.code, err.message);
         }
+        Ok(resp.result.unwrap_or(Value::Null))
     }
 
     pub async fn notify(&self, method: &str, params: Option<Value>) -> Result<()> {
@@ -97,11 +102,66 @@ impl StdioTransport {
         let mut line = serde_json::to_string(&notif)?;
         line.push('\n');
         debug!("MCP stdio notify → {}", line.trim());
+        self.write_line(&line).await
+    }
+
+    async fn write_line(&self, line: &str) -> Result<()> {
         let mut stdin = self.stdin.lock().await;
         stdin.write_all(line.as_bytes()).await?;
         stdin.flush().await?;
         Ok(())
     }
+
+    /// Notifications the server sends from now on.
+    pub fn notifications(&self) -> broadcast::Receiver<JsonRpcNotification> {
+        self.notifications.subscribe()
+    }
+}
+
+impl Drop for StdioTransport {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Route each line the server writes: responses to the request waiting on
+/// their id, notifications to every subscriber. Lines that are neither are
+/// skipped.
+async fn read_messages(
+    stdout: ChildStdout,
+    pending: Pending,
+    notifications: broadcast::Sender<JsonRpcNotification>,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        debug!("MCP stdio ← {}", trimmed);
+        let Ok(raw) = serde_json::from_str::<Value>(trimmed) else {
+            continue;
+        };
+        if raw.get("id").is_none() || raw["id"].is_null() {
+            if let Ok(notif) = serde_json::from_value(raw) {
+                // No subscribers is fine.
+                let _ = notifications.send(notif);
+            }
+            continue;
+        }
+        let Ok(resp) = serde_json::from_value::<JsonRpcResponse>(raw) else {
+            continue;
+        };
+        let waiter = match (resp.id, pending.lock().await.as_mut()) {
+            (Some(id), Some(pending)) => pending.remove(&id),
+            _ => None,
+        };
+        if let Some(waiter) = waiter {
+            let _ = waiter.send(resp);
+        }
+    }
+    // Dropping the waiters fails every request still in flight.
+    pending.lock().await.take();
 }
 
 // ── SSE/HTTP transport ───────────────────────────────────────────────────────
@@ -207,4 +267,13 @@ impl Transport {
             }
         }
     }
+
+    /// Notifications the server sends from now on. `None` over SSE, which
+    /// only sees the responses to its own requests.
+    pub fn notifications(&self) -> Option<broadcast::Receiver<JsonRpcNotification>> {
+        match self {
+            Self::Stdio(t) => Some(t.notifications()),
+            Self::Sse(_) => None,
+        }
+    }
 }
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use std::collections::HashMap;
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
 
 #[async_trait]
 pub trait MemoryStore: Send + Sync {
@@ -11,6 +11,27 @@ pub trait MemoryStore: Send + Sync {
     async fn keys(&self) -> Result<Vec<String>>;
 }
 
+/// Lets one store back both the agent (`KrabsAgentBuilder::memory`) and the
+/// `memory_*` tools.
+#[async_trait]
+impl<T: MemoryStore + ?Sized> MemoryStore for Arc<T> {
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        (**self).set(key, value).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        (**self).get(key).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        (**self).delete(key).await
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        (**self).keys().await
+    }
+}
+
 pub struct InMemoryStore {
     data: RwLock<HashMap<String, String>>,
 }
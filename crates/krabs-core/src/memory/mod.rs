@@ -1,3 +1,7 @@
 #[allow(clippy::module_inception)]
 pub mod memory;
+pub mod scoped;
+pub mod sqlite;
 pub use memory::{InMemoryStore, MemoryStore};
+pub use scoped::{memory_prompt, session_namespace, MemoryScope, ScopedMemory, GLOBAL_NAMESPACE};
+pub use sqlite::SqliteMemoryStore;
@@ -0,0 +1,168 @@
+use super::memory::MemoryStore;
+use anyhow::{bail, Result};
+use std::sync::Arc;
+
+/// Namespace of the memory every session shares.
+pub const GLOBAL_NAMESPACE: &str = "global";
+
+/// Namespace of one session's memory.
+pub fn session_namespace(session_id: &str) -> String {
+    format!("session:{session_id}")
+}
+
+/// Which memory a `memory_*` tool call reads or writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryScope {
+    /// Notes for the current session only.
+    Session,
+    /// Notes every session sees, e.g. the user's preferences.
+    Global,
+}
+
+impl MemoryScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Session => "session",
+            Self::Global => "global",
+        }
+    }
+
+    /// Parse `"session"` or `"global"`.
+    pub fn parse(scope: &str) -> Result<Self> {
+        match scope {
+            "session" => Ok(Self::Session),
+            "global" => Ok(Self::Global),
+            other => bail!("unknown memory scope '{other}': use \"session\" or \"global\""),
+        }
+    }
+}
+
+/// The two memories the `memory_*` tools see: the current session's and the
+/// global one.
+#[derive(Clone)]
+pub struct ScopedMemory {
+    session: Arc<dyn MemoryStore>,
+    global: Arc<dyn MemoryStore>,
+}
+
+impl ScopedMemory {
+    pub fn new(session: Arc<dyn MemoryStore>, global: Arc<dyn MemoryStore>) -> Self {
+        Self { session, global }
+    }
+
+    pub fn store(&self, scope: MemoryScope) -> &Arc<dyn MemoryStore> {
+        match scope {
+            MemoryScope::Session => &self.session,
+            MemoryScope::Global => &self.global,
+        }
+    }
+
+    /// Entries of `scope` whose key or value contains `query`, ignoring case;
+    /// every entry when `query` is empty.
+    pub async fn search(&self, scope: MemoryScope, query: &str) -> Result<Vec<(String, String)>> {
+        let store = self.store(scope);
+        let query = query.to_lowercase();
+        let mut found = Vec::new();
+        for key in store.keys().await? {
+            let Some(value) = store.get(&key).await? else {
+                continue;
+            };
+            if key.to_lowercase().contains(&query) || value.to_lowercase().contains(&query) {
+                found.push((key, value));
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// The system-prompt section listing `global`'s entries, cut to about
+/// `max_tokens` (~4 bytes each). `None` when the memory is empty or
+/// `max_tokens` is 0.
+pub async fn memory_prompt(global: &dyn MemoryStore, max_tokens: usize) -> Result<Option<String>> {
+    const HEADER: &str =
+        "## Memory\n\nNotes saved with `memory_write` (global scope) in earlier sessions:";
+    if max_tokens == 0 {
+        return Ok(None);
+    }
+    let keys = global.keys().await?;
+    if keys.is_empty() {
+        return Ok(None);
+    }
+    let budget = max_tokens.saturating_mul(4);
+    let mut out = HEADER.to_string();
+    let mut shown = 0;
+    for key in &keys {
+        let Some(value) = global.get(key).await? else {
+            continue;
+        };
+        let line = format!("\n- {key}: {value}");
+        if out.len() + line.len() > budget {
+            break;
+        }
+        out.push_str(&line);
+        shown += 1;
+    }
+    if shown < keys.len() {
+        out.push_str(&format!(
+            "\n- … {} more; find them with `memory_search`.",
+            keys.len() - shown
+        ));
+    }
+    Ok(Some(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::InMemoryStore;
+
+    #[tokio::test]
+    async fn search_matches_keys_and_values_in_one_scope() {
+        let memory = ScopedMemory::new(
+            Arc::new(InMemoryStore::new()),
+            Arc::new(InMemoryStore::new()),
+        );
+        let global = memory.store(MemoryScope::Global);
+        global
+            .set("indent", "Prefers tabs over spaces")
+            .await
+            .unwrap();
+        global.set("editor", "helix").await.unwrap();
+        memory
+            .store(MemoryScope::Session)
+            .set("task", "tabs in the Makefile")
+            .await
+            .unwrap();
+
+        let found = memory.search(MemoryScope::Global, "TABS").await.unwrap();
+        assert_eq!(
+            found,
+            [("indent".to_string(), "Prefers tabs over spaces".to_string())]
+        );
+        assert_eq!(
+            memory.search(MemoryScope::Global, "").await.unwrap().len(),
+            2
+        );
+        assert_eq!(
+            memory.search(MemoryScope::Session, "tabs").await.unwrap()[0].0,
+            "task"
+        );
+        assert!(MemoryScope::parse("project").is_err());
+    }
+
+    #[tokio::test]
+    async fn prompt_lists_global_notes_within_the_cap() {
+        let global = InMemoryStore::new();
+        assert!(memory_prompt(&global, 500).await.unwrap().is_none());
+
+        global.set("a", "Prefers tabs over spaces").await.unwrap();
+        global.set("b", "x".repeat(400).as_str()).await.unwrap();
+        let prompt = memory_prompt(&global, 100).await.unwrap().unwrap();
+        assert!(
+            prompt.contains("\n- a: Prefers tabs over spaces"),
+            "{prompt}"
+        );
+        assert!(prompt.ends_with("… 1 more; find them with `memory_search`."));
+        assert!(memory_prompt(&global, 0).await.unwrap().is_none());
+    }
+}
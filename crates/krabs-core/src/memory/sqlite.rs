@@ -0,0 +1,150 @@
+use super::memory::MemoryStore;
+use crate::config::config::SqliteConfig;
+use crate::session::session::connect;
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::{Row, SqlitePool};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MIGRATE: &str = r#"
+CREATE TABLE IF NOT EXISTS memory (
+    namespace   TEXT    NOT NULL,
+    key         TEXT    NOT NULL,
+    value       TEXT    NOT NULL,
+    updated_at  INTEGER NOT NULL,
+    PRIMARY KEY (namespace, key)
+);
+"#;
+
+/// `MemoryStore` backed by the same SQLite database as sessions, so stored
+/// entries survive restarts. Every entry is scoped to a namespace (e.g. a
+/// project directory) and stores never see each other's keys.
+#[derive(Clone)]
+pub struct SqliteMemoryStore {
+    pool: SqlitePool,
+    namespace: String,
+}
+
+impl SqliteMemoryStore {
+    pub async fn open(db_path: &Path, namespace: impl Into<String>) -> Result<Self> {
+        Self::open_with(db_path, namespace, &SqliteConfig::default()).await
+    }
+
+    /// Like [`open`](Self::open), with the pragmas from `sqlite`.
+    pub async fn open_with(
+        db_path: &Path,
+        namespace: impl Into<String>,
+        sqlite: &SqliteConfig,
+    ) -> Result<Self> {
+        let pool = connect(db_path, sqlite).await?;
+        sqlx::query(MIGRATE).execute(&pool).await?;
+        Ok(Self {
+            pool,
+            namespace: namespace.into(),
+        })
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Another namespace of the same database, sharing this store's
+    /// connections.
+    pub fn with_namespace(&self, namespace: impl Into<String>) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            namespace: namespace.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl MemoryStore for SqliteMemoryStore {
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        let updated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        sqlx::query(
+            "INSERT INTO memory (namespace, key, value, updated_at) VALUES (?, ?, ?, ?)
+             ON CONFLICT(namespace, key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+        )
+        .bind(&self.namespace)
+        .bind(key)
+        .bind(value)
+        .bind(updated_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        let row = sqlx::query("SELECT value FROM memory WHERE namespace = ? AND key = ?")
+            .bind(&self.namespace)
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| r.get("value")))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM memory WHERE namespace = ? AND key = ?")
+            .bind(&self.namespace)
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        let rows = sqlx::query("SELECT key FROM memory WHERE namespace = ? ORDER BY key")
+            .bind(&self.namespace)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(|r| r.get("key")).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn temp_db() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("krabs_memory_test_{}.db", Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn fact_survives_reopen() {
+        let path = temp_db();
+        {
+            let store = SqliteMemoryStore::open(&path, "proj").await.unwrap();
+            store.set("db", "postgres 16").await.unwrap();
+        }
+        let store = SqliteMemoryStore::open(&path, "proj").await.unwrap();
+        assert_eq!(
+            store.get("db").await.unwrap().as_deref(),
+            Some("postgres 16")
+        );
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn namespaces_are_isolated() {
+        let path = temp_db();
+        let a = SqliteMemoryStore::open(&path, "a").await.unwrap();
+        let b = SqliteMemoryStore::open(&path, "b").await.unwrap();
+        a.set("k", "from a").await.unwrap();
+        assert!(b.get("k").await.unwrap().is_none());
+        assert!(b.keys().await.unwrap().is_empty());
+
+        a.set("k", "updated").await.unwrap();
+        assert_eq!(a.keys().await.unwrap(), vec!["k".to_string()]);
+        assert_eq!(a.get("k").await.unwrap().as_deref(), Some("updated"));
+
+        a.delete("k").await.unwrap();
+        assert!(a.get("k").await.unwrap().is_none());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,298 @@
+use super::permissions::tool_rule_matches;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Skip the permission prompt for calls of `tool` whose `arg` matches
+/// `arg_pattern`.
+///
+/// Example in `.krabs.json`:
+/// ```json
+/// {
+///   "auto_approve": [
+///     { "tool": "bash", "arg": "command", "arg_pattern": "^git (status|diff|log)( [^;&|`$]*)?$" },
+///     { "tool": "read", "arg": "path", "arg_pattern": "^(\\./)?src/" }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApprovalRule {
+    /// Tool name or glob pattern, as in `auto_approve_tools`.
+    pub tool: String,
+    /// Argument the pattern is matched against. Non-string values are
+    /// matched as JSON.
+    pub arg: String,
+    /// Regex searched for anywhere in the argument; anchor it with `^…$` to
+    /// match the whole value.
+    pub arg_pattern: String,
+}
+
+struct CompiledRule {
+    rule: ApprovalRule,
+    /// `Err` holds why `arg_pattern` didn't compile. Such a rule matches
+    /// nothing, or every call of its tool when `fail_closed`.
+    regex: Result<Regex, String>,
+    /// Set for `never_auto_approve` rules, so a typo asks more, not less.
+    fail_closed: bool,
+    hits: AtomicUsize,
+}
+
+impl CompiledRule {
+    fn compile(rule: &ApprovalRule, fail_closed: bool) -> Self {
+        Self {
+            regex: Regex::new(&rule.arg_pattern).map_err(|e| e.to_string()),
+            rule: rule.clone(),
+            fail_closed,
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    fn matches(&self, tool_name: &str, args: &serde_json::Value) -> bool {
+        if !tool_rule_matches(&self.rule.tool, tool_name) {
+            return false;
+        }
+        let Ok(regex) = &self.regex else {
+            return self.fail_closed;
+        };
+        match args.get(&self.rule.arg) {
+            None | Some(serde_json::Value::Null) => false,
+            Some(serde_json::Value::String(s)) => regex.is_match(s),
+            Some(other) => regex.is_match(&other.to_string()),
+        }
+    }
+
+    fn status(&self) -> ApprovalRuleStatus<'_> {
+        ApprovalRuleStatus {
+            rule: &self.rule,
+            hits: self.hits.load(Ordering::Relaxed),
+            error: self.regex.as_ref().err().map(String::as_str),
+        }
+    }
+}
+
+/// The `auto_approve` rules of a config, counting the calls each approved,
+/// along with `auto_approve_all` and the `never_auto_approve` rules that
+/// override both.
+pub struct AutoApprover {
+    rules: Vec<CompiledRule>,
+    never: Vec<CompiledRule>,
+    approve_all: bool,
+}
+
+/// What [`AutoApprover::decide`] makes of a tool call.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Approval<'a> {
+    /// Run it unasked: an `auto_approve` rule matched.
+    Rule(&'a ApprovalRule),
+    /// Run it unasked: `auto_approve_all` is on.
+    All,
+    /// Ask, unless the user already allowed the tool.
+    Ask,
+    /// Ask even if the user already allowed the tool: a
+    /// `never_auto_approve` rule matched.
+    AlwaysAsk(&'a ApprovalRule),
+}
+
+/// One rule as listed for review.
+pub struct ApprovalRuleStatus<'a> {
+    pub rule: &'a ApprovalRule,
+    /// Calls the rule has approved so far.
+    pub hits: usize,
+    /// Why the pattern is unusable, if it is.
+    pub error: Option<&'a str>,
+}
+
+impl AutoApprover {
+    pub fn new(rules: &[ApprovalRule]) -> Self {
+        Self {
+            rules: rules
+                .iter()
+                .map(|r| CompiledRule::compile(r, false))
+                .collect(),
+            never: Vec::new(),
+            approve_all: false,
+        }
+    }
+
+    /// Approve every call no `never_auto_approve` rule matches.
+    pub fn with_approve_all(mut self, approve_all: bool) -> Self {
+        self.approve_all = approve_all;
+        self
+    }
+
+    /// Calls matching one of `rules` always ask, whatever else approves them.
+    /// A rule whose pattern doesn't compile makes every call of its tool ask.
+    pub fn with_never(mut self, rules: &[ApprovalRule]) -> Self {
+        self.never = rules
+            .iter()
+            .map(|r| CompiledRule::compile(r, true))
+            .collect();
+        self
+    }
+
+    /// The first rule approving a call of `tool_name` with `args`, counted as
+    /// a hit. A call missing the rule's argument is not approved by it.
+    /// Ignores the `never_auto_approve` rules; see [`decide`](Self::decide).
+    pub fn approve(&self, tool_name: &str, args: &serde_json::Value) -> Option<&ApprovalRule> {
+        let compiled = self.rules.iter().find(|c| c.matches(tool_name, args))?;
+        compiled.hits.fetch_add(1, Ordering::Relaxed);
+        Some(&compiled.rule)
+    }
+
+    /// Whether a call of `tool_name` with `args` may run without asking.
+    /// A `never_auto_approve` match wins over everything, then the
+    /// `auto_approve` rules, then `auto_approve_all`.
+    pub fn decide(&self, tool_name: &str, args: &serde_json::Value) -> Approval<'_> {
+        if let Some(never) = self.never.iter().find(|c| c.matches(tool_name, args)) {
+            never.hits.fetch_add(1, Ordering::Relaxed);
+            return Approval::AlwaysAsk(&never.rule);
+        }
+        match self.approve(tool_name, args) {
+            Some(rule) => Approval::Rule(rule),
+            None if self.approve_all => Approval::All,
+            None => Approval::Ask,
+        }
+    }
+
+    /// Every `auto_approve` rule, in config order.
+    pub fn rules(&self) -> impl Iterator<Item = ApprovalRuleStatus<'_>> {
+        self.rules.iter().map(CompiledRule::status)
+    }
+
+    /// Every `never_auto_approve` rule, in config order; `hits` counts the
+    /// calls each made ask.
+    pub fn never_rules(&self) -> impl Iterator<Item = ApprovalRuleStatus<'_>> {
+        self.never.iter().map(CompiledRule::status)
+    }
+
+    /// One line per rule whose pattern doesn't compile, saying what that
+    /// means for it, for showing at startup.
+    pub fn warnings(&self) -> Vec<String> {
+        let invalid = |c: &CompiledRule, effect: &str| {
+            c.regex.as_ref().err().map(|e| {
+                format!(
+                    "{} rule for {} has an invalid arg_pattern /{}/ ({e}); {effect}",
+                    if c.fail_closed {
+                        "never_auto_approve"
+                    } else {
+                        "auto_approve"
+                    },
+                    c.rule.tool,
+                    c.rule.arg_pattern,
+                )
+            })
+        };
+        let rules = self
+            .rules
+            .iter()
+            .filter_map(|c| invalid(c, "it never matches"));
+        let never = self
+            .never
+            .iter()
+            .filter_map(|c| invalid(c, "every call of the tool will ask"));
+        rules.chain(never).collect()
+    }
+
+    pub fn approves_all(&self) -> bool {
+        self.approve_all
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.never.is_empty() && !self.approve_all
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule(tool: &str, arg: &str, arg_pattern: &str) -> ApprovalRule {
+        ApprovalRule {
+            tool: tool.into(),
+            arg: arg.into(),
+            arg_pattern: arg_pattern.into(),
+        }
+    }
+
+    #[test]
+    fn rules_match_on_tool_and_argument() {
+        let approver = AutoApprover::new(&[
+            rule("bash", "command", "^git (status|diff|log)$"),
+            rule("mcp__fs__*", "path", "^src/"),
+            rule("read", "path", "(unclosed"),
+        ]);
+        let git = approver.approve("bash", &json!({ "command": "git status" }));
+        assert_eq!(git.map(|r| r.tool.as_str()), Some("bash"));
+        assert!(approver
+            .approve("bash", &json!({ "command": "git status; rm -rf /" }))
+            .is_none());
+        assert!(approver.approve("bash", &json!({})).is_none());
+        assert!(approver
+            .approve("mcp__fs__read", &json!({ "path": "src/lib.rs" }))
+            .is_some());
+        assert!(approver
+            .approve("write", &json!({ "path": "src/lib.rs" }))
+            .is_none());
+        assert!(approver
+            .approve("read", &json!({ "path": "(unclosed" }))
+            .is_none());
+
+        let statuses: Vec<_> = approver.rules().collect();
+        assert_eq!(
+            statuses.iter().map(|s| s.hits).collect::<Vec<_>>(),
+            [1, 1, 0]
+        );
+        assert!(statuses[0].error.is_none());
+        assert!(statuses[2].error.is_some());
+    }
+
+    #[test]
+    fn never_rules_still_ask_when_approving_all() {
+        let approver = AutoApprover::new(&[rule("bash", "command", "^rm ")])
+            .with_approve_all(true)
+            .with_never(&[rule("bash", "command", r"\brm\b")]);
+        assert_eq!(
+            approver.decide("bash", &json!({ "command": "ls -la" })),
+            Approval::All
+        );
+        assert_eq!(
+            approver.decide("write", &json!({ "path": "a" })),
+            Approval::All
+        );
+        let Approval::AlwaysAsk(never) =
+            approver.decide("bash", &json!({ "command": "rm -rf target" }))
+        else {
+            panic!("rm should always ask");
+        };
+        assert_eq!(never.arg_pattern, r"\brm\b");
+        assert_eq!(approver.never_rules().map(|s| s.hits).sum::<usize>(), 1);
+        // The `auto_approve` rule matching `rm` is overridden too.
+        assert_eq!(approver.rules().map(|s| s.hits).sum::<usize>(), 0);
+
+        let cautious = AutoApprover::new(&[]);
+        assert!(cautious.warnings().is_empty());
+        assert_eq!(
+            cautious.decide("bash", &json!({ "command": "ls" })),
+            Approval::Ask
+        );
+    }
+
+    #[test]
+    fn invalid_never_rules_ask_for_every_call_of_their_tool() {
+        let approver = AutoApprover::new(&[])
+            .with_approve_all(true)
+            .with_never(&[rule("bash", "command", "(unclosed")]);
+        assert!(matches!(
+            approver.decide("bash", &json!({ "command": "ls" })),
+            Approval::AlwaysAsk(_)
+        ));
+        assert_eq!(
+            approver.decide("read", &json!({ "path": "a" })),
+            Approval::All
+        );
+        let warnings = approver.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("every call of the tool will ask"));
+    }
+}
@@ -1,3 +1,5 @@
+pub mod approval;
 #[allow(clippy::module_inception)]
 pub mod permissions;
-pub use permissions::PermissionGuard;
+pub use approval::{Approval, ApprovalRule, ApprovalRuleStatus, AutoApprover};
+pub use permissions::{tool_rule_matches, PermissionGuard};
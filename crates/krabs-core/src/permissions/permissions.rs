@@ -1,9 +1,20 @@
+use anyhow::Result;
+use glob::Pattern;
 use std::collections::HashSet;
 
+/// Decides which tools an agent may call.
+///
+/// Rules are evaluated in this order:
+/// 1. exact deny names, then deny glob patterns — a match always denies
+/// 2. exact allow names, then allow glob patterns — if any allow rule exists,
+///    the tool must match one of them
+/// 3. otherwise the tool is allowed
 #[derive(Debug, Clone)]
 pub struct PermissionGuard {
     allow_list: Option<HashSet<String>>,
     deny_list: HashSet<String>,
+    allow_patterns: Vec<Pattern>,
+    deny_patterns: Vec<Pattern>,
 }
 
 impl PermissionGuard {
@@ -11,26 +22,47 @@ impl PermissionGuard {
         Self {
             allow_list: None,
             deny_list: HashSet::new(),
+            allow_patterns: Vec::new(),
+            deny_patterns: Vec::new(),
         }
     }
     pub fn allow_only(tools: impl IntoIterator<Item = impl Into<String>>) -> Self {
         Self {
             allow_list: Some(tools.into_iter().map(|s| s.into()).collect()),
-            deny_list: HashSet::new(),
+            ..Self::new()
         }
     }
     pub fn deny(mut self, tools: impl IntoIterator<Item = impl Into<String>>) -> Self {
         self.deny_list.extend(tools.into_iter().map(|s| s.into()));
         self
     }
+    /// Allow every tool whose name matches the glob `pattern` (e.g. `mcp__github__*`).
+    /// Adding any allow pattern turns the guard into an allowlist.
+    pub fn allow_pattern(mut self, pattern: &str) -> Result<Self> {
+        self.allow_patterns.push(Pattern::new(pattern)?);
+        Ok(self)
+    }
+    /// Deny every tool whose name matches the glob `pattern` (e.g. `mcp__*`).
+    /// Deny patterns are checked before any allow rule.
+    pub fn deny_pattern(mut self, pattern: &str) -> Result<Self> {
+        self.deny_patterns.push(Pattern::new(pattern)?);
+        Ok(self)
+    }
     pub fn is_allowed(&self, tool_name: &str) -> bool {
         if self.deny_list.contains(tool_name) {
             return false;
         }
-        if let Some(ref allow) = self.allow_list {
-            return allow.contains(tool_name);
+        if self.deny_patterns.iter().any(|p| p.matches(tool_name)) {
+            return false;
+        }
+        let exact = self
+            .allow_list
+            .as_ref()
+            .map(|allow| allow.contains(tool_name));
+        if exact == Some(true) || self.allow_patterns.iter().any(|p| p.matches(tool_name)) {
+            return true;
         }
-        true
+        exact.is_none() && self.allow_patterns.is_empty()
     }
 }
 
@@ -39,3 +71,73 @@ impl Default for PermissionGuard {
         Self::new()
     }
 }
+
+/// `true` when `rule` equals `tool_name` or is a glob pattern matching it.
+/// Invalid patterns only match by exact name.
+pub fn tool_rule_matches(rule: &str, tool_name: &str) -> bool {
+    rule == tool_name
+        || Pattern::new(rule)
+            .map(|p| p.matches(tool_name))
+            .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_allows_everything() {
+        let guard = PermissionGuard::new();
+        assert!(guard.is_allowed("bash"));
+        assert!(guard.is_allowed("mcp__github__create_issue"));
+    }
+
+    #[test]
+    fn exact_rules_still_work() {
+        let guard = PermissionGuard::allow_only(["read", "glob"]).deny(["glob"]);
+        assert!(guard.is_allowed("read"));
+        assert!(!guard.is_allowed("glob"));
+        assert!(!guard.is_allowed("bash"));
+    }
+
+    #[test]
+    fn deny_pattern_blocks_matching_tools() {
+        let guard = PermissionGuard::new().deny_pattern("mcp__*").unwrap();
+        assert!(!guard.is_allowed("mcp__github__create_issue"));
+        assert!(guard.is_allowed("bash"));
+    }
+
+    #[test]
+    fn allow_pattern_turns_guard_into_allowlist() {
+        let guard = PermissionGuard::new()
+            .allow_pattern("mcp__github__*")
+            .unwrap();
+        assert!(guard.is_allowed("mcp__github__list_prs"));
+        assert!(!guard.is_allowed("mcp__slack__post"));
+        assert!(!guard.is_allowed("bash"));
+    }
+
+    #[test]
+    fn deny_pattern_wins_over_allow() {
+        let guard = PermissionGuard::allow_only(["read"])
+            .allow_pattern("mcp__*")
+            .unwrap()
+            .deny_pattern("mcp__slack__*")
+            .unwrap();
+        assert!(guard.is_allowed("read"));
+        assert!(guard.is_allowed("mcp__github__list_prs"));
+        assert!(!guard.is_allowed("mcp__slack__post"));
+    }
+
+    #[test]
+    fn invalid_pattern_is_an_error() {
+        assert!(PermissionGuard::new().allow_pattern("[").is_err());
+    }
+
+    #[test]
+    fn rule_matches_exact_or_glob() {
+        assert!(tool_rule_matches("bash", "bash"));
+        assert!(tool_rule_matches("mcp__fs__*", "mcp__fs__read"));
+        assert!(!tool_rule_matches("mcp__fs__*", "mcp__git__log"));
+    }
+}
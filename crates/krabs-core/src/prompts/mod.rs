@@ -1,2 +1,5 @@
 pub mod system;
-pub use system::{base_system_prompt, SystemPromptBuilder};
+pub use system::{
+    base_system_prompt, load_project_prompt, with_project_prompt, PromptSection,
+    SystemPromptBuilder,
+};
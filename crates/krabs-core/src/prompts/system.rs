@@ -1,4 +1,5 @@
 use crate::tools::tool::ToolDef;
+use std::path::Path;
 
 /// Immutable soul / identity layer — embedded at compile time.
 pub const SOUL: &str = include_str!("system/SOUL.md");
@@ -12,6 +13,38 @@ pub fn base_system_prompt() -> String {
     format!("{}\n\n{}", SOUL, SYSTEM_PROMPT_BASE)
 }
 
+/// Read the project prompt file (`system_prompt_path`). A missing or blank
+/// file yields `None`; so does an unreadable one, after a warning.
+pub async fn load_project_prompt(path: &Path) -> Option<String> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(text) if !text.trim().is_empty() => Some(text.trim().to_string()),
+        Ok(_) => None,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            tracing::warn!("Failed to read system prompt file {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// Combine the caller-supplied system prompt with the project prompt file:
+/// appended after it, or in its place when `replace` is set.
+pub fn with_project_prompt(caller: &str, project: Option<&str>, replace: bool) -> String {
+    match project {
+        None => caller.to_string(),
+        Some(p) if replace || caller.is_empty() => p.to_string(),
+        Some(p) => format!("{caller}\n\n{p}"),
+    }
+}
+
+/// One labelled part of an agent's assembled system prompt, as returned by
+/// [`KrabsAgent::system_prompt_sections`](crate::KrabsAgent::system_prompt_sections).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptSection {
+    pub name: &'static str,
+    pub text: String,
+}
+
 pub struct SystemPromptBuilder {
     base: String,
     sections: Vec<String>,
@@ -48,3 +81,38 @@ impl SystemPromptBuilder {
         format!("{}\n\n{}", self.base, self.sections.join("\n\n"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_prompt_appends_or_replaces() {
+        assert_eq!(with_project_prompt("be terse", None, true), "be terse");
+        assert_eq!(
+            with_project_prompt("be terse", Some("use tabs"), false),
+            "be terse\n\nuse tabs"
+        );
+        assert_eq!(
+            with_project_prompt("be terse", Some("use tabs"), true),
+            "use tabs"
+        );
+        assert_eq!(with_project_prompt("", Some("use tabs"), false), "use tabs");
+    }
+
+    #[tokio::test]
+    async fn missing_or_blank_project_prompt_is_ignored() {
+        let dir = std::env::temp_dir().join(format!("krabs-prompt-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("system.md");
+        assert_eq!(load_project_prompt(&path).await, None);
+        std::fs::write(&path, "  \n").unwrap();
+        assert_eq!(load_project_prompt(&path).await, None);
+        std::fs::write(&path, "Prefer small commits.\n").unwrap();
+        assert_eq!(
+            load_project_prompt(&path).await.as_deref(),
+            Some("Prefer small commits.")
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,4 +1,8 @@
-use super::provider::{LlmProvider, LlmResponse, Message, Role, StreamChunk, TokenUsage, ToolCall};
+use super::provider::{
+    ImageContent, LlmProvider, LlmResponse, Message, ReasoningEffort, ResponseFormat, Role,
+    StreamChunk, TokenUsage, ToolCallBuffer,
+};
+use super::{error::HttpTrace, redact, tokens, ProviderError};
 use crate::tools::tool::ToolDef;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -6,12 +10,27 @@ use futures_util::StreamExt;
 use reqwest::Client;
 use serde_json::{json, Value};
 use tokio::sync::mpsc;
+use tracing::debug;
+
+const PROVIDER: &str = "anthropic";
+
+/// Tool the model is made to call with its answer when a JSON reply is
+/// requested; Anthropic has no response-format switch, but tool input is
+/// always JSON matching the tool's schema.
+const STRUCTURED_TOOL: &str = "structured_response";
+/// `max_tokens` of a reply when no `max_output_tokens` is configured.
+pub const DEFAULT_MAX_TOKENS: u32 = 8096;
 
 pub struct AnthropicProvider {
     client: Client,
     base_url: String,
     api_key: String,
     model: String,
+    prompt_caching: bool,
+    thinking_budget: u32,
+    max_output_tokens: Option<u32>,
+    stop_sequences: Vec<String>,
+    trace_http: bool,
 }
 
 impl AnthropicProvider {
@@ -21,137 +40,189 @@ impl AnthropicProvider {
         model: impl Into<String>,
     ) -> Self {
         Self {
-            client: Client::new(),
+            client: super::http_client(0, 0),
             base_url: base_url.into(),
             api_key: api_key.into(),
             model: model.into(),
+            prompt_caching: false,
+            thinking_budget: 0,
+            max_output_tokens: None,
+            stop_sequences: Vec::new(),
+            trace_http: false,
         }
     }
-}
 
-fn build_anthropic_messages(messages: &[Message]) -> (Option<String>, Vec<Value>) {
-    let mut system_parts = Vec::new();
-    let mut msgs = Vec::new();
+    /// Mark the system prompt and tool definitions with `cache_control`
+    /// breakpoints so Anthropic can serve them from its prompt cache.
+    pub fn with_prompt_caching(mut self, enabled: bool) -> Self {
+        self.prompt_caching = enabled;
+        self
+    }
 
-    for m in messages {
-        match m.role {
-            Role::System => system_parts.push(m.content.clone()),
-            Role::User => msgs.push(json!({ "role": "user", "content": m.content })),
-            Role::Assistant => msgs.push(json!({ "role": "assistant", "content": m.content })),
-            Role::Tool => {
-                // Anthropic tool results go as user messages with tool_result content blocks
-                let id = m.tool_call_id.clone().unwrap_or_default();
-                msgs.push(json!({
-                    "role": "user",
-                    "content": [{
-                        "type": "tool_result",
-                        "tool_use_id": id,
-                        "content": m.content
-                    }]
-                }));
-            }
+    /// Enable extended thinking with up to `budget` reasoning tokens per
+    /// response, streamed as `StreamChunk::Thinking`. 0 disables it.
+    pub fn with_thinking_budget(mut self, budget: u32) -> Self {
+        self.thinking_budget = budget;
+        self
+    }
+
+    /// Enable extended thinking with the budget of `effort`, replacing any
+    /// [`with_thinking_budget`](Self::with_thinking_budget). Anthropic rejects
+    /// budgets under 1024 tokens, so smaller ones are raised to that.
+    pub fn with_reasoning_effort(mut self, effort: Option<ReasoningEffort>) -> Self {
+        if let Some(effort) = effort {
+            self.thinking_budget = match effort.budget_tokens() {
+                0 => 0,
+                n => n.max(1_024),
+            };
         }
+        self
     }
 
-    let system = if system_parts.is_empty() {
-        None
-    } else {
-        Some(system_parts.join("\n"))
-    };
+    /// Cap each reply at `max` tokens (`max_tokens`) instead of
+    /// [`DEFAULT_MAX_TOKENS`]. Any thinking budget comes on top.
+    pub fn with_max_output_tokens(mut self, max: Option<u32>) -> Self {
+        self.max_output_tokens = max;
+        self
+    }
 
-    (system, msgs)
-}
+    /// End each reply at the first of `stop`, sent as `stop_sequences`.
+    pub fn with_stop_sequences(mut self, stop: Vec<String>) -> Self {
+        self.stop_sequences = stop;
+        self
+    }
 
-fn build_anthropic_tools(tools: &[ToolDef]) -> Vec<Value> {
-    tools
-        .iter()
-        .map(|t| {
-            json!({
-                "name": t.name,
-                "description": t.description,
-                "input_schema": t.parameters
-            })
-        })
-        .collect()
-}
+    /// Log raw request and response bodies at `debug` level (target
+    /// `krabs::http`) with credentials redacted.
+    pub fn with_http_trace(mut self, enabled: bool) -> Self {
+        self.trace_http = enabled;
+        self
+    }
 
-#[async_trait]
-impl LlmProvider for AnthropicProvider {
-    async fn complete(&self, messages: &[Message], tools: &[ToolDef]) -> Result<LlmResponse> {
-        let (tx, mut rx) = mpsc::channel(256);
-        self.stream_complete(messages, tools, tx).await?;
+    /// What [`super::error::send`] needs to trace a failed response's body.
+    fn http_trace(&self) -> HttpTrace<'_> {
+        self.trace_http.then_some((PROVIDER, self.api_key.as_str()))
+    }
 
-        let mut content = String::new();
-        let mut tool_calls = Vec::new();
-        let mut usage = TokenUsage {
-            input_tokens: 0,
-            output_tokens: 0,
-        };
+    /// Fail requests that run longer than `request_ms` or can't connect
+    /// within `connect_ms`; 0 disables either limit.
+    pub fn with_timeouts(mut self, request_ms: u64, connect_ms: u64) -> Self {
+        self.client = super::http_client(request_ms, connect_ms);
+        self
+    }
 
-        while let Some(chunk) = rx.recv().await {
-            match chunk {
-                StreamChunk::Delta { text } => content.push_str(&text),
-                StreamChunk::ToolCallReady { call } => tool_calls.push(call),
-                StreamChunk::Done { usage: u } => usage = u,
-                StreamChunk::Status { .. } => {}
-            }
+    /// Ask the count-tokens endpoint what `messages` and `tools` cost.
+    async fn count_remote(&self, messages: &[Message], tools: &[ToolDef]) -> Result<u32> {
+        let (system, msgs) = build_anthropic_messages(messages);
+        if msgs.is_empty() {
+            anyhow::bail!("the count-tokens endpoint needs at least one message");
         }
-
-        if !tool_calls.is_empty() {
-            Ok(LlmResponse::ToolCalls {
-                calls: tool_calls,
-                usage,
-            })
-        } else {
-            Ok(LlmResponse::Message { content, usage })
+        let mut body = json!({ "model": self.model, "messages": msgs });
+        if let Some(sys) = system {
+            body["system"] = json!(sys);
+        }
+        let tools_val = build_anthropic_tools(tools);
+        if !tools_val.is_empty() {
+            body["tools"] = json!(tools_val);
         }
+        let url = format!(
+            "{}/v1/messages/count_tokens",
+            self.base_url.trim_end_matches('/')
+        );
+        let data: Value = super::error::send(
+            self.client
+                .post(&url)
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", "2023-06-01")
+                .timeout(tokens::COUNT_TIMEOUT)
+                .json(&body),
+            self.http_trace(),
+        )
+        .await?
+        .json()
+        .await
+        .map_err(ProviderError::from)?;
+        data["input_tokens"]
+            .as_u64()
+            .map(|n| n as u32)
+            .ok_or_else(|| anyhow::anyhow!("count-tokens response has no input_tokens"))
     }
 
-    async fn stream_complete(
+    /// Stream one Messages API request; `tool_choice` is sent as given.
+    async fn stream_request(
         &self,
         messages: &[Message],
         tools: &[ToolDef],
+        tool_choice: Option<Value>,
         tx: mpsc::Sender<StreamChunk>,
     ) -> Result<()> {
+        let _slot = super::request_slot().await;
         let (system, msgs) = build_anthropic_messages(messages);
-        let tools_val = build_anthropic_tools(tools);
+        let mut tools_val = build_anthropic_tools(tools);
 
+        // Thinking tokens count against max_tokens, so the answer keeps its
+        // usual room on top of the thinking budget.
         let mut body = json!({
             "model": self.model,
-            "max_tokens": 8096,
+            "max_tokens": self.max_output_tokens.unwrap_or(DEFAULT_MAX_TOKENS) + self.thinking_budget,
             "messages": msgs,
             "stream": true
         });
+        if !self.stop_sequences.is_empty() {
+            body["stop_sequences"] = json!(self.stop_sequences);
+        }
+        if self.thinking_budget > 0 {
+            body["thinking"] = json!({
+                "type": "enabled",
+                "budget_tokens": self.thinking_budget
+            });
+        }
 
         if let Some(sys) = system {
             body["system"] = json!(sys);
         }
+        if self.prompt_caching {
+            let mut no_system = Value::Null;
+            let system = body.get_mut("system").unwrap_or(&mut no_system);
+            apply_cache_breakpoints(system, &mut tools_val);
+        }
         if !tools_val.is_empty() {
             body["tools"] = json!(tools_val);
         }
+        if let Some(choice) = tool_choice {
+            body["tool_choice"] = choice;
+        }
+
+        if self.trace_http {
+            redact::trace_body(PROVIDER, "request", &body.to_string(), &self.api_key);
+        }
 
         let url = format!("{}/v1/messages", self.base_url.trim_end_matches('/'));
-        let resp = self
-            .client
-            .post(&url)
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&body)
-            .send()
-            .await?
-            .error_for_status()?;
-
-        // Accumulate tool use blocks: index -> (id, name, args_json)
-        let mut tool_blocks: std::collections::HashMap<usize, (String, String, String)> =
-            std::collections::HashMap::new();
+        let resp = super::error::send(
+            self.client
+                .post(&url)
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", "2023-06-01")
+                .header("content-type", "application/json")
+                .json(&body),
+            self.http_trace(),
+        )
+        .await?;
+
+        // Tool use blocks by content block index, until their block stops.
+        let mut tool_blocks = ToolCallBuffer::default();
         let mut current_block_idx: Option<usize> = None;
+        let mut start_usage = TokenUsage::default();
         let mut byte_stream = resp.bytes_stream();
         let mut leftover = String::new();
 
         while let Some(chunk) = byte_stream.next().await {
-            let bytes = chunk?;
-            leftover.push_str(&String::from_utf8_lossy(&bytes));
+            let bytes = chunk.map_err(ProviderError::from)?;
+            let text = String::from_utf8_lossy(&bytes);
+            if self.trace_http {
+                redact::trace_body(PROVIDER, "response chunk", &text, &self.api_key);
+            }
+            leftover.push_str(&text);
 
             while let Some(pos) = leftover.find('\n') {
                 let line = leftover[..pos].trim_end_matches('\r').to_string();
@@ -179,9 +250,12 @@ impl LlmProvider for AnthropicProvider {
                         let idx = ev["index"].as_u64().unwrap_or(0) as usize;
                         let block = &ev["content_block"];
                         if block["type"].as_str() == Some("tool_use") {
-                            let id = block["id"].as_str().unwrap_or("").to_string();
-                            let name = block["name"].as_str().unwrap_or("").to_string();
-                            tool_blocks.insert(idx, (id, name, String::new()));
+                            tool_blocks.push(
+                                idx,
+                                block["id"].as_str(),
+                                block["name"].as_str(),
+                                None,
+                            );
                             current_block_idx = Some(idx);
                         } else {
                             current_block_idx = None;
@@ -202,45 +276,40 @@ impl LlmProvider for AnthropicProvider {
                                         .await;
                                 }
                             }
-                        } else if delta_type == "input_json_delta" {
-                            if let Some(partial) = delta["partial_json"].as_str() {
-                                if let Some(entry) = tool_blocks.get_mut(&idx) {
-                                    entry.2.push_str(partial);
+                        } else if delta_type == "thinking_delta" {
+                            if let Some(text) = delta["thinking"].as_str() {
+                                if !text.is_empty() {
+                                    let _ = tx
+                                        .send(StreamChunk::Thinking {
+                                            text: text.to_string(),
+                                        })
+                                        .await;
                                 }
                             }
+                        } else if delta_type == "input_json_delta" {
+                            let partial = delta["partial_json"].as_str();
+                            tool_blocks.push(idx, None, None, partial);
                         }
                         let _ = current_block_idx; // suppress warning
                     }
                     "content_block_stop" => {
                         let idx = ev["index"].as_u64().unwrap_or(0) as usize;
-                        if let Some((id, name, args_str)) = tool_blocks.remove(&idx) {
-                            let args: Value = serde_json::from_str(&args_str).unwrap_or(json!({}));
-                            let _ = tx
-                                .send(StreamChunk::ToolCallReady {
-                                    call: ToolCall {
-                                        id,
-                                        name,
-                                        args,
-                                        thought_signature: None,
-                                    },
-                                })
-                                .await;
+                        for chunk in tool_blocks.finish(idx) {
+                            let _ = tx.send(chunk).await;
                         }
                     }
                     "message_delta" => {
                         if let Some(usage) = ev.get("usage") {
-                            let tok = TokenUsage {
-                                input_tokens: ev["usage"]["input_tokens"].as_u64().unwrap_or(0)
-                                    as u32,
-                                output_tokens: usage["output_tokens"].as_u64().unwrap_or(0) as u32,
-                            };
+                            let tok = parse_usage(usage, &start_usage);
                             let _ = tx.send(StreamChunk::Done { usage: tok }).await;
                         }
                     }
                     "message_start" => {
-                        // initial usage (input tokens)
-                        // we'll get final usage in message_delta
+                        // Initial usage (input + cache tokens); the final
+                        // output count arrives in message_delta.
+                        start_usage = parse_usage(&ev["message"]["usage"], &start_usage);
                     }
+                    "error" => return Err(stream_error(&ev["error"]).into()),
                     _ => {}
                 }
             }
@@ -249,3 +318,271 @@ impl LlmProvider for AnthropicProvider {
         Ok(())
     }
 }
+
+/// An `error` event sent mid-stream, after the 200 status: the same error
+/// types the API otherwise returns as HTTP statuses.
+fn stream_error(error: &Value) -> ProviderError {
+    let message = error["message"].as_str().unwrap_or("stream error");
+    match error["type"].as_str().unwrap_or("") {
+        "overloaded_error" => ProviderError::Overloaded,
+        "rate_limit_error" => ProviderError::RateLimited { retry_after: None },
+        "authentication_error" | "permission_error" => ProviderError::Auth,
+        "invalid_request_error" | "not_found_error" | "request_too_large" => {
+            ProviderError::refused(message.to_string())
+        }
+        _ => ProviderError::Network(message.to_string()),
+    }
+}
+
+fn build_anthropic_messages(messages: &[Message]) -> (Option<String>, Vec<Value>) {
+    let mut system_parts = Vec::new();
+    let mut msgs = Vec::new();
+
+    for m in messages {
+        match m.role {
+            Role::System | Role::Developer => system_parts.push(m.content.clone()),
+            Role::User if !m.images.is_empty() => {
+                let mut blocks: Vec<Value> = m.images.iter().map(image_block).collect();
+                blocks.push(json!({ "type": "text", "text": m.content }));
+                msgs.push(json!({ "role": "user", "content": blocks }));
+            }
+            Role::User => msgs.push(json!({ "role": "user", "content": m.content })),
+            Role::Assistant => msgs.push(json!({ "role": "assistant", "content": m.content })),
+            Role::Tool => {
+                // Anthropic tool results go as user messages with tool_result content blocks
+                let id = m.tool_call_id.clone().unwrap_or_default();
+                let content = if m.images.is_empty() {
+                    json!(m.content)
+                } else {
+                    let mut blocks = vec![json!({ "type": "text", "text": m.content })];
+                    blocks.extend(m.images.iter().map(image_block));
+                    Value::Array(blocks)
+                };
+                msgs.push(json!({
+                    "role": "user",
+                    "content": [{
+                        "type": "tool_result",
+                        "tool_use_id": id,
+                        "content": content
+                    }]
+                }));
+            }
+        }
+    }
+
+    let system = if system_parts.is_empty() {
+        None
+    } else {
+        Some(system_parts.join("\n"))
+    };
+
+    (system, msgs)
+}
+
+fn image_block(image: &ImageContent) -> Value {
+    json!({
+        "type": "image",
+        "source": {
+            "type": "base64",
+            "media_type": image.mime_type,
+            "data": image.data
+        }
+    })
+}
+
+fn build_anthropic_tools(tools: &[ToolDef]) -> Vec<Value> {
+    tools
+        .iter()
+        .map(|t| {
+            json!({
+                "name": t.name,
+                "description": t.description,
+                "input_schema": t.parameters
+            })
+        })
+        .collect()
+}
+
+/// Turn the system prompt into a single cached text block, and put a cache
+/// breakpoint on the last tool so the whole tool list is cached with it.
+fn apply_cache_breakpoints(system: &mut Value, tools: &mut [Value]) {
+    let cache_control = json!({ "type": "ephemeral" });
+    if let Some(text) = system.as_str() {
+        *system = json!([{
+            "type": "text",
+            "text": text,
+            "cache_control": cache_control
+        }]);
+    }
+    if let Some(last) = tools.last_mut() {
+        last["cache_control"] = cache_control;
+    }
+}
+
+/// The tool that carries a structured answer for `format`, if it asks for one.
+fn structured_tool(format: &ResponseFormat) -> Option<ToolDef> {
+    format.schema().map(|schema| ToolDef {
+        name: STRUCTURED_TOOL.to_string(),
+        description: "Give your final answer by calling this tool with it as the input. \
+                      Call it once you are done with any other tools."
+            .to_string(),
+        parameters: schema,
+    })
+}
+
+/// Parse an Anthropic `usage` object. Absent fields fall back to `prev`, since
+/// `message_delta` may omit the input/cache counts reported in `message_start`.
+fn parse_usage(usage: &Value, prev: &TokenUsage) -> TokenUsage {
+    let field =
+        |key: &str, fallback: u32| usage[key].as_u64().map(|n| n as u32).unwrap_or(fallback);
+    TokenUsage {
+        input_tokens: field("input_tokens", prev.input_tokens),
+        output_tokens: field("output_tokens", prev.output_tokens),
+        cache_read_tokens: field("cache_read_input_tokens", prev.cache_read_tokens),
+        cache_creation_tokens: field("cache_creation_input_tokens", prev.cache_creation_tokens),
+        // Thinking is billed as output; Anthropic doesn't count it apart.
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    async fn complete(&self, messages: &[Message], tools: &[ToolDef]) -> Result<LlmResponse> {
+        self.complete_with_format(messages, tools, &ResponseFormat::Text)
+            .await
+    }
+
+    /// JSON formats add [`STRUCTURED_TOOL`] and require a tool call, so the
+    /// answer arrives as that tool's input and is returned as the message.
+    /// Extended thinking rules out forcing a tool; the tool is then only
+    /// offered, and callers' validation catches a plain-text answer.
+    async fn complete_with_format(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDef],
+        format: &ResponseFormat,
+    ) -> Result<LlmResponse> {
+        let (tx, mut rx) = mpsc::channel(256);
+        match structured_tool(format) {
+            None => self.stream_request(messages, tools, None, tx).await?,
+            Some(respond) => {
+                let mut tools = tools.to_vec();
+                tools.push(respond);
+                let choice = (self.thinking_budget == 0).then(|| json!({ "type": "any" }));
+                self.stream_request(messages, &tools, choice, tx).await?
+            }
+        }
+
+        let mut content = String::new();
+        let mut tool_calls = Vec::new();
+        let mut usage = TokenUsage::default();
+
+        while let Some(chunk) = rx.recv().await {
+            match chunk {
+                StreamChunk::Delta { text } => content.push_str(&text),
+                StreamChunk::ToolCallReady { call } => tool_calls.push(call),
+                StreamChunk::Done { usage: u } => usage = u,
+                StreamChunk::Thinking { .. }
+                | StreamChunk::Status { .. }
+                | StreamChunk::Interrupted { .. } => {}
+            }
+        }
+
+        if let Some(i) = tool_calls.iter().position(|c| c.name == STRUCTURED_TOOL) {
+            let content = tool_calls.swap_remove(i).args.to_string();
+            return Ok(LlmResponse::Message { content, usage });
+        }
+        if !tool_calls.is_empty() {
+            Ok(LlmResponse::ToolCalls {
+                calls: tool_calls,
+                usage,
+            })
+        } else {
+            Ok(LlmResponse::Message { content, usage })
+        }
+    }
+
+    async fn stream_complete(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDef],
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<()> {
+        self.stream_request(messages, tools, None, tx).await
+    }
+
+    /// A trailing assistant message is treated as a prefill and continued.
+    fn supports_continuation(&self) -> bool {
+        true
+    }
+
+    /// Counted by Anthropic's count-tokens endpoint; estimated when it can't
+    /// be reached.
+    async fn count_tokens(&self, messages: &[Message], tools: &[ToolDef]) -> Result<u32> {
+        match self.count_remote(messages, tools).await {
+            Ok(n) => Ok(n),
+            Err(e) => {
+                debug!("Token count unavailable, estimating: {e}");
+                Ok(tokens::estimate_tokens(messages, tools))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_breakpoints_mark_system_and_last_tool() {
+        let mut system = json!("you are krabs");
+        let mut tools = vec![json!({ "name": "read" }), json!({ "name": "write" })];
+        apply_cache_breakpoints(&mut system, &mut tools);
+        assert_eq!(system[0]["text"], "you are krabs");
+        assert_eq!(system[0]["cache_control"]["type"], "ephemeral");
+        assert!(tools[0].get("cache_control").is_none());
+        assert_eq!(tools[1]["cache_control"]["type"], "ephemeral");
+    }
+
+    #[test]
+    fn json_formats_add_the_answer_tool() {
+        assert!(structured_tool(&ResponseFormat::Text).is_none());
+        let schema = json!({ "type": "object", "required": ["ok"] });
+        let tool = structured_tool(&ResponseFormat::JsonSchema(schema.clone())).unwrap();
+        assert_eq!(tool.name, STRUCTURED_TOOL);
+        assert_eq!(tool.parameters, schema);
+        let any = structured_tool(&ResponseFormat::JsonObject).unwrap();
+        assert_eq!(any.parameters, json!({ "type": "object" }));
+    }
+
+    #[test]
+    fn delta_usage_keeps_cache_counts_from_start() {
+        let start = parse_usage(
+            &json!({
+                "input_tokens": 12,
+                "cache_read_input_tokens": 900,
+                "cache_creation_input_tokens": 30
+            }),
+            &TokenUsage::default(),
+        );
+        let done = parse_usage(&json!({ "output_tokens": 40 }), &start);
+        assert_eq!(done.input_tokens, 12);
+        assert_eq!(done.output_tokens, 40);
+        assert_eq!(done.cache_read_tokens, 900);
+        assert_eq!(done.cache_creation_tokens, 30);
+    }
+
+    #[test]
+    fn images_become_base64_blocks() {
+        let png = ImageContent::new("image/png", "iVBORw0KGgo=");
+        let user = Message::user("what is this?").with_images(vec![png.clone()]);
+        let tool = Message::tool_result("read shot.png", "c1", "read").with_images(vec![png]);
+        let (_, msgs) = build_anthropic_messages(&[user, tool]);
+        assert_eq!(msgs[0]["content"][0]["type"], "image");
+        assert_eq!(msgs[0]["content"][0]["source"]["media_type"], "image/png");
+        assert_eq!(msgs[0]["content"][1]["text"], "what is this?");
+        let result = &msgs[1]["content"][0]["content"];
+        assert_eq!(result[0]["text"], "read shot.png");
+        assert_eq!(result[1]["source"]["data"], "iVBORw0KGgo=");
+    }
+}
@@ -0,0 +1,201 @@
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::Duration;
+
+/// Why a provider call failed, as far as retrying it is concerned.
+///
+/// Providers return these inside their `anyhow::Error`s; callers get them
+/// back with `err.downcast_ref::<ProviderError>()`. An error that isn't one
+/// (a mock provider's, say) is treated as transient.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ProviderError {
+    /// HTTP 429. `retry_after` is the wait the server asked for, if it did.
+    #[error("rate limited{}", retry_hint(.retry_after))]
+    RateLimited { retry_after: Option<Duration> },
+    /// HTTP 503 / 529: the model is busy, not the request wrong.
+    #[error("provider overloaded")]
+    Overloaded,
+    /// HTTP 401 / 403.
+    #[error("authentication failed: check the API key")]
+    Auth,
+    /// Any other 4xx: the request itself was refused. Holds the status and
+    /// the body the provider sent.
+    #[error("{0}")]
+    BadRequest(String),
+    /// A refused request whose messages don't fit the model's context
+    /// window. Holds the provider's message.
+    #[error("context length exceeded: {0}")]
+    ContextLengthExceeded(String),
+    /// The request never got an answer: connection failure, timeout, a
+    /// stalled stream or a 5xx.
+    #[error("{0}")]
+    Network(String),
+    /// The reply arrived but couldn't be read.
+    #[error("unreadable response: {0}")]
+    Deserialize(String),
+}
+
+impl ProviderError {
+    /// Whether sending the same request again may succeed.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            Self::Auth | Self::BadRequest(_) | Self::ContextLengthExceeded(_)
+        )
+    }
+
+    /// A refused request: [`ContextLengthExceeded`](Self::ContextLengthExceeded)
+    /// if `message` says the prompt was too long, else
+    /// [`BadRequest`](Self::BadRequest).
+    pub fn refused(message: String) -> Self {
+        let lower = message.to_lowercase();
+        if CONTEXT_MARKERS.iter().any(|m| lower.contains(m)) {
+            Self::ContextLengthExceeded(message)
+        } else {
+            Self::BadRequest(message)
+        }
+    }
+
+    /// Classify a non-success HTTP status and the body that came with it.
+    pub fn from_status(status: StatusCode, retry_after: Option<Duration>, body: &str) -> Self {
+        match status.as_u16() {
+            429 => Self::RateLimited { retry_after },
+            503 | 529 => Self::Overloaded,
+            401 | 403 => Self::Auth,
+            408 => Self::Network(format!("status {status}: {body}")),
+            400..=499 => Self::refused(format!("status {status}: {body}")),
+            _ => Self::Network(format!("status {status}: {body}")),
+        }
+    }
+
+    /// Check a response's status, reading the body of a failed one. With
+    /// `trace` the body is logged like any other response body.
+    pub(crate) async fn check(resp: Response, trace: HttpTrace<'_>) -> Result<Response, Self> {
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(resp);
+        }
+        let retry_after = retry_after(&resp);
+        let body = resp.text().await.unwrap_or_default();
+        if let Some((provider, secret)) = trace {
+            super::redact::trace_body(
+                provider,
+                &format!("error response ({status})"),
+                &body,
+                secret,
+            );
+        }
+        Err(Self::from_status(status, retry_after, &body))
+    }
+}
+
+/// How OpenAI, Anthropic, Gemini and the usual OpenAI-compatible servers
+/// word "the prompt is over the context window", lowercased.
+const CONTEXT_MARKERS: &[&str] = &[
+    "context_length_exceeded",
+    "maximum context length",
+    "prompt is too long",
+    "context window",
+    "exceeds the maximum number of tokens",
+    "too many tokens",
+];
+
+fn retry_hint(retry_after: &Option<Duration>) -> String {
+    retry_after
+        .map(|d| format!(" (retry after {}s)", d.as_secs()))
+        .unwrap_or_default()
+}
+
+/// The provider name and the secret to redact when `trace_http` is on.
+pub(crate) type HttpTrace<'a> = Option<(&'a str, &'a str)>;
+
+/// Send `request` and [`check`](ProviderError::check) its status.
+pub(crate) async fn send(
+    request: RequestBuilder,
+    trace: HttpTrace<'_>,
+) -> Result<Response, ProviderError> {
+    ProviderError::check(request.send().await?, trace).await
+}
+
+/// The wait a 429 asks for: OpenAI's `retry-after-ms`, else the standard
+/// `retry-after` in seconds.
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let header = |name: &str| {
+        resp.headers()
+            .get(name)?
+            .to_str()
+            .ok()?
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite() && *v >= 0.0)
+    };
+    header("retry-after-ms")
+        .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+        .or_else(|| header("retry-after").map(Duration::from_secs_f64))
+}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            return Self::Deserialize(chain(&e));
+        }
+        match e.status() {
+            Some(status) => Self::from_status(status, None, ""),
+            None => Self::Network(chain(&e)),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ProviderError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Deserialize(e.to_string())
+    }
+}
+
+/// `e` and its sources, e.g. "error sending request for url (…): operation
+/// timed out" — reqwest's own message leaves out the cause.
+fn chain(e: &dyn std::error::Error) -> String {
+    let mut out = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        out.push_str(": ");
+        out.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses_map_to_variants() {
+        let of =
+            |code: u16| ProviderError::from_status(StatusCode::from_u16(code).unwrap(), None, "{}");
+        assert_eq!(of(429), ProviderError::RateLimited { retry_after: None });
+        assert_eq!(of(529), ProviderError::Overloaded);
+        assert_eq!(of(503), ProviderError::Overloaded);
+        assert_eq!(of(401), ProviderError::Auth);
+        assert!(matches!(of(400), ProviderError::BadRequest(b) if b.starts_with("status 400")));
+        let overflow = ProviderError::from_status(
+            StatusCode::BAD_REQUEST,
+            None,
+            r#"{"error":{"code":"context_length_exceeded","message":"This model's maximum context length is 8192 tokens."}}"#,
+        );
+        assert!(matches!(overflow, ProviderError::ContextLengthExceeded(_)));
+        assert!(matches!(of(500), ProviderError::Network(_)));
+        assert!(matches!(of(408), ProviderError::Network(_)));
+    }
+
+    #[test]
+    fn only_auth_and_bad_requests_are_final() {
+        assert!(!ProviderError::Auth.is_retryable());
+        assert!(!ProviderError::BadRequest("status 400".into()).is_retryable());
+        assert!(!ProviderError::ContextLengthExceeded("too long".into()).is_retryable());
+        assert!(ProviderError::Overloaded.is_retryable());
+        assert!(ProviderError::RateLimited { retry_after: None }.is_retryable());
+        assert!(ProviderError::Network("reset".into()).is_retryable());
+        assert!(ProviderError::Deserialize("eof".into()).is_retryable());
+    }
+}
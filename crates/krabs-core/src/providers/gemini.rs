@@ -1,4 +1,8 @@
-use super::provider::{LlmProvider, LlmResponse, Message, Role, StreamChunk, TokenUsage, ToolCall};
+use super::provider::{
+    attach_openai_images, openai_response_format, openai_usage, reasoning_delta, LlmProvider,
+    LlmResponse, Message, ResponseFormat, Role, StreamChunk, TokenUsage, ToolCall, ToolCallBuffer,
+};
+use super::{error::HttpTrace, redact, tokens, ProviderError};
 use crate::tools::tool::ToolDef;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -6,33 +10,133 @@ use futures_util::StreamExt;
 use reqwest::Client;
 use serde_json::{json, Value};
 use tokio::sync::mpsc;
+use tracing::debug;
+
+const PROVIDER: &str = "gemini";
 
 pub struct GeminiProvider {
     client: Client,
     api_key: String,
     model: String,
+    trace_http: bool,
+    max_output_tokens: Option<u32>,
+    stop_sequences: Vec<String>,
 }
 
 impl GeminiProvider {
     pub fn new(api_key: impl Into<String>, model: impl Into<String>) -> Self {
         Self {
-            client: Client::new(),
+            client: super::http_client(0, 0),
             api_key: api_key.into(),
             model: model.into(),
+            trace_http: false,
+            max_output_tokens: None,
+            stop_sequences: Vec::new(),
+        }
+    }
+
+    /// Cap each reply at `max` tokens. Sent as `max_tokens`, which the
+    /// compatible endpoint maps to `maxOutputTokens`.
+    pub fn with_max_output_tokens(mut self, max: Option<u32>) -> Self {
+        self.max_output_tokens = max;
+        self
+    }
+
+    /// End each reply at the first of `stop`. Sent as `stop`, which the
+    /// compatible endpoint maps to `stopSequences`.
+    pub fn with_stop_sequences(mut self, stop: Vec<String>) -> Self {
+        self.stop_sequences = stop;
+        self
+    }
+
+    /// Log raw request and response bodies at `debug` level (target
+    /// `krabs::http`) with credentials redacted.
+    pub fn with_http_trace(mut self, enabled: bool) -> Self {
+        self.trace_http = enabled;
+        self
+    }
+
+    /// What [`super::error::send`] needs to trace a failed response's body.
+    fn http_trace(&self) -> HttpTrace<'_> {
+        self.trace_http.then_some((PROVIDER, self.api_key.as_str()))
+    }
+
+    /// Fail requests that run longer than `request_ms` or can't connect
+    /// within `connect_ms`; 0 disables either limit.
+    pub fn with_timeouts(mut self, request_ms: u64, connect_ms: u64) -> Self {
+        self.client = super::http_client(request_ms, connect_ms);
+        self
+    }
+
+    fn add_output_limits(&self, body: &mut Value) {
+        if let Some(max) = self.max_output_tokens {
+            body["max_tokens"] = json!(max);
+        }
+        if !self.stop_sequences.is_empty() {
+            body["stop"] = json!(self.stop_sequences);
         }
     }
 
     fn base_url(&self) -> String {
         "https://generativelanguage.googleapis.com/v1beta/openai".to_string()
     }
+
+    /// Ask the native `countTokens` endpoint what `messages` and `tools`
+    /// cost. The OpenAI-compatible endpoint has no counter, so everything is
+    /// sent as text: close enough for the context bar.
+    async fn count_remote(&self, messages: &[Message], tools: &[ToolDef]) -> Result<u32> {
+        let mut contents: Vec<Value> = messages
+            .iter()
+            .map(|m| {
+                let mut text = m.content.clone();
+                for call in m.tool_calls.iter().flatten() {
+                    text.push_str(&format!("\n{} {}", call.name, call.args));
+                }
+                let role = if matches!(m.role, Role::Assistant) {
+                    "model"
+                } else {
+                    "user"
+                };
+                (role, text)
+            })
+            .filter(|(_, text)| !text.is_empty())
+            .map(|(role, text)| json!({ "role": role, "parts": [{ "text": text }] }))
+            .collect();
+        if !tools.is_empty() {
+            let text = serde_json::to_string(&build_tools(tools))?;
+            contents.push(json!({ "role": "user", "parts": [{ "text": text }] }));
+        }
+        if contents.is_empty() {
+            return Ok(0);
+        }
+        let model = self.model.trim_start_matches("models/");
+        let url =
+            format!("https://generativelanguage.googleapis.com/v1beta/models/{model}:countTokens");
+        let data: Value = super::error::send(
+            self.client
+                .post(&url)
+                .header("x-goog-api-key", &self.api_key)
+                .timeout(tokens::COUNT_TIMEOUT)
+                .json(&json!({ "contents": contents })),
+            self.http_trace(),
+        )
+        .await?
+        .json()
+        .await
+        .map_err(ProviderError::from)?;
+        data["totalTokens"]
+            .as_u64()
+            .map(|n| n as u32)
+            .ok_or_else(|| anyhow::anyhow!("countTokens response has no totalTokens"))
+    }
 }
 
 fn build_messages(messages: &[Message]) -> Vec<Value> {
-    messages
+    let built: Vec<Value> = messages
         .iter()
         .map(|m| {
             let role = match m.role {
-                Role::System => "system",
+                Role::System | Role::Developer => "system",
                 Role::User => "user",
                 Role::Assistant => "assistant",
                 Role::Tool => "tool",
@@ -65,7 +169,9 @@ fn build_messages(messages: &[Message]) -> Vec<Value> {
             }
             obj
         })
-        .collect()
+        .collect();
+    // Every Gemini model accepts image input.
+    attach_openai_images(messages, built)
 }
 
 fn build_tools(tools: &[ToolDef]) -> Vec<Value> {
@@ -77,32 +183,20 @@ fn build_tools(tools: &[ToolDef]) -> Vec<Value> {
         .collect()
 }
 
-fn flush_tool_calls(
-    tool_calls: &mut std::collections::HashMap<usize, (String, String, String, Option<String>)>,
-) -> Vec<ToolCall> {
-    let mut indices: Vec<usize> = tool_calls.keys().cloned().collect();
-    indices.sort();
-    indices
-        .into_iter()
-        .filter_map(|idx| {
-            tool_calls
-                .remove(&idx)
-                .map(|(id, name, args_str, thought_signature)| {
-                    let args: Value = serde_json::from_str(&args_str).unwrap_or(json!({}));
-                    ToolCall {
-                        id,
-                        name,
-                        args,
-                        thought_signature,
-                    }
-                })
-        })
-        .collect()
-}
-
 #[async_trait]
 impl LlmProvider for GeminiProvider {
     async fn complete(&self, messages: &[Message], tools: &[ToolDef]) -> Result<LlmResponse> {
+        self.complete_with_format(messages, tools, &ResponseFormat::Text)
+            .await
+    }
+
+    async fn complete_with_format(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDef],
+        format: &ResponseFormat,
+    ) -> Result<LlmResponse> {
+        let _slot = super::request_slot().await;
         let msgs = build_messages(messages);
         let tools_val = build_tools(tools);
 
@@ -110,25 +204,32 @@ impl LlmProvider for GeminiProvider {
         if !tools_val.is_empty() {
             body["tools"] = json!(tools_val);
         }
+        // The compatible endpoint translates this into `responseSchema`.
+        if let Some(response_format) = openai_response_format(format) {
+            body["response_format"] = response_format;
+        }
+        self.add_output_limits(&mut body);
+
+        if self.trace_http {
+            redact::trace_body(PROVIDER, "request", &body.to_string(), &self.api_key);
+        }
 
         let url = format!("{}/chat/completions", self.base_url());
-        let resp = self
-            .client
-            .post(&url)
-            .bearer_auth(&self.api_key)
-            .json(&body)
-            .send()
-            .await?
-            .error_for_status()?;
-        let data: Value = resp.json().await?;
-
-        let usage = {
-            let u = &data["usage"];
-            TokenUsage {
-                input_tokens: u["prompt_tokens"].as_u64().unwrap_or(0) as u32,
-                output_tokens: u["completion_tokens"].as_u64().unwrap_or(0) as u32,
-            }
-        };
+        let resp = super::error::send(
+            self.client
+                .post(&url)
+                .bearer_auth(&self.api_key)
+                .json(&body),
+            self.http_trace(),
+        )
+        .await?;
+        let raw = resp.text().await.map_err(ProviderError::from)?;
+        if self.trace_http {
+            redact::trace_body(PROVIDER, "response", &raw, &self.api_key);
+        }
+        let data: Value = serde_json::from_str(&raw).map_err(ProviderError::from)?;
+
+        let usage = openai_usage(&data["usage"]);
 
         let choice = &data["choices"][0];
         let message = &choice["message"];
@@ -174,6 +275,7 @@ impl LlmProvider for GeminiProvider {
         tools: &[ToolDef],
         tx: mpsc::Sender<StreamChunk>,
     ) -> Result<()> {
+        let _slot = super::request_slot().await;
         let msgs = build_messages(messages);
         let tools_val = build_tools(tools);
 
@@ -186,38 +288,34 @@ impl LlmProvider for GeminiProvider {
         if !tools_val.is_empty() {
             body["tools"] = json!(tools_val);
         }
+        self.add_output_limits(&mut body);
 
-        // Debug: dump request body to /tmp/krabs_gemini_request.json
-        if let Ok(pretty) = serde_json::to_string_pretty(&body) {
-            let _ = std::fs::write("/tmp/krabs_gemini_request.json", &pretty);
+        if self.trace_http {
+            redact::trace_body(PROVIDER, "request", &body.to_string(), &self.api_key);
         }
 
         let url = format!("{}/chat/completions", self.base_url());
-        let raw_resp = self
-            .client
-            .post(&url)
-            .bearer_auth(&self.api_key)
-            .json(&body)
-            .send()
-            .await?;
-        if !raw_resp.status().is_success() {
-            let status = raw_resp.status();
-            let body_text = raw_resp.text().await.unwrap_or_default();
-            anyhow::bail!("status {status}: {body_text}");
-        }
+        let raw_resp = super::error::send(
+            self.client
+                .post(&url)
+                .bearer_auth(&self.api_key)
+                .json(&body),
+            self.http_trace(),
+        )
+        .await?;
 
-        // index -> (id, name, args, thought_signature)
-        let mut tool_calls: std::collections::HashMap<
-            usize,
-            (String, String, String, Option<String>),
-        > = std::collections::HashMap::new();
+        let mut tool_calls = ToolCallBuffer::default();
         let mut last_usage: Option<TokenUsage> = None;
         let mut byte_stream = raw_resp.bytes_stream();
         let mut leftover = String::new();
 
         'outer: while let Some(chunk) = byte_stream.next().await {
-            let bytes = chunk?;
-            leftover.push_str(&String::from_utf8_lossy(&bytes));
+            let bytes = chunk.map_err(ProviderError::from)?;
+            let text = String::from_utf8_lossy(&bytes);
+            if self.trace_http {
+                redact::trace_body(PROVIDER, "response chunk", &text, &self.api_key);
+            }
+            leftover.push_str(&text);
 
             while let Some(pos) = leftover.find('\n') {
                 let line = leftover[..pos].trim_end_matches('\r').to_string();
@@ -238,10 +336,7 @@ impl LlmProvider for GeminiProvider {
 
                 // Gemini sends usage on every chunk — track the last one
                 if let Some(usage) = delta.get("usage").filter(|u| !u.is_null()) {
-                    last_usage = Some(TokenUsage {
-                        input_tokens: usage["prompt_tokens"].as_u64().unwrap_or(0) as u32,
-                        output_tokens: usage["completion_tokens"].as_u64().unwrap_or(0) as u32,
-                    });
+                    last_usage = Some(openai_usage(usage));
                 }
 
                 let choices = delta["choices"].as_array();
@@ -253,6 +348,13 @@ impl LlmProvider for GeminiProvider {
                 let msg_delta = &choice["delta"];
                 let finish_reason = choice["finish_reason"].as_str().unwrap_or("");
 
+                if let Some(text) = reasoning_delta(msg_delta) {
+                    let _ = tx
+                        .send(StreamChunk::Thinking {
+                            text: text.to_string(),
+                        })
+                        .await;
+                }
                 if let Some(text) = msg_delta["content"].as_str() {
                     if !text.is_empty() {
                         let _ = tx
@@ -266,47 +368,78 @@ impl LlmProvider for GeminiProvider {
                 if let Some(tc_arr) = msg_delta["tool_calls"].as_array() {
                     for tc in tc_arr {
                         let idx = tc["index"].as_u64().unwrap_or(0) as usize;
-                        let entry = tool_calls
-                            .entry(idx)
-                            .or_insert_with(|| (String::new(), String::new(), String::new(), None));
-                        if let Some(id) = tc["id"].as_str() {
-                            entry.0 = id.to_string();
-                        }
-                        if let Some(name) = tc["function"]["name"].as_str() {
-                            entry.1 = name.to_string();
-                        }
-                        if let Some(args) = tc["function"]["arguments"].as_str() {
-                            entry.2.push_str(args);
-                        }
+                        tool_calls.push(
+                            idx,
+                            tc["id"].as_str(),
+                            tc["function"]["name"].as_str(),
+                            tc["function"]["arguments"].as_str(),
+                        );
                         // Gemini embeds thought_signature in extra_content.google
                         if let Some(sig) =
                             tc["extra_content"]["google"]["thought_signature"].as_str()
                         {
-                            entry.3.get_or_insert_with(String::new).push_str(sig);
+                            tool_calls.push_thought_signature(idx, sig);
                         }
                     }
                 }
 
                 // Gemini may use finish_reason "stop" instead of "tool_calls"
                 if finish_reason == "tool_calls" {
-                    for call in flush_tool_calls(&mut tool_calls) {
-                        let _ = tx.send(StreamChunk::ToolCallReady { call }).await;
+                    for chunk in tool_calls.finish_all() {
+                        let _ = tx.send(chunk).await;
                     }
                 }
             }
         }
 
         // Flush any remaining tool calls (Gemini finish_reason "stop" case)
-        for call in flush_tool_calls(&mut tool_calls) {
-            let _ = tx.send(StreamChunk::ToolCallReady { call }).await;
+        for chunk in tool_calls.finish_all() {
+            let _ = tx.send(chunk).await;
         }
 
-        let usage = last_usage.unwrap_or(TokenUsage {
-            input_tokens: 0,
-            output_tokens: 0,
-        });
+        let usage = last_usage.unwrap_or_default();
         let _ = tx.send(StreamChunk::Done { usage }).await;
 
         Ok(())
     }
+
+    /// Counted by Gemini's `countTokens` endpoint; estimated when it can't be
+    /// reached.
+    async fn count_tokens(&self, messages: &[Message], tools: &[ToolDef]) -> Result<u32> {
+        match self.count_remote(messages, tools).await {
+            Ok(n) => Ok(n),
+            Err(e) => {
+                debug!("Token count unavailable, estimating: {e}");
+                Ok(tokens::estimate_tokens(messages, tools))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thought_signatures_are_read_and_sent_back() {
+        let mut pending = ToolCallBuffer::default();
+        pending.push(0, Some("c1"), Some("read"), Some(r#"{"path":"#));
+        pending.push_thought_signature(0, "sig");
+        pending.push(0, None, None, Some(r#""a.txt"}"#));
+        pending.push_thought_signature(0, "==");
+        let calls: Vec<ToolCall> = pending
+            .finish_all()
+            .into_iter()
+            .filter_map(|chunk| match chunk {
+                StreamChunk::ToolCallReady { call } => Some(call),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(calls[0].thought_signature.as_deref(), Some("sig=="));
+
+        let built = build_messages(&[Message::assistant_tool_calls(calls)]);
+        let tc = &built[0]["tool_calls"][0];
+        assert_eq!(tc["extra_content"]["google"]["thought_signature"], "sig==");
+        assert_eq!(tc["function"]["arguments"], r#"{"path":"a.txt"}"#);
+    }
 }
@@ -1,13 +1,74 @@
 pub mod anthropic;
+pub mod error;
 pub mod gemini;
 pub mod openai;
+mod openai_responses;
 pub mod provider;
+pub mod redact;
+pub mod tokens;
 
 pub use anthropic::AnthropicProvider;
+pub use error::ProviderError;
 pub use gemini::GeminiProvider;
-pub use openai::OpenAiProvider;
+pub use openai::{OpenAiApi, OpenAiProvider};
 pub use provider::{LlmProvider, LlmResponse, Message, Role, TokenUsage, ToolCall};
 
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Clients handed out by [`http_client`], one per pair of timeouts, so every
+/// provider in the process shares their connection pools.
+static CLIENTS: Mutex<BTreeMap<(u64, u64), reqwest::Client>> = Mutex::new(BTreeMap::new());
+
+/// The cap set by [`set_max_concurrent_requests`] and its semaphore; `None`
+/// when requests are unlimited.
+static REQUEST_LIMIT: RwLock<Option<(usize, Arc<Semaphore>)>> = RwLock::new(None);
+
+/// HTTP client for talking to a provider. A request, including reading a
+/// streamed response, is abandoned after `request_timeout_ms` and a connection
+/// attempt after `connect_timeout_ms`; 0 disables either limit. A timeout is an
+/// ordinary request error, so the agent's retry loops retry it.
+pub(crate) fn http_client(request_timeout_ms: u64, connect_timeout_ms: u64) -> reqwest::Client {
+    let mut clients = CLIENTS.lock().unwrap_or_else(|e| e.into_inner());
+    clients
+        .entry((request_timeout_ms, connect_timeout_ms))
+        .or_insert_with(|| {
+            let mut builder = reqwest::Client::builder();
+            if request_timeout_ms > 0 {
+                builder = builder.timeout(std::time::Duration::from_millis(request_timeout_ms));
+            }
+            if connect_timeout_ms > 0 {
+                builder =
+                    builder.connect_timeout(std::time::Duration::from_millis(connect_timeout_ms));
+            }
+            builder.build().unwrap_or_default()
+        })
+        .clone()
+}
+
+/// Allow at most `max` LLM requests in flight at once across the process,
+/// whichever agent or provider sends them; 0 lifts the cap. Setting the
+/// limit it already has changes nothing, so each agent can apply its config
+/// without resetting requests that hold a slot.
+pub fn set_max_concurrent_requests(max: usize) {
+    let mut limit = REQUEST_LIMIT.write().unwrap_or_else(|e| e.into_inner());
+    if limit.as_ref().map_or(0, |(n, _)| *n) != max {
+        *limit = (max > 0).then(|| (max, Arc::new(Semaphore::new(max))));
+    }
+}
+
+/// Wait for a slot under [`set_max_concurrent_requests`]; held until the
+/// permit is dropped. `None` when requests are unlimited.
+pub(crate) async fn request_slot() -> Option<OwnedSemaphorePermit> {
+    let semaphore = REQUEST_LIMIT
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|(_, semaphore)| Arc::clone(semaphore))?;
+    semaphore.acquire_owned().await.ok()
+}
+
 /// Infer a human-readable provider name from the API base URL.
 pub fn provider_name_from_url(base_url: &str) -> String {
     if base_url.contains("anthropic.com") {
@@ -16,6 +77,8 @@ pub fn provider_name_from_url(base_url: &str) -> String {
         || base_url.contains("aiplatform.googleapis.com")
     {
         "gemini".to_string()
+    } else if base_url.contains("openai.azure.com") {
+        "azure".to_string()
     } else if base_url.contains("openai.com") {
         "openai".to_string()
     } else {
@@ -1,18 +1,63 @@
-use super::provider::{LlmProvider, LlmResponse, Message, Role, StreamChunk, TokenUsage, ToolCall};
+use super::openai_responses::{self, ResponsesStream};
+use super::provider::{
+    attach_openai_images, image_count, openai_response_format, openai_usage, reasoning_delta,
+    EffortLevel, LlmProvider, LlmResponse, Message, ReasoningEffort, ResponseFormat, Role,
+    StreamChunk, TokenUsage, ToolCall, ToolCallBuffer,
+};
+use super::{error::HttpTrace, redact, tokens, ProviderError};
 use crate::tools::tool::ToolDef;
 use anyhow::Result;
 use async_trait::async_trait;
 use futures_util::StreamExt;
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::time::Duration;
 use tokio::sync::mpsc;
+use tracing::warn;
+
+const PROVIDER: &str = "openai";
+
+/// `api-version` sent to Azure OpenAI when none is configured.
+pub const AZURE_DEFAULT_API_VERSION: &str = "2024-10-21";
+
+/// How long a stream may go without a chunk before it is abandoned, so a
+/// server that stops sending (e.g. llama.cpp after tool results) can't hang
+/// the turn.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+fn idle_timeout() -> ProviderError {
+    ProviderError::Network(format!("stream idle timeout after {IDLE_TIMEOUT:?}"))
+}
+
+/// Which OpenAI endpoint requests go to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OpenAiApi {
+    /// Chat Completions, except for models OpenAI only serves through the
+    /// Responses API (o1-pro, o3-pro, codex, …) when talking to api.openai.com.
+    #[default]
+    Auto,
+    /// `/chat/completions`, which every compatible server speaks.
+    Chat,
+    /// `/responses`.
+    Responses,
+}
 
 pub struct OpenAiProvider {
     client: Client,
     base_url: String,
     api_key: String,
     model: String,
+    trace_http: bool,
+    vision: bool,
+    developer_role: bool,
+    reasoning_effort: Option<EffortLevel>,
+    max_output_tokens: Option<u32>,
+    stop_sequences: Vec<String>,
+    api: OpenAiApi,
+    /// Set for an Azure OpenAI deployment: its `api-version`.
+    azure_api_version: Option<String>,
 }
 
 impl OpenAiProvider {
@@ -21,25 +66,177 @@ impl OpenAiProvider {
         api_key: impl Into<String>,
         model: impl Into<String>,
     ) -> Self {
-        let client = Client::builder()
-            .connect_timeout(Duration::from_secs(15))
-            .build()
-            .unwrap_or_default();
+        let client = super::http_client(0, 15_000);
+        let model = model.into();
         Self {
             client,
             base_url: base_url.into(),
             api_key: api_key.into(),
-            model: model.into(),
+            vision: model_supports_vision(&model),
+            developer_role: is_reasoning_model(&model),
+            reasoning_effort: None,
+            max_output_tokens: None,
+            stop_sequences: Vec::new(),
+            api: OpenAiApi::Auto,
+            model,
+            trace_http: false,
+            azure_api_version: None,
+        }
+    }
+
+    /// An Azure OpenAI deployment:
+    /// `https://{resource}.openai.azure.com/openai/deployments/{deployment}`.
+    /// The deployment name stands in for the model name.
+    pub fn azure(
+        resource: &str,
+        deployment: &str,
+        api_version: impl Into<String>,
+        api_key: impl Into<String>,
+    ) -> Self {
+        Self::new(
+            format!("https://{resource}.openai.azure.com/openai/deployments/{deployment}"),
+            api_key,
+            deployment,
+        )
+        .with_azure_api_version(api_version)
+    }
+
+    /// An Azure OpenAI deployment from a configured URL: either the
+    /// deployment's URL, or the resource's endpoint with `deployment` added.
+    /// An `api-version` query parameter on the URL is used, otherwise
+    /// [`AZURE_DEFAULT_API_VERSION`].
+    pub fn azure_from_url(url: &str, deployment: &str, api_key: impl Into<String>) -> Self {
+        let (base, query) = url.split_once('?').unwrap_or((url, ""));
+        let api_version = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("api-version="))
+            .unwrap_or(AZURE_DEFAULT_API_VERSION)
+            .to_string();
+        let base = base.trim_end_matches('/');
+        let base = if base.contains("/openai/deployments/") {
+            base.to_string()
+        } else {
+            format!("{base}/openai/deployments/{deployment}")
+        };
+        Self::new(base, api_key, deployment).with_azure_api_version(api_version)
+    }
+
+    /// Talk to `base_url` as an Azure OpenAI deployment: send `api_version`
+    /// as the `api-version` query parameter and the key in an `api-key`
+    /// header instead of `Authorization: Bearer`.
+    pub fn with_azure_api_version(mut self, api_version: impl Into<String>) -> Self {
+        self.azure_api_version = Some(api_version.into());
+        self
+    }
+
+    /// Choose between Chat Completions and the Responses API.
+    pub fn with_api(mut self, api: OpenAiApi) -> Self {
+        self.api = api;
+        self
+    }
+
+    /// Whether requests go to the Responses API. Azure and compatible
+    /// servers stay on Chat Completions unless it is asked for.
+    fn uses_responses(&self) -> bool {
+        match self.api {
+            OpenAiApi::Chat => false,
+            OpenAiApi::Responses => true,
+            OpenAiApi::Auto => {
+                self.azure_api_version.is_none()
+                    && self.base_url.contains("api.openai.com")
+                    && openai_responses::needs_responses_api(&self.model)
+            }
         }
     }
+
+    /// A POST to the chat completions endpoint, authenticated.
+    fn chat_request(&self) -> reqwest::RequestBuilder {
+        self.request("chat/completions")
+    }
+
+    /// A POST to `{base_url}/{path}`, authenticated.
+    fn request(&self, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/{path}", self.base_url.trim_end_matches('/'));
+        match &self.azure_api_version {
+            Some(version) => self
+                .client
+                .post(&url)
+                .query(&[("api-version", version)])
+                .header("api-key", &self.api_key),
+            None => self.client.post(&url).bearer_auth(&self.api_key),
+        }
+    }
+
+    /// Override whether image attachments are sent to this model. By default
+    /// this is guessed from the model name; images are dropped when disabled.
+    pub fn with_vision(mut self, enabled: bool) -> Self {
+        self.vision = enabled;
+        self
+    }
+
+    /// Override whether developer messages are sent with the `developer` role.
+    /// By default only o-series models get it; otherwise they go as `system`.
+    pub fn with_developer_role(mut self, enabled: bool) -> Self {
+        self.developer_role = enabled;
+        self
+    }
+
+    /// Send `reasoning_effort` with each request. Only o-series models accept
+    /// it, so it is dropped for other models; a token budget is sent as the
+    /// nearest level.
+    pub fn with_reasoning_effort(mut self, effort: Option<ReasoningEffort>) -> Self {
+        self.reasoning_effort = effort
+            .filter(|_| is_reasoning_model(&self.model))
+            .map(ReasoningEffort::level);
+        self
+    }
+
+    /// Cap each reply at `max` tokens: `max_completion_tokens` for reasoning
+    /// models and GPT-5, which reject `max_tokens`, and `max_tokens` for the
+    /// rest, which compatible servers understand.
+    pub fn with_max_output_tokens(mut self, max: Option<u32>) -> Self {
+        self.max_output_tokens = max;
+        self
+    }
+
+    /// End each reply at the first of `stop`, sent as `stop`. Reasoning
+    /// models don't accept it, so it is dropped for them.
+    pub fn with_stop_sequences(mut self, stop: Vec<String>) -> Self {
+        self.stop_sequences = stop;
+        if is_reasoning_model(&self.model) {
+            self.stop_sequences.clear();
+        }
+        self
+    }
+
+    /// Log raw request and response bodies at `debug` level (target
+    /// `krabs::http`) with credentials redacted.
+    pub fn with_http_trace(mut self, enabled: bool) -> Self {
+        self.trace_http = enabled;
+        self
+    }
+
+    /// What [`super::error::send`] needs to trace a failed response's body.
+    fn http_trace(&self) -> HttpTrace<'_> {
+        self.trace_http.then_some((PROVIDER, self.api_key.as_str()))
+    }
+
+    /// Fail requests that run longer than `request_ms` or can't connect
+    /// within `connect_ms`; 0 disables either limit.
+    pub fn with_timeouts(mut self, request_ms: u64, connect_ms: u64) -> Self {
+        self.client = super::http_client(request_ms, connect_ms);
+        self
+    }
 }
 
-fn build_messages(messages: &[Message]) -> Vec<Value> {
-    messages
+fn build_messages(messages: &[Message], vision: bool, developer_role: bool) -> Vec<Value> {
+    let built: Vec<Value> = messages
         .iter()
         .map(|m| {
             let role = match m.role {
                 Role::System => "system",
+                Role::Developer if developer_role => "developer",
+                Role::Developer => "system",
                 Role::User => "user",
                 Role::Assistant => "assistant",
                 Role::Tool => "tool",
@@ -66,7 +263,172 @@ fn build_messages(messages: &[Message]) -> Vec<Value> {
             }
             obj
         })
-        .collect()
+        .collect();
+    if vision {
+        return attach_openai_images(messages, built);
+    }
+    let dropped = image_count(messages);
+    if dropped > 0 {
+        warn!("model does not accept images; dropping {dropped} image attachment(s)");
+    }
+    built
+}
+
+/// Whether `model` is known to accept image input. Unknown models (local
+/// servers, custom endpoints) are treated as text-only.
+fn model_supports_vision(model: &str) -> bool {
+    let model = model.to_ascii_lowercase();
+    [
+        "gpt-4o",
+        "gpt-4.1",
+        "gpt-4-turbo",
+        "gpt-5",
+        "o1",
+        "o3",
+        "o4",
+        "vision",
+        "llava",
+    ]
+    .iter()
+    .any(|m| model.contains(m))
+}
+
+impl OpenAiProvider {
+    /// Add the `with_max_output_tokens` / `with_stop_sequences` caps to a
+    /// chat request `body`.
+    fn add_output_limits(&self, body: &mut Value) {
+        if let Some(max) = self.max_output_tokens {
+            let field = if is_reasoning_model(&self.model) || is_gpt5(&self.model) {
+                "max_completion_tokens"
+            } else {
+                "max_tokens"
+            };
+            body[field] = json!(max);
+        }
+        if !self.stop_sequences.is_empty() {
+            body["stop"] = json!(self.stop_sequences);
+        }
+    }
+}
+
+impl OpenAiProvider {
+    /// A Responses API request body. `stop` has no equivalent there.
+    fn responses_body(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDef],
+        format: &ResponseFormat,
+        stream: bool,
+    ) -> Value {
+        let input = openai_responses::build_input(messages, self.vision, self.developer_role);
+        let tools_val = openai_responses::build_tools(tools);
+
+        let mut body = json!({
+            "model": self.model,
+            "input": input,
+            "stream": stream,
+            "store": false,
+        });
+        if !tools_val.is_empty() {
+            body["tools"] = json!(tools_val);
+        }
+        if let Some(text) = openai_responses::text_format(format) {
+            body["text"] = text;
+        }
+        if let Some(effort) = self.reasoning_effort {
+            body["reasoning"] = json!({ "effort": effort.as_str() });
+        }
+        if let Some(max) = self.max_output_tokens {
+            body["max_output_tokens"] = json!(max);
+        }
+        body
+    }
+
+    async fn responses_send(&self, body: &Value) -> Result<Value> {
+        if self.trace_http {
+            redact::trace_body(PROVIDER, "request", &body.to_string(), &self.api_key);
+        }
+        let resp =
+            super::error::send(self.request("responses").json(body), self.http_trace()).await?;
+        let raw = resp.text().await.map_err(ProviderError::from)?;
+        if self.trace_http {
+            redact::trace_body(PROVIDER, "response", &raw, &self.api_key);
+        }
+        Ok(serde_json::from_str(&raw).map_err(ProviderError::from)?)
+    }
+
+    async fn responses_stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDef],
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> Result<()> {
+        let body = self.responses_body(messages, tools, &ResponseFormat::Text, true);
+        if self.trace_http {
+            redact::trace_body(PROVIDER, "request", &body.to_string(), &self.api_key);
+        }
+
+        let raw_resp =
+            super::error::send(self.request("responses").json(&body), self.http_trace()).await?;
+
+        let mut state = ResponsesStream::default();
+        let mut byte_stream = raw_resp.bytes_stream();
+        let mut leftover = String::new();
+
+        loop {
+            let bytes = match tokio::time::timeout(IDLE_TIMEOUT, byte_stream.next()).await {
+                Ok(Some(chunk)) => chunk.map_err(ProviderError::from)?,
+                Ok(None) => break,
+                Err(_) => return Err(idle_timeout().into()),
+            };
+            let text = String::from_utf8_lossy(&bytes);
+            if self.trace_http {
+                redact::trace_body(PROVIDER, "response chunk", &text, &self.api_key);
+            }
+            leftover.push_str(&text);
+
+            // Each event's type is repeated in its data, so `event:` lines
+            // can be skipped.
+            while let Some(pos) = leftover.find('\n') {
+                let line: String = leftover.drain(..=pos).collect();
+                let Some(data) = line.trim_end().strip_prefix("data: ") else {
+                    continue;
+                };
+                let Ok(event) = serde_json::from_str::<Value>(data) else {
+                    continue;
+                };
+                for chunk in state.event(&event)? {
+                    let _ = tx.send(chunk).await;
+                }
+            }
+        }
+
+        for chunk in state.finish() {
+            let _ = tx.send(chunk).await;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `model` is a GPT-5 model, which takes `max_completion_tokens`.
+fn is_gpt5(model: &str) -> bool {
+    model
+        .rsplit('/')
+        .next()
+        .unwrap_or(model)
+        .to_ascii_lowercase()
+        .starts_with("gpt-5")
+}
+
+/// Whether `model` is an o-series reasoning model, which takes instructions
+/// with the `developer` role instead of `system` and accepts `reasoning_effort`.
+fn is_reasoning_model(model: &str) -> bool {
+    let name = model
+        .rsplit('/')
+        .next()
+        .unwrap_or(model)
+        .to_ascii_lowercase();
+    ["o1", "o3", "o4"].iter().any(|p| name.starts_with(p))
 }
 
 fn build_tools(tools: &[ToolDef]) -> Vec<Value> {
@@ -81,32 +443,49 @@ fn build_tools(tools: &[ToolDef]) -> Vec<Value> {
 #[async_trait]
 impl LlmProvider for OpenAiProvider {
     async fn complete(&self, messages: &[Message], tools: &[ToolDef]) -> Result<LlmResponse> {
-        let msgs = build_messages(messages);
+        self.complete_with_format(messages, tools, &ResponseFormat::Text)
+            .await
+    }
+
+    async fn complete_with_format(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDef],
+        format: &ResponseFormat,
+    ) -> Result<LlmResponse> {
+        let _slot = super::request_slot().await;
+        if self.uses_responses() {
+            let body = self.responses_body(messages, tools, format, false);
+            let data = self.responses_send(&body).await?;
+            return openai_responses::parse_response(&data);
+        }
+        let msgs = build_messages(messages, self.vision, self.developer_role);
         let tools_val = build_tools(tools);
 
         let mut body = json!({ "model": self.model, "messages": msgs });
         if !tools_val.is_empty() {
             body["tools"] = json!(tools_val);
         }
+        if let Some(response_format) = openai_response_format(format) {
+            body["response_format"] = response_format;
+        }
+        if let Some(effort) = self.reasoning_effort {
+            body["reasoning_effort"] = json!(effort.as_str());
+        }
+        self.add_output_limits(&mut body);
 
-        let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
-        let resp = self
-            .client
-            .post(&url)
-            .bearer_auth(&self.api_key)
-            .json(&body)
-            .send()
-            .await?
-            .error_for_status()?;
-        let data: Value = resp.json().await?;
-
-        let usage = {
-            let u = &data["usage"];
-            TokenUsage {
-                input_tokens: u["prompt_tokens"].as_u64().unwrap_or(0) as u32,
-                output_tokens: u["completion_tokens"].as_u64().unwrap_or(0) as u32,
-            }
-        };
+        if self.trace_http {
+            redact::trace_body(PROVIDER, "request", &body.to_string(), &self.api_key);
+        }
+
+        let resp = super::error::send(self.chat_request().json(&body), self.http_trace()).await?;
+        let raw = resp.text().await.map_err(ProviderError::from)?;
+        if self.trace_http {
+            redact::trace_body(PROVIDER, "response", &raw, &self.api_key);
+        }
+        let data: Value = serde_json::from_str(&raw).map_err(ProviderError::from)?;
+
+        let usage = openai_usage(&data["usage"]);
 
         let choice = &data["choices"][0];
         let message = &choice["message"];
@@ -148,7 +527,11 @@ impl LlmProvider for OpenAiProvider {
         tools: &[ToolDef],
         tx: mpsc::Sender<StreamChunk>,
     ) -> Result<()> {
-        let msgs = build_messages(messages);
+        let _slot = super::request_slot().await;
+        if self.uses_responses() {
+            return self.responses_stream(messages, tools, tx).await;
+        }
+        let msgs = build_messages(messages, self.vision, self.developer_role);
         let tools_val = build_tools(tools);
 
         let mut body = json!({
@@ -159,41 +542,36 @@ impl LlmProvider for OpenAiProvider {
         if !tools_val.is_empty() {
             body["tools"] = json!(tools_val);
         }
+        if let Some(effort) = self.reasoning_effort {
+            body["reasoning_effort"] = json!(effort.as_str());
+        }
+        self.add_output_limits(&mut body);
 
-        let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
-        let raw_resp = self
-            .client
-            .post(&url)
-            .bearer_auth(&self.api_key)
-            .json(&body)
-            .send()
-            .await?;
-        if !raw_resp.status().is_success() {
-            let status = raw_resp.status();
-            let body_text = raw_resp.text().await.unwrap_or_default();
-            anyhow::bail!("status {status}: {body_text}");
+        if self.trace_http {
+            redact::trace_body(PROVIDER, "request", &body.to_string(), &self.api_key);
         }
 
-        // index -> (id, name, args)
-        let mut tool_calls: std::collections::HashMap<usize, (String, String, String)> =
-            std::collections::HashMap::new();
+        let raw_resp =
+            super::error::send(self.chat_request().json(&body), self.http_trace()).await?;
+
+        let mut tool_calls = ToolCallBuffer::default();
         let mut last_usage: Option<TokenUsage> = None;
         let mut byte_stream = raw_resp.bytes_stream();
         let mut leftover = String::new();
 
-        // 120-second idle timeout per chunk — prevents infinite hang when
-        // the server stops sending data (e.g. llama.cpp after tool results).
-        const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
-
         'outer: loop {
             let maybe = tokio::time::timeout(IDLE_TIMEOUT, byte_stream.next()).await;
             let chunk = match maybe {
                 Ok(Some(c)) => c,
                 Ok(None) => break 'outer,
-                Err(_) => anyhow::bail!("stream idle timeout after {IDLE_TIMEOUT:?}"),
+                Err(_) => return Err(idle_timeout().into()),
             };
-            let bytes = chunk?;
-            leftover.push_str(&String::from_utf8_lossy(&bytes));
+            let bytes = chunk.map_err(ProviderError::from)?;
+            let text = String::from_utf8_lossy(&bytes);
+            if self.trace_http {
+                redact::trace_body(PROVIDER, "response chunk", &text, &self.api_key);
+            }
+            leftover.push_str(&text);
 
             while let Some(pos) = leftover.find('\n') {
                 let line = leftover[..pos].trim_end_matches('\r').to_string();
@@ -213,10 +591,7 @@ impl LlmProvider for OpenAiProvider {
                 };
 
                 if let Some(usage) = delta.get("usage").filter(|u| !u.is_null()) {
-                    last_usage = Some(TokenUsage {
-                        input_tokens: usage["prompt_tokens"].as_u64().unwrap_or(0) as u32,
-                        output_tokens: usage["completion_tokens"].as_u64().unwrap_or(0) as u32,
-                    });
+                    last_usage = Some(openai_usage(usage));
                 }
 
                 // Skip usage-only chunks (no choices)
@@ -229,89 +604,364 @@ impl LlmProvider for OpenAiProvider {
                 let msg_delta = &choice["delta"];
                 let finish_reason = choice["finish_reason"].as_str().unwrap_or("");
 
-                // Some models (e.g. Qwen3 with thinking mode) stream their
-                // chain-of-thought in a `reasoning_content` field and then
-                // output the final answer in `content`.  Surface both so the
-                // user can see the thinking process and the response.
-                for field in &["reasoning_content", "content"] {
-                    if let Some(text) = msg_delta[field].as_str() {
-                        if !text.is_empty() {
-                            let _ = tx
-                                .send(StreamChunk::Delta {
-                                    text: text.to_string(),
-                                })
-                                .await;
-                        }
+                if let Some(text) = reasoning_delta(msg_delta) {
+                    let _ = tx
+                        .send(StreamChunk::Thinking {
+                            text: text.to_string(),
+                        })
+                        .await;
+                }
+                if let Some(text) = msg_delta["content"].as_str() {
+                    if !text.is_empty() {
+                        let _ = tx
+                            .send(StreamChunk::Delta {
+                                text: text.to_string(),
+                            })
+                            .await;
                     }
                 }
 
                 if let Some(tc_arr) = msg_delta["tool_calls"].as_array() {
                     for tc in tc_arr {
-                        let idx = tc["index"].as_u64().unwrap_or(0) as usize;
-                        let entry = tool_calls
-                            .entry(idx)
-                            .or_insert_with(|| (String::new(), String::new(), String::new()));
-                        if let Some(id) = tc["id"].as_str() {
-                            entry.0 = id.to_string();
-                        }
-                        if let Some(name) = tc["function"]["name"].as_str() {
-                            entry.1 = name.to_string();
-                        }
-                        if let Some(args) = tc["function"]["arguments"].as_str() {
-                            entry.2.push_str(args);
-                        }
+                        tool_calls.push(
+                            tc["index"].as_u64().unwrap_or(0) as usize,
+                            tc["id"].as_str(),
+                            tc["function"]["name"].as_str(),
+                            tc["function"]["arguments"].as_str(),
+                        );
                     }
                 }
 
                 if finish_reason == "tool_calls" {
-                    let mut indices: Vec<usize> = tool_calls.keys().cloned().collect();
-                    indices.sort();
-                    for idx in indices {
-                        if let Some((id, name, args_str)) = tool_calls.remove(&idx) {
-                            let args: Value = serde_json::from_str(&args_str).unwrap_or(json!({}));
-                            let _ = tx
-                                .send(StreamChunk::ToolCallReady {
-                                    call: ToolCall {
-                                        id,
-                                        name,
-                                        args,
-                                        thought_signature: None,
-                                    },
-                                })
-                                .await;
-                        }
+                    for chunk in tool_calls.finish_all() {
+                        let _ = tx.send(chunk).await;
                     }
                 }
             }
         }
 
         // Flush any remaining tool calls
-        if !tool_calls.is_empty() {
-            let mut indices: Vec<usize> = tool_calls.keys().cloned().collect();
-            indices.sort();
-            for idx in indices {
-                if let Some((id, name, args_str)) = tool_calls.remove(&idx) {
-                    let args: Value = serde_json::from_str(&args_str).unwrap_or(json!({}));
-                    let _ = tx
-                        .send(StreamChunk::ToolCallReady {
-                            call: ToolCall {
-                                id,
-                                name,
-                                args,
-                                thought_signature: None,
-                            },
-                        })
-                        .await;
-                }
-            }
+        for chunk in tool_calls.finish_all() {
+            let _ = tx.send(chunk).await;
         }
 
-        let usage = last_usage.unwrap_or(TokenUsage {
-            input_tokens: 0,
-            output_tokens: 0,
-        });
+        let usage = last_usage.unwrap_or_default();
         let _ = tx.send(StreamChunk::Done { usage }).await;
 
         Ok(())
     }
+
+    /// Counted locally with OpenAI's tokenizer for the model. Other servers
+    /// speaking the same API get the same count, which beats the estimate
+    /// even when their tokenizer differs.
+    async fn count_tokens(&self, messages: &[Message], tools: &[ToolDef]) -> Result<u32> {
+        Ok(tokens::tiktoken_count(&self.model, messages, tools)
+            .unwrap_or_else(|| tokens::estimate_tokens(messages, tools)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::provider::ImageContent;
+
+    fn png() -> ImageContent {
+        ImageContent::new("image/png", "iVBORw0KGgo=")
+    }
+
+    #[test]
+    fn user_images_become_image_url_parts() {
+        let msgs = build_messages(
+            &[Message::user("look").with_images(vec![png()])],
+            true,
+            false,
+        );
+        assert_eq!(msgs[0]["content"][0]["text"], "look");
+        assert_eq!(
+            msgs[0]["content"][1]["image_url"]["url"],
+            "data:image/png;base64,iVBORw0KGgo="
+        );
+    }
+
+    #[test]
+    fn tool_images_follow_the_tool_results() {
+        let messages = [
+            Message::tool_result("a", "c1", "read").with_images(vec![png()]),
+            Message::tool_result("b", "c2", "read"),
+            Message::assistant("done"),
+        ];
+        let msgs = build_messages(&messages, true, false);
+        assert_eq!(msgs.len(), 4);
+        assert_eq!(msgs[0]["role"], "tool");
+        assert_eq!(msgs[1]["role"], "tool");
+        assert_eq!(msgs[2]["role"], "user");
+        assert_eq!(msgs[2]["content"][1]["type"], "image_url");
+        assert_eq!(msgs[3]["role"], "assistant");
+    }
+
+    #[test]
+    fn text_only_models_drop_images() {
+        let msgs = build_messages(
+            &[Message::user("look").with_images(vec![png()])],
+            false,
+            false,
+        );
+        assert_eq!(msgs[0]["content"], "look");
+        assert!(!model_supports_vision("llama3.2"));
+        assert!(model_supports_vision("gpt-4o-mini"));
+    }
+
+    #[test]
+    fn developer_role_only_for_o_series() {
+        let messages = [Message::system("base"), Message::developer("be terse")];
+        let msgs = build_messages(&messages, false, true);
+        assert_eq!(msgs[1]["role"], "developer");
+        let msgs = build_messages(&messages, false, false);
+        assert_eq!(msgs[1]["role"], "system");
+
+        assert!(is_reasoning_model("o3-mini"));
+        assert!(is_reasoning_model("openai/o4-mini"));
+        assert!(!is_reasoning_model("gpt-4o"));
+    }
+
+    #[test]
+    fn auto_uses_responses_only_for_responses_only_models_on_openai() {
+        let openai = "https://api.openai.com/v1";
+        assert!(OpenAiProvider::new(openai, "k", "o3-pro").uses_responses());
+        assert!(!OpenAiProvider::new(openai, "k", "gpt-4o").uses_responses());
+        assert!(!OpenAiProvider::new("http://localhost:8080/v1", "k", "o3-pro").uses_responses());
+        assert!(!OpenAiProvider::azure("r", "o3-pro", "v", "k").uses_responses());
+        assert!(OpenAiProvider::new(openai, "k", "gpt-4o")
+            .with_api(OpenAiApi::Responses)
+            .uses_responses());
+        assert!(!OpenAiProvider::new(openai, "k", "o3-pro")
+            .with_api(OpenAiApi::Chat)
+            .uses_responses());
+    }
+
+    #[test]
+    fn output_limits_use_each_model_family_s_field() {
+        let stop = vec!["END".to_string()];
+        let mut body = json!({});
+        OpenAiProvider::new("http://x", "k", "gpt-4o")
+            .with_max_output_tokens(Some(512))
+            .with_stop_sequences(stop.clone())
+            .add_output_limits(&mut body);
+        assert_eq!(body, json!({ "max_tokens": 512, "stop": ["END"] }));
+
+        let mut body = json!({});
+        OpenAiProvider::new("http://x", "k", "o3")
+            .with_max_output_tokens(Some(512))
+            .with_stop_sequences(stop.clone())
+            .add_output_limits(&mut body);
+        assert_eq!(body, json!({ "max_completion_tokens": 512 }));
+
+        let mut body = json!({});
+        OpenAiProvider::new("http://x", "k", "gpt-5.1")
+            .with_max_output_tokens(Some(512))
+            .add_output_limits(&mut body);
+        assert_eq!(body, json!({ "max_completion_tokens": 512 }));
+    }
+
+    #[test]
+    fn reasoning_effort_only_for_o_series() {
+        let effort = Some(ReasoningEffort::Budget(30_000));
+        let o3 = OpenAiProvider::new("http://x", "k", "o3").with_reasoning_effort(effort);
+        assert_eq!(o3.reasoning_effort, Some(EffortLevel::High));
+        let gpt = OpenAiProvider::new("http://x", "k", "gpt-4o").with_reasoning_effort(effort);
+        assert_eq!(gpt.reasoning_effort, None);
+    }
+
+    #[test]
+    fn azure_requests_use_deployment_url_and_api_key_header() {
+        let azure = OpenAiProvider::azure("acme", "gpt4o-prod", "2024-06-01", "secret");
+        let req = azure.chat_request().build().unwrap();
+        assert_eq!(
+            req.url().as_str(),
+            "https://acme.openai.azure.com/openai/deployments/gpt4o-prod/chat/completions?api-version=2024-06-01"
+        );
+        assert_eq!(req.headers()["api-key"], "secret");
+        assert!(req.headers().get("authorization").is_none());
+
+        // A resource endpoint gets the deployment added; the version defaults.
+        let from_url =
+            OpenAiProvider::azure_from_url("https://acme.openai.azure.com/", "gpt4o-prod", "k");
+        assert_eq!(from_url.base_url, azure.base_url);
+        assert_eq!(
+            from_url.azure_api_version.as_deref(),
+            Some(AZURE_DEFAULT_API_VERSION)
+        );
+        let versioned = OpenAiProvider::azure_from_url(
+            "https://acme.openai.azure.com/openai/deployments/d1?api-version=2025-01-01",
+            "ignored",
+            "k",
+        );
+        assert!(versioned.base_url.ends_with("/deployments/d1"));
+        assert_eq!(versioned.azure_api_version.as_deref(), Some("2025-01-01"));
+
+        let openai = OpenAiProvider::new("https://api.openai.com/v1", "sk", "gpt-4o");
+        let req = openai.chat_request().build().unwrap();
+        assert_eq!(req.headers()["authorization"], "Bearer sk");
+        assert_eq!(
+            crate::providers::provider_name_from_url(&azure.base_url),
+            "azure"
+        );
+    }
+
+    /// Accept one connection and answer it only after `delay`.
+    async fn slow_server(delay: Duration) -> std::net::SocketAddr {
+        use http_body_util::Full;
+        use hyper::body::Bytes;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = hyper::service::service_fn(move |_req| async move {
+                tokio::time::sleep(delay).await;
+                Ok::<_, std::convert::Infallible>(hyper::Response::new(Full::new(Bytes::from(
+                    r#"{"choices":[{"message":{"content":"late"}}]}"#,
+                ))))
+            });
+            hyper::server::conn::http1::Builder::new()
+                .serve_connection(hyper_util::rt::TokioIo::new(stream), service)
+                .await
+                .ok();
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn request_past_the_timeout_fails_as_a_timeout() {
+        let addr = slow_server(Duration::from_secs(5)).await;
+        let provider = OpenAiProvider::new(format!("http://{addr}/v1"), "key", "gpt-4o")
+            .with_timeouts(100, 1_000);
+
+        let started = std::time::Instant::now();
+        let err = provider
+            .complete(&[Message::user("hi")], &[])
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(
+            matches!(
+                err.downcast_ref::<ProviderError>(),
+                Some(ProviderError::Network(msg)) if msg.contains("timed out")
+            ),
+            "{err:#}"
+        );
+    }
+
+    #[tokio::test]
+    async fn in_flight_requests_never_exceed_the_limit() {
+        use http_body_util::Full;
+        use hyper::body::Bytes;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        const LIMIT: usize = 3;
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (server_in_flight, server_peak) = (Arc::clone(&in_flight), Arc::clone(&peak));
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (in_flight, peak) = (Arc::clone(&server_in_flight), Arc::clone(&server_peak));
+                tokio::spawn(async move {
+                    let service = hyper::service::service_fn(move |_req| {
+                        let (in_flight, peak) = (Arc::clone(&in_flight), Arc::clone(&peak));
+                        async move {
+                            let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                            peak.fetch_max(now, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            in_flight.fetch_sub(1, Ordering::SeqCst);
+                            Ok::<_, std::convert::Infallible>(hyper::Response::new(Full::new(
+                                Bytes::from(r#"{"choices":[{"message":{"content":"ok"}}]}"#),
+                            )))
+                        }
+                    });
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(hyper_util::rt::TokioIo::new(stream), service)
+                        .await
+                        .ok();
+                });
+            }
+        });
+
+        super::super::set_max_concurrent_requests(LIMIT);
+        let provider = Arc::new(OpenAiProvider::new(
+            format!("http://{addr}/v1"),
+            "key",
+            "gpt-4o",
+        ));
+        let calls: Vec<_> = (0..12)
+            .map(|_| {
+                let provider = Arc::clone(&provider);
+                tokio::spawn(async move { provider.complete(&[Message::user("hi")], &[]).await })
+            })
+            .collect();
+        for call in calls {
+            assert!(call.await.unwrap().is_ok());
+        }
+        super::super::set_max_concurrent_requests(0);
+
+        let peak = peak.load(Ordering::SeqCst);
+        assert!((1..=LIMIT).contains(&peak), "peak of {peak} in flight");
+    }
+
+    #[tokio::test]
+    async fn rejected_key_fails_after_one_request() {
+        use crate::agents::agent::{Agent, KrabsAgentBuilder};
+        use crate::config::KrabsConfig;
+        use http_body_util::Full;
+        use hyper::body::Bytes;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_hits = Arc::clone(&hits);
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let hits = Arc::clone(&server_hits);
+                tokio::spawn(async move {
+                    let service = hyper::service::service_fn(move |_req| {
+                        hits.fetch_add(1, Ordering::SeqCst);
+                        async {
+                            let mut resp = hyper::Response::new(Full::new(Bytes::from(
+                                r#"{"error":{"message":"Incorrect API key provided"}}"#,
+                            )));
+                            *resp.status_mut() = hyper::StatusCode::UNAUTHORIZED;
+                            Ok::<_, std::convert::Infallible>(resp)
+                        }
+                    });
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(hyper_util::rt::TokioIo::new(stream), service)
+                        .await
+                        .ok();
+                });
+            }
+        });
+
+        let config = KrabsConfig {
+            max_retries: 3,
+            retry_base_delay_ms: 1_000,
+            ..KrabsConfig::default()
+        };
+        let provider = OpenAiProvider::new(format!("http://{addr}/v1"), "bad-key", "gpt-4o");
+        let agent = KrabsAgentBuilder::new(config, provider).build();
+
+        let started = std::time::Instant::now();
+        let err = agent.run("hi").await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProviderError>(),
+            Some(&ProviderError::Auth)
+        );
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
 }
@@ -0,0 +1,372 @@
+//! OpenAI's Responses API (`/v1/responses`), which [`OpenAiProvider`]
+//! speaks instead of Chat Completions for models only served there.
+//!
+//! Conversation turns go in a flat `input` list: messages carry a role,
+//! while tool calls and their results are separate `function_call` and
+//! `function_call_output` items linked by `call_id`. Streams are typed SSE
+//! events rather than `choices[0].delta` chunks.
+//!
+//! [`OpenAiProvider`]: super::OpenAiProvider
+
+use super::provider::{
+    complete_tool_call, image_count, ImageContent, LlmResponse, Message, ResponseFormat, Role,
+    StreamChunk, TokenUsage, ToolCall,
+};
+use crate::tools::tool::ToolDef;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tracing::warn;
+
+/// Models OpenAI serves only through the Responses API.
+const RESPONSES_ONLY_MODELS: &[&str] =
+    &["o1-pro", "o3-pro", "codex", "deep-research", "computer-use"];
+
+/// Whether `model` is only served through the Responses API.
+pub(super) fn needs_responses_api(model: &str) -> bool {
+    let model = model.to_ascii_lowercase();
+    RESPONSES_ONLY_MODELS.iter().any(|m| model.contains(m))
+}
+
+/// `messages` as Responses `input` items. Images ride along on user
+/// messages; those from tool results follow the run of results in one user
+/// message, as with Chat Completions.
+pub(super) fn build_input(messages: &[Message], vision: bool, developer_role: bool) -> Vec<Value> {
+    if !vision {
+        let dropped = image_count(messages);
+        if dropped > 0 {
+            warn!("model does not accept images; dropping {dropped} image attachment(s)");
+        }
+    }
+    let mut input = Vec::with_capacity(messages.len());
+    let mut pending: Vec<&ImageContent> = Vec::new();
+    for m in messages {
+        if !matches!(m.role, Role::Tool) && !pending.is_empty() {
+            input.push(images_message(
+                "Images returned by the tool calls above.",
+                &pending,
+            ));
+            pending.clear();
+        }
+        match m.role {
+            Role::System => input.push(json!({ "role": "system", "content": m.content })),
+            Role::Developer => {
+                let role = if developer_role {
+                    "developer"
+                } else {
+                    "system"
+                };
+                input.push(json!({ "role": role, "content": m.content }));
+            }
+            Role::User if vision && !m.images.is_empty() => {
+                let images: Vec<&ImageContent> = m.images.iter().collect();
+                input.push(images_message(&m.content, &images));
+            }
+            Role::User => input.push(json!({ "role": "user", "content": m.content })),
+            Role::Assistant => {
+                if !m.content.is_empty() {
+                    input.push(json!({ "role": "assistant", "content": m.content }));
+                }
+                for call in m.tool_calls.iter().flatten() {
+                    input.push(json!({
+                        "type": "function_call",
+                        "call_id": call.id,
+                        "name": call.name,
+                        "arguments": call.args.to_string(),
+                    }));
+                }
+            }
+            Role::Tool => {
+                input.push(json!({
+                    "type": "function_call_output",
+                    "call_id": m.tool_call_id.as_deref().unwrap_or(""),
+                    "output": m.content,
+                }));
+                if vision {
+                    pending.extend(&m.images);
+                }
+            }
+        }
+    }
+    if !pending.is_empty() {
+        input.push(images_message(
+            "Images returned by the tool calls above.",
+            &pending,
+        ));
+    }
+    input
+}
+
+fn images_message(text: &str, images: &[&ImageContent]) -> Value {
+    let mut parts = vec![json!({ "type": "input_text", "text": text })];
+    parts.extend(
+        images
+            .iter()
+            .map(|img| json!({ "type": "input_image", "image_url": img.data_url() })),
+    );
+    json!({ "role": "user", "content": parts })
+}
+
+/// Function tools in the Responses shape, which has no `function` wrapper.
+pub(super) fn build_tools(tools: &[ToolDef]) -> Vec<Value> {
+    tools
+        .iter()
+        .map(|t| {
+            json!({
+                "type": "function",
+                "name": t.name,
+                "description": t.description,
+                "parameters": t.parameters,
+            })
+        })
+        .collect()
+}
+
+/// The `text` request field for `format`; `None` for plain text.
+pub(super) fn text_format(format: &ResponseFormat) -> Option<Value> {
+    match format {
+        ResponseFormat::Text => None,
+        ResponseFormat::JsonObject => Some(json!({ "format": { "type": "json_object" } })),
+        ResponseFormat::JsonSchema(schema) => Some(json!({
+            "format": { "type": "json_schema", "name": "response", "schema": schema }
+        })),
+    }
+}
+
+/// Parse a Responses `usage` object.
+pub(super) fn responses_usage(usage: &Value) -> TokenUsage {
+    let field = |v: &Value| v.as_u64().unwrap_or(0) as u32;
+    TokenUsage {
+        input_tokens: field(&usage["input_tokens"]),
+        output_tokens: field(&usage["output_tokens"]),
+        reasoning_tokens: field(&usage["output_tokens_details"]["reasoning_tokens"]),
+        ..Default::default()
+    }
+}
+
+fn function_call(item: &Value, arguments: &str) -> ToolCall {
+    ToolCall {
+        id: item["call_id"].as_str().unwrap_or("").to_string(),
+        name: item["name"].as_str().unwrap_or("").to_string(),
+        args: serde_json::from_str(arguments).unwrap_or(json!({})),
+        thought_signature: None,
+    }
+}
+
+/// The chunks for a function call item whose arguments finished streaming.
+fn streamed_call(item: &Value, arguments: String) -> Vec<StreamChunk> {
+    complete_tool_call(
+        item["call_id"].as_str().unwrap_or("").to_string(),
+        item["name"].as_str().unwrap_or("").to_string(),
+        arguments,
+        None,
+    )
+}
+
+/// A complete (non-streamed) response: its function calls if it made any,
+/// else the text of its messages.
+pub(super) fn parse_response(data: &Value) -> Result<LlmResponse> {
+    if let Some(message) = data["error"]["message"].as_str() {
+        anyhow::bail!("response failed: {message}");
+    }
+    let usage = responses_usage(&data["usage"]);
+    let output = data["output"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+    let calls: Vec<ToolCall> = output
+        .iter()
+        .filter(|item| item["type"] == "function_call")
+        .map(|item| function_call(item, item["arguments"].as_str().unwrap_or("{}")))
+        .collect();
+    if !calls.is_empty() {
+        return Ok(LlmResponse::ToolCalls { calls, usage });
+    }
+    let content = output
+        .iter()
+        .filter(|item| item["type"] == "message")
+        .flat_map(|item| item["content"].as_array().into_iter().flatten())
+        .filter(|part| part["type"] == "output_text")
+        .filter_map(|part| part["text"].as_str())
+        .collect();
+    Ok(LlmResponse::Message { content, usage })
+}
+
+/// Turns the typed events of a streamed response into [`StreamChunk`]s.
+#[derive(Default)]
+pub(super) struct ResponsesStream {
+    /// Function calls still streaming, by `output_index`: the item that
+    /// opened each and its arguments so far.
+    calls: BTreeMap<u64, (Value, String)>,
+    usage: Option<TokenUsage>,
+}
+
+impl ResponsesStream {
+    /// The chunks one event (the `data:` of an SSE message) completes.
+    pub(super) fn event(&mut self, event: &Value) -> Result<Vec<StreamChunk>> {
+        let text = || event["delta"].as_str().unwrap_or("").to_string();
+        let index = event["output_index"].as_u64().unwrap_or(0);
+        let chunk = match event["type"].as_str().unwrap_or("") {
+            "response.output_text.delta" => StreamChunk::Delta { text: text() },
+            "response.reasoning_summary_text.delta" | "response.reasoning_text.delta" => {
+                StreamChunk::Thinking { text: text() }
+            }
+            "response.output_item.added" if event["item"]["type"] == "function_call" => {
+                let arguments = event["item"]["arguments"]
+                    .as_str()
+                    .unwrap_or("")
+                    .to_string();
+                self.calls.insert(index, (event["item"].clone(), arguments));
+                return Ok(Vec::new());
+            }
+            "response.function_call_arguments.delta" => {
+                if let Some((_, arguments)) = self.calls.get_mut(&index) {
+                    arguments.push_str(event["delta"].as_str().unwrap_or(""));
+                }
+                return Ok(Vec::new());
+            }
+            "response.output_item.done" if event["item"]["type"] == "function_call" => {
+                let streamed = self.calls.remove(&index).map(|(_, args)| args);
+                // The finished item carries the full arguments.
+                let arguments = event["item"]["arguments"]
+                    .as_str()
+                    .map(String::from)
+                    .or(streamed)
+                    .unwrap_or_default();
+                return Ok(streamed_call(&event["item"], arguments));
+            }
+            "response.completed" | "response.incomplete" => {
+                self.usage = Some(responses_usage(&event["response"]["usage"]));
+                return Ok(Vec::new());
+            }
+            "response.failed" => anyhow::bail!(
+                "response failed: {}",
+                event["response"]["error"]["message"]
+                    .as_str()
+                    .unwrap_or("unknown error")
+            ),
+            "error" => anyhow::bail!(
+                "stream error: {}",
+                event["message"].as_str().unwrap_or("unknown error")
+            ),
+            _ => return Ok(Vec::new()),
+        };
+        let empty = matches!(
+            &chunk,
+            StreamChunk::Delta { text } | StreamChunk::Thinking { text } if text.is_empty()
+        );
+        Ok(if empty { Vec::new() } else { vec![chunk] })
+    }
+
+    /// Calls whose stream ended without a `done` event, then the usage.
+    pub(super) fn finish(self) -> Vec<StreamChunk> {
+        let mut chunks: Vec<StreamChunk> = self
+            .calls
+            .into_values()
+            .flat_map(|(item, arguments)| streamed_call(&item, arguments))
+            .collect();
+        chunks.push(StreamChunk::Done {
+            usage: self.usage.unwrap_or_default(),
+        });
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_turns_become_function_call_items() {
+        let call = ToolCall {
+            id: "call_1".into(),
+            name: "read".into(),
+            args: json!({ "path": "a.txt" }),
+            thought_signature: None,
+        };
+        let input = build_input(
+            &[
+                Message::system("be brief"),
+                Message::user("open a.txt"),
+                Message::assistant_tool_calls(vec![call]),
+                Message::tool_result("hello", "call_1", "read"),
+            ],
+            true,
+            false,
+        );
+        assert_eq!(input[0], json!({ "role": "system", "content": "be brief" }));
+        assert_eq!(input[2]["type"], "function_call");
+        assert_eq!(input[2]["call_id"], "call_1");
+        assert_eq!(input[2]["arguments"], r#"{"path":"a.txt"}"#);
+        assert_eq!(
+            input[3],
+            json!({ "type": "function_call_output", "call_id": "call_1", "output": "hello" })
+        );
+    }
+
+    #[test]
+    fn complete_responses_parse_to_calls_or_text() {
+        let text = parse_response(&json!({
+            "output": [
+                { "type": "reasoning", "summary": [] },
+                { "type": "message", "content": [{ "type": "output_text", "text": "hi" }] }
+            ],
+            "usage": { "input_tokens": 10, "output_tokens": 4,
+                       "output_tokens_details": { "reasoning_tokens": 2 } }
+        }))
+        .unwrap();
+        let LlmResponse::Message { content, usage } = text else {
+            panic!("expected a message");
+        };
+        assert_eq!(content, "hi");
+        assert_eq!((usage.input_tokens, usage.reasoning_tokens), (10, 2));
+
+        let calls = parse_response(&json!({
+            "output": [{ "type": "function_call", "call_id": "c1", "name": "glob",
+                         "arguments": "{\"pattern\":\"*.rs\"}" }]
+        }))
+        .unwrap();
+        let LlmResponse::ToolCalls { calls, .. } = calls else {
+            panic!("expected tool calls");
+        };
+        assert_eq!(calls[0].args["pattern"], "*.rs");
+    }
+
+    #[test]
+    fn stream_events_become_chunks() {
+        let mut stream = ResponsesStream::default();
+        let events = [
+            json!({ "type": "response.created" }),
+            json!({ "type": "response.reasoning_summary_text.delta", "delta": "thinking" }),
+            json!({ "type": "response.output_text.delta", "delta": "Let me look." }),
+            json!({ "type": "response.output_item.added", "output_index": 2,
+                    "item": { "type": "function_call", "call_id": "c1", "name": "read", "arguments": "" } }),
+            json!({ "type": "response.function_call_arguments.delta", "output_index": 2, "delta": "{\"path\":" }),
+            json!({ "type": "response.function_call_arguments.delta", "output_index": 2, "delta": "\"a\"}" }),
+            json!({ "type": "response.output_item.added", "output_index": 3,
+                    "item": { "type": "function_call", "call_id": "c2", "name": "glob", "arguments": "" } }),
+            json!({ "type": "response.function_call_arguments.delta", "output_index": 3, "delta": "{}" }),
+            json!({ "type": "response.completed",
+                    "response": { "usage": { "input_tokens": 7, "output_tokens": 3 } } }),
+        ];
+        let mut chunks = Vec::new();
+        for event in &events {
+            chunks.extend(stream.event(event).unwrap());
+        }
+        chunks.extend(stream.finish());
+
+        assert!(matches!(&chunks[0], StreamChunk::Thinking { text } if text == "thinking"));
+        assert!(matches!(&chunks[1], StreamChunk::Delta { text } if text == "Let me look."));
+        let StreamChunk::ToolCallReady { call } = &chunks[2] else {
+            panic!("expected the first tool call");
+        };
+        assert_eq!(
+            (call.id.as_str(), call.args["path"].as_str()),
+            ("c1", Some("a"))
+        );
+        assert!(matches!(&chunks[3], StreamChunk::ToolCallReady { call } if call.name == "glob"));
+        assert!(matches!(&chunks[4], StreamChunk::Done { usage } if usage.input_tokens == 7));
+
+        let failed = json!({ "type": "response.failed",
+                             "response": { "error": { "message": "quota" } } });
+        let err = ResponsesStream::default().event(&failed).unwrap_err();
+        assert!(err.to_string().contains("quota"));
+    }
+}
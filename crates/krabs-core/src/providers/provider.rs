@@ -2,11 +2,20 @@ use crate::tools::tool::ToolDef;
 use anyhow::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::borrow::Cow;
 use tokio::sync::mpsc;
 
+/// Content of the result inserted for a tool call whose real result is missing.
+const MISSING_TOOL_RESULT: &str = "[no result: this tool call was interrupted]";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Role {
     System,
+    /// Instructions from the application rather than the user. Sent with the
+    /// `developer` role to models that have one and folded into the system
+    /// prompt elsewhere. Unlike system messages, they are kept in the history.
+    Developer,
     User,
     Assistant,
     Tool,
@@ -21,6 +30,32 @@ pub struct Message {
     pub tool_name: Option<String>,
     /// Populated on assistant messages that requested tool calls
     pub tool_calls: Option<Vec<ToolCall>>,
+    /// Images sent alongside `content` (user messages and tool results).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageContent>,
+}
+
+/// An inline image attachment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageContent {
+    /// MIME type, e.g. `image/png`.
+    pub mime_type: String,
+    /// Base64-encoded image bytes.
+    pub data: String,
+}
+
+impl ImageContent {
+    pub fn new(mime_type: impl Into<String>, data: impl Into<String>) -> Self {
+        Self {
+            mime_type: mime_type.into(),
+            data: data.into(),
+        }
+    }
+
+    /// The `data:` URL form used by OpenAI-compatible APIs.
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.mime_type, self.data)
+    }
 }
 
 impl Message {
@@ -31,6 +66,17 @@ impl Message {
             tool_call_id: None,
             tool_name: None,
             tool_calls: None,
+            images: Vec::new(),
+        }
+    }
+    pub fn developer(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::Developer,
+            content: content.into(),
+            tool_call_id: None,
+            tool_name: None,
+            tool_calls: None,
+            images: Vec::new(),
         }
     }
     pub fn user(content: impl Into<String>) -> Self {
@@ -40,6 +86,7 @@ impl Message {
             tool_call_id: None,
             tool_name: None,
             tool_calls: None,
+            images: Vec::new(),
         }
     }
     pub fn assistant(content: impl Into<String>) -> Self {
@@ -49,6 +96,7 @@ impl Message {
             tool_call_id: None,
             tool_name: None,
             tool_calls: None,
+            images: Vec::new(),
         }
     }
     pub fn assistant_tool_calls(calls: Vec<ToolCall>) -> Self {
@@ -58,6 +106,7 @@ impl Message {
             tool_call_id: None,
             tool_name: None,
             tool_calls: Some(calls),
+            images: Vec::new(),
         }
     }
     pub fn tool_result(
@@ -71,8 +120,69 @@ impl Message {
             tool_call_id: Some(tool_call_id.into()),
             tool_name: Some(tool_name.into()),
             tool_calls: None,
+            images: Vec::new(),
         }
     }
+    pub fn with_images(mut self, images: Vec<ImageContent>) -> Self {
+        self.images = images;
+        self
+    }
+
+    /// Make `messages` acceptable to providers that require every tool result
+    /// to answer a call from the assistant message right before it.
+    ///
+    /// Results whose call isn't in that message (e.g. left behind when the
+    /// call was trimmed) and duplicate results are dropped; calls that never got
+    /// a result get a placeholder one. Returns `messages` unchanged, without
+    /// copying, when nothing needs fixing.
+    pub fn validate_tool_pairing(messages: &[Message]) -> Cow<'_, [Message]> {
+        enum Slot<'a> {
+            Keep(&'a Message),
+            Missing(&'a ToolCall),
+        }
+
+        fn close<'a>(out: &mut Vec<Slot<'a>>, open: &mut Vec<&'a ToolCall>) {
+            out.extend(open.drain(..).map(Slot::Missing));
+        }
+
+        let mut out: Vec<Slot> = Vec::with_capacity(messages.len());
+        // Calls from the latest assistant message still waiting for a result.
+        let mut open: Vec<&ToolCall> = Vec::new();
+        for m in messages {
+            if matches!(m.role, Role::Tool) {
+                let pos = open
+                    .iter()
+                    .position(|c| m.tool_call_id.as_deref() == Some(c.id.as_str()));
+                if let Some(pos) = pos {
+                    open.remove(pos);
+                    out.push(Slot::Keep(m));
+                }
+                continue;
+            }
+            close(&mut out, &mut open);
+            out.push(Slot::Keep(m));
+            if let Some(calls) = &m.tool_calls {
+                open.extend(calls);
+            }
+        }
+        close(&mut out, &mut open);
+
+        let untouched =
+            out.len() == messages.len() && out.iter().all(|slot| matches!(slot, Slot::Keep(_)));
+        if untouched {
+            return Cow::Borrowed(messages);
+        }
+        Cow::Owned(
+            out.into_iter()
+                .map(|slot| match slot {
+                    Slot::Keep(m) => m.clone(),
+                    Slot::Missing(call) => {
+                        Message::tool_result(MISSING_TOOL_RESULT, &call.id, &call.name)
+                    }
+                })
+                .collect(),
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -81,14 +191,24 @@ pub struct ToolCall {
     pub name: String,
     pub args: serde_json::Value,
     /// Gemini thinking models attach a thought_signature that must be echoed back
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// on the next request. Stored with the call, so resumed sessions keep it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub thought_signature: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TokenUsage {
     pub input_tokens: u32,
     pub output_tokens: u32,
+    /// Input tokens served from the provider's prompt cache (Anthropic only).
+    #[serde(default)]
+    pub cache_read_tokens: u32,
+    /// Input tokens written to the provider's prompt cache (Anthropic only).
+    #[serde(default)]
+    pub cache_creation_tokens: u32,
+    /// Part of `output_tokens` spent on reasoning, when the provider reports it.
+    #[serde(default)]
+    pub reasoning_tokens: u32,
 }
 
 #[derive(Debug)]
@@ -103,16 +223,334 @@ pub enum LlmResponse {
     },
 }
 
+/// Shape the final reply must take. Set per request with
+/// [`LlmProvider::complete_with_format`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ResponseFormat {
+    /// Free-form text.
+    #[default]
+    Text,
+    /// Any JSON object.
+    JsonObject,
+    /// JSON matching this JSON Schema.
+    JsonSchema(Value),
+}
+
+impl ResponseFormat {
+    /// The schema the reply must match, if any. `JsonObject` is any object.
+    pub fn schema(&self) -> Option<Value> {
+        match self {
+            Self::Text => None,
+            Self::JsonObject => Some(json!({ "type": "object" })),
+            Self::JsonSchema(schema) => Some(schema.clone()),
+        }
+    }
+}
+
+/// OpenAI-style `response_format` request field for `format`; `None` for text.
+/// Also accepted by Gemini's OpenAI-compatible endpoint, which maps it to its
+/// native `responseSchema`.
+pub(crate) fn openai_response_format(format: &ResponseFormat) -> Option<Value> {
+    match format {
+        ResponseFormat::Text => None,
+        ResponseFormat::JsonObject => Some(json!({ "type": "json_object" })),
+        ResponseFormat::JsonSchema(schema) => Some(json!({
+            "type": "json_schema",
+            "json_schema": { "name": "response", "schema": schema }
+        })),
+    }
+}
+
+/// How hard a reasoning model thinks before answering: a level, or a token
+/// budget. Written as `"low"`, `"medium"`, `"high"` or a number in config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ReasoningEffort {
+    Level(EffortLevel),
+    Budget(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EffortLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl EffortLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+}
+
+impl ReasoningEffort {
+    /// The effort as a level; budgets are bucketed around the level budgets.
+    pub fn level(self) -> EffortLevel {
+        match self {
+            Self::Level(level) => level,
+            Self::Budget(n) if n <= 4_096 => EffortLevel::Low,
+            Self::Budget(n) if n <= 16_384 => EffortLevel::Medium,
+            Self::Budget(_) => EffortLevel::High,
+        }
+    }
+
+    /// The effort as a reasoning-token budget.
+    pub fn budget_tokens(self) -> u32 {
+        match self {
+            Self::Level(EffortLevel::Low) => 2_048,
+            Self::Level(EffortLevel::Medium) => 8_192,
+            Self::Level(EffortLevel::High) => 24_576,
+            Self::Budget(n) => n,
+        }
+    }
+}
+
+impl std::str::FromStr for ReasoningEffort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "low" => Ok(Self::Level(EffortLevel::Low)),
+            "medium" => Ok(Self::Level(EffortLevel::Medium)),
+            "high" => Ok(Self::Level(EffortLevel::High)),
+            other => other.parse().map(Self::Budget).map_err(|_| {
+                anyhow::anyhow!(
+                    "invalid reasoning effort '{s}': expected low, medium, high or a token budget"
+                )
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for ReasoningEffort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Level(level) => f.write_str(level.as_str()),
+            Self::Budget(n) => write!(f, "{n} tokens"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum StreamChunk {
     /// Incremental text token from the model
     Delta { text: String },
+    /// Incremental reasoning text (extended thinking, `reasoning_content`).
+    /// Shown to the user but never part of the reply or the persisted history.
+    Thinking { text: String },
     /// Tool call ready (args fully accumulated)
     ToolCallReady { call: ToolCall },
     /// Final usage stats, signals end of stream
     Done { usage: TokenUsage },
     /// Informational status message (e.g. tool retry notice)
     Status { text: String },
+    /// The stream broke after `partial` text had already been forwarded. When
+    /// `resumable`, the retry continues from `partial` and its deltas extend it;
+    /// otherwise the turn restarts and consumers should discard `partial`.
+    Interrupted { partial: String, resumable: bool },
+}
+
+/// Parse an OpenAI-style `usage` object. Reasoning tokens come from
+/// `completion_tokens_details`; servers that leave them out of
+/// `completion_tokens` but count them in `total_tokens` (Gemini's compatible
+/// endpoint) have the difference added to the output count.
+pub(crate) fn openai_usage(usage: &Value) -> TokenUsage {
+    let field = |v: &Value| v.as_u64().unwrap_or(0) as u32;
+    let input = field(&usage["prompt_tokens"]);
+    let completion = field(&usage["completion_tokens"]);
+    let unreported = field(&usage["total_tokens"]).saturating_sub(input + completion);
+    let reasoning = field(&usage["completion_tokens_details"]["reasoning_tokens"]);
+    TokenUsage {
+        input_tokens: input,
+        output_tokens: completion + unreported,
+        reasoning_tokens: reasoning.max(unreported),
+        ..Default::default()
+    }
+}
+
+/// Reasoning text in an OpenAI-style stream delta. Reasoning models served
+/// through compatible APIs (Qwen3, DeepSeek-R1, OpenRouter) stream their
+/// chain-of-thought in `reasoning_content` or `reasoning` before the answer
+/// arrives in `content`.
+pub(crate) fn reasoning_delta(delta: &Value) -> Option<&str> {
+    ["reasoning_content", "reasoning"]
+        .iter()
+        .find_map(|field| delta[field].as_str())
+        .filter(|text| !text.is_empty())
+}
+
+/// Key under which a streamed tool call whose arguments weren't valid JSON
+/// carries them, with the parse error. The agent answers such a call with an
+/// error result instead of running the tool.
+pub const MALFORMED_ARGS_KEY: &str = "_malformed_arguments";
+
+/// Why a call's arguments were rejected, if they carry [`MALFORMED_ARGS_KEY`].
+pub fn malformed_args_error(args: &Value) -> Option<&str> {
+    args.get(MALFORMED_ARGS_KEY)?["error"].as_str()
+}
+
+/// Tool calls whose arguments stream in as JSON fragments, buffered by the
+/// index the provider numbers them with. Arguments are only parsed once a
+/// call is complete.
+#[derive(Default)]
+pub(crate) struct ToolCallBuffer {
+    calls: std::collections::BTreeMap<usize, PendingToolCall>,
+}
+
+#[derive(Default)]
+struct PendingToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+    thought_signature: Option<String>,
+}
+
+impl ToolCallBuffer {
+    /// Start or extend the call at `index`. Providers send the id and name
+    /// once and the arguments in pieces, so each part is optional.
+    pub(crate) fn push(
+        &mut self,
+        index: usize,
+        id: Option<&str>,
+        name: Option<&str>,
+        arguments: Option<&str>,
+    ) {
+        let call = self.calls.entry(index).or_default();
+        if let Some(id) = id.filter(|id| !id.is_empty()) {
+            call.id = id.to_string();
+        }
+        if let Some(name) = name.filter(|name| !name.is_empty()) {
+            call.name = name.to_string();
+        }
+        if let Some(fragment) = arguments {
+            call.arguments.push_str(fragment);
+        }
+    }
+
+    /// Add to the Gemini thought signature of the call at `index`.
+    pub(crate) fn push_thought_signature(&mut self, index: usize, signature: &str) {
+        self.calls
+            .entry(index)
+            .or_default()
+            .thought_signature
+            .get_or_insert_with(String::new)
+            .push_str(signature);
+    }
+
+    /// Finish the call at `index`; nothing if there is none.
+    pub(crate) fn finish(&mut self, index: usize) -> Vec<StreamChunk> {
+        self.calls
+            .remove(&index)
+            .map(PendingToolCall::complete)
+            .unwrap_or_default()
+    }
+
+    /// Finish every buffered call, in index order.
+    pub(crate) fn finish_all(&mut self) -> Vec<StreamChunk> {
+        std::mem::take(&mut self.calls)
+            .into_values()
+            .flat_map(PendingToolCall::complete)
+            .collect()
+    }
+}
+
+impl PendingToolCall {
+    fn complete(self) -> Vec<StreamChunk> {
+        complete_tool_call(self.id, self.name, self.arguments, self.thought_signature)
+    }
+}
+
+/// The chunks for a streamed tool call once its arguments have all arrived.
+/// Empty arguments are `{}`. Arguments that don't parse as JSON are announced
+/// with a `Status` chunk and kept under [`MALFORMED_ARGS_KEY`], so the call
+/// still gets a result, an error, and the stream carries on.
+pub(crate) fn complete_tool_call(
+    id: String,
+    name: String,
+    arguments: String,
+    thought_signature: Option<String>,
+) -> Vec<StreamChunk> {
+    let parsed = match arguments.trim() {
+        "" => Ok(json!({})),
+        raw => serde_json::from_str(raw),
+    };
+    let mut chunks = Vec::new();
+    let args = match parsed {
+        Ok(args) => args,
+        Err(e) => {
+            tracing::warn!(tool = %name, "malformed tool-call arguments: {e}");
+            chunks.push(StreamChunk::Status {
+                text: format!("⚠ {name} call had malformed arguments ({e}); not running it"),
+            });
+            json!({ MALFORMED_ARGS_KEY: { "raw": arguments, "error": e.to_string() } })
+        }
+    };
+    chunks.push(StreamChunk::ToolCallReady {
+        call: ToolCall {
+            id,
+            name,
+            args,
+            thought_signature,
+        },
+    });
+    chunks
+}
+
+/// Apply image attachments to OpenAI-style chat messages. `built` must be the
+/// 1:1 serialization of `messages`.
+///
+/// User images become `image_url` content parts. `tool` messages cannot carry
+/// images in this format, so tool-result images are re-sent in one user
+/// message right after the run of tool results they came from.
+pub(crate) fn attach_openai_images(messages: &[Message], built: Vec<Value>) -> Vec<Value> {
+    let mut out = Vec::with_capacity(built.len());
+    let mut pending: Vec<&ImageContent> = Vec::new();
+    for (m, mut obj) in messages.iter().zip(built) {
+        if !matches!(m.role, Role::Tool) && !pending.is_empty() {
+            out.push(tool_images_message(&pending));
+            pending.clear();
+        }
+        match m.role {
+            Role::User if !m.images.is_empty() => {
+                let images: Vec<&ImageContent> = m.images.iter().collect();
+                obj["content"] = openai_content_parts(&m.content, &images);
+            }
+            Role::Tool => pending.extend(&m.images),
+            _ => {}
+        }
+        out.push(obj);
+    }
+    if !pending.is_empty() {
+        out.push(tool_images_message(&pending));
+    }
+    out
+}
+
+fn openai_content_parts(text: &str, images: &[&ImageContent]) -> Value {
+    let mut parts = vec![json!({ "type": "text", "text": text })];
+    parts.extend(
+        images
+            .iter()
+            .map(|img| json!({ "type": "image_url", "image_url": { "url": img.data_url() } })),
+    );
+    Value::Array(parts)
+}
+
+fn tool_images_message(images: &[&ImageContent]) -> Value {
+    json!({
+        "role": "user",
+        "content": openai_content_parts("Images returned by the tool calls above.", images)
+    })
+}
+
+/// Number of image attachments across `messages`.
+pub(crate) fn image_count(messages: &[Message]) -> usize {
+    messages.iter().map(|m| m.images.len()).sum()
 }
 
 #[async_trait]
@@ -125,6 +563,32 @@ pub trait LlmProvider: Send + Sync {
         tools: &[ToolDef],
         tx: mpsc::Sender<StreamChunk>,
     ) -> Result<()>;
+
+    /// Like [`complete`](Self::complete), but a final message must take the
+    /// given [`ResponseFormat`]. Providers that can't enforce a format ignore
+    /// it, so callers should still validate the reply.
+    async fn complete_with_format(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDef],
+        _format: &ResponseFormat,
+    ) -> Result<LlmResponse> {
+        self.complete(messages, tools).await
+    }
+
+    /// Whether a trailing assistant message is continued rather than answered
+    /// anew (assistant prefill), letting an interrupted stream resume from the
+    /// partial text instead of retrying the whole turn.
+    fn supports_continuation(&self) -> bool {
+        false
+    }
+
+    /// Tokens `messages` and `tools` take up in the model's context window.
+    /// Providers with a tokenizer or count endpoint override this; the default
+    /// is a rough ~4 bytes per token.
+    async fn count_tokens(&self, messages: &[Message], tools: &[ToolDef]) -> Result<u32> {
+        Ok(super::tokens::estimate_tokens(messages, tools))
+    }
 }
 
 /// Allow `Arc<dyn LlmProvider>` to be used wherever `impl LlmProvider` is expected.
@@ -142,4 +606,185 @@ impl LlmProvider for std::sync::Arc<dyn LlmProvider> {
     ) -> Result<()> {
         (**self).stream_complete(messages, tools, tx).await
     }
+
+    async fn complete_with_format(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDef],
+        format: &ResponseFormat,
+    ) -> Result<LlmResponse> {
+        (**self).complete_with_format(messages, tools, format).await
+    }
+
+    fn supports_continuation(&self) -> bool {
+        (**self).supports_continuation()
+    }
+
+    async fn count_tokens(&self, messages: &[Message], tools: &[ToolDef]) -> Result<u32> {
+        (**self).count_tokens(messages, tools).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streamed_tool_calls_are_parsed_once_complete() {
+        let mut buffer = ToolCallBuffer::default();
+        // Two calls interleaved, their arguments split mid-token.
+        buffer.push(1, Some("c2"), Some("glob"), Some(""));
+        buffer.push(0, Some("c1"), Some("read"), Some(r#"{"pa"#));
+        buffer.push(1, None, None, Some(r#"{"pattern": "*.rs""#));
+        buffer.push(0, None, None, Some(r#"th": "a.rs"}"#));
+        buffer.push(1, None, None, Some("}"));
+        buffer.push(2, Some("c3"), Some("sysinfo"), None);
+
+        let calls: Vec<ToolCall> = buffer
+            .finish_all()
+            .into_iter()
+            .filter_map(|chunk| match chunk {
+                StreamChunk::ToolCallReady { call } => Some(call),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[0].args, json!({ "path": "a.rs" }));
+        assert_eq!(calls[1].args, json!({ "pattern": "*.rs" }));
+        assert_eq!(calls[2].args, json!({}));
+        assert!(buffer.finish_all().is_empty());
+    }
+
+    #[test]
+    fn malformed_arguments_are_flagged_not_dropped() {
+        let mut buffer = ToolCallBuffer::default();
+        buffer.push(0, Some("c1"), Some("bash"), Some(r#"{"command": "ls"#));
+        let chunks = buffer.finish(0);
+
+        assert!(
+            matches!(&chunks[0], StreamChunk::Status { text } if text.contains("bash call had malformed arguments"))
+        );
+        let StreamChunk::ToolCallReady { call } = &chunks[1] else {
+            panic!("expected the call itself");
+        };
+        assert_eq!(call.id, "c1");
+        assert!(malformed_args_error(&call.args).is_some());
+        assert_eq!(call.args[MALFORMED_ARGS_KEY]["raw"], r#"{"command": "ls"#);
+        assert!(malformed_args_error(&json!({ "command": "ls" })).is_none());
+    }
+
+    fn call(id: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            name: "bash".to_string(),
+            args: json!({}),
+            thought_signature: None,
+        }
+    }
+
+    #[test]
+    fn response_formats_map_to_openai_fields() {
+        assert_eq!(openai_response_format(&ResponseFormat::Text), None);
+        assert_eq!(
+            openai_response_format(&ResponseFormat::JsonObject),
+            Some(json!({ "type": "json_object" }))
+        );
+        let schema = json!({ "type": "object" });
+        let format = openai_response_format(&ResponseFormat::JsonSchema(schema.clone())).unwrap();
+        assert_eq!(format["type"], "json_schema");
+        assert_eq!(format["json_schema"]["schema"], schema);
+    }
+
+    #[test]
+    fn openai_usage_counts_reasoning_tokens() {
+        let openai = openai_usage(&json!({
+            "prompt_tokens": 10,
+            "completion_tokens": 50,
+            "total_tokens": 60,
+            "completion_tokens_details": { "reasoning_tokens": 30 }
+        }));
+        assert_eq!(openai.output_tokens, 50);
+        assert_eq!(openai.reasoning_tokens, 30);
+
+        // Thoughts counted only in the total.
+        let gemini = openai_usage(&json!({
+            "prompt_tokens": 10,
+            "completion_tokens": 20,
+            "total_tokens": 55
+        }));
+        assert_eq!(gemini.output_tokens, 45);
+        assert_eq!(gemini.reasoning_tokens, 25);
+    }
+
+    #[test]
+    fn reasoning_delta_reads_either_field() {
+        assert_eq!(
+            reasoning_delta(&json!({ "reasoning_content": "hmm" })),
+            Some("hmm")
+        );
+        assert_eq!(reasoning_delta(&json!({ "reasoning": "so" })), Some("so"));
+        assert_eq!(
+            reasoning_delta(&json!({ "reasoning_content": "", "content": "hi" })),
+            None
+        );
+    }
+
+    #[test]
+    fn paired_history_is_borrowed_unchanged() {
+        let msgs = vec![
+            Message::user("hi"),
+            Message::assistant_tool_calls(vec![call("a"), call("b")]),
+            Message::tool_result("1", "a", "bash"),
+            Message::tool_result("2", "b", "bash"),
+            Message::assistant("done"),
+        ];
+        assert!(matches!(
+            Message::validate_tool_pairing(&msgs),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn orphaned_result_is_dropped() {
+        // The assistant call for "a" was trimmed away, leaving its result.
+        let msgs = vec![
+            Message::system("sys"),
+            Message::tool_result("stale", "a", "bash"),
+            Message::user("next"),
+            Message::assistant_tool_calls(vec![call("b")]),
+            Message::tool_result("ok", "b", "bash"),
+            Message::tool_result("dup", "b", "bash"),
+        ];
+        let fixed = Message::validate_tool_pairing(&msgs);
+        let contents: Vec<_> = fixed.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["sys", "next", "", "ok"]);
+    }
+
+    #[test]
+    fn missing_result_gets_a_placeholder() {
+        let msgs = vec![
+            Message::assistant_tool_calls(vec![call("a"), call("b")]),
+            Message::tool_result("1", "a", "bash"),
+            Message::user("continue"),
+        ];
+        let fixed = Message::validate_tool_pairing(&msgs);
+        assert_eq!(fixed.len(), 4);
+        assert_eq!(fixed[2].tool_call_id.as_deref(), Some("b"));
+        assert_eq!(fixed[2].content, MISSING_TOOL_RESULT);
+        assert_eq!(fixed[3].content, "continue");
+    }
+
+    #[test]
+    fn reasoning_effort_reads_levels_and_budgets() {
+        let high: ReasoningEffort = serde_json::from_value(json!("high")).unwrap();
+        assert_eq!(high, ReasoningEffort::Level(EffortLevel::High));
+        let budget: ReasoningEffort = serde_json::from_value(json!(6000)).unwrap();
+        assert_eq!(budget, ReasoningEffort::Budget(6000));
+        assert_eq!(budget.level(), EffortLevel::Medium);
+        assert_eq!(
+            "Low".parse::<ReasoningEffort>().unwrap().budget_tokens(),
+            2_048
+        );
+        assert!("extreme".parse::<ReasoningEffort>().is_err());
+    }
 }
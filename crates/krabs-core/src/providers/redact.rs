@@ -0,0 +1,74 @@
+use regex::Regex;
+use std::sync::LazyLock;
+use tracing::debug;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Well-known API key shapes (OpenAI/Anthropic `sk-…`, Google `AIza…`).
+static KEY_SHAPES: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r"sk-[A-Za-z0-9_\-]{16,}|AIza[0-9A-Za-z_\-]{30,}").ok());
+
+/// JSON fields that carry credentials, e.g. `"api_key": "…"`.
+static KEY_FIELDS: LazyLock<Option<Regex>> = LazyLock::new(|| {
+    Regex::new(r#"(?i)("(?:api[_-]?key|x-api-key|authorization)"\s*:\s*")[^"]*""#).ok()
+});
+
+/// Scrub credentials from `text` before it is logged: every occurrence of
+/// `secret`, anything shaped like a known API key, and credential-named JSON fields.
+pub fn redact(text: &str, secret: &str) -> String {
+    let mut out = if secret.len() >= 4 {
+        text.replace(secret, REDACTED)
+    } else {
+        text.to_string()
+    };
+    if let Some(re) = KEY_FIELDS.as_ref() {
+        out = re
+            .replace_all(&out, format!("${{1}}{REDACTED}\"").as_str())
+            .into_owned();
+    }
+    if let Some(re) = KEY_SHAPES.as_ref() {
+        out = re.replace_all(&out, REDACTED).into_owned();
+    }
+    out
+}
+
+/// `true` when `KRABS_TRACE_HTTP` is set to anything other than `""`, `0` or `false`.
+pub fn trace_http_from_env() -> bool {
+    std::env::var("KRABS_TRACE_HTTP")
+        .map(|v| !matches!(v.trim(), "" | "0" | "false"))
+        .unwrap_or(false)
+}
+
+/// Log a raw HTTP body at `debug` level under the `krabs::http` target,
+/// with `secret` and anything key-shaped redacted.
+pub fn trace_body(provider: &str, what: &str, body: &str, secret: &str) {
+    debug!(target: "krabs::http", provider, "{what}: {}", redact(body, secret));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_the_configured_secret() {
+        let out = redact("key=my-secret-value&x=1", "my-secret-value");
+        assert_eq!(out, "key=[REDACTED]&x=1");
+    }
+
+    #[test]
+    fn redacts_key_shaped_tokens() {
+        let out = redact("Bearer sk-abcdefghijklmnopqrstu used", "");
+        assert_eq!(out, "Bearer [REDACTED] used");
+    }
+
+    #[test]
+    fn redacts_credential_json_fields() {
+        let out = redact(r#"{"api_key": "hunter2", "model": "gpt-4o"}"#, "");
+        assert_eq!(out, r#"{"api_key": "[REDACTED]", "model": "gpt-4o"}"#);
+    }
+
+    #[test]
+    fn short_secret_is_not_used_as_a_pattern() {
+        assert_eq!(redact("a b c", "a"), "a b c");
+    }
+}
@@ -0,0 +1,100 @@
+//! Token counting behind [`LlmProvider::count_tokens`](super::LlmProvider::count_tokens):
+//! OpenAI's tokenizer run locally, and the byte-length estimate every
+//! counter falls back on.
+
+use super::provider::Message;
+use crate::agents::compact;
+use crate::tools::tool::ToolDef;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tiktoken_rs::CoreBPE;
+
+/// How long a count-tokens endpoint may take before the count falls back to
+/// an estimate.
+pub(crate) const COUNT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Rough count (~4 bytes per token) of message text, tool calls and tool
+/// definitions.
+pub fn estimate_tokens(messages: &[Message], tools: &[ToolDef]) -> u32 {
+    let tool_bytes: usize = tools
+        .iter()
+        .map(|t| t.name.len() + t.description.len() + t.parameters.to_string().len())
+        .sum();
+    (compact::estimate_tokens(messages) + tool_bytes / 4) as u32
+}
+
+/// Models on the `o200k_base` encoding; older ones use `cl100k_base`.
+fn uses_o200k(model: &str) -> bool {
+    let model = model.rsplit('/').next().unwrap_or(model);
+    [
+        "gpt-4o",
+        "gpt-4.1",
+        "gpt-4.5",
+        "gpt-5",
+        "chatgpt-4o",
+        "o1",
+        "o3",
+        "o4",
+    ]
+    .iter()
+    .any(|prefix| model.starts_with(prefix))
+}
+
+/// The tokenizer for `model`, loaded once per encoding. `None` if it failed
+/// to load.
+fn tokenizer(model: &str) -> Option<&'static CoreBPE> {
+    static O200K: OnceLock<Option<CoreBPE>> = OnceLock::new();
+    static CL100K: OnceLock<Option<CoreBPE>> = OnceLock::new();
+    if uses_o200k(model) {
+        O200K
+            .get_or_init(|| tiktoken_rs::o200k_base().ok())
+            .as_ref()
+    } else {
+        CL100K
+            .get_or_init(|| tiktoken_rs::cl100k_base().ok())
+            .as_ref()
+    }
+}
+
+/// Count with the tokenizer of OpenAI's `model`, including the few tokens
+/// the chat format adds around each message and the reply.
+pub fn tiktoken_count(model: &str, messages: &[Message], tools: &[ToolDef]) -> Option<u32> {
+    let bpe = tokenizer(model)?;
+    let count = |text: &str| bpe.encode_ordinary(text).len();
+    // Every reply is primed with `<|start|>assistant<|message|>`.
+    let mut total = 3;
+    for m in messages {
+        total += 4 + count(&m.content);
+        for call in m.tool_calls.iter().flatten() {
+            total += count(&call.name) + count(&call.args.to_string());
+        }
+    }
+    for t in tools {
+        total += count(&t.name) + count(&t.description) + count(&t.parameters.to_string());
+    }
+    Some(total as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn tokenizer_counts_text_and_framing() {
+        let code = "fn main() { let v: Vec<u8> = (0..10).collect(); println!(\"{v:?}\"); }";
+        let messages = [Message::user(code)];
+        let counted = tiktoken_count("gpt-4o", &messages, &[]).unwrap();
+        // 3 reply-priming tokens, 4 of framing, then the text itself.
+        assert!(counted > 7);
+        assert_eq!(tiktoken_count("gpt-4o", &messages, &[]), Some(counted));
+        assert!(tiktoken_count("gpt-3.5-turbo", &messages, &[]).is_some());
+
+        let tools = [ToolDef {
+            name: "read".into(),
+            description: "Read a file".into(),
+            parameters: json!({ "type": "object" }),
+        }];
+        assert!(estimate_tokens(&messages, &tools) > estimate_tokens(&messages, &[]));
+    }
+}
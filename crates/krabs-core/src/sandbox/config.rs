@@ -10,12 +10,17 @@ pub struct SandboxConfig {
     /// Paths blocked for reads (e.g. ~/.ssh, ~/.secrets)
     #[serde(default)]
     pub denied_read_paths: Vec<PathBuf>,
-    /// Domain allowlist — empty = no allowlist enforced (only blocklist applies)
-    #[serde(default)]
-    pub allowed_domains: Vec<String>,
-    /// Domains always blocked regardless of allowlist
+    /// Host allowlist — empty = no allowlist enforced (only blocklist applies).
+    /// A plain entry (`github.com`) matches the host and its subdomains; an entry
+    /// with `*` is a glob (`*.githubusercontent.com`, `api-*.example.com`).
+    #[serde(default, alias = "allowed_domains")]
+    pub allowed_hosts: Vec<String>,
+    /// Domains always blocked regardless of allowlist. Same matching rules.
     #[serde(default)]
     pub blocked_domains: Vec<String>,
+    /// Block every outbound connection through the proxy, ignoring both lists.
+    #[serde(default)]
+    pub deny_all_network: bool,
 }
 
 impl SandboxConfig {
@@ -99,19 +104,20 @@ impl SandboxConfig {
         // Strip port if present
         let host = domain.split(':').next().unwrap_or(domain);
 
+        if self.deny_all_network {
+            return Err(format!("sandbox: network access denied ({})", host));
+        }
+
         // Explicit blocklist always wins
         for blocked in &self.blocked_domains {
-            if host == blocked.as_str() || host.ends_with(&format!(".{}", blocked)) {
+            if host_matches(blocked, host) {
                 return Err(format!("sandbox: domain {} is blocked", host));
             }
         }
 
         // If allowlist is non-empty, host must match
-        if !self.allowed_domains.is_empty() {
-            let allowed = self
-                .allowed_domains
-                .iter()
-                .any(|a| host == a.as_str() || host.ends_with(&format!(".{}", a)));
+        if !self.allowed_hosts.is_empty() {
+            let allowed = self.allowed_hosts.iter().any(|a| host_matches(a, host));
             if !allowed {
                 return Err(format!("sandbox: domain {} not in allowlist", host));
             }
@@ -121,6 +127,20 @@ impl SandboxConfig {
     }
 }
 
+/// `true` when `host` matches a list entry: a glob if the entry contains `*`,
+/// otherwise the entry itself or any of its subdomains.
+fn host_matches(entry: &str, host: &str) -> bool {
+    if entry.contains('*') {
+        return glob::Pattern::new(entry)
+            .map(|p| p.matches(host))
+            .unwrap_or(false);
+    }
+    host == entry
+        || host
+            .strip_suffix(entry)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -231,7 +251,7 @@ mod tests {
     fn blocklist_overrides_allowlist() {
         let cfg = SandboxConfig {
             enabled: true,
-            allowed_domains: vec!["evil.com".to_string()],
+            allowed_hosts: vec!["evil.com".to_string()],
             blocked_domains: vec!["evil.com".to_string()],
             ..Default::default()
         };
@@ -245,7 +265,7 @@ mod tests {
     fn allowlist_blocks_unlisted_domain() {
         let cfg = SandboxConfig {
             enabled: true,
-            allowed_domains: vec!["api.openai.com".to_string()],
+            allowed_hosts: vec!["api.openai.com".to_string()],
             ..Default::default()
         };
         assert!(cfg.check_domain("api.openai.com:443").is_ok());
@@ -270,11 +290,52 @@ mod tests {
     fn subdomain_matching_works() {
         let cfg = SandboxConfig {
             enabled: true,
-            allowed_domains: vec!["openai.com".to_string()],
+            allowed_hosts: vec!["openai.com".to_string()],
             ..Default::default()
         };
         assert!(cfg.check_domain("api.openai.com:443").is_ok());
         assert!(cfg.check_domain("chat.openai.com:443").is_ok());
         assert!(cfg.check_domain("openai.com:443").is_ok());
     }
+
+    #[test]
+    fn wildcard_entry_matches_subdomains_only() {
+        let cfg = SandboxConfig {
+            enabled: true,
+            allowed_hosts: vec!["*.githubusercontent.com".to_string()],
+            ..Default::default()
+        };
+        assert!(cfg.check_domain("raw.githubusercontent.com:443").is_ok());
+        assert!(cfg.check_domain("githubusercontent.com:443").is_err());
+        assert!(cfg.check_domain("github.com:443").is_err());
+    }
+
+    #[test]
+    fn plain_entry_does_not_match_lookalike_suffix() {
+        let cfg = SandboxConfig {
+            enabled: true,
+            allowed_hosts: vec!["openai.com".to_string()],
+            ..Default::default()
+        };
+        assert!(cfg.check_domain("notopenai.com:443").is_err());
+    }
+
+    #[test]
+    fn deny_all_network_overrides_allowlist() {
+        let cfg = SandboxConfig {
+            enabled: true,
+            allowed_hosts: vec!["api.openai.com".to_string()],
+            deny_all_network: true,
+            ..Default::default()
+        };
+        assert!(cfg.check_domain("api.openai.com:443").is_err());
+    }
+
+    #[test]
+    fn legacy_allowed_domains_key_still_parses() {
+        let cfg: SandboxConfig =
+            serde_json::from_str(r#"{ "enabled": true, "allowed_domains": ["github.com"] }"#)
+                .unwrap();
+        assert_eq!(cfg.allowed_hosts, vec!["github.com".to_string()]);
+    }
 }
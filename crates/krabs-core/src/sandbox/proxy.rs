@@ -12,7 +12,11 @@ pub struct SandboxProxy {
 }
 
 impl SandboxProxy {
-    /// Start a CONNECT proxy on a random OS-assigned port.
+    /// Start a forward proxy on a random OS-assigned port.
+    ///
+    /// Handles `CONNECT host:port` tunnels (HTTPS) and absolute-form plain HTTP
+    /// requests. Every target host is checked against the `SandboxConfig`
+    /// allow/deny rules; rejected requests get a 403 and a warning log.
     pub async fn start(config: Arc<SandboxConfig>) -> Result<Self> {
         let listener = TcpListener::bind("127.0.0.1:0").await?;
         let port = listener.local_addr()?.port();
@@ -60,14 +64,28 @@ async fn handle_connection(mut client: TcpStream, config: Arc<SandboxConfig>) {
         Err(_) => return,
     };
 
-    // Parse: CONNECT host:port HTTP/1.1
+    // Parse: CONNECT host:port HTTP/1.1  or  GET http://host[:port]/path HTTP/1.1
     let first_line = request.lines().next().unwrap_or("");
     let parts: Vec<&str> = first_line.split_whitespace().collect();
-    if parts.len() < 2 || parts[0] != "CONNECT" {
+    if parts.len() < 2 {
         return;
     }
 
-    let target = parts[1];
+    let is_connect = parts[0] == "CONNECT";
+    let target = if is_connect {
+        parts[1].to_string()
+    } else {
+        match http_target(parts[1]) {
+            Some(t) => t,
+            None => {
+                let _ = client
+                    .write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")
+                    .await;
+                return;
+            }
+        }
+    };
+    let target = target.as_str();
 
     // Domain check
     if let Err(reason) = config.check_domain(target) {
@@ -79,7 +97,7 @@ async fn handle_connection(mut client: TcpStream, config: Arc<SandboxConfig>) {
     }
 
     // Connect to upstream
-    let upstream = match TcpStream::connect(target).await {
+    let mut upstream = match TcpStream::connect(target).await {
         Ok(s) => s,
         Err(e) => {
             warn!("SandboxProxy failed to connect to {}: {}", target, e);
@@ -90,12 +108,17 @@ async fn handle_connection(mut client: TcpStream, config: Arc<SandboxConfig>) {
         }
     };
 
-    // Send 200 Connection Established
-    if client
-        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
-        .await
-        .is_err()
-    {
+    if is_connect {
+        // Send 200 Connection Established
+        if client
+            .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+            .await
+            .is_err()
+        {
+            return;
+        }
+    } else if upstream.write_all(&buf[..n]).await.is_err() {
+        // Plain HTTP: replay the request we already read, then relay as-is.
         return;
     }
 
@@ -111,6 +134,21 @@ async fn handle_connection(mut client: TcpStream, config: Arc<SandboxConfig>) {
     let _ = tokio::join!(client_to_upstream, upstream_to_client);
 }
 
+/// `host:port` for an absolute-form `http://` request URI (port defaults to 80).
+fn http_target(uri: &str) -> Option<String> {
+    let rest = uri.strip_prefix("http://")?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    let host_port = authority.rsplit('@').next().unwrap_or(authority);
+    if host_port.is_empty() {
+        return None;
+    }
+    if host_port.contains(':') {
+        Some(host_port.to_string())
+    } else {
+        Some(format!("{host_port}:80"))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -164,7 +202,7 @@ mod tests {
     async fn proxy_blocks_domain_not_in_allowlist() {
         let cfg = Arc::new(SandboxConfig {
             enabled: true,
-            allowed_domains: vec!["api.openai.com".to_string()],
+            allowed_hosts: vec!["api.openai.com".to_string()],
             ..Default::default()
         });
         let proxy = SandboxProxy::start(cfg).await.unwrap();
@@ -191,7 +229,7 @@ mod tests {
 
         let cfg = Arc::new(SandboxConfig {
             enabled: true,
-            allowed_domains: vec!["localhost".to_string()],
+            allowed_hosts: vec!["localhost".to_string()],
             ..Default::default()
         });
         let proxy = SandboxProxy::start(cfg).await.unwrap();
@@ -204,4 +242,89 @@ mod tests {
             response
         );
     }
+
+    /// Helper: send a raw absolute-form HTTP request through the proxy.
+    async fn http_via_proxy(proxy_port: u16, url: &str) -> String {
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", proxy_port))
+            .await
+            .unwrap();
+        let req = format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", url);
+        stream.write_all(req.as_bytes()).await.unwrap();
+
+        let mut buf = vec![0u8; 256];
+        let n = stream.read(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf[..n])
+            .lines()
+            .next()
+            .unwrap_or("")
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn proxy_blocks_everything_when_network_denied() {
+        let cfg = Arc::new(SandboxConfig {
+            enabled: true,
+            allowed_hosts: vec!["localhost".to_string()],
+            deny_all_network: true,
+            ..Default::default()
+        });
+        let proxy = SandboxProxy::start(cfg).await.unwrap();
+        let response = connect_to_proxy(proxy.port(), "localhost:443").await;
+        assert!(response.contains("403"), "got: {}", response);
+    }
+
+    #[tokio::test]
+    async fn proxy_blocks_plain_http_to_disallowed_host() {
+        let cfg = Arc::new(SandboxConfig {
+            enabled: true,
+            allowed_hosts: vec!["*.example.com".to_string()],
+            ..Default::default()
+        });
+        let proxy = SandboxProxy::start(cfg).await.unwrap();
+        let response = http_via_proxy(proxy.port(), "http://github.com/").await;
+        assert!(response.contains("403"), "got: {}", response);
+    }
+
+    #[tokio::test]
+    async fn proxy_forwards_plain_http_to_allowed_host() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = listener.local_addr().unwrap().port();
+
+        // Minimal upstream: read the forwarded request, answer 204.
+        tokio::spawn(async move {
+            if let Ok((mut sock, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 1024];
+                let _ = sock.read(&mut buf).await;
+                let _ = sock
+                    .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                    .await;
+            }
+        });
+
+        let cfg = Arc::new(SandboxConfig {
+            enabled: true,
+            allowed_hosts: vec!["localhost".to_string()],
+            ..Default::default()
+        });
+        let proxy = SandboxProxy::start(cfg).await.unwrap();
+        let response = http_via_proxy(
+            proxy.port(),
+            &format!("http://localhost:{}/health", upstream_port),
+        )
+        .await;
+        assert!(response.contains("204"), "got: {}", response);
+    }
+
+    #[test]
+    fn http_target_defaults_port_80() {
+        assert_eq!(
+            http_target("http://example.com/a?b=1").as_deref(),
+            Some("example.com:80")
+        );
+        assert_eq!(
+            http_target("http://user@example.com:8080/").as_deref(),
+            Some("example.com:8080")
+        );
+        assert_eq!(http_target("/relative"), None);
+    }
 }
@@ -1,20 +1,27 @@
 use super::config::SandboxConfig;
+use crate::tools::patch::patch_paths;
 use crate::tools::tool::{Tool, ToolResult};
 use anyhow::Result;
 use async_trait::async_trait;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 /// Wraps any `Tool` with sandbox enforcement:
 ///
 /// - **`read`** / **`glob`** / **`grep`**: denied-read-path check on `path` arg
+///   (and on each of `grep`'s `paths`)
 /// - **`write`**: allowed-write-path check on `path` arg
+/// - **`apply_patch`**: allowed-write-path check on every file the patch names
 /// - **`bash`**: proxy env vars injected; on macOS also uses `sandbox-exec`
+/// - **`web_fetch`**: host of the `url` arg, and of every redirect it
+///   follows, checked against the network rules
 /// - All other tools: call passes through unchanged
 pub struct SandboxedTool<T> {
     inner: T,
     config: Arc<SandboxConfig>,
     /// Port of the running `SandboxProxy`. Only relevant for bash / web tools.
     proxy_port: u16,
+    /// Client for `web_fetch` that checks every redirect hop, built on first use.
+    web_client: OnceLock<reqwest::Client>,
 }
 
 impl<T: Tool> SandboxedTool<T> {
@@ -23,6 +30,7 @@ impl<T: Tool> SandboxedTool<T> {
             inner,
             config,
             proxy_port,
+            web_client: OnceLock::new(),
         }
     }
 }
@@ -41,11 +49,32 @@ impl<T: Tool> Tool for SandboxedTool<T> {
         self.inner.parameters()
     }
 
+    fn parameters_schema(&self) -> Option<serde_json::Value> {
+        self.inner.parameters_schema()
+    }
+
+    fn max_output_bytes(&self, default: usize) -> usize {
+        self.inner.max_output_bytes(default)
+    }
+
+    fn timeout_ms(&self, default: u64) -> u64 {
+        self.inner.timeout_ms(default)
+    }
+
+    fn is_parallel_safe(&self) -> bool {
+        self.inner.is_parallel_safe()
+    }
+
     async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
         match self.inner.name() {
             // ── read-like tools: guard the `path` arg ──────────────────────
             "read" | "glob" | "grep" => {
-                if let Some(path) = args["path"].as_str() {
+                let listed = args["paths"].as_array().into_iter().flatten();
+                let paths = args["path"]
+                    .as_str()
+                    .into_iter()
+                    .chain(listed.filter_map(|p| p.as_str()));
+                for path in paths {
                     if let Err(reason) = self.config.check_read_path(std::path::Path::new(path)) {
                         return Ok(ToolResult::err(reason));
                     }
@@ -63,9 +92,38 @@ impl<T: Tool> Tool for SandboxedTool<T> {
                 self.inner.call(args).await
             }
 
+            // ── apply_patch: guard every path named in the diff ─────────────
+            "apply_patch" => {
+                let patch = args["patch"].as_str().unwrap_or_default();
+                for path in patch_paths(patch) {
+                    if let Err(reason) = self.config.check_write_path(std::path::Path::new(&path)) {
+                        return Ok(ToolResult::err(reason));
+                    }
+                }
+                self.inner.call(args).await
+            }
+
             // ── bash: rewrite args to run via proxy (+ sandbox-exec on macOS)
             "bash" => self.call_bash(args).await,
 
+            // ── web_fetch: guard the host of the `url` arg ─────────────────
+            "web_fetch" => {
+                if let Some(url) = args["url"].as_str() {
+                    let host = reqwest::Url::parse(url)
+                        .ok()
+                        .and_then(|u| u.host_str().map(str::to_owned));
+                    let check = match host {
+                        Some(h) => self.config.check_domain(&h),
+                        None => Err(format!("sandbox: cannot determine host of {url}")),
+                    };
+                    if let Err(reason) = check {
+                        tracing::warn!("SandboxedTool blocking web_fetch: {}", reason);
+                        return Ok(ToolResult::err(reason));
+                    }
+                }
+                crate::tools::web_fetch::fetch_with_client(self.web_client()?, args).await
+            }
+
             // ── everything else passes through ──────────────────────────────
             _ => self.inner.call(args).await,
         }
@@ -73,17 +131,23 @@ impl<T: Tool> Tool for SandboxedTool<T> {
 }
 
 impl<T: Tool> SandboxedTool<T> {
+    fn web_client(&self) -> Result<&reqwest::Client> {
+        if let Some(client) = self.web_client.get() {
+            return Ok(client);
+        }
+        let client = crate::tools::web_fetch::sandboxed_client(Arc::clone(&self.config))?;
+        Ok(self.web_client.get_or_init(|| client))
+    }
+
     async fn call_bash(&self, args: serde_json::Value) -> Result<ToolResult> {
         let command = match args["command"].as_str() {
             Some(c) => c.to_string(),
             None => return self.inner.call(args).await,
         };
         let timeout_secs = args["timeout_secs"].as_u64().unwrap_or(30);
-        let proxy_addr = format!("http://127.0.0.1:{}", self.proxy_port);
-
         let output = tokio::time::timeout(
             std::time::Duration::from_secs(timeout_secs),
-            self.spawn_bash(&command, &proxy_addr),
+            self.spawn_bash(&command),
         )
         .await
         .map_err(|_| anyhow::anyhow!("Command timed out after {}s", timeout_secs))??;
@@ -107,43 +171,59 @@ impl<T: Tool> SandboxedTool<T> {
                 "(no output)".to_string()
             };
         }
-        Ok(ToolResult { content, is_error })
+        Ok(ToolResult {
+            content,
+            is_error,
+            images: Vec::new(),
+        })
     }
 
-    async fn spawn_bash(&self, command: &str, proxy_addr: &str) -> Result<std::process::Output> {
-        use tokio::process::Command;
-
-        #[cfg(target_os = "macos")]
-        {
-            let profile = super::profile::build_profile(&self.config, self.proxy_port)?;
-            let mut tmp = tempfile::NamedTempFile::new()?;
-            use std::io::Write as _;
-            tmp.write_all(profile.as_bytes())?;
-            let profile_path = tmp.path().to_path_buf();
-            let output = Command::new("sandbox-exec")
-                .arg("-f")
-                .arg(&profile_path)
-                .arg("bash")
-                .arg("-c")
-                .arg(command)
-                .env("http_proxy", proxy_addr)
-                .env("https_proxy", proxy_addr)
-                .output()
-                .await
-                .map_err(|e| anyhow::anyhow!("Failed to execute command: {}", e))?;
-            drop(tmp);
-            Ok(output)
-        }
+    async fn spawn_bash(&self, command: &str) -> Result<std::process::Output> {
+        let (mut cmd, _profile) = sandboxed_bash(&self.config, self.proxy_port, command)?;
+        cmd.output()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to execute command: {}", e))
+    }
+}
 
-        #[cfg(not(target_os = "macos"))]
-        Command::new("bash")
+/// A `bash -c command` that goes through the sandbox: its HTTP traffic is sent
+/// to the proxy on `proxy_port`, and on macOS it runs under `sandbox-exec`.
+/// The returned profile file must outlive the process.
+pub(crate) fn sandboxed_bash(
+    config: &SandboxConfig,
+    proxy_port: u16,
+    command: &str,
+) -> Result<(tokio::process::Command, Option<tempfile::NamedTempFile>)> {
+    use tokio::process::Command;
+
+    let proxy_addr = format!("http://127.0.0.1:{proxy_port}");
+
+    #[cfg(target_os = "macos")]
+    {
+        let profile = super::profile::build_profile(config, proxy_port)?;
+        let mut tmp = tempfile::NamedTempFile::new()?;
+        use std::io::Write as _;
+        tmp.write_all(profile.as_bytes())?;
+        let mut cmd = Command::new("sandbox-exec");
+        cmd.arg("-f")
+            .arg(tmp.path())
+            .arg("bash")
             .arg("-c")
             .arg(command)
-            .env("http_proxy", proxy_addr)
-            .env("https_proxy", proxy_addr)
-            .output()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to execute command: {}", e))
+            .env("http_proxy", &proxy_addr)
+            .env("https_proxy", &proxy_addr);
+        Ok((cmd, Some(tmp)))
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = config;
+        let mut cmd = Command::new("bash");
+        cmd.arg("-c")
+            .arg(command)
+            .env("http_proxy", &proxy_addr)
+            .env("https_proxy", &proxy_addr);
+        Ok((cmd, None))
     }
 }
 
@@ -176,7 +256,7 @@ mod tests {
             ..Default::default()
         });
         let (_proxy, port) = proxy_for(Arc::clone(&cfg)).await;
-        let tool = SandboxedTool::wrap(ReadTool, cfg, port);
+        let tool = SandboxedTool::wrap(ReadTool::default(), cfg, port);
 
         let result: ToolResult = tool
             .call(json!({ "path": secret_file.to_str().unwrap() }))
@@ -198,7 +278,7 @@ mod tests {
             ..Default::default()
         });
         let (_proxy, port) = proxy_for(Arc::clone(&cfg)).await;
-        let tool = SandboxedTool::wrap(ReadTool, cfg, port);
+        let tool = SandboxedTool::wrap(ReadTool::default(), cfg, port);
 
         let result: ToolResult = tool
             .call(json!({ "path": file.to_str().unwrap() }))
@@ -209,6 +289,28 @@ mod tests {
         assert_eq!(result.content, "hello");
     }
 
+    #[tokio::test]
+    async fn sandboxed_read_honours_line_range() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("data.txt");
+        std::fs::write(&file, "one\ntwo\nthree\n").unwrap();
+
+        let cfg = Arc::new(SandboxConfig {
+            enabled: true,
+            ..Default::default()
+        });
+        let (_proxy, port) = proxy_for(Arc::clone(&cfg)).await;
+        let tool = SandboxedTool::wrap(ReadTool::default(), cfg, port);
+
+        let result: ToolResult = tool
+            .call(json!({ "path": file.to_str().unwrap(), "offset": 2, "limit": 1 }))
+            .await
+            .unwrap();
+
+        assert!(!result.is_error);
+        assert_eq!(result.content, "lines 2-2 of 3\ntwo");
+    }
+
     #[tokio::test]
     async fn sandboxed_write_blocks_outside_allowlist() {
         let cfg = Arc::new(SandboxConfig {
@@ -217,7 +319,7 @@ mod tests {
             ..Default::default()
         });
         let (_proxy, port) = proxy_for(Arc::clone(&cfg)).await;
-        let tool = SandboxedTool::wrap(WriteTool, cfg, port);
+        let tool = SandboxedTool::wrap(WriteTool::default(), cfg, port);
 
         let result: ToolResult = tool
             .call(json!({ "path": "/etc/should_not_exist", "content": "bad" }))
@@ -228,6 +330,24 @@ mod tests {
         assert!(result.content.contains("sandbox"));
     }
 
+    #[tokio::test]
+    async fn sandboxed_apply_patch_blocks_any_path_outside_allowlist() {
+        let cfg = Arc::new(SandboxConfig {
+            enabled: true,
+            allowed_write_paths: vec![std::path::PathBuf::from("/tmp")],
+            ..Default::default()
+        });
+        let (_proxy, port) = proxy_for(Arc::clone(&cfg)).await;
+        let tool = SandboxedTool::wrap(crate::tools::patch::ApplyPatchTool::default(), cfg, port);
+
+        let patch = "--- /tmp/ok.txt\n+++ /tmp/ok.txt\n@@ -1 +1 @@\n-a\n+b\n\
+                     --- /dev/null\n+++ /etc/should_not_exist\n@@ -0,0 +1 @@\n+bad\n";
+        let result: ToolResult = tool.call(json!({ "patch": patch })).await.unwrap();
+
+        assert!(result.is_error);
+        assert!(result.content.contains("sandbox"));
+    }
+
     #[tokio::test]
     async fn sandboxed_write_allows_write_inside_cwd() {
         let tmp = tempfile::tempdir().unwrap();
@@ -239,7 +359,7 @@ mod tests {
             ..Default::default()
         });
         let (_proxy, port) = proxy_for(Arc::clone(&cfg)).await;
-        let tool = SandboxedTool::wrap(WriteTool, cfg, port);
+        let tool = SandboxedTool::wrap(WriteTool::default(), cfg, port);
 
         let target = tmp.path().join("output.txt");
         let result: ToolResult = tool
@@ -251,6 +371,25 @@ mod tests {
         assert!(!result.is_error, "{}", result.content);
     }
 
+    #[tokio::test]
+    async fn sandboxed_web_fetch_blocks_disallowed_host() {
+        let cfg = Arc::new(SandboxConfig {
+            enabled: true,
+            allowed_hosts: vec!["*.example.com".to_string()],
+            ..Default::default()
+        });
+        let (_proxy, port) = proxy_for(Arc::clone(&cfg)).await;
+        let tool = SandboxedTool::wrap(crate::tools::web_fetch::WebFetchTool, cfg, port);
+
+        let result: ToolResult = tool
+            .call(json!({ "url": "https://github.com/" }))
+            .await
+            .unwrap();
+
+        assert!(result.is_error);
+        assert!(result.content.contains("not in allowlist"));
+    }
+
     #[tokio::test]
     async fn unsandboxed_read_passes_through() {
         let tmp = tempfile::tempdir().unwrap();
@@ -258,7 +397,7 @@ mod tests {
         std::fs::write(&file, "world").unwrap();
 
         // sandbox disabled — use raw tool, no wrapper needed
-        let tool = ReadTool;
+        let tool = ReadTool::default();
         let result: ToolResult = tool
             .call(json!({ "path": file.to_str().unwrap() }))
             .await
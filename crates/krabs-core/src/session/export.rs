@@ -0,0 +1,261 @@
+use super::session::{Session, StoredMessage, StoredTokenUsage};
+use crate::agents::metrics::ToolStats;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Version of the [`PortableSession`] format. Bumped whenever a file written
+/// by an older krabs could no longer be imported as-is.
+pub const PORTABLE_VERSION: u32 = 1;
+
+/// One element of an exported transcript.
+///
+/// The JSON export is a flat array of these, ordered as they happened, so it
+/// can be read back with [`from_json`] without losing anything.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExportEntry {
+    Message(StoredMessage),
+    Usage(StoredTokenUsage),
+}
+
+/// Interleave messages and token-usage rows by timestamp. Usage rows are
+/// written at the end of a turn, so on a tie they sort after the messages.
+pub fn entries(messages: &[StoredMessage], usage: &[StoredTokenUsage]) -> Vec<ExportEntry> {
+    let mut out: Vec<ExportEntry> = Vec::with_capacity(messages.len() + usage.len());
+    let mut usage = usage.iter().peekable();
+    for msg in messages {
+        while let Some(u) = usage.next_if(|u| u.created_at < msg.created_at) {
+            out.push(ExportEntry::Usage(u.clone()));
+        }
+        out.push(ExportEntry::Message(msg.clone()));
+    }
+    out.extend(usage.cloned().map(ExportEntry::Usage));
+    out
+}
+
+/// Serialize a transcript as a pretty-printed JSON array of [`ExportEntry`].
+pub fn to_json(messages: &[StoredMessage], usage: &[StoredTokenUsage]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&entries(messages, usage))?)
+}
+
+/// Parse a transcript written by [`to_json`] back into messages and usage rows.
+pub fn from_json(json: &str) -> Result<(Vec<StoredMessage>, Vec<StoredTokenUsage>)> {
+    let parsed: Vec<ExportEntry> = serde_json::from_str(json)?;
+    let mut messages = Vec::new();
+    let mut usage = Vec::new();
+    for entry in parsed {
+        match entry {
+            ExportEntry::Message(m) => messages.push(m),
+            ExportEntry::Usage(u) => usage.push(u),
+        }
+    }
+    Ok((messages, usage))
+}
+
+/// A whole session as one self-contained file (`krabs export`), to be
+/// imported into another machine's store and resumed there.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortableSession {
+    /// [`PORTABLE_VERSION`] of the krabs that wrote the file.
+    pub version: u32,
+    pub id: String,
+    pub agent_id: String,
+    pub model: String,
+    pub provider: String,
+    pub created_at: i64,
+    /// Title, tags and the rest of the session's metadata.
+    #[serde(default)]
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+    /// Messages up to the resume point, interleaved with usage rows.
+    pub entries: Vec<ExportEntry>,
+    /// Where the last turn stood when the file was written; `None` when it
+    /// ended at a turn boundary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subturn: Option<PortableSubturn>,
+    #[serde(default)]
+    pub tool_stats: Vec<ToolStats>,
+}
+
+/// The sub-turn checkpoint a session was exported at: `turn` had run its
+/// tool calls up to `tool_idx`, the one with `call_id`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortableSubturn {
+    pub turn: usize,
+    pub tool_idx: usize,
+    pub call_id: String,
+}
+
+impl PortableSession {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parse a file written by [`PortableSession::to_json`], refusing one
+    /// from a newer or unknown format version.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        match value.get("version").and_then(serde_json::Value::as_u64) {
+            Some(v) if v == u64::from(PORTABLE_VERSION) => Ok(serde_json::from_value(value)?),
+            Some(v) => anyhow::bail!(
+                "session file has format version {v}; this krabs reads version {PORTABLE_VERSION}"
+            ),
+            None => anyhow::bail!("not a krabs session file: no format version"),
+        }
+    }
+}
+
+/// Render a transcript as Markdown. Tool calls are shown as JSON blocks and
+/// tool results as plain fenced blocks; usage rows become an italic footer
+/// after the turn they belong to.
+pub fn to_markdown(messages: &[StoredMessage], usage: &[StoredTokenUsage]) -> String {
+    let mut out = String::new();
+    if let Some(first) = messages.first() {
+        out.push_str(&format!("# Session {}\n", first.session_id));
+    }
+    for entry in entries(messages, usage) {
+        match entry {
+            ExportEntry::Message(msg) => push_message(&mut out, &msg),
+            ExportEntry::Usage(u) => out.push_str(&format!(
+                "\n_Turn {}{} — {} input / {} output tokens_\n",
+                u.turn,
+                u.context
+                    .as_deref()
+                    .map(|c| format!(" ({c})"))
+                    .unwrap_or_default(),
+                u.input_tokens,
+                u.output_tokens
+            )),
+        }
+    }
+    out
+}
+
+fn push_message(out: &mut String, msg: &StoredMessage) {
+    match msg.role.as_str() {
+        "user" => out.push_str(&format!("\n## User\n\n{}\n", msg.content)),
+        "developer" => out.push_str(&format!("\n## Developer\n\n{}\n", msg.content)),
+        "assistant" if msg.tool_args.is_some() => {
+            // Fall back to the raw JSON if the stored calls no longer decode.
+            let calls = Session::decode_tool_calls(msg).unwrap_or_default();
+            if calls.is_empty() {
+                out.push_str(&format!(
+                    "\n**Tool calls**\n\n```json\n{}\n```\n",
+                    msg.tool_args.as_deref().unwrap_or_default()
+                ));
+            }
+            for call in calls {
+                let args = serde_json::to_string_pretty(&call.args).unwrap_or_default();
+                out.push_str(&format!(
+                    "\n**Tool call** `{}`\n\n```json\n{args}\n```\n",
+                    call.name
+                ));
+            }
+        }
+        "assistant" => out.push_str(&format!("\n## Assistant\n\n{}\n", msg.content)),
+        "tool" => {
+            let fence = fence_for(&msg.content);
+            out.push_str(&format!(
+                "\n**Tool result** `{}`\n\n{fence}\n{}\n{fence}\n",
+                msg.tool_name.as_deref().unwrap_or("tool"),
+                msg.content
+            ));
+        }
+        other => out.push_str(&format!("\n## {other}\n\n{}\n", msg.content)),
+    }
+}
+
+/// A backtick fence longer than any run of backticks inside `content`.
+fn fence_for(content: &str) -> String {
+    let longest = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(id: i64, role: &str, content: &str, created_at: i64) -> StoredMessage {
+        StoredMessage {
+            id,
+            session_id: "s1".into(),
+            agent_id: "main".into(),
+            turn: 0,
+            role: role.into(),
+            content: content.into(),
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            created_at,
+        }
+    }
+
+    fn sample() -> (Vec<StoredMessage>, Vec<StoredTokenUsage>) {
+        let mut call = msg(2, "assistant", "", 10);
+        call.tool_args = Some(r#"[{"id":"c1","name":"read","args":{"path":"a.rs"}}]"#.into());
+        let mut result = msg(3, "tool", "fn main() {}", 11);
+        result.tool_call_id = Some("c1".into());
+        result.tool_name = Some("read".into());
+        let messages = vec![
+            msg(1, "user", "show a.rs", 10),
+            call,
+            result,
+            msg(4, "assistant", "It's empty.", 12),
+        ];
+        let usage = vec![StoredTokenUsage {
+            id: 1,
+            session_id: "s1".into(),
+            agent_id: "main".into(),
+            turn: 0,
+            input_tokens: 120,
+            output_tokens: 30,
+            created_at: 12,
+            context: None,
+        }];
+        (messages, usage)
+    }
+
+    #[test]
+    fn json_roundtrips() {
+        let (messages, usage) = sample();
+        let json = to_json(&messages, &usage).unwrap();
+        let (back_msgs, back_usage) = from_json(&json).unwrap();
+        assert_eq!(
+            serde_json::to_value(&back_msgs).unwrap(),
+            serde_json::to_value(&messages).unwrap()
+        );
+        assert_eq!(back_usage.len(), 1);
+        assert_eq!(back_usage[0].input_tokens, 120);
+    }
+
+    #[test]
+    fn usage_sorts_after_messages_of_same_timestamp() {
+        let (messages, usage) = sample();
+        let all = entries(&messages, &usage);
+        assert!(matches!(all.last(), Some(ExportEntry::Usage(_))));
+    }
+
+    #[test]
+    fn markdown_includes_calls_results_and_usage() {
+        let (messages, usage) = sample();
+        let md = to_markdown(&messages, &usage);
+        assert!(md.starts_with("# Session s1"));
+        assert!(md.contains("## User\n\nshow a.rs"));
+        assert!(md.contains("**Tool call** `read`"));
+        assert!(md.contains("\"path\": \"a.rs\""));
+        assert!(md.contains("**Tool result** `read`\n\n```\nfn main() {}\n```"));
+        assert!(md.contains("120 input / 30 output tokens"));
+    }
+
+    #[test]
+    fn fence_outgrows_inner_backticks() {
+        assert_eq!(fence_for("plain"), "```");
+        assert_eq!(fence_for("has ```` inside"), "`````");
+    }
+
+    #[test]
+    fn portable_files_of_other_versions_are_refused() {
+        let err = PortableSession::from_json(r#"{"version": 99}"#).unwrap_err();
+        assert!(err.to_string().contains("version 99"));
+        assert!(PortableSession::from_json("[]").is_err());
+    }
+}
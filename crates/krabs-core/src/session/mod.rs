@@ -1,3 +1,8 @@
+pub mod export;
+pub mod replay;
 #[allow(clippy::module_inception)]
 pub mod session;
+pub mod snapshot;
+pub mod title;
+pub use replay::{recorded_tool_calls, RecordedToolCall, ReplayOutcome, Replayer};
 pub use session::{ResumeState, Session, SessionStore, SubturnResume};
@@ -0,0 +1,190 @@
+//! Step through the tool calls a stored session made, without the model.
+//!
+//! Side-effect-free tools (those that are [parallel-safe](crate::Tool::is_parallel_safe))
+//! can be called again with the recorded arguments and their output compared
+//! with the stored result, to find what has changed since: an edited file, a
+//! new match for a search.
+
+use super::session::{Session, StoredMessage};
+use crate::config::config::KrabsConfig;
+use crate::providers::provider::ToolCall;
+use crate::tools::registry::ToolRegistry;
+use crate::tools::tool::call_with_timeout;
+use anyhow::Result;
+
+/// One tool call from a stored session, with what it returned then.
+#[derive(Debug, Clone)]
+pub struct RecordedToolCall {
+    pub turn: usize,
+    pub call: ToolCall,
+    /// The stored result, as the model saw it. `None` when the session
+    /// stopped before the call finished.
+    pub result: Option<String>,
+}
+
+/// How a recorded call compares with calling the tool again now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayOutcome {
+    /// The tool returned the stored result again.
+    Same,
+    /// The tool now returns `current` instead.
+    Diverged { current: String },
+    /// Not called again; the reason says why.
+    Skipped(&'static str),
+}
+
+/// The tool calls in `messages`, in order, paired with their results.
+pub fn recorded_tool_calls(messages: &[StoredMessage]) -> Result<Vec<RecordedToolCall>> {
+    let mut calls: Vec<RecordedToolCall> = Vec::new();
+    for msg in messages {
+        match msg.role.as_str() {
+            "assistant" if msg.tool_args.is_some() => {
+                calls.extend(Session::decode_tool_calls(msg)?.into_iter().map(|call| {
+                    RecordedToolCall {
+                        turn: msg.turn,
+                        call,
+                        result: None,
+                    }
+                }));
+            }
+            "tool" => {
+                let id = msg.tool_call_id.as_deref().unwrap_or("");
+                if let Some(rec) = calls
+                    .iter_mut()
+                    .rev()
+                    .find(|rec| rec.call.id == id && rec.result.is_none())
+                {
+                    rec.result = Some(msg.content.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(calls)
+}
+
+impl Session {
+    /// Every tool call this session made, with its stored result.
+    pub async fn recorded_tool_calls(&self) -> Result<Vec<RecordedToolCall>> {
+        recorded_tool_calls(&self.messages().await?)
+    }
+}
+
+/// Calls side-effect-free tools again and compares their output with what
+/// was stored, truncated the way the agent truncated it.
+pub struct Replayer<'a> {
+    registry: &'a ToolRegistry,
+    max_tool_output_bytes: usize,
+    tool_timeout_ms: u64,
+}
+
+impl<'a> Replayer<'a> {
+    pub fn new(config: &KrabsConfig, registry: &'a ToolRegistry) -> Self {
+        Self {
+            registry,
+            max_tool_output_bytes: config.max_tool_output_bytes,
+            tool_timeout_ms: config.tool_timeout_ms,
+        }
+    }
+
+    pub async fn replay(&self, recorded: &RecordedToolCall) -> ReplayOutcome {
+        let Some(stored) = &recorded.result else {
+            return ReplayOutcome::Skipped("no stored result");
+        };
+        let Some(tool) = self.registry.get(&recorded.call.name) else {
+            return ReplayOutcome::Skipped("tool not available");
+        };
+        if !tool.is_parallel_safe() {
+            return ReplayOutcome::Skipped("has side effects");
+        }
+        let max_bytes = tool.max_output_bytes(self.max_tool_output_bytes);
+        let timeout_ms = tool.timeout_ms(self.tool_timeout_ms);
+        let current =
+            match call_with_timeout(tool.as_ref(), recorded.call.args.clone(), timeout_ms).await {
+                Ok(result) => result.truncated(max_bytes).content,
+                Err(e) => e.to_string(),
+            };
+        // A `PostToolUse` hook may have appended context after the output.
+        let same = *stored == current
+            || stored
+                .strip_prefix(current.as_str())
+                .is_some_and(|rest| rest.starts_with('\n'));
+        if same {
+            ReplayOutcome::Same
+        } else {
+            ReplayOutcome::Diverged { current }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::provider::Message;
+    use crate::tools::read::ReadTool;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn read_only_calls_are_rerun_and_compared() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("notes.txt");
+        std::fs::write(&file, "before\n").unwrap();
+        let path = file.display().to_string();
+
+        let mut registry = ToolRegistry::new();
+        registry.register(Arc::new(ReadTool::default()));
+        let stored_read = registry
+            .get("read")
+            .unwrap()
+            .call(json!({ "path": path }))
+            .await
+            .unwrap()
+            .content;
+
+        let store = crate::SessionStore::open(&dir.path().join("k.db"))
+            .await
+            .unwrap();
+        let session = store.new_session("a", "m", "p").await.unwrap();
+        let calls = vec![
+            ToolCall {
+                id: "c1".into(),
+                name: "read".into(),
+                args: json!({ "path": path }),
+                thought_signature: None,
+            },
+            ToolCall {
+                id: "c2".into(),
+                name: "bash".into(),
+                args: json!({ "command": "echo hi > notes.txt" }),
+                thought_signature: None,
+            },
+        ];
+        for msg in [
+            Message::user("look"),
+            Message::assistant_tool_calls(calls),
+            Message::tool_result(&stored_read, "c1", "read"),
+            Message::tool_result("", "c2", "bash"),
+        ] {
+            session.persist_message(&msg, 1).await.unwrap();
+        }
+
+        let recorded = session.recorded_tool_calls().await.unwrap();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].result.as_deref(), Some(stored_read.as_str()));
+
+        let config = KrabsConfig::default();
+        let replayer = Replayer::new(&config, &registry);
+        assert_eq!(replayer.replay(&recorded[0]).await, ReplayOutcome::Same);
+        assert_eq!(
+            replayer.replay(&recorded[1]).await,
+            ReplayOutcome::Skipped("tool not available")
+        );
+
+        std::fs::write(&file, "after\n").unwrap();
+        let ReplayOutcome::Diverged { current } = replayer.replay(&recorded[0]).await else {
+            panic!("an edited file should diverge");
+        };
+        assert!(current.contains("after"));
+    }
+}
@@ -1,6 +1,13 @@
+use super::export::{self, ExportEntry, PortableSession, PortableSubturn, PORTABLE_VERSION};
+use super::title::preview_title;
+use crate::agents::compact::{summary_message, Compaction};
+use crate::agents::metrics::ToolStats;
+use crate::config::config::SqliteConfig;
+use crate::config::KrabsConfig;
 use crate::providers::provider::{Message, Role, ToolCall};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous};
 use sqlx::{Row, SqlitePool};
 use std::path::Path;
 #[cfg(test)]
@@ -48,7 +55,9 @@ CREATE TABLE IF NOT EXISTS token_usage (
     turn          INTEGER NOT NULL,
     input_tokens  INTEGER NOT NULL,
     output_tokens INTEGER NOT NULL,
-    created_at    INTEGER NOT NULL
+    created_at    INTEGER NOT NULL,
+    context       TEXT,
+    after_msg_id  INTEGER
 );
 
 CREATE TABLE IF NOT EXISTS errors (
@@ -72,6 +81,35 @@ CREATE TABLE IF NOT EXISTS checkpoints (
     subturn_call_id    TEXT,
     created_at         INTEGER NOT NULL
 );
+
+CREATE TABLE IF NOT EXISTS file_snapshots (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id   TEXT    NOT NULL REFERENCES sessions(id),
+    turn         INTEGER NOT NULL,
+    tool_call_id TEXT    NOT NULL,
+    path         TEXT    NOT NULL,
+    content      BLOB,
+    size         INTEGER NOT NULL,
+    created_at   INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS tool_approvals (
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT,
+    rule       TEXT    NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS tool_stats (
+    session_id TEXT    NOT NULL REFERENCES sessions(id),
+    tool_name  TEXT    NOT NULL,
+    calls      INTEGER NOT NULL,
+    errors     INTEGER NOT NULL,
+    retries    INTEGER NOT NULL,
+    total_ms   INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (session_id, tool_name)
+);
 "#;
 
 // ── Types ─────────────────────────────────────────────────────────────────────
@@ -103,6 +141,17 @@ pub struct StoredError {
     pub created_at: i64,
 }
 
+/// How often one kind of error (`context`) was recorded in one session
+/// (returned by `SessionStore::error_counts`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorCount {
+    pub session_id: String,
+    pub context: String,
+    pub count: usize,
+    /// `created_at` of the most recent of these errors.
+    pub last_at: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoredTokenUsage {
     pub id: i64,
@@ -112,6 +161,22 @@ pub struct StoredTokenUsage {
     pub input_tokens: u32,
     pub output_tokens: u32,
     pub created_at: i64,
+    /// Where the tokens went when it wasn't the session's own agent, e.g.
+    /// `delegate:planner` for a sub-agent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+}
+
+/// A file's content from before a `write` or `apply_patch` call changed it.
+#[derive(Debug, Clone)]
+pub struct StoredFileSnapshot {
+    pub id: i64,
+    pub turn: usize,
+    pub tool_call_id: String,
+    pub path: String,
+    /// `None` when the call created the file.
+    pub content: Option<Vec<u8>>,
+    pub created_at: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -138,6 +203,69 @@ pub struct SessionSummary {
     pub model: String,
     pub provider: String,
     pub created_at: i64,
+    /// Labels added with `Session::add_tag`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Title from `Session::set_title`, else a preview of the first user
+    /// message. `None` for a session with no messages yet.
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// A persisted "always allow" decision for a tool name or glob pattern.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolApproval {
+    /// Exact tool name or glob pattern, e.g. `bash` or `mcp__github__*`.
+    pub rule: String,
+    /// The session it applies to; `None` = every session.
+    pub session_id: Option<String>,
+    pub created_at: i64,
+}
+
+impl ToolApproval {
+    pub fn is_global(&self) -> bool {
+        self.session_id.is_none()
+    }
+
+    /// `true` when this approval covers `tool_name`.
+    pub fn matches(&self, tool_name: &str) -> bool {
+        crate::permissions::tool_rule_matches(&self.rule, tool_name)
+    }
+}
+
+/// Key in the session metadata object holding the session's tags.
+const TAGS_KEY: &str = "tags";
+const TITLE_KEY: &str = "title";
+
+/// Parse the `sessions.metadata` column. NULL (sessions created before metadata
+/// was used) and anything that isn't a JSON object read as an empty object.
+fn parse_metadata(raw: Option<&str>) -> serde_json::Map<String, serde_json::Value> {
+    raw.and_then(|s| serde_json::from_str::<serde_json::Value>(s).ok())
+        .and_then(|v| match v {
+            serde_json::Value::Object(map) => Some(map),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn title_of(metadata: &serde_json::Map<String, serde_json::Value>) -> Option<String> {
+    metadata
+        .get(TITLE_KEY)
+        .and_then(|v| v.as_str())
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+}
+
+fn tags_of(metadata: &serde_json::Map<String, serde_json::Value>) -> Vec<String> {
+    metadata
+        .get(TAGS_KEY)
+        .and_then(|v| v.as_array())
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|t| t.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
 // ── Resume helpers ────────────────────────────────────────────────────────────
@@ -166,21 +294,54 @@ pub struct ResumeState {
     pub messages: Vec<crate::providers::provider::Message>,
     /// Present only when resuming from a sub-turn checkpoint.
     pub subturn_resume: Option<SubturnResume>,
+    /// Assistant text from a stream that was interrupted and never completed.
+    /// Not part of `messages`; a provider that supports continuation can be
+    /// handed it as a trailing assistant message.
+    pub partial: Option<String>,
 }
 
 // ── SessionStore ──────────────────────────────────────────────────────────────
 
+/// Open a pool on `db_path`, creating the file and its directory if needed,
+/// with `sqlite`'s pragmas set on every connection.
+pub(crate) async fn connect(db_path: &Path, sqlite: &SqliteConfig) -> Result<SqlitePool> {
+    if let Some(parent) = db_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let journal_mode: SqliteJournalMode = sqlite
+        .journal_mode
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid sqlite.journal_mode '{}'", sqlite.journal_mode))?;
+    let synchronous: SqliteSynchronous = sqlite
+        .synchronous
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid sqlite.synchronous '{}'", sqlite.synchronous))?;
+    let options = SqliteConnectOptions::new()
+        .filename(db_path)
+        .create_if_missing(true)
+        .journal_mode(journal_mode)
+        .busy_timeout(std::time::Duration::from_millis(sqlite.busy_timeout_ms))
+        .synchronous(synchronous);
+    Ok(SqlitePool::connect_with(options).await?)
+}
+
 pub struct SessionStore {
     pool: SqlitePool,
 }
 
 impl SessionStore {
+    /// Open the store at `db_path` with the default [`SqliteConfig`].
     pub async fn open(db_path: &Path) -> Result<Self> {
-        if let Some(parent) = db_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-        let url = format!("sqlite://{}?mode=rwc", db_path.display());
-        let pool = SqlitePool::connect(&url).await?;
+        Self::open_with(db_path, &SqliteConfig::default()).await
+    }
+
+    /// Open the store at `config.db_path` with `config.sqlite`.
+    pub async fn from_config(config: &KrabsConfig) -> Result<Self> {
+        Self::open_with(&config.db_path, &config.sqlite).await
+    }
+
+    pub async fn open_with(db_path: &Path, sqlite: &SqliteConfig) -> Result<Self> {
+        let pool = connect(db_path, sqlite).await?;
         sqlx::query(MIGRATE).execute(&pool).await?;
         // Best-effort migrations for existing DBs that pre-date certain columns.
         // SQLite returns an error if the column already exists; we swallow it.
@@ -193,6 +354,12 @@ impl SessionStore {
         let _ = sqlx::query("ALTER TABLE checkpoints ADD COLUMN subturn_call_id TEXT")
             .execute(&pool)
             .await;
+        let _ = sqlx::query("ALTER TABLE token_usage ADD COLUMN context TEXT")
+            .execute(&pool)
+            .await;
+        let _ = sqlx::query("ALTER TABLE token_usage ADD COLUMN after_msg_id INTEGER")
+            .execute(&pool)
+            .await;
         Ok(Self { pool })
     }
 
@@ -248,28 +415,147 @@ impl SessionStore {
         }))
     }
 
-    /// List all sessions ordered by creation time (newest first).
-    pub async fn list_sessions(&self) -> Result<Vec<SessionSummary>> {
-        let rows =
-            sqlx::query("SELECT id, agent_id, model, provider, created_at FROM sessions ORDER BY created_at DESC")
-                .fetch_all(&self.pool)
-                .await?;
+    /// List sessions ordered by creation time (newest first), optionally only
+    /// those tagged `tag`.
+    pub async fn list_sessions(&self, tag: Option<&str>) -> Result<Vec<SessionSummary>> {
+        let rows = sqlx::query(
+            "SELECT s.id, s.agent_id, s.model, s.provider, s.created_at, s.metadata,
+                    (SELECT m.content FROM messages m
+                     WHERE m.session_id = s.id AND m.role = 'user'
+                     ORDER BY m.id LIMIT 1) AS first_user
+             FROM sessions s ORDER BY s.created_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
 
         let mut result = Vec::with_capacity(rows.len());
         for row in rows {
+            let metadata = parse_metadata(row.try_get::<Option<String>, _>("metadata")?.as_deref());
+            let tags = tags_of(&metadata);
+            if tag.is_some_and(|t| !tags.iter().any(|have| have == t)) {
+                continue;
+            }
+            let title = match title_of(&metadata) {
+                Some(t) => Some(t),
+                None => row
+                    .try_get::<Option<String>, _>("first_user")?
+                    .map(|m| preview_title(&m)),
+            };
             result.push(SessionSummary {
                 id: row.try_get("id")?,
                 agent_id: row.try_get("agent_id")?,
                 model: row.try_get("model")?,
                 provider: row.try_get("provider")?,
                 created_at: row.try_get("created_at")?,
+                tags,
+                title,
             });
         }
         Ok(result)
     }
 
-    /// Delete a session and all related data (messages, token usage, errors, checkpoints).
+    /// Error counts across every session, grouped by session and context,
+    /// most recently failing first.
+    pub async fn error_counts(&self) -> Result<Vec<ErrorCount>> {
+        let rows = sqlx::query(
+            "SELECT session_id, context, COUNT(*) AS cnt, MAX(created_at) AS last_at \
+             FROM errors GROUP BY session_id, context ORDER BY last_at DESC, session_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|r| {
+                Ok(ErrorCount {
+                    session_id: r.try_get("session_id")?,
+                    context: r.try_get("context")?,
+                    count: r.try_get::<i64, _>("cnt")? as usize,
+                    last_at: r.try_get("last_at")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Tool stats summed over every session, most-called tool first.
+    pub async fn tool_stats_all(&self) -> Result<Vec<ToolStats>> {
+        let rows = sqlx::query(
+            "SELECT tool_name, SUM(calls) AS calls, SUM(errors) AS errors, \
+                    SUM(retries) AS retries, SUM(total_ms) AS total_ms \
+             FROM tool_stats GROUP BY tool_name ORDER BY calls DESC, tool_name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(tool_stats_from_row).collect()
+    }
+
+    // ── Tool approvals ────────────────────────────────────────────────────────
+
+    /// Persist an "always allow" for `rule` in `session_id`, or in every
+    /// session when `None`. Approving the same rule twice is a no-op.
+    pub async fn approve_tool(&self, rule: &str, session_id: Option<&str>) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO tool_approvals (session_id, rule, created_at) \
+             SELECT ?, ?, ? WHERE NOT EXISTS \
+             (SELECT 1 FROM tool_approvals WHERE session_id IS ? AND rule = ?)",
+        )
+        .bind(session_id)
+        .bind(rule)
+        .bind(now_ts())
+        .bind(session_id)
+        .bind(rule)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Remove `rule` from the global approvals and from `session_id`'s.
+    /// Returns how many approvals were removed.
+    pub async fn revoke_tool_approval(&self, rule: &str, session_id: Option<&str>) -> Result<u64> {
+        let removed = sqlx::query(
+            "DELETE FROM tool_approvals WHERE rule = ? AND (session_id IS NULL OR session_id = ?)",
+        )
+        .bind(rule)
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(removed)
+    }
+
+    /// Global approvals plus those for `session_id`, oldest first.
+    pub async fn tool_approvals(&self, session_id: Option<&str>) -> Result<Vec<ToolApproval>> {
+        let rows = sqlx::query(
+            "SELECT session_id, rule, created_at FROM tool_approvals \
+             WHERE session_id IS NULL OR session_id = ? ORDER BY id ASC",
+        )
+        .bind(session_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|r| {
+                Ok(ToolApproval {
+                    rule: r.try_get("rule")?,
+                    session_id: r.try_get("session_id")?,
+                    created_at: r.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Delete a session and all related data (messages, token usage, errors,
+    /// checkpoints, file snapshots, tool stats).
     pub async fn delete_session(&self, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM tool_approvals WHERE session_id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM tool_stats WHERE session_id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM file_snapshots WHERE session_id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
         sqlx::query("DELETE FROM checkpoints WHERE session_id = ?")
             .bind(id)
             .execute(&self.pool)
@@ -293,6 +579,123 @@ impl SessionStore {
         Ok(())
     }
 
+    /// Insert a session read from a [`PortableSession`] file (`krabs
+    /// import`), keeping its id unless this store already has a session by
+    /// that id, in which case it gets a new one. A checkpoint is written at
+    /// its last message so it can be resumed straight away.
+    pub async fn import_session(&self, portable: &PortableSession) -> Result<Arc<Session>> {
+        let taken = sqlx::query("SELECT 1 FROM sessions WHERE id = ?")
+            .bind(&portable.id)
+            .fetch_optional(&self.pool)
+            .await?
+            .is_some();
+        let id = if taken {
+            Uuid::new_v4().to_string()
+        } else {
+            portable.id.clone()
+        };
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO sessions (id, agent_id, model, provider, created_at, metadata) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(&portable.agent_id)
+        .bind(&portable.model)
+        .bind(&portable.provider)
+        .bind(portable.created_at)
+        .bind(serde_json::to_string(&portable.metadata)?)
+        .execute(&mut *tx)
+        .await?;
+
+        // New id and turn of the last message, where the checkpoint goes.
+        let mut last = None;
+        for entry in &portable.entries {
+            match entry {
+                ExportEntry::Message(m) => {
+                    let new_id = sqlx::query(
+                        "INSERT INTO messages \
+                         (session_id, agent_id, turn, role, content, tool_call_id, tool_name, tool_args, created_at) \
+                         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    )
+                    .bind(&id)
+                    .bind(&m.agent_id)
+                    .bind(m.turn as i64)
+                    .bind(&m.role)
+                    .bind(&m.content)
+                    .bind(&m.tool_call_id)
+                    .bind(&m.tool_name)
+                    .bind(&m.tool_args)
+                    .bind(m.created_at)
+                    .execute(&mut *tx)
+                    .await?
+                    .last_insert_rowid();
+                    last = Some((new_id, m.turn));
+                }
+                ExportEntry::Usage(u) => {
+                    sqlx::query(
+                        "INSERT INTO token_usage \
+                         (session_id, agent_id, turn, input_tokens, output_tokens, created_at, context, after_msg_id) \
+                         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                    )
+                    .bind(&id)
+                    .bind(&u.agent_id)
+                    .bind(u.turn as i64)
+                    .bind(u.input_tokens as i64)
+                    .bind(u.output_tokens as i64)
+                    .bind(u.created_at)
+                    .bind(&u.context)
+                    .bind(last.map(|(msg_id, _)| msg_id))
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+
+        if let Some((last_msg_id, turn)) = last {
+            let subturn = portable.subturn.as_ref();
+            sqlx::query(
+                "INSERT INTO checkpoints \
+                 (session_id, agent_id, turn, last_msg_id, subturn_tool_idx, subturn_call_id, created_at) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&id)
+            .bind(&portable.agent_id)
+            .bind(subturn.map_or(turn, |s| s.turn) as i64)
+            .bind(last_msg_id)
+            .bind(subturn.map(|s| s.tool_idx as i64))
+            .bind(subturn.map(|s| s.call_id.as_str()))
+            .bind(now_ts())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for stats in &portable.tool_stats {
+            sqlx::query(
+                "INSERT INTO tool_stats \
+                 (session_id, tool_name, calls, errors, retries, total_ms, updated_at) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&id)
+            .bind(&stats.tool_name)
+            .bind(stats.calls as i64)
+            .bind(stats.errors as i64)
+            .bind(stats.retries as i64)
+            .bind(stats.total_ms as i64)
+            .bind(now_ts())
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(Arc::new(Session {
+            id,
+            agent_id: portable.agent_id.clone(),
+            pool: self.pool.clone(),
+        }))
+    }
+
     /// Count messages in a session.
     pub async fn session_message_count(&self, id: &str) -> Result<usize> {
         let row = sqlx::query("SELECT COUNT(*) as cnt FROM messages WHERE session_id = ?")
@@ -313,6 +716,79 @@ pub struct Session {
 }
 
 impl Session {
+    // ── Metadata ──────────────────────────────────────────────────────────────
+
+    /// The session's metadata object. Empty when nothing has been set.
+    pub async fn metadata(&self) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let raw: Option<String> = sqlx::query("SELECT metadata FROM sessions WHERE id = ?")
+            .bind(&self.id)
+            .fetch_one(&self.pool)
+            .await?
+            .try_get("metadata")?;
+        Ok(parse_metadata(raw.as_deref()))
+    }
+
+    /// Set `key` in the session's metadata object, keeping every other key.
+    pub async fn set_metadata(&self, key: &str, value: serde_json::Value) -> Result<()> {
+        self.update_metadata(|m| {
+            m.insert(key.to_string(), value);
+        })
+        .await
+    }
+
+    /// The title set with [`Session::set_title`], if any.
+    pub async fn title(&self) -> Result<Option<String>> {
+        Ok(title_of(&self.metadata().await?))
+    }
+
+    /// Name the session, shown by `/sessions` in place of its first message.
+    pub async fn set_title(&self, title: &str) -> Result<()> {
+        self.set_metadata(TITLE_KEY, serde_json::Value::from(title))
+            .await
+    }
+
+    /// Labels attached to this session, in the order they were added.
+    pub async fn tags(&self) -> Result<Vec<String>> {
+        Ok(tags_of(&self.metadata().await?))
+    }
+
+    /// Tag the session with `tag`. Returns `false` if it already had it.
+    pub async fn add_tag(&self, tag: &str) -> Result<bool> {
+        let mut added = false;
+        self.update_metadata(|m| {
+            let mut tags = tags_of(m);
+            if !tags.iter().any(|t| t == tag) {
+                tags.push(tag.to_string());
+                m.insert(TAGS_KEY.to_string(), serde_json::json!(tags));
+                added = true;
+            }
+        })
+        .await?;
+        Ok(added)
+    }
+
+    /// Read-modify-write the metadata column inside one transaction.
+    async fn update_metadata(
+        &self,
+        f: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>),
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let raw: Option<String> = sqlx::query("SELECT metadata FROM sessions WHERE id = ?")
+            .bind(&self.id)
+            .fetch_one(&mut *tx)
+            .await?
+            .try_get("metadata")?;
+        let mut metadata = parse_metadata(raw.as_deref());
+        f(&mut metadata);
+        sqlx::query("UPDATE sessions SET metadata = ? WHERE id = ?")
+            .bind(serde_json::Value::Object(metadata).to_string())
+            .bind(&self.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
     // ── Persistence ───────────────────────────────────────────────────────────
 
     /// Persist a message from the agent loop.
@@ -322,6 +798,7 @@ impl Session {
     pub async fn persist_message(&self, message: &Message, turn: usize) -> Result<()> {
         let role = match message.role {
             Role::System => "system",
+            Role::Developer => "developer",
             Role::User => "user",
             Role::Assistant => "assistant",
             Role::Tool => "tool",
@@ -352,16 +829,117 @@ impl Session {
         Ok(())
     }
 
+    /// Persist a compaction summary (role `summary`) and checkpoint right
+    /// after it. On resume, history before the latest summary is skipped.
+    pub async fn persist_summary(&self, summary: &Message, turn: usize) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO messages \
+             (session_id, agent_id, turn, role, content, tool_call_id, tool_name, tool_args, created_at) \
+             VALUES (?, ?, ?, 'summary', ?, NULL, NULL, NULL, ?)",
+        )
+        .bind(&self.id)
+        .bind(&self.agent_id)
+        .bind(turn as i64)
+        .bind(&summary.content)
+        .bind(now_ts())
+        .execute(&self.pool)
+        .await?;
+        self.write_checkpoint(turn).await
+    }
+
+    /// Persist a [`Compaction`]: its summary, then the messages it carries over
+    /// verbatim, so a resume starts from the same history. Checkpoints after.
+    pub async fn persist_compaction(&self, compaction: &Compaction, turn: usize) -> Result<()> {
+        self.persist_summary(&summary_message(&compaction.summary), turn)
+            .await?;
+        let carried = compaction.carried_messages();
+        if carried.is_empty() {
+            return Ok(());
+        }
+        for message in carried {
+            self.persist_message(message, turn).await?;
+        }
+        self.write_checkpoint(turn).await
+    }
+
+    /// Persist the text an interrupted stream produced before it broke
+    /// (role `partial`). Replaced on each retry; cleared once a retry succeeds,
+    /// so a surviving row means the turn never completed.
+    pub async fn persist_partial(&self, content: &str, turn: usize) -> Result<()> {
+        self.clear_partials().await?;
+        sqlx::query(
+            "INSERT INTO messages \
+             (session_id, agent_id, turn, role, content, tool_call_id, tool_name, tool_args, created_at) \
+             VALUES (?, ?, ?, 'partial', ?, NULL, NULL, NULL, ?)",
+        )
+        .bind(&self.id)
+        .bind(&self.agent_id)
+        .bind(turn as i64)
+        .bind(content)
+        .bind(now_ts())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Delete every `partial` row of this session.
+    pub async fn clear_partials(&self) -> Result<()> {
+        sqlx::query("DELETE FROM messages WHERE session_id = ? AND role = 'partial'")
+            .bind(&self.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Content of the latest `partial` row with id greater than `after_id`.
+    pub async fn latest_partial(&self, after_id: i64) -> Result<Option<String>> {
+        let row = sqlx::query(
+            "SELECT content FROM messages \
+             WHERE session_id = ? AND role = 'partial' AND id > ? \
+             ORDER BY id DESC LIMIT 1",
+        )
+        .bind(&self.id)
+        .bind(after_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|r| r.try_get::<String, _>("content")).transpose()?)
+    }
+
     pub async fn persist_token_usage(
         &self,
         turn: usize,
         input_tokens: u32,
         output_tokens: u32,
     ) -> Result<()> {
+        self.insert_token_usage(turn, None, input_tokens, output_tokens)
+            .await
+    }
+
+    /// Record tokens spent on this session's behalf elsewhere, labelled with
+    /// `context` (e.g. `delegate:planner` for a sub-agent).
+    pub async fn persist_token_usage_for(
+        &self,
+        turn: usize,
+        context: &str,
+        input_tokens: u32,
+        output_tokens: u32,
+    ) -> Result<()> {
+        self.insert_token_usage(turn, Some(context), input_tokens, output_tokens)
+            .await
+    }
+
+    async fn insert_token_usage(
+        &self,
+        turn: usize,
+        context: Option<&str>,
+        input_tokens: u32,
+        output_tokens: u32,
+    ) -> Result<()> {
+        // `after_msg_id` places the row among the messages, for `branch_from`.
         sqlx::query(
             "INSERT INTO token_usage \
-             (session_id, agent_id, turn, input_tokens, output_tokens, created_at) \
-             VALUES (?, ?, ?, ?, ?, ?)",
+             (session_id, agent_id, turn, input_tokens, output_tokens, created_at, context, after_msg_id) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, (SELECT MAX(id) FROM messages WHERE session_id = ?))",
         )
         .bind(&self.id)
         .bind(&self.agent_id)
@@ -369,6 +947,8 @@ impl Session {
         .bind(input_tokens as i64)
         .bind(output_tokens as i64)
         .bind(now_ts())
+        .bind(context)
+        .bind(&self.id)
         .execute(&self.pool)
         .await?;
 
@@ -399,6 +979,123 @@ impl Session {
         Ok(())
     }
 
+    /// Add one finished call of `tool_name` to this session's tool stats.
+    pub async fn record_tool_call(
+        &self,
+        tool_name: &str,
+        is_error: bool,
+        retries: usize,
+        elapsed_ms: u64,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO tool_stats \
+             (session_id, tool_name, calls, errors, retries, total_ms, updated_at) \
+             VALUES (?, ?, 1, ?, ?, ?, ?) \
+             ON CONFLICT (session_id, tool_name) DO UPDATE SET \
+             calls = calls + 1, errors = errors + excluded.errors, \
+             retries = retries + excluded.retries, total_ms = total_ms + excluded.total_ms, \
+             updated_at = excluded.updated_at",
+        )
+        .bind(&self.id)
+        .bind(tool_name)
+        .bind(i64::from(is_error))
+        .bind(retries as i64)
+        .bind(elapsed_ms as i64)
+        .bind(now_ts())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // ── File snapshots ────────────────────────────────────────────────────────
+
+    /// Record `path`'s content from before tool call `tool_call_id` changed
+    /// it; `None` when the call is creating the file.
+    pub async fn persist_file_snapshot(
+        &self,
+        turn: usize,
+        tool_call_id: &str,
+        path: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO file_snapshots \
+             (session_id, turn, tool_call_id, path, content, size, created_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&self.id)
+        .bind(turn as i64)
+        .bind(tool_call_id)
+        .bind(path)
+        .bind(content)
+        .bind(content.map_or(0, |c| c.len() as i64))
+        .bind(now_ts())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Drop the oldest snapshots until at most `max_count` are left and they
+    /// take at most `max_total_bytes`.
+    pub async fn prune_file_snapshots(&self, max_count: usize, max_total_bytes: u64) -> Result<()> {
+        sqlx::query(
+            "DELETE FROM file_snapshots WHERE id IN ( \
+               SELECT id FROM ( \
+                 SELECT id, \
+                   ROW_NUMBER() OVER (ORDER BY id DESC) AS newer, \
+                   SUM(size) OVER (ORDER BY id DESC) AS bytes \
+                 FROM file_snapshots WHERE session_id = ? \
+               ) WHERE newer > ? OR bytes > ? \
+             )",
+        )
+        .bind(&self.id)
+        .bind(max_count as i64)
+        .bind(max_total_bytes.min(i64::MAX as u64) as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The snapshots taken for the most recent change still recorded: every
+    /// file of the last tool call that has any.
+    pub async fn last_file_snapshots(&self) -> Result<Vec<StoredFileSnapshot>> {
+        let rows = sqlx::query(
+            "SELECT id, turn, tool_call_id, path, content, created_at FROM file_snapshots \
+             WHERE session_id = ?1 AND (turn, tool_call_id) = ( \
+               SELECT turn, tool_call_id FROM file_snapshots \
+               WHERE session_id = ?1 ORDER BY id DESC LIMIT 1 \
+             ) ORDER BY id ASC",
+        )
+        .bind(&self.id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|r| {
+                Ok(StoredFileSnapshot {
+                    id: r.try_get("id")?,
+                    turn: r.try_get::<i64, _>("turn")? as usize,
+                    tool_call_id: r.try_get("tool_call_id")?,
+                    path: r.try_get("path")?,
+                    content: r.try_get("content")?,
+                    created_at: r.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Forget the snapshots of one change, once it has been undone.
+    pub async fn delete_file_snapshots(&self, turn: usize, tool_call_id: &str) -> Result<()> {
+        sqlx::query(
+            "DELETE FROM file_snapshots WHERE session_id = ? AND turn = ? AND tool_call_id = ?",
+        )
+        .bind(&self.id)
+        .bind(turn as i64)
+        .bind(tool_call_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
     // ── Checkpointing ─────────────────────────────────────────────────────────
 
     /// Write a full-turn checkpoint after a completely-finished turn.
@@ -521,6 +1218,259 @@ impl Session {
         Ok(())
     }
 
+    /// Undo the latest user turn so it can be run again: roll back to the
+    /// newest checkpoint written before its user message (or the start of the
+    /// session) and drop the checkpoints written since. Token usage and errors
+    /// are kept; they record what the turn actually spent. Returns `false` when
+    /// the session has no user message.
+    pub async fn rollback_last_turn(&self) -> Result<bool> {
+        let row = sqlx::query(
+            "SELECT MAX(id) as user_id FROM messages WHERE session_id = ? AND role = 'user'",
+        )
+        .bind(&self.id)
+        .fetch_one(&self.pool)
+        .await?;
+        let Some(user_id) = row.try_get::<Option<i64>, _>("user_id")? else {
+            return Ok(false);
+        };
+
+        let checkpoint = sqlx::query(
+            "SELECT id, last_msg_id FROM checkpoints \
+             WHERE session_id = ? AND last_msg_id < ? ORDER BY id DESC LIMIT 1",
+        )
+        .bind(&self.id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let (checkpoint_id, last_msg_id) = match checkpoint {
+            Some(r) => (r.try_get::<i64, _>("id")?, r.try_get("last_msg_id")?),
+            None => (0, 0),
+        };
+
+        self.rollback_to(last_msg_id).await?;
+        sqlx::query("DELETE FROM checkpoints WHERE session_id = ? AND id > ?")
+            .bind(&self.id)
+            .bind(checkpoint_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(true)
+    }
+
+    /// Drop the newest message and the checkpoints that point at it, e.g. the
+    /// note a run leaves at `max_turns` before the run is carried on. Returns
+    /// `false` when the session has no messages.
+    pub async fn rollback_last_message(&self) -> Result<bool> {
+        let row = sqlx::query("SELECT MAX(id) as last_id FROM messages WHERE session_id = ?")
+            .bind(&self.id)
+            .fetch_one(&self.pool)
+            .await?;
+        let Some(last_id) = row.try_get::<Option<i64>, _>("last_id")? else {
+            return Ok(false);
+        };
+
+        self.rollback_to(last_id - 1).await?;
+        sqlx::query("DELETE FROM checkpoints WHERE session_id = ? AND last_msg_id >= ?")
+            .bind(&self.id)
+            .bind(last_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(true)
+    }
+
+    // ── Branching ─────────────────────────────────────────────────────────────
+
+    /// Fork this session into a new one containing every message up to and
+    /// including `last_msg_id`. The original session is left untouched.
+    ///
+    /// Checkpoints at or before the boundary are copied with their message ids
+    /// remapped, and token-usage rows recorded no later than the boundary
+    /// message are copied so usage totals stay accurate in the branch.
+    pub async fn branch_from(&self, last_msg_id: i64) -> Result<Arc<Session>> {
+        let row = sqlx::query("SELECT model, provider FROM sessions WHERE id = ?")
+            .bind(&self.id)
+            .fetch_one(&self.pool)
+            .await?;
+        let model: String = row.try_get("model")?;
+        let provider: String = row.try_get("provider")?;
+
+        let messages = self.messages_up_to(last_msg_id).await?;
+        let boundary = messages
+            .last()
+            .ok_or_else(|| anyhow::anyhow!("no messages at or before id {last_msg_id}"))?;
+        let boundary_ts = boundary.created_at;
+        let boundary_turn = boundary.turn;
+
+        let id = Uuid::new_v4().to_string();
+        let metadata = serde_json::json!({
+            "branched_from": self.id,
+            "branch_msg_id": last_msg_id,
+        })
+        .to_string();
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO sessions (id, agent_id, model, provider, created_at, metadata) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(&self.agent_id)
+        .bind(&model)
+        .bind(&provider)
+        .bind(now_ts())
+        .bind(&metadata)
+        .execute(&mut *tx)
+        .await?;
+
+        // Old message id → new message id, used to remap checkpoints.
+        let mut id_map = std::collections::HashMap::with_capacity(messages.len());
+        for m in &messages {
+            let new_id = sqlx::query(
+                "INSERT INTO messages \
+                 (session_id, agent_id, turn, role, content, tool_call_id, tool_name, tool_args, created_at) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&id)
+            .bind(&m.agent_id)
+            .bind(m.turn as i64)
+            .bind(&m.role)
+            .bind(&m.content)
+            .bind(&m.tool_call_id)
+            .bind(&m.tool_name)
+            .bind(&m.tool_args)
+            .bind(m.created_at)
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid();
+            id_map.insert(m.id, new_id);
+        }
+        let new_last_msg_id = id_map.get(&boundary.id).copied().unwrap_or_default();
+
+        let checkpoints = sqlx::query(
+            "SELECT agent_id, turn, last_msg_id, subturn_tool_idx, subturn_call_id, created_at \
+             FROM checkpoints WHERE session_id = ? AND last_msg_id <= ? ORDER BY id ASC",
+        )
+        .bind(&self.id)
+        .bind(last_msg_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut latest_copied = None;
+        for cp in checkpoints {
+            let old: i64 = cp.try_get("last_msg_id")?;
+            let Some(&mapped) = id_map.get(&old) else {
+                continue;
+            };
+            sqlx::query(
+                "INSERT INTO checkpoints \
+                 (session_id, agent_id, turn, last_msg_id, subturn_tool_idx, subturn_call_id, created_at) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&id)
+            .bind(cp.try_get::<String, _>("agent_id")?)
+            .bind(cp.try_get::<i64, _>("turn")?)
+            .bind(mapped)
+            .bind(cp.try_get::<Option<i64>, _>("subturn_tool_idx")?)
+            .bind(cp.try_get::<Option<String>, _>("subturn_call_id")?)
+            .bind(cp.try_get::<i64, _>("created_at")?)
+            .execute(&mut *tx)
+            .await?;
+            latest_copied = Some(mapped);
+        }
+        // Close the branch with a checkpoint at its last message so a resume
+        // never rolls back any of the copied history.
+        if latest_copied != Some(new_last_msg_id) {
+            sqlx::query(
+                "INSERT INTO checkpoints \
+                 (session_id, agent_id, turn, last_msg_id, subturn_tool_idx, subturn_call_id, created_at) \
+                 VALUES (?, ?, ?, ?, NULL, NULL, ?)",
+            )
+            .bind(&id)
+            .bind(&self.agent_id)
+            .bind(boundary_turn as i64)
+            .bind(new_last_msg_id)
+            .bind(now_ts())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        // Usage recorded by the boundary message. Rows from before
+        // `after_msg_id` existed only have their timestamp to go by.
+        let usage = sqlx::query(
+            "SELECT agent_id, turn, input_tokens, output_tokens, created_at, context, after_msg_id \
+             FROM token_usage WHERE session_id = ? \
+             AND (after_msg_id <= ? OR (after_msg_id IS NULL AND created_at <= ?)) \
+             ORDER BY id ASC",
+        )
+        .bind(&self.id)
+        .bind(last_msg_id)
+        .bind(boundary_ts)
+        .fetch_all(&mut *tx)
+        .await?;
+        for u in usage {
+            let after: Option<i64> = u.try_get("after_msg_id")?;
+            sqlx::query(
+                "INSERT INTO token_usage \
+                 (session_id, agent_id, turn, input_tokens, output_tokens, created_at, context, after_msg_id) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&id)
+            .bind(u.try_get::<String, _>("agent_id")?)
+            .bind(u.try_get::<i64, _>("turn")?)
+            .bind(u.try_get::<i64, _>("input_tokens")?)
+            .bind(u.try_get::<i64, _>("output_tokens")?)
+            .bind(u.try_get::<i64, _>("created_at")?)
+            .bind(u.try_get::<Option<String>, _>("context")?)
+            .bind(after.and_then(|old| id_map.get(&old).copied()))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(Arc::new(Session {
+            id,
+            agent_id: self.agent_id.clone(),
+            pool: self.pool.clone(),
+        }))
+    }
+
+    // ── Portability ───────────────────────────────────────────────────────────
+
+    /// This session as a [`PortableSession`], for `krabs export`. Messages
+    /// stop at the latest checkpoint, where a resume would pick up anyway, so
+    /// an interrupted turn is left out as it would be rolled back.
+    pub async fn to_portable(&self) -> Result<PortableSession> {
+        let row = sqlx::query("SELECT model, provider, created_at FROM sessions WHERE id = ?")
+            .bind(&self.id)
+            .fetch_one(&self.pool)
+            .await?;
+        let checkpoint = self.latest_checkpoint().await?;
+        let mut messages = match &checkpoint {
+            Some(cp) => self.messages_up_to(cp.last_msg_id).await?,
+            None => self.messages().await?,
+        };
+        messages.retain(|m| m.role != "partial");
+        let subturn = checkpoint.and_then(|cp| {
+            Some(PortableSubturn {
+                turn: cp.turn,
+                tool_idx: cp.subturn_tool_idx?,
+                call_id: cp.subturn_call_id.unwrap_or_default(),
+            })
+        });
+
+        Ok(PortableSession {
+            version: PORTABLE_VERSION,
+            id: self.id.clone(),
+            agent_id: self.agent_id.clone(),
+            model: row.try_get("model")?,
+            provider: row.try_get("provider")?,
+            created_at: row.try_get("created_at")?,
+            metadata: self.metadata().await?,
+            entries: export::entries(&messages, &self.token_usage().await?),
+            subturn,
+            tool_stats: self.tool_stats().await?,
+        })
+    }
+
     // ── Reconstruction ────────────────────────────────────────────────────────
 
     /// Convert a `StoredMessage` back into a provider `Message` for replay.
@@ -529,7 +1479,8 @@ impl Session {
     pub fn stored_to_message(stored: &StoredMessage) -> Result<Message> {
         match stored.role.as_str() {
             "system" => Ok(Message::system(&stored.content)),
-            "user" => Ok(Message::user(&stored.content)),
+            "developer" => Ok(Message::developer(&stored.content)),
+            "user" | "summary" => Ok(Message::user(&stored.content)),
             "assistant" => {
                 if stored.tool_args.is_some() {
                     let calls = Self::decode_tool_calls(stored)?;
@@ -587,8 +1538,8 @@ impl Session {
 
     pub async fn token_usage(&self) -> Result<Vec<StoredTokenUsage>> {
         let rows = sqlx::query(
-            "SELECT id, session_id, agent_id, turn, input_tokens, output_tokens, created_at \
-             FROM token_usage WHERE session_id = ? ORDER BY turn ASC",
+            "SELECT id, session_id, agent_id, turn, input_tokens, output_tokens, created_at, \
+             context FROM token_usage WHERE session_id = ? ORDER BY turn ASC",
         )
         .bind(&self.id)
         .fetch_all(&self.pool)
@@ -604,6 +1555,7 @@ impl Session {
                     input_tokens: r.try_get::<i64, _>("input_tokens")? as u32,
                     output_tokens: r.try_get::<i64, _>("output_tokens")? as u32,
                     created_at: r.try_get("created_at")?,
+                    context: r.try_get("context")?,
                 })
             })
             .collect()
@@ -634,6 +1586,18 @@ impl Session {
             .collect()
     }
 
+    /// This session's tool stats, most-called tool first.
+    pub async fn tool_stats(&self) -> Result<Vec<ToolStats>> {
+        let rows = sqlx::query(
+            "SELECT tool_name, calls, errors, retries, total_ms FROM tool_stats \
+             WHERE session_id = ? ORDER BY calls DESC, tool_name",
+        )
+        .bind(&self.id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(tool_stats_from_row).collect()
+    }
+
     pub async fn total_token_usage(&self) -> Result<(u32, u32)> {
         let row = sqlx::query(
             "SELECT COALESCE(SUM(input_tokens), 0) as inp, \
@@ -669,6 +1633,16 @@ impl Session {
 
 // ── Tests ─────────────────────────────────────────────────────────────────────
 
+fn tool_stats_from_row(r: &sqlx::sqlite::SqliteRow) -> Result<ToolStats> {
+    Ok(ToolStats {
+        tool_name: r.try_get("tool_name")?,
+        calls: r.try_get::<i64, _>("calls")? as usize,
+        errors: r.try_get::<i64, _>("errors")? as usize,
+        retries: r.try_get::<i64, _>("retries")? as usize,
+        total_ms: r.try_get::<i64, _>("total_ms")? as u64,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -709,6 +1683,103 @@ mod tests {
         let _ = std::fs::remove_file(path);
     }
 
+    #[tokio::test]
+    async fn tags_are_stored_in_metadata_and_filter_listing() {
+        let (store, path) = open_temp_store().await;
+        let tagged = store.new_session("a", "m", "p").await.unwrap();
+        let other = store.new_session("a", "m", "p").await.unwrap();
+
+        // Sessions created without metadata read as untagged.
+        assert!(tagged.tags().await.unwrap().is_empty());
+
+        tagged
+            .set_metadata("owner", serde_json::json!("ops"))
+            .await
+            .unwrap();
+        assert!(tagged.add_tag("prod-incident").await.unwrap());
+        assert!(!tagged.add_tag("prod-incident").await.unwrap());
+        assert!(other.add_tag("experiment-3").await.unwrap());
+
+        assert_eq!(tagged.tags().await.unwrap(), vec!["prod-incident"]);
+        assert_eq!(tagged.metadata().await.unwrap()["owner"], "ops");
+
+        let hits = store.list_sessions(Some("prod-incident")).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id, tagged.id);
+        assert_eq!(hits[0].tags, vec!["prod-incident"]);
+        assert_eq!(store.list_sessions(None).await.unwrap().len(), 2);
+
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn listing_shows_title_or_first_message_preview() {
+        let (store, path) = open_temp_store().await;
+        let titled = store.new_session("a", "m", "p").await.unwrap();
+        let untitled = store.new_session("a", "m", "p").await.unwrap();
+        let empty = store.new_session("a", "m", "p").await.unwrap();
+        for s in [&titled, &untitled] {
+            s.persist_message(&Message::user("fix the flaky\nlogin test"), 0)
+                .await
+                .unwrap();
+        }
+        titled.set_title("Fix flaky login test").await.unwrap();
+        assert_eq!(
+            titled.title().await.unwrap().as_deref(),
+            Some("Fix flaky login test")
+        );
+
+        let listed = store.list_sessions(None).await.unwrap();
+        let title_of = |id: &str| {
+            listed
+                .iter()
+                .find(|s| s.id == id)
+                .and_then(|s| s.title.clone())
+        };
+        assert_eq!(
+            title_of(&titled.id).as_deref(),
+            Some("Fix flaky login test")
+        );
+        assert_eq!(title_of(&untitled.id).as_deref(), Some("fix the flaky"));
+        assert_eq!(title_of(&empty.id), None);
+
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn tool_approvals_are_scoped_to_session_or_global() {
+        let (store, path) = open_temp_store().await;
+        store.approve_tool("bash", Some("s1")).await.unwrap();
+        store.approve_tool("bash", Some("s1")).await.unwrap();
+        store.approve_tool("mcp__github__*", None).await.unwrap();
+
+        let s1 = store.tool_approvals(Some("s1")).await.unwrap();
+        assert_eq!(s1.len(), 2);
+        assert!(s1.iter().any(|a| a.rule == "bash" && !a.is_global()));
+        assert!(s1
+            .iter()
+            .any(|a| a.is_global() && a.matches("mcp__github__list_prs")));
+
+        let s2 = store.tool_approvals(Some("s2")).await.unwrap();
+        assert_eq!(s2.len(), 1);
+        assert!(s2[0].is_global());
+
+        assert_eq!(
+            store
+                .revoke_tool_approval("mcp__github__*", Some("s2"))
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(store.tool_approvals(None).await.unwrap().len(), 0);
+        assert_eq!(store.tool_approvals(Some("s1")).await.unwrap().len(), 1);
+
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
     #[tokio::test]
     async fn persist_and_read_back_full_conversation() {
         let (store, path) = open_temp_store().await;
@@ -768,6 +1839,62 @@ mod tests {
         let _ = std::fs::remove_file(path);
     }
 
+    #[tokio::test]
+    async fn error_counts_group_by_session_and_context() {
+        let (store, path) = open_temp_store().await;
+        let a = store
+            .new_session("agent-1", "gpt-4o", "openai")
+            .await
+            .unwrap();
+        let b = store
+            .new_session("agent-1", "gpt-4o", "openai")
+            .await
+            .unwrap();
+        let err = anyhow::anyhow!("boom");
+        a.persist_error(0, "llm_stream", &err, 0).await.unwrap();
+        a.persist_error(0, "llm_stream", &err, 1).await.unwrap();
+        a.persist_error(3, "max_turns", &err, 0).await.unwrap();
+        b.persist_error(1, "bash", &err, 0).await.unwrap();
+
+        let counts = store.error_counts().await.unwrap();
+        assert_eq!(counts.len(), 3);
+        let find = |sid: &str, ctx: &str| {
+            counts
+                .iter()
+                .find(|c| c.session_id == sid && c.context == ctx)
+                .map(|c| c.count)
+        };
+        assert_eq!(find(&a.id, "llm_stream"), Some(2));
+        assert_eq!(find(&a.id, "max_turns"), Some(1));
+        assert_eq!(find(&b.id, "bash"), Some(1));
+
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn developer_messages_round_trip() {
+        let (store, path) = open_temp_store().await;
+        let session = store
+            .new_session("agent-1", "o3-mini", "openai")
+            .await
+            .unwrap();
+
+        session
+            .persist_message(&Message::developer("Prefer small diffs."), 0)
+            .await
+            .unwrap();
+
+        let messages = session.messages().await.unwrap();
+        assert_eq!(messages[0].role, "developer");
+        let reconstructed = Session::stored_to_message(&messages[0]).unwrap();
+        assert!(matches!(reconstructed.role, Role::Developer));
+        assert_eq!(reconstructed.content, "Prefer small diffs.");
+
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
     #[tokio::test]
     async fn system_messages_are_persisted() {
         let (store, path) = open_temp_store().await;
@@ -798,6 +1925,64 @@ mod tests {
         let _ = std::fs::remove_file(path);
     }
 
+    #[tokio::test]
+    async fn partial_is_replaced_and_cleared() {
+        let (store, path) = open_temp_store().await;
+        let session = store
+            .new_session("agent-1", "gpt-4o", "openai")
+            .await
+            .unwrap();
+
+        session
+            .persist_message(&Message::user("hello"), 0)
+            .await
+            .unwrap();
+        let user_id = session.messages().await.unwrap()[0].id;
+        session.persist_partial("Hel", 0).await.unwrap();
+        session.persist_partial("Hello the", 0).await.unwrap();
+
+        let messages = session.messages().await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            session.latest_partial(user_id).await.unwrap().as_deref(),
+            Some("Hello the")
+        );
+
+        session.clear_partials().await.unwrap();
+        assert!(session.latest_partial(0).await.unwrap().is_none());
+
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn summary_is_persisted_and_checkpointed() {
+        let (store, path) = open_temp_store().await;
+        let session = store
+            .new_session("agent-1", "gpt-4o", "openai")
+            .await
+            .unwrap();
+
+        session
+            .persist_message(&Message::user("hello"), 0)
+            .await
+            .unwrap();
+        session
+            .persist_summary(&Message::user("[summary] said hello"), 1)
+            .await
+            .unwrap();
+
+        let messages = session.messages().await.unwrap();
+        assert_eq!(messages[1].role, "summary");
+        let replayed = Session::stored_to_message(&messages[1]).unwrap();
+        assert!(matches!(replayed.role, Role::User));
+        let cp = session.latest_checkpoint().await.unwrap().unwrap();
+        assert_eq!(cp.last_msg_id, messages[1].id);
+
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
     #[tokio::test]
     async fn persist_and_read_back_errors_with_attempt() {
         let (store, path) = open_temp_store().await;
@@ -822,6 +2007,153 @@ mod tests {
         let _ = std::fs::remove_file(path);
     }
 
+    #[tokio::test]
+    async fn thought_signatures_survive_the_store() {
+        let (store, path) = open_temp_store().await;
+        let session = store
+            .new_session("a", "gemini-2.5-pro", "gemini")
+            .await
+            .unwrap();
+        let signed = ToolCall {
+            id: "c1".into(),
+            name: "read".into(),
+            args: serde_json::json!({ "path": "a.txt" }),
+            thought_signature: Some("CiQBcsjafE3…sig==".into()),
+        };
+        let unsigned = ToolCall {
+            id: "c2".into(),
+            thought_signature: None,
+            ..signed.clone()
+        };
+        session
+            .persist_message(&Message::assistant_tool_calls(vec![signed, unsigned]), 1)
+            .await
+            .unwrap();
+
+        let reloaded = store.load_session(&session.id).await.unwrap();
+        let stored = reloaded.messages().await.unwrap();
+        assert!(stored[0]
+            .tool_args
+            .as_deref()
+            .is_some_and(|a| a.matches("thought_signature").count() == 1));
+        let message = Session::stored_to_message(&stored[0]).unwrap();
+        let calls = message.tool_calls.unwrap();
+        assert_eq!(
+            calls[0].thought_signature.as_deref(),
+            Some("CiQBcsjafE3…sig==")
+        );
+        assert_eq!(calls[1].thought_signature, None);
+
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn tool_stats_add_up_per_session_and_across_sessions() {
+        let (store, path) = open_temp_store().await;
+        let one = store.new_session("a", "m", "p").await.unwrap();
+        let two = store.new_session("a", "m", "p").await.unwrap();
+        one.record_tool_call("bash", false, 0, 100).await.unwrap();
+        one.record_tool_call("bash", true, 2, 500).await.unwrap();
+        one.record_tool_call("read", false, 0, 5).await.unwrap();
+        two.record_tool_call("read", false, 0, 15).await.unwrap();
+        two.record_tool_call("read", false, 0, 10).await.unwrap();
+
+        let stats = one.tool_stats().await.unwrap();
+        assert_eq!(stats[0].tool_name, "bash");
+        assert_eq!(
+            (
+                stats[0].calls,
+                stats[0].errors,
+                stats[0].retries,
+                stats[0].total_ms
+            ),
+            (2, 1, 2, 600)
+        );
+
+        let all = store.tool_stats_all().await.unwrap();
+        assert_eq!(all[0].tool_name, "read");
+        assert_eq!((all[0].calls, all[0].total_ms), (3, 30));
+
+        store.delete_session(&one.id).await.unwrap();
+        assert_eq!(store.tool_stats_all().await.unwrap().len(), 1);
+
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn rollback_last_turn_returns_to_the_checkpoint_before_it() {
+        let (store, path) = open_temp_store().await;
+        let session = store.new_session("a", "m", "p").await.unwrap();
+        assert!(!session.rollback_last_turn().await.unwrap());
+
+        for (question, answer) in [("one", "1"), ("two", "2")] {
+            session
+                .persist_message(&Message::user(question), 0)
+                .await
+                .unwrap();
+            session.write_checkpoint(0).await.unwrap();
+            session
+                .persist_message(&Message::assistant(answer), 1)
+                .await
+                .unwrap();
+            session.write_checkpoint(1).await.unwrap();
+        }
+        let first_turn_end = session.messages().await.unwrap()[1].id;
+
+        assert!(session.rollback_last_turn().await.unwrap());
+        let left: Vec<_> = session
+            .messages()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| m.content)
+            .collect();
+        assert_eq!(left, ["one", "1"]);
+        let cp = session.latest_checkpoint().await.unwrap().unwrap();
+        assert_eq!(cp.last_msg_id, first_turn_end);
+
+        // The first turn has no checkpoint before it: back to an empty session.
+        assert!(session.rollback_last_turn().await.unwrap());
+        assert!(session.messages().await.unwrap().is_empty());
+        assert!(session.latest_checkpoint().await.unwrap().is_none());
+
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn rollback_last_message_drops_it_and_its_checkpoint() {
+        let (store, path) = open_temp_store().await;
+        let session = store.new_session("a", "m", "p").await.unwrap();
+        assert!(!session.rollback_last_message().await.unwrap());
+
+        session
+            .persist_message(&Message::user("go"), 0)
+            .await
+            .unwrap();
+        session
+            .persist_message(&Message::tool_result("ok", "c1", "bash"), 0)
+            .await
+            .unwrap();
+        session.write_checkpoint(0).await.unwrap();
+        let tool_result = session.messages().await.unwrap()[1].id;
+        session
+            .persist_message(&Message::assistant("[stopped]"), 1)
+            .await
+            .unwrap();
+        session.write_checkpoint(1).await.unwrap();
+
+        assert!(session.rollback_last_message().await.unwrap());
+        assert_eq!(session.messages().await.unwrap().len(), 2);
+        let cp = session.latest_checkpoint().await.unwrap().unwrap();
+        assert_eq!(cp.last_msg_id, tool_result);
+
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
     #[tokio::test]
     async fn checkpoint_write_and_rollback() {
         let (store, path) = open_temp_store().await;
@@ -1192,4 +2524,161 @@ mod tests {
         drop(store);
         let _ = std::fs::remove_file(path);
     }
+
+    #[tokio::test]
+    async fn branch_copies_history_up_to_boundary() {
+        let (store, path) = open_temp_store().await;
+        let session = store
+            .new_session("agent-1", "gpt-4o", "openai")
+            .await
+            .unwrap();
+
+        session
+            .persist_message(&Message::user("first"), 0)
+            .await
+            .unwrap();
+        session
+            .persist_message(&Message::assistant("one"), 0)
+            .await
+            .unwrap();
+        session.persist_token_usage(0, 100, 10).await.unwrap();
+        session.write_checkpoint(0).await.unwrap();
+        let boundary = session.latest_checkpoint().await.unwrap().unwrap();
+
+        session
+            .persist_message(&Message::user("second"), 0)
+            .await
+            .unwrap();
+        session
+            .persist_message(&Message::assistant("two"), 0)
+            .await
+            .unwrap();
+        // Usually within the same second as the boundary; still left out.
+        session.persist_token_usage(0, 50, 5).await.unwrap();
+        session.write_checkpoint(0).await.unwrap();
+
+        let branch = session.branch_from(boundary.last_msg_id).await.unwrap();
+        assert_ne!(branch.id, session.id);
+
+        let msgs = branch.messages().await.unwrap();
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(msgs[1].content, "one");
+
+        let cp = branch.latest_checkpoint().await.unwrap().unwrap();
+        assert_eq!(cp.last_msg_id, msgs[1].id);
+        assert_eq!(branch.total_token_usage().await.unwrap(), (100, 10));
+
+        // The original keeps its full history.
+        assert_eq!(session.messages().await.unwrap().len(), 4);
+
+        // New messages in the branch don't leak into the original.
+        branch
+            .persist_message(&Message::user("alt"), 0)
+            .await
+            .unwrap();
+        assert_eq!(session.messages().await.unwrap().len(), 4);
+        assert_eq!(branch.messages().await.unwrap().len(), 3);
+
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn concurrent_writers_do_not_hit_lock_errors() {
+        let (store, path) = open_temp_store().await;
+        drop(store);
+
+        // Separate stores, as a parent and its sub-agents would have.
+        let mut tasks = Vec::new();
+        for i in 0..8 {
+            let path = path.clone();
+            tasks.push(tokio::spawn(async move {
+                let store = SessionStore::open(&path).await?;
+                let session = store.new_session(&format!("agent-{i}"), "m", "p").await?;
+                for turn in 0..20 {
+                    session
+                        .persist_message(&Message::user(format!("msg {turn}")), turn)
+                        .await?;
+                    session.persist_token_usage(turn, 10, 1).await?;
+                }
+                anyhow::Ok(session.id.clone())
+            }));
+        }
+        let mut ids = Vec::new();
+        for task in tasks {
+            ids.push(task.await.unwrap().expect("no lock errors"));
+        }
+
+        let store = SessionStore::open(&path).await.unwrap();
+        for id in &ids {
+            assert_eq!(store.session_message_count(id).await.unwrap(), 20);
+        }
+        let mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+            .fetch_one(&store.pool)
+            .await
+            .unwrap();
+        assert_eq!(mode, "wal");
+        drop(store);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn exported_session_imports_resumable_elsewhere() {
+        let (store, path) = open_temp_store().await;
+        let session = store
+            .new_session("agent-1", "gpt-4o", "openai")
+            .await
+            .unwrap();
+        session.set_title("port me").await.unwrap();
+        session
+            .persist_message(&Message::user("first"), 0)
+            .await
+            .unwrap();
+        session
+            .persist_message(&Message::assistant("one"), 0)
+            .await
+            .unwrap();
+        session.persist_token_usage(0, 100, 10).await.unwrap();
+        session.write_checkpoint(0).await.unwrap();
+        // An interrupted turn after the checkpoint is left out.
+        session
+            .persist_message(&Message::user("second"), 1)
+            .await
+            .unwrap();
+
+        let json = session.to_portable().await.unwrap().to_json().unwrap();
+        let portable = PortableSession::from_json(&json).unwrap();
+
+        let (other, other_path) = open_temp_store().await;
+        let imported = other.import_session(&portable).await.unwrap();
+        assert_eq!(imported.id, session.id);
+        assert_eq!(imported.title().await.unwrap().as_deref(), Some("port me"));
+        let contents: Vec<String> = imported
+            .messages()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| m.content)
+            .collect();
+        assert_eq!(contents, ["first", "one"]);
+        assert_eq!(imported.total_token_usage().await.unwrap(), (100, 10));
+        let checkpoint = imported.latest_checkpoint().await.unwrap().unwrap();
+        assert_eq!(checkpoint.subturn_tool_idx, None);
+        assert_eq!(
+            imported
+                .messages_up_to(checkpoint.last_msg_id)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // Importing into a store that already has the id picks a new one.
+        let again = other.import_session(&portable).await.unwrap();
+        assert_ne!(again.id, session.id);
+
+        drop((store, other));
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(other_path);
+    }
 }
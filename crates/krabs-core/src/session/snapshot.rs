@@ -0,0 +1,178 @@
+use super::session::{Session, StoredFileSnapshot};
+use crate::tools::patch::patch_paths;
+use anyhow::Result;
+use serde_json::Value;
+use tracing::debug;
+
+/// A file's content read just before a tool call may change it.
+#[derive(Debug, Clone)]
+pub struct FileSnapshot {
+    pub path: String,
+    /// `None` when the file doesn't exist yet.
+    pub content: Option<Vec<u8>>,
+}
+
+/// Files a call to `tool_name` with `args` may change: `write`'s `path`, and
+/// every file an `apply_patch` names.
+pub fn changed_paths(tool_name: &str, args: &Value) -> Vec<String> {
+    match tool_name {
+        "write" => args["path"]
+            .as_str()
+            .map(String::from)
+            .into_iter()
+            .collect(),
+        "apply_patch" => {
+            let mut paths = patch_paths(args["patch"].as_str().unwrap_or_default());
+            paths.sort();
+            paths.dedup();
+            paths
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Read the current content of `paths`. Files over `max_file_bytes`, and ones
+/// that exist but can't be read, are left out, so they can't be undone.
+pub async fn capture(paths: Vec<String>, max_file_bytes: u64) -> Vec<FileSnapshot> {
+    let mut snapshots = Vec::with_capacity(paths.len());
+    for path in paths {
+        let content = match tokio::fs::metadata(&path).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Ok(meta) if meta.is_file() && meta.len() <= max_file_bytes => {
+                match tokio::fs::read(&path).await {
+                    Ok(content) => Some(content),
+                    Err(e) => {
+                        debug!("Not snapshotting {path}: {e}");
+                        continue;
+                    }
+                }
+            }
+            Ok(meta) => {
+                debug!("Not snapshotting {path} ({} bytes)", meta.len());
+                continue;
+            }
+            Err(e) => {
+                debug!("Not snapshotting {path}: {e}");
+                continue;
+            }
+        };
+        snapshots.push(FileSnapshot { path, content });
+    }
+    snapshots
+}
+
+/// Put one file back: write its old content, or remove it if the change
+/// created it.
+async fn restore(snapshot: &StoredFileSnapshot) -> Result<()> {
+    match &snapshot.content {
+        Some(content) => tokio::fs::write(&snapshot.path, content).await?,
+        None => {
+            if let Err(e) = tokio::fs::remove_file(&snapshot.path).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Undo the last `n` file changes recorded in `session`, newest first, and
+/// forget their snapshots. Returns the restored paths; fewer than `n` changes
+/// are undone when fewer were recorded.
+pub async fn undo(session: &Session, n: usize) -> Result<Vec<String>> {
+    let mut restored = Vec::new();
+    for _ in 0..n {
+        let snapshots = session.last_file_snapshots().await?;
+        let Some(first) = snapshots.first() else {
+            break;
+        };
+        for snapshot in &snapshots {
+            restore(snapshot)
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to restore {}: {e}", snapshot.path))?;
+        }
+        session
+            .delete_file_snapshots(first.turn, &first.tool_call_id)
+            .await?;
+        restored.extend(snapshots.into_iter().map(|s| s.path));
+    }
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SessionStore;
+    use serde_json::json;
+
+    async fn record(session: &Session, turn: usize, call: &str, paths: &[&str]) {
+        let paths = paths.iter().map(|p| p.to_string()).collect();
+        for s in capture(paths, 1024).await {
+            session
+                .persist_file_snapshot(turn, call, &s.path, s.content.as_deref())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn undo_restores_changes_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::open(&dir.path().join("krabs.db"))
+            .await
+            .unwrap();
+        let session = store.new_session("a", "m", "p").await.unwrap();
+        let file = dir.path().join("a.txt");
+        let created = dir.path().join("new.txt");
+        let (a, new) = (file.display().to_string(), created.display().to_string());
+        std::fs::write(&file, "v1").unwrap();
+
+        record(&session, 0, "c1", &[&a]).await;
+        std::fs::write(&file, "v2").unwrap();
+        record(&session, 1, "c2", &[&a, &new]).await;
+        std::fs::write(&file, "v3").unwrap();
+        std::fs::write(&created, "hello").unwrap();
+
+        assert_eq!(undo(&session, 1).await.unwrap(), [a.clone(), new.clone()]);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "v2");
+        assert!(!created.exists());
+
+        assert_eq!(undo(&session, 5).await.unwrap(), std::slice::from_ref(&a));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "v1");
+        assert!(undo(&session, 1).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn large_files_are_skipped_and_storage_is_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let big = dir.path().join("big.bin");
+        std::fs::write(&big, vec![0u8; 2048]).unwrap();
+        assert!(capture(vec![big.display().to_string()], 1024)
+            .await
+            .is_empty());
+
+        let store = SessionStore::open(&dir.path().join("krabs.db"))
+            .await
+            .unwrap();
+        let session = store.new_session("a", "m", "p").await.unwrap();
+        let file = dir.path().join("f.txt").display().to_string();
+        for turn in 0..5 {
+            session
+                .persist_file_snapshot(turn, "c", &file, Some(&[b'x'; 100][..]))
+                .await
+                .unwrap();
+        }
+        session.prune_file_snapshots(3, 1_000).await.unwrap();
+        assert_eq!(session.last_file_snapshots().await.unwrap()[0].turn, 4);
+        session.prune_file_snapshots(10, 150).await.unwrap();
+        let restored = undo(&session, 10).await.unwrap();
+        assert_eq!(restored.len(), 1);
+
+        assert_eq!(
+            changed_paths("write", &json!({ "path": "x.rs", "content": "" })),
+            ["x.rs"]
+        );
+        assert!(changed_paths("read", &json!({ "path": "x.rs" })).is_empty());
+    }
+}
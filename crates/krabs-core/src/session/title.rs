@@ -0,0 +1,174 @@
+use crate::config::credentials::Credentials;
+use crate::providers::provider::{LlmProvider, LlmResponse, Message};
+use anyhow::Result;
+
+/// Generated titles are cut to this many words.
+pub const MAX_TITLE_WORDS: usize = 6;
+
+/// Length (in chars) of the first-user-message preview used when a session
+/// has no generated title.
+const PREVIEW_CHARS: usize = 60;
+
+/// Per-message cap on text sent to the titler; the opening of a message says
+/// enough about what the session is for.
+const MAX_CHARS_PER_MESSAGE: usize = 2_000;
+
+const TITLE_PROMPT: &str = "Write a title of at most six words for this conversation \
+between a user and a coding assistant. Reply with the title only: no quotes, no \
+trailing punctuation.";
+
+/// Cheap model used for titles, per provider.
+const TITLE_MODELS: &[(&str, &str)] = &[
+    ("anthropic", "claude-haiku-4-5-20251001"),
+    ("openai", "gpt-4o-mini"),
+    ("gemini", "gemini-2.5-flash-lite"),
+    ("google", "gemini-2.5-flash-lite"),
+];
+
+/// Pick the model to title sessions with, or `None` to skip titling.
+///
+/// `configured` (`session_title_model`) wins when set. Otherwise local Ollama
+/// models are free and reuse the session's model, the hosted providers use a
+/// small model from [`TITLE_MODELS`], and anything else — including an OpenAI
+/// provider pointed at a custom endpoint, where no small model is known to
+/// exist — is skipped rather than billed at the session model's price.
+pub fn title_model(creds: &Credentials, configured: &str) -> Option<String> {
+    if !configured.is_empty() {
+        return Some(configured.to_string());
+    }
+    if creds.provider == "ollama" {
+        return Some(creds.model.clone());
+    }
+    if creds.provider == "openai"
+        && !creds.base_url.is_empty()
+        && !creds.base_url.starts_with("https://api.openai.com")
+    {
+        return None;
+    }
+    TITLE_MODELS
+        .iter()
+        .find(|(p, _)| *p == creds.provider)
+        .map(|(_, m)| m.to_string())
+}
+
+fn clip(s: &str, max: usize) -> &str {
+    match s.char_indices().nth(max) {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
+}
+
+/// Tidy a model reply into a title: first line, no `Title:` label, quotes,
+/// markdown or trailing punctuation, at most [`MAX_TITLE_WORDS`] words.
+fn clean_title(reply: &str) -> String {
+    let line = reply.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    let line = line.trim();
+    let line = line
+        .strip_prefix("Title:")
+        .or_else(|| line.strip_prefix("title:"))
+        .unwrap_or(line);
+    let line = line.trim_matches(|c: char| c.is_whitespace() || "\"'`*#".contains(c));
+    let line = line.trim_end_matches(['.', '!', '?', ':', ';', ',']);
+    line.split_whitespace()
+        .take(MAX_TITLE_WORDS)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Ask `provider` for a short title for a session's first exchange.
+pub async fn generate_title(
+    provider: &dyn LlmProvider,
+    user: &str,
+    assistant: &str,
+) -> Result<String> {
+    let transcript = format!(
+        "User: {}\n\nAssistant: {}",
+        clip(user, MAX_CHARS_PER_MESSAGE),
+        clip(assistant, MAX_CHARS_PER_MESSAGE)
+    );
+    let request = [Message::system(TITLE_PROMPT), Message::user(transcript)];
+    let title = match provider.complete(&request, &[]).await? {
+        LlmResponse::Message { content, .. } => clean_title(&content),
+        LlmResponse::ToolCalls { .. } => anyhow::bail!("titler replied with tool calls"),
+    };
+    if title.is_empty() {
+        anyhow::bail!("titler returned an empty title");
+    }
+    Ok(title)
+}
+
+/// Fallback title: the first line of the first user message, shortened.
+pub fn preview_title(first_user_message: &str) -> String {
+    let line = first_user_message
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or("");
+    let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+    let clipped = clip(&line, PREVIEW_CHARS);
+    if clipped.len() < line.len() {
+        format!("{clipped}…")
+    } else {
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn creds(provider: &str, base_url: &str) -> Credentials {
+        Credentials {
+            provider: provider.into(),
+            api_key: String::new(),
+            base_url: base_url.into(),
+            model: "big-model".into(),
+            is_default: false,
+            prompt_caching: false,
+            thinking_budget: 0,
+            reasoning_effort: None,
+            max_output_tokens: None,
+            stop_sequences: Vec::new(),
+            openai_api: Default::default(),
+            trace_http: false,
+            request_timeout_ms: 0,
+            connect_timeout_ms: 0,
+        }
+    }
+
+    #[test]
+    fn titles_are_cleaned_and_capped() {
+        assert_eq!(clean_title("\"Fix the parser.\""), "Fix the parser");
+        assert_eq!(
+            clean_title("Title: **Refactor session store for title support and more**\nextra"),
+            "Refactor session store for title support"
+        );
+        assert_eq!(clean_title("  \n"), "");
+    }
+
+    #[test]
+    fn preview_uses_first_line_and_clips() {
+        assert_eq!(preview_title("\n  fix   the bug\nmore"), "fix the bug");
+        let long = "word ".repeat(40);
+        let p = preview_title(&long);
+        assert!(p.ends_with('…'));
+        assert_eq!(p.chars().count(), PREVIEW_CHARS + 1);
+    }
+
+    #[test]
+    fn title_model_prefers_config_then_cheap_defaults() {
+        assert_eq!(
+            title_model(&creds("anthropic", ""), "my-model").as_deref(),
+            Some("my-model")
+        );
+        assert_eq!(
+            title_model(&creds("anthropic", "https://api.anthropic.com"), "").as_deref(),
+            Some("claude-haiku-4-5-20251001")
+        );
+        assert_eq!(
+            title_model(&creds("ollama", "http://localhost:11434/v1"), "").as_deref(),
+            Some("big-model")
+        );
+        assert!(title_model(&creds("openai", "https://example.com/v1"), "").is_none());
+        assert!(title_model(&creds("mystery", ""), "").is_none());
+    }
+}
@@ -1,44 +1,113 @@
+use crate::permissions::PermissionGuard;
 use crate::skills::skill::Skill;
 use crate::tools::tool::ToolDef;
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct FsSkill {
     pub name: String,
     pub description: String,
+    /// `allowed_tools` from the frontmatter: tool names or glob patterns.
+    pub allowed_tools: Vec<String>,
+    /// `denied_tools` from the frontmatter: tool names or glob patterns.
+    pub denied_tools: Vec<String>,
+    /// The two lists above as a guard; `None` when both are empty.
+    pub(crate) permissions: Option<PermissionGuard>,
     pub(crate) skill_dir: PathBuf,
 }
 
+/// SKILL.md frontmatter. Fields not listed here are ignored.
 #[derive(Deserialize)]
 struct Frontmatter {
     name: String,
     description: String,
+    #[serde(default, alias = "allowed-tools", deserialize_with = "tool_list")]
+    allowed_tools: Vec<String>,
+    #[serde(default, alias = "denied-tools", deserialize_with = "tool_list")]
+    denied_tools: Vec<String>,
+}
+
+/// A tool list written either as a YAML list or as one comma- or
+/// space-separated string (`allowed-tools: read, grep`).
+fn tool_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        Many(Vec<String>),
+        One(String),
+    }
+    let names = match List::deserialize(deserializer)? {
+        List::Many(names) => names,
+        List::One(s) => s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .map(str::to_string)
+            .collect(),
+    };
+    Ok(names
+        .into_iter()
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .collect())
+}
+
+/// Build the guard a skill applies while active. Names with glob
+/// metacharacters become patterns; an invalid pattern is an error.
+fn skill_guard(allowed: &[String], denied: &[String]) -> Result<Option<PermissionGuard>> {
+    if allowed.is_empty() && denied.is_empty() {
+        return Ok(None);
+    }
+    let is_glob = |r: &&String| r.contains(['*', '?', '[']);
+    let mut guard = if allowed.is_empty() {
+        PermissionGuard::new()
+    } else {
+        PermissionGuard::allow_only(allowed.iter().filter(|r| !is_glob(r)))
+    };
+    for pattern in allowed.iter().filter(is_glob) {
+        guard = guard.allow_pattern(pattern)?;
+    }
+    guard = guard.deny(denied.iter().filter(|r| !is_glob(r)));
+    for pattern in denied.iter().filter(is_glob) {
+        guard = guard.deny_pattern(pattern)?;
+    }
+    Ok(Some(guard))
 }
 
 impl FsSkill {
     pub fn parse(skill_dir: &Path) -> Result<Self> {
         let skill_md = skill_dir.join("SKILL.md");
         let content = std::fs::read_to_string(&skill_md)?;
-        let (name, description) = parse_frontmatter(&content)?;
-        validate_name(&name)?;
-        validate_description(&description)?;
+        let fm = parse_frontmatter(&content)?;
+        validate_name(&fm.name)?;
+        validate_description(&fm.description)?;
+        let permissions = skill_guard(&fm.allowed_tools, &fm.denied_tools)
+            .map_err(|e| anyhow!("invalid tool pattern in SKILL.md: {}", e))?;
         Ok(Self {
-            name,
-            description,
+            name: fm.name,
+            description: fm.description,
+            allowed_tools: fm.allowed_tools,
+            denied_tools: fm.denied_tools,
+            permissions,
             skill_dir: skill_dir.to_path_buf(),
         })
     }
 
+    /// Whether this skill lets the agent call `tool_name` while it is active.
+    pub fn allows_tool(&self, tool_name: &str) -> bool {
+        self.permissions
+            .as_ref()
+            .is_none_or(|g| g.is_allowed(tool_name))
+    }
+
     pub async fn load_body(&self) -> Result<String> {
         let content = tokio::fs::read_to_string(self.skill_dir.join("SKILL.md")).await?;
         Ok(strip_frontmatter(&content))
     }
 }
 
-fn parse_frontmatter(content: &str) -> Result<(String, String)> {
+fn parse_frontmatter(content: &str) -> Result<Frontmatter> {
     let content = content.trim_start();
     if !content.starts_with("---") {
         return Err(anyhow!("SKILL.md missing YAML frontmatter"));
@@ -48,9 +117,7 @@ fn parse_frontmatter(content: &str) -> Result<(String, String)> {
         .find("\n---")
         .ok_or_else(|| anyhow!("SKILL.md frontmatter not closed with ---"))?;
     let yaml = &rest[..end];
-    let fm: Frontmatter =
-        serde_yaml::from_str(yaml).map_err(|e| anyhow!("invalid SKILL.md frontmatter: {}", e))?;
-    Ok((fm.name, fm.description))
+    serde_yaml::from_str(yaml).map_err(|e| anyhow!("invalid SKILL.md frontmatter: {}", e))
 }
 
 fn strip_frontmatter(content: &str) -> String {
@@ -108,7 +175,61 @@ impl Skill for FsSkill {
         vec![]
     }
 
+    fn allowed_tools(&self) -> &[String] {
+        &self.allowed_tools
+    }
+
+    fn denied_tools(&self) -> &[String] {
+        &self.denied_tools
+    }
+
     async fn system_prompt_section(&self) -> Result<String> {
         self.load_body().await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skill(frontmatter: &str) -> Result<FsSkill> {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("SKILL.md"),
+            format!("---\n{frontmatter}\n---\nBody\n"),
+        )
+        .unwrap();
+        FsSkill::parse(dir.path())
+    }
+
+    #[test]
+    fn tool_lists_parse_from_frontmatter() {
+        let s = skill(
+            "name: safe-docs\ndescription: Docs only.\nallowed_tools: [read, glob, grep]\n\
+             denied-tools: bash, write\nlicense: MIT",
+        )
+        .unwrap();
+        assert_eq!(s.allowed_tools, ["read", "glob", "grep"]);
+        assert_eq!(s.denied_tools, ["bash", "write"]);
+        assert!(s.allows_tool("read"));
+        assert!(!s.allows_tool("bash"));
+        assert!(!s.allows_tool("web_fetch"));
+    }
+
+    #[test]
+    fn deny_only_skill_allows_everything_else() {
+        let s = skill("name: no-shell\ndescription: x\ndenied_tools: [bash, 'mcp__*']").unwrap();
+        assert!(!s.allows_tool("bash"));
+        assert!(!s.allows_tool("mcp__github__create_issue"));
+        assert!(s.allows_tool("write"));
+
+        let open = skill("name: open\ndescription: x").unwrap();
+        assert!(open.permissions.is_none());
+        assert!(open.allows_tool("bash"));
+    }
+
+    #[test]
+    fn invalid_tool_pattern_is_rejected() {
+        assert!(skill("name: bad\ndescription: x\ndenied_tools: ['[']").is_err());
+    }
+}
@@ -1,24 +1,36 @@
 use crate::config::SkillsConfig;
 use crate::skills::fs_skill::FsSkill;
 use std::path::Path;
+use std::time::Duration;
 use tracing::warn;
 
 pub struct SkillLoader;
 
 impl SkillLoader {
-    /// Discover all valid skills across all configured paths.
-    /// Invalid skill directories are logged and skipped — never fatal.
+    /// Discover all valid skills across all configured sources and paths.
+    /// Git sources are fetched first (at most once per `refresh_secs`).
+    /// Invalid skill directories and unreachable sources are logged and
+    /// skipped — never fatal.
     pub fn discover(config: &SkillsConfig) -> Vec<FsSkill> {
         let cwd = std::env::current_dir().unwrap_or_default();
+        let refresh = Duration::from_secs(config.refresh_secs);
         let mut skills = Vec::new();
 
-        for path in &config.paths {
-            let dir = if path.is_absolute() {
+        // Fetch every git source before scanning anything.
+        let sources: Vec<_> = config
+            .sources
+            .iter()
+            .filter_map(|s| s.resolve(&cwd, &config.cache_dir, refresh))
+            .collect();
+        let paths = config.paths.iter().map(|path| {
+            if path.is_absolute() {
                 path.clone()
             } else {
                 cwd.join(path)
-            };
+            }
+        });
 
+        for dir in sources.into_iter().chain(paths) {
             match Self::scan_dir(&dir, config) {
                 Ok(found) => skills.extend(found),
                 Err(e) => warn!("Failed to scan skill directory {:?}: {}", dir, e),
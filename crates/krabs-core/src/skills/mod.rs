@@ -2,7 +2,9 @@ pub mod fs_skill;
 pub mod loader;
 pub mod registry;
 pub mod skill;
+pub mod source;
 
 pub use fs_skill::FsSkill;
 pub use registry::SkillRegistry;
 pub use skill::Skill;
+pub use source::SkillSource;
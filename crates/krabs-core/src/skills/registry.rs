@@ -7,6 +7,9 @@ use tracing::info;
 pub struct SkillRegistry {
     config: SkillsConfig,
     skills: RwLock<Vec<FsSkill>>,
+    /// The skill last loaded with `read_skill`; its `allowed_tools` and
+    /// `denied_tools` restrict the agent until another skill is activated.
+    active: RwLock<Option<String>>,
 }
 
 impl SkillRegistry {
@@ -15,6 +18,7 @@ impl SkillRegistry {
         Self {
             config: config.clone(),
             skills: RwLock::new(initial),
+            active: RwLock::new(None),
         }
     }
 
@@ -65,4 +69,82 @@ impl SkillRegistry {
             None => Ok(None),
         }
     }
+
+    /// Load a skill's body and make it the active skill. When the skill
+    /// restricts tools, a note listing the restrictions is appended so the
+    /// model knows up front.
+    pub async fn activate(&self, name: &str) -> Result<Option<String>> {
+        let guard = self.skills.read().await;
+        let Some(skill) = guard.iter().find(|s| s.name == name) else {
+            return Ok(None);
+        };
+        let mut body = skill.load_body().await?;
+        if skill.permissions.is_some() {
+            body.push_str(&format!(
+                "\n\n---\nWhile the '{}' skill is active, tool use is restricted.",
+                skill.name
+            ));
+            if !skill.allowed_tools.is_empty() {
+                body.push_str(&format!("\nAllowed: {}", skill.allowed_tools.join(", ")));
+            }
+            if !skill.denied_tools.is_empty() {
+                body.push_str(&format!("\nDenied: {}", skill.denied_tools.join(", ")));
+            }
+        }
+        *self.active.write().await = Some(skill.name.clone());
+        info!(skill = %skill.name, "skill activated");
+        Ok(Some(body))
+    }
+
+    /// The active skill's name when it forbids `tool_name`, `None` when the
+    /// call may go ahead. `read_skill` is never blocked so the agent can
+    /// always switch skills, and a skill that was unloaded restricts nothing.
+    pub async fn denied_by(&self, tool_name: &str) -> Option<String> {
+        if tool_name == "read_skill" {
+            return None;
+        }
+        let active = self.active.read().await;
+        let name = active.as_deref()?;
+        let guard = self.skills.read().await;
+        let skill = guard.iter().find(|s| s.name == name)?;
+        (!skill.allows_tool(tool_name)).then(|| skill.name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn active_skill_restricts_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, extra) in [("safe-docs", "denied_tools: [bash, write]"), ("open", "")] {
+            let skill = dir.path().join(name);
+            std::fs::create_dir_all(&skill).unwrap();
+            std::fs::write(
+                skill.join("SKILL.md"),
+                format!("---\nname: {name}\ndescription: x\n{extra}\n---\nBody\n"),
+            )
+            .unwrap();
+        }
+        let registry = SkillRegistry::load(&SkillsConfig {
+            paths: vec![dir.path().to_path_buf()],
+            ..SkillsConfig::default()
+        });
+
+        // Nothing is restricted until a skill is activated.
+        assert_eq!(registry.denied_by("bash").await, None);
+
+        let body = registry.activate("safe-docs").await.unwrap().unwrap();
+        assert!(body.contains("Denied: bash, write"));
+        assert_eq!(
+            registry.denied_by("bash").await.as_deref(),
+            Some("safe-docs")
+        );
+        assert_eq!(registry.denied_by("read").await, None);
+        assert_eq!(registry.denied_by("read_skill").await, None);
+
+        registry.activate("open").await.unwrap().unwrap();
+        assert_eq!(registry.denied_by("bash").await, None);
+    }
 }
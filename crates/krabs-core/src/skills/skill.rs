@@ -7,5 +7,15 @@ pub trait Skill: Send + Sync {
     fn name(&self) -> &str;
     fn description(&self) -> &str;
     fn tools(&self) -> Vec<ToolDef>;
+    /// Tool names or glob patterns the agent may call while this skill is
+    /// active. Empty means no allowlist.
+    fn allowed_tools(&self) -> &[String] {
+        &[]
+    }
+    /// Tool names or glob patterns the agent may not call while this skill
+    /// is active.
+    fn denied_tools(&self) -> &[String] {
+        &[]
+    }
     async fn system_prompt_section(&self) -> Result<String>;
 }
@@ -0,0 +1,235 @@
+//! Where skills come from: local directories, or git repositories that are
+//! shallow-fetched into a cache directory and then scanned like local ones.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Touched (inside the checkout's `.git`) on every fetch attempt; its age
+/// decides when a git source is fetched again.
+const ATTEMPT_MARKER: &str = "krabs-attempted";
+/// Touched after a successful fetch; without it there is no usable checkout.
+const FETCHED_MARKER: &str = "krabs-fetched";
+
+/// One place to load skills from, listed under `skills.sources`.
+///
+/// ```json
+/// { "type": "git", "url": "https://github.com/acme/skills", "ref": "main", "subpath": "skills" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SkillSource {
+    /// A directory of skill folders. Relative paths resolve against the
+    /// working directory.
+    Local { path: PathBuf },
+    /// A git repository. `ref` is a branch, tag or commit (default: the
+    /// remote's HEAD); `subpath` is the directory in the repo holding skill
+    /// folders (default: the repo root).
+    Git {
+        url: String,
+        #[serde(default, rename = "ref")]
+        git_ref: Option<String>,
+        #[serde(default)]
+        subpath: PathBuf,
+    },
+}
+
+impl SkillSource {
+    /// The directory to scan for this source's skills.
+    ///
+    /// A git source is fetched into `cache_dir` when its last fetch attempt is
+    /// older than `refresh`. A failed fetch is logged and the previous checkout
+    /// is used; `None` means there has never been a successful one.
+    pub fn resolve(&self, cwd: &Path, cache_dir: &Path, refresh: Duration) -> Option<PathBuf> {
+        match self {
+            Self::Local { path } if path.is_absolute() => Some(path.clone()),
+            Self::Local { path } => Some(cwd.join(path)),
+            Self::Git {
+                url,
+                git_ref,
+                subpath,
+            } => {
+                let checkout = cache_dir.join(cache_key(url, git_ref.as_deref()));
+                let git_dir = checkout.join(".git");
+                if is_stale(&git_dir.join(ATTEMPT_MARKER), refresh) {
+                    debug!(%url, "fetching skill source");
+                    match fetch(url, git_ref.as_deref(), &checkout) {
+                        Ok(()) => touch(&git_dir.join(FETCHED_MARKER)),
+                        Err(e) => warn!(%url, "Failed to fetch skill source: {e:#}"),
+                    }
+                    touch(&git_dir.join(ATTEMPT_MARKER));
+                }
+                git_dir
+                    .join(FETCHED_MARKER)
+                    .exists()
+                    .then(|| checkout.join(subpath))
+            }
+        }
+    }
+}
+
+/// Cache directory name for `url` at `git_ref`: the URL and ref with every
+/// character that isn't safe in a path replaced by `_`.
+fn cache_key(url: &str, git_ref: Option<&str>) -> String {
+    let raw = match git_ref {
+        Some(r) => format!("{url}@{r}"),
+        None => url.to_string(),
+    };
+    raw.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-.@".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn is_stale(marker: &Path, refresh: Duration) -> bool {
+    std::fs::metadata(marker)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .is_none_or(|age| age >= refresh)
+}
+
+fn touch(path: &Path) {
+    if let Err(e) = std::fs::write(path, b"") {
+        warn!("Failed to write {:?}: {}", path, e);
+    }
+}
+
+/// Shallow-fetch `git_ref` of `url` into `checkout` and check it out,
+/// creating the repository on first use.
+fn fetch(url: &str, git_ref: Option<&str>, checkout: &Path) -> Result<()> {
+    if !checkout.join(".git").is_dir() {
+        std::fs::create_dir_all(checkout)?;
+        git(checkout, &["init", "-q"])?;
+    }
+    git(
+        checkout,
+        &[
+            "fetch",
+            "-q",
+            "--depth",
+            "1",
+            url,
+            git_ref.unwrap_or("HEAD"),
+        ],
+    )?;
+    git(checkout, &["checkout", "-q", "--force", "FETCH_HEAD"])
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<()> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    /// A repo with one commit holding `skills/demo/SKILL.md`.
+    fn skills_repo() -> tempfile::TempDir {
+        let repo = tempfile::tempdir().unwrap();
+        let skill = repo.path().join("skills").join("demo");
+        std::fs::create_dir_all(&skill).unwrap();
+        std::fs::write(
+            skill.join("SKILL.md"),
+            "---\nname: demo\ndescription: A demo skill.\n---\nBody\n",
+        )
+        .unwrap();
+        run_git(repo.path(), &["init", "-q"]);
+        run_git(repo.path(), &["add", "."]);
+        run_git(repo.path(), &["commit", "-q", "-m", "skills"]);
+        repo
+    }
+
+    #[test]
+    fn cache_key_is_path_safe() {
+        assert_eq!(
+            cache_key("https://github.com/acme/skills.git", Some("v1/x")),
+            "https___github.com_acme_skills.git@v1_x"
+        );
+    }
+
+    #[test]
+    fn git_source_is_fetched_and_kept_when_unreachable() {
+        let repo = skills_repo();
+        let cache = tempfile::tempdir().unwrap();
+        let source = SkillSource::Git {
+            url: repo.path().display().to_string(),
+            git_ref: None,
+            subpath: PathBuf::from("skills"),
+        };
+
+        let dir = source
+            .resolve(Path::new("."), cache.path(), Duration::ZERO)
+            .expect("fetched");
+        assert!(dir.join("demo").join("SKILL.md").exists());
+
+        // The remote disappears: the cached checkout is still used.
+        drop(repo);
+        let again = source.resolve(Path::new("."), cache.path(), Duration::ZERO);
+        assert_eq!(again, Some(dir));
+    }
+
+    #[test]
+    fn unreachable_git_source_without_cache_is_skipped() {
+        let cache = tempfile::tempdir().unwrap();
+        let source = SkillSource::Git {
+            url: cache.path().join("missing").display().to_string(),
+            git_ref: Some("main".into()),
+            subpath: PathBuf::new(),
+        };
+        assert_eq!(
+            source.resolve(Path::new("."), cache.path(), Duration::ZERO),
+            None
+        );
+    }
+
+    #[test]
+    fn sources_parse_from_config_json() {
+        let sources: Vec<SkillSource> = serde_json::from_str(
+            r#"[{"type": "local", "path": "team-skills"},
+                {"type": "git", "url": "https://example.com/s.git", "ref": "main"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            sources[1],
+            SkillSource::Git {
+                url: "https://example.com/s.git".into(),
+                git_ref: Some("main".into()),
+                subpath: PathBuf::new(),
+            }
+        );
+    }
+}
@@ -1,10 +1,131 @@
+use super::format::ToolOutputFormat;
 use super::tool::{Tool, ToolResult};
+use crate::config::KrabsConfig;
 use anyhow::Result;
 use async_trait::async_trait;
+use regex::Regex;
 use serde_json::json;
+use std::sync::LazyLock;
 use tokio::process::Command;
 
-pub struct BashTool;
+/// Floor for bash's agent-level timeout, above the longest `timeout_secs` we expect.
+const BASH_MIN_TIMEOUT_MS: u64 = 600_000;
+
+/// Commands refused even when no `bash_deny` pattern matches them, unless a
+/// `bash_allow` pattern does: wiping `/` or the home directory, formatting
+/// or overwriting a disk, and the classic fork bomb.
+pub const DESTRUCTIVE_PATTERNS: &[&str] = &[
+    r"\brm\s+(-\S+\s+)*(/|/\*|~/?|\$HOME/?)(\s|;|&|\||$)",
+    r"\bmkfs(\.\w+)?\b",
+    r"\bdd\b.*\bof=/dev/(sd|hd|vd|xvd|nvme|disk)",
+    r">\s*/dev/(sd|hd|vd|xvd|nvme|disk)",
+    r":\(\)\s*\{\s*:\s*\|\s*:\s*&\s*\}\s*;\s*:",
+];
+
+static DESTRUCTIVE: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    DESTRUCTIVE_PATTERNS
+        .iter()
+        .filter_map(|p| Regex::new(p).ok())
+        .collect()
+});
+
+/// A `bash_allow` / `bash_deny` pattern; `Err` holds why it didn't compile.
+struct Pattern {
+    source: String,
+    regex: Result<Regex, String>,
+}
+
+impl Pattern {
+    fn compile(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            regex: Regex::new(source).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Which commands `bash` may run, checked before anything is executed:
+/// a `bash_deny` match is refused; then a [`DESTRUCTIVE_PATTERNS`] match is
+/// refused unless a `bash_allow` pattern names it; then, if `bash_allow` is
+/// non-empty, only commands matching one of its patterns run.
+#[derive(Default)]
+pub struct BashPolicy {
+    allow: Vec<Pattern>,
+    deny: Vec<Pattern>,
+}
+
+impl BashPolicy {
+    pub fn new(allow: &[String], deny: &[String]) -> Self {
+        Self {
+            allow: allow.iter().map(|p| Pattern::compile(p)).collect(),
+            deny: deny.iter().map(|p| Pattern::compile(p)).collect(),
+        }
+    }
+
+    pub fn from_config(config: &KrabsConfig) -> Self {
+        Self::new(&config.bash_allow, &config.bash_deny)
+    }
+
+    /// No allow or deny lists: everything runs except commands matching
+    /// [`DESTRUCTIVE_PATTERNS`]. Same as `BashPolicy::default()`.
+    pub fn refuse_destructive() -> Self {
+        Self::default()
+    }
+
+    /// `Err` says why `command` may not run. A deny pattern that doesn't
+    /// compile refuses everything, rather than silently letting through
+    /// what it was meant to stop; an allow pattern that doesn't compile
+    /// matches nothing.
+    pub fn check(&self, command: &str) -> Result<(), String> {
+        for p in &self.deny {
+            match &p.regex {
+                Ok(re) if re.is_match(command) => {
+                    return Err(format!("command matches bash_deny pattern `{}`", p.source))
+                }
+                Ok(_) => {}
+                Err(e) => return Err(format!("bash_deny pattern `{}` is invalid: {e}", p.source)),
+            }
+        }
+        let allowed = self
+            .allow
+            .iter()
+            .any(|p| p.regex.as_ref().is_ok_and(|re| re.is_match(command)));
+        if !allowed && DESTRUCTIVE.iter().any(|re| re.is_match(command)) {
+            return Err(
+                "command looks destructive; add a bash_allow pattern for it to run it".into(),
+            );
+        }
+        if !allowed && !self.allow.is_empty() {
+            return Err("command matches no bash_allow pattern".into());
+        }
+        Ok(())
+    }
+}
+
+/// Runs shell commands, subject to a [`BashPolicy`].
+///
+/// `BashTool` used to be a unit struct; `BashTool::default()` replaces
+/// `BashTool` and runs under [`BashPolicy::refuse_destructive`].
+#[derive(Default)]
+pub struct BashTool {
+    policy: BashPolicy,
+    format: ToolOutputFormat,
+}
+
+impl BashTool {
+    pub fn new(policy: BashPolicy) -> Self {
+        Self {
+            policy,
+            format: ToolOutputFormat::default(),
+        }
+    }
+
+    /// How binary and JSON output is shown to the model.
+    pub fn with_format(mut self, format: ToolOutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
 
 #[async_trait]
 impl Tool for BashTool {
@@ -24,10 +145,22 @@ impl Tool for BashTool {
             "required": ["command"]
         })
     }
+    /// Commands already carry their own `timeout_secs`; the agent-level limit
+    /// only has to catch the process wrapper itself hanging, so give it room.
+    fn timeout_ms(&self, default: u64) -> u64 {
+        if default == 0 {
+            0
+        } else {
+            default.max(BASH_MIN_TIMEOUT_MS)
+        }
+    }
     async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
         let command = args["command"]
             .as_str()
             .ok_or_else(|| anyhow::anyhow!("Missing 'command' argument"))?;
+        if let Err(reason) = self.policy.check(command) {
+            return Ok(ToolResult::err(format!("Refused to run: {reason}")));
+        }
         let timeout_secs = args["timeout_secs"].as_u64().unwrap_or(30);
         let output = tokio::time::timeout(
             std::time::Duration::from_secs(timeout_secs),
@@ -38,14 +171,14 @@ impl Tool for BashTool {
         .map_err(|e| anyhow::anyhow!("Failed to execute command: {}", e))?;
         let mut content = String::new();
         if !output.stdout.is_empty() {
-            content.push_str(&String::from_utf8_lossy(&output.stdout));
+            content.push_str(&self.format.output(&output.stdout));
         }
         if !output.stderr.is_empty() {
             if !content.is_empty() {
                 content.push('\n');
             }
             content.push_str("stderr: ");
-            content.push_str(&String::from_utf8_lossy(&output.stderr));
+            content.push_str(&self.format.output(&output.stderr));
         }
         let is_error = !output.status.success();
         if content.is_empty() {
@@ -55,6 +188,71 @@ impl Tool for BashTool {
                 "(no output)".to_string()
             };
         }
-        Ok(ToolResult { content, is_error })
+        Ok(ToolResult {
+            content,
+            is_error,
+            images: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str]) -> BashPolicy {
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        BashPolicy::new(&strings(allow), &strings(deny))
+    }
+
+    #[test]
+    fn allow_list_admits_only_matching_commands() {
+        let p = policy(&["^git (status|diff)$", "^cargo "], &[]);
+        assert!(p.check("git status").is_ok());
+        assert!(p.check("cargo test --workspace").is_ok());
+        assert!(p.check("curl example.com").is_err());
+        assert!(p.check("git push").is_err());
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let p = policy(&["^git "], &[r"\bpush\b"]);
+        assert!(p.check("git log").is_ok());
+        let err = p.check("git push --force").unwrap_err();
+        assert!(err.contains("bash_deny"), "{err}");
+        assert!(policy(&[], &["["]).check("ls").is_err());
+    }
+
+    #[test]
+    fn destructive_commands_need_an_explicit_allow() {
+        let p = BashPolicy::refuse_destructive();
+        for cmd in [
+            "rm -rf /",
+            "sudo rm -rf --no-preserve-root /",
+            "rm -fr ~/",
+            "rm -rf /*",
+            "mkfs.ext4 /dev/sda1",
+            "dd if=/dev/zero of=/dev/sda",
+            ":(){ :|:& };:",
+        ] {
+            assert!(p.check(cmd).is_err(), "{cmd} should be refused");
+        }
+        assert!(p.check("rm -rf ./target").is_ok());
+        assert!(p.check("rm -rf /tmp/build").is_ok());
+        assert!(policy(&[r"^rm -rf /$"], &[]).check("rm -rf /").is_ok());
+    }
+
+    #[tokio::test]
+    async fn refused_commands_are_not_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("ran");
+        let tool = BashTool::new(policy(&[], &["touch"]));
+        let result = tool
+            .call(json!({ "command": format!("touch {}", marker.display()) }))
+            .await
+            .unwrap();
+        assert!(result.is_error);
+        assert!(result.content.starts_with("Refused to run"));
+        assert!(!marker.exists());
     }
 }
@@ -1,5 +1,8 @@
 use crate::agents::agent::{Agent, KrabsAgentBuilder};
 use crate::agents::base_agent::BaseAgent;
+use crate::agents::minikrabs::{
+    budget_exhausted, ProgressHook, ProgressSink, TokenBudget, UsageLedger,
+};
 use crate::config::config::KrabsConfig;
 use crate::memory::memory::InMemoryStore;
 use crate::permissions::PermissionGuard;
@@ -29,6 +32,9 @@ pub struct DelegateTool {
     provider: Arc<dyn LlmProvider>,
     registry: ToolRegistry,
     permissions: PermissionGuard,
+    budget: Option<TokenBudget>,
+    progress: Option<ProgressSink>,
+    usage: Option<UsageLedger>,
 }
 
 impl DelegateTool {
@@ -43,9 +49,30 @@ impl DelegateTool {
             provider,
             registry,
             permissions,
+            budget: None,
+            progress: None,
+            usage: None,
         }
     }
 
+    /// Share a token budget with every sub-agent this tool spawns.
+    pub fn with_budget(mut self, budget: Option<TokenBudget>) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Stream each sub-agent's tool calls to `sink`, labelled with its profile name.
+    pub fn with_progress(mut self, sink: Option<ProgressSink>) -> Self {
+        self.progress = sink;
+        self
+    }
+
+    /// Report each sub-agent's token usage to `ledger` as `delegate:<profile>`.
+    pub fn with_usage(mut self, ledger: Option<UsageLedger>) -> Self {
+        self.usage = ledger;
+        self
+    }
+
     /// Resolve a profile name to a `BaseAgent` variant.
     fn resolve_profile(name: &str) -> Option<BaseAgent> {
         BaseAgent::all().iter().find(|a| a.name() == name).copied()
@@ -81,6 +108,11 @@ impl Tool for DelegateTool {
         })
     }
 
+    /// A sub-agent run is bounded by its own turn limit and token budget.
+    fn timeout_ms(&self, _default: u64) -> u64 {
+        0
+    }
+
     async fn call(&self, args: Value) -> Result<ToolResult> {
         let profile_name = args["profile"]
             .as_str()
@@ -99,14 +131,37 @@ impl Tool for DelegateTool {
             )
         })?;
 
-        let agent = KrabsAgentBuilder::new(self.config.clone(), Arc::clone(&self.provider))
+        if budget_exhausted(self.budget.as_ref()) {
+            return Ok(ToolResult::err(
+                "sub-agent token budget exhausted — finish the task without delegating",
+            ));
+        }
+
+        let mut builder = KrabsAgentBuilder::new(self.config.clone(), Arc::clone(&self.provider))
             .registry(self.registry.clone())
             .memory(InMemoryStore::new())
             .permissions(self.permissions.clone())
-            .system_prompt(profile.system_prompt())
-            .build();
+            .system_prompt(profile.system_prompt());
+        if let Some(budget) = &self.budget {
+            builder = builder.token_budget(Arc::clone(budget));
+        }
+        if let Some(sink) = &self.progress {
+            builder = builder.hook(Arc::new(ProgressHook::new(profile_name, sink.clone())));
+        }
+        if let Some(ledger) = &self.usage {
+            builder = builder.report_usage(ledger.clone(), format!("delegate:{profile_name}"));
+        }
+        let agent = builder.build();
 
-        let output = Agent::run(agent.as_ref(), task).await?;
+        let output = match Agent::run(agent.as_ref(), task).await {
+            Ok(output) => output,
+            Err(e) if budget_exhausted(self.budget.as_ref()) => {
+                return Ok(ToolResult::err(format!(
+                    "{profile_name} sub-agent stopped: {e}"
+                )));
+            }
+            Err(e) => return Err(e),
+        };
 
         Ok(ToolResult {
             content: format!(
@@ -114,6 +169,7 @@ impl Tool for DelegateTool {
                 profile_name, output.tool_calls_made, output.result
             ),
             is_error: false,
+            images: Vec::new(),
         })
     }
 }
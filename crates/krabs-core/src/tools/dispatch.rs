@@ -1,5 +1,9 @@
-use crate::agents::agent::{Agent, KrabsAgentBuilder};
+use crate::agents::agent::{Agent, KrabsAgent, KrabsAgentBuilder};
 use crate::agents::base_agent::BaseAgent;
+use crate::agents::minikrabs::{
+    acquire_slot, budget_exhausted, ProgressHook, ProgressSink, SubAgentLimit, TokenBudget,
+    UsageLedger,
+};
 use crate::config::config::KrabsConfig;
 use crate::memory::memory::InMemoryStore;
 use crate::permissions::PermissionGuard;
@@ -10,11 +14,19 @@ use anyhow::Result;
 use async_trait::async_trait;
 use serde_json::Value;
 use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Sub-agents running at once in map mode when `concurrency` is not given.
+const DEFAULT_MAP_CONCURRENCY: usize = 4;
+
+/// Placeholder in `task_template` replaced by each item.
+const ITEM_PLACEHOLDER: &str = "{item}";
 
 /// Dispatch multiple sub-agent tasks concurrently and return all results.
 ///
 /// Unlike `delegate` (which is one-shot and sequential), `dispatch` spawns every
-/// listed task as an independent Tokio task. All sub-agents run in parallel; the
+/// listed task as an independent Tokio task. Sub-agents run in parallel, at most
+/// `max_concurrent_subagents` at a time when a [`SubAgentLimit`] is set; the
 /// tool blocks until the last one finishes and returns aggregated results.
 ///
 /// Typical use-case: fan out an exploration across several directories at once,
@@ -30,11 +42,26 @@ use std::sync::Arc;
 ///   ]
 /// }
 /// ```
+///
+/// Map mode runs one prompt over a list of inputs instead, with at most
+/// `concurrency` sub-agents alive at once, and returns the results keyed by item:
+/// ```json
+/// {
+///   "task_template": "summarize {item} in three bullet points",
+///   "items": ["src/main.rs", "src/lib.rs"],
+///   "profile": "explorer",
+///   "concurrency": 4
+/// }
+/// ```
 pub struct DispatchTool {
     config: KrabsConfig,
     provider: Arc<dyn LlmProvider>,
     registry: ToolRegistry,
     permissions: PermissionGuard,
+    budget: Option<TokenBudget>,
+    limit: Option<SubAgentLimit>,
+    progress: Option<ProgressSink>,
+    usage: Option<UsageLedger>,
 }
 
 impl DispatchTool {
@@ -49,12 +76,191 @@ impl DispatchTool {
             provider,
             registry,
             permissions,
+            budget: None,
+            limit: None,
+            progress: None,
+            usage: None,
         }
     }
 
+    /// Cap how many sub-agents run at once, across every tool sharing `limit`.
+    /// Sub-agents beyond it queue, and the wait is reported to the progress sink.
+    pub fn with_limit(mut self, limit: Option<SubAgentLimit>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Share a token budget with every sub-agent this tool spawns.
+    pub fn with_budget(mut self, budget: Option<TokenBudget>) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Stream each sub-agent's tool calls to `sink`, labelled with its profile name.
+    pub fn with_progress(mut self, sink: Option<ProgressSink>) -> Self {
+        self.progress = sink;
+        self
+    }
+
+    /// Report each sub-agent's token usage to `ledger` as `dispatch:<profile>`.
+    pub fn with_usage(mut self, ledger: Option<UsageLedger>) -> Self {
+        self.usage = ledger;
+        self
+    }
+
     fn resolve_profile(name: &str) -> Option<BaseAgent> {
         BaseAgent::all().iter().find(|a| a.name() == name).copied()
     }
+
+    fn unknown_profile(context: &str, name: &str) -> anyhow::Error {
+        let available: Vec<&str> = BaseAgent::all().iter().map(|a| a.name()).collect();
+        anyhow::anyhow!(
+            "{context}: unknown profile '{name}'. Available: {}",
+            available.join(", ")
+        )
+    }
+
+    fn tool_allow_list(value: &Value) -> Option<Vec<String>> {
+        value.as_array().map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect()
+        })
+    }
+
+    /// Build a sub-agent for `profile`, restricted to `tool_allow_list` when given.
+    fn build_sub_agent(
+        &self,
+        profile: BaseAgent,
+        tool_allow_list: Option<&[String]>,
+    ) -> Arc<KrabsAgent> {
+        let registry = match tool_allow_list {
+            Some(allowed) => {
+                let mut r = ToolRegistry::new();
+                for name in allowed {
+                    if let Some(tool) = self.registry.get(name) {
+                        r.register(tool);
+                    }
+                }
+                r
+            }
+            None => self.registry.clone(),
+        };
+
+        let mut builder = KrabsAgentBuilder::new(self.config.clone(), Arc::clone(&self.provider))
+            .registry(registry)
+            .memory(InMemoryStore::new())
+            .permissions(self.permissions.clone())
+            .system_prompt(profile.system_prompt());
+        if let Some(budget) = self.budget.clone() {
+            builder = builder.token_budget(budget);
+        }
+        if let Some(sink) = self.progress.clone() {
+            builder = builder.hook(Arc::new(ProgressHook::new(profile.name(), sink)));
+        }
+        if let Some(ledger) = self.usage.clone() {
+            builder = builder.report_usage(ledger, format!("dispatch:{}", profile.name()));
+        }
+        builder.build()
+    }
+
+    /// Map mode: run `task_template` once per item with bounded concurrency.
+    /// A failed item is reported in its own section; the rest still run.
+    async fn call_map(&self, args: &Value) -> Result<ToolResult> {
+        let template = args["task_template"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("'task_template' is required with 'items'"))?;
+        let items: Vec<String> = args["items"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("'items' must be an array of strings"))?
+            .iter()
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect();
+
+        if items.is_empty() {
+            return Ok(ToolResult::ok(
+                "dispatch called with no items — nothing to do.",
+            ));
+        }
+
+        let profile_name = args["profile"].as_str().unwrap_or("explorer");
+        let profile = Self::resolve_profile(profile_name)
+            .ok_or_else(|| Self::unknown_profile("map", profile_name))?;
+        let tool_allow_list = Self::tool_allow_list(&args["tools"]);
+        let concurrency = args["concurrency"]
+            .as_u64()
+            .map_or(DEFAULT_MAP_CONCURRENCY, |n| n as usize)
+            .max(1);
+
+        if budget_exhausted(self.budget.as_ref()) {
+            return Ok(ToolResult::err(
+                "sub-agent token budget exhausted — finish the task without dispatching",
+            ));
+        }
+
+        let semaphore = Arc::new(Semaphore::new(concurrency));
+        let mut handles = Vec::with_capacity(items.len());
+        for item in &items {
+            let agent = self.build_sub_agent(profile, tool_allow_list.as_deref());
+            let task = render_template(template, item);
+            let semaphore = Arc::clone(&semaphore);
+            let budget = self.budget.clone();
+            let limit = self.limit.clone();
+            let progress = self.progress.clone();
+
+            handles.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await?;
+                let sink = progress.as_ref().map(|s| (profile.name(), s));
+                let _slot = acquire_slot(limit.as_ref(), sink).await?;
+                // Items queued behind the limit may find the budget already spent.
+                if budget_exhausted(budget.as_ref()) {
+                    anyhow::bail!("skipped — sub-agent token budget exhausted");
+                }
+                Agent::run(agent.as_ref(), &task).await
+            }));
+        }
+
+        // Collect results in item order.
+        let mut failed = 0;
+        let mut sections: Vec<String> = Vec::with_capacity(handles.len());
+        for (item, handle) in items.iter().zip(handles) {
+            match handle.await {
+                Ok(Ok(output)) => sections.push(format!(
+                    "### {item} ({} tool call(s))\n{}",
+                    output.tool_calls_made, output.result
+                )),
+                Ok(Err(e)) => {
+                    failed += 1;
+                    sections.push(format!("### {item}\n[ERROR] {e}"));
+                }
+                Err(join_err) => {
+                    failed += 1;
+                    sections.push(format!("### {item}\n[ERROR] task panicked: {join_err}"));
+                }
+            }
+        }
+
+        Ok(ToolResult {
+            content: format!(
+                "{} of {} item(s) succeeded\n\n{}",
+                items.len() - failed,
+                items.len(),
+                sections.join("\n\n")
+            ),
+            is_error: failed == items.len(),
+            images: Vec::new(),
+        })
+    }
+}
+
+/// Substitute `item` for every `{item}` in `template`, or append it when the
+/// template has no placeholder.
+fn render_template(template: &str, item: &str) -> String {
+    if template.contains(ITEM_PLACEHOLDER) {
+        template.replace(ITEM_PLACEHOLDER, item)
+    } else {
+        format!("{template}\n\nItem: {item}")
+    }
 }
 
 #[async_trait]
@@ -65,12 +271,14 @@ impl Tool for DispatchTool {
 
     fn description(&self) -> &str {
         "Dispatch multiple sub-agent tasks concurrently. \
-         All tasks start at the same time and run in parallel. \
+         Tasks run in parallel, up to the configured sub-agent limit. \
          Each task can optionally specify which tools the sub-agent is allowed to use — \
          useful when the planner wants to restrict or grant specific capabilities \
          (e.g. give an explorer only read tools, give a builder write access too). \
          If tools is omitted the sub-agent inherits the full tool registry. \
-         Returns all results once every task completes."
+         Alternatively pass task_template + items to run the same prompt over each item \
+         (map mode, e.g. summarize each of these files); {item} in the template is \
+         replaced by the item. Returns all results once every task completes."
     }
 
     fn parameters(&self) -> Value {
@@ -105,21 +313,55 @@ impl Tool for DispatchTool {
                         },
                         "required": ["profile", "task"]
                     }
+                },
+                "task_template": {
+                    "type": "string",
+                    "description": "Map mode: prompt run once per item; {item} is replaced by the item."
+                },
+                "items": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Map mode: inputs to substitute into task_template, one sub-agent each."
+                },
+                "profile": {
+                    "type": "string",
+                    "description": "Map mode: agent profile for every item. Default: explorer."
+                },
+                "tools": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Map mode: optional allow-list of tool names for every item."
+                },
+                "concurrency": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": format!(
+                        "Map mode: maximum sub-agents running at once. Default: {DEFAULT_MAP_CONCURRENCY}."
+                    )
                 }
-            },
-            "required": ["tasks"]
+            }
         })
     }
 
+    /// Sub-agent runs are bounded by their own turn limits and token budget.
+    fn timeout_ms(&self, _default: u64) -> u64 {
+        0
+    }
+
     async fn call(&self, args: Value) -> Result<ToolResult> {
+        if !args["items"].is_null() {
+            return self.call_map(&args).await;
+        }
+
         let task_list = args["tasks"]
             .as_array()
-            .ok_or_else(|| anyhow::anyhow!("'tasks' must be an array"))?;
+            .ok_or_else(|| anyhow::anyhow!("'tasks' must be an array (or pass 'items')"))?;
 
         if task_list.is_empty() {
             return Ok(ToolResult {
                 content: "dispatch called with empty task list — nothing to do.".into(),
                 is_error: false,
+                images: Vec::new(),
             });
         }
 
@@ -140,20 +382,9 @@ impl Tool for DispatchTool {
             let task = entry["task"]
                 .as_str()
                 .ok_or_else(|| anyhow::anyhow!("tasks[{}].task is required", i))?;
-            let profile = Self::resolve_profile(profile_name).ok_or_else(|| {
-                let available: Vec<&str> = BaseAgent::all().iter().map(|a| a.name()).collect();
-                anyhow::anyhow!(
-                    "tasks[{}]: unknown profile '{}'. Available: {}",
-                    i,
-                    profile_name,
-                    available.join(", ")
-                )
-            })?;
-            let tool_allow_list = entry["tools"].as_array().map(|arr| {
-                arr.iter()
-                    .filter_map(|v| v.as_str().map(String::from))
-                    .collect::<Vec<_>>()
-            });
+            let profile = Self::resolve_profile(profile_name)
+                .ok_or_else(|| Self::unknown_profile(&format!("tasks[{i}]"), profile_name))?;
+            let tool_allow_list = Self::tool_allow_list(&entry["tools"]);
             specs.push(TaskSpec {
                 profile,
                 profile_name: profile_name.to_string(),
@@ -162,40 +393,26 @@ impl Tool for DispatchTool {
             });
         }
 
-        // Spawn all sub-agents concurrently.
+        if budget_exhausted(self.budget.as_ref()) {
+            return Ok(ToolResult::err(
+                "sub-agent token budget exhausted — finish the task without dispatching",
+            ));
+        }
+
+        // Spawn all sub-agents concurrently, up to the shared limit.
         let mut handles = Vec::with_capacity(specs.len());
         for spec in specs {
-            let config = self.config.clone();
-            let provider = Arc::clone(&self.provider);
-            let full_registry = self.registry.clone();
-            let permissions = self.permissions.clone();
-
-            let handle = tokio::spawn(async move {
-                // Build a filtered registry if the planner specified an allow-list.
-                let registry = if let Some(ref allowed) = spec.tool_allow_list {
-                    let mut r = ToolRegistry::new();
-                    for name in allowed {
-                        if let Some(tool) = full_registry.get(name) {
-                            r.register(tool);
-                        }
-                    }
-                    r
-                } else {
-                    full_registry
+            let agent = self.build_sub_agent(spec.profile, spec.tool_allow_list.as_deref());
+            let limit = self.limit.clone();
+            let progress = self.progress.clone();
+            handles.push(tokio::spawn(async move {
+                let sink = progress.as_ref().map(|s| (spec.profile.name(), s));
+                let result = match acquire_slot(limit.as_ref(), sink).await {
+                    Ok(_slot) => Agent::run(agent.as_ref(), &spec.task).await,
+                    Err(e) => Err(e),
                 };
-
-                let agent = KrabsAgentBuilder::new(config, provider)
-                    .registry(registry)
-                    .memory(InMemoryStore::new())
-                    .permissions(permissions)
-                    .system_prompt(spec.profile.system_prompt())
-                    .build();
-
-                let result = Agent::run(agent.as_ref(), &spec.task).await;
                 (spec.profile_name, spec.task, result)
-            });
-
-            handles.push(handle);
+            }));
         }
 
         // Collect results in dispatch order.
@@ -220,6 +437,94 @@ impl Tool for DispatchTool {
         Ok(ToolResult {
             content: sections.join("\n\n"),
             is_error: false,
+            images: Vec::new(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::minikrabs::subagent_limit;
+    use crate::providers::provider::{LlmResponse, Message, StreamChunk, TokenUsage};
+    use crate::tools::tool::ToolDef;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    /// Answers after a short delay, recording how many calls overlap.
+    #[derive(Default)]
+    struct Slow {
+        in_flight: AtomicUsize,
+        max_seen: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LlmProvider for Slow {
+        async fn complete(&self, _: &[Message], _: &[ToolDef]) -> Result<LlmResponse> {
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_seen.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(LlmResponse::Message {
+                content: "done".into(),
+                usage: TokenUsage::default(),
+            })
+        }
+
+        async fn stream_complete(
+            &self,
+            _: &[Message],
+            _: &[ToolDef],
+            _: mpsc::Sender<StreamChunk>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn sub_agents_never_exceed_the_limit() {
+        let slow = Arc::new(Slow::default());
+        let (tx, mut rx) = mpsc::channel(64);
+        let tool = DispatchTool::new(
+            KrabsConfig::default(),
+            Arc::clone(&slow) as Arc<dyn LlmProvider>,
+            ToolRegistry::new(),
+            PermissionGuard::new(),
+        )
+        .with_limit(subagent_limit(2))
+        .with_progress(Some(tx));
+
+        let tasks: Vec<Value> = (0..6)
+            .map(|i| serde_json::json!({"profile": "explorer", "task": format!("task {i}")}))
+            .collect();
+        let out = tool
+            .call(serde_json::json!({ "tasks": tasks }))
+            .await
+            .unwrap();
+
+        assert_eq!(out.content.matches("(0 tool call(s))").count(), 6);
+        assert_eq!(slow.max_seen.load(Ordering::SeqCst), 2);
+        let mut queued = 0;
+        while let Ok(p) = rx.try_recv() {
+            queued += usize::from(p.text.starts_with("queued"));
+        }
+        assert!(queued > 0, "waiting sub-agents report their queue position");
+    }
+
+    #[test]
+    fn template_placeholder_is_substituted() {
+        assert_eq!(
+            render_template("summarize {item} briefly", "src/lib.rs"),
+            "summarize src/lib.rs briefly"
+        );
+    }
+
+    #[test]
+    fn template_without_placeholder_gets_item_appended() {
+        assert_eq!(
+            render_template("summarize this file", "a.rs"),
+            "summarize this file\n\nItem: a.rs"
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use crate::config::KrabsConfig;
+use serde_json::Value;
+
+/// Bytes of a binary output shown as a hexdump by default.
+pub const DEFAULT_HEXDUMP_BYTES: usize = 256;
+
+/// JSON larger than this is shown as its top-level structure by default.
+pub const DEFAULT_JSON_OUTLINE_BYTES: usize = 64 * 1024;
+
+/// Hexdump bytes per line.
+const HEXDUMP_WIDTH: usize = 16;
+
+/// How `read` and `bash` turn raw bytes into text for the model: binary data
+/// becomes a summary and an optional hexdump, JSON is pretty-printed or, when
+/// huge, outlined.
+#[derive(Debug, Clone)]
+pub struct ToolOutputFormat {
+    /// Leading bytes of binary data shown as a hexdump; 0 = none.
+    pub hexdump_bytes: usize,
+    /// Pretty-print JSON output.
+    pub pretty_json: bool,
+    /// JSON larger than this (in bytes) is shown as its top-level structure
+    /// only; 0 = never.
+    pub json_outline_bytes: usize,
+}
+
+impl Default for ToolOutputFormat {
+    fn default() -> Self {
+        Self {
+            hexdump_bytes: DEFAULT_HEXDUMP_BYTES,
+            pretty_json: true,
+            json_outline_bytes: DEFAULT_JSON_OUTLINE_BYTES,
+        }
+    }
+}
+
+impl ToolOutputFormat {
+    pub fn from_config(config: &KrabsConfig) -> Self {
+        Self {
+            hexdump_bytes: config.tool_output_hexdump_bytes,
+            pretty_json: config.tool_output_pretty_json,
+            json_outline_bytes: config.tool_output_json_outline_bytes,
+        }
+    }
+
+    /// `bytes` as text, or `None` when they aren't valid UTF-8.
+    pub fn text(bytes: &[u8]) -> Option<&str> {
+        std::str::from_utf8(bytes).ok()
+    }
+
+    /// `"binary <what>, N bytes, type <mime>"`, followed by a hexdump of the
+    /// first [`hexdump_bytes`](Self::hexdump_bytes).
+    pub fn binary(&self, what: &str, bytes: &[u8]) -> String {
+        let mut out = format!(
+            "binary {what}, {} bytes, type {}",
+            bytes.len(),
+            sniff_mime(bytes)
+        );
+        let shown = bytes.len().min(self.hexdump_bytes);
+        if shown > 0 {
+            out.push_str(&format!("\nfirst {shown} bytes:\n"));
+            out.push_str(&hexdump(&bytes[..shown]));
+        }
+        out
+    }
+
+    /// Command output: a [`binary`](Self::binary) summary unless it is
+    /// UTF-8, then [`json`](Self::json) if it parses as JSON, else as is.
+    pub fn output(&self, bytes: &[u8]) -> String {
+        match Self::text(bytes) {
+            Some(text) => self.json(text).unwrap_or_else(|| text.to_string()),
+            None => self.binary("output", bytes),
+        }
+    }
+
+    /// `text` reformatted if it is a JSON object or array: its
+    /// [`json_outline`](Self::json_outline) if it has one, else pretty-printed
+    /// if [`pretty_json`](Self::pretty_json). `None` when it isn't JSON or
+    /// there is nothing to change.
+    pub fn json(&self, text: &str) -> Option<String> {
+        if let Some(outline) = self.json_outline(text) {
+            return Some(outline);
+        }
+        if !self.pretty_json {
+            return None;
+        }
+        let value = parse_json(text)?;
+        serde_json::to_string_pretty(&value).ok()
+    }
+
+    /// The top-level structure of `text` if it is a JSON object or array
+    /// over [`json_outline_bytes`](Self::json_outline_bytes).
+    pub fn json_outline(&self, text: &str) -> Option<String> {
+        let len = text.trim().len();
+        if self.json_outline_bytes == 0 || len <= self.json_outline_bytes {
+            return None;
+        }
+        parse_json(text).map(|value| outline(&value, len))
+    }
+}
+
+/// `text` parsed, if it is a JSON object or array.
+fn parse_json(text: &str) -> Option<Value> {
+    let trimmed = text.trim();
+    if !(trimmed.starts_with('{') || trimmed.starts_with('[')) {
+        return None;
+    }
+    serde_json::from_str(trimmed).ok()
+}
+
+/// The top level of a large JSON document: each key or the first items with
+/// their type and size, nested values not expanded.
+fn outline(value: &Value, len: usize) -> String {
+    const SHOWN_ITEMS: usize = 50;
+    let mut out = format!("JSON {}, {len} bytes; top-level structure:\n", shape(value));
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter().take(SHOWN_ITEMS) {
+                out.push_str(&format!("  {key:?}: {}\n", shape(v)));
+            }
+            if map.len() > SHOWN_ITEMS {
+                out.push_str(&format!("  … {} more keys\n", map.len() - SHOWN_ITEMS));
+            }
+        }
+        Value::Array(items) => {
+            for (i, v) in items.iter().take(SHOWN_ITEMS).enumerate() {
+                out.push_str(&format!("  [{i}]: {}\n", shape(v)));
+            }
+            if items.len() > SHOWN_ITEMS {
+                out.push_str(&format!("  … {} more items\n", items.len() - SHOWN_ITEMS));
+            }
+        }
+        _ => {}
+    }
+    out.push_str(
+        "[Output too large to show whole; query it for the parts you need, e.g. with jq.]",
+    );
+    out
+}
+
+/// A JSON value's type and size, or the value itself when it is short.
+fn shape(value: &Value) -> String {
+    match value {
+        Value::Object(map) => format!("object ({} keys)", map.len()),
+        Value::Array(items) => format!("array ({} items)", items.len()),
+        Value::String(s) if s.chars().count() > 40 => {
+            format!("string ({} chars)", s.chars().count())
+        }
+        other => other.to_string(),
+    }
+}
+
+/// MIME type of `bytes` from their magic number;
+/// `application/octet-stream` when unknown.
+pub fn sniff_mime(bytes: &[u8]) -> &'static str {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF8", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"BZh", "application/x-bzip2"),
+        (b"\xfd7zXZ\x00", "application/x-xz"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"\x28\xb5\x2f\xfd", "application/zstd"),
+        (b"\x7fELF", "application/x-elf"),
+        (b"MZ", "application/x-msdownload"),
+        (b"\xcf\xfa\xed\xfe", "application/x-mach-binary"),
+        (b"\x00asm", "application/wasm"),
+        (b"SQLite format 3\x00", "application/vnd.sqlite3"),
+    ];
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    MAGIC
+        .iter()
+        .find(|(magic, _)| bytes.starts_with(magic))
+        .map(|(_, mime)| *mime)
+        .unwrap_or("application/octet-stream")
+}
+
+/// `xxd`-style dump: offset, hex bytes and their printable ASCII.
+pub fn hexdump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(HEXDUMP_WIDTH)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!(
+                "{:08x}  {:<width$}  |{ascii}|",
+                i * HEXDUMP_WIDTH,
+                hex.join(" "),
+                width = HEXDUMP_WIDTH * 3 - 1
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_data_is_summarized_with_a_hexdump() {
+        let bytes = b"\x7fELF\x02\x01\x01\x00\xff\xfe rest";
+        let out = ToolOutputFormat::default().output(bytes);
+        let mut lines = out.lines();
+        assert_eq!(
+            lines.next(),
+            Some("binary output, 15 bytes, type application/x-elf")
+        );
+        assert_eq!(lines.next(), Some("first 15 bytes:"));
+        assert_eq!(
+            lines.next(),
+            Some("00000000  7f 45 4c 46 02 01 01 00 ff fe 20 72 65 73 74     |.ELF...... rest|")
+        );
+
+        let none = ToolOutputFormat {
+            hexdump_bytes: 0,
+            ..ToolOutputFormat::default()
+        };
+        assert_eq!(
+            none.binary("file", &[0xff; 3]),
+            "binary file, 3 bytes, type application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn json_is_pretty_printed_or_outlined() {
+        let format = ToolOutputFormat::default();
+        assert_eq!(format.output(b"plain text"), "plain text");
+        assert_eq!(
+            format.output(br#"{"a":[1,2]}"#),
+            "{\n  \"a\": [\n    1,\n    2\n  ]\n}"
+        );
+        assert_eq!(format.output(b"[not json"), "[not json");
+
+        let small_outline = ToolOutputFormat {
+            json_outline_bytes: 10,
+            ..ToolOutputFormat::default()
+        };
+        let out = small_outline.output(br#"{"items":[1,2,3],"name":"x","nested":{"k":1}}"#);
+        assert!(out.starts_with("JSON object (3 keys), 45 bytes"), "{out}");
+        assert!(out.contains("  \"items\": array (3 items)\n"));
+        assert!(out.contains("  \"name\": \"x\"\n"));
+        assert!(out.contains("  \"nested\": object (1 keys)\n"));
+
+        let raw = ToolOutputFormat {
+            pretty_json: false,
+            ..ToolOutputFormat::default()
+        };
+        assert_eq!(raw.output(br#"{"a":1}"#), r#"{"a":1}"#);
+    }
+
+    #[test]
+    fn sniffs_common_formats() {
+        assert_eq!(sniff_mime(b"%PDF-1.7"), "application/pdf");
+        assert_eq!(sniff_mime(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(sniff_mime(b"\x00\x01"), "application/octet-stream");
+    }
+}
@@ -0,0 +1,255 @@
+use super::tool::{Tool, ToolResult};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+use std::process::Output;
+use tokio::process::Command;
+
+/// Run `git` with `args` in the current directory.
+///
+/// `Err` carries a message fit to hand straight back to the model: git missing
+/// from PATH, or the cwd not being inside a work tree.
+async fn git(args: &[&str]) -> std::result::Result<Output, String> {
+    let inside = Command::new("git")
+        .args(["rev-parse", "--is-inside-work-tree"])
+        .output()
+        .await
+        .map_err(|e| format!("git is not available: {e}"))?;
+    if !inside.status.success() {
+        let cwd = std::env::current_dir()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| ".".into());
+        return Err(format!("{cwd} is not inside a git repository"));
+    }
+    Command::new("git")
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("failed to run git: {e}"))
+}
+
+fn git_failure(output: &Output) -> ToolResult {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    ToolResult::err(format!("git failed: {}", stderr.trim()))
+}
+
+/// Files from `git status --porcelain=v1`, grouped the way `git status` shows them.
+#[derive(Debug, Default, PartialEq)]
+struct StatusSummary {
+    staged: Vec<String>,
+    unstaged: Vec<String>,
+    untracked: Vec<String>,
+}
+
+fn parse_porcelain(porcelain: &str) -> StatusSummary {
+    let mut summary = StatusSummary::default();
+    for line in porcelain.lines() {
+        if line.len() < 4 {
+            continue;
+        }
+        let (code, path) = line.split_at(3);
+        let mut flags = code.chars();
+        let index = flags.next().unwrap_or(' ');
+        let worktree = flags.next().unwrap_or(' ');
+        if index == '?' {
+            summary.untracked.push(path.to_string());
+            continue;
+        }
+        if index != ' ' && index != '!' {
+            summary.staged.push(format!("{index} {path}"));
+        }
+        if worktree != ' ' && worktree != '!' {
+            summary.unstaged.push(format!("{worktree} {path}"));
+        }
+    }
+    summary
+}
+
+impl StatusSummary {
+    fn render(&self, branch: &str) -> String {
+        if self.staged.is_empty() && self.unstaged.is_empty() && self.untracked.is_empty() {
+            return format!("On branch {branch} — working tree clean");
+        }
+        let mut out = format!("On branch {branch}");
+        for (title, files) in [
+            ("Staged", &self.staged),
+            ("Unstaged", &self.unstaged),
+            ("Untracked", &self.untracked),
+        ] {
+            if files.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("\n\n{title} ({}):", files.len()));
+            for f in files {
+                out.push_str("\n  ");
+                out.push_str(f);
+            }
+        }
+        out
+    }
+}
+
+pub struct GitStatusTool;
+
+#[async_trait]
+impl Tool for GitStatusTool {
+    fn name(&self) -> &str {
+        "git_status"
+    }
+    fn description(&self) -> &str {
+        "Show the current git branch and the staged, unstaged, and untracked files."
+    }
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+    async fn call(&self, _args: serde_json::Value) -> Result<ToolResult> {
+        let output = match git(&["status", "--porcelain=v1", "--branch"]).await {
+            Ok(o) => o,
+            Err(msg) => return Ok(ToolResult::err(msg)),
+        };
+        if !output.status.success() {
+            return Ok(git_failure(&output));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let (header, body) = match stdout.split_once('\n') {
+            Some((first, rest)) if first.starts_with("## ") => (first, rest),
+            _ => ("", stdout.as_ref()),
+        };
+        let branch = header
+            .trim_start_matches("## ")
+            .split("...")
+            .next()
+            .filter(|b| !b.is_empty())
+            .unwrap_or("(unknown)");
+        Ok(ToolResult::ok(parse_porcelain(body).render(branch)))
+    }
+}
+
+/// One-line-per-file summary built from `git diff --numstat`.
+fn summarize_numstat(numstat: &str) -> String {
+    let mut lines = Vec::new();
+    let (mut added, mut removed) = (0u64, 0u64);
+    for line in numstat.lines() {
+        let mut cols = line.splitn(3, '\t');
+        let (Some(a), Some(d), Some(path)) = (cols.next(), cols.next(), cols.next()) else {
+            continue;
+        };
+        // Binary files report "-" for both counts.
+        match (a.parse::<u64>(), d.parse::<u64>()) {
+            (Ok(a), Ok(d)) => {
+                added += a;
+                removed += d;
+                lines.push(format!("  {path} (+{a} -{d})"));
+            }
+            _ => lines.push(format!("  {path} (binary)")),
+        }
+    }
+    format!(
+        "{} file(s) changed, +{added} -{removed}\n{}",
+        lines.len(),
+        lines.join("\n")
+    )
+}
+
+pub struct GitDiffTool;
+
+#[async_trait]
+impl Tool for GitDiffTool {
+    fn name(&self) -> &str {
+        "git_diff"
+    }
+    fn description(&self) -> &str {
+        "Show a unified diff of uncommitted changes, optionally limited to a path or to staged changes."
+    }
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Limit the diff to this file or directory" },
+                "staged": { "type": "boolean", "description": "Diff the index instead of the working tree (default: false)", "default": false }
+            }
+        })
+    }
+    async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
+        let staged = args["staged"].as_bool().unwrap_or(false);
+        let path = args["path"].as_str();
+
+        let mut common: Vec<&str> = Vec::new();
+        if staged {
+            common.push("--cached");
+        }
+        let mut pathspec: Vec<&str> = Vec::new();
+        if let Some(p) = path {
+            pathspec.extend(["--", p]);
+        }
+
+        let numstat_args: Vec<&str> = ["diff", "--numstat"]
+            .into_iter()
+            .chain(common.iter().copied())
+            .chain(pathspec.iter().copied())
+            .collect();
+        let numstat = match git(&numstat_args).await {
+            Ok(o) => o,
+            Err(msg) => return Ok(ToolResult::err(msg)),
+        };
+        if !numstat.status.success() {
+            return Ok(git_failure(&numstat));
+        }
+        let numstat = String::from_utf8_lossy(&numstat.stdout);
+        if numstat.trim().is_empty() {
+            let scope = if staged { "staged" } else { "unstaged" };
+            return Ok(ToolResult::ok(format!("No {scope} changes.")));
+        }
+
+        let diff_args: Vec<&str> = ["diff", "--no-color"]
+            .into_iter()
+            .chain(common.iter().copied())
+            .chain(pathspec.iter().copied())
+            .collect();
+        let diff = match git(&diff_args).await {
+            Ok(o) => o,
+            Err(msg) => return Ok(ToolResult::err(msg)),
+        };
+        if !diff.status.success() {
+            return Ok(git_failure(&diff));
+        }
+        Ok(ToolResult::ok(format!(
+            "{}\n\n{}",
+            summarize_numstat(&numstat),
+            String::from_utf8_lossy(&diff.stdout)
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn porcelain_groups_files() {
+        let summary = parse_porcelain("M  src/lib.rs\n M README.md\nMM Cargo.toml\n?? notes.txt\n");
+        assert_eq!(summary.staged, vec!["M src/lib.rs", "M Cargo.toml"]);
+        assert_eq!(summary.unstaged, vec!["M README.md", "M Cargo.toml"]);
+        assert_eq!(summary.untracked, vec!["notes.txt"]);
+    }
+
+    #[test]
+    fn clean_tree_renders_clean() {
+        let summary = parse_porcelain("");
+        assert_eq!(
+            summary.render("main"),
+            "On branch main — working tree clean"
+        );
+    }
+
+    #[test]
+    fn numstat_summary_counts_lines_and_binaries() {
+        let summary = summarize_numstat("3\t1\tsrc/a.rs\n-\t-\tlogo.png\n");
+        assert!(summary.starts_with("2 file(s) changed, +3 -1"));
+        assert!(summary.contains("src/a.rs (+3 -1)"));
+        assert!(summary.contains("logo.png (binary)"));
+    }
+}
@@ -1,11 +1,85 @@
+use super::root::ProjectRoot;
 use super::tool::{Tool, ToolResult};
 use anyhow::Result;
 use async_trait::async_trait;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
 use regex::Regex;
 use serde_json::json;
-use walkdir::WalkDir;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
-pub struct GlobTool;
+/// Matches returned per call when neither the caller nor `KrabsConfig` says otherwise.
+pub const DEFAULT_MAX_RESULTS: usize = 100;
+
+/// Longer lines in `grep` output are cut so one minified file can't flood the context.
+const MAX_LINE_CHARS: usize = 500;
+
+/// Read `max_results` / `offset` from `args`, falling back to `default_limit` and 0.
+fn page_args(args: &serde_json::Value, default_limit: usize) -> (usize, usize) {
+    let limit = args["max_results"]
+        .as_u64()
+        .map(|n| n as usize)
+        .filter(|&n| n > 0)
+        .unwrap_or(default_limit);
+    let offset = args["offset"].as_u64().unwrap_or(0) as usize;
+    (limit, offset)
+}
+
+/// `Showing 1–100 of 2345 matches (more: true, next offset: 100)`.
+fn page_header(noun: &str, offset: usize, shown: usize, total: usize) -> String {
+    let next = offset + shown;
+    if shown == 0 {
+        return format!(
+            "Showing 0 of {total} {noun} (offset {offset} is past the end, more: false)"
+        );
+    }
+    if next < total {
+        format!(
+            "Showing {}–{next} of {total} {noun} (more: true, next offset: {next})",
+            offset + 1
+        )
+    } else {
+        format!(
+            "Showing {}–{next} of {total} {noun} (more: false)",
+            offset + 1
+        )
+    }
+}
+
+fn clip_line(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((end, _)) => format!("{} […]", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+pub struct GlobTool {
+    max_results: usize,
+    root: ProjectRoot,
+}
+
+impl GlobTool {
+    /// `max_results` is the page size used when the model doesn't pass one.
+    pub fn new(max_results: usize) -> Self {
+        Self {
+            max_results,
+            root: ProjectRoot::default(),
+        }
+    }
+
+    /// Resolve relative paths against `root`, and list matches relative to it.
+    pub fn with_root(mut self, root: ProjectRoot) -> Self {
+        self.root = root;
+        self
+    }
+}
+
+impl Default for GlobTool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_RESULTS)
+    }
+}
 
 #[async_trait]
 impl Tool for GlobTool {
@@ -13,42 +87,205 @@ impl Tool for GlobTool {
         "glob"
     }
     fn description(&self) -> &str {
-        "Find files matching a glob pattern."
+        "Find files matching a glob pattern. Results are paginated: use `offset` to fetch the next page."
     }
     fn parameters(&self) -> serde_json::Value {
         json!({
             "type": "object",
             "properties": {
                 "pattern": { "type": "string", "description": "Glob pattern to match files" },
-                "path": { "type": "string", "description": "Directory to search in (default: current directory)" }
+                "path": { "type": "string", "description": "Directory to search in (default: current directory)" },
+                "max_results": { "type": "integer", "description": format!("Maximum paths to return (default: {})", self.max_results) },
+                "offset": { "type": "integer", "description": "Number of paths to skip, for fetching later pages (default: 0)" }
             },
             "required": ["pattern"]
         })
     }
+    fn is_parallel_safe(&self) -> bool {
+        true
+    }
     async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
         let pattern = args["pattern"]
             .as_str()
             .ok_or_else(|| anyhow::anyhow!("Missing 'pattern' argument"))?;
         let base = args["path"].as_str().unwrap_or(".");
+        let (limit, offset) = page_args(&args, self.max_results);
         let full_pattern = if pattern.starts_with('/') {
             pattern.to_string()
         } else {
             format!("{}/{}", base, pattern)
         };
+        let full_pattern = match self.root.resolve(&full_pattern) {
+            Ok(p) => p.display().to_string(),
+            Err(e) => return Ok(ToolResult::err(e)),
+        };
         let matches: Vec<String> = glob::glob(&full_pattern)
             .map_err(|e| anyhow::anyhow!("Invalid glob pattern: {}", e))?
             .filter_map(|r| r.ok())
-            .map(|p| p.display().to_string())
+            .map(|p| self.root.display(&p).display().to_string())
             .collect();
         if matches.is_empty() {
-            Ok(ToolResult::ok("No files matched."))
+            return Ok(ToolResult::ok("No files matched."));
+        }
+        let page: Vec<&str> = matches
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .map(String::as_str)
+            .collect();
+        let header = page_header("files", offset, page.len(), matches.len());
+        Ok(ToolResult::ok(format!("{header}\n{}", page.join("\n"))))
+    }
+}
+
+pub struct GrepTool {
+    max_results: usize,
+    root: ProjectRoot,
+}
+
+impl GrepTool {
+    /// `max_results` is the page size used when the model doesn't pass one.
+    pub fn new(max_results: usize) -> Self {
+        Self {
+            max_results,
+            root: ProjectRoot::default(),
+        }
+    }
+
+    /// Resolve relative paths against `root`, and show matches relative to it.
+    pub fn with_root(mut self, root: ProjectRoot) -> Self {
+        self.root = root;
+        self
+    }
+}
+
+impl Default for GrepTool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_RESULTS)
+    }
+}
+
+/// The `include` / `exclude` globs of a `grep` call, matched against each
+/// file's path relative to the searched directory. A pattern without a `/`
+/// matches the file name at any depth, so `*.rs` still means every Rust file.
+#[derive(Default)]
+struct FileFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+impl FileFilter {
+    fn new(include: Option<&str>, exclude: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            include: include.map(glob_set).transpose()?,
+            exclude: exclude.map(glob_set).transpose()?,
+        })
+    }
+
+    fn accepts(&self, rel: &Path) -> bool {
+        self.include.as_ref().is_none_or(|set| set.is_match(rel))
+            && !self.exclude.as_ref().is_some_and(|set| set.is_match(rel))
+    }
+}
+
+/// Compile a comma-separated list of globs (`*.rs,*.toml`) into one set.
+fn glob_set(patterns: &str) -> Result<GlobSet> {
+    let mut set = GlobSetBuilder::new();
+    for pattern in patterns.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let anchored = if pattern.contains('/') {
+            pattern.to_string()
         } else {
-            Ok(ToolResult::ok(matches.join("\n")))
+            format!("**/{pattern}")
+        };
+        let glob = GlobBuilder::new(&anchored)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| anyhow::anyhow!("Invalid glob '{}': {}", pattern, e))?;
+        set.add(glob);
+    }
+    Ok(set.build()?)
+}
+
+/// `path` relative to the search root `root`; a root that is itself a file
+/// is matched by its file name.
+fn relative_to<'a>(path: &'a Path, root: &Path) -> &'a Path {
+    match path.strip_prefix(root) {
+        Ok(rel) if !rel.as_os_str().is_empty() => rel,
+        _ => path.file_name().map(Path::new).unwrap_or(path),
+    }
+}
+
+/// Files under `roots` accepted by `filter`, in a stable order so that
+/// `offset` pages line up between calls. `.gitignore` and `.ignore` rules
+/// apply unless `respect_ignore` is false; `.git` itself is never searched.
+fn candidate_files(roots: &[PathBuf], filter: &FileFilter, respect_ignore: bool) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+    for root in roots {
+        let walk = WalkBuilder::new(root)
+            .standard_filters(respect_ignore)
+            .hidden(false)
+            .require_git(false)
+            .follow_links(true)
+            .filter_entry(|e| e.file_name() != ".git")
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+        for entry in walk.filter_map(|e| e.ok()) {
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let rel = relative_to(entry.path(), root);
+            if filter.accepts(rel) && seen.insert(entry.path().to_path_buf()) {
+                files.push(entry.into_path());
+            }
         }
     }
+    files
 }
 
-pub struct GrepTool;
+/// Render the matches on `lines` at `hits` (0-based, ascending) with `context`
+/// lines around each, grep-style: `path:N: line` for matches, `path-N- line`
+/// for context, and `--` between non-adjacent groups when `context` > 0.
+fn render_with_context(path: &Path, lines: &[&str], hits: &[usize], context: usize) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut last_printed: Option<usize> = None;
+    for (i, &hit) in hits.iter().enumerate() {
+        let start = hit.saturating_sub(context);
+        let end = (hit + context).min(lines.len().saturating_sub(1));
+        let from = match last_printed {
+            Some(prev) if prev + 1 >= start => prev + 1,
+            Some(_) => {
+                if context > 0 {
+                    out.push("--".to_string());
+                }
+                start
+            }
+            None => start,
+        };
+        let window = lines
+            .iter()
+            .enumerate()
+            .skip(from)
+            .take((end + 1).saturating_sub(from));
+        for (n, line) in window {
+            // A later hit inside this window is printed as a match, not context.
+            let is_hit = n == hit
+                || hits[i + 1..]
+                    .iter()
+                    .take_while(|&&h| h <= end)
+                    .any(|&h| h == n);
+            let sep = if is_hit { ':' } else { '-' };
+            out.push(format!(
+                "{}{sep}{}{sep} {}",
+                path.display(),
+                n + 1,
+                clip_line(line)
+            ));
+        }
+        last_printed = Some(last_printed.map_or(end, |p| p.max(end)));
+    }
+    out
+}
 
 #[async_trait]
 impl Tool for GrepTool {
@@ -56,7 +293,7 @@ impl Tool for GrepTool {
         "grep"
     }
     fn description(&self) -> &str {
-        "Search for a pattern in file contents."
+        "Search for a pattern in file contents. Narrow the files with `include` / `exclude` globs, or pass `paths` (e.g. from `glob`) to search only those. Files ignored by .gitignore are skipped. Results are paginated: use `offset` to fetch the next page."
     }
     fn parameters(&self) -> serde_json::Value {
         json!({
@@ -64,57 +301,253 @@ impl Tool for GrepTool {
             "properties": {
                 "pattern": { "type": "string", "description": "Regex pattern to search for" },
                 "path": { "type": "string", "description": "Directory or file to search in" },
-                "glob": { "type": "string", "description": "File glob filter (e.g. '*.rs')" },
-                "case_insensitive": { "type": "boolean", "description": "Case-insensitive search" }
+                "paths": { "type": "array", "items": { "type": "string" }, "description": "Files or directories to search instead of `path`" },
+                "include": { "type": "string", "description": "Only search files matching these globs, comma-separated, relative to the searched directory (e.g. '**/*.rs' or 'src/**,*.toml')" },
+                "exclude": { "type": "string", "description": "Skip files matching these globs, comma-separated (e.g. 'tests/**')" },
+                "glob": { "type": "string", "description": "Same as `include`" },
+                "no_ignore": { "type": "boolean", "description": "Also search files ignored by .gitignore / .ignore (default: false)" },
+                "case_insensitive": { "type": "boolean", "description": "Case-insensitive search" },
+                "context_lines": { "type": "integer", "description": "Lines of context to show around each match, like grep -C (default: 0)" },
+                "files_only": { "type": "boolean", "description": "List matching file paths only, without line content" },
+                "max_results": { "type": "integer", "description": format!("Maximum matches (or files with files_only) to return (default: {})", self.max_results) },
+                "offset": { "type": "integer", "description": "Number of matches (or files) to skip, for fetching later pages (default: 0)" }
             },
             "required": ["pattern"]
         })
     }
+    fn is_parallel_safe(&self) -> bool {
+        true
+    }
     async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
         let pattern_str = args["pattern"]
             .as_str()
             .ok_or_else(|| anyhow::anyhow!("Missing 'pattern' argument"))?;
-        let search_path = args["path"].as_str().unwrap_or(".").to_string();
-        let file_glob = args["glob"].as_str().map(|s| s.to_string());
+        let roots: Vec<String> = match args["paths"].as_array() {
+            Some(paths) if !paths.is_empty() => paths
+                .iter()
+                .filter_map(|p| p.as_str().map(String::from))
+                .collect(),
+            _ => vec![args["path"].as_str().unwrap_or(".").to_string()],
+        };
+        let roots = match roots
+            .iter()
+            .map(|r| self.root.resolve(r))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(roots) => roots,
+            Err(e) => return Ok(ToolResult::err(e)),
+        };
+        let project_root = self.root.clone();
+        let filter = FileFilter::new(
+            args["include"].as_str().or(args["glob"].as_str()),
+            args["exclude"].as_str(),
+        )?;
+        let respect_ignore = !args["no_ignore"].as_bool().unwrap_or(false);
         let case_insensitive = args["case_insensitive"].as_bool().unwrap_or(false);
+        let context = args["context_lines"].as_u64().unwrap_or(0) as usize;
+        let files_only = args["files_only"].as_bool().unwrap_or(false);
+        let (limit, offset) = page_args(&args, self.max_results);
         let re = if case_insensitive {
             Regex::new(&format!("(?i){}", pattern_str))
         } else {
             Regex::new(pattern_str)
         }
         .map_err(|e| anyhow::anyhow!("Invalid regex: {}", e))?;
-        let glob_pattern = file_glob.as_deref().map(|g| glob::Pattern::new(g).ok());
-        let results = tokio::task::spawn_blocking(move || {
-            let mut out = Vec::new();
-            for entry in WalkDir::new(search_path)
-                .follow_links(true)
-                .into_iter()
-                .filter_map(|e| e.ok())
-                .filter(|e| e.file_type().is_file())
-            {
-                if let Some(Some(ref pat)) = glob_pattern {
-                    let file_name = entry.file_name().to_string_lossy();
-                    if !pat.matches(&file_name) {
-                        continue;
-                    }
-                }
-                let path = entry.path();
-                if let Ok(content) = std::fs::read_to_string(path) {
-                    for (line_num, line) in content.lines().enumerate() {
-                        if re.is_match(line) {
-                            out.push(format!("{}:{}: {}", path.display(), line_num + 1, line));
+        let output = tokio::task::spawn_blocking(move || {
+            // Every hit as (file, line index); only the requested page is rendered.
+            let mut hits: Vec<(usize, usize)> = Vec::new();
+            let mut files: Vec<PathBuf> = Vec::new();
+            for path in candidate_files(&roots, &filter, respect_ignore) {
+                let Ok(content) = std::fs::read_to_string(&path) else {
+                    continue;
+                };
+                let before = hits.len();
+                for (line_num, line) in content.lines().enumerate() {
+                    if re.is_match(line) {
+                        hits.push((files.len(), line_num));
+                        if files_only {
+                            break;
                         }
                     }
                 }
+                if hits.len() > before {
+                    files.push(path);
+                }
             }
-            out
+            if hits.is_empty() {
+                return None;
+            }
+
+            if files_only {
+                let page: Vec<String> = files
+                    .iter()
+                    .skip(offset)
+                    .take(limit)
+                    .map(|p| project_root.display(p).display().to_string())
+                    .collect();
+                let header = page_header("files", offset, page.len(), files.len());
+                return Some(format!("{header}\n{}", page.join("\n")));
+            }
+
+            let page: Vec<(usize, usize)> = hits.iter().skip(offset).take(limit).copied().collect();
+            let mut out = vec![page_header("matches", offset, page.len(), hits.len())];
+            let mut i = 0;
+            while i < page.len() {
+                let file = page[i].0;
+                let mut file_hits = Vec::new();
+                while i < page.len() && page[i].0 == file {
+                    file_hits.push(page[i].1);
+                    i += 1;
+                }
+                let path = &files[file];
+                let Ok(content) = std::fs::read_to_string(path) else {
+                    continue;
+                };
+                let lines: Vec<&str> = content.lines().collect();
+                if context > 0 && out.len() > 1 {
+                    out.push("--".to_string());
+                }
+                out.extend(render_with_context(
+                    project_root.display(path),
+                    &lines,
+                    &file_hits,
+                    context,
+                ));
+            }
+            Some(out.join("\n"))
         })
         .await
         .unwrap_or_default();
-        if results.is_empty() {
-            Ok(ToolResult::ok("No matches found."))
-        } else {
-            Ok(ToolResult::ok(results.join("\n")))
+        match output {
+            Some(text) => Ok(ToolResult::ok(text)),
+            None => Ok(ToolResult::ok("No matches found.")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_header_reports_more_results() {
+        assert_eq!(
+            page_header("matches", 0, 100, 250),
+            "Showing 1–100 of 250 matches (more: true, next offset: 100)"
+        );
+        assert_eq!(
+            page_header("matches", 200, 50, 250),
+            "Showing 201–250 of 250 matches (more: false)"
+        );
+    }
+
+    #[test]
+    fn context_windows_merge_and_mark_hits() {
+        let lines = ["a", "b", "c", "d", "e", "f", "g", "h", "i"];
+        let out = render_with_context(Path::new("f"), &lines, &[1, 3, 8], 1);
+        assert_eq!(
+            out,
+            vec!["f-1- a", "f:2: b", "f-3- c", "f:4: d", "f-5- e", "--", "f-8- h", "f:9: i"]
+        );
+        let out = render_with_context(Path::new("f"), &lines, &[1, 8], 0);
+        assert_eq!(out, vec!["f:2: b", "f:9: i"]);
+    }
+
+    #[tokio::test]
+    async fn grep_paginates_and_lists_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "hit\nmiss\nhit\n").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "hit\n").unwrap();
+        let path = dir.path().display().to_string();
+
+        let r = GrepTool::new(2)
+            .call(json!({ "pattern": "hit", "path": path }))
+            .await
+            .unwrap();
+        let mut lines = r.content.lines();
+        assert_eq!(
+            lines.next(),
+            Some("Showing 1–2 of 3 matches (more: true, next offset: 2)")
+        );
+        assert_eq!(lines.count(), 2);
+
+        let r = GrepTool::new(2)
+            .call(json!({ "pattern": "hit", "path": path, "offset": 2 }))
+            .await
+            .unwrap();
+        assert!(r
+            .content
+            .starts_with("Showing 3–3 of 3 matches (more: false)"));
+        assert!(r.content.ends_with("b.txt:1: hit"));
+
+        let r = GrepTool::default()
+            .call(json!({ "pattern": "hit", "path": path, "files_only": true }))
+            .await
+            .unwrap();
+        assert!(r
+            .content
+            .starts_with("Showing 1–2 of 2 files (more: false)"));
+        assert!(!r.content.contains(": hit"));
+    }
+
+    #[tokio::test]
+    async fn grep_narrows_files_by_glob_paths_and_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for sub in ["src", "vendor"] {
+            std::fs::create_dir(root.join(sub)).unwrap();
+        }
+        std::fs::write(root.join(".gitignore"), "gen.rs\n").unwrap();
+        for file in ["src/a.rs", "src/b.txt", "vendor/c.rs", "gen.rs"] {
+            std::fs::write(root.join(file), "hit\n").unwrap();
+        }
+        let files = |args: serde_json::Value| async move {
+            let r = GrepTool::default().call(args).await.unwrap();
+            let mut names: Vec<String> = r
+                .content
+                .lines()
+                .skip(1)
+                .filter_map(|l| Path::new(l).file_name())
+                .map(|n| n.to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        let path = root.display().to_string();
+
+        let rs = json!({ "pattern": "hit", "path": path, "files_only": true,
+            "include": "**/*.rs", "exclude": "vendor/**" });
+        assert_eq!(files(rs).await, ["a.rs"]);
+
+        let all = json!({ "pattern": "hit", "path": path, "files_only": true, "no_ignore": true,
+            "include": "*.rs" });
+        assert_eq!(files(all).await, ["a.rs", "c.rs", "gen.rs"]);
+
+        let listed = json!({ "pattern": "hit", "files_only": true, "paths": [
+            root.join("src/b.txt").display().to_string(),
+            root.join("vendor").display().to_string(),
+        ] });
+        assert_eq!(files(listed).await, ["b.txt", "c.rs"]);
+    }
+
+    #[tokio::test]
+    async fn glob_respects_max_results() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.rs", "b.rs", "c.rs"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
         }
+        let r = GlobTool::default()
+            .call(json!({
+                "pattern": "*.rs",
+                "path": dir.path().display().to_string(),
+                "max_results": 1,
+                "offset": 1,
+            }))
+            .await
+            .unwrap();
+        assert!(r
+            .content
+            .starts_with("Showing 2–2 of 3 files (more: true, next offset: 2)"));
+        assert!(r.content.ends_with("b.rs"));
     }
 }
@@ -0,0 +1,348 @@
+use super::tool::{Tool, ToolResult};
+use crate::memory::{MemoryScope, MemoryStore, ScopedMemory};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+
+/// The `scope` argument of a `memory_*` call: the scope it names, or both
+/// (session first) when it is omitted and `optional`.
+fn scopes(args: &serde_json::Value, optional: bool) -> Result<Vec<MemoryScope>> {
+    match args["scope"].as_str() {
+        Some(scope) => Ok(vec![MemoryScope::parse(scope)?]),
+        None if optional => Ok(vec![MemoryScope::Session, MemoryScope::Global]),
+        None => anyhow::bail!("Missing 'scope' argument"),
+    }
+}
+
+fn scope_param(what: &str) -> serde_json::Value {
+    json!({
+        "type": "string",
+        "enum": ["session", "global"],
+        "description": what
+    })
+}
+
+/// Writes a note to the session's or the global memory.
+pub struct MemoryWriteTool {
+    memory: ScopedMemory,
+}
+
+impl MemoryWriteTool {
+    pub fn new(memory: ScopedMemory) -> Self {
+        Self { memory }
+    }
+}
+
+#[async_trait]
+impl Tool for MemoryWriteTool {
+    fn name(&self) -> &str {
+        "memory_write"
+    }
+    fn description(&self) -> &str {
+        "Save a note under a short key. Use scope 'global' for lasting facts about the user \
+         or their preferences (e.g. 'prefers tabs over spaces') — they are shown to you in \
+         every later session — and 'session' for notes about the current task only. \
+         Overwrites any note with the same key in that scope."
+    }
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "key": { "type": "string", "description": "Short identifier, e.g. 'indentation'" },
+                "value": { "type": "string", "description": "The note to store" },
+                "scope": scope_param("'global' (every session) or 'session' (this one only)")
+            },
+            "required": ["key", "value", "scope"]
+        })
+    }
+    async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
+        let key = args["key"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing 'key' argument"))?;
+        let value = args["value"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing 'value' argument"))?;
+        let scope = scopes(&args, false)?[0];
+        self.memory.store(scope).set(key, value).await?;
+        Ok(ToolResult::ok(format!(
+            "Saved '{key}' to {} memory.",
+            scope.as_str()
+        )))
+    }
+}
+
+/// Reads one note from the session's or the global memory.
+pub struct MemoryReadTool {
+    memory: ScopedMemory,
+}
+
+impl MemoryReadTool {
+    pub fn new(memory: ScopedMemory) -> Self {
+        Self { memory }
+    }
+}
+
+#[async_trait]
+impl Tool for MemoryReadTool {
+    fn name(&self) -> &str {
+        "memory_read"
+    }
+    fn description(&self) -> &str {
+        "Read the note saved with `memory_write` under a key. Without a scope the session's \
+         note is returned if there is one, else the global one."
+    }
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "key": { "type": "string", "description": "Key of the note" },
+                "scope": scope_param("Where to look (omit for both)")
+            },
+            "required": ["key"]
+        })
+    }
+    async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
+        let key = args["key"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing 'key' argument"))?;
+        for scope in scopes(&args, true)? {
+            if let Some(value) = self.memory.store(scope).get(key).await? {
+                return Ok(ToolResult::ok(value));
+            }
+        }
+        Ok(ToolResult::ok(format!("No note stored under '{key}'.")))
+    }
+    fn is_parallel_safe(&self) -> bool {
+        true
+    }
+}
+
+/// Finds notes in the session's or the global memory by text.
+pub struct MemorySearchTool {
+    memory: ScopedMemory,
+}
+
+impl MemorySearchTool {
+    pub fn new(memory: ScopedMemory) -> Self {
+        Self { memory }
+    }
+}
+
+#[async_trait]
+impl Tool for MemorySearchTool {
+    fn name(&self) -> &str {
+        "memory_search"
+    }
+    fn description(&self) -> &str {
+        "Find notes saved with `memory_write` whose key or text contains the query \
+         (case-insensitive). An empty query lists every note in the scope."
+    }
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "Text to look for" },
+                "scope": scope_param("Where to look (omit for both)")
+            },
+            "required": ["query"]
+        })
+    }
+    async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
+        let query = args["query"].as_str().unwrap_or("");
+        let mut lines = Vec::new();
+        for scope in scopes(&args, true)? {
+            for (key, value) in self.memory.search(scope, query).await? {
+                lines.push(format!("[{}] {key}: {value}", scope.as_str()));
+            }
+        }
+        if lines.is_empty() {
+            return Ok(ToolResult::ok(format!("No notes match '{query}'.")));
+        }
+        Ok(ToolResult::ok(lines.join("\n")))
+    }
+    fn is_parallel_safe(&self) -> bool {
+        true
+    }
+}
+
+/// `remember`: the older name for a global-scope `memory_write`, kept so
+/// prompts and skills written against it still work.
+pub struct RememberTool {
+    memory: ScopedMemory,
+}
+
+impl RememberTool {
+    pub fn new(memory: ScopedMemory) -> Self {
+        Self { memory }
+    }
+}
+
+#[async_trait]
+impl Tool for RememberTool {
+    fn name(&self) -> &str {
+        "remember"
+    }
+    fn description(&self) -> &str {
+        "Save a durable note under a short key so it can be recalled in later sessions. \
+         Same as `memory_write` with scope 'global'. Overwrites any existing note with the \
+         same key."
+    }
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "key": { "type": "string", "description": "Short identifier, e.g. 'test_command'" },
+                "value": { "type": "string", "description": "The fact or note to store" }
+            },
+            "required": ["key", "value"]
+        })
+    }
+    async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
+        let key = args["key"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing 'key' argument"))?;
+        let value = args["value"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing 'value' argument"))?;
+        self.memory
+            .store(MemoryScope::Global)
+            .set(key, value)
+            .await?;
+        Ok(ToolResult::ok(format!("Remembered '{key}'.")))
+    }
+}
+
+/// `recall`: reads the global notes `remember` (or `memory_write` with scope
+/// 'global') saved — one key, or every note.
+pub struct RecallTool {
+    memory: ScopedMemory,
+}
+
+impl RecallTool {
+    pub fn new(memory: ScopedMemory) -> Self {
+        Self { memory }
+    }
+}
+
+#[async_trait]
+impl Tool for RecallTool {
+    fn name(&self) -> &str {
+        "recall"
+    }
+    fn description(&self) -> &str {
+        "Recall notes saved with `remember`. Pass a key to fetch one note, or omit it to list every note."
+    }
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "key": { "type": "string", "description": "Key of the note to fetch (omit to list all)" }
+            }
+        })
+    }
+    async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
+        if let Some(key) = args["key"].as_str() {
+            return Ok(
+                match self.memory.store(MemoryScope::Global).get(key).await? {
+                    Some(value) => ToolResult::ok(value),
+                    None => ToolResult::ok(format!("No note stored under '{key}'.")),
+                },
+            );
+        }
+        let notes = self.memory.search(MemoryScope::Global, "").await?;
+        if notes.is_empty() {
+            return Ok(ToolResult::ok("No notes stored yet."));
+        }
+        let lines: Vec<String> = notes
+            .into_iter()
+            .map(|(key, value)| format!("{key}: {value}"))
+            .collect();
+        Ok(ToolResult::ok(lines.join("\n")))
+    }
+    fn is_parallel_safe(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::InMemoryStore;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn notes_stay_in_their_scope() {
+        let memory = ScopedMemory::new(
+            Arc::new(InMemoryStore::new()),
+            Arc::new(InMemoryStore::new()),
+        );
+        let write = MemoryWriteTool::new(memory.clone());
+        let read = MemoryReadTool::new(memory.clone());
+        let search = MemorySearchTool::new(memory.clone());
+
+        write
+            .call(json!({ "key": "indent", "value": "tabs", "scope": "global" }))
+            .await
+            .unwrap();
+        write
+            .call(json!({ "key": "indent", "value": "2 spaces in this repo", "scope": "session" }))
+            .await
+            .unwrap();
+        assert!(write
+            .call(json!({ "key": "k", "value": "v" }))
+            .await
+            .is_err());
+
+        let got = read
+            .call(json!({ "key": "indent", "scope": "global" }))
+            .await
+            .unwrap();
+        assert_eq!(got.content, "tabs");
+        // Unscoped reads prefer the session's note.
+        let got = read.call(json!({ "key": "indent" })).await.unwrap();
+        assert_eq!(got.content, "2 spaces in this repo");
+
+        let found = search.call(json!({ "query": "TABS" })).await.unwrap();
+        assert_eq!(found.content, "[global] indent: tabs");
+        let found = search
+            .call(json!({ "query": "", "scope": "session" }))
+            .await
+            .unwrap();
+        assert_eq!(found.content, "[session] indent: 2 spaces in this repo");
+    }
+
+    #[tokio::test]
+    async fn remember_and_recall_use_the_global_scope() {
+        let memory = ScopedMemory::new(
+            Arc::new(InMemoryStore::new()),
+            Arc::new(InMemoryStore::new()),
+        );
+        let remember = RememberTool::new(memory.clone());
+        let recall = RecallTool::new(memory.clone());
+
+        remember
+            .call(json!({ "key": "test_command", "value": "cargo test" }))
+            .await
+            .unwrap();
+        assert_eq!(
+            memory
+                .store(MemoryScope::Global)
+                .get("test_command")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("cargo test")
+        );
+        assert!(memory
+            .store(MemoryScope::Session)
+            .get("test_command")
+            .await
+            .unwrap()
+            .is_none());
+
+        let got = recall.call(json!({ "key": "test_command" })).await.unwrap();
+        assert_eq!(got.content, "cargo test");
+        let all = recall.call(json!({})).await.unwrap();
+        assert_eq!(all.content, "test_command: cargo test");
+    }
+}
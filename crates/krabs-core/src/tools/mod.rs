@@ -1,10 +1,16 @@
 pub mod bash;
 pub mod delegate;
 pub mod dispatch;
+pub mod format;
+pub mod git;
 pub mod glob;
+pub mod memory;
+pub mod patch;
 pub mod read;
 pub mod read_skill;
 pub mod registry;
+pub mod root;
+pub mod sysinfo;
 pub mod tool;
 pub mod user_input;
 pub mod web_fetch;
@@ -12,6 +18,7 @@ pub mod write;
 
 pub use delegate::DelegateTool;
 pub use dispatch::DispatchTool;
+pub use format::ToolOutputFormat;
 pub use read_skill::ReadSkillTool;
 pub use registry::ToolRegistry;
 pub use tool::{Tool, ToolDef, ToolResult};
@@ -0,0 +1,569 @@
+use super::root::ProjectRoot;
+use super::tool::{Tool, ToolResult};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+use std::path::PathBuf;
+
+/// Suffix of the sibling file a patched file is staged in before the rename.
+const STAGING_SUFFIX: &str = ".krabs-patch";
+
+#[derive(Debug, PartialEq)]
+enum Line {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug)]
+struct Hunk {
+    header: String,
+    old_start: usize,
+    lines: Vec<Line>,
+}
+
+impl Hunk {
+    /// Lines the hunk expects to find (context + removals).
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|l| match l {
+                Line::Context(s) | Line::Remove(s) => Some(s.as_str()),
+                Line::Add(_) => None,
+            })
+            .collect()
+    }
+
+    /// Lines the hunk leaves behind (context + additions).
+    fn new_lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|l| match l {
+            Line::Context(s) | Line::Add(s) => Some(s.as_str()),
+            Line::Remove(_) => None,
+        })
+    }
+}
+
+/// One file section of a unified diff. `None` paths mean `/dev/null`.
+#[derive(Debug)]
+struct FilePatch {
+    old_path: Option<String>,
+    new_path: Option<String>,
+    hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    /// The path written (or, for a deletion, removed) by this section.
+    fn target(&self) -> &str {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+/// Parse a `---`/`+++` header path: drop a trailing timestamp and a git-style
+/// `a/` or `b/` prefix.
+fn parse_path(raw: &str) -> Option<String> {
+    let raw = raw.split('\t').next().unwrap_or(raw).trim();
+    if raw == "/dev/null" {
+        return None;
+    }
+    let stripped = raw
+        .strip_prefix("a/")
+        .or_else(|| raw.strip_prefix("b/"))
+        .unwrap_or(raw);
+    Some(stripped.to_string())
+}
+
+/// Parse `-l[,s]` or `+l[,s]` from a hunk header; the count defaults to 1.
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    let mut parts = range[1..].splitn(2, ',');
+    let start = parts.next()?.parse().ok()?;
+    let count = match parts.next() {
+        Some(c) => c.parse().ok()?,
+        None => 1,
+    };
+    Some((start, count))
+}
+
+/// `@@ -12,5 +12,6 @@ fn section` → `(12, 5, 6)`.
+fn parse_hunk_header(line: &str) -> Option<(usize, usize, usize)> {
+    let mut fields = line.strip_prefix("@@ ")?.split_whitespace();
+    let (old_start, old_count) = parse_range(fields.next().filter(|f| f.starts_with('-'))?)?;
+    let (_, new_count) = parse_range(fields.next().filter(|f| f.starts_with('+'))?)?;
+    Some((old_start, old_count, new_count))
+}
+
+/// Split a unified diff into per-file sections. Lines outside file headers and
+/// hunks (`diff --git`, `index …`, commentary) are ignored.
+fn parse(patch: &str) -> std::result::Result<Vec<FilePatch>, String> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut lines = patch.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        if let Some(old) = line.strip_prefix("--- ") {
+            let Some(new) = lines.next().and_then(|l| l.strip_prefix("+++ ")) else {
+                return Err(format!("expected a '+++' line after '{line}'"));
+            };
+            files.push(FilePatch {
+                old_path: parse_path(old),
+                new_path: parse_path(new),
+                hunks: Vec::new(),
+            });
+        } else if line.starts_with("@@") {
+            let Some(file) = files.last_mut() else {
+                return Err(format!("hunk '{line}' appears before any file header"));
+            };
+            let (old_start, mut old_left, mut new_left) =
+                parse_hunk_header(line).ok_or_else(|| format!("malformed hunk header '{line}'"))?;
+            let mut hunk = Hunk {
+                header: line.to_string(),
+                old_start,
+                lines: Vec::new(),
+            };
+            // Hunk bodies are consumed by count, so a removed line that happens
+            // to start with "--- " is not mistaken for a file header.
+            while old_left > 0 || new_left > 0 {
+                let Some(body) = lines.next() else {
+                    return Err(format!("hunk '{line}' in {} is truncated", file.target()));
+                };
+                let mut chars = body.chars();
+                let tag = chars.next();
+                let text = chars.as_str().to_string();
+                match tag {
+                    // Editors often strip the lone space of an empty context line.
+                    Some(' ') | None if old_left > 0 && new_left > 0 => {
+                        old_left -= 1;
+                        new_left -= 1;
+                        hunk.lines.push(Line::Context(text));
+                    }
+                    Some('-') if old_left > 0 => {
+                        old_left -= 1;
+                        hunk.lines.push(Line::Remove(text));
+                    }
+                    Some('+') if new_left > 0 => {
+                        new_left -= 1;
+                        hunk.lines.push(Line::Add(text));
+                    }
+                    Some('\\') => {}
+                    _ => {
+                        return Err(format!(
+                            "unexpected line '{body}' in hunk '{line}' of {}",
+                            file.target()
+                        ))
+                    }
+                }
+            }
+            file.hunks.push(hunk);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Every path a patch touches, for sandbox write checks.
+pub fn patch_paths(patch: &str) -> Vec<String> {
+    parse(patch)
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|f| [f.old_path, f.new_path])
+        .flatten()
+        .collect()
+}
+
+/// Apply `hunks` to `original`. Each hunk must match exactly; it is searched
+/// for from the end of the previous hunk, nearest its stated line first.
+fn apply_hunks(path: &str, original: &str, hunks: &[Hunk]) -> std::result::Result<String, String> {
+    let source: Vec<&str> = original.lines().collect();
+    let mut out: Vec<&str> = Vec::with_capacity(source.len());
+    let mut cursor = 0;
+
+    for (idx, hunk) in hunks.iter().enumerate() {
+        let old = hunk.old_lines();
+        // A pure insertion (`-n,0`) goes after line n rather than at it.
+        let stated = if old.is_empty() {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        let expected = stated.max(cursor);
+        let last_start = source.len().checked_sub(old.len());
+        let matches_at = |pos: usize| source[pos..pos + old.len()] == old[..];
+
+        let found = match last_start {
+            Some(last) if expected <= last => (0..=last - cursor)
+                .flat_map(|d| [expected.checked_add(d), expected.checked_sub(d)])
+                .flatten()
+                .filter(|&p| p >= cursor && p <= last)
+                .find(|&p| matches_at(p)),
+            Some(last) => (cursor..=last).rev().find(|&p| matches_at(p)),
+            None => None,
+        };
+        let Some(pos) = found else {
+            return Err(mismatch(path, idx, hunk, &source, expected, &old));
+        };
+
+        out.extend_from_slice(&source[cursor..pos]);
+        out.extend(hunk.new_lines());
+        cursor = pos + old.len();
+    }
+    out.extend_from_slice(&source[cursor..]);
+
+    let eol = if original.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut text = out.join(eol);
+    if !text.is_empty() && (original.is_empty() || original.ends_with('\n')) {
+        text.push_str(eol);
+    }
+    Ok(text)
+}
+
+/// Describe why hunk `idx` does not apply at its stated position.
+fn mismatch(
+    path: &str,
+    idx: usize,
+    hunk: &Hunk,
+    source: &[&str],
+    expected: usize,
+    old: &[&str],
+) -> String {
+    let prefix = format!(
+        "hunk {} ({}) of {path} does not apply",
+        idx + 1,
+        hunk.header
+    );
+    for (i, want) in old.iter().enumerate() {
+        match source.get(expected + i) {
+            Some(got) if got == want => {}
+            Some(got) => {
+                return format!(
+                    "{prefix}: line {} is `{got}`, expected `{want}`",
+                    expected + i + 1
+                )
+            }
+            None => {
+                return format!(
+                    "{prefix}: file ends at line {}, expected `{want}`",
+                    source.len()
+                )
+            }
+        }
+    }
+    format!("{prefix}: context not found after the previous hunk")
+}
+
+/// What applying one file section will do to disk.
+enum Change {
+    Write { path: PathBuf, content: String },
+    Delete { path: PathBuf },
+}
+
+/// Read the current file and compute its patched contents without writing.
+/// Both header paths resolve through `root`. Returns the changes to make and a
+/// one-letter status (`A`/`M`/`D`/`R`).
+async fn plan(
+    file: &FilePatch,
+    root: &ProjectRoot,
+) -> std::result::Result<(Vec<Change>, char), String> {
+    let target = file.target();
+    if target.is_empty() {
+        return Err("file header names /dev/null on both sides".into());
+    }
+    let target_path = root.resolve(target)?;
+    let old_path = file
+        .old_path
+        .as_deref()
+        .map(|old| root.resolve(old))
+        .transpose()?;
+
+    let original = match (&file.old_path, &old_path) {
+        (Some(old), Some(path)) => tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| format!("Failed to read {old}: {e}"))?,
+        _ => {
+            if tokio::fs::try_exists(&target_path).await.unwrap_or(false) {
+                return Err(format!("{target} already exists but the patch creates it"));
+            }
+            String::new()
+        }
+    };
+    let content = apply_hunks(target, &original, &file.hunks)?;
+    let write = Change::Write {
+        path: target_path,
+        content,
+    };
+
+    Ok(match (&file.old_path, old_path, &file.new_path) {
+        (Some(old), Some(path), None) => {
+            if matches!(&write, Change::Write { content, .. } if !content.is_empty()) {
+                return Err(format!(
+                    "patch deletes {old} but its hunks leave content behind"
+                ));
+            }
+            (vec![Change::Delete { path }], 'D')
+        }
+        (Some(old), Some(path), Some(new)) if old != new => {
+            (vec![write, Change::Delete { path }], 'R')
+        }
+        (None, ..) => (vec![write], 'A'),
+        _ => (vec![write], 'M'),
+    })
+}
+
+fn staging_path(path: &std::path::Path) -> PathBuf {
+    let mut staged = path.as_os_str().to_owned();
+    staged.push(STAGING_SUFFIX);
+    PathBuf::from(staged)
+}
+
+/// Write every change to a staging file first, then rename them all into
+/// place, so a failed write leaves the originals untouched.
+async fn commit(changes: &[Change]) -> std::result::Result<(), String> {
+    let mut staged: Vec<PathBuf> = Vec::new();
+    for change in changes {
+        let Change::Write { path, content } = change else {
+            continue;
+        };
+        let tmp = staging_path(path);
+        let written = async {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&tmp, content).await
+        }
+        .await;
+        if let Err(e) = written {
+            for t in &staged {
+                let _ = tokio::fs::remove_file(t).await;
+            }
+            return Err(format!("Failed to write {}: {e}", path.display()));
+        }
+        staged.push(tmp);
+    }
+
+    for change in changes {
+        let (path, result) = match change {
+            Change::Write { path, .. } => (path, tokio::fs::rename(staging_path(path), path).await),
+            Change::Delete { path } => (path, tokio::fs::remove_file(path).await),
+        };
+        result.map_err(|e| format!("Failed to update {}: {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct ApplyPatchTool {
+    root: ProjectRoot,
+}
+
+impl ApplyPatchTool {
+    /// Resolve the patch's file paths against `root`.
+    pub fn with_root(mut self, root: ProjectRoot) -> Self {
+        self.root = root;
+        self
+    }
+}
+
+#[async_trait]
+impl Tool for ApplyPatchTool {
+    fn name(&self) -> &str {
+        "apply_patch"
+    }
+    fn description(&self) -> &str {
+        "Apply a unified diff (as produced by git diff or diff -u), possibly spanning several \
+         files. Every hunk is checked against the current file contents before anything is \
+         written, so either the whole patch applies or no file changes. Use /dev/null as the \
+         old or new path to create or delete a file."
+    }
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "patch": { "type": "string", "description": "Unified diff text with ---/+++ file headers and @@ hunks" }
+            },
+            "required": ["patch"]
+        })
+    }
+    async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
+        let patch = args["patch"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing 'patch' argument"))?;
+        let files = match parse(patch) {
+            Ok(f) if f.is_empty() => {
+                return Ok(ToolResult::err("No ---/+++ file headers found in patch"))
+            }
+            Ok(f) => f,
+            Err(e) => return Ok(ToolResult::err(format!("Invalid patch: {e}"))),
+        };
+
+        for (i, file) in files.iter().enumerate() {
+            if files[..i].iter().any(|f| f.target() == file.target()) {
+                return Ok(ToolResult::err(format!(
+                    "{} appears more than once in the patch",
+                    file.target()
+                )));
+            }
+        }
+
+        // Validate every hunk of every file before touching disk.
+        let mut changes = Vec::with_capacity(files.len());
+        let mut summary = Vec::with_capacity(files.len());
+        for file in &files {
+            match plan(file, &self.root).await {
+                Ok((planned, status)) => {
+                    changes.extend(planned);
+                    summary.push(format!(
+                        "  {status} {} ({} hunk(s))",
+                        file.target(),
+                        file.hunks.len()
+                    ));
+                }
+                Err(e) => return Ok(ToolResult::err(e)),
+            }
+        }
+
+        if let Err(e) = commit(&changes).await {
+            return Ok(ToolResult::err(e));
+        }
+        Ok(ToolResult::ok(format!(
+            "Patched {} file(s):\n{}",
+            files.len(),
+            summary.join("\n")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path_str(dir: &tempfile::TempDir, name: &str) -> String {
+        dir.path().join(name).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn parses_git_headers_and_counts() {
+        let files = parse(
+            "diff --git a/src/a.rs b/src/a.rs\nindex 1..2 100644\n--- a/src/a.rs\n+++ b/src/a.rs\n@@ -1,2 +1,2 @@\n keep\n--- not a header\n+++ nor this\n",
+        )
+        .unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].target(), "src/a.rs");
+        assert_eq!(
+            files[0].hunks[0].lines,
+            vec![
+                Line::Context("keep".into()),
+                Line::Remove("-- not a header".into()),
+                Line::Add("++ nor this".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_multiple_hunks_with_offsets() {
+        let original = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let files =
+            parse("--- x\n+++ x\n@@ -1,2 +1,2 @@\n-a\n+A\n b\n@@ -5,2 +5,3 @@\n e\n+E2\n f\n")
+                .unwrap();
+        let out = apply_hunks("x", original, &files[0].hunks).unwrap();
+        assert_eq!(out, "A\nb\nc\nd\ne\nE2\nf\ng\nh\n");
+
+        // Stated line numbers off by two still apply.
+        let shifted = format!("0\n0\n{original}");
+        let out = apply_hunks("x", &shifted, &files[0].hunks).unwrap();
+        assert_eq!(out, "0\n0\nA\nb\nc\nd\ne\nE2\nf\ng\nh\n");
+    }
+
+    #[test]
+    fn mismatch_names_the_failing_hunk() {
+        let files = parse("--- x\n+++ x\n@@ -2,1 +2,1 @@\n-q\n+Q\n").unwrap();
+        let err = apply_hunks("x", "a\nb\n", &files[0].hunks).unwrap_err();
+        assert!(err.contains("hunk 1 (@@ -2,1 +2,1 @@) of x"), "{err}");
+        assert!(err.contains("line 2 is `b`, expected `q`"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn failing_hunk_leaves_every_file_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (path_str(&dir, "a.txt"), path_str(&dir, "b.txt"));
+        std::fs::write(&a, "one\ntwo\n").unwrap();
+        std::fs::write(&b, "three\n").unwrap();
+
+        let patch = format!(
+            "--- {a}\n+++ {a}\n@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n--- {b}\n+++ {b}\n@@ -1 +1 @@\n-nope\n+NOPE\n"
+        );
+        let result = ApplyPatchTool::default()
+            .call(json!({ "patch": patch }))
+            .await
+            .unwrap();
+        assert!(result.is_error);
+        assert!(result.content.contains("hunk 1"), "{}", result.content);
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "one\ntwo\n");
+        assert!(!std::path::Path::new(&format!("{a}{STAGING_SUFFIX}")).exists());
+    }
+
+    #[tokio::test]
+    async fn creates_modifies_and_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (keep, gone, new) = (
+            path_str(&dir, "keep.txt"),
+            path_str(&dir, "gone.txt"),
+            path_str(&dir, "sub/new.txt"),
+        );
+        std::fs::write(&keep, "x\n").unwrap();
+        std::fs::write(&gone, "bye\n").unwrap();
+
+        let patch = format!(
+            "--- {keep}\n+++ {keep}\n@@ -1 +1,2 @@\n x\n+y\n\
+             --- {gone}\n+++ /dev/null\n@@ -1 +0,0 @@\n-bye\n\
+             --- /dev/null\n+++ {new}\n@@ -0,0 +1 @@\n+hello\n"
+        );
+        let result = ApplyPatchTool::default()
+            .call(json!({ "patch": patch }))
+            .await
+            .unwrap();
+        assert!(!result.is_error, "{}", result.content);
+        assert_eq!(std::fs::read_to_string(&keep).unwrap(), "x\ny\n");
+        assert!(!std::path::Path::new(&gone).exists());
+        assert_eq!(std::fs::read_to_string(&new).unwrap(), "hello\n");
+        assert!(result.content.contains("Patched 3 file(s)"));
+    }
+
+    #[tokio::test]
+    async fn resolves_paths_against_the_project_root() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+        let tool = ApplyPatchTool::default().with_root(ProjectRoot::new(dir.path(), true));
+
+        let result = tool
+            .call(json!({ "patch": "--- a/a.txt\n+++ b/a.txt\n@@ -1 +1 @@\n-one\n+ONE\n" }))
+            .await
+            .unwrap();
+        assert!(!result.is_error, "{}", result.content);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+            "ONE\n"
+        );
+
+        let result = tool
+            .call(json!({ "patch": "--- /dev/null\n+++ b/../escape.txt\n@@ -0,0 +1 @@\n+x\n" }))
+            .await
+            .unwrap();
+        assert!(result.is_error);
+        assert!(
+            result.content.contains("outside the project root"),
+            "{}",
+            result.content
+        );
+        assert!(!dir.path().join("../escape.txt").exists());
+    }
+
+    #[test]
+    fn patch_paths_lists_both_sides() {
+        let paths = patch_paths("--- a/old.rs\n+++ b/new.rs\n@@ -1 +1 @@\n-x\n+y\n");
+        assert_eq!(paths, vec!["old.rs", "new.rs"]);
+    }
+}
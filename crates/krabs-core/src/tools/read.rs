@@ -1,9 +1,121 @@
+use super::format::ToolOutputFormat;
+use super::root::ProjectRoot;
 use super::tool::{Tool, ToolResult};
+use crate::providers::provider::ImageContent;
 use anyhow::Result;
 use async_trait::async_trait;
+use base64::Engine;
 use serde_json::json;
+use std::path::Path;
 
-pub struct ReadTool;
+/// Largest image `read` will attach; providers reject bigger inline images.
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Text files larger than this are not returned whole when no line range is
+/// given; the model gets the first [`PREVIEW_LINES`] and is asked for a range.
+const MAX_WHOLE_FILE_BYTES: usize = 256 * 1024;
+
+/// Lines returned from a large file read without a range.
+const PREVIEW_LINES: usize = 200;
+
+/// MIME type for image files the model can be shown, by extension.
+fn image_mime_type(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Attach the image at `resolved`; `path` is how the model named it.
+async fn read_image(resolved: &Path, path: &str, mime_type: &str) -> ToolResult {
+    let bytes = match tokio::fs::read(resolved).await {
+        Ok(b) => b,
+        Err(e) => return ToolResult::err(format!("Failed to read {}: {}", path, e)),
+    };
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return ToolResult::err(format!(
+            "{} is {} bytes; images larger than {} bytes cannot be attached",
+            path,
+            bytes.len(),
+            MAX_IMAGE_BYTES
+        ));
+    }
+    let data = base64::engine::general_purpose::STANDARD.encode(&bytes);
+    ToolResult::image(
+        format!(
+            "[image {} ({}, {} bytes) attached]",
+            path,
+            mime_type,
+            bytes.len()
+        ),
+        ImageContent::new(mime_type, data),
+    )
+}
+
+/// The part of `content` the model asked for.
+///
+/// With `offset` (1-based) and/or `limit` the slice is prefixed with a
+/// `lines A-B of N` header. Without either, small files come back whole and
+/// large ones are cut to [`PREVIEW_LINES`] with a note asking for a range.
+fn select_lines(content: &str, offset: Option<u64>, limit: Option<u64>) -> Result<String, String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let total = lines.len();
+
+    if offset.is_none() && limit.is_none() {
+        if content.len() <= MAX_WHOLE_FILE_BYTES {
+            return Ok(lines.join("\n"));
+        }
+        let shown = PREVIEW_LINES.min(total);
+        return Ok(format!(
+            "lines 1-{shown} of {total}\n{}\n\n[File is {} bytes; only the first {shown} lines are shown. \
+             Call read again with `offset` and `limit` to see the rest.]",
+            lines[..shown].join("\n"),
+            content.len()
+        ));
+    }
+
+    if total == 0 {
+        return Ok(String::new());
+    }
+    let start = offset.unwrap_or(1).max(1) as usize;
+    if start > total {
+        return Err(format!(
+            "offset {start} is past the end of the file ({total} lines)"
+        ));
+    }
+    let end = match limit {
+        Some(l) => (start - 1).saturating_add(l.max(1) as usize).min(total),
+        None => total,
+    };
+    Ok(format!(
+        "lines {start}-{end} of {total}\n{}",
+        lines[start - 1..end].join("\n")
+    ))
+}
+
+#[derive(Default)]
+pub struct ReadTool {
+    root: ProjectRoot,
+    format: ToolOutputFormat,
+}
+
+impl ReadTool {
+    /// Resolve relative paths against `root`.
+    pub fn with_root(mut self, root: ProjectRoot) -> Self {
+        self.root = root;
+        self
+    }
+
+    /// How binary files and large JSON files are shown to the model.
+    pub fn with_format(mut self, format: ToolOutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
 
 #[async_trait]
 impl Tool for ReadTool {
@@ -11,36 +123,141 @@ impl Tool for ReadTool {
         "read"
     }
     fn description(&self) -> &str {
-        "Read the contents of a file."
+        "Read the contents of a file. Use `offset` and `limit` to read a range of lines from \
+         large files; files over 256 KB read without a range return only their first lines. \
+         Image files (png, jpg, gif, webp) are attached as images; other binary files are \
+         summarized with a hexdump of their start."
     }
     fn parameters(&self) -> serde_json::Value {
         json!({
             "type": "object",
             "properties": {
                 "path": { "type": "string", "description": "Path to the file to read" },
-                "offset": { "type": "integer", "description": "Line number to start reading from (1-indexed)" },
-                "limit": { "type": "integer", "description": "Maximum number of lines to read" }
+                "offset": { "type": "integer", "minimum": 1, "description": "Line number to start reading from (1-indexed)" },
+                "limit": { "type": "integer", "minimum": 1, "description": "Maximum number of lines to read" }
             },
             "required": ["path"]
         })
     }
+    fn is_parallel_safe(&self) -> bool {
+        true
+    }
     async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
         let path = args["path"]
             .as_str()
             .ok_or_else(|| anyhow::anyhow!("Missing path argument"))?;
-        let content = match tokio::fs::read_to_string(path).await {
-            Ok(c) => c,
+        let resolved = match self.root.resolve(path) {
+            Ok(p) => p,
+            Err(e) => return Ok(ToolResult::err(e)),
+        };
+        if let Some(mime_type) = image_mime_type(&resolved) {
+            return Ok(read_image(&resolved, path, mime_type).await);
+        }
+        let bytes = match tokio::fs::read(&resolved).await {
+            Ok(b) => b,
             Err(e) => return Ok(ToolResult::err(format!("Failed to read {}: {}", path, e))),
         };
-        let offset = args["offset"].as_u64().unwrap_or(1).saturating_sub(1) as usize;
-        let limit = args["limit"].as_u64().map(|l| l as usize);
-        let lines: Vec<&str> = content.lines().collect();
-        let slice = &lines[offset.min(lines.len())..];
-        let slice = if let Some(l) = limit {
-            &slice[..l.min(slice.len())]
-        } else {
-            slice
+        let Some(content) = ToolOutputFormat::text(&bytes) else {
+            return Ok(ToolResult::ok(self.format.binary("file", &bytes)));
         };
-        Ok(ToolResult::ok(slice.join("\n")))
+        let (offset, limit) = (args["offset"].as_u64(), args["limit"].as_u64());
+        // Ranges address the file's own lines, so only a whole-file read is
+        // reformatted.
+        if offset.is_none() && limit.is_none() {
+            if let Some(outline) = self.format.json_outline(content) {
+                return Ok(ToolResult::ok(outline));
+            }
+        }
+        match select_lines(content, offset, limit) {
+            Ok(text) => Ok(ToolResult::ok(text)),
+            Err(e) => Ok(ToolResult::err(format!("Failed to read {}: {}", path, e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_image_extensions() {
+        assert_eq!(image_mime_type(Path::new("shot.PNG")), Some("image/png"));
+        assert_eq!(image_mime_type(Path::new("a/b.jpeg")), Some("image/jpeg"));
+        assert_eq!(image_mime_type(Path::new("main.rs")), None);
+    }
+
+    fn numbered(n: usize) -> String {
+        (1..=n).map(|i| format!("line {i}\n")).collect()
+    }
+
+    #[test]
+    fn small_files_without_a_range_are_returned_whole() {
+        assert_eq!(select_lines("a\nb\n", None, None).unwrap(), "a\nb");
+    }
+
+    #[test]
+    fn ranges_are_sliced_with_a_header() {
+        let content = numbered(5000);
+        let out = select_lines(&content, Some(200), Some(61)).unwrap();
+        let mut lines = out.lines();
+        assert_eq!(lines.next(), Some("lines 200-260 of 5000"));
+        assert_eq!(lines.next(), Some("line 200"));
+        assert_eq!(lines.last(), Some("line 260"));
+
+        let tail = select_lines(&content, Some(4999), None).unwrap();
+        assert!(tail.starts_with("lines 4999-5000 of 5000\n"));
+        let head = select_lines(&content, None, Some(2)).unwrap();
+        assert_eq!(head, "lines 1-2 of 5000\nline 1\nline 2");
+    }
+
+    #[test]
+    fn offset_past_the_end_is_an_error() {
+        let err = select_lines(&numbered(3), Some(4), None).unwrap_err();
+        assert!(err.contains("past the end"), "{err}");
+    }
+
+    #[test]
+    fn large_files_without_a_range_are_previewed() {
+        let content = numbered(60_000);
+        assert!(content.len() > MAX_WHOLE_FILE_BYTES);
+        let out = select_lines(&content, None, None).unwrap();
+        assert!(out.starts_with("lines 1-200 of 60000\nline 1\n"));
+        assert!(out.contains("line 200\n"));
+        assert!(!out.contains("line 201\n"));
+        assert!(out.contains("`offset` and `limit`"));
+    }
+
+    #[tokio::test]
+    async fn binary_files_are_summarized() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("doc.pdf");
+        std::fs::write(&path, b"%PDF-1.7\n\xe2\xe3\xcf\xd3").unwrap();
+        let result = ReadTool::default()
+            .call(json!({ "path": path.to_str().unwrap() }))
+            .await
+            .unwrap();
+        assert!(!result.is_error);
+        assert!(
+            result
+                .content
+                .starts_with("binary file, 13 bytes, type application/pdf\n"),
+            "{}",
+            result.content
+        );
+    }
+
+    #[tokio::test]
+    async fn image_files_are_returned_as_attachments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dot.png");
+        std::fs::write(&path, [0x89, b'P', b'N', b'G']).unwrap();
+        let result = ReadTool::default()
+            .call(json!({ "path": path.to_str().unwrap() }))
+            .await
+            .unwrap();
+        assert!(!result.is_error);
+        assert_eq!(result.images.len(), 1);
+        assert_eq!(result.images[0].mime_type, "image/png");
+        assert_eq!(result.images[0].data, "iVBORw==");
     }
 }
@@ -44,7 +44,7 @@ impl Tool for ReadSkillTool {
             None => return Ok(ToolResult::err("missing required argument: skill_name")),
         };
 
-        match self.registry.load_body(name).await {
+        match self.registry.activate(name).await {
             Ok(Some(body)) => Ok(ToolResult::ok(body)),
             Ok(None) => Ok(ToolResult::err(format!("skill '{}' not found", name))),
             Err(e) => Ok(ToolResult::err(format!(
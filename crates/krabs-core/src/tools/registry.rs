@@ -1,4 +1,6 @@
+use super::root::ProjectRoot;
 use super::tool::{Tool, ToolDef};
+use crate::agents::minikrabs::{subagent_limit, token_budget, UsageLedger};
 use crate::config::KrabsConfig;
 use crate::permissions::PermissionGuard;
 use crate::providers::provider::LlmProvider;
@@ -44,15 +46,55 @@ impl ToolRegistry {
         names
     }
 
-    /// Standard Krabs tool set: bash, read, write, glob, grep, web_fetch.
+    /// Standard Krabs tool set: bash, read, write, apply_patch, glob, grep,
+    /// web_fetch, sysinfo.
     pub fn with_defaults() -> Self {
         let mut r = Self::new();
-        r.register(Arc::new(crate::tools::bash::BashTool));
-        r.register(Arc::new(crate::tools::read::ReadTool));
-        r.register(Arc::new(crate::tools::write::WriteTool));
-        r.register(Arc::new(crate::tools::glob::GlobTool));
-        r.register(Arc::new(crate::tools::glob::GrepTool));
+        r.register(Arc::new(crate::tools::bash::BashTool::new(
+            crate::tools::bash::BashPolicy::refuse_destructive(),
+        )));
+        r.register(Arc::new(crate::tools::read::ReadTool::default()));
+        r.register(Arc::new(crate::tools::write::WriteTool::default()));
+        r.register(Arc::new(crate::tools::patch::ApplyPatchTool::default()));
+        r.register(Arc::new(crate::tools::glob::GlobTool::default()));
+        r.register(Arc::new(crate::tools::glob::GrepTool::default()));
         r.register(Arc::new(crate::tools::web_fetch::WebFetchTool));
+        r.register(Arc::new(crate::tools::sysinfo::SysInfoTool::default()));
+        r
+    }
+
+    /// [`with_defaults`](Self::with_defaults) with limits taken from `config`
+    /// (the `bash` allow / deny lists, how `bash` and `read` show binary and JSON
+    /// output, the `glob` / `grep` page size, the `sysinfo` env allowlist,
+    /// `write` backups and the project root the file tools resolve against).
+    pub fn from_config(config: &KrabsConfig) -> Self {
+        let mut r = Self::with_defaults();
+        let root = ProjectRoot::from_config(config);
+        let format = crate::tools::format::ToolOutputFormat::from_config(config);
+        r.register(Arc::new(
+            crate::tools::bash::BashTool::new(crate::tools::bash::BashPolicy::from_config(config))
+                .with_format(format.clone()),
+        ));
+        r.register(Arc::new(
+            crate::tools::read::ReadTool::default()
+                .with_root(root.clone())
+                .with_format(format),
+        ));
+        r.register(Arc::new(
+            crate::tools::write::WriteTool::new(config.backup_suffix()).with_root(root.clone()),
+        ));
+        r.register(Arc::new(
+            crate::tools::patch::ApplyPatchTool::default().with_root(root.clone()),
+        ));
+        r.register(Arc::new(
+            crate::tools::glob::GlobTool::new(config.search_max_results).with_root(root.clone()),
+        ));
+        r.register(Arc::new(
+            crate::tools::glob::GrepTool::new(config.search_max_results).with_root(root),
+        ));
+        r.register(Arc::new(crate::tools::sysinfo::SysInfoTool::new(
+            config.sysinfo_env_vars.clone(),
+        )));
         r
     }
 
@@ -60,19 +102,39 @@ impl ToolRegistry {
     ///
     /// These require config, provider, and a clone of the current registry,
     /// so they must be added after the base tools are registered.
-    pub fn with_orchestration(&mut self, config: &KrabsConfig, provider: &Arc<dyn LlmProvider>) {
-        self.register(Arc::new(crate::tools::delegate::DelegateTool::new(
-            config.clone(),
-            Arc::clone(provider),
-            self.clone(),
-            PermissionGuard::new(),
-        )));
-        self.register(Arc::new(crate::tools::dispatch::DispatchTool::new(
-            config.clone(),
-            Arc::clone(provider),
-            self.clone(),
-            PermissionGuard::new(),
-        )));
+    ///
+    /// Returns the ledger their sub-agents report token usage to; hand it to
+    /// the parent agent's builder with `subagent_usage` so it counts that usage.
+    pub fn with_orchestration(
+        &mut self,
+        config: &KrabsConfig,
+        provider: &Arc<dyn LlmProvider>,
+    ) -> UsageLedger {
+        // Both tools draw from one budget so delegate + dispatch can't each spend the full limit.
+        let budget = token_budget(config.delegate_token_budget);
+        let usage = UsageLedger::new();
+        self.register(Arc::new(
+            crate::tools::delegate::DelegateTool::new(
+                config.clone(),
+                Arc::clone(provider),
+                self.clone(),
+                PermissionGuard::new(),
+            )
+            .with_budget(budget.clone())
+            .with_usage(Some(usage.clone())),
+        ));
+        self.register(Arc::new(
+            crate::tools::dispatch::DispatchTool::new(
+                config.clone(),
+                Arc::clone(provider),
+                self.clone(),
+                PermissionGuard::new(),
+            )
+            .with_budget(budget)
+            .with_limit(subagent_limit(config.max_concurrent_subagents))
+            .with_usage(Some(usage.clone())),
+        ));
+        usage
     }
 }
 
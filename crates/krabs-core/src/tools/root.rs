@@ -0,0 +1,126 @@
+use crate::config::KrabsConfig;
+use std::path::{Component, Path, PathBuf};
+
+/// Where the file tools resolve relative paths, and whether paths outside it
+/// are refused. The default has no root: paths resolve against the process
+/// working directory, as the OS would.
+#[derive(Debug, Clone, Default)]
+pub struct ProjectRoot {
+    dir: Option<PathBuf>,
+    confine: bool,
+}
+
+impl ProjectRoot {
+    /// Resolve relative paths against `dir`; with `confine`, refuse paths
+    /// that end up outside it.
+    pub fn new(dir: impl Into<PathBuf>, confine: bool) -> Self {
+        Self {
+            dir: Some(dir.into()),
+            confine,
+        }
+    }
+
+    /// The root `config` names or detects, confined if it says so.
+    pub fn from_config(config: &KrabsConfig) -> Self {
+        Self::new(config.resolved_project_root(), config.confine_to_root)
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// `path` as the tools should open it. With `confine`, a path outside the
+    /// root is an error; the check is lexical, so `..` is accounted for but
+    /// symlinks are not followed.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let Some(dir) = &self.dir else {
+            return Ok(PathBuf::from(path));
+        };
+        let resolved = normalize(&dir.join(path));
+        if self.confine && !resolved.starts_with(normalize(dir)) {
+            return Err(format!(
+                "{path} is outside the project root {}",
+                dir.display()
+            ));
+        }
+        Ok(resolved)
+    }
+
+    /// `path` as shown to the model: relative to the root when it is under it.
+    pub fn display<'a>(&self, path: &'a Path) -> &'a Path {
+        match self.dir.as_deref().map(|dir| path.strip_prefix(dir)) {
+            Some(Ok(rel)) if !rel.as_os_str().is_empty() => rel,
+            _ => path,
+        }
+    }
+}
+
+/// The nearest of `start` and its ancestors holding a `.git` or `krabs/`
+/// entry: the top of the repository or Krabs project.
+pub fn detect_project_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join(".git").exists() || dir.join("krabs").is_dir())
+        .map(Path::to_path_buf)
+}
+
+/// Drop `.` components and fold `..` into the preceding one, without
+/// touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push("..");
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_paths_resolve_against_the_root() {
+        let root = ProjectRoot::new("/repo", false);
+        assert_eq!(
+            root.resolve("src/main.rs").unwrap(),
+            Path::new("/repo/src/main.rs")
+        );
+        assert_eq!(
+            root.resolve("./a/../b.rs").unwrap(),
+            Path::new("/repo/b.rs")
+        );
+        assert_eq!(root.resolve("/etc/hosts").unwrap(), Path::new("/etc/hosts"));
+        assert_eq!(
+            root.display(Path::new("/repo/src/lib.rs")),
+            Path::new("src/lib.rs")
+        );
+        assert_eq!(ProjectRoot::default().resolve("x").unwrap(), Path::new("x"));
+    }
+
+    #[test]
+    fn confined_roots_refuse_paths_outside() {
+        let root = ProjectRoot::new("/repo", true);
+        assert!(root.resolve("src/../Cargo.toml").is_ok());
+        assert!(root.resolve("/repo/src").is_ok());
+        assert!(root.resolve("../other/file").is_err());
+        assert!(root.resolve("/etc/hosts").is_err());
+        assert!(root.resolve("/repository/x").is_err());
+    }
+
+    #[test]
+    fn detection_walks_up_to_the_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a/b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        assert_eq!(detect_project_root(&nested).as_deref(), Some(dir.path()));
+    }
+}
@@ -0,0 +1,125 @@
+use super::tool::{Tool, ToolResult};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+
+/// Environment variables `sysinfo` reports when `sysinfo_env_vars` isn't set.
+pub const DEFAULT_ENV_VARS: &[&str] = &[
+    "PATH", "HOME", "USER", "SHELL", "LANG", "LC_ALL", "TERM", "TMPDIR",
+];
+
+/// Name fragments that mark a variable as a credential. Such variables are
+/// never reported, even when allowlisted.
+const SECRET_MARKERS: &[&str] = &["KEY", "TOKEN", "SECRET", "PASSWORD"];
+
+fn looks_secret(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    SECRET_MARKERS.iter().any(|m| upper.contains(m))
+}
+
+/// Read-only facts about the machine: OS, architecture, shell, working
+/// directory and an allowlisted set of environment variables.
+pub struct SysInfoTool {
+    env_vars: Vec<String>,
+}
+
+impl SysInfoTool {
+    pub fn new(env_vars: Vec<String>) -> Self {
+        Self { env_vars }
+    }
+
+    fn render(&self, var: impl Fn(&str) -> Option<String>) -> String {
+        let shell = var("SHELL")
+            .or_else(|| var("COMSPEC"))
+            .unwrap_or_else(|| "unknown".into());
+        let cwd = std::env::current_dir()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| "unknown".into());
+        let mut out = format!(
+            "os: {}\narch: {}\nfamily: {}\nshell: {shell}\ncwd: {cwd}\n\nenv:",
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            std::env::consts::FAMILY,
+        );
+        for name in &self.env_vars {
+            if looks_secret(name) {
+                out.push_str(&format!("\n  {name}: withheld (looks like a secret)"));
+                continue;
+            }
+            match var(name) {
+                Some(value) => out.push_str(&format!("\n  {name}={value}")),
+                None => out.push_str(&format!("\n  {name}: unset")),
+            }
+        }
+        out
+    }
+}
+
+impl Default for SysInfoTool {
+    fn default() -> Self {
+        Self::new(DEFAULT_ENV_VARS.iter().map(|v| v.to_string()).collect())
+    }
+}
+
+#[async_trait]
+impl Tool for SysInfoTool {
+    fn name(&self) -> &str {
+        "sysinfo"
+    }
+    fn description(&self) -> &str {
+        "Report the OS, CPU architecture, shell, working directory and a fixed set of \
+         non-secret environment variables. Use this instead of running shell commands \
+         to find out what system you are on."
+    }
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+    async fn call(&self, _args: serde_json::Value) -> Result<ToolResult> {
+        Ok(ToolResult::ok(self.render(|name| std::env::var(name).ok())))
+    }
+    fn is_parallel_safe(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_env(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/crab".into()),
+            "SHELL" => Some("/bin/zsh".into()),
+            "GITHUB_TOKEN" | "OPENAI_API_KEY" | "db_secret" => Some("hunter2".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn reports_system_facts_and_allowlisted_vars() {
+        let tool = SysInfoTool::new(vec!["HOME".into(), "LANG".into()]);
+        let out = tool.render(fake_env);
+        assert!(out.contains(&format!("os: {}", std::env::consts::OS)));
+        assert!(out.contains("shell: /bin/zsh"));
+        assert!(out.contains("HOME=/home/crab"));
+        assert!(out.contains("LANG: unset"));
+        // Only allowlisted variables appear.
+        assert!(!out.contains("GITHUB_TOKEN"));
+    }
+
+    #[test]
+    fn secrets_are_withheld_even_when_allowlisted() {
+        let tool = SysInfoTool::new(vec![
+            "GITHUB_TOKEN".into(),
+            "OPENAI_API_KEY".into(),
+            "db_secret".into(),
+        ]);
+        let out = tool.render(fake_env);
+        assert!(!out.contains("hunter2"));
+        assert!(out.contains("GITHUB_TOKEN: withheld"));
+        assert!(out.contains("db_secret: withheld"));
+    }
+}
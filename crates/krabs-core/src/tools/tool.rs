@@ -1,3 +1,4 @@
+use crate::providers::provider::ImageContent;
 use anyhow::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -8,12 +9,39 @@ pub trait Tool: Send + Sync {
     fn description(&self) -> &str;
     fn parameters(&self) -> serde_json::Value;
     async fn call(&self, args: serde_json::Value) -> Result<ToolResult>;
+
+    /// JSON Schema that call arguments are validated against before dispatch.
+    /// Defaults to [`parameters`](Tool::parameters); return `None` to skip validation.
+    fn parameters_schema(&self) -> Option<serde_json::Value> {
+        Some(self.parameters())
+    }
+
+    /// Maximum bytes of this tool's output sent to the model.
+    /// `default` is the global `max_tool_output_bytes`; return 0 to disable truncation.
+    fn max_output_bytes(&self, default: usize) -> usize {
+        default
+    }
+
+    /// Milliseconds a single call may run before it is abandoned as hung.
+    /// `default` is the global `tool_timeout_ms`; return 0 to never time out.
+    fn timeout_ms(&self, default: u64) -> u64 {
+        default
+    }
+
+    /// Whether this tool may run concurrently with other parallel-safe calls
+    /// from the same turn. Only side-effect-free tools should return `true`.
+    fn is_parallel_safe(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolResult {
     pub content: String,
     pub is_error: bool,
+    /// Images returned alongside `content`, forwarded to vision-capable models.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageContent>,
 }
 
 impl ToolResult {
@@ -21,6 +49,7 @@ impl ToolResult {
         Self {
             content: content.into(),
             is_error: false,
+            images: Vec::new(),
         }
     }
 
@@ -28,8 +57,133 @@ impl ToolResult {
         Self {
             content: content.into(),
             is_error: true,
+            images: Vec::new(),
+        }
+    }
+
+    /// A successful result carrying one image plus a short text description.
+    pub fn image(content: impl Into<String>, image: ImageContent) -> Self {
+        Self {
+            content: content.into(),
+            is_error: false,
+            images: vec![image],
+        }
+    }
+
+    /// Cap `content` at roughly `max_bytes`, keeping the head and the last few
+    /// lines around a `[output truncated: N bytes omitted]` marker.
+    /// `max_bytes == 0` disables truncation.
+    pub fn truncated(mut self, max_bytes: usize) -> Self {
+        self.content = truncate_output(self.content, max_bytes);
+        self
+    }
+}
+
+/// Number of trailing lines kept when a tool result is truncated.
+const TRUNCATE_TAIL_LINES: usize = 5;
+
+fn truncate_output(content: String, max_bytes: usize) -> String {
+    if max_bytes == 0 || content.len() <= max_bytes {
+        return content;
+    }
+
+    // Tail: the last few lines, but never more than a quarter of the budget.
+    let mut tail_start = content.len();
+    for _ in 0..TRUNCATE_TAIL_LINES {
+        let search = &content.as_bytes()[..tail_start.saturating_sub(1)];
+        match search.iter().rposition(|&b| b == b'\n') {
+            Some(i) => tail_start = i + 1,
+            None => break,
+        }
+    }
+    let tail_budget = max_bytes / 4;
+    if content.len() - tail_start > tail_budget {
+        tail_start = content.len() - tail_budget;
+        while !content.is_char_boundary(tail_start) {
+            tail_start += 1;
+        }
+    }
+
+    let mut head_end = max_bytes.saturating_sub(content.len() - tail_start);
+    while !content.is_char_boundary(head_end) {
+        head_end -= 1;
+    }
+
+    let omitted = tail_start - head_end;
+    format!(
+        "{}\n[output truncated: {omitted} bytes omitted]\n{}",
+        &content[..head_end],
+        &content[tail_start..]
+    )
+}
+
+/// Run `tool.call(args)`, failing with `tool timed out after Nms` if it takes
+/// longer than `timeout_ms`. `timeout_ms == 0` waits indefinitely.
+pub async fn call_with_timeout(
+    tool: &dyn Tool,
+    args: serde_json::Value,
+    timeout_ms: u64,
+) -> Result<ToolResult> {
+    if timeout_ms == 0 {
+        return tool.call(args).await;
+    }
+    tokio::time::timeout(
+        std::time::Duration::from_millis(timeout_ms),
+        tool.call(args),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("tool timed out after {timeout_ms}ms"))?
+}
+
+/// Most schema violations listed in one validation error.
+const MAX_ARG_ERRORS: usize = 10;
+
+/// Check `args` against `tool.parameters_schema()`, failing with a message
+/// that lists each missing or invalid field so the model can fix its call.
+/// A schema that doesn't compile skips validation instead of blocking the tool.
+pub fn validate_args(tool: &dyn Tool, args: &serde_json::Value) -> Result<()> {
+    let Some(schema) = tool.parameters_schema() else {
+        return Ok(());
+    };
+    let validator = match jsonschema::validator_for(&schema) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!(
+                tool = tool.name(),
+                "invalid parameter schema, skipping validation: {e}"
+            );
+            return Ok(());
         }
+    };
+    let problems = schema_problems(&validator, args);
+    if problems.is_empty() {
+        return Ok(());
     }
+    anyhow::bail!(
+        "invalid arguments for tool '{}':\n{}",
+        tool.name(),
+        problems.join("\n")
+    )
+}
+
+/// Each way `value` breaks `validator`'s schema as a `- path: problem` line,
+/// at most [`MAX_ARG_ERRORS`] of them.
+pub(crate) fn schema_problems(
+    validator: &jsonschema::Validator,
+    value: &serde_json::Value,
+) -> Vec<String> {
+    validator
+        .iter_errors(value)
+        .take(MAX_ARG_ERRORS)
+        .map(|e| {
+            let path = e.instance_path.to_string();
+            if path.is_empty() {
+                format!("- {e}")
+            } else {
+                format!("- {path}: {e}")
+            }
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,3 +192,117 @@ pub struct ToolDef {
     pub description: String,
     pub parameters: serde_json::Value,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_output_is_untouched() {
+        let r = ToolResult::ok("hello").truncated(100);
+        assert_eq!(r.content, "hello");
+    }
+
+    #[test]
+    fn zero_limit_disables_truncation() {
+        let big = "x".repeat(10_000);
+        assert_eq!(ToolResult::ok(big.clone()).truncated(0).content, big);
+    }
+
+    #[test]
+    fn keeps_head_and_last_lines() {
+        let content: String = (0..1000).map(|i| format!("line {i}\n")).collect();
+        let r = ToolResult::ok(content.clone()).truncated(1000);
+        assert!(r.content.starts_with("line 0\n"));
+        assert!(r.content.ends_with("line 999\n"));
+        assert!(r.content.contains("[output truncated: "));
+        assert!(r.content.len() < content.len());
+    }
+
+    struct Sleepy;
+
+    #[async_trait]
+    impl Tool for Sleepy {
+        fn name(&self) -> &str {
+            "sleepy"
+        }
+        fn description(&self) -> &str {
+            "sleeps for `ms` milliseconds"
+        }
+        fn parameters(&self) -> serde_json::Value {
+            serde_json::json!({})
+        }
+        async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
+            let ms = args["ms"].as_u64().unwrap_or(0);
+            tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+            Ok(ToolResult::ok("awake"))
+        }
+    }
+
+    #[tokio::test]
+    async fn call_past_the_timeout_fails() {
+        let err = call_with_timeout(&Sleepy, serde_json::json!({ "ms": 5_000 }), 20)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "tool timed out after 20ms");
+    }
+
+    #[tokio::test]
+    async fn call_within_the_timeout_or_without_one_succeeds() {
+        let args = serde_json::json!({ "ms": 5 });
+        let r = call_with_timeout(&Sleepy, args.clone(), 5_000)
+            .await
+            .unwrap();
+        assert_eq!(r.content, "awake");
+        let r = call_with_timeout(&Sleepy, args, 0).await.unwrap();
+        assert_eq!(r.content, "awake");
+    }
+
+    struct Strict;
+
+    #[async_trait]
+    impl Tool for Strict {
+        fn name(&self) -> &str {
+            "strict"
+        }
+        fn description(&self) -> &str {
+            "needs a path"
+        }
+        fn parameters(&self) -> serde_json::Value {
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string" },
+                    "limit": { "type": "integer" }
+                },
+                "required": ["path"]
+            })
+        }
+        async fn call(&self, _args: serde_json::Value) -> Result<ToolResult> {
+            Ok(ToolResult::ok("ok"))
+        }
+    }
+
+    #[test]
+    fn valid_args_pass_validation() {
+        validate_args(&Strict, &serde_json::json!({ "path": "a", "limit": 3 })).unwrap();
+        validate_args(&Sleepy, &serde_json::json!({ "anything": true })).unwrap();
+    }
+
+    #[test]
+    fn invalid_args_list_every_problem() {
+        let err = validate_args(&Strict, &serde_json::json!({ "limit": "ten" }))
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("invalid arguments for tool 'strict':"));
+        assert!(err.contains("\"path\" is a required property"), "{err}");
+        assert!(err.contains("/limit:"), "{err}");
+    }
+
+    #[test]
+    fn respects_char_boundaries() {
+        let content = "é".repeat(5000);
+        let r = ToolResult::ok(content).truncated(101);
+        assert!(r.content.contains("bytes omitted]"));
+    }
+}
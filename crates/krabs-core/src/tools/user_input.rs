@@ -11,6 +11,8 @@ use crate::tools::tool::{Tool, ToolResult};
 pub enum InputMode {
     ChooseOne,
     ChooseMany,
+    /// Open question answered in a single text field; no options.
+    FreeText,
 }
 
 /// A user-input request the agent sends to the TUI.
@@ -18,6 +20,7 @@ pub struct UserInputRequest {
     pub mode: InputMode,
     pub question: String,
     /// Up to 4 options. A "custom…" option is always appended by the TUI.
+    /// Always empty for `InputMode::FreeText`.
     pub options: Vec<String>,
     /// Resolved with the user's answer text when they confirm.
     pub respond: oneshot::Sender<String>,
@@ -27,9 +30,10 @@ pub struct UserInputRequest {
 
 /// A tool that pauses the agent and asks the user a structured question.
 ///
-/// Two modes:
+/// Three modes:
 /// - `choose_one`  — radio selection: pick exactly one option or type a custom answer.
 /// - `choose_many` — checkbox selection: pick any subset and/or add a custom note.
+/// - `free_text`   — open question: the user types an answer, no options.
 ///
 /// The agent blocks until the user confirms. The answer is returned as plain text.
 pub struct UserInputTool {
@@ -52,8 +56,10 @@ impl Tool for UserInputTool {
         "Pause and ask the user a structured question before continuing. \
          Use `choose_one` when exactly one answer is needed (e.g. which database, \
          which approach). Use `choose_many` when multiple selections are valid \
-         (e.g. which features to enable). \
-         Provide 2–4 short options; a free-text custom option is always added automatically. \
+         (e.g. which features to enable). Use `free_text` for open questions with no \
+         sensible preset answers (e.g. an API endpoint or a project name). \
+         For the choice modes provide 2–4 short options; a free-text custom option is always \
+         added automatically. \
          Only call this when user input is genuinely required to proceed."
     }
 
@@ -63,8 +69,8 @@ impl Tool for UserInputTool {
             "properties": {
                 "mode": {
                     "type": "string",
-                    "enum": ["choose_one", "choose_many"],
-                    "description": "choose_one: user picks a single option. choose_many: user picks any subset."
+                    "enum": ["choose_one", "choose_many", "free_text"],
+                    "description": "choose_one: user picks a single option. choose_many: user picks any subset. free_text: user types an open answer."
                 },
                 "question": {
                     "type": "string",
@@ -75,16 +81,22 @@ impl Tool for UserInputTool {
                     "minItems": 2,
                     "maxItems": 4,
                     "items": { "type": "string" },
-                    "description": "2–4 short options for the user to choose from. A free-text custom option is always appended."
+                    "description": "2–4 short options for the user to choose from. A free-text custom option is always appended. Required for choose_one and choose_many; omit for free_text."
                 }
             },
-            "required": ["mode", "question", "options"]
+            "required": ["mode", "question"]
         })
     }
 
+    /// Waits on a human, who may take as long as they like.
+    fn timeout_ms(&self, _default: u64) -> u64 {
+        0
+    }
+
     async fn call(&self, args: Value) -> Result<ToolResult> {
         let mode = match args["mode"].as_str().unwrap_or("choose_one") {
             "choose_many" => InputMode::ChooseMany,
+            "free_text" => InputMode::FreeText,
             _ => InputMode::ChooseOne,
         };
 
@@ -93,17 +105,21 @@ impl Tool for UserInputTool {
             .ok_or_else(|| anyhow::anyhow!("missing required field: question"))?
             .to_string();
 
-        let options: Vec<String> = args["options"]
-            .as_array()
-            .ok_or_else(|| anyhow::anyhow!("missing required field: options"))?
-            .iter()
-            .filter_map(|v| v.as_str().map(String::from))
-            .take(4)
-            .collect();
-
-        if options.len() < 2 {
-            anyhow::bail!("ask_user requires at least 2 options");
-        }
+        let options: Vec<String> = if mode == InputMode::FreeText {
+            Vec::new()
+        } else {
+            let options: Vec<String> = args["options"]
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("missing required field: options"))?
+                .iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .take(4)
+                .collect();
+            if options.len() < 2 {
+                anyhow::bail!("ask_user requires at least 2 options");
+            }
+            options
+        };
 
         let (respond, rx) = oneshot::channel::<String>();
 
@@ -124,6 +140,51 @@ impl Tool for UserInputTool {
         Ok(ToolResult {
             content: answer,
             is_error: false,
+            images: Vec::new(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn free_text_asks_without_options() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let tool = UserInputTool::new(tx);
+        tokio::spawn(async move {
+            let req = rx.recv().await.unwrap();
+            assert_eq!(req.mode, InputMode::FreeText);
+            assert_eq!(req.question, "Project name?");
+            assert!(req.options.is_empty());
+            req.respond.send("krabs".to_string()).unwrap();
+        });
+
+        let result = tool
+            .call(json!({
+                "mode": "free_text",
+                "question": "Project name?",
+                "options": ["ignored", "too"]
+            }))
+            .await
+            .unwrap();
+
+        assert!(!result.is_error);
+        assert_eq!(result.content, "krabs");
+    }
+
+    #[tokio::test]
+    async fn choice_modes_still_need_two_options() {
+        let (tx, _rx) = mpsc::channel(1);
+        let tool = UserInputTool::new(tx);
+
+        let err = tool
+            .call(json!({ "mode": "choose_one", "question": "Which?", "options": ["only"] }))
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("at least 2 options"));
+    }
+}
@@ -1,9 +1,10 @@
 use super::tool::{Tool, ToolResult};
+use crate::sandbox::config::SandboxConfig;
 use anyhow::Result;
 use async_trait::async_trait;
-use reqwest::{Client, Method};
+use reqwest::{redirect, Client, Method};
 use serde_json::json;
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 
 static CLIENT: LazyLock<Client> = LazyLock::new(|| {
     Client::builder()
@@ -12,6 +13,34 @@ static CLIENT: LazyLock<Client> = LazyLock::new(|| {
         .expect("failed to build reqwest client")
 });
 
+/// Redirect hops followed before giving up (reqwest's default).
+const MAX_REDIRECTS: usize = 10;
+
+/// A client that only follows redirects to hosts `sandbox` allows, so a
+/// redirect can't leave the allowlist the first URL was checked against.
+pub(crate) fn sandboxed_client(sandbox: Arc<SandboxConfig>) -> Result<Client> {
+    let policy = redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+        let check = match attempt.url().host_str() {
+            Some(host) => sandbox.check_domain(host),
+            None => Err(format!(
+                "sandbox: cannot determine host of {}",
+                attempt.url()
+            )),
+        };
+        match check {
+            Ok(()) => attempt.follow(),
+            Err(reason) => attempt.error(reason),
+        }
+    });
+    Ok(Client::builder()
+        .user_agent("krabs/0.1")
+        .redirect(policy)
+        .build()?)
+}
+
 pub struct WebFetchTool;
 
 #[async_trait]
@@ -54,12 +83,19 @@ impl Tool for WebFetchTool {
         })
     }
 
+    fn is_parallel_safe(&self) -> bool {
+        true
+    }
+
     async fn call(&self, args: serde_json::Value) -> Result<ToolResult> {
         fetch_with_client(&CLIENT, args).await
     }
 }
 
-async fn fetch_with_client(client: &Client, args: serde_json::Value) -> Result<ToolResult> {
+pub(crate) async fn fetch_with_client(
+    client: &Client,
+    args: serde_json::Value,
+) -> Result<ToolResult> {
     let url = args["url"]
         .as_str()
         .ok_or_else(|| anyhow::anyhow!("Missing 'url' argument"))?;
@@ -87,10 +123,17 @@ async fn fetch_with_client(client: &Client, args: serde_json::Value) -> Result<T
         req = req.body(body.to_string());
     }
 
-    let response = req
-        .send()
-        .await
-        .map_err(|e| anyhow::anyhow!("Request failed: {e}"))?;
+    let response = match req.send().await {
+        Ok(response) => response,
+        // A redirect refused by the client's policy carries the reason as its
+        // source; retrying won't change it.
+        Err(e) if e.is_redirect() => {
+            let reason = std::error::Error::source(&e)
+                .map_or_else(|| e.to_string(), |cause| cause.to_string());
+            return Ok(ToolResult::err(reason));
+        }
+        Err(e) => return Err(anyhow::anyhow!("Request failed: {e}")),
+    };
 
     let status = response.status();
     let is_error = status.is_client_error() || status.is_server_error();
@@ -102,7 +145,11 @@ async fn fetch_with_client(client: &Client, args: serde_json::Value) -> Result<T
 
     let content = format!("HTTP {}\n\n{}", status.as_u16(), body);
 
-    Ok(ToolResult { content, is_error })
+    Ok(ToolResult {
+        content,
+        is_error,
+        images: Vec::new(),
+    })
 }
 
 #[cfg(test)]
@@ -264,6 +311,37 @@ mod tests {
         assert!(result.content.contains("sentinel"));
     }
 
+    #[tokio::test]
+    async fn sandboxed_client_refuses_redirects_off_the_allowlist() {
+        let addr = serve_once(|_req| async {
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .status(302)
+                    .header("location", "http://blocked.invalid/secret")
+                    .body(Full::new(Bytes::new()))
+                    .unwrap(),
+            )
+        })
+        .await;
+        let sandbox = Arc::new(SandboxConfig {
+            enabled: true,
+            allowed_hosts: vec!["127.0.0.1".to_string()],
+            ..Default::default()
+        });
+        let client = sandboxed_client(sandbox).unwrap();
+
+        let result = fetch_with_client(&client, json!({ "url": format!("http://{addr}/") }))
+            .await
+            .unwrap();
+
+        assert!(result.is_error);
+        assert!(
+            result.content.contains("blocked.invalid not in allowlist"),
+            "{}",
+            result.content
+        );
+    }
+
     #[tokio::test]
     async fn timeout_returns_err() {
         let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
@@ -1,9 +1,82 @@
+use super::root::ProjectRoot;
 use super::tool::{Tool, ToolResult};
 use anyhow::Result;
 use async_trait::async_trait;
 use serde_json::json;
+use std::path::{Path, PathBuf};
 
-pub struct WriteTool;
+/// Suffix of backup copies when `KrabsConfig` doesn't say otherwise.
+pub const DEFAULT_BACKUP_SUFFIX: &str = ".krabs.bak";
+
+#[derive(Default)]
+pub struct WriteTool {
+    /// Copy a file to `<path><suffix>` before overwriting it; `None` disables.
+    backup_suffix: Option<String>,
+    root: ProjectRoot,
+}
+
+impl WriteTool {
+    /// `backup_suffix` turns on backups of overwritten files.
+    pub fn new(backup_suffix: Option<String>) -> Self {
+        Self {
+            backup_suffix,
+            root: ProjectRoot::default(),
+        }
+    }
+
+    /// Resolve relative paths against `root`.
+    pub fn with_root(mut self, root: ProjectRoot) -> Self {
+        self.root = root;
+        self
+    }
+
+    /// Back up the file about to be overwritten, if backups are on. Returns
+    /// the backup's path.
+    async fn backup(&self, path: &Path) -> Result<Option<PathBuf>> {
+        let Some(suffix) = &self.backup_suffix else {
+            return Ok(None);
+        };
+        let backup = backup_path(path, suffix);
+        tokio::fs::copy(path, &backup)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to back up {}: {}", path.display(), e))?;
+        Ok(Some(backup))
+    }
+}
+
+/// Where the backup of `path` lives with `suffix`.
+pub fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Put the backup of `path` back in place, returning the backup's path. The
+/// backup is kept, so a restore can be repeated.
+pub async fn restore_backup(path: &Path, suffix: &str) -> Result<PathBuf> {
+    let backup = backup_path(path, suffix);
+    if !tokio::fs::try_exists(&backup).await? {
+        anyhow::bail!("no backup of {} at {}", path.display(), backup.display());
+    }
+    tokio::fs::copy(&backup, path).await?;
+    Ok(backup)
+}
+
+/// `Overwrote src/a.rs: 120 → 180 bytes (+60), backup at src/a.rs.krabs.bak`.
+fn overwrite_summary(
+    verb: &str,
+    path: &str,
+    old: u64,
+    new: usize,
+    backup: Option<&Path>,
+) -> String {
+    let delta = new as i64 - old as i64;
+    let mut out = format!("{verb} {path}: {old} → {new} bytes ({delta:+})");
+    if let Some(backup) = backup {
+        out.push_str(&format!(", backup at {}", backup.display()));
+    }
+    out
+}
 
 #[async_trait]
 impl Tool for WriteTool {
@@ -11,7 +84,7 @@ impl Tool for WriteTool {
         "write"
     }
     fn description(&self) -> &str {
-        "Write content to a file. Creates the file if it doesn't exist."
+        "Write content to a file. Creates the file if it doesn't exist. The result says whether a file was created or overwritten, and the change in size."
     }
     fn parameters(&self) -> serde_json::Value {
         json!({
@@ -29,8 +102,12 @@ impl Tool for WriteTool {
         let path = args["path"]
             .as_str()
             .ok_or_else(|| anyhow::anyhow!("Missing 'path' argument"))?;
+        let resolved = match self.root.resolve(path) {
+            Ok(p) => p,
+            Err(e) => return Ok(ToolResult::err(e)),
+        };
         if let (Some(old), Some(new)) = (args["old_string"].as_str(), args["new_string"].as_str()) {
-            let existing = match tokio::fs::read_to_string(path).await {
+            let existing = match tokio::fs::read_to_string(&resolved).await {
                 Ok(c) => c,
                 Err(e) => return Ok(ToolResult::err(format!("Failed to read {}: {}", path, e))),
             };
@@ -38,22 +115,84 @@ impl Tool for WriteTool {
                 return Ok(ToolResult::err(format!("old_string not found in {}", path)));
             }
             let updated = existing.replacen(old, new, 1);
-            tokio::fs::write(path, updated)
+            let backup = self.backup(&resolved).await?;
+            tokio::fs::write(&resolved, &updated)
                 .await
                 .map_err(|e| anyhow::anyhow!("{}", e))?;
-            return Ok(ToolResult::ok(format!("Patched {}", path)));
+            return Ok(ToolResult::ok(overwrite_summary(
+                "Patched",
+                path,
+                existing.len() as u64,
+                updated.len(),
+                backup.as_deref(),
+            )));
         }
         let content = args["content"].as_str().unwrap_or("");
-        if let Some(parent) = std::path::Path::new(path).parent() {
+        let previous = tokio::fs::metadata(&resolved)
+            .await
+            .ok()
+            .filter(|m| m.is_file())
+            .map(|m| m.len());
+        let backup = match previous {
+            Some(_) => self.backup(&resolved).await?,
+            None => None,
+        };
+        if let Some(parent) = resolved.parent() {
             tokio::fs::create_dir_all(parent).await?;
         }
-        tokio::fs::write(path, content)
+        tokio::fs::write(&resolved, content)
             .await
             .map_err(|e| anyhow::anyhow!("{}", e))?;
-        Ok(ToolResult::ok(format!(
-            "Written {} bytes to {}",
-            content.len(),
-            path
-        )))
+        Ok(ToolResult::ok(match previous {
+            Some(old) => {
+                overwrite_summary("Overwrote", path, old, content.len(), backup.as_deref())
+            }
+            None => format!("Created {} ({} bytes)", path, content.len()),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn overwrites_are_backed_up_and_restorable() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("notes.txt");
+        let path = file.display().to_string();
+        let tool = WriteTool::new(Some(DEFAULT_BACKUP_SUFFIX.into()));
+
+        let r = tool
+            .call(json!({ "path": path, "content": "first" }))
+            .await
+            .unwrap();
+        assert_eq!(r.content, format!("Created {path} (5 bytes)"));
+        assert!(!backup_path(&file, DEFAULT_BACKUP_SUFFIX).exists());
+
+        let r = tool
+            .call(json!({ "path": path, "content": "second!" }))
+            .await
+            .unwrap();
+        assert!(r
+            .content
+            .starts_with(&format!("Overwrote {path}: 5 → 7 bytes (+2), backup at")));
+
+        restore_backup(&file, DEFAULT_BACKUP_SUFFIX).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "first");
+    }
+
+    #[tokio::test]
+    async fn no_backup_unless_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, "hello world").unwrap();
+        let r = WriteTool::default()
+            .call(json!({ "path": file.display().to_string(), "old_string": "world", "new_string": "you" }))
+            .await
+            .unwrap();
+        assert!(r.content.ends_with("11 → 9 bytes (-2)"));
+        assert!(!backup_path(&file, DEFAULT_BACKUP_SUFFIX).exists());
+        assert!(restore_backup(&file, DEFAULT_BACKUP_SUFFIX).await.is_err());
     }
 }
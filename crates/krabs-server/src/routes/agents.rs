@@ -57,17 +57,28 @@ pub async fn create_agent(
             base_url: config.base_url.clone(),
             model: config.model.clone(),
             is_default: false,
+            prompt_caching: config.enable_prompt_caching,
+            thinking_budget: config.thinking_budget_tokens,
+            reasoning_effort: config.reasoning_effort,
+            max_output_tokens: config.max_output_tokens,
+            stop_sequences: config.stop_sequences.clone(),
+            openai_api: config.openai_api,
+            trace_http: config.trace_http,
+            request_timeout_ms: config.request_timeout_ms,
+            connect_timeout_ms: config.connect_timeout_ms,
         };
         Arc::from(creds.build_provider())
     };
 
     // Build tool registry with defaults + orchestration
-    let mut registry = ToolRegistry::with_defaults();
-    registry.with_orchestration(&config, &provider);
+    let mut registry = ToolRegistry::from_config(&config);
+    let subagent_usage = registry.with_orchestration(&config, &provider);
 
     let system_prompt = req.system_prompt.clone().unwrap_or_default();
 
-    let factory = AgentFactory::new(config, provider, registry).with_system_prompt(system_prompt);
+    let factory = AgentFactory::new(config, provider, registry)
+        .with_system_prompt(system_prompt)
+        .with_subagent_usage(subagent_usage);
 
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -43,7 +43,7 @@ pub async fn chat(
         .map_err(ServerError::from)?;
 
     // Acquire handle, begin turn, extract factory — then release lock
-    let (turn_input, factory) = {
+    let (turn_input, factory, cancel) = {
         let mut handle = handle_mutex.lock().await;
         let turn = handle
             .begin_turn(&req.message)
@@ -52,10 +52,10 @@ pub async fn chat(
         let cancel = tokio_util::sync::CancellationToken::new();
         {
             let mut tokens = state.cancel_tokens.write().await;
-            tokens.insert(agent_id.clone(), cancel);
+            tokens.insert(agent_id.clone(), cancel.clone());
         }
 
-        (turn, handle.factory.clone())
+        (turn, handle.factory.clone(), cancel)
     };
 
     // Create a per-request event bus for this streaming session
@@ -77,7 +77,7 @@ pub async fn chat(
         .await;
 
     let (stream_rx, done_rx) = agent
-        .run_streaming_with_history(turn_input.messages, turn_input.subturn_resume)
+        .run_streaming_with_history(turn_input.messages, turn_input.subturn_resume, cancel)
         .await
         .map_err(ServerError::Internal)?;
 
@@ -98,6 +98,9 @@ pub async fn chat(
                 StreamChunk::Delta { text } => {
                     ("delta", serde_json::json!({ "text": text }).to_string())
                 }
+                StreamChunk::Thinking { text } => {
+                    ("thinking", serde_json::json!({ "text": text }).to_string())
+                }
                 StreamChunk::ToolCallReady { call } => (
                     "tool_call",
                     serde_json::json!({
@@ -111,13 +114,18 @@ pub async fn chat(
                     "usage",
                     serde_json::json!({
                         "input_tokens": usage.input_tokens,
-                        "output_tokens": usage.output_tokens
+                        "output_tokens": usage.output_tokens,
+                        "reasoning_tokens": usage.reasoning_tokens
                     })
                     .to_string(),
                 ),
                 StreamChunk::Status { text } => {
                     ("status", serde_json::json!({ "text": text }).to_string())
                 }
+                StreamChunk::Interrupted { partial, resumable } => (
+                    "interrupted",
+                    serde_json::json!({ "partial": partial, "resumable": resumable }).to_string(),
+                ),
             };
 
             let id = bus.publish(event_type, data.clone()).await;
@@ -133,8 +141,10 @@ pub async fn chat(
         }
 
         // Wait for the done signal with final messages
-        if let Ok(Ok((_sid, final_messages))) = done_rx.await {
+        let mut hit_max_turns = false;
+        if let Ok(Ok((_sid, final_messages, max_turns_hit))) = done_rx.await {
             session_id = _sid;
+            hit_max_turns = max_turns_hit;
 
             // Update handle via complete_turn
             let mut handle = handle_mutex_bg.lock().await;
@@ -149,6 +159,7 @@ pub async fn chat(
         let done_data = serde_json::json!({
             "session_id": session_id,
             "agent_id": agent_id_bg,
+            "hit_max_turns": hit_max_turns,
         })
         .to_string();
         let id = bus.publish("done", done_data.clone()).await;
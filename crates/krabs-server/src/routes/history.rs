@@ -38,6 +38,7 @@ pub async fn get_history(
         .map(|m| {
             let role = match m.role {
                 krabs_core::Role::System => "system",
+                krabs_core::Role::Developer => "developer",
                 krabs_core::Role::User => "user",
                 krabs_core::Role::Assistant => "assistant",
                 krabs_core::Role::Tool => "tool",
@@ -1,4 +1,4 @@
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
 use axum::routing::get;
 use axum::{Json, Router};
 use serde::Serialize;
@@ -16,6 +16,9 @@ pub struct SessionInfo {
     pub provider: String,
     pub created_at: i64,
     pub message_count: usize,
+    pub tags: Vec<String>,
+    /// Generated title, else a preview of the first user message.
+    pub title: Option<String>,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -23,10 +26,18 @@ pub struct SessionListResponse {
     pub sessions: Vec<SessionInfo>,
 }
 
-/// List all persisted sessions.
+#[derive(Debug, serde::Deserialize)]
+pub struct ListSessionsQuery {
+    pub tag: Option<String>,
+}
+
+/// List persisted sessions, optionally only those with a given tag.
 #[utoipa::path(
     get,
     path = "/api/v1/sessions",
+    params(
+        ("tag" = Option<String>, Query, description = "Only list sessions with this tag"),
+    ),
     responses(
         (status = 200, description = "List of sessions", body = SessionListResponse),
     ),
@@ -34,12 +45,16 @@ pub struct SessionListResponse {
 )]
 pub async fn list_sessions(
     State(state): State<Arc<AppState>>,
+    Query(query): Query<ListSessionsQuery>,
 ) -> Result<Json<SessionListResponse>, ServerError> {
-    let store = krabs_core::SessionStore::open(&state.config.krabs.db_path)
+    let store = krabs_core::SessionStore::from_config(&state.config.krabs)
         .await
         .map_err(ServerError::Internal)?;
 
-    let summaries = store.list_sessions().await.map_err(ServerError::Internal)?;
+    let summaries = store
+        .list_sessions(query.tag.as_deref())
+        .await
+        .map_err(ServerError::Internal)?;
 
     let mut sessions = Vec::with_capacity(summaries.len());
     for s in summaries {
@@ -51,6 +66,8 @@ pub async fn list_sessions(
             provider: s.provider,
             created_at: s.created_at,
             message_count: count,
+            tags: s.tags,
+            title: s.title,
         });
     }
 
@@ -74,11 +91,14 @@ pub async fn get_session(
     State(state): State<Arc<AppState>>,
     Path(session_id): Path<String>,
 ) -> Result<Json<SessionInfo>, ServerError> {
-    let store = krabs_core::SessionStore::open(&state.config.krabs.db_path)
+    let store = krabs_core::SessionStore::from_config(&state.config.krabs)
         .await
         .map_err(ServerError::Internal)?;
 
-    let summaries = store.list_sessions().await.map_err(ServerError::Internal)?;
+    let summaries = store
+        .list_sessions(None)
+        .await
+        .map_err(ServerError::Internal)?;
 
     let summary = summaries
         .into_iter()
@@ -94,6 +114,8 @@ pub async fn get_session(
         provider: summary.provider,
         created_at: summary.created_at,
         message_count: count,
+        tags: summary.tags,
+        title: summary.title,
     }))
 }
 
@@ -114,7 +136,7 @@ pub async fn delete_session(
     State(state): State<Arc<AppState>>,
     Path(session_id): Path<String>,
 ) -> Result<axum::http::StatusCode, ServerError> {
-    let store = krabs_core::SessionStore::open(&state.config.krabs.db_path)
+    let store = krabs_core::SessionStore::from_config(&state.config.krabs)
         .await
         .map_err(ServerError::Internal)?;
 